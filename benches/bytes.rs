@@ -0,0 +1,60 @@
+// Times assembling a message from many fragments: by concatenating bytes one fragment at a
+// time, with a compaction at the end, next to the same work on a `Vec<u8>`, which copies into
+// amortized space, and on a flat copy per concatenation, which is what bytes did before they
+// were chunked. Run with `cargo bench --bench bytes`; this is a plain binary, criterion is not a
+// dependency.
+
+use std::time::{Duration, Instant};
+
+use pan_lang_rs::types::bytes::Bytes;
+
+const FRAGMENTS: usize = 10_000;
+const FRAGMENT_LEN: usize = 300;
+const SAMPLES: usize = 21;
+
+fn main() {
+    let fragments: Vec<Bytes> = (0..FRAGMENTS)
+        .map(|n| Bytes::from_vec(vec![n as u8; FRAGMENT_LEN]))
+        .collect();
+
+    report("concat", || {
+        fragments.iter().fold(Bytes::empty(), |acc, fragment| acc.concat(fragment));
+    });
+    report("concat, compact", || {
+        fragments.iter().fold(Bytes::empty(), |acc, fragment| acc.concat(fragment)).compact();
+    });
+    report("Vec::extend", || {
+        let mut v = vec![];
+        for fragment in fragments.iter() {
+            fragment.as_slice_with(|s| v.extend_from_slice(s));
+        }
+    });
+    // Only the first tenth of the fragments, this is quadratic.
+    report("flat copies (1/10)", || {
+        fragments[..FRAGMENTS / 10].iter().fold(Bytes::empty(), |acc, fragment| {
+            let mut v = acc.to_vec();
+            fragment.as_slice_with(|s| v.extend_from_slice(s));
+            Bytes::from_vec(v)
+        });
+    });
+}
+
+fn report(name: &str, mut f: impl FnMut()) {
+    f();
+    let mut samples: Vec<Duration> = (0..SAMPLES)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .collect();
+    samples.sort();
+    println!(
+        "{:<18} {:>10.3} ms (median of {}, {} fragments of {} bytes)",
+        name,
+        samples[SAMPLES / 2].as_secs_f64() * 1000.0,
+        SAMPLES,
+        FRAGMENTS,
+        FRAGMENT_LEN,
+    );
+}
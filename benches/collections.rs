@@ -0,0 +1,85 @@
+// Times copying and reading a large map: snapshots of a runtime whose global is the map, deep
+// copies, and lookups through the `map_get` builtin next to lookups in a `BTreeMap`. Snapshots of
+// an array of the same size show what sealing (see `Value::deep_freeze`) adds for the values that
+// the map can not share. Run with `cargo bench --bench collections`; this is a plain binary,
+// criterion is not a dependency.
+
+#![allow(clippy::mutable_key_type)]
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use pan_lang_rs::builtins::map::map_get;
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::value::Value;
+
+const ENTRIES: i64 = 100_000;
+const SAMPLES: usize = 21;
+
+fn main() {
+    let entries: BTreeMap<Value, Value> =
+        (0..ENTRIES).map(|n| (Value::Int(n), Value::from(n.to_string().as_str()))).collect();
+    // Every key once, in a scattered order.
+    let keys: Vec<Value> = (0..ENTRIES).map(|n| Value::Int(n * 7_919 % ENTRIES)).collect();
+
+    let map = Value::map(entries.clone());
+    let mut runtime = Runtime::new();
+    runtime.define("m", map.clone()).expect("the map belongs to no other runtime");
+    report("snapshot", || {
+        runtime.snapshot();
+    });
+    report("deep_copy", || {
+        map.deep_copy();
+    });
+
+    // A single entry with a gc handle, which is copied along with the nodes above it.
+    let mut nested = entries.clone();
+    nested.insert(Value::Int(ENTRIES / 2), Value::array(vec![]));
+    let nested = Value::map(nested);
+    let mut runtime = Runtime::new();
+    runtime.define("m", nested.clone()).expect("the map belongs to no other runtime");
+    report("snapshot, nested", || {
+        runtime.snapshot();
+    });
+    // Arrays are copied element by element, unless deep freezing has sealed them.
+    let array = Value::array(entries.values().cloned().collect());
+    let mut runtime = Runtime::new();
+    runtime.define("a", array.clone()).expect("the array belongs to no other runtime");
+    report("snapshot, array", || {
+        runtime.snapshot();
+    });
+    array.deep_freeze();
+    report("snapshot, sealed", || {
+        runtime.snapshot();
+    });
+
+    report("map_get", || {
+        for key in keys.iter() {
+            map_get(&[map.clone(), key.clone()]).expect("the key is a value");
+        }
+    });
+    report("BTreeMap::get", || {
+        for key in keys.iter() {
+            entries.get(key).cloned().expect("the key is in the map");
+        }
+    });
+}
+
+fn report(name: &str, mut f: impl FnMut()) {
+    f();
+    let mut samples: Vec<Duration> = (0..SAMPLES)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .collect();
+    samples.sort();
+    println!(
+        "{:<18} {:>10.3} ms (median of {}, {} entries)",
+        name,
+        samples[SAMPLES / 2].as_secs_f64() * 1000.0,
+        SAMPLES,
+        ENTRIES,
+    );
+}
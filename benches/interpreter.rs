@@ -0,0 +1,47 @@
+// Times the workloads of `testing::workloads` in every execution of the interpreter, and the
+// array building workload without watchpoints and with one (see `types::watch`). Run with
+// `cargo bench --features testing`; this is a plain binary, criterion is not a dependency.
+
+use std::time::{Duration, Instant};
+
+use pan_lang_rs::testing::ir_gen::{Execution, EXECUTIONS};
+use pan_lang_rs::testing::workloads::{self, Instance};
+
+const ITERATIONS: i64 = 100_000;
+const SAMPLES: usize = 21;
+
+fn main() {
+    for workload in workloads::workloads() {
+        for &execution in EXECUTIONS.iter() {
+            let mut instance = workload.instance(execution, ITERATIONS);
+            report(workload.name, &format!("{:?}", execution), &mut instance);
+        }
+    }
+
+    // With a watchpoint, every execution looks up the locations it watches, even if the watched
+    // function never runs.
+    let workload = workloads::array_building();
+    let mut instance = workload.instance(Execution::Baseline, ITERATIONS);
+    report(workload.name, "no watchpoints", &mut instance);
+    instance.runtime().watch_slot(u64::MAX, 0);
+    report(workload.name, "a watchpoint", &mut instance);
+}
+
+fn report(name: &str, variant: &str, instance: &mut Instance) {
+    instance.run().expect("workloads do not throw");
+    let mut samples: Vec<Duration> = (0..SAMPLES)
+        .map(|_| {
+            let start = Instant::now();
+            instance.run().expect("workloads do not throw");
+            start.elapsed()
+        })
+        .collect();
+    samples.sort();
+    println!(
+        "{:<16} {:<16} {:>8.2} ms (median of {})",
+        name,
+        variant,
+        samples[SAMPLES / 2].as_secs_f64() * 1000.0,
+        SAMPLES,
+    );
+}
@@ -0,0 +1,258 @@
+// Derive macros for the `IntoPan` and `FromPan` traits of `pan-lang-rs`, see
+// `pan_lang_rs::value::convert` for the representation of structs and enums as pan values.
+//
+// Supported attributes:
+//
+// - `#[pan(rename = "name")]` on a named field or a variant: use `name` instead of the rust name.
+// - `#[pan(default)]` on a named field: use `Default::default()` if the field is missing.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    parse_macro_input, parse_quote, Data, DeriveInput, Error, Fields, Generics, Ident, Lit, Meta,
+    NestedMeta,
+};
+
+#[proc_macro_derive(IntoPan, attributes(pan))]
+pub fn derive_into_pan(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    into_pan(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+#[proc_macro_derive(FromPan, attributes(pan))]
+pub fn derive_from_pan(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    from_pan(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+const UNSUPPORTED_STRUCT: &str = "only structs with named fields are supported";
+
+// What the `pan` attributes of a field or variant say.
+#[derive(Default)]
+struct Attrs {
+    rename: Option<String>,
+    default: bool,
+}
+
+fn attrs(attrs: &[syn::Attribute]) -> Result<Attrs, Error> {
+    let mut parsed = Attrs::default();
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("pan")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => return Err(Error::new_spanned(meta, "expected #[pan(...)]")),
+        };
+        for nested in list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("rename") => {
+                    match nv.lit {
+                        Lit::Str(s) => parsed.rename = Some(s.value()),
+                        lit => return Err(Error::new_spanned(lit, "expected a string")),
+                    }
+                }
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("default") => {
+                    parsed.default = true;
+                }
+                other => return Err(Error::new_spanned(other, "unknown pan attribute")),
+            }
+        }
+    }
+    Ok(parsed)
+}
+
+// The name of a field or variant in pan.
+fn pan_name(ident: &Ident, attrs: &Attrs) -> String {
+    attrs.rename.clone().unwrap_or_else(|| ident.to_string())
+}
+
+// Require `bound` for all type parameters.
+fn bounded(generics: &Generics, bound: TokenStream2) -> Generics {
+    let mut generics = generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(parse_quote!(#bound));
+    }
+    generics
+}
+
+fn into_pan(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let name = &input.ident;
+    let generics = bounded(&input.generics, quote!(::pan_lang_rs::value::convert::IntoPan));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let body = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => {
+                let entries = fields.named.iter().map(|field| {
+                    let ident = field.ident.as_ref().unwrap();
+                    let key = pan_name(ident, &attrs(&field.attrs)?);
+                    Ok(quote! {
+                        (#key, ::pan_lang_rs::value::convert::IntoPan::into_pan(self.#ident))
+                    })
+                }).collect::<Result<Vec<_>, Error>>()?;
+                quote! { ::pan_lang_rs::value::convert::__struct(vec![#(#entries),*]) }
+            }
+            _ => return Err(Error::new_spanned(name, UNSUPPORTED_STRUCT)),
+        },
+        Data::Enum(data) => {
+            let arms = data.variants.iter().map(|variant| {
+                let ident = &variant.ident;
+                let key = pan_name(ident, &attrs(&variant.attrs)?);
+                Ok(match &variant.fields {
+                    Fields::Unit => quote! {
+                        #name::#ident => ::pan_lang_rs::value::convert::__variant(#key, None)
+                    },
+                    Fields::Unnamed(fields) => {
+                        let bindings: Vec<Ident> = (0..fields.unnamed.len())
+                            .map(|i| Ident::new(&format!("f{}", i), Span::call_site()))
+                            .collect();
+                        let payload = if bindings.len() == 1 {
+                            quote! { ::pan_lang_rs::value::convert::IntoPan::into_pan(f0) }
+                        } else {
+                            quote! {
+                                ::pan_lang_rs::value::Value::array(vec![#(
+                                    ::pan_lang_rs::value::convert::IntoPan::into_pan(#bindings)
+                                ),*])
+                            }
+                        };
+                        quote! {
+                            #name::#ident(#(#bindings),*) =>
+                                ::pan_lang_rs::value::convert::__variant(#key, Some(#payload))
+                        }
+                    }
+                    Fields::Named(fields) => {
+                        let idents: Vec<&Ident> =
+                            fields.named.iter().map(|f| f.ident.as_ref().unwrap()).collect();
+                        let keys = fields.named.iter().map(|field| {
+                            Ok(pan_name(field.ident.as_ref().unwrap(), &attrs(&field.attrs)?))
+                        }).collect::<Result<Vec<_>, Error>>()?;
+                        quote! {
+                            #name::#ident { #(#idents),* } => {
+                                ::pan_lang_rs::value::convert::__variant(#key, Some(
+                                    ::pan_lang_rs::value::convert::__struct(vec![#((
+                                        #keys,
+                                        ::pan_lang_rs::value::convert::IntoPan::into_pan(#idents),
+                                    )),*])
+                                ))
+                            }
+                        }
+                    }
+                })
+            }).collect::<Result<Vec<_>, Error>>()?;
+            quote! { match self { #(#arms,)* } }
+        }
+        Data::Union(_) => return Err(Error::new_spanned(name, "unions are not supported")),
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::pan_lang_rs::value::convert::IntoPan for #name #ty_generics
+        #where_clause
+        {
+            fn into_pan(self) -> ::pan_lang_rs::value::Value {
+                #body
+            }
+        }
+    })
+}
+
+// An expression that converts the named fields out of the map `v`.
+fn named_fields(fields: &syn::FieldsNamed) -> Result<Vec<TokenStream2>, Error> {
+    fields.named.iter().map(|field| {
+        let ident = field.ident.as_ref().unwrap();
+        let attrs = attrs(&field.attrs)?;
+        let key = pan_name(ident, &attrs);
+        let missing = if attrs.default {
+            quote! { ::std::default::Default::default() }
+        } else {
+            quote! { ::pan_lang_rs::value::convert::__missing(#key)? }
+        };
+        Ok(quote! {
+            #ident: match ::pan_lang_rs::value::convert::__field(v, #key)? {
+                Some(field) => ::pan_lang_rs::value::convert::FromPan::from_pan(&field)
+                    .map_err(|err| err.at_field(#key))?,
+                None => #missing,
+            }
+        })
+    }).collect()
+}
+
+fn from_pan(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let name = &input.ident;
+    let generics = bounded(&input.generics, quote!(::pan_lang_rs::value::convert::FromPan));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let body = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => {
+                let fields = named_fields(fields)?;
+                quote! { Ok(#name { #(#fields),* }) }
+            }
+            _ => return Err(Error::new_spanned(name, UNSUPPORTED_STRUCT)),
+        },
+        Data::Enum(data) => {
+            let arms = data.variants.iter().map(|variant| {
+                let ident = &variant.ident;
+                let key = pan_name(ident, &attrs(&variant.attrs)?);
+                Ok(match &variant.fields {
+                    Fields::Unit => quote! { (#key, None) => Ok(#name::#ident) },
+                    Fields::Unnamed(fields) if fields.unnamed.len() == 1 => quote! {
+                        (#key, Some(payload)) => Ok(#name::#ident(
+                            ::pan_lang_rs::value::convert::FromPan::from_pan(&payload)
+                                .map_err(|err| err.at_field(#key))?
+                        ))
+                    },
+                    Fields::Unnamed(fields) => {
+                        let len = fields.unnamed.len();
+                        let elements = (0..len).map(|i| quote! {
+                            ::pan_lang_rs::value::convert::FromPan::from_pan(&elements[#i])
+                                .map_err(|err| err.at_index(#i).at_field(#key))?
+                        });
+                        quote! {
+                            (#key, Some(payload)) => {
+                                let elements = ::pan_lang_rs::value::convert::__elements(
+                                    &payload, #len,
+                                ).map_err(|err| err.at_field(#key))?;
+                                Ok(#name::#ident(#(#elements),*))
+                            }
+                        }
+                    }
+                    Fields::Named(fields) => {
+                        let fields = named_fields(fields)?;
+                        quote! {
+                            (#key, Some(payload)) => {
+                                let v = &payload;
+                                let variant = (|| Ok(#name::#ident { #(#fields),* }))();
+                                variant.map_err(
+                                    |err: ::pan_lang_rs::value::convert::FromPanError| {
+                                        err.at_field(#key)
+                                    },
+                                )
+                            }
+                        }
+                    }
+                })
+            }).collect::<Result<Vec<_>, Error>>()?;
+            quote! {
+                let (variant, payload) = ::pan_lang_rs::value::convert::__variant_of(v)?;
+                match (variant.as_str(), payload) {
+                    #(#arms,)*
+                    (variant, _) => ::pan_lang_rs::value::convert::__unknown_variant(variant),
+                }
+            }
+        }
+        Data::Union(_) => return Err(Error::new_spanned(name, "unions are not supported")),
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::pan_lang_rs::value::convert::FromPan for #name #ty_generics
+        #where_clause
+        {
+            fn from_pan(
+                v: &::pan_lang_rs::value::Value,
+            ) -> Result<Self, ::pan_lang_rs::value::convert::FromPanError> {
+                #body
+            }
+        }
+    })
+}
@@ -0,0 +1,104 @@
+// The functions that pan code can use without importing anything.
+//
+// The messages of the errors that builtins throw start with the name of the builtin, followed by
+// what went wrong: `"<name> expects <what>, got <type>"` for arguments of the wrong type or out of
+// range, and `"<name> failed: <reason>"` (or a more specific verb, like `"<name> failed to read:
+// <reason>"`) when an operation on valid arguments fails.
+
+use crate::capabilities::{Capabilities, Capability};
+use crate::runtime::Runtime;
+
+pub mod arith;
+pub mod array;
+pub mod assert;
+pub mod bits;
+pub mod bytes;
+pub mod channel;
+pub mod char;
+pub mod cmp;
+pub mod convert;
+pub mod csv;
+#[cfg(feature = "secure-random")]
+pub mod entropy;
+pub mod env;
+pub mod error;
+pub mod exit;
+pub mod format;
+pub mod freeze;
+pub mod fs;
+pub mod fun;
+pub mod futures;
+pub mod gc;
+pub mod hash;
+pub mod io;
+pub mod iter;
+pub mod map;
+pub mod math;
+pub mod module;
+pub mod random;
+#[cfg(feature = "regex")]
+pub mod regex;
+pub mod set;
+pub mod sort;
+pub mod stream;
+pub mod string;
+pub mod time;
+pub mod types;
+pub mod userdata;
+
+/// Register the builtins with the runtime: all builtins that do not reach outside of the runtime,
+/// and those guarded by the granted capabilities. Fails if the root of the file system access can
+/// not be canonicalized.
+pub fn register(runtime: &mut Runtime, capabilities: &Capabilities) -> std::io::Result<()> {
+    arith::register(runtime);
+    array::register(runtime);
+    assert::register(runtime);
+    bits::register(runtime);
+    bytes::register(runtime);
+    channel::register(runtime);
+    char::register(runtime);
+    cmp::register(runtime);
+    convert::register(runtime);
+    csv::register(runtime);
+    error::register(runtime);
+    exit::register(runtime);
+    format::register(runtime);
+    freeze::register(runtime);
+    fun::register(runtime);
+    futures::register(runtime);
+    hash::register(runtime);
+    iter::register(runtime);
+    map::register(runtime);
+    math::register(runtime);
+    module::register(runtime);
+    random::register(runtime);
+    #[cfg(feature = "regex")]
+    regex::register(runtime);
+    set::register(runtime);
+    sort::register(runtime);
+    stream::register(runtime);
+    string::register(runtime);
+    time::register(runtime);
+    types::register(runtime);
+    userdata::register(runtime);
+
+    for capability in Capability::ALL.iter().cloned() {
+        if !capabilities.allows(capability) {
+            continue;
+        }
+        match capability {
+            Capability::Fs => fs::register(runtime, capabilities.fs_root())?,
+            Capability::Env => env::register(runtime),
+            #[cfg(feature = "secure-random")]
+            Capability::Entropy => entropy::register(runtime),
+            #[cfg(not(feature = "secure-random"))]
+            Capability::Entropy => {}
+            Capability::Clock => time::register_clock(runtime),
+            Capability::Stdout => io::register_stdout(runtime),
+            Capability::Stderr => io::register_stderr(runtime),
+            Capability::Stdin => io::register_stdin(runtime),
+            Capability::Gc => gc::register(runtime),
+        }
+    }
+    Ok(())
+}
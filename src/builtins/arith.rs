@@ -0,0 +1,81 @@
+// Arithmetic builtins, see `value::ops` for how ints and floats are combined and which errors
+// are thrown.
+
+use crate::runtime::Runtime;
+use crate::value::{ops, Value};
+
+/// Register the arithmetic builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("add", add);
+    runtime.register("sub", sub);
+    runtime.register("mul", mul);
+    runtime.register("div", div);
+    runtime.register("rem", rem);
+    runtime.register("neg", neg);
+    runtime.register("floor_div", floor_div);
+    runtime.register("floor_mod", floor_mod);
+    runtime.register("trunc_div", trunc_div);
+    runtime.register("trunc_mod", trunc_mod);
+    runtime.register("divmod", divmod);
+}
+
+/// `add(a, b)`: The sum of two numbers.
+pub fn add(args: &[Value]) -> Result<Value, Value> {
+    ops::add(&arg(args, 0), &arg(args, 1))
+}
+
+/// `sub(a, b)`: The difference of two numbers.
+pub fn sub(args: &[Value]) -> Result<Value, Value> {
+    ops::sub(&arg(args, 0), &arg(args, 1))
+}
+
+/// `mul(a, b)`: The product of two numbers.
+pub fn mul(args: &[Value]) -> Result<Value, Value> {
+    ops::mul(&arg(args, 0), &arg(args, 1))
+}
+
+/// `div(a, b)`: The quotient of two numbers, truncated towards zero for ints. Dividing an int by
+/// zero throws, dividing a float by zero yields an infinity or NaN.
+pub fn div(args: &[Value]) -> Result<Value, Value> {
+    ops::div(&arg(args, 0), &arg(args, 1))
+}
+
+/// `rem(a, b)`: The remainder of dividing two numbers, with the sign of `a`.
+pub fn rem(args: &[Value]) -> Result<Value, Value> {
+    ops::rem(&arg(args, 0), &arg(args, 1))
+}
+
+/// `neg(a)`: The negation of a number.
+pub fn neg(args: &[Value]) -> Result<Value, Value> {
+    ops::neg(&arg(args, 0))
+}
+
+/// `floor_div(a, b)`: The quotient of two ints, rounded towards negative infinity.
+pub fn floor_div(args: &[Value]) -> Result<Value, Value> {
+    ops::floor_div(&arg(args, 0), &arg(args, 1))
+}
+
+/// `floor_mod(a, b)`: The remainder of `floor_div(a, b)`, with the sign of `b`.
+pub fn floor_mod(args: &[Value]) -> Result<Value, Value> {
+    ops::floor_mod(&arg(args, 0), &arg(args, 1))
+}
+
+/// `trunc_div(a, b)`: The quotient of two ints, rounded towards zero.
+pub fn trunc_div(args: &[Value]) -> Result<Value, Value> {
+    ops::trunc_div(&arg(args, 0), &arg(args, 1))
+}
+
+/// `trunc_mod(a, b)`: The remainder of `trunc_div(a, b)`, with the sign of `a`.
+pub fn trunc_mod(args: &[Value]) -> Result<Value, Value> {
+    ops::trunc_mod(&arg(args, 0), &arg(args, 1))
+}
+
+/// `divmod(a, b)`: The two-element array of `floor_div(a, b)` and `floor_mod(a, b)`.
+pub fn divmod(args: &[Value]) -> Result<Value, Value> {
+    ops::divmod(&arg(args, 0), &arg(args, 1))
+}
+
+// Missing arguments are `nil`.
+fn arg(args: &[Value], i: usize) -> Value {
+    args.get(i).cloned().unwrap_or(Value::Nil)
+}
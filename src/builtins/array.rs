@@ -0,0 +1,315 @@
+// Builtins for working with arrays.
+//
+// Indices are ints counted from zero, indices outside the array throw an error of kind `"range"`.
+// Arguments of the wrong type throw an error of kind `"type"`, mutating a frozen array throws an
+// error of kind `"frozen"`, and storing a value of another runtime (see `types::owner`) throws an
+// error of kind `"runtime"`. Functions that create arrays (e.g. `slice`, `concat` or `zip`)
+// always return new arrays that share no state with their arguments.
+
+use gc::Gc;
+
+use crate::builtins::freeze;
+use crate::runtime::Runtime;
+use crate::types::collection::Collection;
+use crate::types::owner;
+use crate::value::Value;
+
+/// Register the array builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("len", len);
+    runtime.register("get", get);
+    runtime.register("set", set);
+    runtime.register("push", push);
+    runtime.register("pop", pop);
+    runtime.register("insert", insert);
+    runtime.register("remove", remove);
+    runtime.register("slice", slice);
+    runtime.register("concat", concat);
+    runtime.register("index_of", index_of);
+    runtime.register("contains", contains);
+    runtime.register("reverse", reverse);
+    runtime.register("clear", clear);
+    runtime.register("range", range);
+    runtime.register("zip", zip);
+    runtime.register("enumerate", enumerate);
+    runtime.register("flatten", flatten);
+    runtime.register("chunks", chunks);
+}
+
+/// The maximum length of an array created by `range`.
+pub const MAX_RANGE_LEN: usize = 1 << 24;
+
+/// `len(arr)`: The number of elements of an array.
+pub fn len(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Int(array(args, 0, "len")?.borrow().len() as i64))
+}
+
+/// `get(arr, i)`: The element at index `i`.
+pub fn get(args: &[Value]) -> Result<Value, Value> {
+    let arr = array(args, 0, "get")?;
+    let arr = arr.borrow();
+    let i = index(args, 1, arr.len(), "get")?;
+    Ok(arr[i].clone())
+}
+
+/// `set(arr, i, v)`: Replace the element at index `i` with `v`.
+pub fn set(args: &[Value]) -> Result<Value, Value> {
+    let arr = mutable_array(args, 0, "set")?;
+    let v = stored(&arr, arg(args, 2))?;
+    let mut arr = arr.borrow_mut();
+    let i = index(args, 1, arr.len(), "set")?;
+    arr[i] = v;
+    Ok(Value::Nil)
+}
+
+/// `push(arr, v)`: Append `v` to the end of the array.
+pub fn push(args: &[Value]) -> Result<Value, Value> {
+    let arr = mutable_array(args, 0, "push")?;
+    let v = stored(&arr, arg(args, 1))?;
+    arr.borrow_mut().push(v);
+    Ok(Value::Nil)
+}
+
+/// `pop(arr)`: Remove and return the last element, throws if the array is empty.
+pub fn pop(args: &[Value]) -> Result<Value, Value> {
+    mutable_array(args, 0, "pop")?
+        .borrow_mut()
+        .pop()
+        .ok_or_else(|| Value::error("range", "pop expects a non-empty array"))
+}
+
+/// `insert(arr, i, v)`: Insert `v` at index `i`, shifting all later elements back. `i` may be the
+/// length of the array, which appends `v`.
+pub fn insert(args: &[Value]) -> Result<Value, Value> {
+    let arr = mutable_array(args, 0, "insert")?;
+    let v = stored(&arr, arg(args, 2))?;
+    let mut arr = arr.borrow_mut();
+    let i = index(args, 1, arr.len() + 1, "insert")?;
+    arr.insert(i, v);
+    Ok(Value::Nil)
+}
+
+/// `remove(arr, i)`: Remove and return the element at index `i`, shifting all later elements
+/// forward.
+pub fn remove(args: &[Value]) -> Result<Value, Value> {
+    let arr = mutable_array(args, 0, "remove")?;
+    let mut arr = arr.borrow_mut();
+    let i = index(args, 1, arr.len(), "remove")?;
+    Ok(arr.remove(i))
+}
+
+/// `slice(arr, start [, end])`: A new array of the elements from index `start` up to (excluding)
+/// index `end`, which defaults to the length of the array.
+pub fn slice(args: &[Value]) -> Result<Value, Value> {
+    let arr = array(args, 0, "slice")?;
+    let arr = arr.borrow();
+    let start = index(args, 1, arr.len() + 1, "slice")?;
+    let end = match args.get(2) {
+        None | Some(Value::Nil) => arr.len(),
+        Some(_) => index(args, 2, arr.len() + 1, "slice")?,
+    };
+    if start > end {
+        return Err(Value::error("range", &format!(
+            "slice expects the start {} to not be greater than the end {}", start, end,
+        )));
+    }
+    Ok(Value::array(arr[start..end].to_vec()))
+}
+
+/// `concat(a, b)`: A new array of the elements of `a` followed by those of `b`.
+pub fn concat(args: &[Value]) -> Result<Value, Value> {
+    let a = array(args, 0, "concat")?;
+    let b = array(args, 1, "concat")?;
+    let mut elements = a.borrow().clone();
+    elements.extend(b.borrow().iter().cloned());
+    Ok(Value::array(elements))
+}
+
+/// `index_of(arr, v)`: The index of the first element that is equal to `v`, or `nil` if there is
+/// none. Elements are compared structurally, see `eq`.
+pub fn index_of(args: &[Value]) -> Result<Value, Value> {
+    let arr = array(args, 0, "index_of")?;
+    let needle = arg(args, 1);
+    let position = arr.borrow().iter().position(|v| *v == needle);
+    Ok(position.map_or(Value::Nil, |i| Value::Int(i as i64)))
+}
+
+/// `contains(arr, v)`: Whether any element is equal to `v`, compared structurally.
+pub fn contains(args: &[Value]) -> Result<Value, Value> {
+    let arr = array(args, 0, "contains")?;
+    let needle = arg(args, 1);
+    let found = arr.borrow().contains(&needle);
+    Ok(Value::Bool(found))
+}
+
+/// `reverse(arr)`: Reverse the order of the elements, in place.
+pub fn reverse(args: &[Value]) -> Result<Value, Value> {
+    mutable_array(args, 0, "reverse")?.borrow_mut().reverse();
+    Ok(Value::Nil)
+}
+
+/// `clear(arr)`: Remove all elements.
+pub fn clear(args: &[Value]) -> Result<Value, Value> {
+    mutable_array(args, 0, "clear")?.borrow_mut().clear();
+    Ok(Value::Nil)
+}
+
+/// `range(start, end [, step])`: An array of the ints from `start` up to (excluding) `end`, in
+/// increments of `step` (which defaults to `1`). A negative `step` counts down from `start` to
+/// (excluding) `end`. If `start` is already past `end`, the array is empty. Throws an error of
+/// kind `"range"` if `step` is zero or the array would be longer than `MAX_RANGE_LEN`.
+pub fn range(args: &[Value]) -> Result<Value, Value> {
+    let start = int(args, 0, "range")?;
+    let end = int(args, 1, "range")?;
+    let step = match args.get(2) {
+        None | Some(Value::Nil) => 1,
+        Some(_) => int(args, 2, "range")?,
+    };
+    if step == 0 {
+        return Err(Value::error("range", "range expects a step other than zero"));
+    }
+
+    // Computed in 128 bits, so that neither the distance nor the last element can overflow.
+    let (start, end, step) = (i128::from(start), i128::from(end), i128::from(step));
+    let len = if step > 0 {
+        (end - start + step - 1).div_euclid(step).max(0)
+    } else {
+        (start - end - step - 1).div_euclid(-step).max(0)
+    };
+    if len > MAX_RANGE_LEN as i128 {
+        return Err(Value::error("range", &format!(
+            "range would create an array of {} elements, more than the maximum of {}",
+            len, MAX_RANGE_LEN,
+        )));
+    }
+    Ok(Value::array((0..len).map(|i| Value::Int((start + i * step) as i64)).collect()))
+}
+
+/// `zip(a, b)`: A new array of two-element arrays pairing the elements of `a` and `b` at the same
+/// index. As long as the shorter one of the two.
+pub fn zip(args: &[Value]) -> Result<Value, Value> {
+    let a = array(args, 0, "zip")?.borrow().clone();
+    let b = array(args, 1, "zip")?.borrow().clone();
+    Ok(Value::array(a.into_iter().zip(b).map(|(a, b)| Value::array(vec![a, b])).collect()))
+}
+
+/// `enumerate(arr)`: A new array of two-element arrays pairing each index with its element.
+pub fn enumerate(args: &[Value]) -> Result<Value, Value> {
+    let arr = array(args, 0, "enumerate")?.borrow().clone();
+    Ok(Value::array(
+        arr.into_iter()
+            .enumerate()
+            .map(|(i, v)| Value::array(vec![Value::Int(i as i64), v]))
+            .collect(),
+    ))
+}
+
+/// `flatten(arr [, depth])`: A new array in which the elements that are arrays are replaced by
+/// their elements, recursively up to the given depth (which defaults to `1`). Throws an error of
+/// kind `"cycle"` if an array would have to be flattened into itself.
+pub fn flatten(args: &[Value]) -> Result<Value, Value> {
+    let arr = array(args, 0, "flatten")?;
+    let depth = match args.get(1) {
+        None | Some(Value::Nil) => 1,
+        Some(_) => int(args, 1, "flatten")?,
+    };
+    if depth < 0 {
+        return Err(Value::error("range", &format!(
+            "flatten expects a depth of at least zero, got {}", depth,
+        )));
+    }
+
+    let mut flattened = vec![];
+    flatten_into(&arr, depth, &mut vec![], &mut flattened)?;
+    Ok(Value::array(flattened))
+}
+
+// `enclosing` holds the addresses of the arrays currently being flattened.
+fn flatten_into(
+    arr: &Gc<Collection<Vec<Value>>>,
+    depth: i64,
+    enclosing: &mut Vec<usize>,
+    out: &mut Vec<Value>,
+) -> Result<(), Value> {
+    let address = &**arr as *const Collection<Vec<Value>> as usize;
+    if enclosing.contains(&address) {
+        return Err(Value::error("cycle", "flatten cannot flatten an array that contains itself"));
+    }
+
+    enclosing.push(address);
+    for v in arr.borrow().iter() {
+        match v {
+            Value::Array(inner) if depth > 0 => flatten_into(inner, depth - 1, enclosing, out)?,
+            _ => out.push(v.clone()),
+        }
+    }
+    enclosing.pop();
+    Ok(())
+}
+
+/// `chunks(arr, n)`: A new array of arrays of `n` consecutive elements each, the last one may be
+/// shorter. Throws an error of kind `"range"` unless `n` is positive.
+pub fn chunks(args: &[Value]) -> Result<Value, Value> {
+    let arr = array(args, 0, "chunks")?;
+    let n = int(args, 1, "chunks")?;
+    if n <= 0 {
+        return Err(Value::error("range", &format!(
+            "chunks expects a positive chunk size, got {}", n,
+        )));
+    }
+    let arr = arr.borrow();
+    Ok(Value::array(arr.chunks(n as usize).map(|chunk| Value::array(chunk.to_vec())).collect()))
+}
+
+fn array(args: &[Value], i: usize, name: &str) -> Result<Gc<Collection<Vec<Value>>>, Value> {
+    match args.get(i) {
+        Some(Value::Array(arr)) => Ok(arr.clone()),
+        other => Err(Value::error("type", &format!(
+            "{} expects an array, got {}", name, other.map_or("nil", Value::type_name),
+        ))),
+    }
+}
+
+// The array at position `i`, which the builtin is about to mutate.
+fn mutable_array(
+    args: &[Value],
+    i: usize,
+    name: &str,
+) -> Result<Gc<Collection<Vec<Value>>>, Value> {
+    let arr = array(args, i, name)?;
+    freeze::ensure_mutable(&arr, name)?;
+    Ok(arr)
+}
+
+// The value `v`, if it may be stored in the array.
+fn stored(arr: &Collection<Vec<Value>>, v: Value) -> Result<Value, Value> {
+    owner::check_stored(arr.owner(), &v)?;
+    Ok(v)
+}
+
+fn int(args: &[Value], i: usize, name: &str) -> Result<i64, Value> {
+    match args.get(i) {
+        Some(Value::Int(n)) => Ok(*n),
+        other => Err(Value::error("type", &format!(
+            "{} expects an int, got {}", name, other.map_or("nil", Value::type_name),
+        ))),
+    }
+}
+
+// The argument at position `i` as an index less than `bound`.
+fn index(args: &[Value], i: usize, bound: usize, name: &str) -> Result<usize, Value> {
+    match args.get(i) {
+        Some(Value::Int(n)) if 0 <= *n && (*n as u64) < bound as u64 => Ok(*n as usize),
+        Some(Value::Int(n)) => Err(Value::error("range", &format!(
+            "{} expects an index less than {}, got {}", name, bound, n,
+        ))),
+        other => Err(Value::error("type", &format!(
+            "{} expects an int as the index, got {}", name, other.map_or("nil", Value::type_name),
+        ))),
+    }
+}
+
+// Missing arguments are `nil`.
+fn arg(args: &[Value], i: usize) -> Value {
+    args.get(i).cloned().unwrap_or(Value::Nil)
+}
@@ -0,0 +1,118 @@
+// Builtins for writing tests in pan.
+//
+// A failed assertion throws an error of kind `"assertion"`. Failures of `assert_eq` and
+// `assert_ne` additionally have a `data` map with the fields `left` and `right`: the operands
+// printed in literal syntax, with collections nested deeper than `MAX_RENDER_DEPTH` abbreviated
+// as `...`. The message contains the same renderings, so that printing it suffices to diagnose
+// the failure.
+// Arguments of the wrong type throw an error of kind `"type"`.
+
+use crate::runtime::Runtime;
+use crate::types::exit;
+use crate::types::rope::Rope;
+use crate::value::Value;
+
+/// How many levels of nested collections failed assertions print.
+pub const MAX_RENDER_DEPTH: usize = 4;
+
+/// Register the assertion builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("assert", assert);
+    runtime.register("assert_eq", assert_eq);
+    runtime.register("assert_ne", assert_ne);
+    runtime.register("assert_throws", assert_throws);
+    runtime.register("fail", fail);
+}
+
+/// `assert(cond [, msg])`: Throw an assertion error with the message `msg` (a string) if `cond`
+/// is `nil` or `false`.
+pub fn assert(args: &[Value]) -> Result<Value, Value> {
+    let message = match args.get(1) {
+        None | Some(Value::Nil) => "assertion failed".to_string(),
+        Some(Value::String(msg)) => msg.to_string(),
+        Some(other) => return Err(type_error("a string as the message", Some(other), "assert")),
+    };
+    if args.first().is_some_and(Value::truthy) {
+        Ok(Value::Nil)
+    } else {
+        Err(Value::error("assertion", &message))
+    }
+}
+
+/// `assert_eq(a, b)`: Throw an assertion error if `a` and `b` are not equal.
+pub fn assert_eq(args: &[Value]) -> Result<Value, Value> {
+    let (a, b) = operands(args);
+    if a == b {
+        Ok(Value::Nil)
+    } else {
+        Err(operands_error("assert_eq failed, the values are not equal", &a, &b))
+    }
+}
+
+/// `assert_ne(a, b)`: Throw an assertion error if `a` and `b` are equal.
+pub fn assert_ne(args: &[Value]) -> Result<Value, Value> {
+    let (a, b) = operands(args);
+    if a != b {
+        Ok(Value::Nil)
+    } else {
+        Err(operands_error("assert_ne failed, the values are equal", &a, &b))
+    }
+}
+
+/// `assert_throws(f)`: Call `f` without arguments, and return what it throws. Throws an assertion
+/// error if `f` returns instead. Values that unwind to the embedder (e.g. exit values, see
+/// `types::exit::unwinds`) are rethrown rather than returned.
+pub fn assert_throws(args: &[Value]) -> Result<Value, Value> {
+    let f = match args.first() {
+        Some(f @ Value::Fun(_)) => f,
+        other => return Err(type_error("a function", other, "assert_throws")),
+    };
+    match f.apply(&[]) {
+        Err(thrown) if exit::unwinds(&thrown) => Err(thrown),
+        Err(thrown) => Ok(thrown),
+        Ok(returned) => Err(Value::error("assertion", &format!(
+            "assert_throws failed, the function returned {}",
+            returned.literal(MAX_RENDER_DEPTH),
+        ))),
+    }
+}
+
+/// `fail(msg)`: Throw an assertion error with the message `msg` (a string).
+pub fn fail(args: &[Value]) -> Result<Value, Value> {
+    match args.first() {
+        Some(Value::String(msg)) => Err(Value::error("assertion", &msg.to_string())),
+        other => Err(type_error("a string", other, "fail")),
+    }
+}
+
+/// If a thrown value is an assertion error, return its message for reporting a test failure.
+pub fn assertion_report(thrown: &Value) -> Option<String> {
+    match thrown.error_kind() {
+        Some(kind) if kind.to_string() == "assertion" => {
+            thrown.error_message().map(|message| message.to_string())
+        }
+        _ => None,
+    }
+}
+
+// Missing arguments are `nil`.
+fn operands(args: &[Value]) -> (Value, Value) {
+    let arg = |i| args.get(i).cloned().unwrap_or(Value::Nil);
+    (arg(0), arg(1))
+}
+
+fn operands_error(what: &str, a: &Value, b: &Value) -> Value {
+    let left = a.literal(MAX_RENDER_DEPTH).to_string();
+    let right = b.literal(MAX_RENDER_DEPTH).to_string();
+    let message = format!("{}\n  left: {}\n right: {}", what, left, right);
+    Value::error_with_data("assertion", &message, Value::record(vec![
+        ("left", Value::String(Rope::from_str(&left))),
+        ("right", Value::String(Rope::from_str(&right))),
+    ]))
+}
+
+fn type_error(expected: &str, got: Option<&Value>, name: &str) -> Value {
+    Value::error("type", &format!(
+        "{} expects {}, got {}", name, expected, got.map_or("nil", Value::type_name),
+    ))
+}
@@ -0,0 +1,104 @@
+// Bitwise builtins on ints.
+//
+// Ints are treated as 64 bit two's complement numbers, so `bit_not(0)` is `-1` and `shr(-8, 1)`
+// is `-4`. Shift and rotation amounts must be in `0..64`, other amounts throw an error of kind
+// `"range"` instead of being masked. All functions throw an error of kind `"type"` on arguments
+// that are not ints.
+
+use crate::runtime::Runtime;
+use crate::value::Value;
+
+/// Register the bitwise builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("bit_and", bit_and);
+    runtime.register("bit_or", bit_or);
+    runtime.register("bit_xor", bit_xor);
+    runtime.register("bit_not", bit_not);
+    runtime.register("shl", shl);
+    runtime.register("shr", shr);
+    runtime.register("shr_logical", shr_logical);
+    runtime.register("rotate_left", rotate_left);
+    runtime.register("rotate_right", rotate_right);
+    runtime.register("count_ones", count_ones);
+    runtime.register("leading_zeros", leading_zeros);
+}
+
+/// `bit_and(a, b)`: The bitwise and of two ints.
+pub fn bit_and(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Int(int(args, 0, "bit_and")? & int(args, 1, "bit_and")?))
+}
+
+/// `bit_or(a, b)`: The bitwise or of two ints.
+pub fn bit_or(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Int(int(args, 0, "bit_or")? | int(args, 1, "bit_or")?))
+}
+
+/// `bit_xor(a, b)`: The bitwise exclusive or of two ints.
+pub fn bit_xor(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Int(int(args, 0, "bit_xor")? ^ int(args, 1, "bit_xor")?))
+}
+
+/// `bit_not(a)`: Flip all bits of an int.
+pub fn bit_not(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Int(!int(args, 0, "bit_not")?))
+}
+
+/// `shl(a, n)`: Shift `a` left by `n` bits, filling with zeros. Bits shifted out are lost.
+pub fn shl(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Int(int(args, 0, "shl")? << amount(args, "shl")?))
+}
+
+/// `shr(a, n)`: Shift `a` right by `n` bits, filling with the sign bit.
+pub fn shr(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Int(int(args, 0, "shr")? >> amount(args, "shr")?))
+}
+
+/// `shr_logical(a, n)`: Shift `a` right by `n` bits, filling with zeros.
+pub fn shr_logical(args: &[Value]) -> Result<Value, Value> {
+    let a = int(args, 0, "shr_logical")?;
+    Ok(Value::Int(((a as u64) >> amount(args, "shr_logical")?) as i64))
+}
+
+/// `rotate_left(a, n)`: Rotate the bits of `a` left by `n`, bits shifted out at the top come back
+/// in at the bottom.
+pub fn rotate_left(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Int(int(args, 0, "rotate_left")?.rotate_left(amount(args, "rotate_left")?)))
+}
+
+/// `rotate_right(a, n)`: Rotate the bits of `a` right by `n`, bits shifted out at the bottom come
+/// back in at the top.
+pub fn rotate_right(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Int(int(args, 0, "rotate_right")?.rotate_right(amount(args, "rotate_right")?)))
+}
+
+/// `count_ones(a)`: The number of set bits of an int, negative ints have the sign bit set.
+pub fn count_ones(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Int(i64::from(int(args, 0, "count_ones")?.count_ones())))
+}
+
+/// `leading_zeros(a)`: The number of unset bits above the highest set bit, `64` for `0`.
+pub fn leading_zeros(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Int(i64::from(int(args, 0, "leading_zeros")?.leading_zeros())))
+}
+
+fn int(args: &[Value], i: usize, name: &str) -> Result<i64, Value> {
+    match args.get(i) {
+        Some(Value::Int(n)) => Ok(*n),
+        other => Err(Value::error("type", &format!(
+            "{} expects ints, got {}",
+            name, other.map_or("nil", Value::type_name),
+        ))),
+    }
+}
+
+// The shift or rotation amount, the second argument.
+fn amount(args: &[Value], name: &str) -> Result<u32, Value> {
+    let n = int(args, 1, name)?;
+    if (0..64).contains(&n) {
+        Ok(n as u32)
+    } else {
+        Err(Value::error("range", &format!(
+            "{} expects an amount between 0 and 63, got {}", name, n,
+        )))
+    }
+}
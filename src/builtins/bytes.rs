@@ -0,0 +1,379 @@
+// Builtins for working with bytes.
+//
+// Bytes are values, like strings: the builtins that change bytes (`bytes_set`, `bytes_fill`,
+// `bytes_copy_within` and the `bytes_write_*` family) return the changed bytes and leave their
+// argument as it was. The copy this requires is only made if the bytes are shared.
+//
+// Indices are ints counted from zero, and bytes are ints from 0 to 255. Indices outside the bytes
+// and ints that do not fit throw an error of kind `"range"`, malformed hex, base64 or utf-8 input
+// throws an error of kind `"decode"` whose message contains the offending byte offset. Arguments
+// of the wrong type throw an error of kind `"type"`.
+
+use std::convert::TryFrom;
+
+use ordered_float::OrderedFloat;
+
+use crate::runtime::Runtime;
+use crate::types::bytes::{Bytes, BytesError, DecodeError, Utf8Mode};
+use crate::types::rope::Rope;
+use crate::value::Value;
+
+/// Register the bytes builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("bytes_len", bytes_len);
+    runtime.register("bytes_get", bytes_get);
+    runtime.register("bytes_set", bytes_set);
+    runtime.register("bytes_slice", bytes_slice);
+    runtime.register("bytes_concat", bytes_concat);
+    runtime.register("bytes_find", bytes_find);
+    runtime.register("bytes_split", bytes_split);
+    runtime.register("bytes_fill", bytes_fill);
+    runtime.register("bytes_copy_within", bytes_copy_within);
+    runtime.register("bytes_to_hex", bytes_to_hex);
+    runtime.register("bytes_from_hex", bytes_from_hex);
+    runtime.register("bytes_to_base64", bytes_to_base64);
+    runtime.register("bytes_from_base64", bytes_from_base64);
+    runtime.register("bytes_from_int_le", bytes_from_int_le);
+    runtime.register("bytes_from_int_be", bytes_from_int_be);
+    runtime.register("bytes_decode_utf8", bytes_decode_utf8);
+    runtime.register("str_encode_utf8", str_encode_utf8);
+
+    runtime.register("bytes_read_u8", bytes_read_u8);
+    runtime.register("bytes_write_u8", bytes_write_u8);
+    runtime.register("bytes_read_u16_le", bytes_read_u16_le);
+    runtime.register("bytes_read_u16_be", bytes_read_u16_be);
+    runtime.register("bytes_write_u16_le", bytes_write_u16_le);
+    runtime.register("bytes_write_u16_be", bytes_write_u16_be);
+    runtime.register("bytes_read_u32_le", bytes_read_u32_le);
+    runtime.register("bytes_read_u32_be", bytes_read_u32_be);
+    runtime.register("bytes_write_u32_le", bytes_write_u32_le);
+    runtime.register("bytes_write_u32_be", bytes_write_u32_be);
+    runtime.register("bytes_read_u64_le", bytes_read_u64_le);
+    runtime.register("bytes_read_u64_be", bytes_read_u64_be);
+    runtime.register("bytes_write_u64_le", bytes_write_u64_le);
+    runtime.register("bytes_write_u64_be", bytes_write_u64_be);
+    runtime.register("bytes_read_i64_le", bytes_read_i64_le);
+    runtime.register("bytes_read_i64_be", bytes_read_i64_be);
+    runtime.register("bytes_write_i64_le", bytes_write_i64_le);
+    runtime.register("bytes_write_i64_be", bytes_write_i64_be);
+    runtime.register("bytes_read_f64_le", bytes_read_f64_le);
+    runtime.register("bytes_read_f64_be", bytes_read_f64_be);
+    runtime.register("bytes_write_f64_le", bytes_write_f64_le);
+    runtime.register("bytes_write_f64_be", bytes_write_f64_be);
+}
+
+/// `bytes_len(b)`: The number of bytes.
+pub fn bytes_len(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Int(bytes(args, 0, "bytes_len")?.len() as i64))
+}
+
+/// `bytes_get(b, i)`: The byte at index `i`.
+pub fn bytes_get(args: &[Value]) -> Result<Value, Value> {
+    let b = bytes(args, 0, "bytes_get")?;
+    let i = index(args, 1, "bytes_get")?;
+    b.get_checked(i).map(|byte| Value::Int(i64::from(byte))).map_err(|err| {
+        bytes_error(err, "bytes_get")
+    })
+}
+
+/// `bytes_set(b, i, byte)`: The bytes with the byte at index `i` replaced by `byte`.
+pub fn bytes_set(args: &[Value]) -> Result<Value, Value> {
+    let mut b = bytes(args, 0, "bytes_set")?;
+    let i = index(args, 1, "bytes_set")?;
+    let byte = byte(args, 2, "bytes_set")?;
+    b.set_checked(i, byte).map_err(|err| bytes_error(err, "bytes_set"))?;
+    Ok(Value::Bytes(b))
+}
+
+/// `bytes_slice(b, start [, end])`: The bytes from index `start` up to (excluding) index `end`,
+/// which defaults to the length of the bytes.
+pub fn bytes_slice(args: &[Value]) -> Result<Value, Value> {
+    let b = bytes(args, 0, "bytes_slice")?;
+    let start = index(args, 1, "bytes_slice")?;
+    let sliced = match args.get(2) {
+        None | Some(Value::Nil) => b.slice_from(start),
+        Some(_) => b.slice(start..index(args, 2, "bytes_slice")?),
+    };
+    sliced.map(Value::Bytes).map_err(|err| bytes_error(err, "bytes_slice"))
+}
+
+/// `bytes_concat(a, b)`: The bytes of `a` followed by those of `b`.
+pub fn bytes_concat(args: &[Value]) -> Result<Value, Value> {
+    let a = bytes(args, 0, "bytes_concat")?;
+    let b = bytes(args, 1, "bytes_concat")?;
+    Ok(Value::Bytes(a.concat(&b)))
+}
+
+/// `bytes_find(b, needle [, from])`: The index of the first occurrence of the bytes `needle` that
+/// starts at or after index `from` (default `0`), or `nil` if there is none.
+pub fn bytes_find(args: &[Value]) -> Result<Value, Value> {
+    let b = bytes(args, 0, "bytes_find")?;
+    let needle = bytes(args, 1, "bytes_find")?.to_vec();
+    let from = match args.get(2) {
+        None | Some(Value::Nil) => 0,
+        Some(_) => index(args, 2, "bytes_find")?,
+    };
+    Ok(b.find(&needle, from).map_or(Value::Nil, |i| Value::Int(i as i64)))
+}
+
+/// `bytes_split(b, sep)`: An array of the bytes between the occurrences of the bytes `sep`, one
+/// more than there are occurrences. Throws an error of kind `"range"` if `sep` is empty.
+pub fn bytes_split(args: &[Value]) -> Result<Value, Value> {
+    let b = bytes(args, 0, "bytes_split")?;
+    let sep = bytes(args, 1, "bytes_split")?.to_vec();
+    let parts = b.split(&sep).map_err(|err| bytes_error(err, "bytes_split"))?;
+    Ok(Value::array(parts.map(Value::Bytes).collect()))
+}
+
+/// `bytes_fill(b, start, end, byte)`: The bytes with all bytes from index `start` up to
+/// (excluding) index `end` replaced by `byte`.
+pub fn bytes_fill(args: &[Value]) -> Result<Value, Value> {
+    let mut b = bytes(args, 0, "bytes_fill")?;
+    let start = index(args, 1, "bytes_fill")?;
+    let end = index(args, 2, "bytes_fill")?;
+    let byte = byte(args, 3, "bytes_fill")?;
+    b.fill(start..end, byte).map_err(|err| bytes_error(err, "bytes_fill"))?;
+    Ok(Value::Bytes(b))
+}
+
+/// `bytes_copy_within(b, start, end, dest)`: The bytes with the bytes from index `start` up to
+/// (excluding) index `end` copied to index `dest`. The two ranges may overlap.
+pub fn bytes_copy_within(args: &[Value]) -> Result<Value, Value> {
+    let mut b = bytes(args, 0, "bytes_copy_within")?;
+    let start = index(args, 1, "bytes_copy_within")?;
+    let end = index(args, 2, "bytes_copy_within")?;
+    let dest = index(args, 3, "bytes_copy_within")?;
+    b.copy_within(start..end, dest).map_err(|err| bytes_error(err, "bytes_copy_within"))?;
+    Ok(Value::Bytes(b))
+}
+
+/// `bytes_to_hex(b)`: The bytes as a string of lowercase hex digits, two per byte.
+pub fn bytes_to_hex(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::String(bytes(args, 0, "bytes_to_hex")?.to_hex()))
+}
+
+/// `bytes_from_hex(s)`: The bytes encoded by a string of hex digits (in either case).
+pub fn bytes_from_hex(args: &[Value]) -> Result<Value, Value> {
+    let s = string(args, 0, "bytes_from_hex")?.to_string();
+    Bytes::from_hex(&s).map(Value::Bytes).map_err(|err| decode_error(err, "bytes_from_hex"))
+}
+
+/// `bytes_to_base64(b)`: The bytes as a string in the standard base64 encoding, with padding.
+pub fn bytes_to_base64(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::String(bytes(args, 0, "bytes_to_base64")?.to_base64()))
+}
+
+/// `bytes_from_base64(s)`: The bytes encoded by a string in the standard base64 encoding, with
+/// padding.
+pub fn bytes_from_base64(args: &[Value]) -> Result<Value, Value> {
+    let s = string(args, 0, "bytes_from_base64")?.to_string();
+    Bytes::from_base64(&s).map(Value::Bytes).map_err(|err| decode_error(err, "bytes_from_base64"))
+}
+
+/// `bytes_from_int_le(n, width)`: `n` as `width` (1 to 8) little-endian bytes. `n` must fit into
+/// a signed or an unsigned int of that width.
+pub fn bytes_from_int_le(args: &[Value]) -> Result<Value, Value> {
+    let (n, width) = (int(args, 0, "bytes_from_int_le")?, index(args, 1, "bytes_from_int_le")?);
+    Bytes::from_int_le(n, width).map(Value::Bytes).map_err(|err| {
+        bytes_error(err, "bytes_from_int_le")
+    })
+}
+
+/// `bytes_from_int_be(n, width)`: `n` as `width` (1 to 8) big-endian bytes. `n` must fit into a
+/// signed or an unsigned int of that width.
+pub fn bytes_from_int_be(args: &[Value]) -> Result<Value, Value> {
+    let (n, width) = (int(args, 0, "bytes_from_int_be")?, index(args, 1, "bytes_from_int_be")?);
+    Bytes::from_int_be(n, width).map(Value::Bytes).map_err(|err| {
+        bytes_error(err, "bytes_from_int_be")
+    })
+}
+
+/// `bytes_decode_utf8(b [, mode])`: Decode the bytes as utf-8. The mode says what happens to
+/// invalid input:
+///
+/// - `"strict"` (the default): throw.
+/// - `"replace"`: replace every invalid sequence by U+FFFD.
+/// - `"prefix"`: throw, unless the only problem is an incomplete char at the end. Returns a map
+///   `{"text": s, "rest": r}` of the decoded text and the undecoded bytes at the end, which
+///   should be prepended to the next bytes of the input.
+pub fn bytes_decode_utf8(args: &[Value]) -> Result<Value, Value> {
+    let b = bytes(args, 0, "bytes_decode_utf8")?;
+    let mode = match args.get(1) {
+        None | Some(Value::Nil) => Utf8Mode::Strict,
+        Some(Value::String(mode)) => match mode.to_string().as_str() {
+            "strict" => Utf8Mode::Strict,
+            "replace" => Utf8Mode::Replace,
+            "prefix" => Utf8Mode::Prefix,
+            other => {
+                return Err(Value::error("range", &format!(
+                    "bytes_decode_utf8 expects the mode \"strict\", \"replace\" or \"prefix\", \
+                     got {:?}",
+                    other,
+                )));
+            }
+        },
+        Some(other) => {
+            return Err(Value::error("type", &format!(
+                "bytes_decode_utf8 expects a string as the mode, got {}", other.type_name(),
+            )));
+        }
+    };
+
+    let (text, rest) = b.decode_utf8(mode).map_err(|err| decode_error(err, "bytes_decode_utf8"))?;
+    Ok(match mode {
+        Utf8Mode::Prefix => Value::record(vec![
+            ("text", Value::String(text)),
+            ("rest", Value::Bytes(rest)),
+        ]),
+        _ => Value::String(text),
+    })
+}
+
+/// `str_encode_utf8(s)`: The utf-8 encoding of a string.
+pub fn str_encode_utf8(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Bytes(Bytes::encode_utf8_of(&string(args, 0, "str_encode_utf8")?)))
+}
+
+/// `bytes_read_u8(b, offset)`: The byte at the given offset.
+pub fn bytes_read_u8(args: &[Value]) -> Result<Value, Value> {
+    bytes_get(args)
+}
+
+/// `bytes_write_u8(b, offset, byte)`: The bytes with the byte at the given offset replaced.
+pub fn bytes_write_u8(args: &[Value]) -> Result<Value, Value> {
+    bytes_set(args)
+}
+
+// Generates the builtins for reading and writing a number type at a byte offset, in both byte
+// orders. `$to_pan` converts a read number to a value, `$from_pan` takes the number to write from
+// the third argument.
+macro_rules! endian_builtins {
+    ($t:ty, $to_pan:expr, $from_pan:expr,
+     $($builtin_read:ident $read:ident $builtin_write:ident $write:ident),*) => {
+        $(
+            #[doc = concat!("`", stringify!($builtin_read), "(b, offset)`: Read a `",
+                stringify!($t), "` at the given byte offset.")]
+            pub fn $builtin_read(args: &[Value]) -> Result<Value, Value> {
+                let name = stringify!($builtin_read);
+                let b = bytes(args, 0, name)?;
+                let offset = index(args, 1, name)?;
+                let n = b.$read(offset).map_err(|err| bytes_error(err, name))?;
+                $to_pan(n, name)
+            }
+
+            #[doc = concat!("`", stringify!($builtin_write), "(b, offset, n)`: The bytes with `n` \
+                written as a `", stringify!($t), "` at the given byte offset.")]
+            pub fn $builtin_write(args: &[Value]) -> Result<Value, Value> {
+                let name = stringify!($builtin_write);
+                let mut b = bytes(args, 0, name)?;
+                let offset = index(args, 1, name)?;
+                let n: $t = $from_pan(args, name)?;
+                b.$write(offset, n).map_err(|err| bytes_error(err, name))?;
+                Ok(Value::Bytes(b))
+            }
+        )*
+    };
+}
+
+endian_builtins!(u16, read_unsigned, write_unsigned,
+    bytes_read_u16_le read_u16_le bytes_write_u16_le write_u16_le,
+    bytes_read_u16_be read_u16_be bytes_write_u16_be write_u16_be);
+endian_builtins!(u32, read_unsigned, write_unsigned,
+    bytes_read_u32_le read_u32_le bytes_write_u32_le write_u32_le,
+    bytes_read_u32_be read_u32_be bytes_write_u32_be write_u32_be);
+endian_builtins!(u64, read_unsigned, write_unsigned,
+    bytes_read_u64_le read_u64_le bytes_write_u64_le write_u64_le,
+    bytes_read_u64_be read_u64_be bytes_write_u64_be write_u64_be);
+endian_builtins!(i64, |n, _| Ok(Value::Int(n)), |args, name| int(args, 2, name),
+    bytes_read_i64_le read_i64_le bytes_write_i64_le write_i64_le,
+    bytes_read_i64_be read_i64_be bytes_write_i64_be write_i64_be);
+endian_builtins!(f64, |n, _| Ok(Value::Float(OrderedFloat(n))), write_float,
+    bytes_read_f64_le read_f64_le bytes_write_f64_le write_f64_le,
+    bytes_read_f64_be read_f64_be bytes_write_f64_be write_f64_be);
+
+// An unsigned number that was read, as an int. Throws if it is too large.
+fn read_unsigned<T: Into<u64>>(n: T, name: &str) -> Result<Value, Value> {
+    let n: u64 = n.into();
+    if n > i64::MAX as u64 {
+        Err(Value::error("range", &format!("{} read {}, which does not fit into an int", name, n)))
+    } else {
+        Ok(Value::Int(n as i64))
+    }
+}
+
+// The third argument as an unsigned number to write. Throws if it does not fit.
+fn write_unsigned<T: TryFrom<i64>>(args: &[Value], name: &str) -> Result<T, Value> {
+    let n = int(args, 2, name)?;
+    T::try_from(n).map_err(|_| {
+        Value::error("range", &format!(
+            "{} expects an int from 0 to {}, got {}", name, max_unsigned::<T>(), n,
+        ))
+    })
+}
+
+// The largest value of an unsigned number type, given by its size.
+fn max_unsigned<T>() -> u64 {
+    u64::MAX >> (64 - 8 * std::mem::size_of::<T>())
+}
+
+// The third argument as a float to write. Ints are converted.
+fn write_float(args: &[Value], name: &str) -> Result<f64, Value> {
+    match args.get(2) {
+        Some(Value::Float(x)) => Ok(x.into_inner()),
+        Some(Value::Int(n)) => Ok(*n as f64),
+        other => Err(type_error("a number", other, name)),
+    }
+}
+
+fn bytes(args: &[Value], i: usize, name: &str) -> Result<Bytes, Value> {
+    match args.get(i) {
+        Some(Value::Bytes(b)) => Ok(b.clone()),
+        other => Err(type_error("bytes", other, name)),
+    }
+}
+
+fn string(args: &[Value], i: usize, name: &str) -> Result<Rope, Value> {
+    match args.get(i) {
+        Some(Value::String(s)) => Ok(s.clone()),
+        other => Err(type_error("a string", other, name)),
+    }
+}
+
+fn int(args: &[Value], i: usize, name: &str) -> Result<i64, Value> {
+    match args.get(i) {
+        Some(Value::Int(n)) => Ok(*n),
+        other => Err(type_error("an int", other, name)),
+    }
+}
+
+fn index(args: &[Value], i: usize, name: &str) -> Result<usize, Value> {
+    let n = int(args, i, name)?;
+    if n >= 0 {
+        Ok(n as usize)
+    } else {
+        Err(Value::error("range", &format!("{} expects a non-negative int, got {}", name, n)))
+    }
+}
+
+fn byte(args: &[Value], i: usize, name: &str) -> Result<u8, Value> {
+    let n = int(args, i, name)?;
+    if (0..=255).contains(&n) {
+        Ok(n as u8)
+    } else {
+        Err(Value::error("range", &format!("{} expects a byte (0 to 255), got {}", name, n)))
+    }
+}
+
+fn type_error(expected: &str, got: Option<&Value>, name: &str) -> Value {
+    Value::error("type", &format!(
+        "{} expects {}, got {}", name, expected, got.map_or("nil", Value::type_name),
+    ))
+}
+
+fn bytes_error(err: BytesError, name: &str) -> Value {
+    Value::error("range", &format!("{} failed: {}", name, err))
+}
+
+fn decode_error(err: DecodeError, name: &str) -> Value {
+    Value::error("decode", &format!("{} failed: {}", name, err))
+}
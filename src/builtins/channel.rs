@@ -0,0 +1,58 @@
+// Builtins for working with channels.
+
+use std::convert::TryFrom;
+
+use crate::runtime::Runtime;
+use crate::types::{
+    channel::Channel,
+    futures::Future,
+};
+use crate::value::Value;
+
+/// Register the channel builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("chan_new", chan_new);
+    runtime.register("chan_send", chan_send);
+    runtime.register("chan_recv", chan_recv);
+    runtime.register("chan_close", chan_close);
+}
+
+/// `chan_new(capacity)`: A new channel that buffers up to `capacity` values.
+pub fn chan_new(args: &[Value]) -> Result<Value, Value> {
+    match args.first() {
+        Some(Value::Int(capacity)) if *capacity >= 0 => {
+            let capacity = usize::try_from(*capacity).unwrap_or(usize::MAX);
+            Ok(Value::Channel(Channel::new(capacity)))
+        }
+        _ => Err(Value::error("type", "chan_new expects a non-negative int")),
+    }
+}
+
+/// `chan_send(ch, v)`: A future that sends `v` over the channel `ch` once it is run. It resolves
+/// to `nil` once the value has been taken by a receiver or buffered, and rejects with an error of
+/// kind `"closed"` if the channel is or becomes closed first.
+pub fn chan_send(args: &[Value]) -> Result<Value, Value> {
+    let chan = channel(args, "chan_send")?;
+    let v = args.get(1).cloned().unwrap_or(Value::Nil);
+    Ok(Value::Future(Future::send(chan, v)))
+}
+
+/// `chan_recv(ch)`: A future that receives the next value from the channel `ch` once it is run.
+/// Rejects with an error of kind `"closed"` if the channel is closed and no buffered values
+/// remain.
+pub fn chan_recv(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Future(Future::recv(channel(args, "chan_recv")?)))
+}
+
+/// `chan_close(ch)`: Close the channel `ch`, returns whether it was open. Waiting sends and
+/// receives reject, buffered values can still be received.
+pub fn chan_close(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Bool(channel(args, "chan_close")?.close()))
+}
+
+fn channel(args: &[Value], name: &str) -> Result<Channel, Value> {
+    match args.first() {
+        Some(Value::Channel(chan)) => Ok(chan.clone()),
+        _ => Err(Value::error("type", &format!("{} expects a channel", name))),
+    }
+}
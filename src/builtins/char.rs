@@ -0,0 +1,137 @@
+// Builtins for working with chars.
+//
+// The classification functions use the unicode definitions, so e.g. `is_alpha('é')` is `true`.
+// Arguments of the wrong type throw an error of kind `"type"`.
+
+use std::convert::TryFrom;
+
+use crate::runtime::Runtime;
+use crate::value::Value;
+
+/// Register the char builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("char_to_int", char_to_int);
+    runtime.register("int_to_char", int_to_char);
+    runtime.register("is_alpha", is_alpha);
+    runtime.register("is_digit", is_digit);
+    runtime.register("is_alphanumeric", is_alphanumeric);
+    runtime.register("is_whitespace", is_whitespace);
+    runtime.register("is_upper", is_upper);
+    runtime.register("is_lower", is_lower);
+    runtime.register("char_to_upper", char_to_upper);
+    runtime.register("char_to_lower", char_to_lower);
+    runtime.register("digit_value", digit_value);
+}
+
+/// `char_to_int(c)`: The code point of a char.
+pub fn char_to_int(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Int(i64::from(u32::from(character(args, "char_to_int")?))))
+}
+
+/// `int_to_char(n)`: The char with the code point `n`. Throws an error of kind `"range"` if `n` is
+/// negative, a surrogate (`0xD800` to `0xDFFF`) or greater than `0x10FFFF`.
+pub fn int_to_char(args: &[Value]) -> Result<Value, Value> {
+    match args.first() {
+        Some(Value::Int(n)) => {
+            let c = u32::try_from(*n).ok().and_then(char::from_u32);
+            c.map(Value::Char).ok_or_else(|| {
+                let reason = if (0xD800..=0xDFFF).contains(n) {
+                    "it is a surrogate"
+                } else {
+                    "it is not a code point"
+                };
+                Value::error("range", &format!(
+                    "int_to_char cannot convert {} to a char, {}", n, reason,
+                ))
+            })
+        }
+        other => Err(Value::error("type", &format!(
+            "int_to_char expects an int, got {}", other.map_or("nil", Value::type_name),
+        ))),
+    }
+}
+
+/// `is_alpha(c)`: Whether a char is alphabetic.
+pub fn is_alpha(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Bool(character(args, "is_alpha")?.is_alphabetic()))
+}
+
+/// `is_digit(c)`: Whether a char is numeric, e.g. `'7'`, `'٣'` or `'½'`. Use `digit_value` to
+/// check for the digits of a specific base.
+pub fn is_digit(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Bool(character(args, "is_digit")?.is_numeric()))
+}
+
+/// `is_alphanumeric(c)`: Whether a char is alphabetic or numeric.
+pub fn is_alphanumeric(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Bool(character(args, "is_alphanumeric")?.is_alphanumeric()))
+}
+
+/// `is_whitespace(c)`: Whether a char is whitespace.
+pub fn is_whitespace(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Bool(character(args, "is_whitespace")?.is_whitespace()))
+}
+
+/// `is_upper(c)`: Whether a char is uppercase.
+pub fn is_upper(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Bool(character(args, "is_upper")?.is_uppercase()))
+}
+
+/// `is_lower(c)`: Whether a char is lowercase.
+pub fn is_lower(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Bool(character(args, "is_lower")?.is_lowercase()))
+}
+
+/// `char_to_upper(c)`: The uppercase version of a char. Chars whose uppercase version consists of
+/// several chars (e.g. `'ß'`, which uppercases to `"SS"`) are returned unchanged, use
+/// `str_to_upper` to convert those.
+pub fn char_to_upper(args: &[Value]) -> Result<Value, Value> {
+    let c = character(args, "char_to_upper")?;
+    Ok(Value::Char(single(c, c.to_uppercase())))
+}
+
+/// `char_to_lower(c)`: The lowercase version of a char. Chars whose lowercase version consists of
+/// several chars (e.g. `'İ'`) are returned unchanged, use `str_to_lower` to convert those.
+pub fn char_to_lower(args: &[Value]) -> Result<Value, Value> {
+    let c = character(args, "char_to_lower")?;
+    Ok(Value::Char(single(c, c.to_lowercase())))
+}
+
+/// `digit_value(c [, base])`: The value of a char as a digit in the given base (2 to 36, default
+/// 10), or `nil` if it is not a digit in that base. Digits above 9 are the ascii letters in
+/// either case.
+pub fn digit_value(args: &[Value]) -> Result<Value, Value> {
+    let c = character(args, "digit_value")?;
+    let base = match args.get(1) {
+        None | Some(Value::Nil) => 10,
+        Some(Value::Int(base)) if (2..=36).contains(base) => *base as u32,
+        Some(Value::Int(base)) => {
+            return Err(Value::error("range", &format!(
+                "digit_value expects a base between 2 and 36, got {}", base,
+            )));
+        }
+        Some(other) => {
+            return Err(Value::error("type", &format!(
+                "digit_value expects an int as the base, got {}", other.type_name(),
+            )));
+        }
+    };
+    Ok(c.to_digit(base).map_or(Value::Nil, |d| Value::Int(i64::from(d))))
+}
+
+// The only char of a case mapping, or `c` if the mapping has several chars.
+fn single(c: char, mut mapped: impl Iterator<Item = char>) -> char {
+    match (mapped.next(), mapped.next()) {
+        (Some(m), None) => m,
+        _ => c,
+    }
+}
+
+fn character(args: &[Value], name: &str) -> Result<char, Value> {
+    match args.first() {
+        Some(Value::Char(c)) => Ok(*c),
+        other => Err(Value::error("type", &format!(
+            "{} expects a char, got {}", name, other.map_or("nil", Value::type_name),
+        ))),
+    }
+}
@@ -0,0 +1,173 @@
+// Equality and comparison builtins.
+//
+// `eq` and `neq` accept any two values. Equality is structural for collections and strings, and
+// by identity for functions, futures, channels and streams. Floats are compared via their total
+// order, so `NaN` equals itself and ints never equal floats.
+//
+// The ordered comparisons only accept two values of the same type and throw an error of kind
+// `"type"` naming both types otherwise, so `lt(1, 1.5)` and `lt(1, "a")` throw. Within a type,
+// values are ordered by the structural order of `Value`: numbers and chars by magnitude (`NaN` is
+// greater than every other float), strings and bytes lexicographically, and collections
+// lexicographically by their elements (where elements of different types are ordered by the
+// global type order).
+//
+// `min`, `max` and `clamp` follow the same policy: all their arguments must have the same type.
+// Among equal values, `min` and `max` return the first one. As `NaN` is the greatest float,
+// `max` returns it if it is an argument, and `clamp` with an upper bound of `NaN` does not limit
+// `x` from above. A lower bound of `NaN` throws an error of kind `"range"` (unless the upper bound
+// is `NaN` as well), like any other lower bound that is greater than the upper bound.
+
+use std::cmp::Ordering;
+
+use crate::runtime::Runtime;
+use crate::value::Value;
+
+/// Register the comparison builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("eq", eq);
+    runtime.register("neq", neq);
+    runtime.register("lt", lt);
+    runtime.register("lte", lte);
+    runtime.register("gt", gt);
+    runtime.register("gte", gte);
+    runtime.register("compare", compare);
+    runtime.register("min", min);
+    runtime.register("max", max);
+    runtime.register("clamp", clamp);
+    runtime.register("arr_min", arr_min);
+    runtime.register("arr_max", arr_max);
+}
+
+/// `eq(a, b)`: Whether two values are equal.
+pub fn eq(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Bool(arg(args, 0) == arg(args, 1)))
+}
+
+/// `neq(a, b)`: Whether two values are not equal.
+pub fn neq(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Bool(arg(args, 0) != arg(args, 1)))
+}
+
+/// `lt(a, b)`: Whether `a` is less than `b`.
+pub fn lt(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Bool(ordering(args, "lt")? == Ordering::Less))
+}
+
+/// `lte(a, b)`: Whether `a` is less than or equal to `b`.
+pub fn lte(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Bool(ordering(args, "lte")? != Ordering::Greater))
+}
+
+/// `gt(a, b)`: Whether `a` is greater than `b`.
+pub fn gt(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Bool(ordering(args, "gt")? == Ordering::Greater))
+}
+
+/// `gte(a, b)`: Whether `a` is greater than or equal to `b`.
+pub fn gte(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Bool(ordering(args, "gte")? != Ordering::Less))
+}
+
+/// `compare(a, b)`: `-1` if `a` is less than `b`, `0` if they are equal, `1` if `a` is greater.
+/// Can be used as the comparator for sorting.
+pub fn compare(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Int(match ordering(args, "compare")? {
+        Ordering::Less => -1,
+        Ordering::Equal => 0,
+        Ordering::Greater => 1,
+    }))
+}
+
+/// `min(a, b, ...)`: The least of the arguments, of which there must be at least one.
+pub fn min(args: &[Value]) -> Result<Value, Value> {
+    at_least_one(extremum(args, Ordering::Less, "min")?, "min")
+}
+
+/// `max(a, b, ...)`: The greatest of the arguments, of which there must be at least one.
+pub fn max(args: &[Value]) -> Result<Value, Value> {
+    at_least_one(extremum(args, Ordering::Greater, "max")?, "max")
+}
+
+/// `clamp(x, lo, hi)`: `lo` if `x` is less than `lo`, `hi` if `x` is greater than `hi`, `x`
+/// otherwise. Throws an error of kind `"range"` if `lo` is greater than `hi`.
+pub fn clamp(args: &[Value]) -> Result<Value, Value> {
+    let (x, lo, hi) = (arg(args, 0), arg(args, 1), arg(args, 2));
+    checked_cmp(&x, &lo, "clamp")?;
+    if checked_cmp(&lo, &hi, "clamp")? == Ordering::Greater {
+        return Err(Value::error("range", &format!(
+            "clamp expects a lower bound that is not greater than the upper bound, got {} and {}",
+            lo, hi,
+        )));
+    }
+    Ok(if x < lo {
+        lo
+    } else if x > hi {
+        hi
+    } else {
+        x
+    })
+}
+
+/// `arr_min(arr)`: The least element of the array, or `nil` if it is empty.
+pub fn arr_min(args: &[Value]) -> Result<Value, Value> {
+    let arr = array(args, "arr_min")?;
+    let least = extremum(&arr, Ordering::Less, "arr_min")?;
+    Ok(least.unwrap_or(Value::Nil))
+}
+
+/// `arr_max(arr)`: The greatest element of the array, or `nil` if it is empty.
+pub fn arr_max(args: &[Value]) -> Result<Value, Value> {
+    let arr = array(args, "arr_max")?;
+    let greatest = extremum(&arr, Ordering::Greater, "arr_max")?;
+    Ok(greatest.unwrap_or(Value::Nil))
+}
+
+/// Compare two values of the same type, or throw an error naming both types.
+pub(crate) fn checked_cmp(a: &Value, b: &Value, name: &str) -> Result<Ordering, Value> {
+    if a.type_name() == b.type_name() {
+        Ok(a.cmp(b))
+    } else {
+        Err(Value::error("type", &format!(
+            "{} expects two values of the same type, got {} and {}",
+            name, a.type_name(), b.type_name(),
+        )))
+    }
+}
+
+fn ordering(args: &[Value], name: &str) -> Result<Ordering, Value> {
+    checked_cmp(&arg(args, 0), &arg(args, 1), name)
+}
+
+// The first of the values that no other value is ordered before (for `Ordering::Less`) or after
+// (for `Ordering::Greater`), or `None` if there are no values.
+fn extremum(values: &[Value], wanted: Ordering, name: &str) -> Result<Option<Value>, Value> {
+    let mut best: Option<&Value> = None;
+    for v in values {
+        match best {
+            // Compare in argument order, so that type errors name the types in that order.
+            Some(b) if checked_cmp(b, v, name)? != wanted.reverse() => {}
+            _ => best = Some(v),
+        }
+    }
+    Ok(best.cloned())
+}
+
+fn at_least_one(v: Option<Value>, name: &str) -> Result<Value, Value> {
+    v.ok_or_else(|| Value::error("type", &format!("{} expects at least one argument", name)))
+}
+
+// A copy of the elements of the array that is the first argument, so that comparing them can not
+// observe a borrow of the array.
+fn array(args: &[Value], name: &str) -> Result<Vec<Value>, Value> {
+    match args.first() {
+        Some(Value::Array(arr)) => Ok(arr.borrow().clone()),
+        other => Err(Value::error("type", &format!(
+            "{} expects an array, got {}", name, other.map_or("nil", Value::type_name),
+        ))),
+    }
+}
+
+// Missing arguments are `nil`.
+fn arg(args: &[Value], i: usize) -> Value {
+    args.get(i).cloned().unwrap_or(Value::Nil)
+}
@@ -0,0 +1,137 @@
+// Builtins that convert between numbers, chars and strings. The syntax of numbers is defined in
+// `value::num`.
+
+use ordered_float::OrderedFloat;
+
+use crate::runtime::Runtime;
+use crate::types::rope::{Rope, RopeBuilder};
+use crate::value::num::{self, ParseNumberError};
+use crate::value::{ops, Value};
+
+/// Register the conversion builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("to_int", to_int);
+    runtime.register("to_float", to_float);
+    runtime.register("parse_int", parse_int);
+    runtime.register("parse_float", parse_float);
+    runtime.register("to_string", to_string);
+}
+
+/// `to_int(v)`: Convert a number, char or string to an int.
+///
+/// Floats are truncated towards zero, NaN and floats outside the range of ints throw an error of
+/// kind `"range"`. Chars are converted to their code point. Strings are parsed in base 10 like
+/// `parse_int`.
+pub fn to_int(args: &[Value]) -> Result<Value, Value> {
+    match args.first() {
+        Some(Value::Int(n)) => Ok(Value::Int(*n)),
+        Some(Value::Float(x)) => ops::float_to_int(x.trunc()).map(Value::Int).ok_or_else(|| {
+            Value::error("range", &format!(
+                "to_int cannot convert {} to an int", num::format_float(x.into_inner()),
+            ))
+        }),
+        Some(Value::Char(c)) => Ok(Value::Int(i64::from(u32::from(*c)))),
+        Some(Value::String(s)) => parse_int_str(s, 10, "to_int"),
+        other => Err(Value::error("type", &format!(
+            "to_int expects a number, char or string, got {}",
+            other.map_or("nil", Value::type_name),
+        ))),
+    }
+}
+
+/// `to_float(v)`: Convert a number or string to a float.
+///
+/// Ints are converted to the nearest float. Strings are parsed like `parse_float`.
+pub fn to_float(args: &[Value]) -> Result<Value, Value> {
+    match args.first() {
+        Some(Value::Int(n)) => Ok(Value::Float(OrderedFloat(*n as f64))),
+        Some(Value::Float(x)) => Ok(Value::Float(*x)),
+        Some(Value::String(s)) => parse_float_str(s, "to_float"),
+        other => Err(Value::error("type", &format!(
+            "to_float expects a number or string, got {}",
+            other.map_or("nil", Value::type_name),
+        ))),
+    }
+}
+
+/// `parse_int(s [, base])`: Parse a string as an int in the given base (2 to 36, default 10).
+///
+/// Throws an error of kind `"parse"` if the string is not an int, and of kind `"range"` if the
+/// base is invalid or the int does not fit into 64 bits.
+pub fn parse_int(args: &[Value]) -> Result<Value, Value> {
+    let base = match args.get(1) {
+        None | Some(Value::Nil) => 10,
+        Some(Value::Int(base)) if (2..=36).contains(base) => *base as u32,
+        Some(Value::Int(base)) => {
+            return Err(Value::error("range", &format!(
+                "parse_int expects a base between 2 and 36, got {}", base,
+            )));
+        }
+        Some(other) => {
+            return Err(Value::error("type", &format!(
+                "parse_int expects an int as the base, got {}", other.type_name(),
+            )));
+        }
+    };
+    match args.first() {
+        Some(Value::String(s)) => parse_int_str(s, base, "parse_int"),
+        other => Err(Value::error("type", &format!(
+            "parse_int expects a string, got {}", other.map_or("nil", Value::type_name),
+        ))),
+    }
+}
+
+/// `parse_float(s)`: Parse a string as a float. Parsing the output of `to_string` yields the
+/// original float. Throws an error of kind `"parse"` if the string is not a float.
+pub fn parse_float(args: &[Value]) -> Result<Value, Value> {
+    match args.first() {
+        Some(Value::String(s)) => parse_float_str(s, "parse_float"),
+        other => Err(Value::error("type", &format!(
+            "parse_float expects a string, got {}", other.map_or("nil", Value::type_name),
+        ))),
+    }
+}
+
+/// `to_string(v)`: A string representing the value.
+///
+/// Strings are returned unchanged and chars become strings of length one. All other values are
+/// rendered as literals, with floats in the shortest form that parses back to the same float.
+pub fn to_string(args: &[Value]) -> Result<Value, Value> {
+    match args.first() {
+        Some(Value::String(s)) => Ok(Value::String(s.clone())),
+        Some(v) => {
+            let mut b = RopeBuilder::new();
+            push_string(v, &mut b);
+            Ok(Value::String(b.finish()))
+        }
+        None => Ok(Value::String(Rope::from_str("nil"))),
+    }
+}
+
+/// Append the string that `to_string` returns for `v`.
+pub(crate) fn push_string(v: &Value, b: &mut RopeBuilder) {
+    match v {
+        Value::String(s) => b.push_rope(s),
+        Value::Char(c) => b.push_char(*c),
+        v => b.push_str(&v.to_string()),
+    }
+}
+
+fn parse_int_str(s: &Rope, base: u32, name: &str) -> Result<Value, Value> {
+    let s = s.to_string();
+    num::parse_int(&s, base).map(Value::Int).map_err(|err| match err {
+        ParseNumberError::OutOfRange => Value::error("range", &format!(
+            "{} cannot convert {:?} to an int, it is out of range", name, s,
+        )),
+        _ => Value::error("parse", &format!(
+            "{} cannot parse {:?} as an int in base {}", name, s, base,
+        )),
+    })
+}
+
+fn parse_float_str(s: &Rope, name: &str) -> Result<Value, Value> {
+    let s = s.to_string();
+    num::parse_float(&s).map(|x| Value::Float(OrderedFloat(x))).map_err(|_| {
+        Value::error("parse", &format!("{} cannot parse {:?} as a float", name, s))
+    })
+}
@@ -0,0 +1,425 @@
+// Builtins for parsing and emitting comma-separated values, as described in RFC 4180.
+//
+// A csv text is a sequence of rows separated by line breaks (`\r\n`, `\n` or `\r`), the line
+// break after the last row is optional. A row is a sequence of fields separated by the delimiter
+// (`,` by default). A field that contains the delimiter, a quote or a line break must be quoted:
+// it is enclosed in quotes (`"`), and quotes inside it are doubled. An empty line is a row with a
+// single empty field.
+//
+// Both builtins take an optional map of options as their last argument. Common options:
+//
+// - `delimiter`: the char (or string of one char) that separates fields. Must be an ASCII char
+//   other than a quote or a line break.
+//
+// Options of `csv_parse`:
+//
+// - `headers`: if true, the first row names the columns, and the remaining rows are returned as
+//   maps from column names to fields instead of as arrays of fields. Defaults to false.
+// - `mode`: what happens to rows whose number of fields differs from that of the first row.
+//   `"strict"` (the default) throws, `"lenient"` pads rows with too few fields with nil. Rows
+//   with too many fields throw in both modes.
+//
+// Options of `csv_emit`:
+//
+// - `columns`: an array of the keys to emit when emitting maps, in order. Defaults to the keys of
+//   the first map. When emitting arrays, the columns are emitted as a header row.
+// - `headers`: whether to emit a header row of the column names when emitting maps. Defaults to
+//   true.
+//
+// Malformed csv throws an error of kind `"parse"` whose message contains the (one-based) row and
+// column of the problem, bytes that are not valid utf-8 throw an error of kind `"decode"`. Invalid
+// options throw an error of kind `"range"`, and arguments of the wrong type throw an error of kind
+// `"type"`.
+
+use crate::builtins::convert;
+use crate::runtime::Runtime;
+use crate::types::persistent::PersistentMap;
+use crate::types::rope::{Rope, RopeBuilder};
+use crate::value::Value;
+
+/// Register the csv builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("csv_parse", csv_parse);
+    runtime.register("csv_emit", csv_emit);
+}
+
+/// `csv_parse(input [, options])`: Parse a string or bytes of csv into an array of rows. Each row
+/// is an array of strings, or a map from the column names to strings if `options.headers` is
+/// true.
+pub fn csv_parse(args: &[Value]) -> Result<Value, Value> {
+    let options = Options::new(args.get(1), "csv_parse")?;
+    let mut parser = Parser::new(&options)?;
+
+    match args.first() {
+        Some(Value::String(s)) => {
+            for chunk in s.chunks() {
+                parser.feed(chunk.as_bytes())?;
+            }
+        }
+        Some(Value::Bytes(b)) => {
+            for chunk in b.chunks() {
+                parser.feed(chunk)?;
+            }
+        }
+        other => {
+            return Err(Value::error("type", &format!(
+                "csv_parse expects a string or bytes, got {}",
+                other.map_or("nil", Value::type_name),
+            )));
+        }
+    }
+
+    parser.finish().map(Value::array)
+}
+
+/// `csv_emit(rows [, options])`: Render an array of rows as csv. The rows are either all arrays
+/// or all maps. Fields are rendered like `to_string`, with nil rendered as an empty field, and are
+/// quoted only if necessary. Every row ends in `\r\n`.
+pub fn csv_emit(args: &[Value]) -> Result<Value, Value> {
+    let options = Options::new(args.get(1), "csv_emit")?;
+    let delimiter = options.delimiter()?;
+    let rows = match args.first() {
+        Some(Value::Array(rows)) => rows.borrow().clone(),
+        other => {
+            return Err(Value::error("type", &format!(
+                "csv_emit expects an array of rows, got {}",
+                other.map_or("nil", Value::type_name),
+            )));
+        }
+    };
+
+    let mut b = RopeBuilder::new();
+    let columns = match options.get("columns") {
+        Some(Value::Array(columns)) => Some(columns.borrow().clone()),
+        None | Some(Value::Nil) => None,
+        Some(other) => {
+            return Err(Value::error("type", &format!(
+                "csv_emit expects an array of columns, got {}", other.type_name(),
+            )));
+        }
+    };
+
+    match rows.first() {
+        None => {}
+        Some(Value::Map(first)) => {
+            let columns = columns.unwrap_or_else(|| first.borrow().keys().cloned().collect());
+            if options.flag("headers", true)? {
+                emit_row(&columns, delimiter, &mut b);
+            }
+            for (i, row) in rows.iter().enumerate() {
+                match row {
+                    Value::Map(row) => {
+                        let row = row.borrow();
+                        let fields: Vec<Value> = columns
+                            .iter()
+                            .map(|column| row.get(column).cloned().unwrap_or(Value::Nil))
+                            .collect();
+                        emit_row(&fields, delimiter, &mut b);
+                    }
+                    other => return Err(row_type_error("a map", other, i)),
+                }
+            }
+        }
+        Some(_) => {
+            if let Some(columns) = columns {
+                emit_row(&columns, delimiter, &mut b);
+            }
+            for (i, row) in rows.iter().enumerate() {
+                match row {
+                    Value::Array(row) => emit_row(&row.borrow(), delimiter, &mut b),
+                    other => return Err(row_type_error("an array", other, i)),
+                }
+            }
+        }
+    }
+
+    Ok(Value::String(b.finish()))
+}
+
+fn row_type_error(expected: &str, got: &Value, index: usize) -> Value {
+    Value::error("type", &format!(
+        "csv_emit expects {} as the row at index {}, got {}", expected, index, got.type_name(),
+    ))
+}
+
+fn emit_row(fields: &[Value], delimiter: char, b: &mut RopeBuilder) {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            b.push_char(delimiter);
+        }
+        match field {
+            Value::Nil => {}
+            Value::String(s) => {
+                if s.chunks().any(|chunk| needs_quotes(chunk, delimiter)) {
+                    b.push_char('"');
+                    for chunk in s.chunks() {
+                        push_escaped(chunk, b);
+                    }
+                    b.push_char('"');
+                } else {
+                    b.push_rope(s);
+                }
+            }
+            other => {
+                let mut rendered = RopeBuilder::new();
+                convert::push_string(other, &mut rendered);
+                let rendered = rendered.finish().to_string();
+                if needs_quotes(&rendered, delimiter) {
+                    b.push_char('"');
+                    push_escaped(&rendered, b);
+                    b.push_char('"');
+                } else {
+                    b.push_str(&rendered);
+                }
+            }
+        }
+    }
+    b.push_str("\r\n");
+}
+
+fn needs_quotes(s: &str, delimiter: char) -> bool {
+    s.chars().any(|c| c == delimiter || c == '"' || c == '\r' || c == '\n')
+}
+
+// Push `s` with all quotes doubled.
+fn push_escaped(s: &str, b: &mut RopeBuilder) {
+    let mut parts = s.split('"');
+    if let Some(first) = parts.next() {
+        b.push_str(first);
+    }
+    for part in parts {
+        b.push_str("\"\"");
+        b.push_str(part);
+    }
+}
+
+// The options map of a csv builtin, and the name of the builtin for error messages.
+struct Options<'a> {
+    map: Option<PersistentMap<Value, Value>>,
+    name: &'a str,
+}
+
+impl<'a> Options<'a> {
+    fn new(options: Option<&Value>, name: &'a str) -> Result<Options<'a>, Value> {
+        match options {
+            None | Some(Value::Nil) => Ok(Options { map: None, name }),
+            Some(Value::Map(map)) => Ok(Options { map: Some(map.borrow().clone()), name }),
+            Some(other) => Err(Value::error("type", &format!(
+                "{} expects a map of options, got {}", name, other.type_name(),
+            ))),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&Value> {
+        self.map.as_ref()?.get(&Value::String(Rope::from_str(key)))
+    }
+
+    fn flag(&self, key: &str, default: bool) -> Result<bool, Value> {
+        match self.get(key) {
+            None | Some(Value::Nil) => Ok(default),
+            Some(Value::Bool(b)) => Ok(*b),
+            Some(other) => Err(Value::error("type", &format!(
+                "{} expects a bool as the {} option, got {}", self.name, key, other.type_name(),
+            ))),
+        }
+    }
+
+    fn delimiter(&self) -> Result<char, Value> {
+        let delimiter = match self.get("delimiter") {
+            None | Some(Value::Nil) => return Ok(','),
+            Some(Value::Char(c)) => Some(*c),
+            Some(Value::String(s)) if s.len_chars() == 1 => s.get(0),
+            Some(Value::String(_)) => None,
+            Some(other) => {
+                return Err(Value::error("type", &format!(
+                    "{} expects a char as the delimiter, got {}", self.name, other.type_name(),
+                )));
+            }
+        };
+        match delimiter {
+            Some(c) if c.is_ascii() && c != '"' && c != '\r' && c != '\n' => Ok(c),
+            _ => Err(Value::error("range", &format!(
+                "{} expects an ASCII char other than a quote or a line break as the delimiter",
+                self.name,
+            ))),
+        }
+    }
+}
+
+// Where in a row the parser is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    // At the start of a row, nothing of it has been read yet.
+    RowStart,
+    // At the start of a field other than the first one of its row.
+    FieldStart,
+    // Inside a field without quotes.
+    Unquoted,
+    // Inside a quoted field.
+    Quoted,
+    // Inside a quoted field, right after a quote. That quote either closes the field, or it is
+    // the first half of a doubled quote.
+    QuoteInQuoted,
+}
+
+// A parser that is fed the input a chunk at a time, and never looks back at earlier chunks.
+struct Parser {
+    delimiter: u8,
+    headers: bool,
+    lenient: bool,
+    state: State,
+    // Whether the last byte was a `\r`, so that a directly following `\n` is not another break.
+    after_cr: bool,
+    // The bytes of the current field so far.
+    field: Vec<u8>,
+    // The fields of the current row so far.
+    row: Vec<Value>,
+    // The number of the current row, starting at one.
+    row_number: usize,
+    // The number of fields of the first row, once it has been read.
+    width: Option<usize>,
+    // The column names, once they have been read.
+    columns: Option<Vec<Value>>,
+    rows: Vec<Value>,
+}
+
+impl Parser {
+    fn new(options: &Options) -> Result<Parser, Value> {
+        let lenient = match options.get("mode") {
+            None | Some(Value::Nil) => false,
+            Some(Value::String(mode)) => match mode.to_string().as_str() {
+                "strict" => false,
+                "lenient" => true,
+                other => {
+                    return Err(Value::error("range", &format!(
+                        "csv_parse expects the mode \"strict\" or \"lenient\", got {:?}", other,
+                    )));
+                }
+            },
+            Some(other) => {
+                return Err(Value::error("type", &format!(
+                    "csv_parse expects a string as the mode, got {}", other.type_name(),
+                )));
+            }
+        };
+
+        Ok(Parser {
+            delimiter: options.delimiter()? as u8,
+            headers: options.flag("headers", false)?,
+            lenient,
+            state: State::RowStart,
+            after_cr: false,
+            field: vec![],
+            row: vec![],
+            row_number: 1,
+            width: None,
+            columns: None,
+            rows: vec![],
+        })
+    }
+
+    // An error in the given column of the current row.
+    fn error(&self, kind: &str, what: &str, column: usize) -> Value {
+        Value::error(kind, &format!(
+            "csv_parse failed: {} at row {}, column {}", what, self.row_number, column,
+        ))
+    }
+
+    // An error in the current field.
+    fn field_error(&self, kind: &str, what: &str) -> Value {
+        self.error(kind, what, self.row.len() + 1)
+    }
+
+    fn feed(&mut self, chunk: &[u8]) -> Result<(), Value> {
+        for &byte in chunk {
+            if self.after_cr {
+                self.after_cr = false;
+                if byte == b'\n' && self.state == State::RowStart {
+                    continue;
+                }
+            }
+
+            match (self.state, byte) {
+                (State::Quoted, b'"') => self.state = State::QuoteInQuoted,
+                (State::Quoted, _) => self.field.push(byte),
+                (State::QuoteInQuoted, b'"') => {
+                    self.field.push(b'"');
+                    self.state = State::Quoted;
+                }
+                (State::RowStart, b'"') | (State::FieldStart, b'"') => self.state = State::Quoted,
+                (State::Unquoted, b'"') => {
+                    return Err(self.field_error("parse", "quote inside an unquoted field"));
+                }
+                (_, b'\r') | (_, b'\n') => {
+                    self.end_field()?;
+                    self.end_row()?;
+                    self.after_cr = byte == b'\r';
+                }
+                (_, _) if byte == self.delimiter => {
+                    self.end_field()?;
+                    self.state = State::FieldStart;
+                }
+                (State::QuoteInQuoted, _) => {
+                    let what = "unexpected character after a closing quote";
+                    return Err(self.field_error("parse", what));
+                }
+                (_, _) => {
+                    self.field.push(byte);
+                    self.state = State::Unquoted;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Vec<Value>, Value> {
+        match self.state {
+            State::RowStart => {}
+            State::Quoted => return Err(self.field_error("parse", "unclosed quoted field")),
+            _ => {
+                self.end_field()?;
+                self.end_row()?;
+            }
+        }
+        Ok(self.rows)
+    }
+
+    fn end_field(&mut self) -> Result<(), Value> {
+        let field = String::from_utf8(std::mem::take(&mut self.field))
+            .map_err(|_| self.field_error("decode", "invalid utf-8"))?;
+        self.row.push(Value::String(Rope::from_str(&field)));
+        Ok(())
+    }
+
+    fn end_row(&mut self) -> Result<(), Value> {
+        let mut row = std::mem::take(&mut self.row);
+        match self.width {
+            None => self.width = Some(row.len()),
+            Some(width) if row.len() < width && self.lenient => row.resize(width, Value::Nil),
+            Some(width) if row.len() != width => {
+                let what = format!("expected {} fields, got {}", width, row.len());
+                return Err(self.error("parse", &what, width.min(row.len()) + 1));
+            }
+            Some(_) => {}
+        }
+
+        if !self.headers {
+            self.rows.push(Value::array(row));
+        } else if let Some(columns) = &self.columns {
+            let map = columns.iter().cloned().zip(row).collect();
+            self.rows.push(Value::map(map));
+        } else {
+            for (i, column) in row.iter().enumerate() {
+                if row[..i].contains(column) {
+                    let what = format!("duplicate column name {}", column);
+                    return Err(self.error("parse", &what, i + 1));
+                }
+            }
+            self.columns = Some(row);
+        }
+
+        self.row_number += 1;
+        self.state = State::RowStart;
+        Ok(())
+    }
+}
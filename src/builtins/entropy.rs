@@ -0,0 +1,83 @@
+// Builtins for unpredictable randomness, from the entropy source of the operating system (see
+// `types::entropy`). Use these rather than the random builtins for anything that must not be
+// guessed, as the random builtins can be seeded and are not cryptographically secure.
+//
+// The embedder can deny access to the entropy source (see `Runtime::allow_entropy`), in which case
+// the builtins throw an error of kind `"forbidden"`. If reading from the entropy source fails,
+// they throw an error of kind `"io"`. Arguments of the wrong type throw an error of kind `"type"`.
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::runtime::Runtime;
+use crate::types::bytes::Bytes;
+use crate::types::entropy;
+use crate::types::rope::Rope;
+use crate::value::Value;
+
+/// The maximal number of bytes that `random_bytes` returns.
+pub const MAX_RANDOM_BYTES: usize = 1 << 16;
+
+type Builtin = fn(&Cell<bool>, &[Value]) -> Result<Value, Value>;
+
+/// Register the entropy builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    let builtins: [(&'static str, Builtin); 2] = [
+        ("random_bytes", random_bytes),
+        ("uuid_v4", uuid_v4),
+    ];
+    for (name, builtin) in builtins.iter().cloned() {
+        let allowed: Rc<Cell<bool>> = runtime.entropy();
+        runtime.register(name, move |args| builtin(&allowed, args));
+    }
+}
+
+/// `random_bytes(n)`: `n` unpredictable bytes. Throws an error of kind `"range"` if `n` is
+/// negative or greater than `MAX_RANDOM_BYTES`.
+pub fn random_bytes(allowed: &Cell<bool>, args: &[Value]) -> Result<Value, Value> {
+    let n = match args.first() {
+        Some(Value::Int(n)) if 0 <= *n && *n as u64 <= MAX_RANDOM_BYTES as u64 => *n as usize,
+        Some(Value::Int(n)) => {
+            return Err(Value::error("range", &format!(
+                "random_bytes expects a length from 0 to {}, got {}", MAX_RANDOM_BYTES, n,
+            )));
+        }
+        other => {
+            return Err(Value::error("type", &format!(
+                "random_bytes expects an int, got {}", other.map_or("nil", Value::type_name),
+            )));
+        }
+    };
+    let mut buf = vec![0; n];
+    fill(allowed, &mut buf, "random_bytes")?;
+    Ok(Value::Bytes(Bytes::from_vec(buf)))
+}
+
+/// `uuid_v4()`: A new random (version 4) UUID, as a string of lowercase hexadecimal digits in the
+/// canonical grouping, e.g. `"0b9a3a4e-8d53-4c5e-9a3e-6f1c2d7e8b90"`.
+pub fn uuid_v4(allowed: &Cell<bool>, _args: &[Value]) -> Result<Value, Value> {
+    let mut bytes = [0; 16];
+    fill(allowed, &mut bytes, "uuid_v4")?;
+    bytes[6] = (bytes[6] & 0x0f) | 0x40; // version 4
+    bytes[8] = (bytes[8] & 0x3f) | 0x80; // variant 1 (RFC 4122)
+
+    let mut uuid = String::with_capacity(36);
+    for (i, byte) in bytes.iter().enumerate() {
+        if i == 4 || i == 6 || i == 8 || i == 10 {
+            uuid.push('-');
+        }
+        uuid.push_str(&format!("{:02x}", byte));
+    }
+    Ok(Value::String(Rope::from_str(&uuid)))
+}
+
+fn fill(allowed: &Cell<bool>, buf: &mut [u8], name: &str) -> Result<(), Value> {
+    if !allowed.get() {
+        return Err(Value::error("forbidden", &format!(
+            "{} may not use the entropy source of the operating system", name,
+        )));
+    }
+    entropy::fill(buf).map_err(|err| {
+        Value::error("io", &format!("{} failed to read entropy: {}", name, err))
+    })
+}
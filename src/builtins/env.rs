@@ -0,0 +1,64 @@
+// Builtins for the inputs of a program: its arguments and its environment variables.
+//
+// Embedders install the arguments with `Runtime::set_args` (there are none by default), and can
+// replace the environment of the process with a fixed set of variables through
+// `Runtime::set_env`. Environment variables whose name or value is not valid unicode are skipped.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::env;
+use std::rc::Rc;
+
+use crate::runtime::Runtime;
+use crate::types::rope::Rope;
+use crate::value::Value;
+
+type Vars = RefCell<Option<BTreeMap<String, String>>>;
+
+/// Register the argument and environment builtins under their names, reading what the runtime
+/// was configured with.
+pub fn register(runtime: &mut Runtime) {
+    let installed = runtime.args();
+    runtime.register("args", move |values| args(&installed, values));
+    let vars = runtime.env();
+    runtime.register("env", move |args| env(&vars, args));
+    let vars = runtime.env();
+    runtime.register("env_all", move |args| env_all(&vars, args));
+}
+
+/// `args()`: A new array of the program arguments, as strings.
+pub fn args(installed: &Rc<RefCell<Vec<String>>>, _args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::array(installed.borrow().iter().map(|arg| string(arg)).collect()))
+}
+
+/// `env(name)`: The value of the environment variable `name` (a string), or nil if it is not set.
+pub fn env(vars: &Vars, args: &[Value]) -> Result<Value, Value> {
+    let name = match args.first() {
+        Some(Value::String(name)) => name.to_string(),
+        other => return Err(Value::error("type", &format!(
+            "env expects a string, got {}", other.map_or("nil", Value::type_name),
+        ))),
+    };
+    let value = match &*vars.borrow() {
+        Some(vars) => vars.get(&name).cloned(),
+        // `var` panics on names containing `=` or NUL, which can never be set anyway.
+        None if name.is_empty() || name.contains(['=', '\0']) => None,
+        None => env::var(&name).ok(),
+    };
+    Ok(value.map_or(Value::Nil, |value| string(&value)))
+}
+
+/// `env_all()`: A new map from the names of all environment variables to their values.
+pub fn env_all(vars: &Vars, _args: &[Value]) -> Result<Value, Value> {
+    let all: BTreeMap<Value, Value> = match &*vars.borrow() {
+        Some(vars) => vars.iter().map(|(name, value)| (string(name), string(value))).collect(),
+        None => env::vars_os()
+            .filter_map(|(name, value)| Some((string(name.to_str()?), string(value.to_str()?))))
+            .collect(),
+    };
+    Ok(Value::map(all))
+}
+
+fn string(s: &str) -> Value {
+    Value::String(Rope::from_str(s))
+}
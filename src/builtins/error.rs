@@ -0,0 +1,77 @@
+// Builtins for constructing and inspecting errors.
+//
+// Errors thrown by the builtins and the interpreter are maps with the fields `kind` (a string such
+// as `"type"` or `"range"`) and `message` (a human-readable string), and optionally `data` (any
+// value with further information, documented by the throwing builtin) and `source` (the error
+// that caused this one, see `types::pan_error`). `error` constructs such a map for pan code to
+// throw.
+//
+// Pan code can throw arbitrary values, so the accessors do not require a well-formed error: they
+// return `nil` for values that are not errors, and never throw. This makes them safe to use in
+// catch handlers.
+
+use crate::runtime::Runtime;
+use crate::types::rope::Rope;
+use crate::value::Value;
+
+/// Register the error builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("error", error);
+    runtime.register("is_error", is_error);
+    runtime.register("error_kind", error_kind);
+    runtime.register("error_message", error_message);
+    runtime.register("error_data", error_data);
+}
+
+/// `error(kind, message [, data])`: A new error with the given kind and message (both strings),
+/// and `data` if it is given.
+pub fn error(args: &[Value]) -> Result<Value, Value> {
+    let kind = string(args, 0, "error")?;
+    let message = string(args, 1, "error")?;
+    Ok(match args.get(2) {
+        None => Value::error(&kind, &message),
+        Some(data) => Value::error_with_data(&kind, &message, data.clone()),
+    })
+}
+
+/// `is_error(v)`: Whether `v` is a map with a string `kind` and a string `message`.
+pub fn is_error(args: &[Value]) -> Result<Value, Value> {
+    let v = arg(args);
+    Ok(Value::Bool(v.error_kind().is_some() && v.error_message().is_some()))
+}
+
+/// `error_kind(v)`: The `kind` of an error, or `nil` if `v` is not a map with a string `kind`.
+pub fn error_kind(args: &[Value]) -> Result<Value, Value> {
+    Ok(arg(args).error_kind().map_or(Value::Nil, Value::String))
+}
+
+/// `error_message(v)`: The `message` of an error, or `nil` if `v` is not a map with a string
+/// `message`.
+pub fn error_message(args: &[Value]) -> Result<Value, Value> {
+    Ok(arg(args).error_message().map_or(Value::Nil, Value::String))
+}
+
+/// `error_data(v)`: The `data` of an error, or `nil` if `v` is not a map with a `data` field.
+pub fn error_data(args: &[Value]) -> Result<Value, Value> {
+    Ok(match args.first() {
+        Some(Value::Map(map)) => {
+            let data = map.borrow().get(&Value::String(Rope::from_str("data"))).cloned();
+            data.unwrap_or(Value::Nil)
+        }
+        _ => Value::Nil,
+    })
+}
+
+fn string(args: &[Value], i: usize, name: &str) -> Result<String, Value> {
+    match args.get(i) {
+        Some(Value::String(s)) => Ok(s.to_string()),
+        other => Err(Value::error("type", &format!(
+            "{} expects a string, got {}", name, other.map_or("nil", Value::type_name),
+        ))),
+    }
+}
+
+// Missing arguments are `nil`.
+fn arg(args: &[Value]) -> Value {
+    args.first().cloned().unwrap_or(Value::Nil)
+}
@@ -0,0 +1,26 @@
+// The builtin for terminating a program early, see `types::exit`.
+
+use crate::runtime::Runtime;
+use crate::types::exit::exit_value;
+use crate::value::Value;
+
+/// Register the exit builtin under its name.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("exit", exit);
+}
+
+/// `exit(code)`: Throw an exit value with the given code (`0` if omitted), which unwinds the whole
+/// program unless caught with the `catch_exit` option. Throws an error of kind `"type"` if the code
+/// is not an int, or of kind `"range"` if it does not fit into 32 bits.
+pub fn exit(args: &[Value]) -> Result<Value, Value> {
+    match args.first() {
+        None | Some(Value::Nil) => Err(exit_value(0)),
+        Some(Value::Int(code)) if *code as i32 as i64 == *code => Err(exit_value(*code as i32)),
+        Some(Value::Int(code)) => Err(Value::error("range", &format!(
+            "exit expects a code from {} to {}, got {}", i32::MIN, i32::MAX, code,
+        ))),
+        Some(other) => Err(Value::error("type", &format!(
+            "exit expects an int, got {}", other.type_name(),
+        ))),
+    }
+}
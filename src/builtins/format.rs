@@ -0,0 +1,266 @@
+// String formatting builtins.
+//
+// A template is a string in which placeholders in braces are replaced by values. `{{` and `}}`
+// stand for literal braces. `fmt` takes the values as arguments: `{}` is replaced by the next
+// argument (starting with the first one, and counting only `{}` placeholders) and `{i}` by the
+// argument at index `i`. `fmt_map` takes the values from a map: `{name}` is replaced by the value
+// of the key `"name"`. Each function only accepts its own kind of placeholders, so a template can
+// not mix positional and named placeholders.
+//
+// A placeholder can specify how to render its value after a colon, e.g. `{:>8.2}` or
+// `{name:*^10?}`. The spec consists of, in order and all optional:
+//
+// - a fill char (defaulting to a space) followed by an alignment: `<` (left), `^` (center) or `>`
+//   (right). Without an alignment, numbers are aligned right and all other values left.
+// - a width: the minimum number of chars, shorter renderings are padded with the fill char. At
+//   most `MAX_WIDTH`.
+// - a precision: `.` followed by the number of digits after the decimal point, at most
+//   `MAX_WIDTH`. Only allowed for floats (and ints, which are converted to floats).
+// - `?`: render the value as a literal (e.g. strings in quotes), instead of like `to_string`.
+//
+// Malformed templates and specs, placeholders that do not fit the builtin, and missing values
+// throw an error of kind `"format"` whose message contains the char offset of the offending
+// placeholder in the template. The padding is charged to the memory budget (see `types::heap`)
+// while the result is built, so the builtins throw an out-of-memory value once it exceeds the
+// limit.
+
+use crate::builtins::convert;
+use crate::runtime::Runtime;
+use crate::types::heap::{self, Charge};
+use crate::types::persistent::PersistentMap;
+use crate::types::rope::{Rope, RopeBuilder};
+use crate::value::Value;
+
+/// The largest width and precision a format spec can have.
+pub const MAX_WIDTH: usize = u16::MAX as usize;
+
+/// Register the formatting builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("fmt", fmt);
+    runtime.register("fmt_map", fmt_map);
+}
+
+/// `fmt(template, args...)`: Replace the positional placeholders in `template` by the arguments.
+pub fn fmt(args: &[Value]) -> Result<Value, Value> {
+    let template = template(args, "fmt")?;
+    format(&template, Values::Positional(&args[1..]), "fmt").map(Value::String)
+}
+
+/// `fmt_map(template, map)`: Replace the named placeholders in `template` by the values of the
+/// map.
+pub fn fmt_map(args: &[Value]) -> Result<Value, Value> {
+    let template = template(args, "fmt_map")?;
+    match args.get(1) {
+        Some(Value::Map(map)) => {
+            let map = map.borrow().clone();
+            format(&template, Values::Named(&map), "fmt_map").map(Value::String)
+        }
+        other => Err(Value::error("type", &format!(
+            "fmt_map expects a map, got {}", other.map_or("nil", Value::type_name),
+        ))),
+    }
+}
+
+// Where the values for the placeholders come from.
+enum Values<'a> {
+    Positional(&'a [Value]),
+    Named(&'a PersistentMap<Value, Value>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+// A parsed format spec.
+struct Spec {
+    fill: char,
+    align: Option<Align>,
+    width: usize,
+    precision: Option<usize>,
+    literal: bool,
+}
+
+impl Spec {
+    // Parse a spec, or describe why it is invalid.
+    fn parse(spec: &[char]) -> Result<Spec, String> {
+        let malformed = || format!("malformed format spec `{}`", spec.iter().collect::<String>());
+        let align = |c: char| match c {
+            '<' => Some(Align::Left),
+            '^' => Some(Align::Center),
+            '>' => Some(Align::Right),
+            _ => None,
+        };
+
+        let (fill, align, mut rest) = match spec {
+            [fill, a, rest @ ..] if align(*a).is_some() => (*fill, align(*a), rest),
+            [a, rest @ ..] if align(*a).is_some() => (' ', align(*a), rest),
+            rest => (' ', None, rest),
+        };
+        let width = take_number(&mut rest).unwrap_or(0);
+        let precision = match rest {
+            ['.', tail @ ..] => {
+                rest = tail;
+                Some(take_number(&mut rest).ok_or_else(malformed)?)
+            }
+            _ => None,
+        };
+        let literal = match rest {
+            [] => false,
+            ['?'] => true,
+            _ => return Err(malformed()),
+        };
+        if width > MAX_WIDTH {
+            return Err(format!("width {} is larger than {}", width, MAX_WIDTH));
+        }
+        match precision {
+            Some(precision) if precision > MAX_WIDTH => {
+                Err(format!("precision {} is larger than {}", precision, MAX_WIDTH))
+            }
+            _ => Ok(Spec { fill, align, width, precision, literal }),
+        }
+    }
+
+    // Render `v` according to the spec, charging the padding to `charge`. Format errors are
+    // created by `error` from a description.
+    fn render(
+        &self,
+        v: &Value,
+        b: &mut RopeBuilder,
+        charge: &Charge,
+        error: impl Fn(&str) -> Value,
+    ) -> Result<(), Value> {
+        let rendered = match (self.precision, v) {
+            (Some(precision), Value::Float(x)) => format!("{:.*}", precision, x.into_inner()),
+            (Some(precision), Value::Int(n)) => format!("{:.*}", precision, *n as f64),
+            (Some(_), v) => {
+                return Err(error(&format!("precision for a value of type {}", v.type_name())));
+            }
+            (None, v) if self.literal => v.to_string(),
+            (None, v) if self.width == 0 => {
+                convert::push_string(v, b);
+                return Ok(());
+            }
+            (None, v) => {
+                let mut s = RopeBuilder::new();
+                convert::push_string(v, &mut s);
+                s.finish().to_string()
+            }
+        };
+
+        let padding = self.width.saturating_sub(rendered.chars().count());
+        charge.resize(charge.bytes() + (padding * self.fill.len_utf8()) as u64);
+        if let Some(oom) = heap::out_of_memory() {
+            return Err(oom);
+        }
+        let align = self.align.unwrap_or(match v {
+            Value::Int(_) | Value::Float(_) => Align::Right,
+            _ => Align::Left,
+        });
+        let (before, after) = match align {
+            Align::Left => (0, padding),
+            Align::Center => (padding / 2, padding - padding / 2),
+            Align::Right => (padding, 0),
+        };
+        for _ in 0..before {
+            b.push_char(self.fill);
+        }
+        b.push_str(&rendered);
+        for _ in 0..after {
+            b.push_char(self.fill);
+        }
+        Ok(())
+    }
+}
+
+// Consume a decimal number from the start of `chars`, if there is one.
+fn take_number(chars: &mut &[char]) -> Option<usize> {
+    let digits = chars.iter().take_while(|c| c.is_ascii_digit()).count();
+    let n = chars[..digits].iter().collect::<String>().parse().ok()?;
+    *chars = &chars[digits..];
+    Some(n)
+}
+
+fn format(template: &Rope, values: Values, name: &str) -> Result<Rope, Value> {
+    let error = |offset: usize, what: &str| {
+        Value::error("format", &format!("{} failed: {} at offset {}", name, what, offset))
+    };
+
+    let chars: Vec<char> = template.chars().collect();
+    let mut b = RopeBuilder::new();
+    // The padding pushed so far.
+    let padding = Charge::new(0);
+    // The index of the argument for the next `{}` placeholder.
+    let mut next = 0;
+    let mut i = 0;
+
+    while i < chars.len() {
+        match (chars[i], chars.get(i + 1)) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                b.push_char(chars[i]);
+                i += 2;
+            }
+            ('}', _) => return Err(error(i, "unmatched `}`")),
+            ('{', _) => {
+                let close = chars[i..]
+                    .iter()
+                    .position(|c| *c == '}')
+                    .map(|len| i + len)
+                    .ok_or_else(|| error(i, "unclosed placeholder"))?;
+                let placeholder = &chars[i + 1..close];
+                let (key, spec) = match placeholder.iter().position(|c| *c == ':') {
+                    Some(colon) => (&placeholder[..colon], &placeholder[colon + 1..]),
+                    None => (placeholder, &[][..]),
+                };
+                let key: String = key.iter().collect();
+                let spec = Spec::parse(spec).map_err(|what| error(i, &what))?;
+
+                let value = match values {
+                    Values::Positional(args) => {
+                        let index = if key.is_empty() {
+                            next += 1;
+                            next - 1
+                        } else {
+                            key.parse::<usize>().map_err(|_| {
+                                error(i, &format!("unexpected named placeholder `{{{}}}`", key))
+                            })?
+                        };
+                        args.get(index).ok_or_else(|| {
+                            error(i, &format!("missing argument {}", index))
+                        })?
+                    }
+                    Values::Named(map) => {
+                        if key.is_empty() || key.chars().all(|c| c.is_ascii_digit()) {
+                            return Err(error(i, &format!(
+                                "unexpected positional placeholder `{{{}}}`", key,
+                            )));
+                        }
+                        map.get(&Value::String(Rope::from_str(&key))).ok_or_else(|| {
+                            error(i, &format!("missing key `{}`", key))
+                        })?
+                    }
+                };
+                spec.render(value, &mut b, &padding, |what| error(i, what))?;
+                i = close + 1;
+            }
+            (c, _) => {
+                b.push_char(c);
+                i += 1;
+            }
+        }
+    }
+
+    Ok(b.finish())
+}
+
+fn template(args: &[Value], name: &str) -> Result<Rope, Value> {
+    match args.first() {
+        Some(Value::String(s)) => Ok(s.clone()),
+        other => Err(Value::error("type", &format!(
+            "{} expects a string as the template, got {}",
+            name, other.map_or("nil", Value::type_name),
+        ))),
+    }
+}
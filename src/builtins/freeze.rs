@@ -0,0 +1,71 @@
+// Builtins for copying and freezing collections.
+//
+// A frozen array, set or map can still be read, but all builtins that would mutate it throw an
+// error of kind `"frozen"` instead. Freezing can not be undone, but `thaw` returns an unfrozen
+// copy of a collection, and `deep_copy` a copy in which nothing is frozen. Thawing a set or map of
+// plain data takes constant time, as the copy shares its entries (see `types::persistent`). See
+// `Value::deep_copy`, `Value::thaw`, `Value::freeze` and `Value::is_frozen` for the details.
+
+use gc::Trace;
+
+use crate::runtime::Runtime;
+use crate::types::collection::Collection;
+use crate::value::Value;
+
+/// Register the copying and freezing builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("deep_copy", deep_copy);
+    runtime.register("thaw", thaw);
+    runtime.register("freeze", freeze);
+    runtime.register("deep_freeze", deep_freeze);
+    runtime.register("is_frozen", is_frozen);
+}
+
+/// `deep_copy(v)`: A copy of `v` that shares no arrays, sets or maps with it, and in which none of
+/// them are frozen. Collections that occur several times in `v` (or inside themselves) are copied
+/// once, so the copy has the same shape.
+pub fn deep_copy(args: &[Value]) -> Result<Value, Value> {
+    Ok(arg(args).deep_copy())
+}
+
+/// `thaw(v)`: An unfrozen copy of `v` if it is an array, set or map, containing the same values.
+/// Other values are returned as they are.
+pub fn thaw(args: &[Value]) -> Result<Value, Value> {
+    Ok(arg(args).thaw())
+}
+
+/// `freeze(v)`: Freeze `v` if it is an array, set or map, and return it. The collections it
+/// contains are not frozen.
+pub fn freeze(args: &[Value]) -> Result<Value, Value> {
+    let v = arg(args);
+    v.freeze();
+    Ok(v)
+}
+
+/// `deep_freeze(v)`: Freeze `v` and all arrays, sets and maps it contains, and return it.
+pub fn deep_freeze(args: &[Value]) -> Result<Value, Value> {
+    let v = arg(args);
+    v.deep_freeze();
+    Ok(v)
+}
+
+/// `is_frozen(v)`: Whether `v` can not be mutated: true for frozen collections and for values of
+/// the immutable types (`nil`, `bool`, `int`, `float`, `char`, `string` and `bytes`).
+pub fn is_frozen(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Bool(arg(args).is_frozen()))
+}
+
+/// Throw an error of kind `"frozen"` if the collection is frozen. For builtins that are about to
+/// mutate it.
+pub fn ensure_mutable<T: Trace>(collection: &Collection<T>, name: &str) -> Result<(), Value> {
+    if collection.is_frozen() {
+        Err(Value::error("frozen", &format!("{} cannot mutate a frozen collection", name)))
+    } else {
+        Ok(())
+    }
+}
+
+// Missing arguments are `nil`.
+fn arg(args: &[Value]) -> Value {
+    args.first().cloned().unwrap_or(Value::Nil)
+}
@@ -0,0 +1,240 @@
+// Builtins for working with files. They are only registered for runtimes with the `fs`
+// capability (see `capabilities`), or once an embedder calls `Runtime::allow_fs`.
+//
+// Paths are strings. If the embedder restricted access to a root directory, relative paths are
+// resolved against the root, and paths that lead outside of it after resolving `..` components
+// and symbolic links (including absolute paths elsewhere) throw an error of kind `"forbidden"`.
+// Otherwise, relative paths are resolved against the working directory of the process.
+//
+// The builtins access the file system of their runtime (see `host_io`), which is the one of the
+// process unless the embedder replaced it with `Runtime::set_host_io` or
+// `Runtime::set_file_system`. The root is resolved in the file system that is current at the time
+// of each call.
+//
+// Failed operations throw an error of kind `"io"` whose `data` is a map with the fields `io_kind`
+// (the kind of the error reported by the operating system, e.g. `"not_found"`) and `path`. The
+// `data` of `"forbidden"` errors is the path. Text that is not
+// valid UTF-8 throws an error of kind `"decode"`, arguments of the wrong type throw an error of
+// kind `"type"`.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::host_io::FileSystem;
+use crate::runtime::{Files, Runtime};
+use crate::types::bytes::Bytes;
+use crate::types::pan_error;
+use crate::types::rope::Rope;
+use crate::value::Value;
+
+/// The part of the file system that the builtins may access.
+#[derive(Clone)]
+pub struct Fs {
+    files: Files,
+    // As given, it is canonicalized for every call so that resolved paths can be checked by
+    // comparing prefixes.
+    root: Option<PathBuf>,
+}
+
+impl fmt::Debug for Fs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Fs").field("root", &self.root).finish()
+    }
+}
+
+type Builtin = fn(&Fs, &[Value]) -> Result<Value, Value>;
+
+/// Register the file system builtins under their names, restricted to the given root directory
+/// if there is one. Fails if the root can not be canonicalized (e.g. because it does not exist) in
+/// the current file system of the runtime.
+pub fn register(runtime: &mut Runtime, root: Option<&Path>) -> io::Result<()> {
+    let files = runtime.files();
+    if let Some(root) = root {
+        files.borrow().canonicalize(root)?;
+    }
+    let fs = Rc::new(Fs { files, root: root.map(Path::to_owned) });
+    let builtins: [(&'static str, Builtin); 8] = [
+        ("read_file", read_file),
+        ("read_file_text", read_file_text),
+        ("write_file", write_file),
+        ("append_file", append_file),
+        ("file_exists", file_exists),
+        ("remove_file", remove_file),
+        ("list_dir", list_dir),
+        ("mkdir_all", mkdir_all),
+    ];
+    for (name, builtin) in builtins.iter().cloned() {
+        let fs = fs.clone();
+        runtime.register(name, move |args| builtin(&fs, args));
+    }
+    Ok(())
+}
+
+/// `read_file(path)`: The contents of the file, as bytes.
+pub fn read_file(fs: &Fs, args: &[Value]) -> Result<Value, Value> {
+    let (path, resolved) = fs.path(args, "read_file")?;
+    match fs.read(&resolved) {
+        Ok(contents) => Ok(Value::Bytes(Bytes::from_vec(contents))),
+        Err(err) => Err(io_error("read_file", &path, &err)),
+    }
+}
+
+/// `read_file_text(path)`: The contents of the file, as a string.
+pub fn read_file_text(fs: &Fs, args: &[Value]) -> Result<Value, Value> {
+    let (path, resolved) = fs.path(args, "read_file_text")?;
+    let contents = fs.read(&resolved).map_err(|err| io_error("read_file_text", &path, &err))?;
+    match String::from_utf8(contents) {
+        Ok(text) => Ok(Value::String(Rope::from_str(&text))),
+        Err(err) => Err(Value::error("decode", &format!(
+            "read_file_text read invalid UTF-8 from {}: {}", path, err.utf8_error(),
+        ))),
+    }
+}
+
+/// `write_file(path, contents)`: Replace the contents of the file (creating it if necessary) with
+/// `contents`, bytes or a string (which is written as UTF-8).
+pub fn write_file(fs: &Fs, args: &[Value]) -> Result<Value, Value> {
+    write(fs, args, false, "write_file")
+}
+
+/// `append_file(path, contents)`: Append `contents`, bytes or a string (which is written as
+/// UTF-8), to the file, creating it if necessary.
+pub fn append_file(fs: &Fs, args: &[Value]) -> Result<Value, Value> {
+    write(fs, args, true, "append_file")
+}
+
+/// `file_exists(path)`: Whether there is a file or directory at the path.
+pub fn file_exists(fs: &Fs, args: &[Value]) -> Result<Value, Value> {
+    let (_, resolved) = fs.path(args, "file_exists")?;
+    Ok(Value::Bool(fs.get().canonicalize(&resolved).is_ok()))
+}
+
+/// `remove_file(path)`: Remove the file.
+pub fn remove_file(fs: &Fs, args: &[Value]) -> Result<Value, Value> {
+    let (path, resolved) = fs.path(args, "remove_file")?;
+    match fs.get().remove_file(&resolved) {
+        Ok(()) => Ok(Value::Nil),
+        Err(err) => Err(io_error("remove_file", &path, &err)),
+    }
+}
+
+/// `list_dir(path)`: A new array of the names of the entries of the directory, in ascending
+/// order. Throws an error of kind `"decode"` if a name is not valid unicode.
+pub fn list_dir(fs: &Fs, args: &[Value]) -> Result<Value, Value> {
+    let (path, resolved) = fs.path(args, "list_dir")?;
+    let mut names = vec![];
+    let entries = fs.get().list(&resolved).map_err(|err| io_error("list_dir", &path, &err))?;
+    for entry in entries {
+        match entry.into_string() {
+            Ok(name) => names.push(name),
+            Err(name) => {
+                return Err(Value::error("decode", &format!(
+                    "list_dir found the name {:?} in {}, which is not valid unicode", name, path,
+                )));
+            }
+        }
+    }
+    names.sort();
+    Ok(Value::array(names.iter().map(|name| Value::String(Rope::from_str(name))).collect()))
+}
+
+/// `mkdir_all(path)`: Create the directory and all of its missing ancestors.
+pub fn mkdir_all(fs: &Fs, args: &[Value]) -> Result<Value, Value> {
+    let (path, resolved) = fs.path(args, "mkdir_all")?;
+    match fs.get().create_dir_all(&resolved) {
+        Ok(()) => Ok(Value::Nil),
+        Err(err) => Err(io_error("mkdir_all", &path, &err)),
+    }
+}
+
+fn write(fs: &Fs, args: &[Value], append: bool, name: &str) -> Result<Value, Value> {
+    let (path, resolved) = fs.path(args, name)?;
+    let contents = match args.get(1) {
+        Some(Value::Bytes(b)) => b.to_vec(),
+        Some(Value::String(s)) => s.to_string().into_bytes(),
+        other => return Err(type_error("bytes or a string", other, name)),
+    };
+    fs.get()
+        .open_write(&resolved, append)
+        .and_then(|mut file| file.write_all(&contents).and_then(|()| file.flush()))
+        .map(|()| Value::Nil)
+        .map_err(|err| io_error(name, &path, &err))
+}
+
+impl Fs {
+    // The current file system of the runtime.
+    fn get(&self) -> Rc<dyn FileSystem> {
+        self.files.borrow().clone()
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let mut contents = vec![];
+        self.get().open_read(path)?.read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+
+    // The path that is the first argument, as given and resolved.
+    fn path(&self, args: &[Value], name: &str) -> Result<(String, PathBuf), Value> {
+        let path = match args.first() {
+            Some(Value::String(path)) => path.to_string(),
+            other => return Err(type_error("a string as the path", other, name)),
+        };
+        let resolved = match &self.root {
+            None => PathBuf::from(&path),
+            Some(root) => {
+                let root = self.get().canonicalize(root).map_err(|err| {
+                    io_error(name, &root.to_string_lossy(), &err)
+                })?;
+                self.confine(&root, &path, name)?
+            }
+        };
+        Ok((path, resolved))
+    }
+
+    // Resolve the path against the root, and check that the result lies inside of it. The path
+    // may not exist yet, so only its longest existing ancestor is canonicalized. The components
+    // after it are plain names: `Path::file_name` yields no `..` or `.` components.
+    fn confine(&self, root: &Path, path: &str, name: &str) -> Result<PathBuf, Value> {
+        let fs = self.get();
+        let mut existing = root.join(path);
+        let mut missing = vec![];
+        while fs.metadata(&existing).is_err() {
+            match (existing.file_name(), existing.parent()) {
+                (Some(file_name), Some(parent)) => {
+                    missing.push(file_name.to_owned());
+                    existing = parent.to_owned();
+                }
+                _ => return Err(forbidden(path, name)),
+            }
+        }
+        let mut resolved = fs.canonicalize(&existing).map_err(|err| io_error(name, path, &err))?;
+        resolved.extend(missing.iter().rev());
+        if resolved.starts_with(root) {
+            Ok(resolved)
+        } else {
+            Err(forbidden(path, name))
+        }
+    }
+}
+
+fn forbidden(path: &str, name: &str) -> Value {
+    Value::error_with_data("forbidden", &format!(
+        "{} may not access {}, which is outside of the root directory", name, path,
+    ), Value::String(Rope::from_str(path)))
+}
+
+fn io_error(name: &str, path: &str, err: &io::Error) -> Value {
+    let message = format!("{} failed on {}: {}", name, path, err);
+    Value::error_with_data("io", &message, Value::record(vec![
+        ("io_kind", pan_error::io_kind(err)),
+        ("path", Value::String(Rope::from_str(path))),
+    ]))
+}
+
+fn type_error(expected: &str, got: Option<&Value>, name: &str) -> Value {
+    Value::error("type", &format!(
+        "{} expects {}, got {}", name, expected, got.map_or("nil", Value::type_name),
+    ))
+}
@@ -0,0 +1,85 @@
+// Builtins for inspecting functions.
+//
+// Pan functions know the name they were bound to in the source (anonymous function literals have
+// none) and the number of arguments they take. Rust functions know the name they were registered
+// under, and the number of arguments they use if the embedder declared it (see
+// `Runtime::register_with_arity`). Functions that can be called with named arguments also know the
+// names of their parameters (see `Value::apply_named`). Every function has a stamp that orders it
+// by creation, see `Fun::id`. Arguments that are not functions throw an error of kind `"type"`.
+
+use crate::runtime::Runtime;
+use crate::types::rope::Rope;
+use crate::value::{Fun, Value};
+
+/// Register the function inspection builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("fun_arity", fun_arity);
+    runtime.register("fun_name", fun_name);
+    runtime.register("fun_kind", fun_kind);
+    runtime.register("fun_params", fun_params);
+    runtime.register("fun_id", fun_id);
+}
+
+/// `fun_arity(f)`: The maximum number of arguments `f` uses (additional ones are ignored), or
+/// `nil` for a rust function that did not declare it.
+pub fn fun_arity(args: &[Value]) -> Result<Value, Value> {
+    let arity = match fun(args, "fun_arity")? {
+        Fun::Pan(closure) => Some(closure.arity()),
+        Fun::Native(native) => native.arity(),
+        Fun::NativeAsync(native) => native.arity(),
+    };
+    Ok(arity.map_or(Value::Nil, |n| Value::Int(n as i64)))
+}
+
+/// `fun_name(f)`: The name of `f` as a string, or `nil` if it is anonymous.
+pub fn fun_name(args: &[Value]) -> Result<Value, Value> {
+    let name = match fun(args, "fun_name")? {
+        Fun::Pan(closure) => closure.name().map(Rope::from_str),
+        Fun::Native(native) => Some(Rope::from_str(native.name())),
+        Fun::NativeAsync(native) => Some(Rope::from_str(native.name())),
+    };
+    Ok(name.map_or(Value::Nil, Value::String))
+}
+
+/// `fun_kind(f)`: `"pan"` for functions written in pan, `"native"` for rust functions, and
+/// `"native_async"` for rust functions that return futures.
+pub fn fun_kind(args: &[Value]) -> Result<Value, Value> {
+    let kind = match fun(args, "fun_kind")? {
+        Fun::Pan(_) => "pan",
+        Fun::Native(_) => "native",
+        Fun::NativeAsync(_) => "native_async",
+    };
+    Ok(Value::String(Rope::from_str(kind)))
+}
+
+/// `fun_params(f)`: The names of the parameters of `f` as an array of strings, or `nil` if it can
+/// not be called with named arguments.
+pub fn fun_params(args: &[Value]) -> Result<Value, Value> {
+    let params: Option<Vec<Value>> = match fun(args, "fun_params")? {
+        Fun::Pan(closure) => closure.params().map(|params| {
+            params.iter().map(|param| Value::String(Rope::from_str(param))).collect()
+        }),
+        Fun::Native(native) => native.params().map(|params| {
+            params.iter().map(|param| Value::String(Rope::from_str(param))).collect()
+        }),
+        Fun::NativeAsync(native) => native.params().map(|params| {
+            params.iter().map(|param| Value::String(Rope::from_str(param))).collect()
+        }),
+    };
+    Ok(params.map_or(Value::Nil, Value::array))
+}
+
+/// `fun_id(f)`: The stamp of `f`, an int that orders functions by when they were created. For
+/// debugging only: stamps are not unique across runtimes and differ between runs.
+pub fn fun_id(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Int(fun(args, "fun_id")?.id() as i64))
+}
+
+fn fun<'a>(args: &'a [Value], name: &str) -> Result<&'a Fun, Value> {
+    match args.first() {
+        Some(Value::Fun(f)) => Ok(f),
+        other => Err(Value::error("type", &format!(
+            "{} expects a function, got {}", name, other.map_or("nil", Value::type_name),
+        ))),
+    }
+}
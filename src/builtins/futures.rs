@@ -0,0 +1,170 @@
+// Builtins for working with futures.
+
+use crate::runtime::Runtime;
+use crate::types::{
+    rope::Rope,
+    futures::Future,
+};
+use crate::value::Value;
+
+/// Register the future builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("fut_resolve", fut_resolve);
+    runtime.register("fut_reject", fut_reject);
+    runtime.register("fut_never", fut_never);
+    runtime.register("fut_state", fut_state);
+    runtime.register("fut_cancel", fut_cancel);
+    runtime.register("fut_on_idle", fut_on_idle);
+    runtime.register("fut_sleep", fut_sleep);
+    runtime.register("fut_then", fut_then);
+    runtime.register("fut_map_err", fut_map_err);
+    runtime.register("fut_finally", fut_finally);
+    runtime.register("fut_scope", fut_scope);
+    runtime.register("fut_timeout", fut_timeout);
+    runtime.register("fut_all", fut_all);
+    runtime.register("fut_race", fut_race);
+    runtime.register("fut_any", fut_any);
+}
+
+/// `fut_resolve(v)`: A future that resolves to `v` as soon as it is run.
+pub fn fut_resolve(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::future_resolve(args.first().cloned().unwrap_or(Value::Nil)))
+}
+
+/// `fut_reject(v)`: A future that rejects with `v` as soon as it is run.
+pub fn fut_reject(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::future_reject(args.first().cloned().unwrap_or(Value::Nil)))
+}
+
+/// `fut_never()`: A future that never settles once it is run, it can only be cancelled. It does
+/// not keep the event loop busy.
+pub fn fut_never(_args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Future(Future::never()))
+}
+
+/// `fut_state(f)`: The name of the lifecycle state the future `f` is in, one of `"inert"`,
+/// `"staged"`, `"pending"`, `"resolved"`, `"rejected"` or `"cancelled"`.
+pub fn fut_state(args: &[Value]) -> Result<Value, Value> {
+    match args.first() {
+        Some(Value::Future(fut)) => Ok(Value::String(Rope::from_str(fut.state().name()))),
+        _ => Err(Value::error("type", "fut_state expects a future")),
+    }
+}
+
+/// `fut_cancel(f)`: Cancel the future `f` if it is pending, together with the pending futures it
+/// was composed from. Futures that depend on `f` reject with an error of kind `"cancelled"`.
+/// Returns whether `f` was pending, futures in any other state are not affected.
+pub fn fut_cancel(args: &[Value]) -> Result<Value, Value> {
+    match args.first() {
+        Some(Value::Future(fut)) => Ok(Value::Bool(fut.cancel())),
+        _ => Err(Value::error("type", "fut_cancel expects a future")),
+    }
+}
+
+/// `fut_on_idle()`: A future that resolves to `nil` once the event loop has no other work to do:
+/// all other futures wait for external events or timers, and no combinator has an outcome to
+/// process. If several such futures are waiting, only the one that was run first resolves, the
+/// next one waits until the loop is idle again.
+pub fn fut_on_idle(_args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Future(Future::on_idle()))
+}
+
+/// `fut_sleep(millis)`: A future that resolves to `nil` once `millis` milliseconds have passed
+/// after it was run. Durations of zero or less are over on the next turn of the event loop.
+pub fn fut_sleep(args: &[Value]) -> Result<Value, Value> {
+    match args.first() {
+        Some(Value::Int(millis)) => Ok(Value::Future(Future::sleep(*millis))),
+        _ => Err(Value::error("type", "fut_sleep expects an int")),
+    }
+}
+
+/// `fut_then(f, on_resolve)`: A future that settles like `f`, except that if `f` resolves, its
+/// value is passed to `on_resolve`. The future then resolves to the return value, or rejects with
+/// the thrown value. The callback is called by the event loop, never by `fut_then` itself.
+pub fn fut_then(args: &[Value]) -> Result<Value, Value> {
+    let (fut, callback) = future_and_callback(args, "fut_then")?;
+    Ok(Value::Future(Future::then(fut, callback)))
+}
+
+/// `fut_map_err(f, on_reject)`: The mirror image of `fut_then`, a future that settles like `f`,
+/// except that if `f` rejects, the rejection value is passed to `on_reject`. Values that unwind to
+/// the embedder (e.g. exit values) are not passed to `on_reject`, see `types::exit::unwinds`.
+pub fn fut_map_err(args: &[Value]) -> Result<Value, Value> {
+    let (fut, callback) = future_and_callback(args, "fut_map_err")?;
+    Ok(Value::Future(Future::map_err(fut, callback)))
+}
+
+/// `fut_finally(f, callback)`: A future that calls `callback` without arguments once `f` is done,
+/// whether it resolved, rejected or was cancelled, and then settles like `f`. If the callback
+/// throws or returns a future that rejects, the future rejects with that value instead. If the
+/// callback returns a future, the future only settles once that one has resolved.
+pub fn fut_finally(args: &[Value]) -> Result<Value, Value> {
+    let (fut, callback) = future_and_callback(args, "fut_finally")?;
+    Ok(Value::Future(Future::finally(fut, callback)))
+}
+
+/// `fut_scope(body)`: A future that, once run, calls `body` with a function `spawn`, which runs a
+/// future as a child of the scope and returns it. The scope settles like the future returned by
+/// `body` (or resolves to the returned value if it is not a future). Once the scope is done, all
+/// of its children that are still pending are cancelled, before the scope itself settles. If a
+/// child rejects while nothing observes it, the scope rejects with the same value, which also
+/// cancels the other children.
+pub fn fut_scope(args: &[Value]) -> Result<Value, Value> {
+    match args.first() {
+        Some(body @ Value::Fun(_)) => Ok(Value::Future(Future::scope(body.clone()))),
+        _ => Err(Value::error("type", "fut_scope expects a function")),
+    }
+}
+
+/// `fut_timeout(f, millis)`: A future that settles like `f` if `f` settles within `millis`
+/// milliseconds after it was run. Otherwise, it rejects with an error of kind `"timeout"` and
+/// cancels `f`. If `f` settles exactly at the deadline, `f` wins.
+pub fn fut_timeout(args: &[Value]) -> Result<Value, Value> {
+    match (args.first(), args.get(1)) {
+        (Some(Value::Future(fut)), Some(Value::Int(millis))) => {
+            Ok(Value::Future(Future::timeout(fut.clone(), *millis)))
+        }
+        _ => Err(Value::error("type", "fut_timeout expects a future and an int")),
+    }
+}
+
+/// `fut_all(arr)`: A future that resolves to an array of the values of the futures in `arr`, in
+/// the same order, once all of them have resolved. Rejects as soon as any of them rejects, with
+/// the same value, and cancels the others. Elements of `arr` that are not futures are used as
+/// they are, as if they were futures that resolved to themselves. An empty array yields a
+/// future that resolves to an empty array.
+pub fn fut_all(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Future(Future::all(inputs(args, "fut_all")?)))
+}
+
+/// `fut_race(arr)`: A future that settles like the first of the futures in `arr` to settle, and
+/// cancels the others. Elements of `arr` that are not futures are treated like futures that
+/// resolved to themselves. If `arr` is empty, the future rejects with an error of kind `"empty"`.
+pub fn fut_race(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Future(Future::race(inputs(args, "fut_race")?)))
+}
+
+/// `fut_any(arr)`: A future that resolves like the first of the futures in `arr` to resolve, and
+/// cancels the others. If all of them reject, the future rejects with an array of the rejection
+/// values, in the same order. Elements of `arr` that are not futures are treated like futures
+/// that resolved to themselves. If `arr` is empty, the future rejects with an error of kind
+/// `"empty"`.
+pub fn fut_any(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Future(Future::any(inputs(args, "fut_any")?)))
+}
+
+fn inputs(args: &[Value], name: &str) -> Result<Vec<Value>, Value> {
+    match args.first() {
+        Some(Value::Array(arr)) => Ok(arr.borrow().clone()),
+        _ => Err(Value::error("type", &format!("{} expects an array", name))),
+    }
+}
+
+fn future_and_callback(args: &[Value], name: &str) -> Result<(Future, Value), Value> {
+    match (args.first(), args.get(1)) {
+        (Some(Value::Future(fut)), Some(callback @ Value::Fun(_))) => {
+            Ok((fut.clone(), callback.clone()))
+        }
+        _ => Err(Value::error("type", &format!("{} expects a future and a function", name))),
+    }
+}
@@ -0,0 +1,60 @@
+// Builtins for controlling the garbage collector and observing the heap.
+//
+// `gc_stats` returns a map with the int fields `live_collections`, `collections_allocated`,
+// `environments_created`, `collections_forced`, `collections_triggered` and `collection_micros`
+// (the time spent collecting in microseconds), see `types::heap::GcStats`. The `gc` crate does not
+// report how many bytes it manages, so there is no such field.
+//
+// The embedder can deny these builtins (see `Runtime::allow_gc_control`), in which case they throw
+// an error of kind `"forbidden"`.
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::runtime::Runtime;
+use crate::types::heap;
+use crate::value::Value;
+
+type Builtin = fn(&Cell<bool>, &[Value]) -> Result<Value, Value>;
+
+/// Register the gc builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    let builtins: [(&'static str, Builtin); 2] = [
+        ("gc_collect", gc_collect),
+        ("gc_stats", gc_stats),
+    ];
+    for (name, builtin) in builtins.iter().cloned() {
+        let allowed: Rc<Cell<bool>> = runtime.gc_control();
+        runtime.register(name, move |args| builtin(&allowed, args));
+    }
+}
+
+/// `gc_collect()`: Force a garbage collection.
+pub fn gc_collect(allowed: &Cell<bool>, _args: &[Value]) -> Result<Value, Value> {
+    ensure_allowed(allowed, "gc_collect")?;
+    heap::collect();
+    Ok(Value::Nil)
+}
+
+/// `gc_stats()`: The current heap statistics.
+pub fn gc_stats(allowed: &Cell<bool>, _args: &[Value]) -> Result<Value, Value> {
+    ensure_allowed(allowed, "gc_stats")?;
+    let stats = heap::stats();
+    let int = |n: u64| Value::Int(n as i64);
+    Ok(Value::record(vec![
+        ("live_collections", int(stats.live_collections)),
+        ("collections_allocated", int(stats.collections_allocated)),
+        ("environments_created", int(stats.environments_created)),
+        ("collections_forced", int(stats.collections_forced)),
+        ("collections_triggered", int(stats.collections_triggered)),
+        ("collection_micros", int(stats.collection_time.as_micros() as u64)),
+    ]))
+}
+
+fn ensure_allowed(allowed: &Cell<bool>, name: &str) -> Result<(), Value> {
+    if allowed.get() {
+        Ok(())
+    } else {
+        Err(Value::error("forbidden", &format!("{} has been disabled by the embedder", name)))
+    }
+}
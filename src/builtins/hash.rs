@@ -0,0 +1,52 @@
+// Builtins for hashing values.
+//
+// Hashes are consistent with equality (see `Hash for Value`): equal values have equal hashes,
+// no matter how they were constructed. They are stable for the lifetime of a process, but may
+// change between versions of this crate, so they must not be persisted. Functions, futures,
+// channels, streams and userdata are hashed by identity.
+//
+// `hash` uses a fixed key, so an adversary who controls the hashed values can produce collisions.
+// `hash_seeded` mixes a seed into the hash, so choosing a secret seed prevents this.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use crate::runtime::Runtime;
+use crate::value::Value;
+
+/// Register the hashing builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("hash", hash);
+    runtime.register("hash_seeded", hash_seeded);
+}
+
+/// `hash(v)`: The hash of `v`, an int.
+pub fn hash(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Int(hash_value(&arg(args), None)))
+}
+
+/// `hash_seeded(v, seed)`: The hash of `v` under the seed (an int). Different seeds yield
+/// unrelated hashes.
+pub fn hash_seeded(args: &[Value]) -> Result<Value, Value> {
+    match args.get(1) {
+        Some(Value::Int(seed)) => Ok(Value::Int(hash_value(&arg(args), Some(*seed)))),
+        other => Err(Value::error("type", &format!(
+            "hash_seeded expects an int as the seed, got {}",
+            other.map_or("nil", Value::type_name),
+        ))),
+    }
+}
+
+fn hash_value(v: &Value, seed: Option<i64>) -> i64 {
+    let mut hasher = DefaultHasher::new();
+    if let Some(seed) = seed {
+        hasher.write_i64(seed);
+    }
+    v.hash(&mut hasher);
+    hasher.finish() as i64
+}
+
+// Missing arguments are `nil`.
+fn arg(args: &[Value]) -> Value {
+    args.first().cloned().unwrap_or(Value::Nil)
+}
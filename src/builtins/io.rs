@@ -0,0 +1,142 @@
+// Builtins for reading input and writing output.
+//
+// `print` and `println` write to the stdout sink of the runtime, `eprint` and `eprintln` to its
+// stderr sink. Both default to the corresponding streams of the process, embedders can redirect
+// them with `Runtime::set_stdout` and `Runtime::set_stderr`. Strings are written verbatim, all
+// other values in literal syntax (see `Display for Value`), separated by single spaces. The sink
+// is flushed after every call.
+//
+// `read_line` and `read_all_stdin` read from the stdin source of the runtime, which defaults to
+// the standard input of the process and can be replaced with `Runtime::set_stdin`. They read at
+// most `MAX_READ_LEN` bytes at once, longer input throws an error of kind `"range"` (and is
+// consumed). Input that is not valid UTF-8 throws an error of kind `"decode"`.
+//
+// Failing to read or write throws an error of kind `"io"`, the output may then be incomplete.
+
+use std::io::{BufRead, Read, Write};
+
+use crate::runtime::{Runtime, Sink, Source};
+use crate::types::rope::Rope;
+use crate::value::Value;
+
+/// The maximum number of bytes that `read_line` and `read_all_stdin` read.
+pub const MAX_READ_LEN: usize = 1 << 24;
+
+/// Register `print` and `println` under their names, writing to the stdout sink of the runtime.
+pub fn register_stdout(runtime: &mut Runtime) {
+    let stdout = runtime.stdout();
+    runtime.register("print", move |args| print(&stdout, args));
+    let stdout = runtime.stdout();
+    runtime.register("println", move |args| println(&stdout, args));
+}
+
+/// Register `eprint` and `eprintln` under their names, writing to the stderr sink of the runtime.
+pub fn register_stderr(runtime: &mut Runtime) {
+    let stderr = runtime.stderr();
+    runtime.register("eprint", move |args| eprint(&stderr, args));
+    let stderr = runtime.stderr();
+    runtime.register("eprintln", move |args| eprintln(&stderr, args));
+}
+
+/// Register the input builtins under their names, reading from the stdin source of the runtime.
+pub fn register_stdin(runtime: &mut Runtime) {
+    let stdin = runtime.stdin();
+    runtime.register("read_line", move |args| read_line(&stdin, args));
+    let stdin = runtime.stdin();
+    runtime.register("read_all_stdin", move |args| read_all_stdin(&stdin, args));
+}
+
+/// `print(v...)`: Write the values to stdout.
+pub fn print(stdout: &Sink, args: &[Value]) -> Result<Value, Value> {
+    write_values(stdout, args, false, "print")
+}
+
+/// `println(v...)`: Write the values to stdout, followed by a newline.
+pub fn println(stdout: &Sink, args: &[Value]) -> Result<Value, Value> {
+    write_values(stdout, args, true, "println")
+}
+
+/// `eprint(v...)`: Write the values to stderr.
+pub fn eprint(stderr: &Sink, args: &[Value]) -> Result<Value, Value> {
+    write_values(stderr, args, false, "eprint")
+}
+
+/// `eprintln(v...)`: Write the values to stderr, followed by a newline.
+pub fn eprintln(stderr: &Sink, args: &[Value]) -> Result<Value, Value> {
+    write_values(stderr, args, true, "eprintln")
+}
+
+/// `read_line()`: The next line of stdin without the line break (`\n` or `\r\n`), or nil if
+/// the input has ended.
+pub fn read_line(stdin: &Source, _args: &[Value]) -> Result<Value, Value> {
+    let mut line = vec![];
+    let mut stdin = stdin.borrow_mut();
+    let read = stdin.by_ref().take(MAX_READ_LEN as u64 + 1).read_until(b'\n', &mut line);
+    match read {
+        Ok(0) => return Ok(Value::Nil),
+        Ok(_) => {}
+        Err(err) => return Err(io_error("read_line", &err)),
+    }
+    if line.ends_with(b"\n") {
+        line.pop();
+        if line.ends_with(b"\r") {
+            line.pop();
+        }
+    } else if line.len() > MAX_READ_LEN {
+        return Err(too_long("read_line"));
+    }
+    decode(line, "read_line")
+}
+
+/// `read_all_stdin()`: The remaining input of stdin, as a string.
+pub fn read_all_stdin(stdin: &Source, _args: &[Value]) -> Result<Value, Value> {
+    let mut input = vec![];
+    let mut stdin = stdin.borrow_mut();
+    if let Err(err) = stdin.by_ref().take(MAX_READ_LEN as u64 + 1).read_to_end(&mut input) {
+        return Err(io_error("read_all_stdin", &err));
+    }
+    if input.len() > MAX_READ_LEN {
+        return Err(too_long("read_all_stdin"));
+    }
+    decode(input, "read_all_stdin")
+}
+
+fn decode(input: Vec<u8>, name: &str) -> Result<Value, Value> {
+    match String::from_utf8(input) {
+        Ok(s) => Ok(Value::String(Rope::from_str(&s))),
+        Err(err) => Err(Value::error("decode", &format!(
+            "{} read invalid UTF-8: {}", name, err.utf8_error(),
+        ))),
+    }
+}
+
+fn too_long(name: &str) -> Value {
+    Value::error("range", &format!("{} can read at most {} bytes at once", name, MAX_READ_LEN))
+}
+
+fn io_error(name: &str, err: &std::io::Error) -> Value {
+    Value::error("io", &format!("{} failed to read: {}", name, err))
+}
+
+fn write_values(sink: &Sink, args: &[Value], newline: bool, name: &str) -> Result<Value, Value> {
+    let mut w = sink.borrow_mut();
+    let mut write = || {
+        for (i, v) in args.iter().enumerate() {
+            if i > 0 {
+                w.write_all(b" ")?;
+            }
+            match v {
+                Value::String(s) => s.write_to(&mut *w)?,
+                _ => write!(w, "{}", v)?,
+            }
+        }
+        if newline {
+            w.write_all(b"\n")?;
+        }
+        w.flush()
+    };
+    match write() {
+        Ok(()) => Ok(Value::Nil),
+        Err(err) => Err(Value::error("io", &format!("{} failed to write: {}", name, err))),
+    }
+}
@@ -0,0 +1,280 @@
+// Iterators, and higher-order builtins that call a function for the elements of a container.
+//
+// `iter(v)` returns an iterator over an array (its elements), a set (its members in ascending
+// order), a map (its entries as two-element arrays of the key and the value, in ascending order
+// of the keys), a string (its chars) or bytes (its bytes, as ints). `iter` of an iterator returns
+// the iterator itself. `iter_next(it)` returns `{"done": false, "value": v}` for the next element
+// `v`, or `{"done": true}` once the iterator is exhausted. See `types::iter` for how iterators
+// behave if their container is mutated during the iteration.
+//
+// The higher-order builtins accept anything that `iter` accepts, and iterate over it in the same
+// way. They pass each element to the callback, except for maps, for which they pass each key and
+// its value as two arguments. Throws from the callback stop the iteration and are thrown by the
+// builtin. No container is borrowed while the callback runs, so the callback may freely access
+// and mutate it.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::runtime::Runtime;
+use crate::types::iter::Iter;
+use crate::types::stream::{done, item};
+use crate::value::Value;
+
+/// Register the iteration builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("iter", iter);
+    runtime.register("iter_next", iter_next);
+    runtime.register("arr_map", arr_map);
+    runtime.register("arr_filter", arr_filter);
+    runtime.register("fold", fold);
+    runtime.register("each", each);
+    runtime.register("find", find);
+    runtime.register("any", any);
+    runtime.register("all", all);
+    runtime.register("group_by", group_by);
+    runtime.register("unique", unique);
+    runtime.register("unique_by", unique_by);
+    runtime.register("partition", partition);
+    runtime.register("count_by", count_by);
+}
+
+/// `iter(v)`: An iterator over `v`.
+pub fn iter(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Iter(iterator(args, "iter")?))
+}
+
+/// `iter_next(it)`: The next element of the iterator, as `{"done": false, "value": v}`, or
+/// `{"done": true}` if there is none.
+pub fn iter_next(args: &[Value]) -> Result<Value, Value> {
+    match args.first() {
+        Some(Value::Iter(it)) => Ok(it.next()?.map_or_else(done, item)),
+        other => Err(Value::error("type", &format!(
+            "iter_next expects an iterator, got {}", other.map_or("nil", Value::type_name),
+        ))),
+    }
+}
+
+/// `arr_map(coll, f)`: A new array of the results of calling `f` for each element of `coll`.
+pub fn arr_map(args: &[Value]) -> Result<Value, Value> {
+    let f = function(args, 1, "arr_map")?;
+    let mut results = vec![];
+    for_each(args, "arr_map", |item| {
+        results.push(f.apply(&item)?);
+        Ok(true)
+    })?;
+    Ok(Value::array(results))
+}
+
+/// `arr_filter(coll, pred)`: The elements (or entries) of `coll` for which `pred` returns a truthy
+/// value, as a new set if `coll` is a set, a new map if it is a map, and a new array otherwise.
+pub fn arr_filter(args: &[Value]) -> Result<Value, Value> {
+    let pred = function(args, 1, "arr_filter")?;
+    let mut kept = vec![];
+    for_each(args, "arr_filter", |item| {
+        if pred.apply(&item)?.truthy() {
+            kept.push(item);
+        }
+        Ok(true)
+    })?;
+
+    Ok(match args.first() {
+        Some(Value::Set(_)) => {
+            let set: BTreeSet<Value> = kept.into_iter().flatten().collect();
+            Value::set(set)
+        }
+        Some(Value::Map(_)) => {
+            let map: BTreeMap<Value, Value> = kept
+                .into_iter()
+                .map(|mut entry| {
+                    let value = entry.pop().expect("entries have a key and a value");
+                    (entry.pop().expect("entries have a key and a value"), value)
+                })
+                .collect();
+            Value::map(map)
+        }
+        _ => Value::array(kept.into_iter().flatten().collect()),
+    })
+}
+
+/// `fold(coll, init, f)`: Combine the elements of `coll` into a single value, by calling
+/// `f(acc, element)` (or `f(acc, key, value)`) for each element, where `acc` is `init` for the
+/// first call and the result of the previous call afterwards. Returns the result of the last
+/// call, or `init` if `coll` is empty.
+pub fn fold(args: &[Value]) -> Result<Value, Value> {
+    let f = function(args, 2, "fold")?;
+    let mut acc = args.get(1).cloned().unwrap_or(Value::Nil);
+    for_each(args, "fold", |mut item| {
+        item.insert(0, acc.clone());
+        acc = f.apply(&item)?;
+        Ok(true)
+    })?;
+    Ok(acc)
+}
+
+/// `each(coll, f)`: Call `f` for each element of `coll`, for its side effects.
+pub fn each(args: &[Value]) -> Result<Value, Value> {
+    let f = function(args, 1, "each")?;
+    for_each(args, "each", |item| {
+        f.apply(&item)?;
+        Ok(true)
+    })?;
+    Ok(Value::Nil)
+}
+
+/// `find(coll, pred)`: The first element for which `pred` returns a truthy value, or `nil` if
+/// there is none. For maps, the entry is returned as an array of the key and the value.
+pub fn find(args: &[Value]) -> Result<Value, Value> {
+    let pred = function(args, 1, "find")?;
+    let mut found = Value::Nil;
+    for_each(args, "find", |item| {
+        if pred.apply(&item)?.truthy() {
+            found = element(item);
+            Ok(false)
+        } else {
+            Ok(true)
+        }
+    })?;
+    Ok(found)
+}
+
+/// `any(coll, pred)`: Whether `pred` returns a truthy value for any element. Stops at the first
+/// such element, `false` for empty collections.
+pub fn any(args: &[Value]) -> Result<Value, Value> {
+    let pred = function(args, 1, "any")?;
+    let mut result = false;
+    for_each(args, "any", |item| {
+        result = pred.apply(&item)?.truthy();
+        Ok(!result)
+    })?;
+    Ok(Value::Bool(result))
+}
+
+/// `all(coll, pred)`: Whether `pred` returns a truthy value for all elements. Stops at the first
+/// element for which it does not, `true` for empty collections.
+pub fn all(args: &[Value]) -> Result<Value, Value> {
+    let pred = function(args, 1, "all")?;
+    let mut result = true;
+    for_each(args, "all", |item| {
+        result = pred.apply(&item)?.truthy();
+        Ok(result)
+    })?;
+    Ok(Value::Bool(result))
+}
+
+/// `group_by(coll, f)`: A new map from each result of `f` to a new array of the elements (or
+/// entries) for which `f` returned it, in the order of iteration.
+pub fn group_by(args: &[Value]) -> Result<Value, Value> {
+    let f = function(args, 1, "group_by")?;
+    let mut groups: BTreeMap<Value, Vec<Value>> = BTreeMap::new();
+    for_each(args, "group_by", |item| {
+        let key = f.apply(&item)?;
+        groups.entry(key).or_default().push(element(item));
+        Ok(true)
+    })?;
+    Ok(Value::map(groups.into_iter().map(|(key, group)| (key, Value::array(group))).collect()))
+}
+
+/// `unique(coll)`: A new array of the elements (or entries) of `coll` without duplicates, keeping
+/// the first occurrence of each.
+pub fn unique(args: &[Value]) -> Result<Value, Value> {
+    let mut seen = BTreeSet::new();
+    let mut kept = vec![];
+    for_each(args, "unique", |item| {
+        let element = element(item);
+        if seen.insert(element.clone()) {
+            kept.push(element);
+        }
+        Ok(true)
+    })?;
+    Ok(Value::array(kept))
+}
+
+/// `unique_by(coll, f)`: A new array of the elements (or entries) of `coll`, keeping only the
+/// first of those for which `f` returns equal values.
+pub fn unique_by(args: &[Value]) -> Result<Value, Value> {
+    let f = function(args, 1, "unique_by")?;
+    let mut seen = BTreeSet::new();
+    let mut kept = vec![];
+    for_each(args, "unique_by", |item| {
+        if seen.insert(f.apply(&item)?) {
+            kept.push(element(item));
+        }
+        Ok(true)
+    })?;
+    Ok(Value::array(kept))
+}
+
+/// `partition(coll, pred)`: A two-element array of a new array of the elements (or entries) for
+/// which `pred` returns a truthy value, and a new array of the others.
+pub fn partition(args: &[Value]) -> Result<Value, Value> {
+    let pred = function(args, 1, "partition")?;
+    let (mut yes, mut no) = (vec![], vec![]);
+    for_each(args, "partition", |item| {
+        if pred.apply(&item)?.truthy() {
+            yes.push(element(item));
+        } else {
+            no.push(element(item));
+        }
+        Ok(true)
+    })?;
+    Ok(Value::array(vec![Value::array(yes), Value::array(no)]))
+}
+
+/// `count_by(coll, f)`: A new map from each result of `f` to the number of elements for which `f`
+/// returned it.
+pub fn count_by(args: &[Value]) -> Result<Value, Value> {
+    let f = function(args, 1, "count_by")?;
+    let mut counts: BTreeMap<Value, i64> = BTreeMap::new();
+    for_each(args, "count_by", |item| {
+        *counts.entry(f.apply(&item)?).or_insert(0) += 1;
+        Ok(true)
+    })?;
+    Ok(Value::map(counts.into_iter().map(|(key, n)| (key, Value::Int(n))).collect()))
+}
+
+// An element from its callback arguments: map entries become an array of the key and the value.
+fn element(mut item: Vec<Value>) -> Value {
+    if item.len() == 1 {
+        item.remove(0)
+    } else {
+        Value::array(item)
+    }
+}
+
+// Call `f` with the callback arguments for each element of the container that is the first
+// argument, until `f` returns `false` or throws.
+fn for_each(
+    args: &[Value],
+    name: &str,
+    mut f: impl FnMut(Vec<Value>) -> Result<bool, Value>,
+) -> Result<(), Value> {
+    let it = iterator(args, name)?;
+    let entries = matches!(args.first(), Some(Value::Map(_)));
+    while let Some(element) = it.next()? {
+        let item = match &element {
+            Value::Array(entry) if entries => entry.borrow().clone(),
+            _ => vec![element],
+        };
+        if !f(item)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn iterator(args: &[Value], name: &str) -> Result<Iter, Value> {
+    let v = args.first().cloned().unwrap_or(Value::Nil);
+    Iter::of(&v).ok_or_else(|| Value::error("type", &format!(
+        "{} expects an array, set, map, string, bytes or an iterator, got {}",
+        name, v.type_name(),
+    )))
+}
+
+fn function(args: &[Value], i: usize, name: &str) -> Result<Value, Value> {
+    match args.get(i) {
+        Some(f @ Value::Fun(_)) => Ok(f.clone()),
+        other => Err(Value::error("type", &format!(
+            "{} expects a function, got {}", name, other.map_or("nil", Value::type_name),
+        ))),
+    }
+}
@@ -0,0 +1,157 @@
+// Builtins for working with maps.
+//
+// Keys can be arbitrary values and are compared structurally, see `eq`. Mutating a collection
+// that is (part of) a key changes its position in the order of keys without moving the entry, so
+// lookups of that entry may fail afterwards. Functions that list the contents of a map return new
+// arrays in ascending order of the keys. Arguments of the wrong type throw an error of kind
+// `"type"`, mutating a frozen map throws an error of kind `"frozen"`, and storing a value of
+// another runtime (see `types::owner`) throws an error of kind `"runtime"`.
+
+use gc::Gc;
+
+use crate::builtins::freeze;
+use crate::runtime::Runtime;
+use crate::types::collection::Collection;
+use crate::types::owner;
+use crate::types::persistent::PersistentMap;
+use crate::value::Value;
+
+type Map = Gc<Collection<PersistentMap<Value, Value>>>;
+
+/// Register the map builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("map_get", map_get);
+    runtime.register("map_get_or_throw", map_get_or_throw);
+    runtime.register("map_insert", map_insert);
+    runtime.register("map_remove", map_remove);
+    runtime.register("map_has", map_has);
+    runtime.register("map_len", map_len);
+    runtime.register("map_keys", map_keys);
+    runtime.register("map_values", map_values);
+    runtime.register("map_entries", map_entries);
+    runtime.register("map_merge", map_merge);
+    runtime.register("map_clear", map_clear);
+}
+
+/// `map_get(m, k [, default])`: The value of the key `k`, or `default` (`nil` if omitted) if `m`
+/// has no such key.
+pub fn map_get(args: &[Value]) -> Result<Value, Value> {
+    let m = map(args, 0, "map_get")?;
+    let found = m.borrow().get(&arg(args, 1)).cloned();
+    Ok(found.unwrap_or_else(|| arg(args, 2)))
+}
+
+/// `map_get_or_throw(m, k)`: The value of the key `k`, throws an error of kind `"key"` if `m` has
+/// no such key.
+pub fn map_get_or_throw(args: &[Value]) -> Result<Value, Value> {
+    let m = map(args, 0, "map_get_or_throw")?;
+    let k = arg(args, 1);
+    let found = m.borrow().get(&k).cloned();
+    found.ok_or_else(|| {
+        Value::error("key", &format!("map_get_or_throw failed: the map has no key {}", k))
+    })
+}
+
+/// `map_insert(m, k, v)`: Set the value of the key `k` to `v`. Returns the previous value, or
+/// `nil` if there was none.
+pub fn map_insert(args: &[Value]) -> Result<Value, Value> {
+    let m = map(args, 0, "map_insert")?;
+    owner::check_stored(m.owner(), &arg(args, 1))?;
+    owner::check_stored(m.owner(), &arg(args, 2))?;
+    let previous = mutate(&m, "map_insert", |entries| entries.insert(arg(args, 1), arg(args, 2)))?;
+    Ok(previous.unwrap_or(Value::Nil))
+}
+
+/// `map_remove(m, k)`: Remove the key `k` from the map. Returns its value, or `nil` if there was
+/// none.
+pub fn map_remove(args: &[Value]) -> Result<Value, Value> {
+    let m = map(args, 0, "map_remove")?;
+    let removed = mutate(&m, "map_remove", |entries| entries.remove(&arg(args, 1)))?;
+    Ok(removed.unwrap_or(Value::Nil))
+}
+
+/// `map_has(m, k)`: Whether the map has the key `k`.
+pub fn map_has(args: &[Value]) -> Result<Value, Value> {
+    let m = map(args, 0, "map_has")?;
+    let has = m.borrow().contains_key(&arg(args, 1));
+    Ok(Value::Bool(has))
+}
+
+/// `map_len(m)`: The number of entries of the map.
+pub fn map_len(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Int(map(args, 0, "map_len")?.borrow().len() as i64))
+}
+
+/// `map_keys(m)`: An array of the keys of the map.
+pub fn map_keys(args: &[Value]) -> Result<Value, Value> {
+    let m = map(args, 0, "map_keys")?;
+    let keys = m.borrow().keys().cloned().collect();
+    Ok(Value::array(keys))
+}
+
+/// `map_values(m)`: An array of the values of the map, in the order of their keys.
+pub fn map_values(args: &[Value]) -> Result<Value, Value> {
+    let m = map(args, 0, "map_values")?;
+    let values = m.borrow().values().cloned().collect();
+    Ok(Value::array(values))
+}
+
+/// `map_entries(m)`: An array of the entries of the map, each a two-element array of the key and
+/// the value.
+pub fn map_entries(args: &[Value]) -> Result<Value, Value> {
+    let m = map(args, 0, "map_entries")?;
+    let entries = m
+        .borrow()
+        .iter()
+        .map(|(k, v)| Value::array(vec![k.clone(), v.clone()]))
+        .collect();
+    Ok(Value::array(entries))
+}
+
+/// `map_merge(a, b)`: A new map with the entries of both maps. For keys that are in both maps,
+/// the value from `b` is used.
+pub fn map_merge(args: &[Value]) -> Result<Value, Value> {
+    let a = map(args, 0, "map_merge")?;
+    let b = map(args, 1, "map_merge")?;
+    let mut merged = a.borrow().clone();
+    let right = b.borrow().clone();
+    merged.extend(right);
+    Ok(Value::Map(Gc::new(Collection::new(merged))))
+}
+
+/// `map_clear(m)`: Remove all entries.
+pub fn map_clear(args: &[Value]) -> Result<Value, Value> {
+    let m = map(args, 0, "map_clear")?;
+    freeze::ensure_mutable(&m, "map_clear")?;
+    m.borrow_mut().clear();
+    Ok(Value::Nil)
+}
+
+// Apply `f` to the entries of the map, unless it is frozen. The map is not borrowed while `f`
+// runs, as comparing keys may need to read the map itself (if it is part of a key). It appears
+// empty during that time.
+fn mutate<T>(
+    m: &Map,
+    name: &str,
+    f: impl FnOnce(&mut PersistentMap<Value, Value>) -> T,
+) -> Result<T, Value> {
+    freeze::ensure_mutable(m, name)?;
+    let mut entries = std::mem::take(&mut *m.borrow_mut());
+    let result = f(&mut entries);
+    *m.borrow_mut() = entries;
+    Ok(result)
+}
+
+fn map(args: &[Value], i: usize, name: &str) -> Result<Map, Value> {
+    match args.get(i) {
+        Some(Value::Map(m)) => Ok(m.clone()),
+        other => Err(Value::error("type", &format!(
+            "{} expects a map, got {}", name, other.map_or("nil", Value::type_name),
+        ))),
+    }
+}
+
+// Missing arguments are `nil`.
+fn arg(args: &[Value], i: usize) -> Value {
+    args.get(i).cloned().unwrap_or(Value::Nil)
+}
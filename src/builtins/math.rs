@@ -0,0 +1,203 @@
+// Floating point math builtins.
+//
+// The functions accept ints as well as floats, ints are converted to the nearest float. Unlike
+// int arithmetic (see `value::ops`), they never throw on numeric input: they follow IEEE 754, so
+// arguments outside the domain of a function yield NaN (`sqrt(-1)`) or an infinity (`ln(0)`).
+//
+// The rounding functions `floor`, `ceil`, `round` and `trunc` return an int if the rounded value
+// fits into one, and the rounded float otherwise (which happens for NaN, the infinities and
+// floats of very large magnitude). Ints are returned unchanged. `abs` maps ints to ints and
+// floats to floats, the absolute value of the smallest int throws an error of kind `"overflow"`.
+// `pow` follows the int arithmetic of `value::ops` instead: the power of two ints is an int.
+// `sign` always returns an int, and throws an error of kind `"range"` for NaN, which has no sign.
+
+use std::f64::consts;
+
+use ordered_float::OrderedFloat;
+
+use crate::runtime::Runtime;
+use crate::value::{ops, Value};
+
+/// Register the math builtins and constants under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("sqrt", sqrt);
+    runtime.register("cbrt", cbrt);
+    runtime.register("pow", pow);
+    runtime.register("exp", exp);
+    runtime.register("ln", ln);
+    runtime.register("log2", log2);
+    runtime.register("log10", log10);
+    runtime.register("sin", sin);
+    runtime.register("cos", cos);
+    runtime.register("tan", tan);
+    runtime.register("asin", asin);
+    runtime.register("acos", acos);
+    runtime.register("atan", atan);
+    runtime.register("atan2", atan2);
+    runtime.register("floor", floor);
+    runtime.register("ceil", ceil);
+    runtime.register("round", round);
+    runtime.register("trunc", trunc);
+    runtime.register("abs", abs);
+    runtime.register("sign", sign);
+    let constants = [
+        ("pi", consts::PI),
+        ("e", consts::E),
+        ("inf", f64::INFINITY),
+        ("nan", f64::NAN),
+    ];
+    for &(name, x) in constants.iter() {
+        runtime.define(name, float(x)).expect("numbers belong to no runtime");
+    }
+}
+
+/// `sqrt(x)`: The square root of `x`.
+pub fn sqrt(args: &[Value]) -> Result<Value, Value> {
+    unary(args, "sqrt", f64::sqrt)
+}
+
+/// `cbrt(x)`: The cube root of `x`.
+pub fn cbrt(args: &[Value]) -> Result<Value, Value> {
+    unary(args, "cbrt", f64::cbrt)
+}
+
+/// `pow(x, y)`: `x` raised to the power of `y`. Unlike the other functions, `pow` of two ints is
+/// an int, see `value::ops` for the details.
+pub fn pow(args: &[Value]) -> Result<Value, Value> {
+    let arg = |i| args.get(i).cloned().unwrap_or(Value::Nil);
+    ops::pow(&arg(0), &arg(1))
+}
+
+/// `exp(x)`: `e` raised to the power of `x`.
+pub fn exp(args: &[Value]) -> Result<Value, Value> {
+    unary(args, "exp", f64::exp)
+}
+
+/// `ln(x)`: The natural logarithm of `x`.
+pub fn ln(args: &[Value]) -> Result<Value, Value> {
+    unary(args, "ln", f64::ln)
+}
+
+/// `log2(x)`: The base 2 logarithm of `x`.
+pub fn log2(args: &[Value]) -> Result<Value, Value> {
+    unary(args, "log2", f64::log2)
+}
+
+/// `log10(x)`: The base 10 logarithm of `x`.
+pub fn log10(args: &[Value]) -> Result<Value, Value> {
+    unary(args, "log10", f64::log10)
+}
+
+/// `sin(x)`: The sine of `x` (in radians).
+pub fn sin(args: &[Value]) -> Result<Value, Value> {
+    unary(args, "sin", f64::sin)
+}
+
+/// `cos(x)`: The cosine of `x` (in radians).
+pub fn cos(args: &[Value]) -> Result<Value, Value> {
+    unary(args, "cos", f64::cos)
+}
+
+/// `tan(x)`: The tangent of `x` (in radians).
+pub fn tan(args: &[Value]) -> Result<Value, Value> {
+    unary(args, "tan", f64::tan)
+}
+
+/// `asin(x)`: The arcsine of `x`, in radians.
+pub fn asin(args: &[Value]) -> Result<Value, Value> {
+    unary(args, "asin", f64::asin)
+}
+
+/// `acos(x)`: The arccosine of `x`, in radians.
+pub fn acos(args: &[Value]) -> Result<Value, Value> {
+    unary(args, "acos", f64::acos)
+}
+
+/// `atan(x)`: The arctangent of `x`, in radians.
+pub fn atan(args: &[Value]) -> Result<Value, Value> {
+    unary(args, "atan", f64::atan)
+}
+
+/// `atan2(y, x)`: The angle of the point `(x, y)` in radians, in the range `-pi..=pi`.
+pub fn atan2(args: &[Value]) -> Result<Value, Value> {
+    Ok(float(number(args, 0, "atan2")?.atan2(number(args, 1, "atan2")?)))
+}
+
+/// `floor(x)`: The largest integer less than or equal to `x`.
+pub fn floor(args: &[Value]) -> Result<Value, Value> {
+    rounding(args, "floor", f64::floor)
+}
+
+/// `ceil(x)`: The smallest integer greater than or equal to `x`.
+pub fn ceil(args: &[Value]) -> Result<Value, Value> {
+    rounding(args, "ceil", f64::ceil)
+}
+
+/// `round(x)`: The integer nearest to `x`, rounding half-way cases away from zero.
+pub fn round(args: &[Value]) -> Result<Value, Value> {
+    rounding(args, "round", f64::round)
+}
+
+/// `trunc(x)`: The integer part of `x`, rounding towards zero.
+pub fn trunc(args: &[Value]) -> Result<Value, Value> {
+    rounding(args, "trunc", f64::trunc)
+}
+
+/// `abs(x)`: The absolute value of `x`.
+pub fn abs(args: &[Value]) -> Result<Value, Value> {
+    match args.first() {
+        Some(Value::Int(n)) => n
+            .checked_abs()
+            .map(Value::Int)
+            .ok_or_else(|| Value::error("overflow", "abs overflowed")),
+        Some(Value::Float(x)) => Ok(float(x.into_inner().abs())),
+        other => Err(not_a_number("abs", other)),
+    }
+}
+
+/// `sign(x)`: `-1` if `x` is negative, `0` if it is zero (including `-0.0`), `1` if it is
+/// positive.
+pub fn sign(args: &[Value]) -> Result<Value, Value> {
+    match args.first() {
+        Some(Value::Int(n)) => Ok(Value::Int(n.signum())),
+        Some(Value::Float(x)) => match x.into_inner().partial_cmp(&0.0) {
+            Some(ordering) => Ok(Value::Int(ordering as i64)),
+            None => Err(Value::error("range", "sign expects a number other than NaN")),
+        },
+        other => Err(not_a_number("sign", other)),
+    }
+}
+
+fn float(x: f64) -> Value {
+    Value::Float(OrderedFloat(x))
+}
+
+fn unary(args: &[Value], name: &str, f: fn(f64) -> f64) -> Result<Value, Value> {
+    Ok(float(f(number(args, 0, name)?)))
+}
+
+fn rounding(args: &[Value], name: &str, f: fn(f64) -> f64) -> Result<Value, Value> {
+    match args.first() {
+        Some(Value::Int(n)) => Ok(Value::Int(*n)),
+        Some(Value::Float(x)) => {
+            let rounded = f(x.into_inner());
+            Ok(ops::float_to_int(rounded).map_or_else(|| float(rounded), Value::Int))
+        }
+        other => Err(not_a_number(name, other)),
+    }
+}
+
+// The argument at position `i` as a float, converting ints.
+fn number(args: &[Value], i: usize, name: &str) -> Result<f64, Value> {
+    match args.get(i) {
+        Some(Value::Int(n)) => Ok(*n as f64),
+        Some(Value::Float(x)) => Ok(x.into_inner()),
+        other => Err(not_a_number(name, other)),
+    }
+}
+
+fn not_a_number(name: &str, arg: Option<&Value>) -> Value {
+    Value::error("type", &format!(
+        "{} expects numbers, got {}", name, arg.map_or("nil", Value::type_name),
+    ))
+}
@@ -0,0 +1,41 @@
+// Builtins for importing modules, see `module`.
+//
+// Arguments of the wrong type throw an error of kind `"type"`, failed imports throw an error of
+// kind `"import"`.
+
+use std::rc::Rc;
+
+use crate::module::{self, ModuleRequest, Modules};
+use crate::runtime::Runtime;
+use crate::types::owner::RuntimeId;
+use crate::value::Value;
+
+/// Register the module builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    let modules = runtime.modules();
+    let id = runtime.id();
+    runtime.register("import", move |args| import(&modules, id, args));
+}
+
+/// `import(specifier, importer)`: The module that the resolver of the runtime finds for the
+/// string `specifier`, loading it on the first import of its key. `importer` is the key of the
+/// importing module, or `nil` (the default) for imports from outside of any module.
+pub fn import(modules: &Rc<Modules>, runtime: RuntimeId, args: &[Value]) -> Result<Value, Value> {
+    let specifier = match args.first() {
+        Some(Value::String(s)) => s.to_string(),
+        other => return Err(type_error("a string as the specifier", other)),
+    };
+    let importer = match args.get(1) {
+        None | Some(Value::Nil) => None,
+        Some(Value::String(s)) => Some(s.to_string()),
+        other => return Err(type_error("a string or nil as the importer", other)),
+    };
+    let request = ModuleRequest { specifier, importer };
+    modules.import(&request, runtime).map_err(|err| module::import_error(&request, &err))
+}
+
+fn type_error(expected: &str, got: Option<&Value>) -> Value {
+    Value::error("type", &format!(
+        "import expects {}, got {}", expected, got.map_or("nil", Value::type_name),
+    ))
+}
@@ -0,0 +1,125 @@
+// Pseudo-random number builtins, see `types::random` for the generator.
+//
+// Every runtime has its own generator, which is seeded from entropy unless the runtime is
+// deterministic (then it is seeded with the seed of the runtime). `random_get_seed` reveals the
+// seed so that a run can be reproduced with `random_seed`. Seeds are ints, all 64 bits of which
+// are used.
+//
+// Empty ranges and arrays throw an error of kind `"range"`, arguments of the wrong type throw an
+// error of kind `"type"`. Shuffling a frozen array throws an error of kind `"frozen"`.
+
+use ordered_float::OrderedFloat;
+
+use crate::builtins::freeze;
+use crate::runtime::Runtime;
+use crate::types::random::Random;
+use crate::value::Value;
+
+type Builtin = fn(&Random, &[Value]) -> Result<Value, Value>;
+
+/// Register the random builtins under their names, using the generator of the runtime.
+pub fn register(runtime: &mut Runtime) {
+    let builtins: [(&'static str, Builtin); 7] = [
+        ("random_seed", random_seed),
+        ("random_get_seed", random_get_seed),
+        ("random_int", random_int),
+        ("random_float", random_float),
+        ("random_bool", random_bool),
+        ("shuffle", shuffle),
+        ("choice", choice),
+    ];
+    for (name, builtin) in builtins.iter().cloned() {
+        let random = runtime.random().clone();
+        runtime.register(name, move |args| builtin(&random, args));
+    }
+}
+
+/// `random_seed(seed)`: Restart the generator with the given seed.
+pub fn random_seed(random: &Random, args: &[Value]) -> Result<Value, Value> {
+    random.seed(int(args, 0, "random_seed")? as u64);
+    Ok(Value::Nil)
+}
+
+/// `random_get_seed()`: The seed the generator was last started with.
+pub fn random_get_seed(random: &Random, _args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Int(random.get_seed() as i64))
+}
+
+/// `random_int(lo, hi)`: A uniformly distributed int from `lo` up to (excluding) `hi`. Throws an
+/// error of kind `"range"` if `hi` is not greater than `lo`.
+pub fn random_int(random: &Random, args: &[Value]) -> Result<Value, Value> {
+    let lo = int(args, 0, "random_int")?;
+    let hi = int(args, 1, "random_int")?;
+    if hi <= lo {
+        return Err(Value::error("range", &format!(
+            "random_int expects a non-empty range, got {} to {}", lo, hi,
+        )));
+    }
+    let span = (i128::from(hi) - i128::from(lo)) as u64;
+    Ok(Value::Int((i128::from(lo) + i128::from(random.below(span))) as i64))
+}
+
+/// `random_float()`: A uniformly distributed float from `0.0` up to (excluding) `1.0`.
+pub fn random_float(random: &Random, _args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Float(OrderedFloat(random.float())))
+}
+
+/// `random_bool([p])`: `true` with probability `p` (a number from `0` to `1`, defaulting to
+/// `0.5`), `false` otherwise.
+pub fn random_bool(random: &Random, args: &[Value]) -> Result<Value, Value> {
+    let p = match args.first() {
+        None | Some(Value::Nil) => 0.5,
+        Some(Value::Int(n)) => *n as f64,
+        Some(Value::Float(x)) => x.into_inner(),
+        other => return Err(type_error("a number", other, "random_bool")),
+    };
+    if !(0.0..=1.0).contains(&p) {
+        return Err(Value::error("range", &format!(
+            "random_bool expects a probability from 0 to 1, got {}", p,
+        )));
+    }
+    Ok(Value::Bool(random.float() < p))
+}
+
+/// `shuffle(arr)`: Put the elements of the array into a uniformly random order.
+pub fn shuffle(random: &Random, args: &[Value]) -> Result<Value, Value> {
+    let arr = match args.first() {
+        Some(Value::Array(arr)) => arr.clone(),
+        other => return Err(type_error("an array", other, "shuffle")),
+    };
+    freeze::ensure_mutable(&arr, "shuffle")?;
+    let mut arr = arr.borrow_mut();
+    // Fisher-Yates: each position in turn receives a uniformly chosen remaining element.
+    for i in (1..arr.len()).rev() {
+        let j = random.below(i as u64 + 1) as usize;
+        arr.swap(i, j);
+    }
+    Ok(Value::Nil)
+}
+
+/// `choice(arr)`: A uniformly chosen element of the array. Throws an error of kind `"range"` if the
+/// array is empty.
+pub fn choice(random: &Random, args: &[Value]) -> Result<Value, Value> {
+    let arr = match args.first() {
+        Some(Value::Array(arr)) => arr.clone(),
+        other => return Err(type_error("an array", other, "choice")),
+    };
+    let arr = arr.borrow();
+    if arr.is_empty() {
+        return Err(Value::error("range", "choice expects a non-empty array"));
+    }
+    Ok(arr[random.below(arr.len() as u64) as usize].clone())
+}
+
+fn int(args: &[Value], i: usize, name: &str) -> Result<i64, Value> {
+    match args.get(i) {
+        Some(Value::Int(n)) => Ok(*n),
+        other => Err(type_error("an int", other, name)),
+    }
+}
+
+fn type_error(expected: &str, got: Option<&Value>, name: &str) -> Value {
+    Value::error("type", &format!(
+        "{} expects {}, got {}", name, expected, got.map_or("nil", Value::type_name),
+    ))
+}
@@ -0,0 +1,255 @@
+// Regular expression builtins, see `types::regex` for the supported syntax.
+//
+// `re_compile` returns a compiled regex as a userdata, which the other builtins take as their
+// first argument. The matching builtins materialize the string they search as an array of chars,
+// which takes time and space linear in its length, even if the match is found early.
+//
+// A match is described by a map `{"start": start, "end": end, "text": text}` of the char indices
+// at which it starts and ends, and of the matched string. Captures are described by a map from
+// the index (an int, zero for the whole match) and, for named groups, the name (a string) of each
+// group to the string it matched, or nil if it did not participate in the match.
+//
+// Invalid patterns throw an error of kind `"parse"`, and arguments of the wrong type throw an
+// error of kind `"type"`. Throws from replacement functions are thrown by `re_replace`.
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use crate::runtime::Runtime;
+use crate::types::regex::{Captures, Regex};
+use crate::types::rope::{Rope, RopeBuilder};
+use crate::types::userdata::{PanUserdata, Userdata};
+use crate::value::Value;
+
+impl PanUserdata for Regex {
+    fn type_name(&self) -> &'static str {
+        "regex"
+    }
+}
+
+/// Register the regex builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("re_compile", re_compile);
+    runtime.register("re_is_match", re_is_match);
+    runtime.register("re_find", re_find);
+    runtime.register("re_find_all", re_find_all);
+    runtime.register("re_captures", re_captures);
+    runtime.register("re_replace", re_replace);
+    runtime.register("re_split", re_split);
+}
+
+/// `re_compile(pattern)`: Compile a pattern into a regex.
+pub fn re_compile(args: &[Value]) -> Result<Value, Value> {
+    let pattern = match args.first() {
+        Some(Value::String(s)) => s.to_string(),
+        other => return Err(type_error("a string", other, "re_compile")),
+    };
+    match Regex::new(&pattern) {
+        Ok(regex) => Ok(Value::Userdata(Userdata::new(regex))),
+        Err(err) => Err(Value::error("parse", &format!("re_compile failed: {}", err))),
+    }
+}
+
+/// `re_is_match(re, s)`: Whether the regex matches anywhere in the string.
+pub fn re_is_match(args: &[Value]) -> Result<Value, Value> {
+    let (re, s) = arguments(args, "re_is_match")?;
+    let text: Vec<char> = s.chars().collect();
+    Ok(Value::Bool(with_regex(&re, |regex| regex.is_match(&text))))
+}
+
+/// `re_find(re, s)`: The leftmost match of the regex in the string, or nil if there is none.
+pub fn re_find(args: &[Value]) -> Result<Value, Value> {
+    let (re, s) = arguments(args, "re_find")?;
+    let text: Vec<char> = s.chars().collect();
+    Ok(match with_regex(&re, |regex| regex.captures_at(&text, 0)) {
+        Some(captures) => match_record(&s, whole(&captures)),
+        None => Value::Nil,
+    })
+}
+
+/// `re_find_all(re, s)`: An array of all successive non-overlapping matches of the regex in the
+/// string. After an empty match, the search resumes one char further.
+pub fn re_find_all(args: &[Value]) -> Result<Value, Value> {
+    let (re, s) = arguments(args, "re_find_all")?;
+    let text: Vec<char> = s.chars().collect();
+    let matches: Vec<Captures> = with_regex(&re, |regex| regex.captures_iter(&text).collect());
+    Ok(Value::array(matches.iter().map(|captures| match_record(&s, whole(captures))).collect()))
+}
+
+/// `re_captures(re, s)`: The captures of the leftmost match of the regex in the string, or nil if
+/// there is none.
+pub fn re_captures(args: &[Value]) -> Result<Value, Value> {
+    let (re, s) = arguments(args, "re_captures")?;
+    let text: Vec<char> = s.chars().collect();
+    Ok(match with_regex(&re, |regex| regex.captures_at(&text, 0)) {
+        Some(captures) => captures_map(&re, &s, &captures),
+        None => Value::Nil,
+    })
+}
+
+/// `re_replace(re, s, replacement)`: The string with all successive non-overlapping matches of
+/// the regex replaced.
+///
+/// The replacement is either a string or a function. In a string, `$n` and `${n}` stand for the
+/// string matched by the group with index `n` and `$name` and `${name}` for the group with that
+/// name (the empty string if it did not participate in the match or does not exist). `$name`
+/// extends as far as possible over letters, digits and `_`. `$$` stands for a literal `$`. A
+/// function is called with the captures of each match, and must return the string to replace the
+/// match with.
+pub fn re_replace(args: &[Value]) -> Result<Value, Value> {
+    let (re, s) = arguments(args, "re_replace")?;
+    let replacement = match args.get(2) {
+        Some(Value::String(replacement)) => Replacement::Template(replacement.to_string()),
+        Some(f @ Value::Fun(_)) => Replacement::Function(f.clone()),
+        other => return Err(type_error("a string or a function", other, "re_replace")),
+    };
+    let text: Vec<char> = s.chars().collect();
+    let matches: Vec<Captures> = with_regex(&re, |regex| regex.captures_iter(&text).collect());
+
+    let mut b = RopeBuilder::new();
+    let mut end = 0;
+    for captures in &matches {
+        let matched = whole(captures);
+        b.push_rope(&slice(&s, end..matched.start));
+        match &replacement {
+            Replacement::Template(template) => expand(template, &re, &s, captures, &mut b),
+            Replacement::Function(f) => match &f.apply(&[captures_map(&re, &s, captures)])? {
+                Value::String(replaced) => b.push_rope(replaced),
+                other => {
+                    return Err(Value::error("type", &format!(
+                        "re_replace expects the replacement function to return a string, got {}",
+                        other.type_name(),
+                    )));
+                }
+            },
+        }
+        end = matched.end;
+    }
+    b.push_rope(&slice(&s, end..s.len_chars()));
+
+    Ok(Value::String(b.finish()))
+}
+
+enum Replacement {
+    Template(String),
+    Function(Value),
+}
+
+/// `re_split(re, s)`: An array of the strings between the successive non-overlapping matches of
+/// the regex in the string, one more than there are matches.
+pub fn re_split(args: &[Value]) -> Result<Value, Value> {
+    let (re, s) = arguments(args, "re_split")?;
+    let text: Vec<char> = s.chars().collect();
+    let matches: Vec<Captures> = with_regex(&re, |regex| regex.captures_iter(&text).collect());
+
+    let mut parts = vec![];
+    let mut end = 0;
+    for captures in &matches {
+        let matched = whole(captures);
+        parts.push(Value::String(slice(&s, end..matched.start)));
+        end = matched.end;
+    }
+    parts.push(Value::String(slice(&s, end..s.len_chars())));
+
+    Ok(Value::array(parts))
+}
+
+// Push the replacement string with its group references expanded.
+fn expand(replacement: &str, re: &Userdata, s: &Rope, captures: &Captures, b: &mut RopeBuilder) {
+    let mut rest = replacement;
+    while let Some(dollar) = rest.find('$') {
+        b.push_str(&rest[..dollar]);
+        rest = &rest[dollar + 1..];
+
+        let (name, after) = if rest.starts_with('$') {
+            b.push_char('$');
+            rest = &rest[1..];
+            continue;
+        } else if let Some(braced) = rest.strip_prefix('{') {
+            match braced.find('}') {
+                Some(close) => (&braced[..close], &braced[close + 1..]),
+                None => ("", rest),
+            }
+        } else {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            (&rest[..len], &rest[len..])
+        };
+
+        if name.is_empty() {
+            // Not a group reference, so the `$` is literal.
+            b.push_char('$');
+            continue;
+        }
+        let group = match name.parse::<usize>() {
+            Ok(index) => Some(index),
+            Err(_) => with_regex(re, |regex| {
+                (0..regex.captures_len()).find(|group| regex.group_name(*group) == Some(name))
+            }),
+        };
+        if let Some(Some(range)) = group.and_then(|group| captures.get(group)) {
+            b.push_rope(&slice(s, range.clone()));
+        }
+        rest = after;
+    }
+    b.push_str(rest);
+}
+
+// The map of the captures of a match.
+fn captures_map(re: &Userdata, s: &Rope, captures: &Captures) -> Value {
+    let mut map = BTreeMap::new();
+    with_regex(re, |regex| {
+        for (group, range) in captures.iter().enumerate() {
+            let matched = match range {
+                Some(range) => Value::String(slice(s, range.clone())),
+                None => Value::Nil,
+            };
+            if let Some(name) = regex.group_name(group) {
+                map.insert(Value::String(Rope::from_str(name)), matched.clone());
+            }
+            map.insert(Value::Int(group as i64), matched);
+        }
+    });
+    Value::map(map)
+}
+
+fn match_record(s: &Rope, range: Range<usize>) -> Value {
+    Value::record(vec![
+        ("start", Value::Int(range.start as i64)),
+        ("end", Value::Int(range.end as i64)),
+        ("text", Value::String(slice(s, range))),
+    ])
+}
+
+// The range of the whole match.
+fn whole(captures: &Captures) -> Range<usize> {
+    captures[0].clone().unwrap()
+}
+
+// The ranges come from matching against the chars of `s`, so they are in bounds.
+fn slice(s: &Rope, range: Range<usize>) -> Rope {
+    s.slice(range).unwrap()
+}
+
+fn with_regex<R>(re: &Userdata, f: impl FnOnce(&Regex) -> R) -> R {
+    re.with(f).unwrap()
+}
+
+// The regex and the string that all matching builtins take.
+fn arguments(args: &[Value], name: &str) -> Result<(Userdata, Rope), Value> {
+    let re = match args.first() {
+        Some(Value::Userdata(re)) if re.is::<Regex>() => re.clone(),
+        other => return Err(type_error("a regex", other, name)),
+    };
+    match args.get(1) {
+        Some(Value::String(s)) => Ok((re, s.clone())),
+        other => Err(type_error("a string", other, name)),
+    }
+}
+
+fn type_error(expected: &str, got: Option<&Value>, name: &str) -> Value {
+    Value::error("type", &format!(
+        "{} expects {}, got {}", name, expected, got.map_or("nil", Value::type_name),
+    ))
+}
@@ -0,0 +1,154 @@
+// Builtins for working with sets.
+//
+// Members can be arbitrary values and are compared structurally, see `eq`, with the same caveat
+// about mutating members as for the keys of maps. The set algebra functions (`union` and so on)
+// return new sets and never mutate their arguments, both arguments may be the same set.
+// Arguments of the wrong type throw an error of kind `"type"`, mutating a frozen set throws an
+// error of kind `"frozen"`, and storing a value of another runtime (see `types::owner`) throws an
+// error of kind `"runtime"`.
+
+use gc::Gc;
+
+use crate::builtins::freeze;
+use crate::runtime::Runtime;
+use crate::types::collection::Collection;
+use crate::types::owner;
+use crate::types::persistent::PersistentSet;
+use crate::value::Value;
+
+type Set = Gc<Collection<PersistentSet<Value>>>;
+
+/// Register the set builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("set_add", set_add);
+    runtime.register("set_remove", set_remove);
+    runtime.register("set_has", set_has);
+    runtime.register("set_len", set_len);
+    runtime.register("set_to_array", set_to_array);
+    runtime.register("union", union);
+    runtime.register("intersection", intersection);
+    runtime.register("difference", difference);
+    runtime.register("symmetric_difference", symmetric_difference);
+    runtime.register("is_subset", is_subset);
+    runtime.register("is_superset", is_superset);
+    runtime.register("is_disjoint", is_disjoint);
+}
+
+/// `set_add(s, v)`: Add `v` to the set. Returns whether it was not a member before.
+pub fn set_add(args: &[Value]) -> Result<Value, Value> {
+    let s = set(args, 0, "set_add")?;
+    owner::check_stored(s.owner(), &arg(args, 1))?;
+    Ok(Value::Bool(mutate(&s, "set_add", |members| members.insert(arg(args, 1)))?))
+}
+
+/// `set_remove(s, v)`: Remove `v` from the set. Returns whether it was a member.
+pub fn set_remove(args: &[Value]) -> Result<Value, Value> {
+    let s = set(args, 0, "set_remove")?;
+    Ok(Value::Bool(mutate(&s, "set_remove", |members| members.remove(&arg(args, 1)))?))
+}
+
+/// `set_has(s, v)`: Whether `v` is a member of the set.
+pub fn set_has(args: &[Value]) -> Result<Value, Value> {
+    let s = set(args, 0, "set_has")?;
+    let has = s.borrow().contains(&arg(args, 1));
+    Ok(Value::Bool(has))
+}
+
+/// `set_len(s)`: The number of members of the set.
+pub fn set_len(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Int(set(args, 0, "set_len")?.borrow().len() as i64))
+}
+
+/// `set_to_array(s)`: A new array of the members of the set, in ascending order.
+pub fn set_to_array(args: &[Value]) -> Result<Value, Value> {
+    let s = set(args, 0, "set_to_array")?;
+    let members = s.borrow().iter().cloned().collect();
+    Ok(Value::array(members))
+}
+
+/// `union(a, b)`: A new set of the values that are members of `a` or `b`.
+pub fn union(args: &[Value]) -> Result<Value, Value> {
+    algebra(args, "union", |a, b| a.union(b).cloned().collect())
+}
+
+/// `intersection(a, b)`: A new set of the values that are members of both `a` and `b`.
+pub fn intersection(args: &[Value]) -> Result<Value, Value> {
+    algebra(args, "intersection", |a, b| a.intersection(b).cloned().collect())
+}
+
+/// `difference(a, b)`: A new set of the members of `a` that are not members of `b`.
+pub fn difference(args: &[Value]) -> Result<Value, Value> {
+    algebra(args, "difference", |a, b| a.difference(b).cloned().collect())
+}
+
+/// `symmetric_difference(a, b)`: A new set of the values that are members of exactly one of `a`
+/// and `b`.
+pub fn symmetric_difference(args: &[Value]) -> Result<Value, Value> {
+    algebra(args, "symmetric_difference", |a, b| a.symmetric_difference(b).cloned().collect())
+}
+
+/// `is_subset(a, b)`: Whether all members of `a` are members of `b`.
+pub fn is_subset(args: &[Value]) -> Result<Value, Value> {
+    predicate(args, "is_subset", PersistentSet::is_subset)
+}
+
+/// `is_superset(a, b)`: Whether all members of `b` are members of `a`.
+pub fn is_superset(args: &[Value]) -> Result<Value, Value> {
+    predicate(args, "is_superset", PersistentSet::is_superset)
+}
+
+/// `is_disjoint(a, b)`: Whether `a` and `b` have no members in common.
+pub fn is_disjoint(args: &[Value]) -> Result<Value, Value> {
+    predicate(args, "is_disjoint", PersistentSet::is_disjoint)
+}
+
+// Both sets are only borrowed immutably, so they may be the same set.
+fn algebra(
+    args: &[Value],
+    name: &str,
+    f: impl FnOnce(&PersistentSet<Value>, &PersistentSet<Value>) -> PersistentSet<Value>,
+) -> Result<Value, Value> {
+    let a = set(args, 0, name)?;
+    let b = set(args, 1, name)?;
+    let result = f(&a.borrow(), &b.borrow());
+    Ok(Value::Set(Gc::new(Collection::new(result))))
+}
+
+fn predicate(
+    args: &[Value],
+    name: &str,
+    f: impl FnOnce(&PersistentSet<Value>, &PersistentSet<Value>) -> bool,
+) -> Result<Value, Value> {
+    let a = set(args, 0, name)?;
+    let b = set(args, 1, name)?;
+    let result = f(&a.borrow(), &b.borrow());
+    Ok(Value::Bool(result))
+}
+
+// Apply `f` to the members of the set unless it is frozen. The set is not borrowed while `f`
+// runs, see the `mutate` function of the map builtins.
+fn mutate<T>(
+    s: &Set,
+    name: &str,
+    f: impl FnOnce(&mut PersistentSet<Value>) -> T,
+) -> Result<T, Value> {
+    freeze::ensure_mutable(s, name)?;
+    let mut members = std::mem::take(&mut *s.borrow_mut());
+    let result = f(&mut members);
+    *s.borrow_mut() = members;
+    Ok(result)
+}
+
+fn set(args: &[Value], i: usize, name: &str) -> Result<Set, Value> {
+    match args.get(i) {
+        Some(Value::Set(s)) => Ok(s.clone()),
+        other => Err(Value::error("type", &format!(
+            "{} expects a set, got {}", name, other.map_or("nil", Value::type_name),
+        ))),
+    }
+}
+
+// Missing arguments are `nil`.
+fn arg(args: &[Value], i: usize) -> Value {
+    args.get(i).cloned().unwrap_or(Value::Nil)
+}
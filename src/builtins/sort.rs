@@ -0,0 +1,217 @@
+// Sorting builtins, and builtins for working with sorted arrays.
+//
+// All sorts are stable and happen in place. They sort a copy of the elements and write it back
+// once done, so a comparator or key function that reads the array sees the original order, and
+// any changes it makes to the array are overwritten. If a comparator or key function throws,
+// the sort stops, the array is left unchanged and the builtin throws the same value. A
+// comparator that is inconsistent results in an unspecified order, but always terminates.
+// Sorting a frozen array throws an error of kind `"frozen"`.
+
+use gc::Gc;
+
+use crate::builtins::freeze;
+use crate::runtime::Runtime;
+use crate::types::collection::Collection;
+use crate::value::Value;
+
+/// Register the sorting builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("sort", sort);
+    runtime.register("sort_by", sort_by);
+    runtime.register("sort_by_key", sort_by_key);
+    runtime.register("binary_search", binary_search);
+    runtime.register("binary_search_by", binary_search_by);
+    runtime.register("is_sorted", is_sorted);
+    runtime.register("is_sorted_by", is_sorted_by);
+}
+
+/// `sort(arr)`: Sort an array in ascending structural order, see `compare`.
+pub fn sort(args: &[Value]) -> Result<Value, Value> {
+    let arr = array(args, "sort")?;
+    freeze::ensure_mutable(&arr, "sort")?;
+    let elements = arr.borrow().clone();
+    let sorted = merge_sort(elements, &mut |a, b| Ok(a < b))?;
+    write_back(&arr, sorted, "sort")
+}
+
+/// `sort_by(arr, cmp)`: Sort an array by a comparator, a function that takes two elements and
+/// returns a negative int if the first one is less than the second, zero if they are equal, and
+/// a positive int if the first one is greater (e.g. `compare`).
+pub fn sort_by(args: &[Value]) -> Result<Value, Value> {
+    let arr = array(args, "sort_by")?;
+    freeze::ensure_mutable(&arr, "sort_by")?;
+    let cmp = function(args, "sort_by")?;
+    let elements = arr.borrow().clone();
+    let sorted = merge_sort(elements, &mut |a, b| {
+        Ok(comparison(cmp.apply(&[a.clone(), b.clone()])?, "sort_by")? < 0)
+    })?;
+    write_back(&arr, sorted, "sort_by")
+}
+
+/// `sort_by_key(arr, key)`: Sort an array by the structural order of the values that the function
+/// `key` returns for the elements. `key` is called exactly once per element, in order.
+pub fn sort_by_key(args: &[Value]) -> Result<Value, Value> {
+    let arr = array(args, "sort_by_key")?;
+    freeze::ensure_mutable(&arr, "sort_by_key")?;
+    let key = function(args, "sort_by_key")?;
+    let elements = arr.borrow().clone();
+    let keyed = elements
+        .into_iter()
+        .map(|v| Ok((key.apply(std::slice::from_ref(&v))?, v)))
+        .collect::<Result<Vec<_>, Value>>()?;
+    let sorted = merge_sort(keyed, &mut |(a, _), (b, _)| Ok(a < b))?;
+    write_back(&arr, sorted.into_iter().map(|(_, v)| v).collect(), "sort_by_key")
+}
+
+/// `binary_search(arr, needle)`: Search an array sorted in ascending structural order for
+/// `needle`. Returns `{"found": true, "index": i}` if the element at index `i` equals `needle`,
+/// otherwise `{"found": false, "insert_at": i}` where `i` is the index at which `needle` could be
+/// inserted to keep the array sorted. If several elements equal `needle`, any of their indices
+/// may be returned. The result is unspecified if the array is not sorted.
+pub fn binary_search(args: &[Value]) -> Result<Value, Value> {
+    let arr = array(args, "binary_search")?;
+    let needle = args.get(1).cloned().unwrap_or(Value::Nil);
+    let result = arr.borrow().binary_search(&needle);
+    Ok(search_result(result))
+}
+
+/// `binary_search_by(arr, probe)`: Search a sorted array with a function `probe` that returns a
+/// negative int for elements before the one searched for, zero for a match, and a positive int
+/// for elements after it. Returns the same maps as `binary_search`, with the same caveats. The
+/// search covers the indices below the length of the array at the time of the call, if the probe
+/// shrinks the array so that a later probe would read past its end, the search throws an error
+/// of kind `"range"`.
+pub fn binary_search_by(args: &[Value]) -> Result<Value, Value> {
+    let arr = array(args, "binary_search_by")?;
+    let probe = function(args, "binary_search_by")?;
+    let (mut low, mut high) = (0, arr.borrow().len());
+    while low < high {
+        let mid = low + (high - low) / 2;
+        let element = element(&arr, mid, "binary_search_by")?;
+        match comparison(probe.apply(&[element])?, "binary_search_by")? {
+            n if n < 0 => low = mid + 1,
+            0 => return Ok(search_result(Ok(mid))),
+            _ => high = mid,
+        }
+    }
+    Ok(search_result(Err(low)))
+}
+
+/// `is_sorted(arr)`: Whether each element of an array is less than or equal to the next one, in
+/// structural order.
+pub fn is_sorted(args: &[Value]) -> Result<Value, Value> {
+    let arr = array(args, "is_sorted")?;
+    let sorted = arr.borrow().windows(2).all(|pair| pair[0] <= pair[1]);
+    Ok(Value::Bool(sorted))
+}
+
+/// `is_sorted_by(arr, cmp)`: Whether the comparator `cmp` (see `sort_by`) does not return a
+/// positive int for any element and the one after it. Stops at the first such pair. Reads each
+/// element right before passing it to `cmp` and stops at the end of the array, even if the
+/// comparator changed its length.
+pub fn is_sorted_by(args: &[Value]) -> Result<Value, Value> {
+    let arr = array(args, "is_sorted_by")?;
+    let cmp = function(args, "is_sorted_by")?;
+    let mut i = 1;
+    loop {
+        let pair = {
+            let arr = arr.borrow();
+            match (arr.get(i - 1), arr.get(i)) {
+                (Some(a), Some(b)) => [a.clone(), b.clone()],
+                _ => return Ok(Value::Bool(true)),
+            }
+        };
+        if comparison(cmp.apply(&pair)?, "is_sorted_by")? > 0 {
+            return Ok(Value::Bool(false));
+        }
+        i += 1;
+    }
+}
+
+fn search_result(result: Result<usize, usize>) -> Value {
+    match result {
+        Ok(i) => Value::record(vec![
+            ("found", Value::Bool(true)),
+            ("index", Value::Int(i as i64)),
+        ]),
+        Err(i) => Value::record(vec![
+            ("found", Value::Bool(false)),
+            ("insert_at", Value::Int(i as i64)),
+        ]),
+    }
+}
+
+// Replace the elements of a sorted array. A comparator or key function may have frozen the array
+// in the meantime, in which case it is left unchanged.
+fn write_back(
+    arr: &Gc<Collection<Vec<Value>>>,
+    sorted: Vec<Value>,
+    name: &str,
+) -> Result<Value, Value> {
+    freeze::ensure_mutable(arr, name)?;
+    *arr.borrow_mut() = sorted;
+    Ok(Value::Nil)
+}
+
+fn element(arr: &Gc<Collection<Vec<Value>>>, i: usize, name: &str) -> Result<Value, Value> {
+    arr.borrow().get(i).cloned().ok_or_else(|| {
+        Value::error("range", &format!("{} read past the end of an array that shrank", name))
+    })
+}
+
+// The result of a comparator or probe.
+fn comparison(result: Value, name: &str) -> Result<i64, Value> {
+    match result {
+        Value::Int(n) => Ok(n),
+        other => Err(Value::error("type", &format!(
+            "{} expects the function to return an int, got {}", name, other.type_name(),
+        ))),
+    }
+}
+
+// A stable merge sort with a comparator that can fail. Unlike the sorts of the standard library,
+// it does not panic if the comparator is not a total order.
+fn merge_sort<T>(
+    mut v: Vec<T>,
+    less: &mut impl FnMut(&T, &T) -> Result<bool, Value>,
+) -> Result<Vec<T>, Value> {
+    if v.len() <= 1 {
+        return Ok(v);
+    }
+    let right = v.split_off(v.len() / 2);
+    let left = merge_sort(v, less)?;
+    let right = merge_sort(right, less)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        // Taking from the left unless the right one is strictly less keeps the sort stable.
+        if less(r, l)? {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+fn array(args: &[Value], name: &str) -> Result<Gc<Collection<Vec<Value>>>, Value> {
+    match args.first() {
+        Some(Value::Array(arr)) => Ok(arr.clone()),
+        other => Err(Value::error("type", &format!(
+            "{} expects an array, got {}", name, other.map_or("nil", Value::type_name),
+        ))),
+    }
+}
+
+fn function(args: &[Value], name: &str) -> Result<Value, Value> {
+    match args.get(1) {
+        Some(f @ Value::Fun(_)) => Ok(f.clone()),
+        other => Err(Value::error("type", &format!(
+            "{} expects a function, got {}", name, other.map_or("nil", Value::type_name),
+        ))),
+    }
+}
@@ -0,0 +1,78 @@
+// Builtins for working with streams.
+
+use std::convert::TryFrom;
+
+use crate::runtime::Runtime;
+use crate::types::stream::Stream;
+use crate::value::Value;
+
+/// Register the stream builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("stream_next", stream_next);
+    runtime.register("stream_from_array", stream_from_array);
+    runtime.register("stream_from_channel", stream_from_channel);
+    runtime.register("stream_interval", stream_interval);
+    runtime.register("stream_map", stream_map);
+    runtime.register("stream_take", stream_take);
+}
+
+/// `stream_next(s)`: A future for the next item of the stream `s`, which resolves to
+/// `{"done": false, "value": v}`, or to `{"done": true}` once the stream is exhausted. The item is
+/// only taken from the stream once the future settles, so cancelling it loses no item.
+pub fn stream_next(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Future(stream(args, "stream_next")?.next()))
+}
+
+/// `stream_from_array(arr)`: A stream of the elements of `arr`, in order.
+pub fn stream_from_array(args: &[Value]) -> Result<Value, Value> {
+    match args.first() {
+        Some(Value::Array(arr)) => Ok(Value::Stream(Stream::from_array(arr.borrow().clone()))),
+        _ => Err(Value::error("type", "stream_from_array expects an array")),
+    }
+}
+
+/// `stream_from_channel(ch)`: A stream of the values received from the channel `ch`, which is
+/// done once the channel has been closed and drained.
+pub fn stream_from_channel(args: &[Value]) -> Result<Value, Value> {
+    match args.first() {
+        Some(Value::Channel(chan)) => Ok(Value::Stream(Stream::from_channel(chan.clone()))),
+        _ => Err(Value::error("type", "stream_from_channel expects a channel")),
+    }
+}
+
+/// `stream_interval(millis)`: A stream of the numbers `0, 1, 2, ...`, where the future for each
+/// item resolves `millis` milliseconds after it was run.
+pub fn stream_interval(args: &[Value]) -> Result<Value, Value> {
+    match args.first() {
+        Some(Value::Int(millis)) => Ok(Value::Stream(Stream::interval(*millis))),
+        _ => Err(Value::error("type", "stream_interval expects an int")),
+    }
+}
+
+/// `stream_map(s, f)`: A stream of the items of `s`, passed through the function `f`.
+pub fn stream_map(args: &[Value]) -> Result<Value, Value> {
+    let s = stream(args, "stream_map")?;
+    match args.get(1) {
+        Some(f @ Value::Fun(_)) => Ok(Value::Stream(s.map(f.clone()))),
+        _ => Err(Value::error("type", "stream_map expects a stream and a function")),
+    }
+}
+
+/// `stream_take(s, n)`: A stream of the first `n` items of `s`. Once the `n`-th item has been
+/// produced, `s` is cancelled.
+pub fn stream_take(args: &[Value]) -> Result<Value, Value> {
+    let s = stream(args, "stream_take")?;
+    match args.get(1) {
+        Some(Value::Int(n)) if *n >= 0 => {
+            Ok(Value::Stream(s.take(usize::try_from(*n).unwrap_or(usize::MAX))))
+        }
+        _ => Err(Value::error("type", "stream_take expects a stream and a non-negative int")),
+    }
+}
+
+fn stream(args: &[Value], name: &str) -> Result<Stream, Value> {
+    match args.first() {
+        Some(Value::Stream(s)) => Ok(s.clone()),
+        _ => Err(Value::error("type", &format!("{} expects a stream", name))),
+    }
+}
@@ -0,0 +1,262 @@
+// Builtins for working with strings.
+//
+// Indices are char indices (not byte offsets), counted from zero. Indices outside the string
+// throw an error of kind `"range"`, arguments of the wrong type throw an error of kind `"type"`.
+//
+// The functions that build a string from an array throw errors whose `data` is the index of the
+// first element that could not be converted.
+
+use std::convert::TryFrom;
+
+use crate::runtime::Runtime;
+use crate::types::bytes::Bytes;
+use crate::types::rope::{Rope, RopeBuilder, RopeError};
+use crate::value::Value;
+
+/// Register the string builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("str_len", str_len);
+    runtime.register("str_byte_len", str_byte_len);
+    runtime.register("str_get", str_get);
+    runtime.register("str_slice", str_slice);
+    runtime.register("str_concat", str_concat);
+    runtime.register("str_split", str_split);
+    runtime.register("str_join", str_join);
+    runtime.register("str_contains", str_contains);
+    runtime.register("str_find", str_find);
+    runtime.register("str_starts_with", str_starts_with);
+    runtime.register("str_ends_with", str_ends_with);
+    runtime.register("str_trim", str_trim);
+    runtime.register("str_trim_start", str_trim_start);
+    runtime.register("str_trim_end", str_trim_end);
+    runtime.register("str_to_upper", str_to_upper);
+    runtime.register("str_to_lower", str_to_lower);
+    runtime.register("str_chars", str_chars);
+    runtime.register("chars_to_str", chars_to_str);
+    runtime.register("str_codepoints", str_codepoints);
+    runtime.register("codepoints_to_str", codepoints_to_str);
+    runtime.register("str_bytes", str_bytes);
+}
+
+/// `str_len(s)`: The number of chars of a string.
+pub fn str_len(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Int(string(args, 0, "str_len")?.len_chars() as i64))
+}
+
+/// `str_byte_len(s)`: The number of bytes of the utf-8 encoding of a string.
+pub fn str_byte_len(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Int(string(args, 0, "str_byte_len")?.len_bytes() as i64))
+}
+
+/// `str_get(s, i)`: The char at index `i`.
+pub fn str_get(args: &[Value]) -> Result<Value, Value> {
+    let s = string(args, 0, "str_get")?;
+    let i = index(args, 1, "str_get")?;
+    s.get(i).map(Value::Char).ok_or_else(|| {
+        rope_error(RopeError::OutOfBounds { index: i, len: s.len_chars() }, "str_get")
+    })
+}
+
+/// `str_slice(s, start [, end])`: The substring from index `start` up to (excluding) index `end`,
+/// which defaults to the length of the string.
+pub fn str_slice(args: &[Value]) -> Result<Value, Value> {
+    let s = string(args, 0, "str_slice")?;
+    let start = index(args, 1, "str_slice")?;
+    let end = match args.get(2) {
+        None | Some(Value::Nil) => s.len_chars(),
+        Some(_) => index(args, 2, "str_slice")?,
+    };
+    s.slice(start..end).map(Value::String).map_err(|err| rope_error(err, "str_slice"))
+}
+
+/// `str_concat(a, b)`: The string of the chars of `a` followed by those of `b`.
+pub fn str_concat(args: &[Value]) -> Result<Value, Value> {
+    let a = string(args, 0, "str_concat")?;
+    let b = string(args, 1, "str_concat")?;
+    Ok(Value::String(a.concat(&b)))
+}
+
+/// `str_split(s, sep)`: An array of the substrings between the occurrences of `sep`, scanning
+/// from left to right. An empty separator splits the string into its chars, so splitting the
+/// empty string yields an empty array. Otherwise, the array has one element more than there are
+/// occurrences, e.g. splitting `""` by `","` yields `[""]`.
+pub fn str_split(args: &[Value]) -> Result<Value, Value> {
+    let s = string(args, 0, "str_split")?;
+    let sep = string(args, 1, "str_split")?.to_string();
+    Ok(Value::array(s.split(&sep).into_iter().map(Value::String).collect()))
+}
+
+/// `str_join(arr, sep)`: The concatenation of the strings in the array, with `sep` between any
+/// two of them. Throws an error of kind `"type"` whose `data` is the index of the first element
+/// that is not a string.
+pub fn str_join(args: &[Value]) -> Result<Value, Value> {
+    let pieces = match args.first() {
+        Some(Value::Array(arr)) => arr.borrow().clone(),
+        other => return Err(type_error("an array", other, "str_join")),
+    };
+    let sep = string(args, 1, "str_join")?;
+
+    let mut b = RopeBuilder::new();
+    for (i, piece) in pieces.iter().enumerate() {
+        if i > 0 {
+            b.push_rope(&sep);
+        }
+        match piece {
+            Value::String(piece) => b.push_rope(piece),
+            other => {
+                return Err(element_error("type", &format!(
+                    "str_join expects an array of strings, got {} at index {}",
+                    other.type_name(), i,
+                ), i));
+            }
+        }
+    }
+    Ok(Value::String(b.finish()))
+}
+
+/// `str_contains(s, pat)`: Whether `pat` occurs in `s`.
+pub fn str_contains(args: &[Value]) -> Result<Value, Value> {
+    let s = string(args, 0, "str_contains")?;
+    let pat = string(args, 1, "str_contains")?;
+    Ok(Value::Bool(s.contains(&pat.to_string())))
+}
+
+/// `str_find(s, pat)`: The index of the first occurrence of `pat` in `s`, or `nil` if there is
+/// none. The empty string is found at index `0`.
+pub fn str_find(args: &[Value]) -> Result<Value, Value> {
+    let s = string(args, 0, "str_find")?;
+    let pat = string(args, 1, "str_find")?;
+    Ok(s.find(&pat.to_string()).map_or(Value::Nil, |i| Value::Int(i as i64)))
+}
+
+/// `str_starts_with(s, pat)`: Whether `s` begins with `pat`.
+pub fn str_starts_with(args: &[Value]) -> Result<Value, Value> {
+    let s = string(args, 0, "str_starts_with")?;
+    let pat = string(args, 1, "str_starts_with")?;
+    Ok(Value::Bool(s.starts_with(&pat.to_string())))
+}
+
+/// `str_ends_with(s, pat)`: Whether `s` ends with `pat`.
+pub fn str_ends_with(args: &[Value]) -> Result<Value, Value> {
+    let s = string(args, 0, "str_ends_with")?;
+    let pat = string(args, 1, "str_ends_with")?;
+    Ok(Value::Bool(s.ends_with(&pat.to_string())))
+}
+
+/// `str_trim(s)`: The string without leading and trailing whitespace.
+pub fn str_trim(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::String(string(args, 0, "str_trim")?.trim()))
+}
+
+/// `str_trim_start(s)`: The string without leading whitespace.
+pub fn str_trim_start(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::String(string(args, 0, "str_trim_start")?.trim_start()))
+}
+
+/// `str_trim_end(s)`: The string without trailing whitespace.
+pub fn str_trim_end(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::String(string(args, 0, "str_trim_end")?.trim_end()))
+}
+
+/// `str_to_upper(s)`: The string in upper case, using the full unicode case mappings (which may
+/// change the length, e.g. `"ß"` becomes `"SS"`).
+pub fn str_to_upper(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::String(string(args, 0, "str_to_upper")?.to_uppercase()))
+}
+
+/// `str_to_lower(s)`: The string in lower case, using the full unicode case mappings.
+pub fn str_to_lower(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::String(string(args, 0, "str_to_lower")?.to_lowercase()))
+}
+
+/// `str_chars(s)`: A new array of the chars of the string.
+pub fn str_chars(args: &[Value]) -> Result<Value, Value> {
+    let s = string(args, 0, "str_chars")?;
+    Ok(Value::array(s.chars().map(Value::Char).collect()))
+}
+
+/// `chars_to_str(arr)`: The string of the chars in the array.
+pub fn chars_to_str(args: &[Value]) -> Result<Value, Value> {
+    build(args, "chars_to_str", |i, v| match v {
+        Value::Char(c) => Ok(*c),
+        other => Err(element_error("type", &format!(
+            "chars_to_str expects an array of chars, got {} at index {}", other.type_name(), i,
+        ), i)),
+    })
+}
+
+/// `str_codepoints(s)`: A new array of the unicode scalar values of the chars of the string, as
+/// ints.
+pub fn str_codepoints(args: &[Value]) -> Result<Value, Value> {
+    let s = string(args, 0, "str_codepoints")?;
+    Ok(Value::array(s.chars().map(|c| Value::Int(c as i64)).collect()))
+}
+
+/// `codepoints_to_str(arr)`: The string of the chars whose unicode scalar values are the ints in
+/// the array. Throws an error of kind `"range"` for surrogates and ints that are not code points.
+pub fn codepoints_to_str(args: &[Value]) -> Result<Value, Value> {
+    build(args, "codepoints_to_str", |i, v| match v {
+        Value::Int(n) => u32::try_from(*n).ok().and_then(char::from_u32).ok_or_else(|| {
+            element_error("range", &format!(
+                "codepoints_to_str expects unicode scalar values, got {} at index {}", n, i,
+            ), i)
+        }),
+        other => Err(element_error("type", &format!(
+            "codepoints_to_str expects an array of ints, got {} at index {}", other.type_name(), i,
+        ), i)),
+    })
+}
+
+/// `str_bytes(s)`: The utf-8 encoding of the string, same as `str_encode_utf8`.
+pub fn str_bytes(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Bytes(Bytes::encode_utf8_of(&string(args, 0, "str_bytes")?)))
+}
+
+// Build a string from the array that is the first argument, converting each element (together
+// with its index) via `f`.
+fn build(
+    args: &[Value],
+    name: &str,
+    f: impl Fn(usize, &Value) -> Result<char, Value>,
+) -> Result<Value, Value> {
+    let arr = match args.first() {
+        Some(Value::Array(arr)) => arr.clone(),
+        other => return Err(type_error("an array", other, name)),
+    };
+    let mut b = RopeBuilder::new();
+    for (i, v) in arr.borrow().iter().enumerate() {
+        b.push_char(f(i, v)?);
+    }
+    Ok(Value::String(b.finish()))
+}
+
+fn element_error(kind: &str, message: &str, i: usize) -> Value {
+    Value::error_with_data(kind, message, Value::Int(i as i64))
+}
+
+fn string(args: &[Value], i: usize, name: &str) -> Result<Rope, Value> {
+    match args.get(i) {
+        Some(Value::String(s)) => Ok(s.clone()),
+        other => Err(type_error("a string", other, name)),
+    }
+}
+
+fn index(args: &[Value], i: usize, name: &str) -> Result<usize, Value> {
+    match args.get(i) {
+        Some(Value::Int(n)) if *n >= 0 => Ok(*n as usize),
+        Some(Value::Int(n)) => Err(Value::error("range", &format!(
+            "{} expects a non-negative index, got {}", name, n,
+        ))),
+        other => Err(type_error("an int", other, name)),
+    }
+}
+
+fn type_error(expected: &str, got: Option<&Value>, name: &str) -> Value {
+    Value::error("type", &format!(
+        "{} expects {}, got {}", name, expected, got.map_or("nil", Value::type_name),
+    ))
+}
+
+fn rope_error(err: RopeError, name: &str) -> Value {
+    Value::error("range", &format!("{} failed: {}", name, err))
+}
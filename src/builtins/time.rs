@@ -0,0 +1,273 @@
+// Builtins for reading the clock and for converting timestamps to and from strings.
+//
+// Both clock builtins read the clock of the event loop (see `types::futures::Clock`), so that
+// they agree with the timers, and so that embedders can fake the passage of time. On a
+// deterministic runtime the clock is virtual: it starts at zero, and its wall clock time starts at
+// the Unix epoch.
+//
+// Timestamps are ints counting the milliseconds since the Unix epoch (1970-01-01T00:00:00Z), and
+// are always converted in UTC. A format is a string in which the following directives stand for
+// components of the timestamp, all other chars stand for themselves:
+//
+// - `%Y`: the year, four digits (`0000` to `9999`)
+// - `%m`: the month, two digits (`01` to `12`)
+// - `%d`: the day of the month, two digits (`01` to `31`)
+// - `%H`: the hour, two digits (`00` to `23`)
+// - `%M`: the minute, two digits (`00` to `59`)
+// - `%S`: the second, two digits (`00` to `59`, there are no leap seconds)
+// - `%3f`: the millisecond, three digits (`000` to `999`)
+// - `%z`: the offset from UTC, always `+0000` when formatting. Parsing also accepts `-0000` and
+//   `Z`, other offsets are not supported yet.
+// - `%%`: a literal `%`
+//
+// Malformed formats throw an error of kind `"format"`. Strings that do not match the format throw
+// an error of kind `"parse"`, and components that are out of range (e.g. February 30th) an error
+// of kind `"range"`. Arguments of the wrong type throw an error of kind `"type"`.
+
+use crate::runtime::Runtime;
+use crate::types::futures::Clock;
+use crate::types::rope::Rope;
+use crate::value::Value;
+
+const MILLIS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+
+/// Register the timestamp conversion builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("format_timestamp", format_timestamp);
+    runtime.register("parse_timestamp", parse_timestamp);
+}
+
+/// Register the clock builtins under their names, reading the clock of the runtime's event loop.
+pub fn register_clock(runtime: &mut Runtime) {
+    let clock = runtime.event_loop().clock();
+    runtime.register("monotonic_millis", move |args| monotonic_millis(&*clock, args));
+    let clock = runtime.event_loop().clock();
+    runtime.register("wall_clock_millis", move |args| wall_clock_millis(&*clock, args));
+}
+
+/// `monotonic_millis()`: The number of milliseconds since an arbitrary but fixed point in time.
+/// Never decreases, so the difference between two calls is the duration that passed between them.
+pub fn monotonic_millis(clock: &dyn Clock, _args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Int(clock.now() as i64))
+}
+
+/// `wall_clock_millis()`: The current time as a timestamp. May jump around when the system time is
+/// adjusted, so use `monotonic_millis` to measure durations.
+pub fn wall_clock_millis(clock: &dyn Clock, _args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Int(clock.unix_millis()))
+}
+
+/// `format_timestamp(millis, format)`: Render a timestamp according to the format. Throws an error
+/// of kind `"range"` if the format contains `%Y` and the year is not from 0 to 9999.
+pub fn format_timestamp(args: &[Value]) -> Result<Value, Value> {
+    let millis = match args.first() {
+        Some(Value::Int(n)) => *n,
+        other => return Err(type_error("an int", other, "format_timestamp")),
+    };
+    let format = directives(args, "format_timestamp")?;
+
+    let (year, month, day) = civil_from_days(millis.div_euclid(MILLIS_PER_DAY));
+    let of_day = millis.rem_euclid(MILLIS_PER_DAY);
+    let mut out = String::new();
+    for directive in &format {
+        match directive {
+            Directive::Literal(c) => out.push(*c),
+            Directive::Year if (0..=9999).contains(&year) => out += &format!("{:04}", year),
+            Directive::Year => {
+                return Err(Value::error("range", &format!(
+                    "format_timestamp can only format the years from 0 to 9999, got {}", year,
+                )));
+            }
+            Directive::Month => out += &format!("{:02}", month),
+            Directive::Day => out += &format!("{:02}", day),
+            Directive::Hour => out += &format!("{:02}", of_day / 3_600_000),
+            Directive::Minute => out += &format!("{:02}", of_day / 60_000 % 60),
+            Directive::Second => out += &format!("{:02}", of_day / 1000 % 60),
+            Directive::Millis => out += &format!("{:03}", of_day % 1000),
+            Directive::Offset => out += "+0000",
+        }
+    }
+    Ok(Value::String(Rope::from_str(&out)))
+}
+
+/// `parse_timestamp(s, format)`: The timestamp described by the string, which must match the
+/// format exactly. Components that the format does not mention default to the epoch, i.e. year
+/// 1970, January, the first day of the month, and zero for the time of day.
+pub fn parse_timestamp(args: &[Value]) -> Result<Value, Value> {
+    let s = match args.first() {
+        Some(Value::String(s)) => s.to_string(),
+        other => return Err(type_error("a string", other, "parse_timestamp")),
+    };
+    let format = directives(args, "parse_timestamp")?;
+
+    let (mut year, mut month, mut day) = (1970, 1, 1);
+    let (mut hour, mut minute, mut second, mut millis) = (0, 0, 0, 0);
+    let mut rest = s.as_str();
+    for directive in &format {
+        match directive {
+            Directive::Literal(c) => match rest.strip_prefix(*c) {
+                Some(after) => rest = after,
+                None => return Err(mismatch(&s, rest, &format!("{:?}", c))),
+            },
+            Directive::Year => year = digits(&s, &mut rest, 4)?,
+            Directive::Month => month = digits(&s, &mut rest, 2)?,
+            Directive::Day => day = digits(&s, &mut rest, 2)?,
+            Directive::Hour => hour = digits(&s, &mut rest, 2)?,
+            Directive::Minute => minute = digits(&s, &mut rest, 2)?,
+            Directive::Second => second = digits(&s, &mut rest, 2)?,
+            Directive::Millis => millis = digits(&s, &mut rest, 3)?,
+            Directive::Offset => rest = offset(&s, rest)?,
+        }
+    }
+    if !rest.is_empty() {
+        return Err(mismatch(&s, rest, "the end of the string"));
+    }
+
+    check_range("month", month, 1, 12)?;
+    check_range("day", day, 1, days_in_month(year, month))?;
+    check_range("hour", hour, 0, 23)?;
+    check_range("minute", minute, 0, 59)?;
+    check_range("second", second, 0, 59)?;
+    Ok(Value::Int(
+        days_from_civil(year, month, day) * MILLIS_PER_DAY
+            + ((hour * 60 + minute) * 60 + second) * 1000
+            + millis,
+    ))
+}
+
+// The parts of a format.
+enum Directive {
+    Literal(char),
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    Millis,
+    Offset,
+}
+
+// The format, the second argument of both conversion builtins.
+fn directives(args: &[Value], name: &str) -> Result<Vec<Directive>, Value> {
+    let format = match args.get(1) {
+        Some(Value::String(format)) => format.to_string(),
+        other => return Err(type_error("a string as the format", other, name)),
+    };
+    let mut directives = vec![];
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            directives.push(Directive::Literal(c));
+            continue;
+        }
+        let at = format.len() - chars.as_str().len() - 1;
+        directives.push(match chars.next() {
+            Some('Y') => Directive::Year,
+            Some('m') => Directive::Month,
+            Some('d') => Directive::Day,
+            Some('H') => Directive::Hour,
+            Some('M') => Directive::Minute,
+            Some('S') => Directive::Second,
+            Some('3') if chars.next() == Some('f') => Directive::Millis,
+            Some('z') => Directive::Offset,
+            Some('%') => Directive::Literal('%'),
+            _ => {
+                return Err(Value::error("format", &format!(
+                    "{} does not support the directive at char {} of {:?}",
+                    name, format[..at].chars().count(), format,
+                )));
+            }
+        });
+    }
+    Ok(directives)
+}
+
+// Parse exactly `n` ascii digits from the start of `rest`.
+fn digits(s: &str, rest: &mut &str, n: usize) -> Result<i64, Value> {
+    match rest.get(..n) {
+        Some(digits) if digits.bytes().all(|b| b.is_ascii_digit()) => {
+            *rest = &rest[n..];
+            Ok(digits.parse().unwrap())
+        }
+        _ => Err(mismatch(s, rest, &format!("{} digits", n))),
+    }
+}
+
+// Parse a UTC offset from the start of `rest`, and return what follows it.
+fn offset<'a>(s: &str, rest: &'a str) -> Result<&'a str, Value> {
+    if let Some(after) = rest.strip_prefix('Z') {
+        return Ok(after);
+    }
+    match rest.get(..5) {
+        Some("+0000") | Some("-0000") => Ok(&rest[5..]),
+        Some(offset)
+            if offset.starts_with(['+', '-'])
+                && offset[1..].bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            Err(Value::error("range", &format!(
+                "parse_timestamp only supports UTC, got the offset {}", offset,
+            )))
+        }
+        _ => Err(mismatch(s, rest, "a UTC offset")),
+    }
+}
+
+fn mismatch(s: &str, rest: &str, expected: &str) -> Value {
+    Value::error("parse", &format!(
+        "parse_timestamp expected {} at char {} of {:?}",
+        expected, s.chars().count() - rest.chars().count(), s,
+    ))
+}
+
+fn check_range(component: &str, n: i64, min: i64, max: i64) -> Result<(), Value> {
+    if n < min || n > max {
+        Err(Value::error("range", &format!(
+            "parse_timestamp expects the {} to be from {} to {}, got {}", component, min, max, n,
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// The number of days between the epoch and the given date of the proleptic Gregorian calendar,
+// after Howard Hinnant's `days_from_civil`. Counting years from March on puts the leap day at
+// the end of the year.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// The inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn type_error(expected: &str, got: Option<&Value>, name: &str) -> Value {
+    Value::error("type", &format!(
+        "{} expects {}, got {}", name, expected, got.map_or("nil", Value::type_name),
+    ))
+}
@@ -0,0 +1,95 @@
+// Builtins for inspecting the type of a value.
+//
+// `typeof` returns the name of the type of a value, one of `"nil"`, `"bool"`, `"int"`, `"float"`,
+// `"char"`, `"string"`, `"bytes"`, `"array"`, `"set"`, `"map"`, `"function"`, `"future"`,
+// `"channel"`, `"stream"`, `"iterator"` and `"userdata"`. These names are stable, programs may
+// rely on them.
+//
+// There is a predicate for every type. They are generated from a single table, together with a
+// match over all variants of `Value`, so adding a variant without a predicate does not compile.
+// Missing arguments are `nil`.
+
+use crate::runtime::Runtime;
+use crate::types::rope::Rope;
+use crate::value::Value;
+
+/// Register the type inspection builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    runtime.register("typeof", type_of);
+    runtime.register("is_nil", is_nil);
+    runtime.register("is_bool", is_bool);
+    runtime.register("is_int", is_int);
+    runtime.register("is_float", is_float);
+    runtime.register("is_number", is_number);
+    runtime.register("is_char", is_char);
+    runtime.register("is_string", is_string);
+    runtime.register("is_bytes", is_bytes);
+    runtime.register("is_array", is_array);
+    runtime.register("is_set", is_set);
+    runtime.register("is_map", is_map);
+    runtime.register("is_fun", is_fun);
+    runtime.register("is_callable", is_callable);
+    runtime.register("is_future", is_future);
+    runtime.register("is_channel", is_channel);
+    runtime.register("is_stream", is_stream);
+    runtime.register("is_iterator", is_iterator);
+    runtime.register("is_userdata", is_userdata);
+}
+
+/// `typeof(v)`: The name of the type of `v`.
+pub fn type_of(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::String(Rope::from_str(arg(args).type_name())))
+}
+
+macro_rules! predicates {
+    ($($name:ident $variant:ident $type_name:literal,)*) => {
+        $(
+            #[doc = concat!(
+                "`", stringify!($name), "(v)`: Whether `v` is of type `", $type_name, "`.",
+            )]
+            pub fn $name(args: &[Value]) -> Result<Value, Value> {
+                Ok(Value::Bool(predicate(&arg(args)) == stringify!($name)))
+            }
+        )*
+
+        // The name of the predicate that holds for a value.
+        fn predicate(v: &Value) -> &'static str {
+            match v {
+                $(Value::$variant { .. } => stringify!($name),)*
+            }
+        }
+    };
+}
+
+predicates! {
+    is_nil Nil "nil",
+    is_bool Bool "bool",
+    is_int Int "int",
+    is_float Float "float",
+    is_char Char "char",
+    is_string String "string",
+    is_bytes Bytes "bytes",
+    is_array Array "array",
+    is_set Set "set",
+    is_map Map "map",
+    is_fun Fun "function",
+    is_future Future "future",
+    is_channel Channel "channel",
+    is_stream Stream "stream",
+    is_iterator Iter "iterator",
+    is_userdata Userdata "userdata",
+}
+
+/// `is_number(v)`: Whether `v` is an int or a float.
+pub fn is_number(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Bool(matches!(arg(args), Value::Int(_) | Value::Float(_))))
+}
+
+/// `is_callable(v)`: Whether `v` can be called. Currently only functions can.
+pub fn is_callable(args: &[Value]) -> Result<Value, Value> {
+    Ok(Value::Bool(matches!(arg(args), Value::Fun(_))))
+}
+
+fn arg(args: &[Value]) -> Value {
+    args.first().cloned().unwrap_or(Value::Nil)
+}
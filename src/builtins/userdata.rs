@@ -0,0 +1,61 @@
+// Builtins for working with userdata, see `types::userdata`.
+//
+// Arguments that should be userdata but are not throw an error of kind `"type"`.
+
+use crate::runtime::{HostCtx, Runtime};
+use crate::types::userdata::Userdata;
+use crate::value::Value;
+
+/// Register the userdata builtins under their names.
+pub fn register(runtime: &mut Runtime) {
+    let ctx = runtime.host_ctx();
+    runtime.register("ud_call", move |args| ud_call(&mut ctx.clone(), args));
+    runtime.register("ud_methods", ud_methods);
+    runtime.register("ud_close", ud_close);
+    runtime.register("ud_is_closed", ud_is_closed);
+}
+
+/// `ud_call(obj, name, args...)`: Call the method `name` of the userdata `obj` with the remaining
+/// arguments, see `PanUserdata::call_method`. Throws an error of kind `"method"` if there is no
+/// such method, of kind `"concurrent-modification"` if a method of `obj` is already running, and
+/// of kind `"closed"` if `obj` has been closed.
+pub fn ud_call(ctx: &mut HostCtx, args: &[Value]) -> Result<Value, Value> {
+    let obj = userdata(args, 0, "ud_call")?;
+    let name = match args.get(1) {
+        Some(Value::String(name)) => name.to_string(),
+        other => return Err(Value::error("type", &format!(
+            "ud_call expects a method name, got {}", other.map_or("nil", Value::type_name),
+        ))),
+    };
+    obj.call_method(&name, args.get(2..).unwrap_or(&[]), ctx)
+}
+
+/// `ud_methods(obj)`: The names of the methods of the userdata `obj`, as an array of strings.
+pub fn ud_methods(args: &[Value]) -> Result<Value, Value> {
+    let obj = userdata(args, 0, "ud_methods")?;
+    Ok(Value::array(obj.methods().into_iter().map(Value::from).collect()))
+}
+
+/// `ud_close(obj)`: Release the resources of the userdata `obj` now (see
+/// `PanUserdata::finalize`), after which its methods throw errors of kind `"closed"`. Closing a
+/// closed userdata does nothing. Throws an error of kind `"concurrent-modification"` if a method
+/// of `obj` is running.
+pub fn ud_close(args: &[Value]) -> Result<Value, Value> {
+    userdata(args, 0, "ud_close")?.close()?;
+    Ok(Value::Nil)
+}
+
+/// `ud_is_closed(obj)`: Whether the userdata `obj` has been closed with `ud_close`.
+pub fn ud_is_closed(args: &[Value]) -> Result<Value, Value> {
+    let obj = userdata(args, 0, "ud_is_closed")?;
+    Ok(Value::Bool(obj.is_closed()))
+}
+
+fn userdata<'a>(args: &'a [Value], i: usize, name: &str) -> Result<&'a Userdata, Value> {
+    match args.get(i) {
+        Some(Value::Userdata(obj)) => Ok(obj),
+        other => Err(Value::error("type", &format!(
+            "{} expects a userdata, got {}", name, other.map_or("nil", Value::type_name),
+        ))),
+    }
+}
@@ -0,0 +1,235 @@
+// Which builtins that reach outside of the runtime pan code may use.
+//
+// Some builtins give pan code access to the world outside of the runtime: the file system, the
+// arguments and environment variables of the process, the entropy source of the operating system,
+// the clock, the standard streams, and the garbage collector. Each of these is guarded by a
+// capability. A runtime only registers the builtins of the capabilities it was created with (see
+// `Runtime::with_capabilities`), the others are not bound at all. `Runtime::resolve` tells apart
+// names that are not bound because a capability is missing from names that are not bound at all,
+// so that a missing capability can be reported as such.
+//
+// Capabilities only guard the builtins. Everything the embedder hands to pan code directly (values
+// defined with `Runtime::define`, functions registered with `Runtime::register` and friends, and
+// the file system builtins registered with `Runtime::allow_fs`) is available regardless, it is up
+// to the embedder to not hand out more than it wants to.
+//
+// All other builtins only compute with their arguments (or with state of the runtime, like the
+// generator behind the random builtins, and the timers of the event loop), and are always
+// registered.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A kind of access to the world outside of the runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Capability {
+    /// Reading and writing files, see `builtins::fs`.
+    Fs,
+    /// Reading the program arguments and environment variables, see `builtins::env`.
+    Env,
+    /// Reading the entropy source of the operating system, see `builtins::entropy`.
+    Entropy,
+    /// Reading the clock (`monotonic_millis` and `wall_clock_millis`).
+    Clock,
+    /// Writing to stdout (`print` and `println`).
+    Stdout,
+    /// Writing to stderr (`eprint` and `eprintln`).
+    Stderr,
+    /// Reading from stdin (`read_line` and `read_all_stdin`).
+    Stdin,
+    /// Controlling the garbage collector, see `builtins::gc`.
+    Gc,
+}
+
+impl Capability {
+    /// All capabilities.
+    pub const ALL: [Capability; 8] = [
+        Capability::Fs,
+        Capability::Env,
+        Capability::Entropy,
+        Capability::Clock,
+        Capability::Stdout,
+        Capability::Stderr,
+        Capability::Stdin,
+        Capability::Gc,
+    ];
+
+    /// The name of the capability, as used in diagnostics.
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::Fs => "fs",
+            Capability::Env => "env",
+            Capability::Entropy => "entropy",
+            Capability::Clock => "clock",
+            Capability::Stdout => "stdout",
+            Capability::Stderr => "stderr",
+            Capability::Stdin => "stdin",
+            Capability::Gc => "gc",
+        }
+    }
+
+    /// The names of the builtins guarded by the capability. The entropy builtins only exist
+    /// with the `secure-random` feature.
+    pub fn builtins(self) -> &'static [&'static str] {
+        match self {
+            Capability::Fs => &[
+                "read_file",
+                "read_file_text",
+                "write_file",
+                "append_file",
+                "file_exists",
+                "remove_file",
+                "list_dir",
+                "mkdir_all",
+            ],
+            Capability::Env => &["args", "env", "env_all"],
+            Capability::Entropy => &["random_bytes", "uuid_v4"],
+            Capability::Clock => &["monotonic_millis", "wall_clock_millis"],
+            Capability::Stdout => &["print", "println"],
+            Capability::Stderr => &["eprint", "eprintln"],
+            Capability::Stdin => &["read_line", "read_all_stdin"],
+            Capability::Gc => &["gc_collect", "gc_stats"],
+        }
+    }
+
+    /// The capability that guards the builtin of the given name, if any.
+    pub fn required_by(builtin: &str) -> Option<Capability> {
+        Capability::ALL.iter().cloned().find(|cap| cap.builtins().contains(&builtin))
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A set of capabilities to create a runtime with, built from one of the profiles by adding and
+/// removing capabilities.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    // `None` if denied, otherwise the directory to confine file system access to, if any.
+    fs: Option<Option<PathBuf>>,
+    env: bool,
+    entropy: bool,
+    clock: bool,
+    stdout: bool,
+    stderr: bool,
+    stdin: bool,
+    gc: bool,
+}
+
+impl Capabilities {
+    /// No capabilities at all: pan code can only compute.
+    pub const PURE: Capabilities = Capabilities {
+        fs: None,
+        env: false,
+        entropy: false,
+        clock: false,
+        stdout: false,
+        stderr: false,
+        stdin: false,
+        gc: false,
+    };
+
+    /// Everything except for the file system. This is what runtimes get by default.
+    pub const SCRIPTING: Capabilities = Capabilities {
+        fs: None,
+        env: true,
+        entropy: true,
+        clock: true,
+        stdout: true,
+        stderr: true,
+        stdin: true,
+        gc: true,
+    };
+
+    /// Everything, including unconfined access to the file system.
+    pub const FULL: Capabilities = Capabilities {
+        fs: Some(None),
+        ..Capabilities::SCRIPTING
+    };
+
+    /// Allow access to the file system. If `root` is given, only paths inside that directory can
+    /// be accessed, see `Runtime::allow_fs`.
+    pub fn allow_fs(mut self, root: Option<&Path>) -> Capabilities {
+        self.fs = Some(root.map(Path::to_path_buf));
+        self
+    }
+
+    pub fn allow_env(mut self) -> Capabilities {
+        self.env = true;
+        self
+    }
+
+    pub fn allow_entropy(mut self) -> Capabilities {
+        self.entropy = true;
+        self
+    }
+
+    pub fn allow_clock(mut self) -> Capabilities {
+        self.clock = true;
+        self
+    }
+
+    pub fn allow_stdout(mut self) -> Capabilities {
+        self.stdout = true;
+        self
+    }
+
+    pub fn allow_stderr(mut self) -> Capabilities {
+        self.stderr = true;
+        self
+    }
+
+    pub fn allow_stdin(mut self) -> Capabilities {
+        self.stdin = true;
+        self
+    }
+
+    pub fn allow_gc(mut self) -> Capabilities {
+        self.gc = true;
+        self
+    }
+
+    /// Remove a capability.
+    pub fn deny(mut self, capability: Capability) -> Capabilities {
+        match capability {
+            Capability::Fs => self.fs = None,
+            Capability::Env => self.env = false,
+            Capability::Entropy => self.entropy = false,
+            Capability::Clock => self.clock = false,
+            Capability::Stdout => self.stdout = false,
+            Capability::Stderr => self.stderr = false,
+            Capability::Stdin => self.stdin = false,
+            Capability::Gc => self.gc = false,
+        }
+        self
+    }
+
+    /// Returns whether the capability is granted.
+    pub fn allows(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Fs => self.fs.is_some(),
+            Capability::Env => self.env,
+            Capability::Entropy => self.entropy,
+            Capability::Clock => self.clock,
+            Capability::Stdout => self.stdout,
+            Capability::Stderr => self.stderr,
+            Capability::Stdin => self.stdin,
+            Capability::Gc => self.gc,
+        }
+    }
+
+    /// The directory that file system access is confined to, `None` if it is not confined (or
+    /// denied).
+    pub fn fs_root(&self) -> Option<&Path> {
+        self.fs.as_ref().and_then(|root| root.as_deref())
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Capabilities {
+        Capabilities::SCRIPTING
+    }
+}
@@ -0,0 +1,370 @@
+// The input and output of a runtime: its standard streams and its file system.
+//
+// The io builtins (see `builtins::io`) and the file system builtins (see `builtins::fs`) never
+// touch the streams and files of the process directly. They go through the writers, the reader
+// and the `FileSystem` of their runtime, which default to those of the process and can be
+// replaced as a whole with `Runtime::set_host_io` (or one by one with `Runtime::set_stdout` and
+// friends). `InMemoryHostIo` provides in-memory replacements for tests and for environments
+// without a file system.
+//
+// Paths are passed to the file system as they are after the builtins resolved them against the
+// root directory (if any), so a file system only ever sees paths that pan code may access.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufReader, Cursor, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+
+/// The standard streams and the file system of a runtime, see `Runtime::set_host_io`.
+pub struct HostIo {
+    pub stdout: Box<dyn Write>,
+    pub stderr: Box<dyn Write>,
+    pub stdin: Box<dyn Read>,
+    pub fs: Rc<dyn FileSystem>,
+}
+
+impl HostIo {
+    /// The standard streams and the file system of the process.
+    pub fn real() -> HostIo {
+        HostIo {
+            stdout: Box::new(io::stdout()),
+            stderr: Box::new(io::stderr()),
+            stdin: Box::new(io::stdin()),
+            fs: Rc::new(RealFs),
+        }
+    }
+}
+
+impl Default for HostIo {
+    fn default() -> HostIo {
+        HostIo::real()
+    }
+}
+
+/// What the file system builtins need from a file system.
+pub trait FileSystem {
+    /// Open the file for reading.
+    fn open_read(&self, path: &Path) -> io::Result<Box<dyn Read>>;
+
+    /// Open the file for writing, creating it if necessary. Unless `append` is set, the file is
+    /// truncated.
+    fn open_write(&self, path: &Path, append: bool) -> io::Result<Box<dyn Write>>;
+
+    /// The metadata of the entry at the path itself, without following a symbolic link at the
+    /// end of the path.
+    fn metadata(&self, path: &Path) -> io::Result<Metadata>;
+
+    /// The names of the entries of the directory, in no particular order.
+    fn list(&self, path: &Path) -> io::Result<Vec<OsString>>;
+
+    /// Remove the file.
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    /// Create the directory and all of its missing ancestors.
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    /// The absolute path of the existing entry, with all symbolic links and `.` and `..`
+    /// components resolved.
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+}
+
+/// What `FileSystem::metadata` reports about an entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    /// Whether the entry is a directory.
+    pub is_dir: bool,
+    /// The size of a file in bytes.
+    pub len: u64,
+}
+
+/// The file system of the process.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealFs;
+
+impl FileSystem for RealFs {
+    fn open_read(&self, path: &Path) -> io::Result<Box<dyn Read>> {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+
+    fn open_write(&self, path: &Path, append: bool) -> io::Result<Box<dyn Write>> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)?;
+        Ok(Box::new(file))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        let metadata = fs::symlink_metadata(path)?;
+        Ok(Metadata { is_dir: metadata.is_dir(), len: metadata.len() })
+    }
+
+    fn list(&self, path: &Path) -> io::Result<Vec<OsString>> {
+        fs::read_dir(path)?.map(|entry| entry.map(|entry| entry.file_name())).collect()
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::canonicalize(path)
+    }
+}
+
+/// A file system that only exists in memory. It has no symbolic links, and its working directory
+/// is the root `/`. Clones share the same files.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryFs {
+    // All entries but the root, by their normalized absolute path.
+    entries: Rc<RefCell<BTreeMap<PathBuf, Entry>>>,
+}
+
+#[derive(Debug, Clone)]
+enum Entry {
+    File(Vec<u8>),
+    Dir,
+}
+
+impl InMemoryFs {
+    pub fn new() -> InMemoryFs {
+        InMemoryFs::default()
+    }
+
+    /// Create or replace a file, and all of its missing ancestors.
+    pub fn insert_file(&self, path: impl AsRef<Path>, contents: impl Into<Vec<u8>>) {
+        let path = normalize(path.as_ref());
+        if let Some(parent) = path.parent() {
+            let _ = self.create_dir_all(parent);
+        }
+        self.entries.borrow_mut().insert(path, Entry::File(contents.into()));
+    }
+
+    /// The contents of a file, `None` if there is no file at the path.
+    pub fn file(&self, path: impl AsRef<Path>) -> Option<Vec<u8>> {
+        match self.entries.borrow().get(&normalize(path.as_ref())) {
+            Some(Entry::File(contents)) => Some(contents.clone()),
+            _ => None,
+        }
+    }
+
+    /// The paths of all files, in ascending order.
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.entries
+            .borrow()
+            .iter()
+            .filter(|(_, entry)| matches!(entry, Entry::File(_)))
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    fn entry(&self, path: &Path) -> io::Result<Entry> {
+        if path == Path::new("/") {
+            return Ok(Entry::Dir);
+        }
+        self.entries.borrow().get(path).cloned().ok_or_else(not_found)
+    }
+
+    // Fail unless the parent of the path is a directory.
+    fn parent_dir(&self, path: &Path) -> io::Result<()> {
+        match self.entry(path.parent().ok_or_else(not_found)?)? {
+            Entry::Dir => Ok(()),
+            Entry::File(_) => Err(not_a_directory()),
+        }
+    }
+}
+
+impl FileSystem for InMemoryFs {
+    fn open_read(&self, path: &Path) -> io::Result<Box<dyn Read>> {
+        match self.entry(&normalize(path))? {
+            Entry::File(contents) => Ok(Box::new(Cursor::new(contents))),
+            Entry::Dir => Err(is_a_directory()),
+        }
+    }
+
+    fn open_write(&self, path: &Path, append: bool) -> io::Result<Box<dyn Write>> {
+        let path = normalize(path);
+        self.parent_dir(&path)?;
+        let mut entries = self.entries.borrow_mut();
+        match entries.get_mut(&path) {
+            Some(Entry::Dir) => return Err(is_a_directory()),
+            Some(Entry::File(contents)) if !append => contents.clear(),
+            Some(Entry::File(_)) => {}
+            None => {
+                entries.insert(path.clone(), Entry::File(vec![]));
+            }
+        }
+        Ok(Box::new(InMemoryFile { fs: self.clone(), path }))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        Ok(match self.entry(&normalize(path))? {
+            Entry::File(contents) => Metadata { is_dir: false, len: contents.len() as u64 },
+            Entry::Dir => Metadata { is_dir: true, len: 0 },
+        })
+    }
+
+    fn list(&self, path: &Path) -> io::Result<Vec<OsString>> {
+        let path = normalize(path);
+        if let Entry::File(_) = self.entry(&path)? {
+            return Err(not_a_directory());
+        }
+        let entries = self.entries.borrow();
+        Ok(entries
+            .keys()
+            .filter(|entry| entry.parent() == Some(&*path))
+            .filter_map(|entry| entry.file_name().map(|name| name.to_owned()))
+            .collect())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let path = normalize(path);
+        match self.entry(&path)? {
+            Entry::File(_) => {
+                self.entries.borrow_mut().remove(&path);
+                Ok(())
+            }
+            Entry::Dir => Err(is_a_directory()),
+        }
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        let path = normalize(path);
+        for ancestor in path.ancestors().collect::<Vec<_>>().into_iter().rev().skip(1) {
+            let mut entries = self.entries.borrow_mut();
+            match entries.get(ancestor) {
+                Some(Entry::Dir) => {}
+                Some(Entry::File(_)) => return Err(not_a_directory()),
+                None => {
+                    entries.insert(ancestor.to_owned(), Entry::Dir);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        let path = normalize(path);
+        self.entry(&path)?;
+        Ok(path)
+    }
+}
+
+// Writes to a file of an `InMemoryFs`.
+struct InMemoryFile {
+    fs: InMemoryFs,
+    path: PathBuf,
+}
+
+impl Write for InMemoryFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.fs.entries.borrow_mut().get_mut(&self.path) {
+            Some(Entry::File(contents)) => {
+                contents.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            _ => Err(not_found()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// The absolute path, with `.` and `..` components resolved lexically against the root.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+        }
+    }
+    normalized
+}
+
+fn not_found() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "no such file or directory")
+}
+
+fn not_a_directory() -> io::Error {
+    io::Error::other("not a directory")
+}
+
+fn is_a_directory() -> io::Error {
+    io::Error::other("is a directory")
+}
+
+/// A writer that appends to a buffer shared by its clones.
+#[derive(Debug, Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn new() -> SharedBuffer {
+        SharedBuffer::default()
+    }
+
+    /// Everything written so far.
+    pub fn contents(&self) -> Vec<u8> {
+        self.0.borrow().clone()
+    }
+
+    /// Everything written so far, decoded as UTF-8 with invalid sequences replaced.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Standard streams and a file system that only exist in memory: the output is captured in
+/// `SharedBuffer`s, the input is given upfront, and the files live in an `InMemoryFs`. The
+/// embedder keeps this and hands `host_io` to the runtime, and can then inspect the output and
+/// the files.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryHostIo {
+    pub stdout: SharedBuffer,
+    pub stderr: SharedBuffer,
+    pub fs: InMemoryFs,
+    stdin: Vec<u8>,
+}
+
+impl InMemoryHostIo {
+    /// Empty output, no files, and the given input.
+    pub fn new(stdin: impl Into<Vec<u8>>) -> InMemoryHostIo {
+        InMemoryHostIo { stdin: stdin.into(), ..InMemoryHostIo::default() }
+    }
+
+    /// The streams and file system to hand to a runtime. The output goes to the buffers of this,
+    /// and the files are those of this, but every call reads the input from the start.
+    pub fn host_io(&self) -> HostIo {
+        HostIo {
+            stdout: Box::new(self.stdout.clone()),
+            stderr: Box::new(self.stderr.clone()),
+            stdin: Box::new(BufReader::new(Cursor::new(self.stdin.clone()))),
+            fs: Rc::new(self.fs.clone()),
+        }
+    }
+}
@@ -1,10 +1,17 @@
 // The intermediate representation of this pan implementation. Pan functions are compiled into ir
 // functions, which are then interpreted.
 
+// Nothing constructs ir code yet.
+#![allow(dead_code)]
+
 use std::collections::{
     BTreeSet,
     BTreeMap,
 };
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter;
 use std::rc::Rc;
 
 use gc::{Gc, GcCell};
@@ -14,23 +21,35 @@ use ordered_float::OrderedFloat;
 use crate::types::{
     rope::Rope,
     bytes::Bytes,
+    collection::Collection,
+    exit,
+    heap::{self, Charge},
+    interrupt,
+    owner::{self, RuntimeId},
+    persistent::PersistentMap,
+    throw_observer,
+    watch::{self, ActiveScope, WatchId, Watchpoint},
 };
-use crate::value::{Value, Fun};
+#[cfg(feature = "nan-boxing")]
+use crate::types::packed::PackedValue;
+use crate::value::{Copies, Value, Fun};
 
 // What identifiers do in pan, DeBruijnPairs do in the ir.
 //
 // The `up` field addresses an environment: 0 is the current environment, 1 the parent environment,
 // 2 the parent's parent environment, and so on. Within the correct environment, `index` addresses
-// the binding.
+// the binding. Both are stored in 32 bits, to keep the instructions that contain addresses small.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct DeBruijnPair {
-    up: usize,
-    index: usize,
+    up: u32,
+    index: u32,
 }
 
 impl DeBruijnPair {
+    /// Panics if `up` or `index` do not fit in 32 bits.
     pub fn new(up: usize, index: usize) -> DeBruijnPair {
-        DeBruijnPair { up, index }
+        let narrow = |n: usize| u32::try_from(n).expect("ir addresses fit in 32 bits");
+        DeBruijnPair { up: narrow(up), index: narrow(index) }
     }
 }
 
@@ -40,11 +59,21 @@ impl DeBruijnPair {
 // correctly translated to DeBruijnPairs and no disallowed mutations occur. We don't go so far as
 // to use unsafe access though, but in theory we could.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Trace, Finalize)]
-struct Environment {
+pub(crate) struct Environment {
     // The bindings local to this environment.
     bindings: Vec<Value>,
     // (Mutable) access to the parent binding, which is `None` for the top-level environment.
     parent: Option<Gc<GcCell<Environment>>>,
+    // The size of the bindings, charged to the memory budget.
+    #[unsafe_ignore_trace]
+    charge: Charge,
+    // The runtime that created the environment, see `types::owner`.
+    #[unsafe_ignore_trace]
+    owner: RuntimeId,
+    // The stamp of the closures of this environment, see `types::owner`. Zero for the
+    // environments of executions, which no closure captures.
+    #[unsafe_ignore_trace]
+    stamp: u64,
 }
 
 impl Environment {
@@ -52,7 +81,7 @@ impl Environment {
     // (which only happens if compilation is buggy).
     fn get(&self, mut addr: DeBruijnPair) -> Value {
         if addr.up == 0 {
-            self.bindings[addr.index].clone()
+            self.bindings[addr.index as usize].clone()
         } else {
             addr.up -= 1;
             self.parent.as_ref().unwrap().borrow().get(addr)
@@ -63,7 +92,7 @@ impl Environment {
     // compilation is buggy).
     fn set(&mut self, mut addr: DeBruijnPair, val: Value) {
         if addr.up == 0 {
-            self.bindings[addr.index] = val;
+            self.bindings[addr.index as usize] = val;
         } else {
             addr.up -= 1;
             self.parent.as_ref().unwrap().borrow_mut().set(addr, val);
@@ -71,13 +100,94 @@ impl Environment {
     }
 
     fn child(parent: Gc<GcCell<Environment>>, env_size: usize) -> Gc<GcCell<Environment>> {
+        heap::environment_created();
         let mut bindings = Vec::with_capacity(env_size);
         bindings.resize(env_size, Value::nil());
         Gc::new(GcCell::new(Environment {
             bindings,
             parent: Some(parent),
+            charge: Charge::new(env_size as u64 * heap::slot_size()),
+            owner: owner::current(),
+            stamp: owner::stamp(),
         }))
     }
+
+    // A copy of the environment and its ancestors, whose bindings are copied with `copies`, see
+    // `Value::snapshot_copy`.
+    fn copy_with(env: &Gc<GcCell<Environment>>, copies: &mut Copies) -> Gc<GcCell<Environment>> {
+        let address = &**env as *const GcCell<Environment> as usize;
+        if let Some(copy) = copies.environments.get(&address) {
+            return copy.clone();
+        }
+
+        // As with collections, the copy is registered before the bindings are copied, so that
+        // closures that are bound in their own environment end at it.
+        let parent = env.borrow().parent.as_ref().map(|p| Environment::copy_with(p, copies));
+        let size = env.borrow().bindings.len();
+        let copy = Gc::new(GcCell::new(Environment {
+            bindings: vec![Value::nil(); size],
+            parent,
+            charge: Charge::new(size as u64 * heap::slot_size()),
+            owner: owner::current(),
+            stamp: owner::stamp(),
+        }));
+        copies.environments.insert(address, copy.clone());
+        let bindings = env.borrow().bindings.iter().map(|v| v.deep_copy_with(copies)).collect();
+        copy.borrow_mut().bindings = bindings;
+        copy
+    }
+}
+
+/// A read-only view of an environment and its ancestors, for tooling such as debuggers. The
+/// bindings are addressed like by `DeBruijnPair`s: by how many levels `up` from the viewed
+/// environment they are, and by their index within that environment. Values are returned as
+/// clones, nothing can be mutated through a view. The ir does not record the names of bindings,
+/// so a view can only report their indices.
+#[derive(Clone)]
+pub struct EnvView {
+    env: Gc<GcCell<Environment>>,
+}
+
+impl EnvView {
+    /// The number of environments in the chain, the viewed one included.
+    pub fn depth(&self) -> usize {
+        let mut depth = 1;
+        let mut env = self.env.borrow().parent.clone();
+        while let Some(parent) = env {
+            depth += 1;
+            env = parent.borrow().parent.clone();
+        }
+        depth
+    }
+
+    /// The view of the environment `up` levels up, `None` if the chain is not that long.
+    pub fn ancestor(&self, up: usize) -> Option<EnvView> {
+        let mut env = self.env.clone();
+        for _ in 0..up {
+            let parent = env.borrow().parent.clone()?;
+            env = parent;
+        }
+        Some(EnvView { env })
+    }
+
+    /// Clones of the bindings of the environment `up` levels up, `None` if the chain is not that
+    /// long.
+    pub fn bindings(&self, up: usize) -> Option<Vec<Value>> {
+        self.ancestor(up).map(|view| view.env.borrow().bindings.clone())
+    }
+
+    /// A clone of the binding at the given address, `None` if there is no such binding.
+    pub fn get(&self, addr: DeBruijnPair) -> Option<Value> {
+        let view = self.ancestor(addr.up as usize)?;
+        let binding = view.env.borrow().bindings.get(addr.index as usize).cloned();
+        binding
+    }
+}
+
+impl fmt::Debug for EnvView {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries((0..self.depth()).map(|up| self.bindings(up))).finish()
+    }
 }
 
 // Holds some ir code to be interpreted. Each standalone pan function compiles to an IrFunction.
@@ -85,70 +195,268 @@ impl Environment {
 // call optimizations. Different functions of the same `rec` group begin execution of the ir code
 // at different offsets. The offset at which to start is part of the runtime values.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-struct IrFunction {
+pub(crate) struct IrFunction {
     // The maximum number of arguments the function takes. Any additional arguments are ignored.
     // For multiple pan rec functions, this is the maximum over the number of argument of the pan
     // functions.
-    args: usize,
+    pub(crate) args: usize,
     // The maximum number of temporary values this function needs.
-    storage_size: usize,
+    pub(crate) storage_size: usize,
     // The number of bindings in the environments for this function.
-    env_size: usize,
+    pub(crate) env_size: usize,
     // The ir code.
-    code: Box<[Instruction]>,
+    pub(crate) code: Box<[Instruction]>,
+    // The literals of the code, referred to by index. Keeping them out of the instructions keeps
+    // the instructions small, literals can be arbitrarily large.
+    pub(crate) literals: Box<[IrLiteral]>,
+    // The names of the pan functions whose code begins at the given offsets. Functions that were
+    // not bound to a name (anonymous function literals) have no entry.
+    pub(crate) names: BTreeMap<usize, Box<str>>,
+    // The names of the parameters of the pan functions whose code begins at the given offsets,
+    // for calls with named arguments (see `Value::apply_named`). Functions without an entry only
+    // take positional arguments.
+    pub(crate) params: BTreeMap<usize, Box<[Box<str>]>>,
+}
+
+impl IrFunction {
+    // The offsets of the `Return` instructions that the code beginning at `entry` can reach both
+    // with the `throw` flag set and with it clear, in ascending order. Such a return throws or
+    // returns depending on the path taken to it, which is almost certainly a compilation bug: a
+    // path that set the flag for a throw in tail position merged with one that returns normally,
+    // without a `ClearThrowFlag` in between. Like `Apply`, this assumes that a call may continue
+    // at any catch target of the function.
+    pub(crate) fn ambiguous_returns(&self, entry: usize) -> Vec<usize> {
+        const CLEAR: u8 = 1;
+        const SET: u8 = 2;
+
+        let handlers = self.handlers();
+
+        // For every instruction, the states of the flag it can be reached with.
+        let mut reached = vec![0u8; self.code.len()];
+        let mut pending = vec![(entry, CLEAR)];
+        while let Some((pc, flag)) = pending.pop() {
+            if pc >= self.code.len() || reached[pc] & flag != 0 {
+                continue;
+            }
+            reached[pc] |= flag;
+            match &self.code[pc] {
+                Instruction::Jump(target) => pending.push((*target, flag)),
+                Instruction::CondJump(_, target) => {
+                    pending.push((pc + 1, flag));
+                    pending.push((*target, flag));
+                }
+                Instruction::Apply { .. } | Instruction::ApplyNamed { .. } => {
+                    pending.push((pc + 1, flag));
+                    pending.extend(handlers.iter().map(|&handler| (handler, flag)));
+                }
+                Instruction::ThrowFlag => pending.push((pc + 1, SET)),
+                Instruction::ClearThrowFlag => pending.push((pc + 1, CLEAR)),
+                Instruction::Return(_) | Instruction::Throw(_) => {}
+                _ => pending.push((pc + 1, flag)),
+            }
+        }
+
+        self.code
+            .iter()
+            .enumerate()
+            .filter(|(pc, instruction)| {
+                matches!(instruction, Instruction::Return(_)) && reached[*pc] == CLEAR | SET
+            })
+            .map(|(pc, _)| pc)
+            .collect()
+    }
+
+    // A copy of the function and the functions it creates, in which every `Write` from a storage
+    // slot that is not read again before it is overwritten moves the value.
+    pub(crate) fn with_moves(&self) -> IrFunction {
+        let live = self.live_after();
+        let code = self
+            .code
+            .iter()
+            .enumerate()
+            .map(|(i, instruction)| match instruction {
+                Instruction::Write { src: Addr::Storage(slot), dst, .. }
+                    if !live[i].contains(slot) =>
+                {
+                    Instruction::Write { src: Addr::Storage(*slot), dst: dst.clone(), moved: true }
+                }
+                other => other.clone(),
+            })
+            .collect();
+        let literals = self
+            .literals
+            .iter()
+            .map(|literal| match literal {
+                IrLiteral::Fun(fun, entry) => IrLiteral::Fun(Rc::new(fun.with_moves()), *entry),
+                other => other.clone(),
+            })
+            .collect();
+        IrFunction { code, literals, ..self.clone() }
+    }
+
+    // For every instruction, the storage slots that may be read after it before being
+    // overwritten. This is conservative: a call may continue at any catch target of the function,
+    // and the destinations of calls and of caught values do not count as overwritten.
+    fn live_after(&self) -> Vec<BTreeSet<usize>> {
+        let code = &self.code;
+        let handlers = self.handlers();
+        let successors = |i: usize| -> Vec<usize> {
+            match &code[i] {
+                Instruction::Jump(target) => vec![*target],
+                Instruction::CondJump(_, target) => vec![i + 1, *target],
+                Instruction::Return(_) | Instruction::Throw(_) => vec![],
+                Instruction::Apply { .. } | Instruction::ApplyNamed { .. } => {
+                    iter::once(i + 1).chain(handlers.iter().cloned()).collect()
+                }
+                _ => vec![i + 1],
+            }
+        };
+
+        let mut live_in = vec![BTreeSet::new(); code.len()];
+        let mut live_out = vec![BTreeSet::new(); code.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for i in (0..code.len()).rev() {
+                let out: BTreeSet<usize> = successors(i)
+                    .into_iter()
+                    .filter(|&successor| successor < code.len())
+                    .flat_map(|successor| live_in[successor].iter().cloned())
+                    .collect();
+                let mut live = out.clone();
+                if let Some(slot) = code[i].overwritten() {
+                    live.remove(&slot);
+                }
+                live.extend(code[i].read());
+                if live != live_in[i] {
+                    live_in[i] = live;
+                    changed = true;
+                }
+                live_out[i] = out;
+            }
+        }
+        live_out
+    }
+
+    // The catch targets of the code.
+    fn handlers(&self) -> Vec<usize> {
+        self.code
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::Catch { target, .. } | Instruction::CatchExit { target, .. } => {
+                    Some(*target)
+                }
+                _ => None,
+            })
+            .collect()
+    }
 }
 
 // Instructions deal with values either in the environment or in the IrFunction's storage. This
 // enum can address either.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-enum Addr {
+pub(crate) enum Addr {
     Storage(usize),
     Environment(DeBruijnPair),
 }
 
 // A single instruction of ir code. It can operate on the temporary storage, the pc (offset of the
-// next instruction), the `throw` flag and the `catch` offset (where to continue execution when a
-// called function throws), as well as on the environment of the executing closure. After executing
-// an instruction that does not modify the pc, increment the pc.
+// next instruction), the `throw` flag and the `catch` offset and destination (where to continue
+// execution and where to put the thrown value when a called function throws), as well as on the
+// environment of the executing closure. After executing an instruction that does not modify the
+// pc, increment the pc.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-enum Instruction {
-    // Write the value in `src` to `dst`.
-    Write { src: Addr, dst: Addr },
+pub(crate) enum Instruction {
+    // Write the value in `src` to `dst`. If `moved` is set and `src` is in the storage, the value
+    // is moved rather than cloned, leaving nil behind. Only valid if the storage slot is not read
+    // again before it is written, `IrFunction::with_moves` sets it wherever that is the case.
+    Write { src: Addr, dst: Addr, moved: bool },
     // Apply the value at `fun` to the first `numArgs` values in the storage and write the return
     // value to `dst`. If the function has thrown, set the pc to the `catch` address and write the
-    // return value to `storage[0]`.
+    // thrown value to the `catch` destination.
     Apply { fun: Addr, num_args: usize, dst: Addr},
+    // Like `Apply`, but the last of the `numArgs` values is a map from parameter names to the
+    // named arguments of the call, see `Value::apply_named`. Calls of functions that are known
+    // statically pass all arguments positionally instead.
+    ApplyNamed { fun: Addr, num_args: usize, dst: Addr },
     // Set the pc to this value.
     Jump(usize),
     // Set the pc to this value if the value at the given Addr is truthy.
     CondJump(Addr, usize),
-    // Create a value from the literal and write it to the address.
-    Literal(IrLiteral, Addr),
+    // Create a value from the literal at the given index of the literals of the function and write
+    // it to the address.
+    Literal(usize, Addr),
     // Set the `throw` flag, indicating that the function should throw instead of returning.
-    // This exists to allow tail call optimization when throwing in tail position.
+    // This exists to allow tail call optimization when throwing in tail position. The flag stays
+    // set until cleared, so code paths that set it must not merge with paths that return normally
+    // unless the latter clear it first (see `IrFunction::ambiguous_returns`). The flag belongs to
+    // a single execution: a called function starts with its own, clear flag, and if it returns
+    // with its flag set, the call throws like any other, so the caller catches the value or
+    // throws it on, no matter the caller's own flag.
     ThrowFlag,
-    // Set the `catch` address.
-    Catch(usize),
+    // Clear the `throw` flag, so that `Return` returns again.
+    ClearThrowFlag,
+    // Set the `catch` address, and the destination for thrown values. The destination is not
+    // fixed, because the storage slots from 0 on double as the arguments of calls, and may hold
+    // values that the handler still needs. Exit values (see `types::exit`) are not caught, they
+    // are rethrown.
+    Catch { target: usize, dst: Addr },
+    // Like `Catch`, but also for exit values. This is the `catch_exit` option.
+    CatchExit { target: usize, dst: Addr },
     // Return the value at the address. If the `throw` flag is set, throw the value instead.
     Return(Addr),
     // Throw the value at the address.
     Throw(Addr),
 }
 
+impl Instruction {
+    // The storage slots the instruction reads.
+    fn read(&self) -> Vec<usize> {
+        let slot = |addr: &Addr| match addr {
+            Addr::Storage(slot) => Some(*slot),
+            Addr::Environment(_) => None,
+        };
+        match self {
+            Instruction::Write { src, .. } => slot(src).into_iter().collect(),
+            Instruction::Apply { fun, num_args, .. }
+            | Instruction::ApplyNamed { fun, num_args, .. } => {
+                slot(fun).into_iter().chain(0..*num_args).collect()
+            }
+            Instruction::CondJump(addr, _)
+            | Instruction::Return(addr)
+            | Instruction::Throw(addr) => slot(addr).into_iter().collect(),
+            _ => vec![],
+        }
+    }
+
+    // The storage slot the instruction always overwrites, if any.
+    fn overwritten(&self) -> Option<usize> {
+        match self {
+            Instruction::Write { dst: Addr::Storage(slot), .. }
+            | Instruction::Literal(_, Addr::Storage(slot)) => Some(*slot),
+            _ => None,
+        }
+    }
+}
+
 // If the `catch` offset has this value, rethrow rather than continuing execution.
-static NO_CATCH: usize = std::usize::MAX;
+static NO_CATCH: usize = usize::MAX;
 
 // The ir pendant to literals in pan source code. Note that pan literals that include expressions
 // can not be translated into IrLiterals directly, they are compiled into multiple Instructions.
+//
+// Strings and byte strings are immutable, so they are stored as ready-made values that every
+// execution of the literal shares. Collections are mutable, so their literals create fresh ones
+// on every execution.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-enum IrLiteral {
+pub(crate) enum IrLiteral {
     Nil,
     Bool(bool),
     Int(i64),
     Float(OrderedFloat<f64>),
     Char(char),
-    String(Box<str>),
-    Bytes(Box<[u8]>),
+    String(Rope),
+    Bytes(Bytes),
     Array(Vec<IrLiteral>),
     Set(BTreeSet<IrLiteral>),
     Map(BTreeMap<IrLiteral, IrLiteral>),
@@ -156,123 +464,476 @@ enum IrLiteral {
 }
 
 impl IrLiteral {
+    // Collection literals are converted with an explicit stack of the collections under
+    // construction rather than by recursion, so that deeply nested literals can not overflow the
+    // stack of the interpreter.
     fn to_value(&self, env: &Gc<GcCell<Environment>>) -> Value {
+        let mut stack: Vec<LiteralFrame> = vec![];
+        let mut current = self;
+        loop {
+            let mut converted = match current.children() {
+                Some(children) => {
+                    stack.push(LiteralFrame { literal: current, children, done: vec![] });
+                    None
+                }
+                None => Some(current.leaf_value(env)),
+            };
+
+            // Hand the converted value to the collection under construction, and finish all
+            // collections whose children have been converted, until there is a child left to
+            // convert.
+            loop {
+                let frame = match stack.last_mut() {
+                    Some(frame) => frame,
+                    None => return converted.expect("the outermost literal has been converted"),
+                };
+                frame.done.extend(converted.take());
+                match frame.children.next() {
+                    Some(child) => {
+                        current = child;
+                        break;
+                    }
+                    None => converted = stack.pop().map(LiteralFrame::finish),
+                }
+            }
+        }
+    }
+
+    // The literals of the elements of a collection literal (keys and values alternating for
+    // maps), `None` for all other literals.
+    fn children(&self) -> Option<Box<dyn Iterator<Item = &IrLiteral> + '_>> {
+        match self {
+            IrLiteral::Array(inners) => Some(Box::new(inners.iter())),
+            IrLiteral::Set(inners) => Some(Box::new(inners.iter())),
+            IrLiteral::Map(inners) => Some(Box::new(
+                inners.iter().flat_map(|(key, val)| iter::once(key).chain(iter::once(val))),
+            )),
+            _ => None,
+        }
+    }
+
+    // The value of a literal that is not a collection literal.
+    fn leaf_value(&self, env: &Gc<GcCell<Environment>>) -> Value {
         match *self {
             IrLiteral::Nil => Value::Nil,
             IrLiteral::Bool(b) => Value::Bool(b),
             IrLiteral::Int(n) => Value::Int(n),
             IrLiteral::Float(f) => Value::Float(f),
             IrLiteral::Char(c) => Value::Char(c),
-            IrLiteral::String(ref s) => Value::String(Rope::from_str(s)),
-            IrLiteral::Bytes(ref b) => Value::Bytes(Bytes::from_slice(b)),
-            IrLiteral::Array(ref inners) => {
-                let arr_val = Gc::new(GcCell::new(Vec::with_capacity(inners.len())));
-                {
-                    let mut arr_ref = arr_val.borrow_mut();
-                    for inner in inners {
-                        arr_ref.push(inner.to_value(env));
-                    }
-                }
-                Value::Array(arr_val)
+            IrLiteral::String(ref s) => Value::String(s.clone()),
+            IrLiteral::Bytes(ref b) => Value::Bytes(b.clone()),
+            IrLiteral::Fun(ref fun, entry) => {
+                let env = Environment::child(env.clone(), fun.env_size);
+                let stamp = env.borrow().stamp;
+                Value::Fun(Fun::Pan(IrClosure { env, fun: fun.clone(), entry, stamp }))
             }
-            IrLiteral::Set(ref inners) => {
-                let set_val = Gc::new(GcCell::new(BTreeSet::new()));
-                {
-                    let mut set_ref = set_val.borrow_mut();
-                    for inner in inners {
-                        set_ref.insert(inner.to_value(env));
-                    }
-                }
-                Value::Set(set_val)
+            IrLiteral::Array(_) | IrLiteral::Set(_) | IrLiteral::Map(_) => {
+                unreachable!("collection literals are converted by to_value")
             }
-            IrLiteral::Map(ref inners) => {
-                let map_val = Gc::new(GcCell::new(BTreeMap::new()));
-                {
-                    let mut map_ref = map_val.borrow_mut();
-                    for (key, val) in inners {
-                        map_ref.insert(key.to_value(env), val.to_value(env));
-                    }
-                }
-                Value::Map(map_val)
+        }
+    }
+}
+
+// A collection literal that `IrLiteral::to_value` is converting.
+struct LiteralFrame<'a> {
+    literal: &'a IrLiteral,
+    // The literals of the elements that have not been converted yet.
+    children: Box<dyn Iterator<Item = &'a IrLiteral> + 'a>,
+    // The values of the elements that have been converted.
+    done: Vec<Value>,
+}
+
+impl LiteralFrame<'_> {
+    // The collection, once all of its elements have been converted.
+    fn finish(self) -> Value {
+        match self.literal {
+            IrLiteral::Array(_) => Value::Array(Gc::new(Collection::new(self.done))),
+            IrLiteral::Set(_) => {
+                Value::Set(Gc::new(Collection::new(self.done.into_iter().collect())))
             }
-            IrLiteral::Fun(ref fun, entry) => {
-                Value::Fun(Fun::Pan(IrClosure {
-                    env: Environment::child(env.clone(), fun.env_size),
-                    fun: fun.clone(),
-                    entry,
-                }))
+            _ => {
+                let mut entries = PersistentMap::new();
+                let mut done = self.done.into_iter();
+                while let (Some(key), Some(val)) = (done.next(), done.next()) {
+                    entries.insert(key, val);
+                }
+                Value::Map(Gc::new(Collection::new(entries)))
             }
         }
     }
 }
 
-// An IrFunction together with an environment. This is a runtime value.
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Trace, Finalize)]
+// An IrFunction together with an environment. This is a runtime value. Closures are compared by
+// identity.
+#[derive(Debug, Clone, Trace, Finalize)]
 pub struct IrClosure {
     env: Gc<GcCell<Environment>>,
     #[unsafe_ignore_trace]
     fun: Rc<IrFunction>,
     // The offset at which to begin execution of the `fun`.
     entry: usize,
+    // The stamp of the environment, kept here so that comparing closures need not borrow it.
+    stamp: u64,
+}
+
+// The watched storage slots of an execution, and the scope in which its watched bindings are
+// watched, see `types::watch`.
+struct FrameWatches {
+    slots: Vec<(WatchId, Watchpoint, usize)>,
+    _active: ActiveScope,
+}
+
+// The temporary storage of an execution of ir code. With the `nan-boxing` feature, the values are
+// packed, see `types::packed`, and the arguments of calls are unpacked into a buffer that every
+// call of the execution reuses.
+struct Storage {
+    slots: Vec<Slot>,
+    #[cfg(feature = "nan-boxing")]
+    args: Vec<Value>,
+}
+
+#[cfg(not(feature = "nan-boxing"))]
+type Slot = Value;
+
+#[cfg(feature = "nan-boxing")]
+type Slot = PackedValue;
+
+impl Storage {
+    fn new(size: usize) -> Storage {
+        let mut slots = Vec::with_capacity(size);
+        slots.resize_with(size, Storage::nil);
+        Storage {
+            slots,
+            #[cfg(feature = "nan-boxing")]
+            args: vec![],
+        }
+    }
+
+    // Set the slot to nil, and return the value it held.
+    fn take(&mut self, index: usize) -> Value {
+        Storage::unpack(std::mem::replace(&mut self.slots[index], Storage::nil()))
+    }
+
+    fn set(&mut self, index: usize, v: Value) {
+        self.slots[index] = Slot::from(v);
+    }
+}
+
+#[cfg(not(feature = "nan-boxing"))]
+impl Storage {
+    fn get(&self, index: usize) -> Value {
+        self.slots[index].clone()
+    }
+
+    // The values of the first `num_args` slots, to pass to a function.
+    fn args(&mut self, num_args: usize) -> &[Value] {
+        &self.slots[..num_args]
+    }
+
+    // Drop what `args` copied, once the call is done.
+    fn release_args(&mut self) {}
+
+    fn nil() -> Slot {
+        Value::nil()
+    }
+
+    fn unpack(slot: Slot) -> Value {
+        slot
+    }
+}
+
+#[cfg(feature = "nan-boxing")]
+impl Storage {
+    fn get(&self, index: usize) -> Value {
+        self.slots[index].to_value()
+    }
+
+    // The values of the first `num_args` slots, to pass to a function.
+    fn args(&mut self, num_args: usize) -> &[Value] {
+        self.args.clear();
+        self.args.extend(self.slots[..num_args].iter().map(PackedValue::to_value));
+        &self.args
+    }
+
+    // Drop what `args` copied, once the call is done, so that the buffer does not keep the
+    // arguments alive.
+    fn release_args(&mut self) {
+        self.args.clear();
+    }
+
+    fn nil() -> Slot {
+        PackedValue::default()
+    }
+
+    fn unpack(slot: Slot) -> Value {
+        slot.into_value()
+    }
+}
+
+impl PartialEq for IrClosure {
+    fn eq(&self, other: &IrClosure) -> bool {
+        self.identity() == other.identity()
+    }
+}
+
+impl Eq for IrClosure {}
+
+impl PartialOrd for IrClosure {
+    fn partial_cmp(&self, other: &IrClosure) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Closures are ordered by their stamps (see `types::owner`), so that the order does not depend on
+// where they were allocated. The owners and addresses only tell apart closures of different
+// runtimes with the same stamp.
+impl Ord for IrClosure {
+    fn cmp(&self, other: &IrClosure) -> std::cmp::Ordering {
+        self.stamp
+            .cmp(&other.stamp)
+            .then_with(|| self.owner().cmp(&other.owner()))
+            .then_with(|| self.identity().cmp(&other.identity()))
+    }
+}
+
+// Consistent with the comparison by identity.
+impl Hash for IrClosure {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.stamp.hash(state);
+    }
 }
 
 impl IrClosure {
+    fn identity(&self) -> (usize, usize, usize) {
+        (
+            &*self.env as *const GcCell<Environment> as usize,
+            &*self.fun as *const IrFunction as usize,
+            self.entry,
+        )
+    }
+
+    // A copy of the closure with a copy of its environment, see `Value::snapshot_copy`.
+    pub(crate) fn copy_with(&self, copies: &mut Copies) -> IrClosure {
+        let env = Environment::copy_with(&self.env, copies);
+        let stamp = env.borrow().stamp;
+        IrClosure { env, fun: self.fun.clone(), entry: self.entry, stamp }
+    }
+
+    /// The stamp of the closure, see `Fun::id`.
+    pub fn id(&self) -> u64 {
+        self.stamp
+    }
+
+    // A closure of the function that begins at `entry`, in a top-level environment of its own.
+    pub(crate) fn top_level(fun: Rc<IrFunction>, entry: usize) -> IrClosure {
+        heap::environment_created();
+        let env = Gc::new(GcCell::new(Environment {
+            bindings: vec![Value::nil(); fun.env_size],
+            parent: None,
+            charge: Charge::new(fun.env_size as u64 * heap::slot_size()),
+            owner: owner::current(),
+            stamp: owner::stamp(),
+        }));
+        let stamp = env.borrow().stamp;
+        IrClosure { env, fun, entry, stamp }
+    }
+
+    // A fresh environment for an execution of the closure.
+    fn frame(&self) -> Gc<GcCell<Environment>> {
+        let env = self.env.borrow();
+        heap::environment_created();
+        Gc::new(GcCell::new(Environment {
+            bindings: vec![Value::nil(); env.bindings.len()],
+            parent: env.parent.clone(),
+            charge: Charge::new(env.bindings.len() as u64 * heap::slot_size()),
+            owner: env.owner,
+            stamp: 0,
+        }))
+    }
+
+    // The locations that an execution in the environment `frame` watches, see `types::watch`.
+    #[cold]
+    fn watch_frame(&self, frame: &Gc<GcCell<Environment>>) -> FrameWatches {
+        let mut slots = vec![];
+        let mut locations = vec![];
+        for (id, point) in watch::points_of(self.stamp) {
+            match point {
+                Watchpoint::Slot { index, .. } => slots.push((id, point, index)),
+                Watchpoint::Env { pair, .. } => {
+                    let view = EnvView { env: frame.clone() }.ancestor(pair.up as usize);
+                    if let Some(EnvView { env }) = view {
+                        let address = &*env as *const GcCell<Environment> as usize;
+                        locations.push((address, pair.index as usize, id, point));
+                    }
+                }
+            }
+        }
+        FrameWatches { slots, _active: ActiveScope::enter(locations) }
+    }
+
+    // Report a write of `new` to `dst` to the watchpoints, before it happens. Fails if the watch
+    // hook stops the code.
+    #[cold]
+    #[inline(never)]
+    fn watch_write(
+        &self,
+        watched: &FrameWatches,
+        storage: &Storage,
+        env: &Gc<GcCell<Environment>>,
+        dst: &Addr,
+        new: &Value,
+        pc: usize,
+    ) -> Result<(), Value> {
+        match dst {
+            Addr::Storage(index) => {
+                for (id, point, _) in watched.slots.iter().filter(|(_, _, slot)| slot == index) {
+                    watch::hit(*id, *point, &storage.get(*index), new, self.stamp, pc)?;
+                }
+            }
+            Addr::Environment(pair) => {
+                let view = EnvView { env: env.clone() }.ancestor(pair.up as usize);
+                let target = view.expect("the ir addresses an existing environment").env;
+                let address = &*target as *const GcCell<Environment> as usize;
+                for (id, point) in watch::env_watches(address, pair.index as usize) {
+                    let old = target.borrow().bindings[pair.index as usize].clone();
+                    watch::hit(id, point, &old, new, self.stamp, pc)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// A view of the environment the closure captured, for tooling. Executions of the closure
+    /// run in fresh child environments of its parent, so the bindings at level 0 are never
+    /// written, the captured state starts at level 1.
+    pub fn environment(&self) -> EnvView {
+        EnvView { env: self.env.clone() }
+    }
+
+    /// The runtime that created the closure, see `types::owner`.
+    pub fn owner(&self) -> RuntimeId {
+        self.env.borrow().owner
+    }
+
+    /// The name the function was bound to in the pan source, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.fun.names.get(&self.entry).map(|name| &**name)
+    }
+
+    /// The names of the parameters of the function, if it can be called with named arguments.
+    pub fn params(&self) -> Option<&[Box<str>]> {
+        self.fun.params.get(&self.entry).map(|params| &**params)
+    }
+
+    /// The maximum number of arguments the function takes, additional ones are ignored. For a
+    /// function of a `rec` group, this is the maximum over all functions of the group.
+    pub fn arity(&self) -> usize {
+        self.fun.args
+    }
+
     pub fn run(&self, args: &[Value]) -> Result<Value, Value> {
-        // The local state of this particular execution.
-        let mut storage = Vec::with_capacity(self.fun.storage_size);
-        storage.resize(self.fun.storage_size, Value::nil());
+        // The local state of this particular execution. Every execution gets its own environment
+        // (with the same parent as the one of the closure), so that executions that are nested
+        // through natives calling back into pan code (possibly into this very closure) do not
+        // overwrite the arguments and locals of each other.
+        let env = self.frame();
+        let mut storage = Storage::new(self.fun.storage_size);
         let mut pc = self.entry;
         let mut catch = NO_CATCH;
+        let mut catch_dst = Addr::Storage(0);
+        let mut catch_exit = false;
         let mut throw = false;
+        // The number of instructions to execute until the next check for interruptions.
+        let mut until_check = interrupt::CHECK_INTERVAL;
 
         // Move the arguments into the environment.
         for (i, arg) in args.iter().take(self.fun.args).enumerate() {
-            self.env.borrow_mut().set(DeBruijnPair {
-                up: 0,
-                index: i,
-            }, arg.clone());
+            env.borrow_mut().set(DeBruijnPair::new(0, i), arg.clone());
         }
 
+        // The locations this execution watches, if there are any watchpoints at all.
+        let watched = if watch::watching() { Some(self.watch_frame(&env)) } else { None };
+
         // Execute ir code until a return or throw instruction is hit. This is the part where
         // turing-completeness happens, it is undecidable in general whether this loop terminates.
         loop {
+            until_check -= 1;
+            if until_check == 0 {
+                until_check = interrupt::CHECK_INTERVAL;
+                interrupt::check()?;
+            }
+
             match &self.fun.code[pc] {
-                Instruction::Write { src, dst } => {
+                Instruction::Write { src, dst, moved } => {
                     let val = match src {
-                        Addr::Storage(index) => storage[*index].clone(),
-                        Addr::Environment(pair) => self.env.borrow().get(*pair),
+                        Addr::Storage(index) if *moved => storage.take(*index),
+                        Addr::Storage(index) => storage.get(*index),
+                        Addr::Environment(pair) => env.borrow().get(*pair),
                     };
 
+                    if let Some(watched) = &watched {
+                        self.watch_write(watched, &storage, &env, dst, &val, pc)?;
+                    }
                     match dst {
-                        Addr::Storage(index) => storage[*index] = val,
-                        Addr::Environment(pair) => self.env.borrow_mut().set(*pair, val),
+                        Addr::Storage(index) => storage.set(*index, val),
+                        Addr::Environment(pair) => env.borrow_mut().set(*pair, val),
                     }
 
                     pc += 1;
                 }
 
-                Instruction::Apply { fun, num_args, dst} => {
+                instruction @ (Instruction::Apply { fun, num_args, dst }
+                | Instruction::ApplyNamed { fun, num_args, dst }) => {
                     let val = match fun {
-                        Addr::Storage(index) => storage[*index].clone(),
-                        Addr::Environment(pair) => self.env.borrow().get(*pair),
+                        Addr::Storage(index) => storage.get(*index),
+                        Addr::Environment(pair) => env.borrow().get(*pair),
                     };
 
-                    let result = val.apply(&storage[..*num_args]);
+                    let args = storage.args(*num_args);
+                    let result = match (instruction, args.split_last()) {
+                        (Instruction::ApplyNamed { .. }, Some((named, positional))) => {
+                            val.apply_named(positional, named)
+                        }
+                        _ => val.apply(args),
+                    };
+                    storage.release_args();
                     match result {
                         Ok(returned) => {
+                            if let Some(watched) = &watched {
+                                self.watch_write(watched, &storage, &env, dst, &returned, pc)?;
+                            }
                             match dst {
-                                Addr::Storage(index) => storage[*index] = returned,
-                                Addr::Environment(pair) => self.env.borrow_mut().set(*pair, returned),
+                                Addr::Storage(index) => storage.set(*index, returned),
+                                Addr::Environment(pair) => env.borrow_mut().set(*pair, returned),
                             }
 
                             pc += 1;
                         }
 
                         Err(thrown) => {
-                            if catch == NO_CATCH {
+                            if throw_observer::observing() {
+                                throw_observer::arrived(&thrown, &val);
+                            }
+                            if catch == NO_CATCH
+                                || (!catch_exit && exit::is_exit(&thrown))
+                                || heap::out_of_memory_of(&thrown).is_some()
+                                || interrupt::is_interrupted(&thrown)
+                                || interrupt::deadline_exceeded_of(&thrown).is_some()
+                            {
                                 return Err(thrown);
                             } else {
-                                storage[0] = thrown;
+                                if throw_observer::observing() {
+                                    throw_observer::caught(&thrown, self.name());
+                                }
+                                if let Some(watched) = &watched {
+                                    self.watch_write(
+                                        watched, &storage, &env, &catch_dst, &thrown, pc,
+                                    )?;
+                                }
+                                match &catch_dst {
+                                    Addr::Storage(index) => storage.set(*index, thrown),
+                                    Addr::Environment(pair) => env.borrow_mut().set(*pair, thrown),
+                                }
                                 pc = catch;
                             }
                         }
@@ -283,8 +944,8 @@ impl IrClosure {
 
                 Instruction::CondJump(addr, new_pc) => {
                     let val = match addr {
-                        Addr::Storage(index) => storage[*index].clone(),
-                        Addr::Environment(pair) => self.env.borrow().get(*pair),
+                        Addr::Storage(index) => storage.get(*index),
+                        Addr::Environment(pair) => env.borrow().get(*pair),
                     };
 
                     if val.truthy() {
@@ -295,9 +956,15 @@ impl IrClosure {
                 }
 
                 Instruction::Literal(lit, dst) => {
+                    // Create the value before borrowing the environment, creating a closure may
+                    // trigger a collection.
+                    let val = self.fun.literals[*lit].to_value(&env);
+                    if let Some(watched) = &watched {
+                        self.watch_write(watched, &storage, &env, dst, &val, pc)?;
+                    }
                     match dst {
-                        Addr::Storage(index) => storage[*index] = lit.to_value(&self.env),
-                        Addr::Environment(pair) => self.env.borrow_mut().set(*pair, lit.to_value(&self.env)),
+                        Addr::Storage(index) => storage.set(*index, val),
+                        Addr::Environment(pair) => env.borrow_mut().set(*pair, val),
                     }
 
                     pc += 1;
@@ -308,30 +975,1181 @@ impl IrClosure {
                     pc += 1;
                 }
 
-                Instruction::Catch(offset) => {
-                    catch = *offset;
+                Instruction::ClearThrowFlag => {
+                    throw = false;
+                    pc += 1;
+                }
+
+                Instruction::Catch { target, dst } => {
+                    catch = *target;
+                    catch_dst = dst.clone();
+                    catch_exit = false;
+                    pc += 1;
+                }
+
+                Instruction::CatchExit { target, dst } => {
+                    catch = *target;
+                    catch_dst = dst.clone();
+                    catch_exit = true;
                     pc += 1;
                 }
 
                 Instruction::Return(addr) => {
+                    // The storage is dropped on return, so its values can be moved out of it.
+                    let val = match addr {
+                        Addr::Storage(index) => storage.take(*index),
+                        Addr::Environment(pair) => env.borrow().get(*pair),
+                    };
                     if throw {
-                        return Err(match addr {
-                            Addr::Storage(index) => storage[*index].clone(),
-                            Addr::Environment(pair) => self.env.borrow().get(*pair),
-                        });
+                        if throw_observer::observing() {
+                            throw_observer::thrown(&val, self.name());
+                        }
+                        return Err(val);
                     } else {
-                        return Ok(match addr {
-                            Addr::Storage(index) => storage[*index].clone(),
-                            Addr::Environment(pair) => self.env.borrow().get(*pair),
-                        });
+                        return Ok(val);
                     }
                 }
 
-                Instruction::Throw(addr) => return Err(match addr {
-                    Addr::Storage(index) => storage[*index].clone(),
-                    Addr::Environment(pair) => self.env.borrow().get(*pair),
-                }),
+                Instruction::Throw(addr) => {
+                    let thrown = match addr {
+                        Addr::Storage(index) => storage.take(*index),
+                        Addr::Environment(pair) => env.borrow().get(*pair),
+                    };
+                    if throw_observer::observing() {
+                        throw_observer::thrown(&thrown, self.name());
+                    }
+                    return Err(thrown);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+    use std::thread;
+
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::runtime::{Arity, RunError, Runtime};
+    use crate::types::watch::WatchAction;
+    use crate::value::Native;
+
+    fn function(
+        storage_size: usize,
+        code: Vec<Instruction>,
+        literals: Vec<IrLiteral>,
+    ) -> IrFunction {
+        IrFunction {
+            args: 0,
+            storage_size,
+            env_size: 0,
+            code: code.into(),
+            literals: literals.into(),
+            names: BTreeMap::new(),
+            params: BTreeMap::new(),
+        }
+    }
+
+    fn run(fun: IrFunction) -> Result<Value, Value> {
+        let mut runtime = Runtime::new();
+        let program = Value::Fun(Fun::Pan(IrClosure::top_level(Rc::new(fun), 0)));
+        runtime.call_raw(&program, &[])
+    }
+
+    fn literal(value: i64) -> IrFunction {
+        let code = vec![
+            Instruction::Literal(0, Addr::Storage(0)),
+            Instruction::Return(Addr::Storage(0)),
+        ];
+        function(1, code, vec![IrLiteral::Int(value)])
+    }
+
+    fn thrower() -> IrFunction {
+        let code = vec![
+            Instruction::Literal(0, Addr::Storage(0)),
+            Instruction::Throw(Addr::Storage(0)),
+        ];
+        function(1, code, vec![IrLiteral::Int(2)])
+    }
+
+    // Calls a function that returns, then sets the flag to throw the result of a call in tail
+    // position. That call throws, and the handler returns the value in slot 0, which it reaches
+    // with the flag set unless it clears it.
+    fn merging(clear: bool) -> IrFunction {
+        let mut code = vec![
+            Instruction::Literal(0, Addr::Storage(0)),
+            Instruction::Literal(1, Addr::Storage(1)),
+            Instruction::Literal(2, Addr::Storage(2)),
+            Instruction::Catch { target: 8, dst: Addr::Storage(3) },
+            Instruction::Apply { fun: Addr::Storage(2), num_args: 0, dst: Addr::Storage(3) },
+            Instruction::ThrowFlag,
+            Instruction::Apply { fun: Addr::Storage(1), num_args: 0, dst: Addr::Storage(3) },
+            Instruction::Return(Addr::Storage(3)),
+        ];
+        if clear {
+            code.push(Instruction::ClearThrowFlag);
+        }
+        code.push(Instruction::Return(Addr::Storage(0)));
+        let literals = vec![
+            IrLiteral::Int(1),
+            IrLiteral::Fun(Rc::new(thrower()), 0),
+            IrLiteral::Fun(Rc::new(literal(3)), 0),
+        ];
+        function(4, code, literals)
+    }
+
+    #[test]
+    fn a_set_flag_leaks_into_a_merged_return() {
+        assert_eq!(run(merging(false)), Err(Value::Int(1)));
+        assert_eq!(merging(false).ambiguous_returns(0), vec![8]);
+    }
+
+    #[test]
+    fn clearing_the_flag_keeps_the_merged_return() {
+        assert_eq!(run(merging(true)), Ok(Value::Int(1)));
+        assert_eq!(merging(true).ambiguous_returns(0), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn returns_with_a_single_flag_state_are_not_ambiguous() {
+        let code = vec![
+            Instruction::Literal(0, Addr::Storage(0)),
+            Instruction::CondJump(Addr::Storage(0), 4),
+            Instruction::ThrowFlag,
+            Instruction::Return(Addr::Storage(0)),
+            Instruction::Return(Addr::Storage(0)),
+        ];
+        let fun = function(1, code, vec![IrLiteral::Bool(true)]);
+        assert_eq!(fun.ambiguous_returns(0), Vec::<usize>::new());
+        assert_eq!(run(fun), Ok(Value::Bool(true)));
+    }
+
+    // Calls the function with the flag set, returning what it returns.
+    fn tail_throwing(callee: IrFunction) -> IrFunction {
+        let code = vec![
+            Instruction::Literal(0, Addr::Storage(0)),
+            Instruction::ThrowFlag,
+            Instruction::Apply { fun: Addr::Storage(0), num_args: 0, dst: Addr::Storage(0) },
+            Instruction::Return(Addr::Storage(0)),
+        ];
+        function(1, code, vec![IrLiteral::Fun(Rc::new(callee), 0)])
+    }
+
+    #[test]
+    fn tail_throws_through_a_call_chain() {
+        let chain = tail_throwing(tail_throwing(tail_throwing(literal(5))));
+        assert_eq!(run(chain), Err(Value::Int(5)));
+    }
+
+    #[test]
+    fn a_callee_flag_does_not_leak_into_the_caller() {
+        let code = vec![
+            Instruction::Literal(0, Addr::Storage(0)),
+            Instruction::Catch { target: 4, dst: Addr::Storage(1) },
+            Instruction::Apply { fun: Addr::Storage(0), num_args: 0, dst: Addr::Storage(0) },
+            Instruction::Return(Addr::Storage(0)),
+            Instruction::Return(Addr::Storage(1)),
+        ];
+        let callee = IrLiteral::Fun(Rc::new(tail_throwing(literal(5))), 0);
+        let caller = function(2, code, vec![callee]);
+        assert_eq!(run(caller), Ok(Value::Int(5)));
+    }
+
+    fn moved(fun: &IrFunction) -> Vec<usize> {
+        fun.code
+            .iter()
+            .enumerate()
+            .filter(|(_, instruction)| {
+                matches!(instruction, Instruction::Write { moved: true, .. })
+            })
+            .map(|(pc, _)| pc)
+            .collect()
+    }
+
+    fn write(src: usize, dst: usize) -> Instruction {
+        Instruction::Write { src: Addr::Storage(src), dst: Addr::Storage(dst), moved: false }
+    }
+
+    #[test]
+    fn writes_from_dead_slots_move() {
+        let code = vec![
+            Instruction::Literal(0, Addr::Storage(0)),
+            write(0, 1),
+            write(1, 2),
+            Instruction::Literal(0, Addr::Storage(1)),
+            Instruction::Return(Addr::Storage(0)),
+        ];
+        let fun = function(3, code, vec![IrLiteral::Array(vec![])]);
+        let with_moves = fun.with_moves();
+        assert_eq!(moved(&with_moves), vec![2]);
+        assert_eq!(run(fun), run(with_moves));
+    }
+
+    #[test]
+    fn slots_read_by_a_handler_or_a_later_iteration_do_not_move() {
+        let code = vec![
+            Instruction::Literal(0, Addr::Storage(0)),
+            Instruction::Literal(1, Addr::Storage(2)),
+            Instruction::Catch { target: 7, dst: Addr::Storage(3) },
+            // A loop that copies slot 0 to slot 1 and slot 1 to slot 3, until the call throws.
+            write(0, 1),
+            write(1, 3),
+            Instruction::Apply { fun: Addr::Storage(2), num_args: 0, dst: Addr::Storage(3) },
+            Instruction::Jump(3),
+            Instruction::Return(Addr::Storage(1)),
+        ];
+        let fun = function(4, code, vec![IrLiteral::Int(1), IrLiteral::Fun(Rc::new(thrower()), 0)]);
+        let with_moves = fun.with_moves();
+        assert_eq!(moved(&with_moves), Vec::<usize>::new());
+        assert_eq!(run(fun), Ok(Value::Int(1)));
+    }
+
+    #[test]
+    fn moves_are_marked_in_created_functions() {
+        let inner = function(2, vec![write(0, 1), Instruction::Return(Addr::Storage(1))], vec![]);
+        let fun = function(1, vec![], vec![IrLiteral::Fun(Rc::new(inner), 0)]);
+        match &fun.with_moves().literals[0] {
+            IrLiteral::Fun(inner, _) => assert_eq!(moved(inner), vec![0]),
+            other => panic!("not a function literal: {:?}", other),
+        }
+    }
+
+    // Converts an array literal nested 100,000 levels deep, and checks the value level by level.
+    fn convert_nested() {
+        const DEPTH: usize = 100_000;
+        let mut literal = IrLiteral::Int(1);
+        for depth in 0..DEPTH {
+            let entry = (IrLiteral::Int(depth as i64), IrLiteral::Nil);
+            let first = match depth % 3 {
+                0 => IrLiteral::Nil,
+                1 => IrLiteral::Map(iter::once(entry).collect()),
+                _ => IrLiteral::Set(BTreeSet::new()),
+            };
+            literal = IrLiteral::Array(vec![first, literal]);
+        }
+        let closure = IrClosure::top_level(Rc::new(function(0, vec![], vec![])), 0);
+        let mut value = literal.to_value(&closure.env);
+
+        // Walk down, taking the nested arrays apart so that nothing is dropped recursively.
+        let mut depth = DEPTH;
+        while let Value::Array(arr) = &value {
+            let mut elements = arr.borrow_mut();
+            assert_eq!(elements.len(), 2);
+            depth -= 1;
+            match (depth % 3, &elements[0]) {
+                (0, Value::Nil) | (2, Value::Set(_)) => {}
+                (1, Value::Map(map)) => {
+                    let map = map.borrow();
+                    assert_eq!(map.len(), 1);
+                    assert_eq!(map.get(&Value::Int(depth as i64)), Some(&Value::Nil));
+                }
+                (_, other) => panic!("unexpected element {:?} at depth {}", other, depth),
+            }
+            let inner = elements.pop().unwrap();
+            drop(elements);
+            value = inner;
+        }
+        assert_eq!((depth, value), (0, Value::Int(1)));
+        while let IrLiteral::Array(mut elements) = literal {
+            literal = elements.pop().unwrap();
+        }
+    }
+
+    #[test]
+    fn deeply_nested_literals_are_converted() {
+        // The collector marks nested collections recursively, so the conversion runs on a stack
+        // that is large enough for marking the result, but too small to convert it recursively.
+        let converting = thread::Builder::new().stack_size(64 << 20).spawn(convert_nested);
+        converting.unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn instructions_stay_small() {
+        // An address fits in two words, and the largest operands are those of `Apply`: two
+        // addresses and an argument count. Literals are in the pool and do not count.
+        assert!(mem::size_of::<Addr>() <= 2 * mem::size_of::<usize>());
+        let apply = 2 * mem::size_of::<Addr>() + mem::size_of::<usize>();
+        assert!(mem::size_of::<Instruction>() <= apply + mem::size_of::<usize>());
+    }
+
+    // Applies the builtin of the given name (passed as the only argument) to the operands. With
+    // `catching`, the call is in a catch region whose handler returns the thrown value.
+    fn applied(name: &str, operands: Vec<IrLiteral>, catching: bool) -> Result<Value, Value> {
+        let n = operands.len();
+        let mut code: Vec<_> = (0..n).map(|i| Instruction::Literal(i, Addr::Storage(i))).collect();
+        if catching {
+            code.push(Instruction::Catch { target: n + 3, dst: Addr::Storage(n) });
+        }
+        let fun = Addr::Environment(DeBruijnPair::new(0, 0));
+        code.push(Instruction::Apply { fun, num_args: n, dst: Addr::Storage(n) });
+        code.push(Instruction::Return(Addr::Storage(n)));
+        if catching {
+            code.push(Instruction::Return(Addr::Storage(n)));
+        }
+        let fun = IrFunction { args: 1, env_size: 1, ..function(n + 1, code, operands) };
+        let mut runtime = Runtime::new();
+        let builtin = runtime.global(name).unwrap();
+        let program = Value::Fun(Fun::Pan(IrClosure::top_level(Rc::new(fun), 0)));
+        runtime.call_raw(&program, &[builtin])
+    }
+
+    #[test]
+    fn arithmetic_builtins_are_applied() {
+        let int = IrLiteral::Int;
+        let float = |x: f64| IrLiteral::Float(OrderedFloat(x));
+        assert_eq!(applied("add", vec![int(2), int(3)], false), Ok(Value::Int(5)));
+        assert_eq!(applied("sub", vec![int(2), float(0.5)], false), Ok(Value::from(1.5)));
+        assert_eq!(applied("mul", vec![int(-2), int(3)], false), Ok(Value::Int(-6)));
+        assert_eq!(applied("div", vec![int(7), int(2)], false), Ok(Value::Int(3)));
+        assert_eq!(applied("rem", vec![int(-7), int(2)], false), Ok(Value::Int(-1)));
+        assert_eq!(applied("neg", vec![int(7)], false), Ok(Value::Int(-7)));
+        match applied("add", vec![float(f64::NAN), int(1)], false) {
+            Ok(Value::Float(x)) => assert!(x.is_nan()),
+            other => panic!("not a float: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn arithmetic_throws_are_caught() {
+        let int = IrLiteral::Int;
+        let cases = vec![
+            ("add", vec![int(i64::MAX), int(1)], "overflow"),
+            ("neg", vec![int(i64::MIN)], "overflow"),
+            ("div", vec![int(1), int(0)], "division_by_zero"),
+            ("rem", vec![int(1), int(0)], "division_by_zero"),
+            ("mul", vec![int(1), IrLiteral::Nil], "type"),
+        ];
+        for (name, operands, kind) in cases {
+            let uncaught = applied(name, operands.clone(), false).unwrap_err();
+            assert_eq!(uncaught.error_kind().unwrap().to_string(), kind, "{}", name);
+            let caught = applied(name, operands, true).unwrap();
+            assert_eq!(caught, uncaught, "{}", name);
+        }
+    }
+
+    #[test]
+    fn caught_values_go_to_the_destination_of_their_region() {
+        let env = |index| Addr::Environment(DeBruijnPair::new(0, index));
+        let add = |dst| Instruction::Apply { fun: env(0), num_args: 2, dst: Addr::Storage(dst) };
+        let throws_three = function(1, thrower().code.into(), vec![IrLiteral::Int(3)]);
+        let code = vec![
+            // Slot 0 is the first argument slot, and stays live across the throwing calls.
+            Instruction::Literal(0, Addr::Storage(0)),
+            Instruction::Literal(1, Addr::Storage(3)),
+            Instruction::Literal(2, Addr::Storage(4)),
+            Instruction::Catch { target: 10, dst: env(1) },
+            Instruction::Catch { target: 7, dst: Addr::Storage(1) },
+            Instruction::Apply { fun: Addr::Storage(3), num_args: 0, dst: Addr::Storage(2) },
+            Instruction::Return(Addr::Storage(2)),
+            // The inner handler, back in the outer region.
+            Instruction::Catch { target: 10, dst: env(1) },
+            Instruction::Apply { fun: Addr::Storage(4), num_args: 0, dst: Addr::Storage(2) },
+            Instruction::Return(Addr::Storage(2)),
+            // The outer handler adds up the live value and both thrown values.
+            add(0),
+            Instruction::Write { src: env(1), dst: Addr::Storage(1), moved: false },
+            add(0),
+            Instruction::Return(Addr::Storage(0)),
+        ];
+        let literals = vec![
+            IrLiteral::Int(7),
+            IrLiteral::Fun(Rc::new(thrower()), 0),
+            IrLiteral::Fun(Rc::new(throws_three), 0),
+        ];
+        let fun = IrFunction { args: 1, env_size: 2, ..function(5, code, literals) };
+        let mut runtime = Runtime::new();
+        let add = runtime.global("add").unwrap();
+        let program = Value::Fun(Fun::Pan(IrClosure::top_level(Rc::new(fun), 0)));
+        assert_eq!(runtime.call_raw(&program, &[add]), Ok(Value::Int(12)));
+    }
+
+    // A pan function of two arguments.
+    fn binary(code: Vec<Instruction>, literals: Vec<IrLiteral>) -> Value {
+        let fun = IrFunction { args: 2, env_size: 2, ..function(1, code, literals) };
+        Value::Fun(Fun::Pan(IrClosure::top_level(Rc::new(fun), 0)))
+    }
+
+    #[test]
+    fn pan_comparators_sort_and_throw() {
+        let mut runtime = Runtime::new();
+        let sort_by = runtime.global("sort_by").unwrap();
+        let ints = |ns: &[i64]| Value::array(ns.iter().map(|n| Value::Int(*n)).collect());
+        let arr = ints(&[3, 1, 2]);
+
+        let code = vec![
+            Instruction::Literal(0, Addr::Storage(0)),
+            Instruction::Return(Addr::Storage(0)),
+        ];
+        let equal = binary(code, vec![IrLiteral::Int(0)]);
+        assert_eq!(runtime.call_raw(&sort_by, &[arr.clone(), equal]), Ok(Value::Nil));
+        assert_eq!(arr, ints(&[3, 1, 2]));
+
+        let code = vec![Instruction::Throw(Addr::Environment(DeBruijnPair::new(0, 1)))];
+        let throwing = binary(code, vec![]);
+        let thrown = runtime.call_raw(&sort_by, &[arr.clone(), throwing]).unwrap_err();
+        assert!(matches!(thrown, Value::Int(1..=3)));
+        assert_eq!(arr, ints(&[3, 1, 2]));
+    }
+
+    #[test]
+    fn pan_functions_are_reflected() {
+        let mut runtime = Runtime::new();
+        let string = |s: &str| Value::String(Rope::from_str(s));
+
+        // Two functions in the same code, as in a `rec` group: one named with parameter names at
+        // offset 0, one anonymous at offset 2.
+        let code = vec![
+            Instruction::Return(Addr::Environment(DeBruijnPair::new(0, 0))),
+            Instruction::Return(Addr::Environment(DeBruijnPair::new(0, 1))),
+            Instruction::Return(Addr::Environment(DeBruijnPair::new(0, 2))),
+        ];
+        let mut fun = IrFunction { args: 3, env_size: 3, ..function(0, code, vec![]) };
+        fun.names.insert(0, "named".into());
+        fun.params.insert(0, vec!["a".into(), "b".into(), "c".into()].into());
+        let fun = Rc::new(fun);
+        let create = |entry: usize| {
+            let code = vec![
+                Instruction::Literal(0, Addr::Storage(0)),
+                Instruction::Return(Addr::Storage(0)),
+            ];
+            let literals = vec![IrLiteral::Fun(fun.clone(), entry)];
+            Value::Fun(Fun::Pan(IrClosure::top_level(Rc::new(function(1, code, literals)), 0)))
+        };
+        let named = runtime.call_raw(&create(0), &[]).unwrap();
+        let anonymous = runtime.call_raw(&create(2), &[]).unwrap();
+        let again = runtime.call_raw(&create(0), &[]).unwrap();
+        let mut reflect = |name: &str, f: &Value| {
+            let builtin = runtime.global(name).unwrap();
+            runtime.call_raw(&builtin, std::slice::from_ref(f)).unwrap()
+        };
+
+        assert_eq!(reflect("fun_name", &named), string("named"));
+        assert_eq!(reflect("fun_name", &anonymous), Value::Nil);
+        for f in [&named, &anonymous].iter() {
+            assert_eq!(reflect("fun_arity", f), Value::Int(3));
+            assert_eq!(reflect("fun_kind", f), string("pan"));
+        }
+        let params = Value::array(vec![string("a"), string("b"), string("c")]);
+        assert_eq!(reflect("fun_params", &named), params);
+        assert_eq!(reflect("fun_params", &anonymous), Value::Nil);
+        // The names belong to the function, not to a particular closure of it.
+        assert_eq!(reflect("fun_name", &again), string("named"));
+        assert!(reflect("fun_id", &named) < reflect("fun_id", &anonymous));
+    }
+
+    // Calls a function of the parameters `a`, `b` and `c` that returns `c`, with the positional
+    // argument 1 and the given named arguments, through `ApplyNamed` on a slot.
+    fn named_call(named: Vec<(&str, IrLiteral)>) -> Result<Value, Value> {
+        let code = vec![Instruction::Return(Addr::Environment(DeBruijnPair::new(0, 2)))];
+        let mut callee = IrFunction { args: 3, env_size: 3, ..function(0, code, vec![]) };
+        callee.names.insert(0, "third".into());
+        callee.params.insert(0, vec!["a".into(), "b".into(), "c".into()].into());
+        let named = named
+            .into_iter()
+            .map(|(name, literal)| (IrLiteral::String(Rope::from_str(name)), literal))
+            .collect();
+        let code = vec![
+            Instruction::Literal(0, Addr::Storage(2)),
+            Instruction::Literal(1, Addr::Storage(0)),
+            Instruction::Literal(2, Addr::Storage(1)),
+            Instruction::ApplyNamed { fun: Addr::Storage(2), num_args: 2, dst: Addr::Storage(0) },
+            Instruction::Return(Addr::Storage(0)),
+        ];
+        let literals =
+            vec![IrLiteral::Fun(Rc::new(callee), 0), IrLiteral::Int(1), IrLiteral::Map(named)];
+        run(function(3, code, literals))
+    }
+
+    #[test]
+    fn named_arguments_are_passed_to_dynamic_callees() {
+        assert_eq!(named_call(vec![("c", IrLiteral::Int(3))]), Ok(Value::Int(3)));
+        assert_eq!(named_call(vec![("b", IrLiteral::Int(2))]), Ok(Value::Nil));
+        assert_eq!(named_call(vec![]), Ok(Value::Nil));
+        let message = |named| named_call(named).unwrap_err().error_message().unwrap().to_string();
+        assert_eq!(message(vec![("d", IrLiteral::Nil)]), "third has no parameter named d");
+        assert_eq!(
+            message(vec![("a", IrLiteral::Nil)]),
+            "the argument a of third is given both positionally and by name",
+        );
+    }
+
+    // Ten closures of the same anonymous function, created by ten calls in the runtime.
+    fn ten_closures(runtime: &mut Runtime) -> Vec<Value> {
+        let code = vec![
+            Instruction::Literal(0, Addr::Storage(0)),
+            Instruction::Return(Addr::Storage(0)),
+        ];
+        let literals = vec![IrLiteral::Fun(Rc::new(literal(1)), 0)];
+        let create = IrClosure::top_level(Rc::new(function(1, code, literals)), 0);
+        let create = Value::Fun(Fun::Pan(create));
+        (0..10).map(|_| runtime.call_raw(&create, &[]).unwrap()).collect()
+    }
+
+    #[test]
+    fn closures_are_ordered_by_creation() {
+        let mut runtime = Runtime::new();
+        let closures = ten_closures(&mut runtime);
+        let ids: Vec<u64> = closures
+            .iter()
+            .map(|closure| match closure {
+                Value::Fun(fun) => fun.id(),
+                other => panic!("not a function: {}", other),
+            })
+            .collect();
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", ids);
+        assert_eq!(closures[0].to_string(), format!("<function #{}>", ids[0]));
+
+        // A set iterates its closures in creation order, whatever order they were added in.
+        let set_add = runtime.global("set_add").unwrap();
+        let set = Value::set(Default::default());
+        for closure in closures.iter().rev() {
+            runtime.call_raw(&set_add, &[set.clone(), closure.clone()]).unwrap();
+        }
+        let set_to_array = runtime.global("set_to_array").unwrap();
+        assert_eq!(runtime.call_raw(&set_to_array, &[set]), Ok(Value::array(closures.clone())));
+
+        // Another runtime stamps its closures independently, so they get the same ids, but they
+        // are different functions.
+        let mut other = Runtime::new();
+        let others = ten_closures(&mut other);
+        for (closure, other) in closures.iter().zip(others.iter()) {
+            assert_eq!(closure.to_string(), other.to_string());
+            assert_ne!(closure, other);
+        }
+    }
+
+    // A watch hit as the old value, the new value, the writing function and the pc.
+    type Hits = Rc<RefCell<Vec<(Value, Value, u64, usize)>>>;
+
+    // The watch hits of a runtime. The hook acts as `action` tells it from the new value.
+    fn record_hits(
+        runtime: &mut Runtime,
+        action: impl Fn(&Value) -> WatchAction + 'static,
+    ) -> Hits {
+        let hits = Rc::new(RefCell::new(vec![]));
+        let recorded = hits.clone();
+        runtime.set_watch_hook(move |hit| {
+            recorded.borrow_mut().push((hit.old.clone(), hit.new.clone(), hit.fun, hit.pc));
+            action(hit.new)
+        });
+        hits
+    }
+
+    #[test]
+    fn watched_bindings_report_writes_of_nested_closures() {
+        let mut runtime = Runtime::new();
+        // Sets the binding of the function that created it to 2.
+        let code = vec![
+            Instruction::Literal(0, Addr::Environment(DeBruijnPair::new(1, 0))),
+            Instruction::Return(Addr::Environment(DeBruijnPair::new(1, 0))),
+        ];
+        let inner = function(0, code, vec![IrLiteral::Int(2)]);
+        // Sets its binding to 1, then creates and calls the inner function and returns the binding.
+        let code = vec![
+            Instruction::Literal(0, Addr::Environment(DeBruijnPair::new(0, 0))),
+            Instruction::Literal(1, Addr::Storage(0)),
+            Instruction::Apply { fun: Addr::Storage(0), num_args: 0, dst: Addr::Storage(0) },
+            Instruction::Return(Addr::Environment(DeBruijnPair::new(0, 0))),
+        ];
+        let literals = vec![IrLiteral::Int(1), IrLiteral::Fun(Rc::new(inner), 0)];
+        let outer = IrFunction { env_size: 1, ..function(1, code, literals) };
+        let outer = IrClosure::top_level(Rc::new(outer), 0);
+        let id = outer.id();
+        let outer = Value::Fun(Fun::Pan(outer));
+
+        let hits = record_hits(&mut runtime, |_| WatchAction::Continue);
+        let watch = runtime.watch_env(id, DeBruijnPair::new(0, 0));
+        assert_eq!(runtime.call_raw(&outer, &[]), Ok(Value::Int(2)));
+        let hits = hits.borrow();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0], (Value::Nil, Value::Int(1), id, 0));
+        let (old, new, writer, pc) = hits[1].clone();
+        assert_eq!((old, new, pc), (Value::Int(1), Value::Int(2), 0));
+        assert_ne!(writer, id);
+        assert!(runtime.unwatch(watch));
+    }
+
+    // Counts slot 2 up from 0 to 5 in a loop, with `add` and `lt` as the arguments.
+    fn counting_loop() -> Value {
+        let env = |index| Addr::Environment(DeBruijnPair::new(0, index));
+        let code = vec![
+            Instruction::Literal(0, Addr::Storage(2)),
+            write(2, 0),
+            Instruction::Literal(1, Addr::Storage(1)),
+            Instruction::Apply { fun: env(0), num_args: 2, dst: Addr::Storage(2) },
+            write(2, 0),
+            Instruction::Literal(2, Addr::Storage(1)),
+            Instruction::Apply { fun: env(1), num_args: 2, dst: Addr::Storage(0) },
+            Instruction::CondJump(Addr::Storage(0), 1),
+            Instruction::Return(Addr::Storage(2)),
+        ];
+        let literals = vec![IrLiteral::Int(0), IrLiteral::Int(1), IrLiteral::Int(5)];
+        let fun = IrFunction { args: 2, env_size: 2, ..function(3, code, literals) };
+        Value::Fun(Fun::Pan(IrClosure::top_level(Rc::new(fun), 0)))
+    }
+
+    #[test]
+    fn watched_slots_report_every_iteration_until_unwatched() {
+        let mut runtime = Runtime::new();
+        let args = [runtime.global("add").unwrap(), runtime.global("lt").unwrap()];
+        let counting = counting_loop();
+        let id = match &counting {
+            Value::Fun(fun) => fun.id(),
+            _ => unreachable!(),
+        };
+
+        // Unwatches once the count reaches 3.
+        let hits = record_hits(&mut runtime, |new| match new {
+            Value::Int(3) => WatchAction::Unwatch,
+            _ => WatchAction::Continue,
+        });
+        let watch = runtime.watch_slot(id, 2);
+        assert_eq!(runtime.call_raw(&counting, &args), Ok(Value::Int(5)));
+        let news: Vec<_> = hits.borrow().iter().map(|(_, new, _, _)| new.clone()).collect();
+        assert_eq!(news, (0..4).map(Value::Int).collect::<Vec<_>>());
+        assert_eq!(hits.borrow()[2], (Value::Int(1), Value::Int(2), id, 3));
+        assert!(!runtime.unwatch(watch));
+
+        // Stopping skips the write and interrupts the code.
+        let hits = record_hits(&mut runtime, |new| match new {
+            Value::Int(2) => WatchAction::Stop,
+            _ => WatchAction::Continue,
+        });
+        runtime.watch_slot(id, 2);
+        let stopped = runtime.call_raw(&counting, &args).unwrap_err();
+        assert!(interrupt::is_interrupted(&stopped));
+        assert_eq!(hits.borrow().len(), 3);
+
+        // Without a hook, nothing is reported.
+        runtime.clear_watch_hook();
+        assert_eq!(runtime.call_raw(&counting, &args), Ok(Value::Int(5)));
+        assert_eq!(hits.borrow().len(), 3);
+    }
+
+    // A pan function of a callee and a recorder that calls the callee in a catch region. The
+    // handler passes the thrown value to the recorder, then rethrows it or returns it.
+    fn catching(catch_exit: bool, rethrow: bool) -> Value {
+        let (target, dst) = (3, Addr::Storage(0));
+        let code = vec![
+            if catch_exit {
+                Instruction::CatchExit { target, dst }
+            } else {
+                Instruction::Catch { target, dst }
+            },
+            Instruction::Apply {
+                fun: Addr::Environment(DeBruijnPair::new(0, 0)),
+                num_args: 0,
+                dst: Addr::Storage(0),
+            },
+            Instruction::Return(Addr::Storage(0)),
+            Instruction::Write { src: Addr::Storage(0), dst: Addr::Storage(2), moved: false },
+            Instruction::Apply {
+                fun: Addr::Environment(DeBruijnPair::new(0, 1)),
+                num_args: 1,
+                dst: Addr::Storage(1),
+            },
+            if rethrow {
+                Instruction::Throw(Addr::Storage(2))
+            } else {
+                Instruction::Return(Addr::Storage(2))
+            },
+        ];
+        let fun = IrFunction { args: 2, env_size: 2, ..function(3, code, vec![]) };
+        Value::Fun(Fun::Pan(IrClosure::top_level(Rc::new(fun), 0)))
+    }
+
+    #[test]
+    fn exits_pass_through_catch_regions() {
+        let mut runtime = Runtime::new();
+        let exit = runtime.global("exit").unwrap();
+        let recorded = Rc::new(RefCell::new(vec![]));
+        let record = recorded.clone();
+        let recorder = Value::Fun(Fun::Native(Native::new("recorder", move |args| {
+            record.borrow_mut().push(args[0].clone());
+            Ok(Value::Nil)
+        })));
+        let native = |name, fun: Box<dyn Fn() -> Result<Value, Value>>| {
+            Value::Fun(Fun::Native(Native::new(name, move |_| fun())))
+        };
+        // Calls `callee` through a rust frame, inside a catch region of `region`. The rust frames
+        // end up in environments, so the pan closures they hold are released at the end of the
+        // test: dropping them while the environments are swept would be unsafe.
+        type Held = Rc<RefCell<Vec<Value>>>;
+        let captured: RefCell<Vec<Held>> = RefCell::default();
+        let nested = |region: Value, callee: Value, recorder: &Value| {
+            let held = Rc::new(RefCell::new(vec![region, callee, recorder.clone()]));
+            captured.borrow_mut().push(held.clone());
+            native("nested", Box::new(move || {
+                let held = held.borrow().clone();
+                held[0].apply(&held[1..])
+            }))
+        };
+        let exits = native("exits", Box::new(move || exit.apply(&[Value::Int(3)])));
+
+        // Plain catch regions do not see the exit, however deep it comes from.
+        let inner = nested(catching(false, false), exits.clone(), &recorder);
+        let middle = nested(catching(false, false), inner, &recorder);
+        match runtime.call(&catching(false, false), &[middle, recorder.clone()]) {
+            Err(RunError::Exit(3)) => {}
+            other => panic!("expected an exit, got {:?}", other),
+        }
+        assert!(recorded.borrow().is_empty());
+
+        // Handlers of `catch_exit` regions run on the way out, and can let the exit continue.
+        let inner = nested(catching(true, true), exits.clone(), &recorder);
+        let middle = nested(catching(false, false), inner, &recorder);
+        match runtime.call(&catching(true, true), &[middle, recorder.clone()]) {
+            Err(RunError::Exit(3)) => {}
+            other => panic!("expected an exit, got {:?}", other),
+        }
+        let codes: Vec<_> = recorded.borrow().iter().map(exit::exit_code).collect();
+        assert_eq!(codes, vec![Some(3), Some(3)]);
+
+        // Or stop it.
+        recorded.borrow_mut().clear();
+        let inner = nested(catching(true, false), exits, &recorder);
+        let caught = runtime.call(&catching(true, true), &[inner, recorder.clone()]).unwrap();
+        assert_eq!(exit::exit_code(&caught), Some(3));
+        assert_eq!(recorded.borrow().len(), 1);
+
+        // Other values are caught by plain regions.
+        recorded.borrow_mut().clear();
+        let throws = native("throws", Box::new(|| Err(Value::Int(7))));
+        let caught = runtime.call(&catching(false, false), &[throws, recorder]).unwrap();
+        assert_eq!(caught, Value::Int(7));
+        assert_eq!(*recorded.borrow(), vec![Value::Int(7)]);
+        recorded.borrow_mut().clear();
+        for held in captured.borrow().iter() {
+            held.borrow_mut().clear();
+        }
+    }
+
+    #[test]
+    fn running_out_of_memory_passes_through_catch_regions() {
+        let mut runtime = Runtime::new();
+        let push = runtime.global("push").unwrap();
+        let array = Value::array(vec![]);
+        // `grow` ends up in environments, so the array it holds is released at the end of the
+        // test, see `exits_pass_through_catch_regions`.
+        let held = Rc::new(RefCell::new(Some(array.clone())));
+        let target = held.clone();
+        let grow = Value::Fun(Fun::Native(Native::new("grow", move |_| loop {
+            let array = target.borrow().clone().unwrap();
+            push.apply(&[array, Value::Int(0)])?;
+        })));
+        let recorded = Rc::new(RefCell::new(vec![]));
+        let record = recorded.clone();
+        let recorder = Value::Fun(Fun::Native(Native::new("recorder", move |args| {
+            record.borrow_mut().push(args[0].clone());
+            Ok(Value::Nil)
+        })));
+
+        // Not even `catch_exit` regions see it.
+        for catch_exit in [false, true].iter() {
+            runtime.set_memory_limit(Some(runtime.memory_used() + 4096));
+            let region = catching(*catch_exit, false);
+            match runtime.call(&region, &[grow.clone(), recorder.clone()]) {
+                Err(RunError::OutOfMemory(oom)) => assert!(oom.used > oom.limit),
+                other => panic!("expected out of memory, got {:?}", other),
+            }
+            assert!(recorded.borrow().is_empty());
+        }
+        match &array {
+            Value::Array(arr) => assert!(arr.borrow().len() as u64 >= 2 * 4096 / heap::slot_size()),
+            _ => unreachable!(),
+        }
+        held.borrow_mut().take();
+    }
+
+    #[test]
+    fn interrupts_stop_infinite_loops() {
+        let mut runtime = Runtime::new();
+        let top_level = |fun| Value::Fun(Fun::Pan(IrClosure::top_level(Rc::new(fun), 0)));
+        let looping = top_level(function(0, vec![Instruction::Jump(0)], vec![]));
+        // Catch regions do not see the interruption either.
+        let code = vec![
+            Instruction::CatchExit { target: 2, dst: Addr::Storage(0) },
+            Instruction::Jump(1),
+            Instruction::Return(Addr::Storage(0)),
+        ];
+        let catching = top_level(function(1, code, vec![]));
+
+        for program in [&looping, &catching].iter() {
+            let handle = runtime.interrupt_handle();
+            let interrupter = std::thread::spawn(move || {
+                std::thread::sleep(std::time::Duration::from_millis(20));
+                handle.interrupt();
+            });
+            let start = std::time::Instant::now();
+            match runtime.call(program, &[]) {
+                Err(RunError::Interrupted) => {}
+                other => panic!("expected an interruption, got {:?}", other),
+            }
+            assert!(start.elapsed() < std::time::Duration::from_secs(5));
+            interrupter.join().unwrap();
+            assert!(!runtime.interrupt_handle().is_interrupted());
+
+            // The runtime is still usable.
+            let answer = top_level(literal(42));
+            assert_eq!(runtime.call(&answer, &[]).unwrap(), Value::Int(42));
+        }
+
+        // An interruption asked for while nothing runs stops the next call, and only that one.
+        runtime.interrupt_handle().interrupt();
+        assert!(matches!(runtime.call(&top_level(literal(1)), &[]), Err(RunError::Interrupted)));
+        assert_eq!(runtime.call(&top_level(literal(1)), &[]).unwrap(), Value::Int(1));
+    }
+
+    #[test]
+    fn deadlines_stop_spin_loops() {
+        use crate::types::futures::{Clock, ManualClock};
+        use std::time::Duration;
+
+        let clock = Rc::new(ManualClock::new());
+        let mut runtime = Runtime::with_clock(clock.clone());
+        let ticking = clock.clone();
+        let tick = Value::Fun(Fun::Native(Native::new("tick", move |_| {
+            ticking.advance(1);
+            Ok(Value::Nil)
+        })));
+        // Calls its argument forever, in a catch region that does not see the deadline either.
+        let code = vec![
+            Instruction::CatchExit { target: 3, dst: Addr::Storage(0) },
+            Instruction::Apply {
+                fun: Addr::Environment(DeBruijnPair::new(0, 0)),
+                num_args: 0,
+                dst: Addr::Storage(0),
+            },
+            Instruction::Jump(1),
+            Instruction::Return(Addr::Storage(0)),
+        ];
+        let spin = IrFunction { args: 1, env_size: 1, ..function(1, code, vec![]) };
+        let spin = Value::Fun(Fun::Pan(IrClosure::top_level(Rc::new(spin), 0)));
+
+        let limit = Duration::from_millis(1000);
+        match runtime.call_with_deadline(&spin, &[tick], limit) {
+            Err(RunError::DeadlineExceeded(exceeded)) => {
+                assert_eq!(exceeded.limit_millis, 1000);
+                assert_eq!(exceeded.elapsed_millis, clock.now());
+                assert!(clock.now() >= 1000);
+                assert!(clock.now() < 1000 + interrupt::CHECK_INTERVAL as u64);
+            }
+            other => panic!("expected a missed deadline, got {:?}", other),
+        }
+
+        // A fast program does not notice its deadline.
+        let fast = Value::Fun(Fun::Pan(IrClosure::top_level(Rc::new(literal(1)), 0)));
+        let start = clock.now();
+        assert_eq!(runtime.call_with_deadline(&fast, &[], limit).unwrap(), Value::Int(1));
+        assert_eq!(clock.now(), start);
+    }
+
+    #[test]
+    fn restored_closures_keep_their_environments() {
+        // Puts an array into its environment, and returns a closure that returns the array.
+        let getter = Rc::new(function(
+            0,
+            vec![Instruction::Return(Addr::Environment(DeBruijnPair::new(1, 0)))],
+            vec![],
+        ));
+        let code = vec![
+            Instruction::Literal(0, Addr::Environment(DeBruijnPair::new(0, 0))),
+            Instruction::Literal(1, Addr::Storage(0)),
+            Instruction::Return(Addr::Storage(0)),
+        ];
+        let literals = vec![IrLiteral::Array(vec![]), IrLiteral::Fun(getter, 0)];
+        let make = IrFunction { env_size: 1, ..function(1, code, literals) };
+        let make = Value::Fun(Fun::Pan(IrClosure::top_level(Rc::new(make), 0)));
+
+        let mut runtime = Runtime::new();
+        let closure = runtime.call(&make, &[]).unwrap();
+        let array = runtime.call(&closure, &[]).unwrap();
+        runtime.define("get", closure.clone()).unwrap();
+        runtime.define("arr", array.clone()).unwrap();
+        let push = |array: &Value, n: i64| match array {
+            Value::Array(arr) => arr.borrow_mut().push(Value::Int(n)),
+            other => panic!("not an array: {:?}", other),
+        };
+        push(&array, 1);
+        let snapshot = runtime.snapshot();
+        push(&array, 2);
+        runtime.define("extra", Value::Nil).unwrap();
+
+        for _ in 0..2 {
+            runtime.restore(&snapshot).unwrap();
+            assert!(runtime.global("extra").is_none());
+            let get = runtime.global("get").unwrap();
+            let restored = runtime.call(&get, &[]).unwrap();
+            assert_eq!(restored, Value::array(vec![Value::Int(1)]));
+            // The closure and the global still share the array, which is not the original.
+            match (&restored, &runtime.global("arr").unwrap(), &array) {
+                (Value::Array(a), Value::Array(b), Value::Array(original)) => {
+                    assert!(Gc::ptr_eq(a, b));
+                    assert!(!Gc::ptr_eq(a, original));
+                }
+                other => panic!("not arrays: {:?}", other),
+            }
+            push(&runtime.global("arr").unwrap(), 3);
+            assert_eq!(runtime.call(&get, &[]).unwrap().to_string(), "[1, 3]");
+        }
+        // The closure from before the snapshot still sees the original array.
+        assert_eq!(runtime.call(&closure, &[]).unwrap().to_string(), "[1, 2]");
+    }
+
+    #[test]
+    fn natives_and_pan_code_reenter_each_other() {
+        let mut runtime = Runtime::new();
+        let top_level = |fun| Value::Fun(Fun::Pan(IrClosure::top_level(Rc::new(fun), 0)));
+        // `pan(n, native)` calls `native(n)`, then returns its own argument `n`.
+        let code = vec![
+            Instruction::Write {
+                src: Addr::Environment(DeBruijnPair::new(0, 0)),
+                dst: Addr::Storage(0),
+                moved: false,
+            },
+            Instruction::Apply {
+                fun: Addr::Environment(DeBruijnPair::new(0, 1)),
+                num_args: 1,
+                dst: Addr::Storage(0),
+            },
+            Instruction::Return(Addr::Environment(DeBruijnPair::new(0, 0))),
+        ];
+        let pan = top_level(IrFunction { args: 2, env_size: 2, ..function(1, code, vec![]) });
+
+        // `reenter(n)` calls `pan(n - 1, reenter)` unless `n` is zero. The functions end up in
+        // environments, so they are released at the end of the test, see
+        // `exits_pass_through_catch_regions`.
+        let held: Rc<RefCell<Vec<Value>>> = Rc::new(RefCell::new(vec![]));
+        let entered = Rc::new(RefCell::new(vec![]));
+        let returned = Rc::new(RefCell::new(vec![]));
+        let (funs, enter, ret) = (held.clone(), entered.clone(), returned.clone());
+        runtime.register_native("reenter", Arity::Max(1), move |ctx, args| {
+            let n = match args[0] {
+                Value::Int(n) => n,
+                _ => unreachable!(),
+            };
+            enter.borrow_mut().push(n);
+            if n == 0 {
+                return Ok(Value::Int(0));
+            }
+            let (pan, reenter) = (funs.borrow()[0].clone(), funs.borrow()[1].clone());
+            let r = ctx.call(&pan, &[Value::Int(n - 1), reenter])?;
+            ret.borrow_mut().push(r.clone());
+            Ok(r)
+        })
+        .unwrap();
+        let reenter = runtime.global("reenter").unwrap();
+        held.borrow_mut().extend(vec![pan.clone(), reenter.clone()]);
+        // Every level sees its own argument after the nested levels returned.
+        assert_eq!(runtime.call(&pan, &[Value::Int(4), reenter]).unwrap(), Value::Int(4));
+        assert_eq!(*entered.borrow(), vec![4, 3, 2, 1, 0]);
+        let expected: Vec<Value> = (0..4).map(Value::Int).collect();
+        assert_eq!(*returned.borrow(), expected);
+
+        // A native translates what its callback throws.
+        let callback = top_level(thrower());
+        runtime.register_native("translate", Arity::Max(0), move |ctx, _| {
+            ctx.call(&callback, &[]).map_err(|thrown| {
+                Value::error("callback", &format!("the callback threw {}", thrown))
+            })
+        })
+        .unwrap();
+        let translate = runtime.global("translate").unwrap();
+        let thrown = runtime.call_raw(&translate, &[]).unwrap_err();
+        assert_eq!(thrown.error_kind().unwrap().to_string(), "callback");
+        assert_eq!(thrown.error_message().unwrap().to_string(), "the callback threw 2");
+
+        // A native creates another native mid-flight, which the pan code then calls.
+        let code = vec![
+            Instruction::Apply {
+                fun: Addr::Environment(DeBruijnPair::new(0, 0)),
+                num_args: 0,
+                dst: Addr::Storage(0),
+            },
+            Instruction::Apply { fun: Addr::Storage(0), num_args: 0, dst: Addr::Storage(0) },
+            Instruction::Return(Addr::Storage(0)),
+        ];
+        let call_made = top_level(IrFunction { args: 1, env_size: 1, ..function(1, code, vec![]) });
+        let make = Value::Fun(Fun::Native(Native::new("make", |_| {
+            Ok(Value::Fun(Fun::Native(Native::new("made", |_| Ok(Value::Int(9))))))
+        })));
+        assert_eq!(runtime.call(&call_made, &[make]).unwrap(), Value::Int(9));
+
+        // Callbacks are interrupted, and refuse values of other runtimes.
+        let handle = runtime.interrupt_handle();
+        let answer = top_level(literal(42));
+        let foreign = Runtime::new().global("len").unwrap();
+        runtime.register_native("callbacks", Arity::Max(1), move |ctx, args| {
+            assert_eq!(ctx.call(&answer, &[])?, Value::Int(42));
+            if args[0].truthy() {
+                handle.interrupt();
+                ctx.call(&answer, &[])
+            } else {
+                ctx.call(&foreign, &[Value::array(vec![])])
+            }
+        })
+        .unwrap();
+        let callbacks = runtime.global("callbacks").unwrap();
+        let thrown = runtime.call_raw(&callbacks, &[Value::Nil]).unwrap_err();
+        assert_eq!(thrown.error_kind().unwrap().to_string(), "runtime");
+        match runtime.call(&callbacks, &[Value::Bool(true)]) {
+            Err(RunError::Interrupted) => {}
+            other => panic!("expected an interruption, got {:?}", other),
+        }
+        held.borrow_mut().clear();
+    }
+
+    #[test]
+    fn environments_can_be_inspected() {
+        let env = |up, index| Addr::Environment(DeBruijnPair::new(up, index));
+        // `outer()` binds 1 and returns `middle`, `middle(x)` binds `x` and "b" and returns
+        // `inner`, which returns the binding of `outer`.
+        let inner = function(0, vec![Instruction::Return(env(2, 0))], vec![]);
+        let code = vec![
+            Instruction::Literal(0, env(0, 1)),
+            Instruction::Literal(1, Addr::Storage(0)),
+            Instruction::Return(Addr::Storage(0)),
+        ];
+        let b = IrLiteral::String(Rope::from_str("b"));
+        let literals = vec![b, IrLiteral::Fun(Rc::new(inner), 0)];
+        let middle = IrFunction { args: 1, env_size: 2, ..function(1, code, literals) };
+        let code = vec![
+            Instruction::Literal(0, env(0, 0)),
+            Instruction::Literal(1, Addr::Storage(0)),
+            Instruction::Return(Addr::Storage(0)),
+        ];
+        let literals = vec![IrLiteral::Int(1), IrLiteral::Fun(Rc::new(middle), 0)];
+        let outer = IrFunction { env_size: 1, ..function(1, code, literals) };
+        let outer = Value::Fun(Fun::Pan(IrClosure::top_level(Rc::new(outer), 0)));
+
+        let mut runtime = Runtime::new();
+        let middle = runtime.call_raw(&outer, &[]).unwrap();
+        let inner = runtime.call_raw(&middle, &[Value::Int(5)]).unwrap();
+        assert_eq!(runtime.call_raw(&inner, &[]), Ok(Value::Int(1)));
+        let view = match &inner {
+            Value::Fun(Fun::Pan(closure)) => closure.environment(),
+            other => panic!("not a pan function: {:?}", other),
+        };
+        assert_eq!(view.depth(), 3);
+        assert_eq!(view.bindings(0), Some(vec![]));
+        let string = Value::String(Rope::from_str("b"));
+        assert_eq!(view.bindings(1), Some(vec![Value::Int(5), string.clone()]));
+        assert_eq!(view.bindings(2), Some(vec![Value::Int(1)]));
+        assert_eq!(view.bindings(3), None);
+        assert_eq!(view.get(DeBruijnPair::new(1, 1)), Some(string));
+        assert_eq!(view.get(DeBruijnPair::new(1, 2)), None);
+        assert_eq!(view.ancestor(2).unwrap().depth(), 1);
+        assert!(view.ancestor(3).is_none());
+        assert_eq!(format!("{:?}", view.ancestor(2).unwrap()), "[Some([Int(1)])]");
+    }
+
+    #[test]
+    fn throw_observers_see_caught_and_escaping_throws() {
+        use crate::types::throw_observer::ThrowEventKind::{self, Caught, Escaped, Thrown};
+
+        let mut boom = thrower();
+        boom.names.insert(0, "boom".into());
+        // `main(fail)` catches a throw of `boom`, then one of `fail`, then rethrows the latter.
+        let code = vec![
+            Instruction::Literal(0, Addr::Storage(1)),
+            Instruction::Catch { target: 3, dst: Addr::Storage(0) },
+            Instruction::Apply { fun: Addr::Storage(1), num_args: 0, dst: Addr::Storage(0) },
+            Instruction::Catch { target: 5, dst: Addr::Storage(0) },
+            Instruction::Apply {
+                fun: Addr::Environment(DeBruijnPair::new(0, 0)),
+                num_args: 0,
+                dst: Addr::Storage(0),
+            },
+            Instruction::Throw(Addr::Storage(0)),
+        ];
+        let literals = vec![IrLiteral::Fun(Rc::new(boom), 0)];
+        let mut main = IrFunction { args: 1, env_size: 1, ..function(2, code, literals) };
+        main.names.insert(0, "main".into());
+        let main = Value::Fun(Fun::Pan(IrClosure::top_level(Rc::new(main), 0)));
+        // The native creates its error anew, as it ends up in an environment, see
+        // `exits_pass_through_catch_regions`.
+        let error = Value::error("failed", "fail failed");
+        let fail = Value::Fun(Fun::Native(Native::new("fail", |_| {
+            Err(Value::error("failed", "fail failed"))
+        })));
+
+        type Event = (u64, ThrowEventKind, Value, Option<String>);
+        let events: Rc<RefCell<Vec<Event>>> = Rc::new(RefCell::new(vec![]));
+        let log = events.clone();
+        let mut runtime = Runtime::new();
+        runtime.set_throw_observer(move |event| {
+            let function = event.function.map(String::from);
+            log.borrow_mut().push((event.id, event.kind, event.value.clone(), function));
+        });
+        assert_eq!(runtime.call_raw(&main, std::slice::from_ref(&fail)), Err(error.clone()));
+
+        let first = events.borrow()[0].0;
+        let relative: Vec<Event> = events
+            .borrow()
+            .iter()
+            .map(|(id, kind, value, function)| (id - first, *kind, value.clone(), function.clone()))
+            .collect();
+        let name = |name: &str| Some(name.to_string());
+        assert_eq!(relative, vec![
+            (0, Thrown, Value::Int(2), name("boom")),
+            (0, Caught, Value::Int(2), name("main")),
+            (1, Thrown, error.clone(), name("fail")),
+            (1, Caught, error.clone(), name("main")),
+            (2, Thrown, error.clone(), name("main")),
+            (2, Escaped, error.clone(), None),
+        ]);
+
+        // A panicking observer changes nothing, and cleared observers are not told anything.
+        runtime.set_throw_observer(|_| panic!("the observer panics"));
+        assert_eq!(runtime.call_raw(&main, std::slice::from_ref(&fail)), Err(error.clone()));
+        events.borrow_mut().clear();
+        runtime.clear_throw_observer();
+        assert_eq!(runtime.call_raw(&main, &[fail]), Err(error));
+        assert!(events.borrow().is_empty());
+    }
+
+    // The values that two executions of the literal produce.
+    fn executed_twice(literal: IrLiteral) -> (Value, Value) {
+        let code = vec![
+            Instruction::Literal(0, Addr::Storage(0)),
+            Instruction::Return(Addr::Storage(0)),
+        ];
+        let mut runtime = Runtime::new();
+        let fun = Rc::new(function(1, code, vec![literal]));
+        let program = Value::Fun(Fun::Pan(IrClosure::top_level(fun, 0)));
+        (runtime.call_raw(&program, &[]).unwrap(), runtime.call_raw(&program, &[]).unwrap())
+    }
+
+    #[test]
+    fn string_and_bytes_literals_are_shared() {
+        match &executed_twice(IrLiteral::String(Rope::from_str("a string literal"))) {
+            (Value::String(a), Value::String(b)) => assert!(Rope::ptr_eq(a, b)),
+            other => panic!("not strings: {:?}", other),
+        }
+        match &executed_twice(IrLiteral::Bytes(Bytes::from_slice(b"bytes"))) {
+            (Value::Bytes(a), Value::Bytes(b)) => assert!(Bytes::ptr_eq(a, b)),
+            other => panic!("not bytes: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn collection_literals_are_fresh() {
+        let literal = IrLiteral::Array(vec![IrLiteral::String(Rope::from_str("element"))]);
+        match &executed_twice(literal) {
+            (Value::Array(a), Value::Array(b)) => {
+                assert!(!Gc::ptr_eq(a, b));
+                a.borrow_mut().push(Value::Nil);
+                assert_eq!(b.borrow().len(), 1);
+                // The elements are strings, which are shared.
+                match (&a.borrow()[0], &b.borrow()[0]) {
+                    (Value::String(a), Value::String(b)) => assert!(Rope::ptr_eq(a, b)),
+                    other => panic!("not strings: {:?}", other),
+                }
             }
+            other => panic!("not arrays: {:?}", other),
         }
     }
 }
@@ -1,3 +1,15 @@
+// The derive macros of the gc crate predate this lint.
+#![allow(non_local_definitions)]
+// Pan values are used as map keys by design.
+#![allow(clippy::mutable_key_type)]
+
 pub mod value;
 pub mod types;
 pub mod ir;
+pub mod builtins;
+pub mod runtime;
+pub mod capabilities;
+pub mod host_io;
+pub mod module;
+#[cfg(feature = "testing")]
+pub mod testing;
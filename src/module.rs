@@ -0,0 +1,121 @@
+// Loading modules through a resolver provided by the host.
+//
+// Pan code imports a module with the `import` builtin (see `builtins::module`), the embedder with
+// `Runtime::import`. Neither knows where modules come from: the runtime asks its resolver (see
+// `Runtime::set_module_resolver`), a function that maps a `ModuleRequest` (the import specifier
+// and the key of the importing module) to a `ResolvedModule`, or fails with a `ModuleError`.
+// Without a resolver, every import fails.
+//
+// A resolved module carries a canonical key chosen by the resolver, and the runtime caches
+// modules by key: the first import of a key loads the module, every later import of the same key
+// (even through a different specifier) yields the very same value. So the resolver decides which
+// imports refer to the same module, and modules imported along several paths (a diamond) share
+// their state.
+//
+// Modules are native: a value (usually a map of functions) provided by the host. There is no
+// compiler in this crate, so modules can not be given as source text.
+//
+// Failed imports throw an error of kind `"import"` whose `data` is a map with the `specifier` and
+// the `importer` (`nil` for imports that do not come from a module).
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use failure_derive::Fail;
+
+use crate::types::owner::{self, RuntimeId};
+use crate::value::Value;
+
+/// What to import, see `Runtime::set_module_resolver`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleRequest {
+    /// The import specifier, as written by the importer.
+    pub specifier: String,
+    /// The canonical key of the importing module, `None` for imports from outside of any module.
+    pub importer: Option<String>,
+}
+
+/// A module found by a resolver.
+#[derive(Debug, Clone)]
+pub struct ResolvedModule {
+    /// The identity of the module: imports that resolve to the same key share one module.
+    pub key: String,
+    /// The value of the module, usually a map of functions.
+    pub value: Value,
+}
+
+impl ResolvedModule {
+    pub fn native(key: impl Into<String>, value: Value) -> ResolvedModule {
+        ResolvedModule { key: key.into(), value }
+    }
+}
+
+/// Why an import failed.
+#[derive(Debug, Clone, PartialEq, Eq, Fail)]
+#[fail(display = "{}", message)]
+pub struct ModuleError {
+    pub message: String,
+}
+
+impl ModuleError {
+    pub fn new(message: impl Into<String>) -> ModuleError {
+        ModuleError { message: message.into() }
+    }
+}
+
+/// Maps import requests to modules, see `Runtime::set_module_resolver`.
+pub type Resolver = dyn Fn(&ModuleRequest) -> Result<ResolvedModule, ModuleError>;
+
+/// The resolver of a runtime and the modules it has loaded.
+#[derive(Default)]
+pub struct Modules {
+    resolver: RefCell<Option<Rc<Resolver>>>,
+    // The loaded modules, by canonical key.
+    loaded: RefCell<BTreeMap<String, Value>>,
+}
+
+impl Modules {
+    pub(crate) fn new() -> Modules {
+        Modules::default()
+    }
+
+    pub(crate) fn set_resolver(&self, resolver: Rc<Resolver>) {
+        *self.resolver.borrow_mut() = Some(resolver);
+    }
+
+    /// The keys of the loaded modules, in ascending order.
+    pub(crate) fn keys(&self) -> Vec<String> {
+        self.loaded.borrow().keys().cloned().collect()
+    }
+
+    /// Resolve the request, and return the module under its key, loading it if it has not been
+    /// loaded yet. Fails if the value of a new module belongs to a runtime other than `runtime`.
+    pub(crate) fn import(
+        &self,
+        request: &ModuleRequest,
+        runtime: RuntimeId,
+    ) -> Result<Value, ModuleError> {
+        let resolver = self.resolver.borrow().clone();
+        let resolver = resolver.ok_or_else(|| ModuleError::new(format!(
+            "cannot import {}, there is no module resolver", request.specifier,
+        )))?;
+        let resolved = resolver(request)?;
+        if let Some(module) = self.loaded.borrow().get(&resolved.key) {
+            return Ok(module.clone());
+        }
+        owner::check(runtime, &resolved.value).map_err(|err| ModuleError::new(format!(
+            "cannot import {}: {}", request.specifier, err,
+        )))?;
+        self.loaded.borrow_mut().insert(resolved.key, resolved.value.clone());
+        Ok(resolved.value)
+    }
+}
+
+/// The error for pan code: an error of kind `"import"`.
+pub(crate) fn import_error(request: &ModuleRequest, err: &ModuleError) -> Value {
+    Value::error_with_data("import", &err.message, Value::record(vec![
+        ("specifier", Value::from(request.specifier.as_str())),
+        ("importer", request.importer.as_deref().map_or(Value::Nil, Value::from)),
+    ]))
+}
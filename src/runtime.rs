@@ -0,0 +1,1284 @@
+// Everything needed to run pan code, and the entry points for embedders.
+//
+// A runtime owns all the state that pan code can reach besides its arguments: the global bindings
+// (the builtins and whatever the embedder registers), the event loop with its clock, the
+// generator behind the random builtins, and the host io (see `host_io`): the readers and writers
+// of the io builtins and the file system of the fs builtins. Runtimes do not share any of this, so
+// pan code running in one runtime can not observe another one. Dropping a runtime drops all of it.
+// Values created by a runtime can not be used with another one, see `types::owner`.
+
+use std::any::{Any, TypeId};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use failure_derive::Fail;
+use futures::future::{Future, LocalFutureObj};
+
+use crate::builtins;
+use crate::capabilities::{Capabilities, Capability};
+use crate::host_io::{FileSystem, HostIo, RealFs};
+use crate::ir::DeBruijnPair;
+use crate::module::{ModuleError, ModuleRequest, Modules, ResolvedModule};
+use crate::types::futures::{
+    Clock, Drive, EventLoop, Future as PanFuture, Job, WeakEventLoop,
+};
+use crate::types::exit;
+use crate::types::heap::{
+    self, BudgetScope, GcConfig, GcStats, MemoryBudget, OutOfMemory, ThresholdScope,
+};
+use crate::types::interrupt::{
+    self, Deadline, DeadlineExceeded, DeadlineScope, InterruptHandle, InterruptScope,
+};
+use crate::types::native_panic::StrictScope;
+use crate::types::owner::{self, CrossRuntimeError, OwnerScope, RuntimeId, Stamps};
+use crate::types::random::Random;
+use crate::types::rooted::{RootTable, Rooted};
+use crate::types::throw_observer::{self, ObserverScope, Observer, ThrowEvent};
+use crate::types::watch::{WatchAction, WatchHit, WatchId, WatchScope, Watches, Watchpoint};
+use crate::value::convert::IntoPanArgs;
+use crate::value::{Copies, Fun, Native, NativeAsync, Value};
+
+/// The state in which pan code runs.
+pub struct Runtime {
+    event_loop: EventLoop,
+    // The builtins and the values registered by the embedder, by name.
+    globals: BTreeMap<&'static str, Value>,
+    // The constants defined by the embedder, by name. They shadow the globals.
+    consts: BTreeMap<&'static str, Value>,
+    // The generator behind the random builtins.
+    random: Random,
+    // Where `print` and `eprint` write to.
+    stdout: Sink,
+    stderr: Sink,
+    // Where `read_line` and `read_all_stdin` read from.
+    stdin: Source,
+    // What the file system builtins access.
+    files: Files,
+    // The module resolver and the modules loaded through it.
+    modules: Rc<Modules>,
+    // The values the host keeps alive through `Rooted` handles.
+    roots: Rc<RefCell<RootTable>>,
+    // What `args` returns.
+    args: Rc<RefCell<Vec<String>>>,
+    // The variables that `env` reads, or `None` for those of the process.
+    env: Rc<RefCell<Option<BTreeMap<String, String>>>>,
+    // Whether the entropy builtins may read from the operating system.
+    entropy: Rc<Cell<bool>>,
+    // Whether pan code may use the gc builtins.
+    gc_control: Rc<Cell<bool>>,
+    // When to collect garbage besides the collections the `gc` crate triggers by itself.
+    gc_config: GcConfig,
+    // Whether panics of natives propagate, see `types::native_panic`.
+    strict_panics: bool,
+    // Told about every throw, see `types::throw_observer`.
+    throw_observer: Option<Observer>,
+    // The watchpoints and the watch hook, see `types::watch`.
+    watches: Rc<Watches>,
+    // The state of the embedder that natives can reach through their `HostCtx`, by type.
+    host_state: HostState,
+    // What the code run by this runtime may allocate, see `types::heap`.
+    budget: Rc<MemoryBudget>,
+    // Set from other threads to stop the code this runtime runs, see `types::interrupt`.
+    interrupt: InterruptHandle,
+    // Tags the values this runtime creates, see `types::owner`.
+    id: RuntimeId,
+    // Stamps the functions this runtime creates, see `types::owner`.
+    stamps: Stamps,
+}
+
+/// A writer shared between a runtime and its builtins.
+pub(crate) type Sink = Rc<RefCell<Box<dyn Write>>>;
+
+/// A reader shared between a runtime and its builtins.
+pub(crate) type Source = Rc<RefCell<Box<dyn BufRead>>>;
+
+/// A file system shared between a runtime and its builtins.
+pub(crate) type Files = Rc<RefCell<Rc<dyn FileSystem>>>;
+
+type HostState = Rc<RefCell<BTreeMap<TypeId, Box<dyn Any>>>>;
+
+/// How many arguments a native function registered with `Runtime::register_native` uses. Like
+/// all functions, it can be called with any number of arguments, this is only for reflection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Arity {
+    /// The function uses at most this many arguments.
+    Max(usize),
+    /// The function uses any number of arguments.
+    Variadic,
+    /// The function uses as many arguments as there are names, and can be called with named
+    /// arguments of these names, see `Value::apply_named`.
+    Named(&'static [&'static str]),
+}
+
+/// Everything that can go wrong when calling a function through `Runtime::call_typed`.
+#[derive(Debug, Clone)]
+pub enum CallError {
+    /// The argument at the given position (starting at zero) could not be converted.
+    Argument { position: usize, message: String },
+    /// The called value is not a function.
+    NotCallable { type_name: &'static str },
+    /// Running the function failed.
+    Run(RunError),
+    /// The function returned `value`, which could not be converted into the requested type.
+    Return { value: Value, message: String },
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CallError::Argument { position, message } => {
+                write!(f, "argument {} could not be converted: {}", position, message)
+            }
+            CallError::NotCallable { type_name } => {
+                write!(f, "cannot call a value of type {}", type_name)
+            }
+            CallError::Run(err) => err.fmt(f),
+            CallError::Return { message, .. } => {
+                write!(f, "the return value could not be converted: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CallError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CallError::Run(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<RunError> for CallError {
+    fn from(err: RunError) -> CallError {
+        CallError::Run(err)
+    }
+}
+
+/// Why running pan code did not produce a value, see `Runtime::call` and `Runtime::block_on`.
+///
+/// Exit, out-of-memory, interrupted and deadline-exceeded values get their own variants,
+/// everything else pan code throws is `Thrown`. `RunError::from_thrown` and
+/// `RunError::into_value` convert between the two representations, for callers that want to work
+/// with the thrown value itself.
+#[derive(Debug, Clone)]
+pub enum RunError {
+    /// The code threw `value`, which renders as `rendered`: as `kind: message` if it is an error
+    /// (see `Value::error`), and in literal syntax otherwise.
+    Thrown { value: Value, rendered: String },
+    /// The code exceeded the memory limit of the runtime, see `Runtime::set_memory_limit`.
+    OutOfMemory(OutOfMemory),
+    /// The code called `exit` with the given code.
+    Exit(i32),
+    /// The code was interrupted through an `InterruptHandle`.
+    Interrupted,
+    /// The code ran past its deadline, see `Runtime::call_with_deadline`.
+    DeadlineExceeded(DeadlineExceeded),
+    /// A value of another runtime was passed in, see `types::owner`.
+    CrossRuntime(CrossRuntimeError),
+}
+
+impl RunError {
+    /// Classify a thrown value.
+    pub fn from_thrown(thrown: Value) -> RunError {
+        if let Some(code) = exit::exit_code(&thrown) {
+            RunError::Exit(code)
+        } else if let Some(oom) = heap::out_of_memory_of(&thrown) {
+            RunError::OutOfMemory(oom)
+        } else if interrupt::is_interrupted(&thrown) {
+            RunError::Interrupted
+        } else if let Some(exceeded) = interrupt::deadline_exceeded_of(&thrown) {
+            RunError::DeadlineExceeded(exceeded)
+        } else {
+            RunError::Thrown { rendered: render(&thrown), value: thrown }
+        }
+    }
+
+    /// The value that was thrown, or an equivalent one for exits and out-of-memory conditions.
+    pub fn into_value(self) -> Value {
+        match self {
+            RunError::Thrown { value, .. } => value,
+            RunError::OutOfMemory(oom) => heap::out_of_memory_value(oom),
+            RunError::Exit(code) => exit::exit_value(code),
+            RunError::Interrupted => interrupt::interrupted_value(),
+            RunError::DeadlineExceeded(exceeded) => interrupt::deadline_exceeded_value(exceeded),
+            RunError::CrossRuntime(err) => err.to_value(),
+        }
+    }
+
+    /// The thrown value, if the code threw something other than a value that unwinds to the
+    /// embedder (see `types::exit::unwinds`), and did not fail to run because of a value of
+    /// another runtime.
+    pub fn thrown(&self) -> Option<&Value> {
+        match self {
+            RunError::Thrown { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunError::Thrown { rendered, .. } => f.write_str(rendered),
+            RunError::OutOfMemory(oom) => write!(
+                f, "out of memory: used {} bytes with a limit of {} bytes", oom.used, oom.limit,
+            ),
+            RunError::Exit(code) => write!(f, "exited with code {}", code),
+            RunError::Interrupted => f.write_str("interrupted"),
+            RunError::DeadlineExceeded(exceeded) => write!(
+                f, "deadline exceeded: ran for {} ms with a limit of {} ms",
+                exceeded.elapsed_millis, exceeded.limit_millis,
+            ),
+            RunError::CrossRuntime(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for RunError {}
+
+impl From<RunError> for Value {
+    fn from(err: RunError) -> Value {
+        err.into_value()
+    }
+}
+
+/// The global bindings of a runtime at some point, see `Runtime::snapshot`.
+#[derive(Debug)]
+pub struct Snapshot {
+    globals: BTreeMap<&'static str, Value>,
+    // The runtime the snapshot was taken of.
+    runtime: RuntimeId,
+}
+
+impl Snapshot {
+    /// The names bound in the snapshot.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.globals.keys().cloned()
+    }
+}
+
+/// A name that is not bound in a runtime, see `Runtime::resolve`.
+#[derive(Debug, Clone, PartialEq, Eq, Fail)]
+pub enum ResolveError {
+    #[fail(display = "{} requires the {} capability", name, capability)]
+    Denied { name: String, capability: Capability },
+    #[fail(display = "{} is not bound", name)]
+    Unbound { name: String },
+}
+
+/// Why a value can not be defined as a constant, see `Runtime::define_const`.
+#[derive(Debug, Clone, PartialEq, Eq, Fail)]
+pub enum ConstError {
+    #[fail(
+        display = "the constant {} contains an unfrozen {}, but constants must be immutable",
+        name, type_name
+    )]
+    Mutable { name: &'static str, type_name: &'static str },
+    #[fail(
+        display = "the constant {} contains a value of type {}, but constants can only contain \
+                   nil, bools, numbers, chars, strings, bytes and frozen arrays, sets and maps",
+        name, type_name
+    )]
+    Unsupported { name: &'static str, type_name: &'static str },
+    #[fail(display = "the constant {} contains itself", name)]
+    Cyclic { name: &'static str },
+}
+
+/// Why a global function can not be replaced, see `Runtime::hot_swap`.
+#[derive(Debug, Clone, PartialEq, Eq, Fail)]
+pub enum SwapError {
+    #[fail(display = "there is no global named {}", name)]
+    Undefined { name: String },
+    #[fail(display = "the global {} is a constant", name)]
+    Constant { name: String },
+    #[fail(display = "the global {} is a value of type {}, not a function", name, type_name)]
+    NotAFunction { name: String, type_name: &'static str },
+    #[fail(
+        display = "the replacement for {} is a value of type {}, not a function",
+        name, type_name
+    )]
+    NotAFunctionReplacement { name: String, type_name: &'static str },
+    #[fail(display = "{}", _0)]
+    CrossRuntime(CrossRuntimeError),
+}
+
+/// Everything that can go wrong when registering a native function.
+#[derive(Debug, Clone, PartialEq, Eq, Fail)]
+pub enum RegisterError {
+    #[fail(display = "there already is a global named {}", name)]
+    Duplicate { name: &'static str },
+}
+
+impl Runtime {
+    pub fn new() -> Runtime {
+        Runtime::with_event_loop(EventLoop::new())
+    }
+
+    /// A runtime whose timers are driven by the given clock.
+    pub fn with_clock(clock: Rc<dyn Clock>) -> Runtime {
+        Runtime::with_event_loop(EventLoop::with_clock(clock))
+    }
+
+    /// A runtime on a deterministic event loop, see `EventLoop::new_deterministic`. The random
+    /// builtins are seeded with the same seed, and the entropy builtins are denied.
+    pub fn new_deterministic(seed: u64) -> Runtime {
+        let mut runtime = Runtime::with_event_loop(EventLoop::new_deterministic(seed));
+        runtime.random.seed(seed);
+        runtime.allow_entropy(false);
+        runtime
+    }
+
+    /// A runtime on the given event loop, e.g. one configured through `EventLoop::builder`.
+    pub fn with_event_loop(event_loop: EventLoop) -> Runtime {
+        Runtime::with_capabilities(event_loop, &Capabilities::SCRIPTING)
+            .expect("registering the builtins only fails for file system access")
+    }
+
+    /// A runtime on the given event loop whose capability-guarded builtins are limited to the
+    /// given capabilities, see `capabilities`. Fails if the root of the file system access can not
+    /// be canonicalized.
+    pub fn with_capabilities(
+        event_loop: EventLoop,
+        capabilities: &Capabilities,
+    ) -> io::Result<Runtime> {
+        let stderr: Sink = Rc::new(RefCell::new(Box::new(io::stderr())));
+        let report = stderr.clone();
+        event_loop.on_unhandled_rejection(move |value, _| {
+            if let Ok(mut stderr) = report.try_borrow_mut() {
+                let _ = writeln!(stderr, "unhandled rejection: {}", value);
+            }
+        });
+        let mut runtime = Runtime {
+            event_loop,
+            globals: BTreeMap::new(),
+            consts: BTreeMap::new(),
+            random: Random::new(),
+            stdout: Rc::new(RefCell::new(Box::new(io::stdout()))),
+            stderr,
+            stdin: Rc::new(RefCell::new(Box::new(BufReader::new(io::stdin())))),
+            files: Rc::new(RefCell::new(Rc::new(RealFs))),
+            modules: Rc::new(Modules::new()),
+            roots: Rc::new(RefCell::new(RootTable::new())),
+            args: Rc::new(RefCell::new(vec![])),
+            env: Rc::new(RefCell::new(None)),
+            entropy: Rc::new(Cell::new(true)),
+            gc_control: Rc::new(Cell::new(true)),
+            gc_config: GcConfig::default(),
+            strict_panics: false,
+            throw_observer: None,
+            watches: Rc::new(Watches::default()),
+            host_state: Rc::new(RefCell::new(BTreeMap::new())),
+            budget: Rc::new(MemoryBudget::new()),
+            interrupt: InterruptHandle::new(),
+            id: owner::next_id(),
+            stamps: Rc::new(Cell::new(1)),
+        };
+        let _entered = runtime.enter();
+        builtins::register(&mut runtime, capabilities)?;
+        Ok(runtime)
+    }
+
+    /// Register a function to be called with every rejection that nobody observed (see
+    /// `EventLoop::on_unhandled_rejection`), replacing the default of printing the rejection
+    /// value to the standard error of the runtime (see `set_stderr`).
+    pub fn set_unhandled_rejection_hook(
+        &mut self,
+        hook: impl Fn(Value, Option<PanFuture>) + 'static,
+    ) {
+        self.event_loop.on_unhandled_rejection(hook);
+    }
+
+    /// Make a rust function available to pan code under the given name, replacing any function
+    /// previously registered under that name. The function need not be `UnwindSafe`: if it
+    /// panics, pan code gets an error of kind `"native-panic"`, see `types::native_panic`.
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        fun: impl Fn(&[Value]) -> Result<Value, Value> + 'static,
+    ) {
+        let _entered = self.enter();
+        self.globals.insert(name, Value::Fun(Fun::Native(Native::new(name, fun))));
+    }
+
+    /// Like `register`, but also declares the maximum number of arguments the function uses, which
+    /// pan code can query with `fun_arity`.
+    pub fn register_with_arity(
+        &mut self,
+        name: &'static str,
+        arity: usize,
+        fun: impl Fn(&[Value]) -> Result<Value, Value> + 'static,
+    ) {
+        let _entered = self.enter();
+        self.globals.insert(name, Value::Fun(Fun::Native(Native::with_arity(name, arity, fun))));
+    }
+
+    /// Like `register`, but for a rust function that returns a rust future. Pan code that calls
+    /// it receives a pan future right away, which polls the rust future once it is run.
+    pub fn register_async(
+        &mut self,
+        name: &'static str,
+        fun: impl Fn(&[Value]) -> LocalFutureObj<'static, Result<Value, Value>> + 'static,
+    ) {
+        let _entered = self.enter();
+        self.globals.insert(name, Value::Fun(Fun::NativeAsync(NativeAsync::new(name, fun))));
+    }
+
+    /// Make a rust function available to pan code under the given name. Unlike `register`, this
+    /// fails if there already is a global of that name, and the function receives a `HostCtx`
+    /// through which it can reach the runtime.
+    pub fn register_native(
+        &mut self,
+        name: &'static str,
+        arity: Arity,
+        fun: impl Fn(&mut HostCtx, &[Value]) -> Result<Value, Value> + 'static,
+    ) -> Result<(), RegisterError> {
+        if self.globals.contains_key(name) {
+            return Err(RegisterError::Duplicate { name });
+        }
+        let _entered = self.enter();
+        let ctx = self.host_ctx();
+        let fun = move |args: &[Value]| fun(&mut ctx.clone(), args);
+        let native = match arity {
+            Arity::Max(arity) => Native::with_arity(name, arity, fun),
+            Arity::Variadic => Native::new(name, fun),
+            Arity::Named(params) => Native::with_params(name, params, fun),
+        };
+        self.globals.insert(name, Value::Fun(Fun::Native(native)));
+        Ok(())
+    }
+
+    /// The id that the values created by this runtime are tagged with, see `types::owner`.
+    pub fn id(&self) -> RuntimeId {
+        self.id
+    }
+
+    // Make this runtime the current one of the thread: the memory budget, the interrupt flag and
+    // the id apply to everything until the guard is dropped.
+    fn enter(&self) -> Entered {
+        Entered {
+            _budget: BudgetScope::enter(&self.budget),
+            _interrupt: InterruptScope::enter(&self.interrupt),
+            _owner: OwnerScope::enter(self.id, &self.stamps),
+            _threshold: ThresholdScope::enter(self.gc_config.allocation_threshold),
+            _strict: StrictScope::enter(self.strict_panics),
+            _observer: ObserverScope::enter(self.throw_observer.clone()),
+            _watch: WatchScope::enter(&self.watches),
+        }
+    }
+
+    // Fail if the function or one of the arguments belongs to another runtime.
+    fn check_owned(&self, fun: &Value, args: &[Value]) -> Result<(), CrossRuntimeError> {
+        owner::check(self.id, fun)?;
+        args.iter().try_for_each(|arg| owner::check(self.id, arg))
+    }
+
+    // The context for natives of this runtime.
+    pub(crate) fn host_ctx(&self) -> HostCtx {
+        HostCtx {
+            event_loop: self.event_loop.downgrade(),
+            state: self.host_state.clone(),
+            interrupt: self.interrupt.clone(),
+            runtime: self.id,
+        }
+    }
+
+    /// Store a value of the embedder that natives registered with `register_native` can access
+    /// by its type (see `HostCtx::with_state`), replacing any previously stored value of the
+    /// same type. The value must not hold any pan values, the garbage collector can not see it.
+    pub fn set_host_state<T: Any>(&mut self, state: T) {
+        self.host_state.borrow_mut().insert(TypeId::of::<T>(), Box::new(state));
+    }
+
+    /// Call `f` with the stored value of type `T`, see `set_host_state`, and return its result.
+    ///
+    /// Panics if a native is currently accessing the state of this runtime.
+    pub fn with_host_state<T: Any, R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        with_state(&self.host_state, f)
+    }
+
+    /// Make a value available to pan code under the given name, replacing any value previously
+    /// registered under that name. Fails if the value belongs to another runtime.
+    pub fn define(&mut self, name: &'static str, value: Value) -> Result<(), CrossRuntimeError> {
+        owner::check(self.id, &value)?;
+        self.globals.insert(name, value);
+        Ok(())
+    }
+
+    /// Make an immutable value available to pan code under the given name, replacing any constant
+    /// previously defined under that name. Unlike values bound with `define`, constants can only
+    /// be built from nil, bools, numbers, chars, strings, bytes and frozen arrays, sets and maps
+    /// (without cycles), like literals in pan source, so a compiler may fold their uses. The
+    /// value is deep copied and frozen, so later changes to the original do not show. Constants
+    /// shadow the other global bindings, and are not affected by `restore`.
+    pub fn define_const(&mut self, name: &'static str, value: Value) -> Result<(), ConstError> {
+        check_const(name, &value, &mut vec![])?;
+        let value = value.deep_copy();
+        value.deep_freeze();
+        self.consts.insert(name, value);
+        Ok(())
+    }
+
+    /// Replace the function bound to the global of the given name, for live coding. The new
+    /// function may take a different number of arguments. Fails without changing anything if
+    /// there is no such global, if it is a constant or not a function, or if `fun` is not a
+    /// function of this runtime.
+    ///
+    /// Only lookups by name see the swap. The global binding is updated in place, so code that
+    /// looks the function up by name for every call (through `global`, `resolve` or a `snapshot`
+    /// taken afterwards) runs the new version from then on. Everything that already holds the
+    /// function value itself keeps running the old code: closures that captured it, data
+    /// structures that contain it, natives that were handed it, and rust code that resolved it
+    /// before the swap. Pan code only reaches globals through such values, so to make a caller
+    /// pick up a new version, the caller has to look the function up by name at each call.
+    ///
+    /// There is no compiler in this crate, so the replacement is a function value rather than
+    /// source code.
+    pub fn hot_swap(&mut self, name: &str, fun: Value) -> Result<(), SwapError> {
+        if self.consts.contains_key(name) {
+            return Err(SwapError::Constant { name: name.to_string() });
+        }
+        let old = self
+            .globals
+            .get_mut(name)
+            .ok_or_else(|| SwapError::Undefined { name: name.to_string() })?;
+        if !matches!(old, Value::Fun(_)) {
+            return Err(SwapError::NotAFunction {
+                name: name.to_string(),
+                type_name: old.type_name(),
+            });
+        }
+        if !matches!(fun, Value::Fun(_)) {
+            return Err(SwapError::NotAFunctionReplacement {
+                name: name.to_string(),
+                type_name: fun.type_name(),
+            });
+        }
+        owner::check(self.id, &fun).map_err(SwapError::CrossRuntime)?;
+        *old = fun;
+        Ok(())
+    }
+
+    /// The constants defined with `define_const`, by name in ascending order.
+    pub fn consts(&self) -> impl Iterator<Item = (&'static str, &Value)> + '_ {
+        self.consts.iter().map(|(name, value)| (*name, value))
+    }
+
+    /// All global bindings (constants, builtins and the values registered by the embedder) with
+    /// clones of their values, sorted by name. A constant hides a global of the same name.
+    pub fn top_level_bindings(&self) -> Vec<(String, Value)> {
+        let mut bindings: BTreeMap<&str, &Value> =
+            self.globals.iter().map(|(name, value)| (*name, value)).collect();
+        bindings.extend(self.consts.iter().map(|(name, value)| (*name, value)));
+        bindings.into_iter().map(|(name, value)| (name.to_string(), value.clone())).collect()
+    }
+
+    /// The constant or value registered under the given name, if any.
+    pub fn global(&self, name: &str) -> Option<Value> {
+        self.consts.get(name).or_else(|| self.globals.get(name)).cloned()
+    }
+
+    /// A copy of the global bindings, to go back to with `restore`. The values are deep copied
+    /// (see `Value::deep_copy`), except that frozen collections stay frozen, deep frozen
+    /// collections with immutable contents are shared (see `Value::deep_freeze`) and pan closures
+    /// are copied together with their environments, and what they share is preserved, so
+    /// mutating the globals later does not affect the snapshot. Functions implemented in rust,
+    /// futures, channels, streams and userdata are shared rather than copied. Sets and maps of
+    /// plain data share their entries with the snapshot, so copying them takes constant time.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot { globals: copy_globals(&self.globals), runtime: self.id }
+    }
+
+    /// Reset the global bindings to those of the snapshot: bindings added since are removed, and
+    /// the others are replaced with fresh copies of the values in the snapshot, so a snapshot can
+    /// be restored several times. Functions implemented in rust that were registered after the
+    /// snapshot are kept. Everything else the runtime owns (the event loop, the io, the random
+    /// generator, host state) is not affected. Fails if the snapshot was taken of another
+    /// runtime.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), CrossRuntimeError> {
+        if snapshot.runtime != self.id {
+            return Err(CrossRuntimeError { expected: self.id, found: snapshot.runtime });
+        }
+        let _entered = self.enter();
+        let mut globals = copy_globals(&snapshot.globals);
+        for (name, value) in &self.globals {
+            if let Value::Fun(Fun::Native(_)) | Value::Fun(Fun::NativeAsync(_)) = value {
+                globals.entry(*name).or_insert_with(|| value.clone());
+            }
+        }
+        self.globals = globals;
+        Ok(())
+    }
+
+    /// Like `global`, but explains why a name is not bound: either it is a builtin guarded by a
+    /// capability the runtime does not have, or it is not bound at all.
+    pub fn resolve(&self, name: &str) -> Result<Value, ResolveError> {
+        self.global(name).ok_or_else(|| match Capability::required_by(name) {
+            Some(capability) => ResolveError::Denied { name: name.to_string(), capability },
+            None => ResolveError::Unbound { name: name.to_string() },
+        })
+    }
+
+    /// Keep the value alive for as long as both the returned handle and this runtime exist, see
+    /// `types::rooted`. Fails if the value belongs to another runtime.
+    pub fn root(&self, v: Value) -> Result<Rooted, CrossRuntimeError> {
+        owner::check(self.id, &v)?;
+        Ok(Rooted::new(&self.roots, self.id, v))
+    }
+
+    /// The number of values currently rooted through `root`.
+    pub fn rooted_count(&self) -> usize {
+        self.roots.borrow().len()
+    }
+
+    /// Call a pan function (or a rust function) with the given arguments, and return what it
+    /// returns, or what went wrong. Fails with a thrown error of kind `"type"` if `fun` is not a
+    /// function. If the call returns a future, it is not run, see `block_on` for that.
+    pub fn call(&mut self, fun: &Value, args: &[Value]) -> Result<Value, RunError> {
+        self.check_owned(fun, args).map_err(RunError::CrossRuntime)?;
+        self.call_raw(fun, args).map_err(RunError::from_thrown)
+    }
+
+    /// Like `call`, but returns the thrown value as is. A value of another runtime is reported
+    /// as a thrown error of kind `"runtime"`.
+    pub fn call_raw(&mut self, fun: &Value, args: &[Value]) -> Result<Value, Value> {
+        self.check_owned(fun, args).map_err(CrossRuntimeError::to_value)?;
+        let _entered = self.enter();
+        self.interrupt.check()?;
+        fun.apply(args).inspect_err(|thrown| {
+            if throw_observer::observing() {
+                throw_observer::escaped(thrown, fun);
+            }
+        })
+    }
+
+    /// Like `call`, but fails with `RunError::DeadlineExceeded` once the call has run for
+    /// `limit`, as measured by the clock of the event loop, see `types::interrupt`. The
+    /// interpreter notices the deadline when it checks for interruptions, natives when they call
+    /// back into pan code through their `HostCtx` or check `HostCtx::remaining_time` themselves.
+    pub fn call_with_deadline(
+        &mut self,
+        fun: &Value,
+        args: &[Value],
+        limit: Duration,
+    ) -> Result<Value, RunError> {
+        let _deadline = DeadlineScope::enter(Deadline::after(self.event_loop.clock(), limit));
+        self.call(fun, args)
+    }
+
+    /// Like `call`, but converts the arguments from and the return value into rust values, see
+    /// `value::convert`.
+    pub fn call_typed<Args, R>(&mut self, fun: &Value, args: Args) -> Result<R, CallError>
+    where
+        Args: IntoPanArgs,
+        R: TryFrom<Value>,
+        R::Error: fmt::Display,
+    {
+        let args = args.into_pan_args().map_err(|err| {
+            CallError::Argument { position: err.position, message: err.message }
+        })?;
+        if !matches!(fun, Value::Fun(_)) {
+            return Err(CallError::NotCallable { type_name: fun.type_name() });
+        }
+        let returned = self.call(fun, &args)?;
+        R::try_from(returned.clone()).map_err(|err| {
+            CallError::Return { value: returned, message: err.to_string() }
+        })
+    }
+
+    /// The event loop that drives the futures of this runtime.
+    pub fn event_loop(&self) -> &EventLoop {
+        &self.event_loop
+    }
+
+    /// The generator behind the random builtins of this runtime.
+    pub fn random(&self) -> &Random {
+        &self.random
+    }
+
+    /// Make `print` and `println` write to the given writer instead of the standard output of the
+    /// process.
+    pub fn set_stdout(&mut self, stdout: Box<dyn Write>) {
+        *self.stdout.borrow_mut() = stdout;
+    }
+
+    /// Make `eprint` and `eprintln` write to the given writer instead of the standard error of
+    /// the process.
+    pub fn set_stderr(&mut self, stderr: Box<dyn Write>) {
+        *self.stderr.borrow_mut() = stderr;
+    }
+
+    /// Make `read_line` and `read_all_stdin` read from the given reader instead of the standard
+    /// input of the process.
+    pub fn set_stdin(&mut self, stdin: Box<dyn Read>) {
+        *self.stdin.borrow_mut() = Box::new(BufReader::new(stdin));
+    }
+
+    /// Make the file system builtins access the given file system instead of the one of the
+    /// process. A root directory given to `allow_fs` or the capabilities is resolved in it.
+    pub fn set_file_system(&mut self, fs: Rc<dyn FileSystem>) {
+        *self.files.borrow_mut() = fs;
+    }
+
+    /// Replace the standard streams and the file system of the runtime all at once, e.g. with
+    /// those of an `InMemoryHostIo`, see `host_io`.
+    pub fn set_host_io(&mut self, host_io: HostIo) {
+        self.set_stdout(host_io.stdout);
+        self.set_stderr(host_io.stderr);
+        self.set_stdin(host_io.stdin);
+        self.set_file_system(host_io.fs);
+    }
+
+    /// Make imports (through the `import` builtin or `import`) resolve through the given
+    /// function, replacing any previous resolver. Modules loaded so far stay loaded, see `module`.
+    pub fn set_module_resolver(
+        &mut self,
+        resolver: impl Fn(&ModuleRequest) -> Result<ResolvedModule, ModuleError> + 'static,
+    ) {
+        self.modules.set_resolver(Rc::new(resolver));
+    }
+
+    /// Import a module from outside of any module, like the `import` builtin does.
+    pub fn import(&mut self, specifier: &str) -> Result<Value, ModuleError> {
+        let request = ModuleRequest { specifier: specifier.to_string(), importer: None };
+        self.modules.import(&request, self.id)
+    }
+
+    /// The canonical keys of the modules loaded so far, in ascending order.
+    pub fn loaded_modules(&self) -> Vec<String> {
+        self.modules.keys()
+    }
+
+    /// Set the program arguments that `args` returns, empty by default.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        *self.args.borrow_mut() = args;
+    }
+
+    /// Make `env` and `env_all` read the given variables instead of the environment of the
+    /// process, or the environment of the process again if `vars` is `None`.
+    pub fn set_env(&mut self, vars: Option<BTreeMap<String, String>>) {
+        *self.env.borrow_mut() = vars;
+    }
+
+    /// Allow or deny the entropy builtins (`random_bytes` and `uuid_v4`, behind the
+    /// `secure-random` feature) to read from the entropy source of the operating system. While
+    /// denied, they throw an error of kind `"forbidden"`. Allowed by default, except for
+    /// deterministic runtimes.
+    pub fn allow_entropy(&mut self, allowed: bool) {
+        self.entropy.set(allowed);
+    }
+
+    /// Allow or deny pan code to force garbage collections and read heap statistics through the
+    /// gc builtins. While denied, they throw an error of kind `"forbidden"`. Allowed by default.
+    pub fn allow_gc_control(&mut self, allowed: bool) {
+        self.gc_control.set(allowed);
+    }
+
+    /// Statistics about the garbage-collected heap of the current thread, see `types::heap`.
+    pub fn gc_stats(&self) -> GcStats {
+        heap::stats()
+    }
+
+    /// Force a garbage collection of the heap of the current thread, counted and timed in the
+    /// `gc_stats`.
+    pub fn gc_collect(&self) {
+        heap::collect();
+    }
+
+    /// Collect garbage at the points given by the config, in addition to the collections the `gc`
+    /// crate triggers by itself. The allocation threshold applies to the code run through `call`
+    /// and `block_on` (and their variants), the idle policy replaces any function registered
+    /// with `EventLoop::on_idle` on the event loop of the runtime.
+    pub fn gc_configure(&mut self, config: GcConfig) {
+        self.gc_config = config;
+        if config.collect_at_idle {
+            self.event_loop.on_idle(heap::trigger);
+        } else {
+            self.event_loop.on_idle(|| {});
+        }
+    }
+
+    /// The config set with `gc_configure`.
+    pub fn gc_config(&self) -> GcConfig {
+        self.gc_config
+    }
+
+    /// Let panics of rust functions called by pan code unwind to the embedder, instead of turning
+    /// them into errors of kind `"native-panic"` that pan code can catch, see
+    /// `types::native_panic`. Not strict by default.
+    pub fn set_strict_panics(&mut self, strict: bool) {
+        self.strict_panics = strict;
+    }
+
+    /// Call `observer` for every throw of the code this runtime runs, including the throws that
+    /// pan code catches, see `types::throw_observer`. The observer can not alter what the code
+    /// does, a panic of the observer is ignored.
+    pub fn set_throw_observer(&mut self, observer: impl FnMut(&ThrowEvent) + 'static) {
+        self.throw_observer = Some(Rc::new(RefCell::new(observer)));
+    }
+
+    /// Stop telling the throw observer (if any) about throws.
+    pub fn clear_throw_observer(&mut self) {
+        self.throw_observer = None;
+    }
+
+    /// Call `hook` before every write to a location that is watched with `watch_env` or
+    /// `watch_slot`, see `types::watch`. What it returns decides whether the code continues, and
+    /// whether the location stays watched. A panic of the hook is ignored, the code continues.
+    pub fn set_watch_hook(&mut self, hook: impl FnMut(&WatchHit) -> WatchAction + 'static) {
+        self.watches.set_hook(Some(Box::new(hook)));
+    }
+
+    /// Stop calling the watch hook (if any). The watchpoints are kept.
+    pub fn clear_watch_hook(&mut self) {
+        self.watches.set_hook(None);
+    }
+
+    /// Watch the binding at `pair`, as addressed by the code of the pan function of stamp `fun`
+    /// (see `Fun::id`), while executions of the function run, see `types::watch`.
+    pub fn watch_env(&mut self, fun: u64, pair: DeBruijnPair) -> WatchId {
+        self.watches.add(Watchpoint::Env { fun, pair })
+    }
+
+    /// Watch the storage slot at `index` of the executions of the pan function of stamp `fun`
+    /// (see `Fun::id`), see `types::watch`.
+    pub fn watch_slot(&mut self, fun: u64, index: usize) -> WatchId {
+        self.watches.add(Watchpoint::Slot { fun, index })
+    }
+
+    /// Remove a watchpoint. Returns whether there was one of that id.
+    pub fn unwatch(&mut self, id: WatchId) -> bool {
+        self.watches.remove(id)
+    }
+
+    /// A handle that stops the code this runtime runs when `interrupt` is called on it, from any
+    /// thread. The interrupted call fails with `RunError::Interrupted`, see `types::interrupt`.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// Limit the estimated memory that the collections and environments allocated by code run
+    /// through `call`, `call_typed` and `block_on` (and their raw variants) may use, in bytes, or
+    /// remove the limit. Once the limit is exceeded, function calls throw an out-of-memory value
+    /// (see `types::heap`) that pan code can not catch, until enough memory is released. No limit
+    /// by default.
+    pub fn set_memory_limit(&mut self, limit: Option<u64>) {
+        self.budget.set_limit(limit);
+    }
+
+    /// The estimated memory used by the collections and environments that the code run by this
+    /// runtime has allocated and that have not been dropped yet, in bytes.
+    pub fn memory_used(&self) -> u64 {
+        self.budget.used()
+    }
+
+    /// Give pan code access to the file system by registering the `fs` builtins, regardless of
+    /// the capabilities the runtime was created with. If `root` is given, only paths inside that
+    /// directory can be accessed, and relative paths are resolved against it. Fails if the root
+    /// can not be canonicalized.
+    pub fn allow_fs(&mut self, root: Option<&Path>) -> io::Result<()> {
+        builtins::fs::register(self, root)
+    }
+
+    pub(crate) fn stdout(&self) -> Sink {
+        self.stdout.clone()
+    }
+
+    pub(crate) fn stderr(&self) -> Sink {
+        self.stderr.clone()
+    }
+
+    pub(crate) fn stdin(&self) -> Source {
+        self.stdin.clone()
+    }
+
+    pub(crate) fn files(&self) -> Files {
+        self.files.clone()
+    }
+
+    pub(crate) fn modules(&self) -> Rc<Modules> {
+        self.modules.clone()
+    }
+
+    pub(crate) fn args(&self) -> Rc<RefCell<Vec<String>>> {
+        self.args.clone()
+    }
+
+    #[cfg(feature = "secure-random")]
+    pub(crate) fn entropy(&self) -> Rc<Cell<bool>> {
+        self.entropy.clone()
+    }
+
+    pub(crate) fn gc_control(&self) -> Rc<Cell<bool>> {
+        self.gc_control.clone()
+    }
+
+    pub(crate) fn env(&self) -> Rc<RefCell<Option<BTreeMap<String, String>>>> {
+        self.env.clone()
+    }
+
+    /// Turn a rust future into a pan future, so that pan code can await it and combine it with
+    /// other futures. The rust future is run on the event loop right away, and its waker
+    /// reschedules it there. If polling it panics, the pan future rejects with an error of kind
+    /// `"native-panic"`, see `types::native_panic`.
+    pub fn spawn_rust<F>(&self, fut: F) -> Value
+    where
+        F: Future<Output = Result<Value, Value>> + 'static,
+    {
+        let fut = PanFuture::from_rust(LocalFutureObj::new(Box::new(fut)));
+        self.event_loop.run_future(&fut).expect("a new future can always be run");
+        Value::Future(fut)
+    }
+
+    /// A pan future for a scope whose body is the given rust function, see `fut_scope`. The
+    /// future is run right away, the body is called on the next turn of the event loop. Futures
+    /// spawned through the `Scope` are cancelled once the scope is done.
+    pub fn scope<B>(&self, body: B) -> Value
+    where
+        B: FnOnce(&Scope) -> Result<Value, Value> + 'static,
+    {
+        let body = RefCell::new(Some(body));
+        let body = Native::new("scope", move |args| {
+            let body = body.borrow_mut().take().expect("the body of a scope is only called once");
+            body(&Scope { spawn: args.first().cloned().unwrap_or(Value::Nil) })
+        });
+        let fut = PanFuture::scope(Value::Fun(Fun::Native(body)));
+        self.event_loop.run_future(&fut).expect("a new future can always be run");
+        Value::Future(fut)
+    }
+
+    /// Run the event loop until the given pan future is done, and return its outcome. The
+    /// future is run first if it has not been run yet. Instead of hanging, this fails with an
+    /// error of kind `"deadlock"` if the loop runs out of work while the future is still pending.
+    /// A cancelled future counts as rejected with an error of kind `"cancelled"`.
+    pub fn block_on(&mut self, fut: &Value) -> Result<Value, RunError> {
+        self.check_owned(fut, &[]).map_err(RunError::CrossRuntime)?;
+        self.block_on_raw(fut).map_err(RunError::from_thrown)
+    }
+
+    /// Like `block_on`, but fails with `RunError::DeadlineExceeded` once the future has run for
+    /// `limit`, as measured by the clock of the event loop (see `call_with_deadline`). The
+    /// future is cancelled then, which cancels the futures it waits for and drops its timers.
+    pub fn block_on_with_deadline(
+        &mut self,
+        fut: &Value,
+        limit: Duration,
+    ) -> Result<Value, RunError> {
+        self.check_owned(fut, &[]).map_err(RunError::CrossRuntime)?;
+        let deadline = Deadline::after(self.event_loop.clock(), limit);
+        let _entered = self.enter();
+        let _deadline = DeadlineScope::enter(deadline.clone());
+        self.interrupt.check().map_err(RunError::from_thrown)?;
+        let job = self.handle(fut).map_err(RunError::from_thrown)?.job;
+        self.event_loop.run_blocking_until_time(&job, deadline.end());
+        match job.poll_outcome(&mut Context::from_waker(&futures::task::noop_waker())) {
+            Poll::Ready(outcome) => outcome
+                .inspect_err(|thrown| {
+                    if throw_observer::observing() {
+                        throw_observer::escaped(thrown, fut);
+                    }
+                })
+                .map_err(RunError::from_thrown),
+            Poll::Pending => match deadline.check() {
+                Err(exceeded) => {
+                    job.cancel();
+                    Err(RunError::DeadlineExceeded(exceeded))
+                }
+                Ok(()) => Err(RunError::from_thrown(Value::error(
+                    "deadlock",
+                    "the event loop ran out of work while the future was pending",
+                ))),
+            },
+        }
+    }
+
+    /// Like `block_on`, but returns the rejection value as is.
+    pub fn block_on_raw(&mut self, fut: &Value) -> Result<Value, Value> {
+        self.check_owned(fut, &[]).map_err(CrossRuntimeError::to_value)?;
+        let _entered = self.enter();
+        self.interrupt.check()?;
+        let job = self.handle(fut)?.job;
+        self.event_loop.run_blocking_until(&job);
+        match job.poll_outcome(&mut Context::from_waker(&futures::task::noop_waker())) {
+            Poll::Ready(outcome) => outcome.inspect_err(|thrown| {
+                if throw_observer::observing() {
+                    throw_observer::escaped(thrown, fut);
+                }
+            }),
+            Poll::Pending => Err(Value::error(
+                "deadlock",
+                "the event loop ran out of work while the future was pending",
+            )),
+        }
+    }
+
+    /// Like `block_on_raw`, but as a rust future for another single-threaded executor to run,
+    /// e.g. inside a tokio `LocalSet`: it drives the event loop of the runtime until the pan
+    /// future is done, awaiting `sleep` with the time until the next timer is due whenever only
+    /// timers remain, see `EventLoop::drive`. Instead of failing with a deadlock, it waits for
+    /// the rust futures on the loop to be woken, as the other executor may wake them.
+    ///
+    /// With tokio, `sleep` is `tokio::time::sleep`. This crate does not depend on tokio, so
+    /// there is no driver that spawns itself onto a `LocalSet`, and timers keep following the
+    /// clock of the loop rather than tokio's.
+    pub fn drive<'a, S, F>(
+        &'a self,
+        fut: &Value,
+        sleep: S,
+    ) -> Result<impl Future<Output = Result<Value, Value>> + 'a, Value>
+    where
+        S: FnMut(Duration) -> F + Unpin + 'a,
+        F: Future<Output = ()> + 'a,
+    {
+        self.check_owned(fut, &[]).map_err(CrossRuntimeError::to_value)?;
+        let job = self.handle(fut)?.job;
+        Ok(Driving { runtime: self, drive: self.event_loop.drive(&job, sleep) })
+    }
+
+    /// A rust future that settles like the given pan future, running it first if it has not
+    /// been run yet. The event loop of the runtime still needs to be driven by someone, the
+    /// handle only waits for the outcome.
+    pub fn handle(&self, fut: &Value) -> Result<PanFutureHandle, Value> {
+        match fut {
+            Value::Future(fut) => Ok(PanFutureHandle { job: self.event_loop.job_of(fut) }),
+            _ => Err(Value::error("type", "expected a future")),
+        }
+    }
+}
+
+/// The parts of a runtime that a native function registered with `Runtime::register_native` can
+/// access. It refers to the runtime without keeping it alive.
+#[derive(Clone)]
+pub struct HostCtx {
+    event_loop: WeakEventLoop,
+    state: HostState,
+    interrupt: InterruptHandle,
+    runtime: RuntimeId,
+}
+
+impl HostCtx {
+    /// Run a pan future on the event loop of the runtime if it has not been run yet, and return
+    /// it. Fails with an error of kind `"type"` if `fut` is not a future, or of kind `"closed"`
+    /// if the runtime has been dropped.
+    pub fn spawn(&mut self, fut: &Value) -> Result<Value, Value> {
+        match fut {
+            Value::Future(f) => {
+                self.event_loop()?.job_of(f);
+                Ok(fut.clone())
+            }
+            _ => Err(Value::error("type", &format!(
+                "expected a future, got {}", fut.type_name(),
+            ))),
+        }
+    }
+
+    /// Turn a rust future into a pan future that is run on the event loop of the runtime right
+    /// away, see `Runtime::spawn_rust`. Fails with an error of kind `"closed"` if the runtime has
+    /// been dropped.
+    pub fn spawn_rust<F>(&mut self, fut: F) -> Result<Value, Value>
+    where
+        F: Future<Output = Result<Value, Value>> + 'static,
+    {
+        let fut = PanFuture::from_rust(LocalFutureObj::new(Box::new(fut)));
+        self.event_loop()?.run_future(&fut).expect("a new future can always be run");
+        Ok(Value::Future(fut))
+    }
+
+    /// Call `f` with the value of type `T` that the embedder stored with
+    /// `Runtime::set_host_state`, and return its result, or `None` if there is no such value.
+    ///
+    /// Panics if the state is already being accessed, e.g. by a native further up the stack.
+    pub fn with_state<T: Any, R>(&mut self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        with_state(&self.state, f)
+    }
+
+    /// Call a pan function (or a rust function) from within a native, and return what it returns
+    /// or throws, e.g. to call a callback that pan code passed to the native. Calls may nest to
+    /// any depth, also back into the native or pan function that is calling: every execution of a
+    /// pan function has its own environment and temporaries. The call runs under the memory
+    /// budget, interrupt flag, deadline and panic handling of the code that called the native.
+    /// Throws an interrupted value if the runtime has been asked to stop, a deadline-exceeded
+    /// value if the deadline has passed, and an error of kind `"runtime"` if `fun` or an argument
+    /// belongs to another runtime.
+    pub fn call(&mut self, fun: &Value, args: &[Value]) -> Result<Value, Value> {
+        self.interrupt.check()?;
+        interrupt::check_deadline()?;
+        std::iter::once(fun)
+            .chain(args)
+            .try_for_each(|v| owner::check(self.runtime, v))
+            .map_err(CrossRuntimeError::to_value)?;
+        fun.apply(args).inspect_err(|thrown| {
+            if throw_observer::observing() {
+                throw_observer::arrived(thrown, fun);
+            }
+        })
+    }
+
+    /// Returns whether the code of the runtime has been asked to stop, see
+    /// `Runtime::interrupt_handle`. Natives that do a lot of work should check this now and then.
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupt.is_interrupted()
+    }
+
+    /// The time left until the deadline of the running code passes (zero once it has passed), or
+    /// `None` if there is no deadline, see `Runtime::call_with_deadline`. Natives that take long
+    /// should give up once this reaches zero, by throwing the error of `check_interrupt`.
+    pub fn remaining_time(&self) -> Option<Duration> {
+        interrupt::remaining_time()
+    }
+
+    /// Fail with an interrupted value if the code of the runtime has been asked to stop, or with
+    /// a deadline-exceeded value if its deadline has passed, to be thrown by the native. This
+    /// clears the request to stop, like the interpreter does when it notices one.
+    pub fn check_interrupt(&mut self) -> Result<(), Value> {
+        self.interrupt.check()?;
+        interrupt::check_deadline()
+    }
+
+    fn event_loop(&self) -> Result<EventLoop, Value> {
+        self.event_loop.upgrade().ok_or_else(|| {
+            Value::error("closed", "the runtime of this function has been dropped")
+        })
+    }
+}
+
+// Copy global bindings for `snapshot` and `restore`.
+fn copy_globals(globals: &BTreeMap<&'static str, Value>) -> BTreeMap<&'static str, Value> {
+    let mut copies = Copies::default();
+    globals.iter().map(|(name, value)| (*name, value.snapshot_copy(&mut copies))).collect()
+}
+
+// Fail unless the value can be a constant. `enclosing` holds the addresses of the collections the
+// value is nested in.
+fn check_const(
+    name: &'static str,
+    v: &Value,
+    enclosing: &mut Vec<usize>,
+) -> Result<(), ConstError> {
+    let address = match v {
+        Value::Nil
+        | Value::Bool(_)
+        | Value::Int(_)
+        | Value::Float(_)
+        | Value::Char(_)
+        | Value::String(_)
+        | Value::Bytes(_) => return Ok(()),
+        Value::Array(arr) => &**arr as *const _ as usize,
+        Value::Set(set) => &**set as *const _ as usize,
+        Value::Map(map) => &**map as *const _ as usize,
+        _ => return Err(ConstError::Unsupported { name, type_name: v.type_name() }),
+    };
+    if !v.is_frozen() {
+        return Err(ConstError::Mutable { name, type_name: v.type_name() });
+    }
+    if enclosing.contains(&address) {
+        return Err(ConstError::Cyclic { name });
+    }
+    enclosing.push(address);
+    let checked = match v {
+        Value::Array(arr) => arr.borrow().iter().try_for_each(|v| check_const(name, v, enclosing)),
+        Value::Set(set) => set.borrow().iter().try_for_each(|v| check_const(name, v, enclosing)),
+        Value::Map(map) => map.borrow().iter().try_for_each(|(k, v)| {
+            check_const(name, k, enclosing)?;
+            check_const(name, v, enclosing)
+        }),
+        _ => Ok(()),
+    };
+    enclosing.pop();
+    checked
+}
+
+// How a thrown value is shown to the embedder: errors as their kind and message, other values
+// in literal syntax.
+fn render(thrown: &Value) -> String {
+    match (thrown.error_kind(), thrown.error_message()) {
+        (Some(kind), Some(message)) => format!("{}: {}", kind, message),
+        _ => thrown.literal(builtins::assert::MAX_RENDER_DEPTH).to_string(),
+    }
+}
+
+fn with_state<T: Any, R>(state: &HostState, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+    state.borrow_mut().get_mut(&TypeId::of::<T>()).and_then(|s| s.downcast_mut()).map(f)
+}
+
+// Restores the previous state of the thread when dropped, see `Runtime::enter`.
+struct Entered {
+    _budget: BudgetScope,
+    _interrupt: InterruptScope,
+    _owner: OwnerScope,
+    _threshold: ThresholdScope,
+    _strict: StrictScope,
+    _observer: ObserverScope,
+    _watch: WatchScope,
+}
+
+/// Spawns children of a scope, see `Runtime::scope`.
+pub struct Scope {
+    // The function that the event loop passes to the body of a scope.
+    spawn: Value,
+}
+
+impl Scope {
+    /// Run a pan future as a child of the scope, and return it. Fails if `fut` is not a future.
+    pub fn spawn(&self, fut: &Value) -> Result<Value, Value> {
+        self.spawn.apply(std::slice::from_ref(fut))
+    }
+
+    /// Turn a rust future into a pan future that runs as a child of the scope, see
+    /// `Runtime::spawn_rust`.
+    pub fn spawn_rust<F>(&self, fut: F) -> Value
+    where
+        F: Future<Output = Result<Value, Value>> + 'static,
+    {
+        let fut = Value::Future(PanFuture::from_rust(LocalFutureObj::new(Box::new(fut))));
+        self.spawn(&fut).expect("a new future can always be spawned")
+    }
+}
+
+/// A pan future as a rust future, see `Runtime::handle`. A cancelled pan future counts as
+/// rejected with an error of kind `"cancelled"`.
+#[derive(Debug, Clone)]
+pub struct PanFutureHandle {
+    job: Job,
+}
+
+impl Future for PanFutureHandle {
+    type Output = Result<Value, Value>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<Value, Value>> {
+        self.job.poll_outcome(cx)
+    }
+}
+
+// Enters the runtime while driving its event loop, see `Runtime::drive`.
+struct Driving<'a, S, F> {
+    runtime: &'a Runtime,
+    drive: Drive<S, F>,
+}
+
+impl<S, F> Future for Driving<'_, S, F>
+where
+    S: FnMut(Duration) -> F + Unpin,
+    F: Future<Output = ()>,
+{
+    type Output = Result<Value, Value>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<Value, Value>> {
+        let _entered = self.runtime.enter();
+        Pin::new(&mut self.drive).poll(cx)
+    }
+}
+
+impl Default for Runtime {
+    fn default() -> Runtime {
+        Runtime::new()
+    }
+}
@@ -0,0 +1,4 @@
+// Tools for testing the interpreter, behind the `testing` feature.
+
+pub mod ir_gen;
+pub mod workloads;
@@ -0,0 +1,367 @@
+// Random ir programs, and a differential harness that runs them in several configurations of the
+// interpreter and checks that they all behave the same.
+//
+// A generated program is a function without arguments that uses random `Write`, `Literal`,
+// `Apply`, `CondJump`, `Jump`, `Catch`, `CatchExit`, `ThrowFlag`, `ClearThrowFlag`, `Throw` and
+// `Return` instructions. Programs always terminate: all jumps go forward, and the functions a
+// program calls are generated function literals of a greater depth, up to a maximum depth. As
+// generated functions take no arguments and only address their own environment, they can not get
+// hold of a function of the same or a smaller depth. A caught value jumps to the catch target no
+// matter where the call is, so the code of a function is split into a body and handlers: catch
+// targets are in the handlers, and only the body calls functions or sets catch targets.
+//
+// `differential` runs every program as generated and in each of the other `Execution`s, and
+// compares the outcomes (returned or thrown, and the printed value). A program whose outcomes
+// differ is shrunk by replacing its instructions with no-ops for as long as the outcomes still
+// differ, and reported with its disassembly. Before the generated programs, it runs a few fixed
+// programs for bugs that have been fixed, and compares their outcomes with the known right ones.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use std::rc::Rc;
+
+use ordered_float::OrderedFloat;
+
+use crate::ir::{Addr, DeBruijnPair, Instruction, IrClosure, IrFunction, IrLiteral};
+use crate::runtime::Runtime;
+use crate::types::random::Random;
+use crate::types::rope::Rope;
+use crate::value::{Fun, Value};
+
+/// The shape of generated programs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GenConfig {
+    /// The maximum number of instructions of a function.
+    pub max_len: usize,
+    /// How deeply the functions of a program may nest: the program itself has depth zero, and a
+    /// function of depth `n` only creates functions of depth `n + 1`.
+    pub max_depth: usize,
+    /// The number of storage slots of every function. Zero is treated as one.
+    pub storage_size: usize,
+    /// The number of bindings in the environments of every function.
+    pub env_size: usize,
+}
+
+impl Default for GenConfig {
+    fn default() -> GenConfig {
+        GenConfig { max_len: 24, max_depth: 2, storage_size: 4, env_size: 2 }
+    }
+}
+
+/// How to run a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Execution {
+    /// As generated.
+    Baseline,
+    /// With every `Write` from a storage slot that is not read again turned into a move.
+    MovedWrites,
+}
+
+impl Execution {
+    // The code of the function as it runs in this execution.
+    pub(crate) fn prepare(self, fun: &Rc<IrFunction>) -> Rc<IrFunction> {
+        match self {
+            Execution::Baseline => fun.clone(),
+            Execution::MovedWrites => Rc::new(fun.with_moves()),
+        }
+    }
+}
+
+/// All executions, the baseline first.
+pub const EXECUTIONS: [Execution; 2] = [Execution::Baseline, Execution::MovedWrites];
+
+/// What running a program did. Values are compared in their printed form, so that different
+/// functions with the same stamp (printed as `<function #stamp>`) count as the same.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Returned(String),
+    Threw(String),
+}
+
+/// A generated ir function that takes no arguments.
+#[derive(Clone)]
+pub struct Program {
+    fun: Rc<IrFunction>,
+}
+
+impl Program {
+    /// The program for the given seed. The same seed and config always give the same program.
+    pub fn generate(seed: u64, config: &GenConfig) -> Program {
+        let config = GenConfig { storage_size: config.storage_size.max(1), ..*config };
+        let gen = Gen { random: Random::with_seed(seed), config };
+        Program { fun: Rc::new(gen.function(0)) }
+    }
+
+    /// The number of instructions of the program, without those of the functions it creates.
+    pub fn instructions(&self) -> usize {
+        self.fun.code.len()
+    }
+
+    /// Run the program in a fresh runtime.
+    pub fn run(&self, execution: Execution) -> Outcome {
+        let mut runtime = Runtime::new();
+        let program = Value::Fun(Fun::Pan(IrClosure::top_level(execution.prepare(&self.fun), 0)));
+        match runtime.call_raw(&program, &[]) {
+            Ok(v) => Outcome::Returned(v.to_string()),
+            Err(v) => Outcome::Threw(v.to_string()),
+        }
+    }
+
+    /// A listing of the instructions and literals of the program and the functions it creates.
+    pub fn disassemble(&self) -> String {
+        let mut out = String::new();
+        disassemble(&self.fun, "program", &mut out);
+        out
+    }
+}
+
+impl fmt::Debug for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.disassemble())
+    }
+}
+
+/// A program that did not behave as expected, see `differential`.
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    /// The seed the program was generated from, `None` for the fixed programs.
+    pub seed: Option<u64>,
+    /// The program, shrunk.
+    pub program: Program,
+    /// The outcome of the baseline execution, or the known right one for the fixed programs.
+    pub expected: Outcome,
+    /// The execution that went wrong, and its outcome.
+    pub execution: Execution,
+    pub found: Outcome,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.seed {
+            Some(seed) => write!(f, "the program of seed {}", seed)?,
+            None => f.write_str("a fixed program")?,
+        }
+        writeln!(
+            f,
+            " had the outcome {:?} in the {:?} execution, expected {:?}",
+            self.found, self.execution, self.expected,
+        )?;
+        f.write_str(&self.program.disassemble())
+    }
+}
+
+/// Run the fixed programs, then the programs of the seeds from `first_seed` on, `cases` many, in
+/// all executions. Fails with the first program that does not behave as expected.
+pub fn differential(first_seed: u64, cases: u64, config: &GenConfig) -> Result<(), Mismatch> {
+    for (program, expected) in regressions() {
+        for &execution in EXECUTIONS.iter() {
+            let found = program.run(execution);
+            if found != expected {
+                return Err(Mismatch { seed: None, program, expected, execution, found });
+            }
+        }
+    }
+
+    for seed in first_seed..first_seed.saturating_add(cases) {
+        let program = Program::generate(seed, config);
+        let expected = program.run(Execution::Baseline);
+        for &execution in EXECUTIONS[1..].iter() {
+            if program.run(execution) != expected {
+                let program = shrink(&program, |p| {
+                    p.run(Execution::Baseline) != p.run(execution)
+                });
+                return Err(Mismatch {
+                    seed: Some(seed),
+                    expected: program.run(Execution::Baseline),
+                    execution,
+                    found: program.run(execution),
+                    program,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+// Generates the functions of a program.
+struct Gen {
+    random: Random,
+    config: GenConfig,
+}
+
+impl Gen {
+    fn below(&self, n: usize) -> usize {
+        self.random.below(n as u64) as usize
+    }
+
+    fn coin(&self) -> bool {
+        self.below(2) == 0
+    }
+
+    fn function(&self, depth: usize) -> IrFunction {
+        let len = 1 + self.below(self.config.max_len.max(1));
+        // The handlers begin here, and contain at least the final `Return`.
+        let handlers = self.below(len);
+        let mut literals = vec![];
+        let mut code = Vec::with_capacity(len);
+        for i in 0..len - 1 {
+            let kind = if i < handlers {
+                self.below(10)
+            } else {
+                [0, 1, 2, 5, 6, 8, 9][self.below(7)]
+            };
+            code.push(match kind {
+                0 => Instruction::Write { src: self.addr(), dst: self.addr(), moved: false },
+                1 | 2 => {
+                    literals.push(self.literal(depth));
+                    Instruction::Literal(literals.len() - 1, self.addr())
+                }
+                3 | 4 => Instruction::Apply {
+                    fun: self.addr(),
+                    num_args: self.below(self.config.storage_size.min(3) + 1),
+                    dst: self.addr(),
+                },
+                5 => Instruction::CondJump(self.addr(), self.target(i, len)),
+                6 => Instruction::Jump(self.target(i, len)),
+                7 if self.below(4) == 0 => {
+                    let target = handlers + self.below(len - handlers);
+                    Instruction::CatchExit { target, dst: self.addr() }
+                }
+                7 => {
+                    let target = handlers + self.below(len - handlers);
+                    Instruction::Catch { target, dst: self.addr() }
+                }
+                8 if self.coin() => Instruction::ThrowFlag,
+                8 => Instruction::ClearThrowFlag,
+                _ if self.coin() => Instruction::Throw(self.addr()),
+                _ => Instruction::Return(self.addr()),
+            });
+        }
+        code.push(Instruction::Return(self.addr()));
+
+        IrFunction {
+            args: 0,
+            storage_size: self.config.storage_size,
+            env_size: self.config.env_size,
+            code: code.into(),
+            literals: literals.into(),
+            names: BTreeMap::new(),
+            params: BTreeMap::new(),
+        }
+    }
+
+    // A jump target after the instruction at `i`, but within the code.
+    fn target(&self, i: usize, len: usize) -> usize {
+        i + 1 + self.below(len - 1 - i)
+    }
+
+    fn addr(&self) -> Addr {
+        if self.config.env_size > 0 && self.below(3) == 0 {
+            Addr::Environment(DeBruijnPair::new(0, self.below(self.config.env_size)))
+        } else {
+            Addr::Storage(self.below(self.config.storage_size))
+        }
+    }
+
+    fn literal(&self, depth: usize) -> IrLiteral {
+        match self.below(4) {
+            0 => IrLiteral::Array((0..self.below(3)).map(|_| self.scalar()).collect()),
+            1 if depth < self.config.max_depth => {
+                IrLiteral::Fun(Rc::new(self.function(depth + 1)), 0)
+            }
+            _ => self.scalar(),
+        }
+    }
+
+    fn scalar(&self) -> IrLiteral {
+        match self.below(7) {
+            0 => IrLiteral::Nil,
+            1 => IrLiteral::Bool(self.coin()),
+            2 => IrLiteral::Int(self.below(7) as i64 - 3),
+            3 => IrLiteral::Float(OrderedFloat(self.below(4) as f64 / 2.0)),
+            4 => IrLiteral::Char(['a', 'z'][self.below(2)]),
+            _ => IrLiteral::String(Rope::from_str(["", "a", "pan"][self.below(3)])),
+        }
+    }
+}
+
+// Programs that once behaved wrongly, with their right outcomes.
+fn regressions() -> Vec<(Program, Outcome)> {
+    // A caught value used to be written to storage slot 0, where the handler expects the value
+    // that was staged there before the throwing call.
+    let thrower = IrFunction {
+        args: 0,
+        storage_size: 1,
+        env_size: 0,
+        code: vec![
+            Instruction::Literal(0, Addr::Storage(0)),
+            Instruction::Throw(Addr::Storage(0)),
+        ]
+        .into(),
+        literals: vec![IrLiteral::Int(1)].into(),
+        names: BTreeMap::new(),
+        params: BTreeMap::new(),
+    };
+    let staged = IrFunction {
+        args: 0,
+        storage_size: 4,
+        env_size: 0,
+        code: vec![
+            Instruction::Literal(0, Addr::Storage(0)),
+            Instruction::Literal(1, Addr::Storage(1)),
+            Instruction::Catch { target: 5, dst: Addr::Storage(2) },
+            Instruction::Apply { fun: Addr::Storage(1), num_args: 0, dst: Addr::Storage(3) },
+            Instruction::Return(Addr::Storage(3)),
+            Instruction::Return(Addr::Storage(0)),
+        ]
+        .into(),
+        literals: vec![IrLiteral::Int(7), IrLiteral::Fun(Rc::new(thrower), 0)].into(),
+        names: BTreeMap::new(),
+        params: BTreeMap::new(),
+    };
+    vec![(Program { fun: Rc::new(staged) }, Outcome::Returned("7".to_string()))]
+}
+
+// Replace the instructions of the program (but not those of the functions it creates) with
+// no-ops, one by one, keeping each replacement after which the program still fails.
+fn shrink(program: &Program, fails: impl Fn(&Program) -> bool) -> Program {
+    let mut current = program.clone();
+    for i in 0..current.fun.code.len() - 1 {
+        if current.fun.code[i] == Instruction::Jump(i + 1) {
+            continue;
+        }
+        let mut code = current.fun.code.to_vec();
+        code[i] = Instruction::Jump(i + 1);
+        let candidate = Program {
+            fun: Rc::new(IrFunction { code: code.into(), ..(*current.fun).clone() }),
+        };
+        if fails(&candidate) {
+            current = candidate;
+        }
+    }
+    current
+}
+
+fn disassemble(fun: &IrFunction, name: &str, out: &mut String) {
+    let _ = writeln!(
+        out,
+        "{}: {} storage slots, {} bindings",
+        name, fun.storage_size, fun.env_size,
+    );
+    for (i, instruction) in fun.code.iter().enumerate() {
+        let _ = writeln!(out, "  {:4}  {:?}", i, instruction);
+    }
+    for (i, literal) in fun.literals.iter().enumerate() {
+        let _ = match literal {
+            IrLiteral::Fun(_, entry) => {
+                writeln!(out, "  literal {}: {}.{} from {}", i, name, i, entry)
+            }
+            other => writeln!(out, "  literal {}: {:?}", i, other),
+        };
+    }
+    for (i, literal) in fun.literals.iter().enumerate() {
+        if let IrLiteral::Fun(fun, _) = literal {
+            disassemble(fun, &format!("{}.{}", name, i), out);
+        }
+    }
+}
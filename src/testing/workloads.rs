@@ -0,0 +1,243 @@
+// Ir programs for benchmarking the interpreter, see `benches/interpreter.rs`.
+//
+// Nothing compiles pan source code to ir yet, so the programs are written by hand, the way a
+// compiler would write them: arguments are staged in the storage slots from 0 on, and values pass
+// through temporary slots. A workload takes the builtins it calls as arguments, followed by the
+// number of iterations, so that it runs in a fresh runtime without a global environment.
+
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use ordered_float::OrderedFloat;
+
+use crate::ir::{Addr, DeBruijnPair, Instruction, IrClosure, IrFunction, IrLiteral};
+use crate::runtime::Runtime;
+use crate::testing::ir_gen::Execution;
+use crate::types::bytes::Bytes;
+use crate::types::rope::Rope;
+use crate::value::{Fun, Value};
+
+/// A benchmark program, see the module documentation.
+pub struct Workload {
+    /// A short name, for reports.
+    pub name: &'static str,
+    fun: Rc<IrFunction>,
+    // The builtins the program takes as its first arguments.
+    builtins: &'static [&'static str],
+}
+
+impl Workload {
+    /// A runtime with the program, ready to run it for `n` iterations in the given execution.
+    pub fn instance(&self, execution: Execution, n: i64) -> Instance {
+        let runtime = Runtime::new();
+        let fun = Value::Fun(Fun::Pan(IrClosure::top_level(execution.prepare(&self.fun), 0)));
+        let mut args: Vec<Value> = self
+            .builtins
+            .iter()
+            .map(|name| runtime.global(name).expect("workloads only call builtins"))
+            .collect();
+        args.push(Value::Int(n));
+        Instance { runtime, fun, args }
+    }
+}
+
+/// A workload in a runtime, see `Workload::instance`.
+pub struct Instance {
+    runtime: Runtime,
+    fun: Value,
+    args: Vec<Value>,
+}
+
+impl Instance {
+    /// Run the program once.
+    pub fn run(&mut self) -> Result<Value, Value> {
+        self.runtime.call_raw(&self.fun, &self.args)
+    }
+
+    /// The runtime the program runs in, e.g. to add watchpoints.
+    pub fn runtime(&mut self) -> &mut Runtime {
+        &mut self.runtime
+    }
+}
+
+/// All workloads.
+pub fn workloads() -> Vec<Workload> {
+    vec![numeric_loop(), call_heavy(), array_building(), literal_heavy()]
+}
+
+/// Sums the numbers from 0 to `n` (exclusive).
+pub fn numeric_loop() -> Workload {
+    let (add, lt, n) = (env(0), env(1), env(2));
+    let code = vec![
+        Instruction::Literal(0, slot(3)),
+        Instruction::Literal(0, slot(4)),
+        // while i < n
+        write(slot(4), slot(0)),
+        write(n, slot(1)),
+        Instruction::Apply { fun: lt, num_args: 2, dst: slot(2) },
+        Instruction::CondJump(slot(2), 7),
+        Instruction::Return(slot(3)),
+        // sum = add(sum, i)
+        write(slot(3), slot(0)),
+        write(slot(4), slot(1)),
+        Instruction::Apply { fun: add.clone(), num_args: 2, dst: slot(3) },
+        // i = add(i, 1)
+        write(slot(4), slot(0)),
+        Instruction::Literal(1, slot(1)),
+        Instruction::Apply { fun: add, num_args: 2, dst: slot(4) },
+        Instruction::Jump(2),
+    ];
+    Workload {
+        name: "numeric-loop",
+        fun: function(3, 5, code, vec![IrLiteral::Int(0), IrLiteral::Int(1)]),
+        builtins: &["add", "lt"],
+    }
+}
+
+/// Counts to `n` by calling a pan function that adds one to its argument.
+pub fn call_heavy() -> Workload {
+    let (lt, n) = (env(1), env(2));
+    // |x| add(x, 1), where `add` is the first binding of the enclosing environment
+    let increment = function(
+        1,
+        2,
+        vec![
+            write(env(0), slot(0)),
+            Instruction::Literal(0, slot(1)),
+            Instruction::Apply {
+                fun: Addr::Environment(DeBruijnPair::new(1, 0)),
+                num_args: 2,
+                dst: slot(0),
+            },
+            Instruction::Return(slot(0)),
+        ],
+        vec![IrLiteral::Int(1)],
+    );
+    let code = vec![
+        Instruction::Literal(1, slot(4)),
+        Instruction::Literal(0, slot(3)),
+        // while i < n
+        write(slot(3), slot(0)),
+        write(n, slot(1)),
+        Instruction::Apply { fun: lt, num_args: 2, dst: slot(2) },
+        Instruction::CondJump(slot(2), 7),
+        Instruction::Return(slot(3)),
+        // i = increment(i)
+        write(slot(3), slot(0)),
+        Instruction::Apply { fun: slot(4), num_args: 1, dst: slot(3) },
+        Instruction::Jump(2),
+    ];
+    Workload {
+        name: "call-heavy",
+        fun: function(3, 5, code, vec![IrLiteral::Int(0), IrLiteral::Fun(increment, 0)]),
+        builtins: &["add", "lt"],
+    }
+}
+
+/// Creates an array for every number `i` from 0 to `n` (exclusive), pushes `i` to it twice, and
+/// returns the last one, or nil if `n` is not positive. The array passes through a temporary slot
+/// on its way to `push`. Arrays stay short because every push roots all elements of the array
+/// (see `GcCell::borrow_mut`), which would dominate the time otherwise.
+pub fn array_building() -> Workload {
+    let (push, lt, add, n) = (env(0), env(1), env(2), env(3));
+    let code = vec![
+        Instruction::Literal(1, slot(4)),
+        // while i < n
+        write(slot(4), slot(0)),
+        write(n, slot(1)),
+        Instruction::Apply { fun: lt, num_args: 2, dst: slot(2) },
+        Instruction::CondJump(slot(2), 6),
+        Instruction::Return(slot(3)),
+        // arr = [], push(arr, i), push(arr, i)
+        Instruction::Literal(0, slot(3)),
+        write(slot(3), slot(5)),
+        write(slot(5), slot(0)),
+        write(slot(4), slot(1)),
+        Instruction::Apply { fun: push.clone(), num_args: 2, dst: slot(2) },
+        write(slot(3), slot(0)),
+        write(slot(4), slot(1)),
+        Instruction::Apply { fun: push, num_args: 2, dst: slot(2) },
+        // i = add(i, 1)
+        write(slot(4), slot(0)),
+        Instruction::Literal(2, slot(1)),
+        Instruction::Apply { fun: add, num_args: 2, dst: slot(4) },
+        Instruction::Jump(1),
+    ];
+    let literals = vec![IrLiteral::Array(vec![]), IrLiteral::Int(0), IrLiteral::Int(1)];
+    Workload {
+        name: "array-building",
+        fun: function(4, 6, code, literals),
+        builtins: &["push", "lt", "add"],
+    }
+}
+
+/// Executes a string, a bytes, a float and an array literal on each of `n` iterations, and returns
+/// the last array, or nil if `n` is not positive.
+pub fn literal_heavy() -> Workload {
+    let (lt, add, n) = (env(0), env(1), env(2));
+    let code = vec![
+        Instruction::Literal(0, slot(3)),
+        // while i < n
+        write(slot(3), slot(0)),
+        write(n, slot(1)),
+        Instruction::Apply { fun: lt, num_args: 2, dst: slot(2) },
+        Instruction::CondJump(slot(2), 6),
+        Instruction::Return(slot(7)),
+        Instruction::Literal(2, slot(4)),
+        Instruction::Literal(3, slot(5)),
+        Instruction::Literal(4, slot(6)),
+        Instruction::Literal(5, slot(7)),
+        // i = add(i, 1)
+        write(slot(3), slot(0)),
+        Instruction::Literal(1, slot(1)),
+        Instruction::Apply { fun: add, num_args: 2, dst: slot(3) },
+        Instruction::Jump(1),
+    ];
+    let text = "a string literal that is long enough to not be trivial to copy";
+    let literals = vec![
+        IrLiteral::Int(0),
+        IrLiteral::Int(1),
+        IrLiteral::String(Rope::from_str(text)),
+        IrLiteral::Bytes(Bytes::from_slice(text.as_bytes())),
+        IrLiteral::Float(OrderedFloat(0.5)),
+        IrLiteral::Array(vec![
+            IrLiteral::String(Rope::from_str(text)),
+            IrLiteral::Int(1),
+            IrLiteral::Int(2),
+        ]),
+    ];
+    Workload {
+        name: "literal-heavy",
+        fun: function(3, 8, code, literals),
+        builtins: &["lt", "add"],
+    }
+}
+
+fn function(
+    args: usize,
+    storage_size: usize,
+    code: Vec<Instruction>,
+    literals: Vec<IrLiteral>,
+) -> Rc<IrFunction> {
+    Rc::new(IrFunction {
+        args,
+        storage_size,
+        env_size: args,
+        code: code.into(),
+        literals: literals.into(),
+        names: BTreeMap::new(),
+        params: BTreeMap::new(),
+    })
+}
+
+fn slot(index: usize) -> Addr {
+    Addr::Storage(index)
+}
+
+fn env(index: usize) -> Addr {
+    Addr::Environment(DeBruijnPair::new(0, index))
+}
+
+fn write(src: Addr, dst: Addr) -> Instruction {
+    Instruction::Write { src, dst, moved: false }
+}
@@ -1,3 +1,25 @@
 pub mod bytes;
+pub mod collection;
+pub mod exit;
+pub mod heap;
+pub mod interrupt;
+pub mod native_panic;
+pub mod owner;
+pub mod persistent;
+#[cfg(feature = "nan-boxing")]
+pub mod packed;
+pub mod pan_error;
+pub mod rooted;
 pub mod rope;
+pub mod throw_observer;
+pub mod watch;
 pub mod futures;
+pub mod channel;
+pub mod stream;
+pub mod iter;
+pub mod userdata;
+pub mod random;
+#[cfg(feature = "secure-random")]
+pub mod entropy;
+#[cfg(feature = "regex")]
+pub mod regex;
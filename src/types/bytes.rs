@@ -1,21 +1,1364 @@
 // The internal representation of pan bytes.
+//
+// Like ropes, bytes are an immutable binary tree whose leaves are views into shared buffers.
+// Cloning only bumps a reference count, slicing and concatenating share the untouched parts of
+// the inputs. Small byte strings are a single leaf and don't pay for the tree. Which
+// representation a value has is never observable, `compact` produces a single leaf on demand.
+//
+// Bytes have value semantics: mutation is copy-on-write. A buffer is only ever written to while
+// a single leaf covering all of it holds the only reference to it. Otherwise the viewed bytes are
+// copied into a fresh buffer first, so writing through one slice never affects any other slice,
+// clone or concatenation that shares the original buffer.
 
+use std::cmp::{min, Ordering};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
 use std::rc::Rc;
-use std::cell::RefCell;
 
+use failure_derive::Fail;
 use gc_derive::{Trace, Finalize};
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Trace, Finalize)]
-pub struct Bytes {
-    #[unsafe_ignore_trace]
-    data: Rc<RefCell<[u8]>>,
-    start: usize, // inclusive
-    end: usize, // exclusive
-    // invariant: start and end are always < data.len()
+use crate::types::rope::{self, Rope, RopeBuilder};
+
+// Concatenations resulting in at most this many bytes are flattened into a single leaf.
+const FLATTEN_SIZE: usize = 256;
+
+// If a concatenation results in a tree deeper than this, the tree is rebalanced.
+const MAX_DEPTH: usize = 64;
+
+// Byte strings longer than this are debug-printed in a compact, truncated form.
+const DEBUG_LEN: usize = 32;
+
+// The number of bytes shown by the compact debug form.
+const DEBUG_PREVIEW_LEN: usize = 16;
+
+#[derive(Clone, Trace, Finalize)]
+pub struct Bytes(#[unsafe_ignore_trace] Rc<Node>);
+
+enum Node {
+    // The bytes `data[start..end]`.
+    Leaf {
+        data: Rc<[u8]>,
+        start: usize,
+        end: usize,
+    },
+    // The concatenation of two byte strings.
+    Concat {
+        left: Bytes,
+        right: Bytes,
+        len: usize,
+        depth: usize,
+    },
+}
+
+/// Everything that can go wrong when working with bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Fail)]
+pub enum BytesError {
+    #[fail(display = "index {} is out of bounds for bytes of length {}", index, len)]
+    OutOfBounds { index: usize, len: usize },
+    #[fail(display = "range start {} is greater than range end {}", start, end)]
+    InvertedRange { start: usize, end: usize },
+    #[fail(display = "the separator must not be empty")]
+    EmptyPattern,
+    #[fail(display = "integer width must be between 1 and 8 bytes, got {}", width)]
+    InvalidWidth { width: usize },
+    #[fail(display = "{} does not fit into {} bytes", value, width)]
+    IntOutOfRange { value: i64, width: usize },
+}
+
+/// Everything that can go wrong when decoding bytes from a textual representation.
+#[derive(Debug, Clone, PartialEq, Eq, Fail)]
+pub enum DecodeError {
+    #[fail(display = "hex input of odd length {}", len)]
+    OddLength { len: usize },
+    #[fail(display = "base64 input of length {} is not a multiple of four", len)]
+    InvalidLength { len: usize },
+    #[fail(display = "invalid character at byte offset {}", offset)]
+    InvalidChar { offset: usize },
+    #[fail(display = "invalid padding at byte offset {}", offset)]
+    InvalidPadding { offset: usize },
+    #[fail(display = "invalid utf-8 at byte offset {}", offset)]
+    InvalidUtf8 { offset: usize },
+}
+
+/// How `Bytes::decode_utf8` deals with bytes that are not valid utf-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Utf8Mode {
+    /// Fail at the first invalid or incomplete sequence.
+    Strict,
+    /// Replace each maximal invalid sequence (and an incomplete sequence at the end) with
+    /// U+FFFD, like `String::from_utf8_lossy`.
+    Replace,
+    /// Fail at the first invalid sequence, but treat an incomplete sequence at the end as the
+    /// start of a char whose remaining bytes have not arrived yet, and return it undecoded.
+    Prefix,
 }
 
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
 impl Bytes {
+    /// Create an empty byte string.
+    pub fn empty() -> Bytes {
+        Bytes::from_vec(vec![])
+    }
+
+    /// Create a byte string holding a copy of the given bytes.
     pub fn from_slice(b: &[u8]) -> Bytes {
-        unimplemented!()
+        Bytes::from_vec(b.to_vec())
+    }
+
+    /// Create a byte string taking ownership of the given bytes.
+    pub fn from_vec(v: Vec<u8>) -> Bytes {
+        Bytes::leaf(Rc::from(v.into_boxed_slice()))
+    }
+
+    fn leaf(data: Rc<[u8]>) -> Bytes {
+        let end = data.len();
+        Bytes(Rc::new(Node::Leaf { data, start: 0, end }))
+    }
+
+    /// The number of bytes.
+    pub fn len(&self) -> usize {
+        match *self.0 {
+            Node::Leaf { start, end, .. } => end - start,
+            Node::Concat { len, .. } => len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn depth(&self) -> usize {
+        match *self.0 {
+            Node::Leaf { .. } => 0,
+            Node::Concat { depth, .. } => depth,
+        }
+    }
+
+    /// Returns whether both byte strings share the same root, i.e. whether one is a cheap clone
+    /// of the other.
+    pub fn ptr_eq(a: &Bytes, b: &Bytes) -> bool {
+        Rc::ptr_eq(&a.0, &b.0)
+    }
+
+    /// The byte at the given index, or `None` if the index is out of bounds.
+    pub fn get(&self, index: usize) -> Option<u8> {
+        if index >= self.len() {
+            return None;
+        }
+
+        let mut bytes = self;
+        let mut index = index;
+        loop {
+            match *bytes.0 {
+                Node::Leaf { ref data, start, .. } => return Some(data[start + index]),
+                Node::Concat { ref left, ref right, .. } => {
+                    if index < left.len() {
+                        bytes = left;
+                    } else {
+                        index -= left.len();
+                        bytes = right;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns whether mutating these bytes would need to copy them first, because the buffer
+    /// is shared with other values, only partially viewed, or split into several chunks.
+    pub fn is_shared(&self) -> bool {
+        match *self.0 {
+            Node::Leaf { ref data, start, end } => {
+                Rc::strong_count(&self.0) > 1 || Rc::strong_count(data) > 1 ||
+                    start != 0 || end != data.len()
+            }
+            Node::Concat { .. } => true,
+        }
+    }
+
+    /// Ensure that `self` is the sole owner of a contiguous buffer, copying the bytes if
+    /// necessary. Afterwards, mutations happen in place.
+    pub fn make_unique(&mut self) {
+        if self.is_shared() {
+            *self = Bytes::from_vec(self.to_vec());
+        }
+    }
+
+    // Mutable access to the bytes, copying them first if they are shared.
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.make_unique();
+        match Rc::get_mut(&mut self.0) {
+            Some(Node::Leaf { data, .. }) => Rc::get_mut(data).unwrap(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Set the byte at the given index, copying the bytes first if they are shared (see
+    /// `is_shared`). Other values sharing the old buffer are not affected.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn set(&mut self, index: usize, byte: u8) {
+        let len = self.len();
+        assert!(index < len, "index {} is out of bounds for bytes of length {}", index, len);
+        self.as_mut_slice()[index] = byte;
+    }
+
+    /// The byte at the given index.
+    pub fn get_checked(&self, index: usize) -> Result<u8, BytesError> {
+        self.get(index).ok_or(BytesError::OutOfBounds { index, len: self.len() })
+    }
+
+    /// Set the byte at the given index, copying the bytes first if they are shared.
+    pub fn set_checked(&mut self, index: usize, byte: u8) -> Result<(), BytesError> {
+        self.check_index(index)?;
+        self.as_mut_slice()[index] = byte;
+        Ok(())
+    }
+
+    /// Set all bytes in the given range to `byte`, copying the bytes first if they are shared.
+    pub fn fill(&mut self, range: Range<usize>, byte: u8) -> Result<(), BytesError> {
+        self.check_range(&range)?;
+        if range.start < range.end {
+            for b in &mut self.as_mut_slice()[range] {
+                *b = byte;
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy the bytes in the range `src` to the position starting at `dest`. The ranges may
+    /// overlap. Copies the bytes first if they are shared.
+    pub fn copy_within(&mut self, src: Range<usize>, dest: usize) -> Result<(), BytesError> {
+        self.check_range(&src)?;
+        let dest_end = dest.saturating_add(src.end - src.start);
+        if dest_end > self.len() {
+            return Err(BytesError::OutOfBounds { index: dest_end, len: self.len() });
+        }
+        if src.start < src.end && src.start != dest {
+            self.as_mut_slice().copy_within(src, dest);
+        }
+        Ok(())
+    }
+
+    fn check_index(&self, index: usize) -> Result<(), BytesError> {
+        if index < self.len() {
+            Ok(())
+        } else {
+            Err(BytesError::OutOfBounds { index, len: self.len() })
+        }
+    }
+
+    fn check_range(&self, range: &Range<usize>) -> Result<(), BytesError> {
+        if range.start > range.end {
+            Err(BytesError::InvertedRange { start: range.start, end: range.end })
+        } else if range.end > self.len() {
+            Err(BytesError::OutOfBounds { index: range.end, len: self.len() })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Iterate over the contiguous chunks that make up these bytes, from left to right. Never
+    /// yields empty chunks.
+    pub fn chunks(&self) -> Chunks<'_> {
+        Chunks {
+            next: Some(self),
+            stack: vec![],
+        }
+    }
+
+    /// Iterate over the contiguous chunks that make up these bytes, from right to left. Never
+    /// yields empty chunks.
+    pub fn chunks_rev(&self) -> ChunksRev<'_> {
+        ChunksRev {
+            next: Some(self),
+            stack: vec![],
+        }
+    }
+
+    /// Iterate over the bytes, from left to right.
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        self.chunks().flat_map(|chunk| chunk.iter().copied())
+    }
+
+    /// Call `f` with a borrow of the bytes, returning its result. If the bytes are not stored
+    /// contiguously, `f` receives a temporary copy; use `compact` to avoid repeated copies.
+    pub fn as_slice_with<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
+        match *self.0 {
+            Node::Leaf { ref data, start, end } => f(&data[start..end]),
+            Node::Concat { .. } => f(&self.to_vec()),
+        }
+    }
+
+    /// Copy the bytes into a fresh vector.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut v = Vec::with_capacity(self.len());
+        for chunk in self.chunks() {
+            v.extend_from_slice(chunk);
+        }
+        v
+    }
+
+    /// Return the same bytes stored as a single contiguous chunk. Takes constant time if that
+    /// is already the case.
+    pub fn compact(&self) -> Bytes {
+        match *self.0 {
+            Node::Leaf { .. } => self.clone(),
+            Node::Concat { .. } => Bytes::from_vec(self.to_vec()),
+        }
+    }
+
+    /// Return the bytes of `self` followed by the bytes of `other`. This shares both inputs
+    /// (unless they are small enough to be copied cheaply) and takes `O(log(n))` time.
+    pub fn concat(&self, other: &Bytes) -> Bytes {
+        if other.is_empty() {
+            return self.clone();
+        }
+        if self.is_empty() {
+            return other.clone();
+        }
+
+        if self.len() + other.len() <= FLATTEN_SIZE {
+            let mut v = Vec::with_capacity(self.len() + other.len());
+            for chunk in self.chunks().chain(other.chunks()) {
+                v.extend_from_slice(chunk);
+            }
+            return Bytes::from_vec(v);
+        }
+
+        let joined = if self.depth() > other.depth() + 1 {
+            Bytes::join_right(self, other)
+        } else if other.depth() > self.depth() + 1 {
+            Bytes::join_left(self, other)
+        } else {
+            Bytes::join(self.clone(), other.clone())
+        };
+        // The joins keep trees of balanced inputs balanced, this only catches trees that were
+        // put together differently.
+        if joined.depth() > MAX_DEPTH {
+            joined.rebalance()
+        } else {
+            joined
+        }
+    }
+
+    // Join a tree with a shallower one by descending the right spine of the deeper tree and
+    // rotating on the way back up, as in the join of AVL trees. Takes time proportional to the
+    // difference in depth, and the result is at most one level deeper than `left`.
+    fn join_right(left: &Bytes, right: &Bytes) -> Bytes {
+        let (l, c) = match left.children() {
+            Some(children) => children,
+            None => return Bytes::join(left.clone(), right.clone()),
+        };
+        if c.depth() <= right.depth() + 1 {
+            let joined = Bytes::join(c.clone(), right.clone());
+            if joined.depth() <= l.depth() + 1 {
+                Bytes::join(l.clone(), joined)
+            } else {
+                Bytes::join(l.clone(), joined.rotate_right()).rotate_left()
+            }
+        } else {
+            let joined = Bytes::join_right(c, right);
+            let depth = joined.depth();
+            let node = Bytes::join(l.clone(), joined);
+            if depth <= l.depth() + 1 {
+                node
+            } else {
+                node.rotate_left()
+            }
+        }
+    }
+
+    // The mirror image of `join_right`, for a `right` tree that is deeper than `left`.
+    fn join_left(left: &Bytes, right: &Bytes) -> Bytes {
+        let (c, r) = match right.children() {
+            Some(children) => children,
+            None => return Bytes::join(left.clone(), right.clone()),
+        };
+        if c.depth() <= left.depth() + 1 {
+            let joined = Bytes::join(left.clone(), c.clone());
+            if joined.depth() <= r.depth() + 1 {
+                Bytes::join(joined, r.clone())
+            } else {
+                Bytes::join(joined.rotate_left(), r.clone()).rotate_right()
+            }
+        } else {
+            let joined = Bytes::join_left(left, c);
+            let depth = joined.depth();
+            let node = Bytes::join(joined, r.clone());
+            if depth <= r.depth() + 1 {
+                node
+            } else {
+                node.rotate_right()
+            }
+        }
+    }
+
+    fn children(&self) -> Option<(&Bytes, &Bytes)> {
+        match *self.0 {
+            Node::Leaf { .. } => None,
+            Node::Concat { ref left, ref right, .. } => Some((left, right)),
+        }
+    }
+
+    // Turn `a (b c)` into `(a b) c`, or return `self` if its right child is a leaf.
+    fn rotate_left(&self) -> Bytes {
+        match self.children() {
+            Some((a, bc)) => match bc.children() {
+                Some((b, c)) => Bytes::join(Bytes::join(a.clone(), b.clone()), c.clone()),
+                None => self.clone(),
+            },
+            None => self.clone(),
+        }
+    }
+
+    // Turn `(a b) c` into `a (b c)`, or return `self` if its left child is a leaf.
+    fn rotate_right(&self) -> Bytes {
+        match self.children() {
+            Some((ab, c)) => match ab.children() {
+                Some((a, b)) => Bytes::join(a.clone(), Bytes::join(b.clone(), c.clone())),
+                None => self.clone(),
+            },
+            None => self.clone(),
+        }
+    }
+
+    // Create a concatenation node, without flattening or rebalancing.
+    fn join(left: Bytes, right: Bytes) -> Bytes {
+        let len = left.len() + right.len();
+        let depth = 1 + std::cmp::max(left.depth(), right.depth());
+        Bytes(Rc::new(Node::Concat { left, right, len, depth }))
+    }
+
+    // Build a balanced tree over the given pieces, in order.
+    fn join_balanced(pieces: &[Bytes]) -> Bytes {
+        match pieces.len() {
+            0 => Bytes::empty(),
+            1 => pieces[0].clone(),
+            n => {
+                let (left, right) = pieces.split_at(n / 2);
+                Bytes::join(Bytes::join_balanced(left), Bytes::join_balanced(right))
+            }
+        }
+    }
+
+    // Rebuild the tree so that it is balanced, sharing all leaves.
+    fn rebalance(&self) -> Bytes {
+        let mut leaves = vec![];
+        self.collect_leaves(&mut leaves);
+        Bytes::join_balanced(&leaves)
+    }
+
+    fn collect_leaves(&self, leaves: &mut Vec<Bytes>) {
+        match *self.0 {
+            Node::Leaf { .. } => {
+                if !self.is_empty() {
+                    leaves.push(self.clone());
+                }
+            }
+            Node::Concat { ref left, ref right, .. } => {
+                left.collect_leaves(leaves);
+                right.collect_leaves(leaves);
+            }
+        }
+    }
+
+    /// Return the bytes in the given range. This shares all structure with `self` and takes
+    /// `O(log(n))` time (constant time for contiguous bytes).
+    pub fn slice(&self, range: Range<usize>) -> Result<Bytes, BytesError> {
+        self.check_range(&range)?;
+        Ok(self.slice_unchecked(range.start, range.end))
+    }
+
+    /// Return the bytes from the given index to the end.
+    pub fn slice_from(&self, start: usize) -> Result<Bytes, BytesError> {
+        if start > self.len() {
+            return Err(BytesError::OutOfBounds { index: start, len: self.len() });
+        }
+        self.slice(start..self.len())
+    }
+
+    /// Return the bytes from the start up to (excluding) the given index.
+    pub fn slice_to(&self, end: usize) -> Result<Bytes, BytesError> {
+        self.slice(0..end)
+    }
+
+    fn slice_unchecked(&self, from: usize, to: usize) -> Bytes {
+        if from == 0 && to == self.len() {
+            return self.clone();
+        }
+
+        match *self.0 {
+            Node::Leaf { ref data, start, .. } => Bytes(Rc::new(Node::Leaf {
+                data: data.clone(),
+                start: start + from,
+                end: start + to,
+            })),
+            Node::Concat { ref left, ref right, .. } => {
+                let mid = left.len();
+                if to <= mid {
+                    left.slice_unchecked(from, to)
+                } else if from >= mid {
+                    right.slice_unchecked(from - mid, to - mid)
+                } else {
+                    left.slice_unchecked(from, mid).concat(&right.slice_unchecked(0, to - mid))
+                }
+            }
+        }
+    }
+}
+
+// Searching.
+impl Bytes {
+    /// Returns the index of the first occurrence of `needle` that starts at or after `from`, if
+    /// any. An empty needle is found at `from`. Occurrences may span any number of chunks.
+    pub fn find(&self, needle: &[u8], from: usize) -> Option<usize> {
+        if from > self.len() {
+            return None;
+        }
+        if needle.is_empty() {
+            return Some(from);
+        }
+
+        // The last `needle.len() - 1` bytes searched so far, which may be the start of a match
+        // continuing in the next chunk.
+        let mut carry: Vec<u8> = Vec::with_capacity(needle.len());
+        // The offset of the first byte of `carry` (relative to `from`).
+        let mut carry_start = 0;
+        // The offset of the current chunk (relative to `from`).
+        let mut offset = 0;
+
+        for chunk in self.slice_unchecked(from, self.len()).chunks() {
+            // Look for matches beginning in the carry.
+            if !carry.is_empty() {
+                let carried = carry.len();
+                carry.extend_from_slice(&chunk[..min(chunk.len(), needle.len() - 1)]);
+                if let Some(i) = find_bytes(&carry, needle) {
+                    if i < carried {
+                        return Some(from + carry_start + i);
+                    }
+                }
+                carry.truncate(carried);
+            }
+
+            // Look for matches beginning in the chunk.
+            if let Some(i) = find_bytes(chunk, needle) {
+                return Some(from + offset + i);
+            }
+
+            // Update the carry to the last `needle.len() - 1` bytes searched.
+            carry.extend_from_slice(chunk);
+            let excess = carry.len().saturating_sub(needle.len() - 1);
+            carry.drain(..excess);
+            carry_start = offset + chunk.len() - carry.len();
+            offset += chunk.len();
+        }
+
+        None
+    }
+
+    /// Returns the index of the last occurrence of `needle` that ends at or before `to`, if
+    /// any. An empty needle is found at `to`. This mirrors `find`, walking the chunks from right
+    /// to left.
+    pub fn rfind(&self, needle: &[u8], to: usize) -> Option<usize> {
+        if to > self.len() {
+            return None;
+        }
+        if needle.is_empty() {
+            return Some(to);
+        }
+
+        // The first `needle.len() - 1` bytes searched so far, which may be the end of a match
+        // beginning in the next chunk.
+        let mut carry: Vec<u8> = Vec::with_capacity(needle.len());
+        // The offset one past the end of the current chunk.
+        let mut end = to;
+
+        for chunk in self.slice_unchecked(0, to).chunks_rev() {
+            let start = end - chunk.len();
+
+            // Look for matches ending in the carry.
+            if !carry.is_empty() {
+                let tail = &chunk[chunk.len() - min(chunk.len(), needle.len() - 1)..];
+                let mut window = Vec::with_capacity(tail.len() + carry.len());
+                window.extend_from_slice(tail);
+                window.extend_from_slice(&carry);
+                if let Some(i) = rfind_bytes(&window, needle) {
+                    if i + needle.len() > tail.len() {
+                        return Some(end - tail.len() + i);
+                    }
+                }
+            }
+
+            // Look for matches ending in the chunk.
+            if let Some(i) = rfind_bytes(chunk, needle) {
+                return Some(start + i);
+            }
+
+            // Update the carry to the first `needle.len() - 1` bytes searched.
+            let mut new_carry = Vec::with_capacity(needle.len());
+            new_carry.extend_from_slice(chunk);
+            new_carry.extend_from_slice(&carry);
+            new_carry.truncate(needle.len() - 1);
+            carry = new_carry;
+            end = start;
+        }
+
+        None
+    }
+
+    /// Returns whether the bytes begin with `prefix`.
+    pub fn starts_with(&self, prefix: &[u8]) -> bool {
+        let mut rest = prefix;
+        for chunk in self.chunks() {
+            if rest.is_empty() {
+                break;
+            }
+            let n = min(chunk.len(), rest.len());
+            if chunk[..n] != rest[..n] {
+                return false;
+            }
+            rest = &rest[n..];
+        }
+        rest.is_empty()
+    }
+
+    /// Returns whether the bytes end with `suffix`.
+    pub fn ends_with(&self, suffix: &[u8]) -> bool {
+        suffix.len() <= self.len() &&
+            self.slice_unchecked(self.len() - suffix.len(), self.len()).starts_with(suffix)
+    }
+
+    /// Iterate over the sub-slices separated by `sep`, sharing the buffers of `self`. Like
+    /// `str::split`, this yields one more slice than there are occurrences of `sep`.
+    pub fn split<'a>(&'a self, sep: &'a [u8]) -> Result<Split<'a>, BytesError> {
+        if sep.is_empty() {
+            return Err(BytesError::EmptyPattern);
+        }
+        Ok(Split { bytes: self, sep, position: Some(0) })
+    }
+}
+
+// Return the index of the first occurrence of `needle` in `haystack`, if any.
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.len() > haystack.len() {
+        return None;
+    }
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+// Return the index of the last occurrence of `needle` in `haystack`, if any.
+fn rfind_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.len() > haystack.len() {
+        return None;
+    }
+    haystack.windows(needle.len()).rposition(|window| window == needle)
+}
+
+// Generates little- and big-endian read and write methods for a number type.
+macro_rules! endian_accessors {
+    ($t:ty, $read_le:ident, $read_be:ident, $write_le:ident, $write_be:ident) => {
+        #[doc = concat!("Read a little-endian `", stringify!($t), "` at the given byte offset.")]
+        pub fn $read_le(&self, offset: usize) -> Result<$t, BytesError> {
+            self.read_array(offset).map(<$t>::from_le_bytes)
+        }
+
+        #[doc = concat!("Read a big-endian `", stringify!($t), "` at the given byte offset.")]
+        pub fn $read_be(&self, offset: usize) -> Result<$t, BytesError> {
+            self.read_array(offset).map(<$t>::from_be_bytes)
+        }
+
+        #[doc = concat!("Write a little-endian `", stringify!($t), "` at the given byte offset, ",
+            "copying the bytes first if they are shared.")]
+        pub fn $write_le(&mut self, offset: usize, value: $t) -> Result<(), BytesError> {
+            self.write_array(offset, value.to_le_bytes())
+        }
+
+        #[doc = concat!("Write a big-endian `", stringify!($t), "` at the given byte offset, ",
+            "copying the bytes first if they are shared.")]
+        pub fn $write_be(&mut self, offset: usize, value: $t) -> Result<(), BytesError> {
+            self.write_array(offset, value.to_be_bytes())
+        }
+    };
+}
+
+// Reading and writing binary numbers.
+impl Bytes {
+    /// Read the byte at the given offset.
+    pub fn read_u8(&self, offset: usize) -> Result<u8, BytesError> {
+        self.get_checked(offset)
+    }
+
+    /// Write the byte at the given offset, copying the bytes first if they are shared.
+    pub fn write_u8(&mut self, offset: usize, value: u8) -> Result<(), BytesError> {
+        self.set_checked(offset, value)
+    }
+
+    endian_accessors!(u16, read_u16_le, read_u16_be, write_u16_le, write_u16_be);
+    endian_accessors!(u32, read_u32_le, read_u32_be, write_u32_le, write_u32_be);
+    endian_accessors!(u64, read_u64_le, read_u64_be, write_u64_le, write_u64_be);
+    endian_accessors!(i64, read_i64_le, read_i64_be, write_i64_le, write_i64_be);
+    endian_accessors!(f64, read_f64_le, read_f64_be, write_f64_le, write_f64_be);
+
+    /// Encode an integer as `width` little-endian bytes. The integer must be representable as
+    /// either a signed or an unsigned integer of that width.
+    pub fn from_int_le(value: i64, width: usize) -> Result<Bytes, BytesError> {
+        check_int_width(value, width)?;
+        Ok(Bytes::from_slice(&value.to_le_bytes()[..width]))
+    }
+
+    /// Encode an integer as `width` big-endian bytes. The integer must be representable as
+    /// either a signed or an unsigned integer of that width.
+    pub fn from_int_be(value: i64, width: usize) -> Result<Bytes, BytesError> {
+        check_int_width(value, width)?;
+        Ok(Bytes::from_slice(&value.to_be_bytes()[8 - width..]))
+    }
+
+    // Read `N` bytes starting at `offset`, which may span several chunks.
+    fn read_array<const N: usize>(&self, offset: usize) -> Result<[u8; N], BytesError> {
+        let end = offset.saturating_add(N);
+        self.check_range(&(offset..end))?;
+
+        let mut array = [0; N];
+        if let Node::Leaf { ref data, start, .. } = *self.0 {
+            array.copy_from_slice(&data[start + offset..start + end]);
+        } else {
+            let mut filled = 0;
+            for chunk in self.slice_unchecked(offset, end).chunks() {
+                array[filled..filled + chunk.len()].copy_from_slice(chunk);
+                filled += chunk.len();
+            }
+        }
+        Ok(array)
+    }
+
+    fn write_array<const N: usize>(&mut self, offset: usize, array: [u8; N])
+        -> Result<(), BytesError> {
+        let end = offset.saturating_add(N);
+        self.check_range(&(offset..end))?;
+        self.as_mut_slice()[offset..end].copy_from_slice(&array);
+        Ok(())
+    }
+}
+
+fn check_int_width(value: i64, width: usize) -> Result<(), BytesError> {
+    if width == 0 || width > 8 {
+        return Err(BytesError::InvalidWidth { width });
+    }
+    if width < 8 {
+        let bits = 8 * width as u32;
+        let min = -(1i64 << (bits - 1));
+        let max = (1i64 << bits) - 1;
+        if value < min || value > max {
+            return Err(BytesError::IntOutOfRange { value, width });
+        }
+    }
+    Ok(())
+}
+
+// Encodings as text.
+impl Bytes {
+    /// Encode as lowercase hexadecimal digits, two per byte.
+    pub fn to_hex(&self) -> Rope {
+        let mut builder = RopeBuilder::new();
+        for chunk in self.chunks() {
+            for &b in chunk {
+                builder.push_char(HEX_DIGITS[(b >> 4) as usize] as char);
+                builder.push_char(HEX_DIGITS[(b & 0xf) as usize] as char);
+            }
+        }
+        builder.finish()
+    }
+
+    /// Decode a string of hexadecimal digits (upper or lower case), two per byte.
+    pub fn from_hex(s: &str) -> Result<Bytes, DecodeError> {
+        let s = s.as_bytes();
+        if !s.len().is_multiple_of(2) {
+            return Err(DecodeError::OddLength { len: s.len() });
+        }
+
+        let mut v = Vec::with_capacity(s.len() / 2);
+        for (i, pair) in s.chunks(2).enumerate() {
+            let hi = hex_value(pair[0]).ok_or(DecodeError::InvalidChar { offset: 2 * i })?;
+            let lo = hex_value(pair[1]).ok_or(DecodeError::InvalidChar { offset: 2 * i + 1 })?;
+            v.push(hi << 4 | lo);
+        }
+        Ok(Bytes::from_vec(v))
+    }
+
+    /// Encode as base64 with the standard alphabet and padding (RFC 4648, section 4).
+    pub fn to_base64(&self) -> Rope {
+        let mut builder = RopeBuilder::new();
+        let mut group = [0u8; 3];
+        let mut n = 0;
+        for chunk in self.chunks() {
+            for &b in chunk {
+                group[n] = b;
+                n += 1;
+                if n == 3 {
+                    push_base64_group(&mut builder, group, 3);
+                    n = 0;
+                }
+            }
+        }
+        if n > 0 {
+            group[n..].iter_mut().for_each(|b| *b = 0);
+            push_base64_group(&mut builder, group, n);
+        }
+        builder.finish()
+    }
+
+    /// Decode base64 with the standard alphabet (RFC 4648, section 4).
+    ///
+    /// Decoding is strict: the input must be padded to a multiple of four characters, may not
+    /// contain whitespace or characters outside the alphabet, and the unused bits of the final
+    /// group must be zero. Hence every byte string has exactly one accepted encoding, the one
+    /// produced by `to_base64`.
+    pub fn from_base64(s: &str) -> Result<Bytes, DecodeError> {
+        let s = s.as_bytes();
+        if !s.len().is_multiple_of(4) {
+            return Err(DecodeError::InvalidLength { len: s.len() });
+        }
+
+        let mut v = Vec::with_capacity(s.len() / 4 * 3);
+        for (i, quad) in s.chunks(4).enumerate() {
+            let offset = 4 * i;
+            let last = offset + 4 == s.len();
+
+            let mut group = 0u32;
+            let mut padding = 0;
+            for (j, &c) in quad.iter().enumerate() {
+                if c == b'=' {
+                    if !last || j < 2 {
+                        return Err(DecodeError::InvalidPadding { offset: offset + j });
+                    }
+                    padding += 1;
+                } else if padding > 0 {
+                    return Err(DecodeError::InvalidPadding { offset: offset + j - 1 });
+                } else {
+                    let value = base64_value(c)
+                        .ok_or(DecodeError::InvalidChar { offset: offset + j })?;
+                    group |= u32::from(value) << (18 - 6 * j);
+                }
+            }
+
+            // Reject nonzero bits in the last char before the padding.
+            if group & (0xffffff >> (24 - 8 * padding)) != 0 {
+                return Err(DecodeError::InvalidChar { offset: offset + 3 - padding });
+            }
+
+            v.extend_from_slice(&group.to_be_bytes()[1..4 - padding]);
+        }
+        Ok(Bytes::from_vec(v))
+    }
+}
+
+// Conversions from and to text.
+impl Bytes {
+    /// Decode the bytes as utf-8, handling invalid data according to `mode`. Returns the decoded
+    /// text and the bytes left undecoded, which are always empty unless `mode` is
+    /// `Utf8Mode::Prefix`. In that mode, prepending the undecoded bytes to the next input
+    /// reassembles chars that were split across inputs.
+    pub fn decode_utf8(&self, mode: Utf8Mode) -> Result<(Rope, Bytes), DecodeError> {
+        let mut builder = RopeBuilder::new();
+        // An incomplete char at the end of the chunks decoded so far.
+        let mut carry: Vec<u8> = Vec::with_capacity(4);
+        // The offset of the first byte that has not been decoded (the start of `carry`).
+        let mut offset = 0;
+
+        for mut chunk in self.chunks() {
+            // Complete the carried char with bytes from this chunk. Chars are at most four
+            // bytes long, so after this, the carried char is either complete or invalid, or
+            // the chunk has been used up.
+            if !carry.is_empty() {
+                let carried = carry.len();
+                let taken = min(chunk.len(), 4 - carried);
+                carry.extend_from_slice(&chunk[..taken]);
+                let (valid, incomplete) = match std::str::from_utf8(&carry) {
+                    Ok(_) => (carry.len(), false),
+                    Err(e) => (e.valid_up_to(), e.error_len().is_none()),
+                };
+
+                if valid > 0 {
+                    builder.push_str(std::str::from_utf8(&carry[..valid]).unwrap());
+                    offset += valid;
+                    chunk = &chunk[valid - carried..];
+                } else if incomplete {
+                    continue;
+                } else {
+                    // The carried bytes are an invalid sequence, the chunk is not part of it.
+                    if mode != Utf8Mode::Replace {
+                        return Err(DecodeError::InvalidUtf8 { offset });
+                    }
+                    builder.push_char(char::REPLACEMENT_CHARACTER);
+                    offset += carried;
+                }
+                carry.clear();
+            }
+
+            loop {
+                match std::str::from_utf8(chunk) {
+                    Ok(text) => {
+                        builder.push_str(text);
+                        offset += chunk.len();
+                        break;
+                    }
+                    Err(e) => {
+                        let valid = e.valid_up_to();
+                        builder.push_str(std::str::from_utf8(&chunk[..valid]).unwrap());
+                        offset += valid;
+
+                        match e.error_len() {
+                            Some(invalid) => {
+                                if mode != Utf8Mode::Replace {
+                                    return Err(DecodeError::InvalidUtf8 { offset });
+                                }
+                                builder.push_char(char::REPLACEMENT_CHARACTER);
+                                offset += invalid;
+                                chunk = &chunk[valid + invalid..];
+                            }
+                            None => {
+                                carry.extend_from_slice(&chunk[valid..]);
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut rest = Bytes::empty();
+        if !carry.is_empty() {
+            match mode {
+                Utf8Mode::Strict => return Err(DecodeError::InvalidUtf8 { offset }),
+                Utf8Mode::Replace => builder.push_char(char::REPLACEMENT_CHARACTER),
+                Utf8Mode::Prefix => rest = self.slice_unchecked(offset, self.len()),
+            }
+        }
+        Ok((builder.finish(), rest))
+    }
+
+    /// The utf-8 encoding of the given text.
+    pub fn encode_utf8_of(text: &Rope) -> Bytes {
+        let mut builder = BytesBuilder::with_capacity(text.len_bytes());
+        for chunk in text.chunks() {
+            builder.extend_from_slice(chunk.as_bytes());
+        }
+        builder.finish()
+    }
+}
+
+impl Bytes {
+    /// Render the first `max_len` bytes in the classic hexdump layout: the offset, sixteen
+    /// bytes in hex, and the same bytes as ascii (with a `.` for unprintable bytes) per line.
+    /// If bytes were omitted, a line saying how many follows. The last line is the total length.
+    pub fn hexdump(&self, max_len: usize) -> String {
+        use std::fmt::Write;
+
+        let shown = min(self.len(), max_len);
+        let mut out = String::new();
+        let mut bytes = self.iter().take(shown);
+        let mut line = Vec::with_capacity(16);
+
+        for offset in (0..shown).step_by(16) {
+            line.clear();
+            line.extend(bytes.by_ref().take(16));
+
+            write!(out, "{:08x} ", offset).unwrap();
+            for i in 0..16 {
+                if i % 8 == 0 {
+                    out.push(' ');
+                }
+                match line.get(i) {
+                    Some(b) => write!(out, "{:02x} ", b).unwrap(),
+                    None => out.push_str("   "),
+                }
+            }
+            out.push_str(" |");
+            for &b in &line {
+                out.push(if (0x20..0x7f).contains(&b) { b as char } else { '.' });
+            }
+            out.push_str("|\n");
+        }
+
+        if shown < self.len() {
+            writeln!(out, "... ({} more bytes)", self.len() - shown).unwrap();
+        }
+        writeln!(out, "{:08x}", self.len()).unwrap();
+        out
+    }
+}
+
+fn hex_value(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+fn base64_value(c: u8) -> Option<u8> {
+    match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+// Push the encoding of the first `n` bytes of `group` (whose remaining bytes are zero).
+fn push_base64_group(builder: &mut RopeBuilder, group: [u8; 3], n: usize) {
+    let bits = u32::from(group[0]) << 16 | u32::from(group[1]) << 8 | u32::from(group[2]);
+    for i in 0..4 {
+        if i <= n {
+            let index = (bits >> (18 - 6 * i)) & 0x3f;
+            builder.push_char(BASE64_ALPHABET[index as usize] as char);
+        } else {
+            builder.push_char('=');
+        }
+    }
+}
+
+impl Default for Bytes {
+    fn default() -> Bytes {
+        Bytes::empty()
+    }
+}
+
+// Short byte strings are printed as escaped literals, long ones as a hex preview followed by the
+// length, e.g. `b"de ad be ef…" (1024 bytes)`. The alternate flag (`{:#?}`) prints a full
+// hexdump instead.
+impl fmt::Debug for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            return f.write_str(&self.hexdump(usize::MAX));
+        }
+
+        f.write_str("b\"")?;
+        if self.len() <= DEBUG_LEN {
+            for b in self.iter() {
+                write!(f, "{}", std::ascii::escape_default(b))?;
+            }
+            f.write_str("\"")
+        } else {
+            for (i, b) in self.iter().take(DEBUG_PREVIEW_LEN).enumerate() {
+                if i > 0 {
+                    f.write_str(" ")?;
+                }
+                write!(f, "{:02x}", b)?;
+            }
+            write!(f, "…\" ({} bytes)", self.len())
+        }
+    }
+}
+
+impl PartialEq for Bytes {
+    fn eq(&self, other: &Bytes) -> bool {
+        self.len() == other.len() && self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Bytes {}
+
+impl PartialOrd for Bytes {
+    fn partial_cmp(&self, other: &Bytes) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Hashes only depend on the viewed bytes, consistent with equality, also for hashers that are not
+// streaming, see `impl Hash for Rope`.
+impl Hash for Bytes {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        rope::hash_blocks(self.len(), self.chunks(), state);
+    }
+}
+
+// Lexicographic comparison of the viewed bytes, independent of the buffers they live in and of
+// how they are chunked.
+impl Ord for Bytes {
+    fn cmp(&self, other: &Bytes) -> Ordering {
+        if Bytes::ptr_eq(self, other) {
+            return Ordering::Equal;
+        }
+
+        let mut chunks_a = self.chunks();
+        let mut chunks_b = other.chunks();
+        let mut a: &[u8] = &[];
+        let mut b: &[u8] = &[];
+
+        loop {
+            if a.is_empty() {
+                a = chunks_a.next().unwrap_or(&[]);
+            }
+            if b.is_empty() {
+                b = chunks_b.next().unwrap_or(&[]);
+            }
+
+            match (a.is_empty(), b.is_empty()) {
+                (true, true) => return Ordering::Equal,
+                (true, false) => return Ordering::Less,
+                (false, true) => return Ordering::Greater,
+                (false, false) => {}
+            }
+
+            let n = min(a.len(), b.len());
+            match a[..n].cmp(&b[..n]) {
+                Ordering::Equal => {
+                    a = &a[n..];
+                    b = &b[n..];
+                }
+                unequal => return unequal,
+            }
+        }
+    }
+}
+
+/// An iterator over the chunks of a byte string, see `Bytes::chunks`.
+pub struct Chunks<'a> {
+    // The next node to descend into. Kept out of the stack so that iterating over a single leaf
+    // does not allocate.
+    next: Option<&'a Bytes>,
+    // The nodes to the right of `next`, the rightmost one at the bottom.
+    stack: Vec<&'a Bytes>,
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        loop {
+            let bytes = match self.next.take() {
+                Some(bytes) => bytes,
+                None => self.stack.pop()?,
+            };
+
+            match *bytes.0 {
+                Node::Leaf { ref data, start, end } => {
+                    if start < end {
+                        return Some(&data[start..end]);
+                    }
+                }
+                Node::Concat { ref left, ref right, .. } => {
+                    self.stack.push(right);
+                    self.next = Some(left);
+                }
+            }
+        }
+    }
+}
+
+/// An iterator over the chunks of a byte string in reverse order, see `Bytes::chunks_rev`.
+pub struct ChunksRev<'a> {
+    next: Option<&'a Bytes>,
+    // The nodes to the left of `next`, the leftmost one at the bottom.
+    stack: Vec<&'a Bytes>,
+}
+
+impl<'a> Iterator for ChunksRev<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        loop {
+            let bytes = match self.next.take() {
+                Some(bytes) => bytes,
+                None => self.stack.pop()?,
+            };
+
+            match *bytes.0 {
+                Node::Leaf { ref data, start, end } => {
+                    if start < end {
+                        return Some(&data[start..end]);
+                    }
+                }
+                Node::Concat { ref left, ref right, .. } => {
+                    self.stack.push(left);
+                    self.next = Some(right);
+                }
+            }
+        }
+    }
+}
+
+/// An iterator over the parts of a byte string separated by a separator, see `Bytes::split`.
+pub struct Split<'a> {
+    bytes: &'a Bytes,
+    sep: &'a [u8],
+    // Where the next part begins, or `None` once the last part has been yielded.
+    position: Option<usize>,
+}
+
+impl<'a> Iterator for Split<'a> {
+    type Item = Bytes;
+
+    fn next(&mut self) -> Option<Bytes> {
+        let start = self.position?;
+        match self.bytes.find(self.sep, start) {
+            Some(end) => {
+                self.position = Some(end + self.sep.len());
+                Some(self.bytes.slice_unchecked(start, end))
+            }
+            None => {
+                self.position = None;
+                Some(self.bytes.slice_unchecked(start, self.bytes.len()))
+            }
+        }
+    }
+}
+
+// Generates little- and big-endian methods for appending a number type to a builder.
+macro_rules! builder_endian_writers {
+    ($t:ty, $write_le:ident, $write_be:ident) => {
+        #[doc = concat!("Append a little-endian `", stringify!($t), "`.")]
+        pub fn $write_le(&mut self, value: $t) {
+            self.extend_from_slice(&value.to_le_bytes());
+        }
+
+        #[doc = concat!("Append a big-endian `", stringify!($t), "`.")]
+        pub fn $write_be(&mut self, value: $t) {
+            self.extend_from_slice(&value.to_be_bytes());
+        }
+    };
+}
+
+/// Incrementally builds a byte string. Small pieces are collected into a contiguous buffer,
+/// large byte strings are incorporated by reference rather than copied.
+pub struct BytesBuilder {
+    // Completed pieces of the byte string, in order.
+    pieces: Vec<Bytes>,
+    // Bytes that have not been turned into a piece yet.
+    buf: Vec<u8>,
+}
+
+impl BytesBuilder {
+    pub fn new() -> BytesBuilder {
+        BytesBuilder::with_capacity(0)
+    }
+
+    /// Create a builder that can hold `capacity` bytes before reallocating.
+    pub fn with_capacity(capacity: usize) -> BytesBuilder {
+        BytesBuilder {
+            pieces: vec![],
+            buf: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// The number of bytes pushed so far.
+    pub fn len(&self) -> usize {
+        self.pieces.iter().map(Bytes::len).sum::<usize>() + self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn push(&mut self, byte: u8) {
+        self.buf.push(byte);
+    }
+
+    pub fn extend_from_slice(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Append a byte string. Byte strings that are not tiny are shared rather than copied.
+    pub fn push_bytes(&mut self, bytes: &Bytes) {
+        if bytes.len() <= FLATTEN_SIZE {
+            for chunk in bytes.chunks() {
+                self.buf.extend_from_slice(chunk);
+            }
+        } else {
+            self.flush();
+            self.pieces.push(bytes.clone());
+        }
+    }
+
+    builder_endian_writers!(u16, write_u16_le, write_u16_be);
+    builder_endian_writers!(u32, write_u32_le, write_u32_be);
+    builder_endian_writers!(u64, write_u64_le, write_u64_be);
+    builder_endian_writers!(i64, write_i64_le, write_i64_be);
+    builder_endian_writers!(f64, write_f64_le, write_f64_be);
+
+    // Turn the buffered bytes into a piece, keeping the buffer's allocation for reuse.
+    fn flush(&mut self) {
+        if !self.buf.is_empty() {
+            self.pieces.push(Bytes::leaf(Rc::from(&self.buf[..])));
+            self.buf.clear();
+        }
+    }
+
+    pub fn finish(mut self) -> Bytes {
+        if self.pieces.is_empty() {
+            return Bytes::leaf(Rc::from(&self.buf[..]));
+        }
+
+        self.flush();
+        let joined = Bytes::join_balanced(&self.pieces);
+        if joined.depth() > MAX_DEPTH {
+            joined.rebalance()
+        } else {
+            joined
+        }
+    }
+}
+
+impl Default for BytesBuilder {
+    fn default() -> BytesBuilder {
+        BytesBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fragments(n: usize) -> Vec<Bytes> {
+        (0..n).map(|i| Bytes::from_vec(vec![i as u8; FLATTEN_SIZE + 1])).collect()
+    }
+
+    // Checks the cached lengths and depths, returns the depth.
+    fn check(bytes: &Bytes) -> usize {
+        match bytes.children() {
+            None => 0,
+            Some((left, right)) => {
+                let depth = 1 + std::cmp::max(check(left), check(right));
+                assert_eq!(bytes.depth(), depth);
+                assert_eq!(bytes.len(), left.len() + right.len());
+                depth
+            }
+        }
+    }
+
+    #[test]
+    fn concatenation_keeps_trees_shallow() {
+        let pieces = fragments(10_000);
+        let expected: Vec<u8> = pieces.iter().flat_map(Bytes::to_vec).collect();
+        // An AVL tree with 10_000 leaves is at most 19 levels deep.
+        let appended = pieces.iter().fold(Bytes::empty(), |acc, piece| acc.concat(piece));
+        assert!(check(&appended) <= 19, "depth {}", appended.depth());
+        assert_eq!(appended.to_vec(), expected);
+        let prepended = pieces.iter().rev().fold(Bytes::empty(), |acc, piece| piece.concat(&acc));
+        assert!(check(&prepended) <= 19, "depth {}", prepended.depth());
+        assert_eq!(prepended.to_vec(), expected);
+
+        let halves = pieces[..5_000]
+            .iter()
+            .fold(Bytes::empty(), |acc, piece| acc.concat(piece))
+            .concat(&pieces[5_000..].iter().fold(Bytes::empty(), |acc, piece| acc.concat(piece)));
+        assert!(check(&halves) <= 19, "depth {}", halves.depth());
+        assert_eq!(halves.to_vec(), expected);
+
+        // A deep tree joined with a single leaf, from both sides.
+        let small = Bytes::from_vec(vec![7; FLATTEN_SIZE + 1]);
+        let depth = appended.depth();
+        assert!(appended.concat(&small).depth() <= depth + 1);
+        assert!(small.concat(&appended).depth() <= depth + 1);
+        check(&small.concat(&appended));
     }
 }
@@ -0,0 +1,216 @@
+// Channels pass values between pan futures that run on the same event loop.
+//
+// A channel buffers up to `capacity` values. Sending and receiving happen through futures: a
+// send future resolves once its value has been accepted (by a waiting receiver or by the buffer),
+// a receive future resolves with the next value. Futures that can not complete right away wait in
+// a queue and are served strictly in the order in which they were run. Waiting futures that have
+// been cancelled in the meantime are skipped.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use gc::{Gc, GcCell};
+use gc_derive::{Trace, Finalize};
+
+use crate::types::futures::{Job, LifecycleState};
+use crate::value::Value;
+
+/// A single-threaded channel. Clones refer to the same channel.
+#[derive(Clone, Trace, Finalize)]
+pub struct Channel(Gc<GcCell<ChannelState>>);
+
+#[derive(Trace, Finalize)]
+struct ChannelState {
+    #[unsafe_ignore_trace]
+    capacity: usize,
+    buffer: VecDeque<Value>,
+    // Send futures waiting for space, with the values they send.
+    senders: VecDeque<(Job, Value)>,
+    // Receive futures waiting for a value.
+    receivers: VecDeque<Job>,
+    #[unsafe_ignore_trace]
+    closed: bool,
+}
+
+impl ChannelState {
+    fn pop_sender(&mut self) -> Option<(Job, Value)> {
+        while let Some((job, v)) = self.senders.pop_front() {
+            if job.state() == LifecycleState::Running {
+                return Some((job, v));
+            }
+        }
+        None
+    }
+
+    fn pop_receiver(&mut self) -> Option<Job> {
+        while let Some(job) = self.receivers.pop_front() {
+            if job.state() == LifecycleState::Running {
+                return Some(job);
+            }
+        }
+        None
+    }
+}
+
+// The jobs to settle once the channel has been released.
+type Settlements = Vec<(Job, Result<Value, Value>)>;
+
+impl Channel {
+    /// A channel that buffers up to `capacity` values. With a capacity of zero, a send only
+    /// completes once a receiver takes the value.
+    pub fn new(capacity: usize) -> Channel {
+        Channel(Gc::new(GcCell::new(ChannelState {
+            capacity,
+            buffer: VecDeque::new(),
+            senders: VecDeque::new(),
+            receivers: VecDeque::new(),
+            closed: false,
+        })))
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.0.borrow().capacity
+    }
+
+    /// The number of buffered values.
+    pub fn len(&self) -> usize {
+        self.0.borrow().buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.0.borrow().closed
+    }
+
+    /// Close the channel. Waiting send and receive futures reject with an error of kind
+    /// `"closed"`, as do all later send futures. Buffered values can still be received, once they
+    /// are gone, receive futures reject as well. Returns whether the channel was open.
+    pub fn close(&self) -> bool {
+        let settlements = {
+            let mut state = self.0.borrow_mut();
+            if state.closed {
+                return false;
+            }
+            state.closed = true;
+
+            let mut settlements: Settlements = vec![];
+            while let Some((job, _)) = state.pop_sender() {
+                settlements.push((job, Err(closed())));
+            }
+            while let Some(job) = state.pop_receiver() {
+                settlements.push((job, Err(closed())));
+            }
+            settlements
+        };
+        settle(settlements);
+        true
+    }
+
+    /// Returns whether both values refer to the same channel.
+    pub fn ptr_eq(a: &Channel, b: &Channel) -> bool {
+        Gc::ptr_eq(&a.0, &b.0)
+    }
+
+    // Called when a send future is run, settles its job once the value has been accepted.
+    pub(crate) fn send(&self, job: Job, v: Value) {
+        let settlements = {
+            let mut state = self.0.borrow_mut();
+            if state.closed {
+                vec![(job, Err(closed()))]
+            } else if let Some(receiver) = state.pop_receiver() {
+                vec![(receiver, Ok(v)), (job, Ok(Value::Nil))]
+            } else if state.buffer.len() < state.capacity {
+                state.buffer.push_back(v);
+                vec![(job, Ok(Value::Nil))]
+            } else {
+                state.senders.push_back((job, v));
+                vec![]
+            }
+        };
+        settle(settlements);
+    }
+
+    // Called when a receive future is run, settles its job once there is a value.
+    pub(crate) fn recv(&self, job: Job) {
+        let settlements = {
+            let mut state = self.0.borrow_mut();
+            if let Some(v) = state.buffer.pop_front() {
+                let mut settlements = vec![(job, Ok(v))];
+                // Make room for the oldest waiting sender.
+                if let Some((sender, v)) = state.pop_sender() {
+                    state.buffer.push_back(v);
+                    settlements.push((sender, Ok(Value::Nil)));
+                }
+                settlements
+            } else if let Some((sender, v)) = state.pop_sender() {
+                vec![(sender, Ok(Value::Nil)), (job, Ok(v))]
+            } else if state.closed {
+                vec![(job, Err(closed()))]
+            } else {
+                state.receivers.push_back(job);
+                vec![]
+            }
+        };
+        settle(settlements);
+    }
+
+    fn address(&self) -> usize {
+        &*self.0 as *const GcCell<ChannelState> as usize
+    }
+}
+
+// Settling a job may run arbitrary code (the transition hook of the event loop), so this happens
+// only after the state of the channel has been released.
+fn settle(settlements: Settlements) {
+    for (job, outcome) in settlements {
+        job.settle(outcome);
+    }
+}
+
+// The value that futures on a closed channel reject with.
+fn closed() -> Value {
+    Value::error("closed", "the channel has been closed")
+}
+
+impl fmt::Debug for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.0.borrow();
+        write!(f, "Channel({}/{}", state.buffer.len(), state.capacity)?;
+        if state.closed {
+            f.write_str(", closed")?;
+        }
+        f.write_str(")")
+    }
+}
+
+// Channels are compared by identity.
+impl PartialEq for Channel {
+    fn eq(&self, other: &Channel) -> bool {
+        Channel::ptr_eq(self, other)
+    }
+}
+
+impl Eq for Channel {}
+
+impl PartialOrd for Channel {
+    fn partial_cmp(&self, other: &Channel) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Channel {
+    fn cmp(&self, other: &Channel) -> std::cmp::Ordering {
+        self.address().cmp(&other.address())
+    }
+}
+
+// Consistent with the comparison by identity.
+impl Hash for Channel {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.address().hash(state);
+    }
+}
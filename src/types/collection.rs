@@ -0,0 +1,195 @@
+// The shared, mutable contents of arrays, sets and maps.
+//
+// A `Collection` is a `GcCell` together with a flag that marks it as frozen. It dereferences to
+// the cell, so the contents are borrowed as usual. Freezing is advisory on the rust side: code
+// that mutates a collection on behalf of pan code must check `is_frozen` first (the builtins
+// throw an error of kind `"frozen"`), while the runtime itself may still fill in collections it
+// has just created.
+//
+// A frozen collection can also be sealed (by `Value::deep_freeze`), which records that nothing
+// it transitively contains can be mutated by pan code either. Sealed collections are immutable
+// values, so snapshots share them instead of copying them (see `Value::snapshot_copy`). Like
+// freezing, sealing can not be undone: mutating a sealed value requires a `thaw` or `deep_copy`
+// of it.
+//
+// Sealing overlaps with the structural sharing of sets and maps (see `types::persistent`), but
+// neither covers the other. Sharing makes copies of sets and maps of plain data cheap whether they
+// are frozen or not, but arrays are copied element by element, and nodes that contain collections
+// are never shared. Sealing lets snapshots keep deep frozen arrays and nested collections without
+// copying anything, and lets `deep_freeze` return immediately for values it has frozen before.
+//
+// Mutable borrows go through `Collection::borrow_mut`, which shadows the method of the cell: once
+// the borrow ends, the size of the contents is charged to the memory budget (see `types::heap`).
+
+use std::cell::Cell;
+use std::ops::{Deref, DerefMut};
+
+use gc::{GcCell, GcCellRefMut, Trace};
+use gc_derive::{Trace, Finalize};
+
+use crate::types::heap::{self, Charge, LiveCollection};
+use crate::types::owner::{self, RuntimeId};
+use crate::types::persistent::{PersistentMap, PersistentSet};
+use crate::value::Value;
+
+/// The contents of a collection, and whether it is frozen.
+#[derive(Debug, Trace, Finalize)]
+pub struct Collection<T: Trace + 'static> {
+    #[unsafe_ignore_trace]
+    frozen: Cell<bool>,
+    #[unsafe_ignore_trace]
+    sealed: Cell<bool>,
+    contents: GcCell<T>,
+    // Counts the collection in the heap statistics while it exists.
+    #[unsafe_ignore_trace]
+    _live: LiveCollection,
+    // The estimated size of the contents, charged to the memory budget.
+    #[unsafe_ignore_trace]
+    charge: Charge,
+    // The number of slots and the size of the payloads that have been charged.
+    #[unsafe_ignore_trace]
+    slots: Cell<usize>,
+    #[unsafe_ignore_trace]
+    payload: Cell<u64>,
+    // The runtime that created the collection, see `types::owner`.
+    #[unsafe_ignore_trace]
+    owner: RuntimeId,
+}
+
+/// What a collection can contain: arrays, sets and maps of values.
+pub trait Contents: Trace {
+    /// The number of values stored.
+    fn slots(&self) -> usize;
+
+    /// The size of the payloads of the values from the given index on, for contents that append
+    /// new values at the end.
+    fn appended(&self, _from: usize) -> u64 {
+        0
+    }
+}
+
+impl Contents for Vec<Value> {
+    fn slots(&self) -> usize {
+        self.len()
+    }
+
+    fn appended(&self, from: usize) -> u64 {
+        self.get(from..).map_or(0, |added| {
+            added.iter().map(|v| heap::element_size(v) - heap::slot_size()).sum()
+        })
+    }
+}
+
+impl Contents for PersistentSet<Value> {
+    fn slots(&self) -> usize {
+        self.len()
+    }
+}
+
+impl Contents for PersistentMap<Value, Value> {
+    fn slots(&self) -> usize {
+        self.len() * 2
+    }
+}
+
+impl<T: Trace + 'static> Collection<T> {
+    /// Whether the collection has been frozen.
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.get()
+    }
+
+    /// Mark the collection as frozen. Collections can not be unfrozen.
+    pub fn freeze(&self) {
+        self.frozen.set(true);
+    }
+
+    /// Whether the collection has been sealed, see the module documentation.
+    pub fn is_sealed(&self) -> bool {
+        self.sealed.get()
+    }
+
+    // Mark the collection as frozen and sealed. The caller guarantees that everything the
+    // collection contains is immutable.
+    pub(crate) fn seal(&self) {
+        self.frozen.set(true);
+        self.sealed.set(true);
+    }
+
+    /// The runtime that created the collection, see `types::owner`.
+    pub fn owner(&self) -> RuntimeId {
+        self.owner
+    }
+}
+
+impl<T: Contents + 'static> Collection<T> {
+    /// A new, unfrozen collection.
+    pub fn new(contents: T) -> Collection<T> {
+        let collection = Collection {
+            frozen: Cell::new(false),
+            sealed: Cell::new(false),
+            contents: GcCell::new(contents),
+            _live: LiveCollection::new(),
+            charge: Charge::new(0),
+            slots: Cell::new(0),
+            payload: Cell::new(0),
+            owner: owner::current(),
+        };
+        collection.account();
+        collection
+    }
+
+    /// Mutably borrow the contents, like `GcCell::borrow_mut`, and update the charge to the
+    /// memory budget once the borrow ends.
+    ///
+    /// Panics if the contents are currently borrowed.
+    pub fn borrow_mut(&self) -> CollectionRefMut<'_, T> {
+        CollectionRefMut { collection: self, contents: Some(self.contents.borrow_mut()) }
+    }
+
+    // Charge the current size of the contents.
+    fn account(&self) {
+        let contents = self.contents.borrow();
+        let slots = contents.slots();
+        if slots > self.slots.get() {
+            self.payload.set(self.payload.get() + contents.appended(self.slots.get()));
+        }
+        self.slots.set(slots);
+        self.charge.resize(slots as u64 * heap::slot_size() + self.payload.get());
+    }
+}
+
+impl<T: Trace + 'static> Deref for Collection<T> {
+    type Target = GcCell<T>;
+
+    fn deref(&self) -> &GcCell<T> {
+        &self.contents
+    }
+}
+
+/// A mutable borrow of the contents of a collection, see `Collection::borrow_mut`.
+pub struct CollectionRefMut<'a, T: Contents + 'static> {
+    collection: &'a Collection<T>,
+    // Always `Some` until dropped.
+    contents: Option<GcCellRefMut<'a, T>>,
+}
+
+impl<T: Contents + 'static> Deref for CollectionRefMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.contents.as_ref().expect("the borrow is alive")
+    }
+}
+
+impl<T: Contents + 'static> DerefMut for CollectionRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.contents.as_mut().expect("the borrow is alive")
+    }
+}
+
+impl<T: Contents + 'static> Drop for CollectionRefMut<'_, T> {
+    fn drop(&mut self) {
+        self.contents = None;
+        self.collection.account();
+    }
+}
@@ -0,0 +1,26 @@
+// Access to the entropy source of the operating system, for randomness that has to be
+// unpredictable (keys, tokens, identifiers). Unlike `types::random`, it can not be seeded, and it
+// does not affect (and is not affected by) the generators of the runtimes.
+//
+// On unix systems, the bytes are read from `/dev/urandom`. Other systems are not supported yet,
+// reading from them always fails.
+
+use std::io;
+
+/// Fill the buffer with bytes from the entropy source of the operating system.
+#[cfg(unix)]
+pub fn fill(buf: &mut [u8]) -> io::Result<()> {
+    use std::fs::File;
+    use std::io::Read;
+
+    File::open("/dev/urandom")?.read_exact(buf)
+}
+
+/// Fill the buffer with bytes from the entropy source of the operating system.
+#[cfg(not(unix))]
+pub fn fill(_buf: &mut [u8]) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "there is no supported entropy source on this platform",
+    ))
+}
@@ -0,0 +1,50 @@
+// Early termination of a program with an exit code.
+//
+// The `exit` builtin does not terminate the process. Instead, it throws an exit value: a userdata
+// holding the code. Unlike other thrown values, exit values pass through the catch regions of pan
+// functions (unless the region was entered with the `catch_exit` option), so they unwind all the
+// way to the embedder, through any rust functions in between. The embedder can then recognize the
+// value with `exit_code` and decide what to do with it, e.g. a command line runner would terminate
+// the process with that code.
+
+use crate::types::{heap, interrupt};
+use crate::types::userdata::{PanUserdata, Userdata};
+use crate::value::Value;
+
+/// The host value of an exit value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exit(pub i32);
+
+impl PanUserdata for Exit {
+    fn type_name(&self) -> &'static str {
+        "exit"
+    }
+}
+
+/// A new exit value with the given code, to be thrown.
+pub fn exit_value(code: i32) -> Value {
+    Value::Userdata(Userdata::new(Exit(code)))
+}
+
+/// The code of an exit value, or `None` if the value is not an exit value.
+pub fn exit_code(v: &Value) -> Option<i32> {
+    match v {
+        Value::Userdata(u) => u.with(|exit: &Exit| exit.0),
+        _ => None,
+    }
+}
+
+/// Returns whether the value is an exit value.
+pub fn is_exit(v: &Value) -> bool {
+    exit_code(v).is_some()
+}
+
+/// Returns whether the value is an exit value, an out-of-memory value (see `types::heap`), or an
+/// interrupted or deadline-exceeded value (see `types::interrupt`), which unwind to the embedder
+/// rather than being handled by pan code.
+pub fn unwinds(v: &Value) -> bool {
+    is_exit(v)
+        || heap::out_of_memory_of(v).is_some()
+        || interrupt::is_interrupted(v)
+        || interrupt::deadline_exceeded_of(v).is_some()
+}
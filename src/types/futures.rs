@@ -1,10 +1,31 @@
+// The internal representation of pan futures.
+//
+// Pan futures are lazy: a future does nothing until it is run, at which point it transitions
+// into the pending state and tells the event loop what to do about it (see `Run`). All changes
+// of the lifecycle state go through `PanFuture::transition_to`, which enforces the transitions
+// described in the README.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use failure_derive::Fail;
 use futures::future::LocalFutureObj;
 use gc::{Gc, GcCell};
+use gc_derive::{Trace, Finalize};
 
+use crate::types::channel::Channel;
 use crate::value::Value;
 
-pub struct Job;
+mod clock;
+pub use self::clock::{Clock, SystemClock, ManualClock};
+mod event_loop;
+pub use self::event_loop::{Drive, EventLoop, EventLoopBuilder, Job, WeakEventLoop};
+mod sched;
+pub use self::sched::SchedEvent;
 
+/// The states a pan future goes through, see the README for the full lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LifecycleState {
     Inert,
     Staged,
@@ -14,48 +35,404 @@ pub enum LifecycleState {
     Cancelled,
 }
 
-enum PanFuture {
-    Resolve(Resolve),
-    Reject(Reject),
-    Never(Never),
-    OnIdle(OnIdle),
+impl LifecycleState {
+    /// The name of the state as used in the README (`Running` is called *pending* there).
+    pub fn name(self) -> &'static str {
+        match self {
+            LifecycleState::Inert => "inert",
+            LifecycleState::Staged => "staged",
+            LifecycleState::Running => "pending",
+            LifecycleState::Resolved => "resolved",
+            LifecycleState::Rejected => "rejected",
+            LifecycleState::Cancelled => "cancelled",
+        }
+    }
+
+    /// Returns whether a future may move from this state to the given one.
+    pub fn can_transition_to(self, to: LifecycleState) -> bool {
+        use self::LifecycleState::*;
+
+        matches!(
+            (self, to),
+            (Inert, Staged) | (Inert, Running) | (Staged, Running) |
+            (Running, Resolved) | (Running, Rejected) | (Running, Cancelled)
+        )
+    }
+
+    /// Returns whether the state is resolved, rejected or cancelled.
+    pub fn is_done(self) -> bool {
+        use self::LifecycleState::*;
+
+        matches!(self, Resolved | Rejected | Cancelled)
+    }
 }
 
-// Possible states of a `fut_resolve` future.
-enum Resolve {
-    Inactive(Value),
-    Resolved,
+/// Everything that can go wrong when manipulating futures.
+#[derive(Debug, Clone, PartialEq, Eq, Fail)]
+pub enum FutureError {
+    #[fail(display = "a future can not transition from {:?} to {:?}", from, to)]
+    IllegalTransition { from: LifecycleState, to: LifecycleState },
 }
 
-// Possible states of a `fut_reject` future.
-enum Reject {
-    Inactive(Value),
-    Rejected,
+/// A pan future. Clones refer to the same future.
+#[derive(Clone, Trace, Finalize)]
+pub struct Future(Gc<GcCell<PanFuture>>);
+
+impl Future {
+    /// A future that resolves to `v` as soon as it is run.
+    pub fn resolve(v: Value) -> Future {
+        Future::new(Kind::Resolve(Some(v)))
+    }
+
+    /// A future that rejects with `v` as soon as it is run.
+    pub fn reject(v: Value) -> Future {
+        Future::new(Kind::Reject(Some(v)))
+    }
+
+    /// A future that never settles once it is run, it can only be cancelled. It does not keep
+    /// the event loop busy.
+    pub fn never() -> Future {
+        Future::new(Kind::Never)
+    }
+
+    /// A future that resolves to `nil` once the event loop it runs on has nothing else to do
+    /// (see `EventLoop::is_idle`). Futures of this kind that are waiting at the same time resolve
+    /// one per idle point, in the order in which they were run.
+    pub fn on_idle() -> Future {
+        Future::new(Kind::OnIdle)
+    }
+
+    /// A future that resolves to `nil` once `millis` milliseconds have passed on the clock of the
+    /// event loop it runs on. Durations of zero or less are over on the next turn of the loop.
+    pub fn sleep(millis: i64) -> Future {
+        Future::new(Kind::Sleep(millis))
+    }
+
+    /// A future that settles like `source`, except that a resolution value is passed to
+    /// `callback` and the future settles with its result instead. Running this runs `source`.
+    pub fn then(source: Future, callback: Value) -> Future {
+        Future::new(Kind::Then { source, callback: Some(callback), branch: Branch::Resolve })
+    }
+
+    /// A future that settles like `source`, except that a rejection value is passed to
+    /// `callback` and the future settles with its result instead. Values that unwind to the
+    /// embedder (exit, out-of-memory, interrupted and deadline-exceeded values, see
+    /// `types::exit::unwinds`) are not passed to the callback. Running this runs `source`.
+    pub fn map_err(source: Future, callback: Value) -> Future {
+        Future::new(Kind::Then { source, callback: Some(callback), branch: Branch::Reject })
+    }
+
+    /// A future that calls `callback` without arguments once `source` is done, whether it
+    /// resolved, rejected or was cancelled, and then settles like `source`. If the callback
+    /// throws, or returns a future that rejects, the future rejects with that value instead. If
+    /// the callback returns a future, settling waits for it. Running this runs `source`.
+    pub fn finally(source: Future, callback: Value) -> Future {
+        Future::new(Kind::Then { source, callback: Some(callback), branch: Branch::Finally })
+    }
+
+    /// A future for a scope: once run, it calls `body` with a function that runs a future as a
+    /// child of the scope (and returns it). The scope then settles like the future returned by
+    /// the body (or resolves to the returned value if it is not a future, or rejects if the body
+    /// throws). Once the scope is done, all children that are still pending are cancelled, before
+    /// the future of the scope itself settles. If a child rejects while nothing observes it, the
+    /// scope rejects with the same value.
+    pub fn scope(body: Value) -> Future {
+        Future::new(Kind::Scope(Some(body)))
+    }
+
+    /// A future that settles like `source` if it settles within `millis` milliseconds, and
+    /// otherwise rejects with an error of kind `"timeout"` and cancels `source`. If `source` is
+    /// done already when this is run, the future settles like it right away. If `source` settles
+    /// exactly at the deadline, it wins. Running this runs `source`.
+    pub fn timeout(source: Future, millis: i64) -> Future {
+        Future::new(Kind::Timeout(source, millis))
+    }
+
+    /// A future that resolves to an array of the values of all `inputs` once they have all
+    /// resolved, or rejects with the first rejection, cancelling the remaining inputs. Inputs
+    /// that are not futures count as resolved to themselves. Running this runs all inputs.
+    pub fn all(inputs: Vec<Value>) -> Future {
+        Future::new(Kind::Join(Some(inputs), JoinMode::All))
+    }
+
+    /// A future that settles like the first of the `inputs` to settle, cancelling the others.
+    /// Inputs that are not futures count as resolved to themselves. Rejects with an error of kind
+    /// `"empty"` if there are no inputs. Running this runs all inputs.
+    pub fn race(inputs: Vec<Value>) -> Future {
+        Future::new(Kind::Join(Some(inputs), JoinMode::Race))
+    }
+
+    /// A future that resolves like the first of the `inputs` to resolve, cancelling the others.
+    /// Rejects with an array of all rejection values (in input order) if all inputs reject, or
+    /// with an error of kind `"empty"` if there are no inputs. Inputs that are not futures count
+    /// as resolved to themselves. Running this runs all inputs.
+    pub fn any(inputs: Vec<Value>) -> Future {
+        Future::new(Kind::Join(Some(inputs), JoinMode::Any))
+    }
+
+    /// A future that settles like the given rust future, which is polled by the event loop
+    /// that this is run on.
+    pub fn from_rust(fut: LocalFutureObj<'static, Result<Value, Value>>) -> Future {
+        Future::new(Kind::Rust(RustFuture(Some(fut))))
+    }
+
+    /// A future that sends `v` over the channel once it is run, see `Channel`.
+    pub fn send(chan: Channel, v: Value) -> Future {
+        Future::new(Kind::Send(chan, Some(v)))
+    }
+
+    /// A future that receives a value from the channel once it is run, see `Channel`.
+    pub fn recv(chan: Channel) -> Future {
+        Future::new(Kind::Recv(chan))
+    }
+
+    fn new(kind: Kind) -> Future {
+        drop_deferred();
+        Future(Gc::new(GcCell::new(PanFuture {
+            state: LifecycleState::Inert,
+            kind,
+            job: None,
+        })))
+    }
+
+    pub fn state(&self) -> LifecycleState {
+        let fut = self.0.borrow();
+        match (&fut.state, &fut.job) {
+            // The event loop does not keep never futures alive, so they learn about being
+            // cancelled from their job.
+            (LifecycleState::Running, Some(job)) => job.state(),
+            (state, _) => *state,
+        }
+    }
+
+    // The state as recorded by the future itself, the event loop changes it via `transition_to`.
+    fn recorded_state(&self) -> LifecycleState {
+        self.0.borrow().state
+    }
+
+    /// The job of the event loop this future runs on, if it has been run.
+    pub fn job(&self) -> Option<Job> {
+        self.0.borrow().job.clone()
+    }
+
+    /// Cancel the future if it is pending, together with the futures it was composed from that
+    /// are not done yet. Returns whether the future was pending. Futures in any other state are
+    /// not affected.
+    pub fn cancel(&self) -> bool {
+        match self.job() {
+            Some(job) => job.cancel_future(self),
+            None => false,
+        }
+    }
+
+    /// Returns whether both values refer to the same future.
+    pub fn ptr_eq(a: &Future, b: &Future) -> bool {
+        Gc::ptr_eq(&a.0, &b.0)
+    }
+
+    fn transition_to(&self, to: LifecycleState) -> Result<(), FutureError> {
+        self.0.borrow_mut().transition_to(to)
+    }
+
+    // Called after transitioning into the pending state. Returns what the event loop needs to
+    // do, handing over any values the future holds.
+    fn take_run(&self) -> Run {
+        match &mut self.0.borrow_mut().kind {
+            Kind::Resolve(v) => Run::ResolveImmediately(v.take().unwrap_or(Value::Nil)),
+            Kind::Reject(v) => Run::RejectImmediately(v.take().unwrap_or(Value::Nil)),
+            Kind::Never => Run::Never,
+            Kind::OnIdle => Run::OnIdle,
+            Kind::Rust(fut) => match fut.0.take() {
+                Some(fut) => Run::SpawnOnEventLoop(fut),
+                None => Run::Never,
+            },
+            Kind::Sleep(millis) => Run::Sleep(*millis),
+            Kind::Then { source, callback, branch } => Run::Chain {
+                source: source.clone(),
+                callback: callback.take().unwrap_or(Value::Nil),
+                branch: *branch,
+            },
+            Kind::Timeout(source, millis) => Run::Timeout(source.clone(), *millis),
+            Kind::Join(inputs, mode) => Run::Join(inputs.take().unwrap_or_default(), *mode),
+            Kind::Send(chan, v) => Run::Send(chan.clone(), v.take().unwrap_or(Value::Nil)),
+            Kind::Recv(chan) => Run::Recv(chan.clone()),
+            Kind::Scope(body) => Run::Scope(body.take().unwrap_or(Value::Nil)),
+        }
+    }
+
+    // Remember the job that the event loop created for this future.
+    fn attach(&self, job: Job) {
+        self.0.borrow_mut().job = Some(job);
+    }
+
+    fn address(&self) -> usize {
+        &*self.0 as *const GcCell<PanFuture> as usize
+    }
 }
 
-// Possible states of a `fut_never` future.
-enum Never {
-    Inactive(Job),
-    Cancelled,
+impl fmt::Debug for Future {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Future({:?})", self.state())
+    }
 }
 
-// Possible states of a `fut_on_idle` future.
-enum OnIdle {
-    Inactive(Job),
-    Cancelled,
+// Futures are compared by identity.
+impl PartialEq for Future {
+    fn eq(&self, other: &Future) -> bool {
+        Future::ptr_eq(self, other)
+    }
+}
+
+impl Eq for Future {}
+
+impl PartialOrd for Future {
+    fn partial_cmp(&self, other: &Future) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Future {
+    fn cmp(&self, other: &Future) -> std::cmp::Ordering {
+        self.address().cmp(&other.address())
+    }
+}
+
+// Consistent with the comparison by identity.
+impl Hash for Future {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.address().hash(state);
+    }
+}
+
+#[derive(Trace, Finalize)]
+struct PanFuture {
+    #[unsafe_ignore_trace]
+    state: LifecycleState,
+    kind: Kind,
+    // The job that the event loop created for this future once it was run.
+    job: Option<Job>,
+}
+
+impl PanFuture {
+    fn transition_to(&mut self, to: LifecycleState) -> Result<(), FutureError> {
+        if self.state.can_transition_to(to) {
+            self.state = to;
+            Ok(())
+        } else {
+            Err(FutureError::IllegalTransition { from: self.state, to })
+        }
+    }
+}
+
+// The different kinds of futures, with the data they need.
+#[derive(Trace, Finalize)]
+enum Kind {
+    // `fut_resolve`, the value is handed to the event loop when the future is run.
+    Resolve(Option<Value>),
+    // `fut_reject`, the value is handed to the event loop when the future is run.
+    Reject(Option<Value>),
+    // `fut_never`
+    Never,
+    // `fut_on_idle`
+    OnIdle,
+    // A future implemented in rust, handed to the event loop when the future is run.
+    Rust(#[unsafe_ignore_trace] RustFuture),
+    // `fut_sleep`, with the duration in milliseconds.
+    Sleep(i64),
+    // `fut_then`, `fut_map_err` and `fut_finally`, the callback is handed to the event loop when
+    // the future is run.
+    Then {
+        source: Future,
+        callback: Option<Value>,
+        #[unsafe_ignore_trace]
+        branch: Branch,
+    },
+    // `fut_timeout`, with the duration in milliseconds.
+    Timeout(Future, i64),
+    // `fut_all`, `fut_race` and `fut_any`, the inputs are handed to the event loop when the
+    // future is run.
+    Join(Option<Vec<Value>>, #[unsafe_ignore_trace] JoinMode),
+    // `chan_send`, the value is handed to the channel when the future is run.
+    Send(Channel, Option<Value>),
+    // `chan_recv`
+    Recv(Channel),
+    // `fut_scope`, the body is handed to the event loop when the future is run.
+    Scope(Option<Value>),
+}
+
+// The rust future of a future that has not been run yet. It may hold pan values, which must not
+// be dropped while the garbage collector sweeps (as happens when the pan future is collected), so
+// dropping it is deferred in that case.
+struct RustFuture(Option<LocalFutureObj<'static, Result<Value, Value>>>);
+
+thread_local! {
+    // Rust futures dropped by the garbage collector, dropped for real by `drop_deferred`.
+    static DEFERRED: RefCell<Vec<LocalFutureObj<'static, Result<Value, Value>>>> =
+        const { RefCell::new(vec![]) };
+}
+
+impl Drop for RustFuture {
+    fn drop(&mut self) {
+        if !gc::finalizer_safe() {
+            let mut fut = self.0.take();
+            let _ = DEFERRED.try_with(|deferred| deferred.borrow_mut().extend(fut.take()));
+            // The thread is exiting and has dropped the deferred futures already, so this one is
+            // leaked rather than dropped during the final sweep.
+            std::mem::forget(fut);
+        }
+    }
+}
+
+// Drop the rust futures whose drop was deferred. Called whenever pan futures are created or the
+// event loop makes progress, neither of which happens during garbage collection.
+pub(crate) fn drop_deferred() {
+    let deferred = DEFERRED.with(|deferred| std::mem::take(&mut *deferred.borrow_mut()));
+    drop(deferred);
+}
+
+// Which outcome of its source a chained future passes to its callback. The other outcome is
+// passed through unchanged. `Finally` calls the callback without arguments on either outcome, and
+// passes the outcome through unless the callback fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Branch {
+    Resolve,
+    Reject,
+    Finally,
+}
+
+// How a joining future combines the outcomes of its inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JoinMode {
+    // Resolve once all inputs resolved, reject once any input rejected.
+    All,
+    // Settle once any input settled.
+    Race,
+    // Resolve once any input resolved, reject once all inputs rejected.
+    Any,
 }
 
 // Represents what can happen when a PanFuture successfully transitions into the pending state.
 //
 // `ResolveImmediately` and `RejectImmediately` are special cases for the built-in `fut_resolve`
-// and `fut_reject` futures to circumvent the event loop. `OnIdle` is a special case for the
-// built-in `fut_on_idle` future.
+// and `fut_reject` futures to circumvent the event loop. `Never` and `OnIdle` are special cases
+// for the built-in `fut_never` and `fut_on_idle` futures, which register a job with the event
+// loop that is never polled. `Sleep` registers a timer with the event loop. `Chain` settles with
+// the outcome of another future, possibly after applying a callback to it, `Timeout` does so
+// unless its timer fires first, and `Join` combines the outcomes of several values. `Send` and
+// `Recv` leave it to a channel to settle the job. `Scope` calls a body that may spawn children.
 //
 // Everything else spawns a rust future on the event loop.
 enum Run {
     ResolveImmediately(Value),
     RejectImmediately(Value),
-    OnIdle(Job),
+    Never,
+    OnIdle,
+    Sleep(i64),
     SpawnOnEventLoop(LocalFutureObj<'static, Result<Value, Value>>),
+    Chain { source: Future, callback: Value, branch: Branch },
+    Timeout(Future, i64),
+    Join(Vec<Value>, JoinMode),
+    Send(Channel, Value),
+    Recv(Channel),
+    Scope(Value),
 }
-// combinators here? Add case for Never to avoid spwaning an actual future on the loop?
@@ -0,0 +1,101 @@
+// Time sources for the timers of an event loop, and for the time builtins.
+
+use std::cell::Cell;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The source of time for the timers of an event loop. Times are given in milliseconds since an
+/// arbitrary but fixed point in time.
+pub trait Clock {
+    /// The current time, never decreases.
+    fn now(&self) -> u64;
+
+    /// Block until `now` returns at least `deadline`.
+    fn sleep_until(&self, deadline: u64);
+
+    /// The current wall clock time, in milliseconds since the Unix epoch. Unlike `now`, this may
+    /// jump around when the system time is adjusted. Defaults to the system time.
+    fn unix_millis(&self) -> i64 {
+        system_unix_millis()
+    }
+}
+
+fn system_unix_millis() -> i64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_millis() as i64,
+        Err(err) => -(err.duration().as_millis() as i64),
+    }
+}
+
+/// A clock that follows the real passage of time. This is what event loops use by default.
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> SystemClock {
+        SystemClock { start: Instant::now() }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> SystemClock {
+        SystemClock::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+
+    fn sleep_until(&self, deadline: u64) {
+        let now = self.now();
+        if deadline > now {
+            thread::sleep(Duration::from_millis(deadline - now));
+        }
+    }
+}
+
+/// A clock that only advances when told to, so that timers fire deterministically. Sleeping
+/// until a deadline simply moves the clock forward to it. Its wall clock time is an offset from
+/// the Unix epoch (zero by default) plus `now`.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: Cell<u64>,
+    epoch: i64,
+}
+
+impl ManualClock {
+    /// A clock that starts at time zero.
+    pub fn new() -> ManualClock {
+        ManualClock::default()
+    }
+
+    /// A clock that starts at time zero, at which point its wall clock reads `unix_millis`.
+    pub fn starting_at(unix_millis: i64) -> ManualClock {
+        ManualClock { now: Cell::new(0), epoch: unix_millis }
+    }
+
+    /// Move the clock forward by `millis` milliseconds.
+    pub fn advance(&self, millis: u64) {
+        self.now.set(self.now.get() + millis);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.now.get()
+    }
+
+    fn sleep_until(&self, deadline: u64) {
+        if deadline > self.now.get() {
+            self.now.set(deadline);
+        }
+    }
+
+    fn unix_millis(&self) -> i64 {
+        self.epoch + self.now.get() as i64
+    }
+}
@@ -0,0 +1,1569 @@
+// The single-threaded executor that drives pan futures.
+//
+// Jobs live in a slab. A job either wraps a rust future that is polled whenever its waker fires,
+// or it waits to be settled by the loop itself (e.g. once the loop becomes idle). Wakers only
+// push the job onto a queue of jobs to poll, so they are cheap and can be invoked from anywhere.
+//
+// Combinator jobs (`fut_then`, `fut_all`, `fut_race` and friends) observe the jobs of their
+// sources. Whenever a source is done, a microtask is enqueued that passes its outcome to the
+// combinator (this is when callbacks such as those of `fut_then` and `fut_finally` run). The
+// intermediate state of a combinator (e.g. the values collected by `fut_all`) lives in its slot.
+// Once a combinator is done (whether it settled or was cancelled), it cancels all of its sources
+// that are not done yet. Cancelling a source in turn makes its observers receive a rejection.
+//
+// Polling a rust future is guarded against panics, a panic rejects the job instead of unwinding
+// through the loop.
+//
+// The loop guarantees the following order of execution:
+//
+// - Microtasks run in the order in which they were enqueued.
+// - Macrotasks are firing a due timer and polling a woken rust future. Timers take precedence
+//   over polling. Due timers fire in order of their deadlines, woken rust futures are polled in
+//   the order in which they were woken (unless a deterministic loop picks differently, see
+//   below).
+// - The microtask queue drains completely before the next macrotask, so a chain of callbacks
+//   settles without interleaving with other jobs. If the loop has a microtask budget, at most
+//   that many microtasks run in a row while a macrotask is waiting; the remaining microtasks run
+//   after the macrotask, so that a never-ending chain of microtasks can not starve timers.
+// - Only if neither microtasks nor macrotasks remain is the loop idle: then the oldest job
+//   waiting for the loop to become idle (`fut_on_idle`) settles. Anything that it causes happens
+//   before the next job waiting for idleness settles.
+//
+// Each turn of the loop does a single one of these steps.
+//
+// The order in which woken rust futures are polled is up to the scheduling policy of the loop.
+// Deterministic loops pick among them according to a seed (or in the order in which they were
+// woken), run on a virtual clock that jumps to the next deadline whenever only timers remain, and
+// record every scheduling decision in a trace.
+//
+// A scope (`fut_scope`) is a job whose children are among its sources, so they are cancelled
+// once the scope is done. A job always cancels its sources before its pan future transitions, so
+// nested scopes are torn down inside-out. A child that rejects while nothing observes it makes
+// its scope reject, unless something observes it before the scope gets to check.
+//
+// A job that rejects while nothing observes it (no combinator, no waiting rust task) is
+// remembered as unhandled, unless somebody looks at its outcome later. Once the loop would
+// otherwise be idle, including having settled all jobs that wait for idleness, the remaining
+// unhandled rejections are reported to a hook.
+//
+// Timers are kept in a queue sorted by deadline, as measured by the `Clock` of the loop. A timer
+// whose job is done (e.g. because it has been cancelled) stays in the queue until it is popped,
+// but it does not keep the loop waiting. The timer of a `fut_timeout` fires after all other timers
+// with the same deadline, and only once all microtasks have run. So a future that settles at the
+// deadline beats its timeout.
+//
+// A slot is freed once its job is done and all `Job` handles to it have been dropped, so the
+// slab does not grow over the lifetime of a long-running program.
+
+use std::cell::{Cell, RefCell};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::rc::{Rc, Weak};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use futures::future::{Future, LocalFutureObj};
+use futures::task::{self, ArcWake};
+use gc::{Finalize, Trace, unsafe_empty_trace};
+
+use crate::types::{exit, native_panic};
+use crate::value::{Fun, Native, Value};
+use super::{
+    Branch, Clock, FutureError, JoinMode, LifecycleState, ManualClock, Run, SchedEvent,
+    SystemClock,
+};
+use super::sched::{Policy, SplitMix64};
+use super::Future as PanFuture;
+
+/// Drives pan futures to completion.
+pub struct EventLoop {
+    core: Rc<Core>,
+}
+
+struct Core {
+    // The loop itself, for reactions that need to run further futures.
+    this: Weak<Core>,
+    slots: RefCell<Vec<Slot>>,
+    // Indices of vacant slots.
+    free: RefCell<Vec<usize>>,
+    // Jobs whose rust future should be polled, in the order in which they were woken.
+    ready: Arc<ReadyQueue>,
+    // Jobs that settle once there is nothing else to do, in the order in which they were
+    // registered.
+    idle: RefCell<VecDeque<JobId>>,
+    // Outcomes to pass to observing jobs, in the order in which the sources were done.
+    microtasks: RefCell<VecDeque<Microtask>>,
+    // Jobs that settle once their deadline has passed, earliest deadline first. Among timers with
+    // the same deadline, sleeps fire before timeouts, otherwise in the order in which their jobs
+    // were created.
+    timers: RefCell<BinaryHeap<Reverse<Timer>>>,
+    clock: Rc<dyn Clock>,
+    next_id: Cell<u64>,
+    // Called on every lifecycle transition of a pan future on this loop.
+    on_transition: RefCell<Option<Box<TransitionHook>>>,
+    // Called with the rejections nobody observed, once the loop would otherwise be idle.
+    on_unhandled_rejection: RefCell<Option<Box<RejectionHook>>>,
+    // The rejections nobody has observed so far, in the order in which the jobs settled.
+    unhandled: RefCell<Vec<Rejection>>,
+    // Called once the loop runs out of work after having done some.
+    on_idle: RefCell<Option<Box<IdleHook>>>,
+    // Whether the loop has done some work since it last ran out of work.
+    busy: Cell<bool>,
+    // How to choose the next woken rust future to poll.
+    policy: RefCell<Policy>,
+    // How many microtasks may run in a row while a macrotask is waiting, unlimited if `None`.
+    microtask_budget: Option<usize>,
+    // How many microtasks have run since the last macrotask.
+    microtask_streak: Cell<usize>,
+    // The scheduling decisions made so far, only recorded by deterministic loops. Their clock is
+    // virtual, it advances to the next deadline whenever only timers remain.
+    trace: Option<RefCell<Vec<SchedEvent>>>,
+    // Slots removed while the garbage collector was sweeping. Their values must not be dropped
+    // during the sweep, so this happens on the next turn or insertion instead.
+    graveyard: RefCell<Vec<Slot>>,
+}
+
+type TransitionHook = dyn Fn(&PanFuture, LifecycleState, LifecycleState);
+
+type RejectionHook = dyn Fn(Value, Option<PanFuture>);
+
+type IdleHook = dyn Fn();
+
+enum Slot {
+    Vacant,
+    Occupied {
+        id: u64,
+        state: JobState,
+        // The pan future to settle together with the job, if any.
+        future: Option<PanFuture>,
+        // Jobs to notify once this job is done, and which of their sources this job is.
+        observers: Vec<(JobId, usize)>,
+        // Jobs that this job observes, cancelled once this job is done.
+        sources: Vec<JobId>,
+        // Rust tasks waiting for this job to be done (see `Job::poll_outcome`).
+        wakers: Vec<Waker>,
+        // Whether all handles to the job have been dropped.
+        detached: bool,
+        // The scope that this job is a child of, if any.
+        scope: Option<JobId>,
+    },
+}
+
+enum JobState {
+    // A rust future to poll. `None` while it is being polled.
+    Task(Option<LocalFutureObj<'static, Result<Value, Value>>>),
+    // Nothing to poll, the job is settled by the loop.
+    Waiting,
+    // Nothing to poll, the job can only be cancelled.
+    Never,
+    // Waits for the outcome of another job, then applies the callback if the outcome matches the
+    // branch.
+    Chained(Value, Branch),
+    // Waits for the outcomes of several jobs.
+    Joining(Join),
+    // Waits for the outcome of another job until the timer of this job fires.
+    TimingOut,
+    // A `fut_scope` that calls its body on the next microtask (`Some`), then waits for the future
+    // returned by the body (`None`).
+    Scoping(Option<Value>),
+    // Waits for the future returned by a `fut_finally` callback, then settles with the outcome
+    // of the source.
+    Finalizing(Result<Value, Value>),
+    // The outcome, kept until it is taken.
+    Done(Result<Value, Value>),
+    // The outcome has been taken, only the final state is remembered.
+    Taken(LifecycleState),
+}
+
+impl JobState {
+    fn lifecycle(&self) -> LifecycleState {
+        match self {
+            JobState::Task(_)
+            | JobState::Waiting
+            | JobState::Never
+            | JobState::Chained(..)
+            | JobState::Joining(_)
+            | JobState::TimingOut
+            | JobState::Scoping(_)
+            | JobState::Finalizing(_) => {
+                LifecycleState::Running
+            }
+            JobState::Done(Ok(_)) => LifecycleState::Resolved,
+            JobState::Done(Err(_)) => LifecycleState::Rejected,
+            JobState::Taken(state) => *state,
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        matches!(self, JobState::Done(_) | JobState::Taken(_))
+    }
+
+    // What observers of a job in this state receive, `None` if the job is not done yet.
+    fn outcome(&self) -> Option<Result<Value, Value>> {
+        match self {
+            JobState::Done(outcome) => Some(outcome.clone()),
+            JobState::Taken(LifecycleState::Cancelled) => Some(Err(cancelled())),
+            JobState::Taken(_) => Some(Err(Value::error(
+                "future",
+                "the outcome of the future has already been taken",
+            ))),
+            _ => None,
+        }
+    }
+}
+
+// The progress of a `fut_all`, `fut_race` or `fut_any` job.
+struct Join {
+    mode: JoinMode,
+    // The outcomes collected so far, in input order: resolution values for `fut_all`, rejection
+    // values for `fut_any`.
+    values: Vec<Option<Value>>,
+    // How many sources have not settled with an outcome to collect yet.
+    remaining: usize,
+}
+
+// What remains of a job once it is done. The previous state may hold job handles (e.g. inside
+// a rust future), so it is dropped only after the slots have been released.
+struct Finished {
+    future: Option<PanFuture>,
+    _state: JobState,
+    sources: Vec<JobId>,
+    wakers: Vec<Waker>,
+    // Whether some job or rust task was waiting for the outcome.
+    observed: bool,
+    scope: Option<JobId>,
+}
+
+// A rejection that nobody has observed so far.
+struct Rejection {
+    job: JobId,
+    // The scope of the job, which rejects unless the rejection is observed before its next
+    // microtask.
+    scope: Option<JobId>,
+    value: Value,
+    future: Option<PanFuture>,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Timer {
+    deadline: u64,
+    kind: TimerKind,
+    job: JobId,
+}
+
+// The declaration order determines which timers fire first if their deadlines are equal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum TimerKind {
+    Sleep,
+    Timeout,
+}
+
+// Passes the outcome of a source to an observing job.
+struct Microtask {
+    job: JobId,
+    // Which of the sources of the job is done.
+    source: usize,
+    outcome: Result<Value, Value>,
+}
+
+// The source of the microtask that makes a scope check whether one of its children has rejected
+// unobserved.
+const CHILD_REJECTED: usize = usize::MAX;
+
+// Identifies a job within its loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct JobId {
+    // Unique among all jobs of the loop.
+    id: u64,
+    // Where the job is stored in the slab, reused once the job has been removed.
+    index: usize,
+}
+
+// The jobs whose rust future has been woken, shared with their wakers.
+#[derive(Default)]
+struct ReadyQueue {
+    jobs: Mutex<VecDeque<JobId>>,
+    // Woken whenever a job is enqueued, so that an executor driving the loop polls it again.
+    waker: Mutex<Option<Waker>>,
+}
+
+// Wakes a job by enqueuing it for polling.
+struct JobWaker {
+    job: JobId,
+    ready: Arc<ReadyQueue>,
+}
+
+impl ArcWake for JobWaker {
+    fn wake_by_ref(arc_self: &Arc<JobWaker>) {
+        arc_self.ready.jobs.lock().unwrap().push_back(arc_self.job);
+        if let Some(waker) = &*arc_self.ready.waker.lock().unwrap() {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+/// A handle to a job on an event loop, through which its state and outcome can be queried.
+/// Clones refer to the same job. A job whose loop has been dropped counts as cancelled.
+#[derive(Clone)]
+pub struct Job(Rc<JobHandle>);
+
+struct JobHandle {
+    job: JobId,
+    core: Weak<Core>,
+}
+
+impl Drop for JobHandle {
+    fn drop(&mut self) {
+        if let Some(core) = self.core.upgrade() {
+            core.detach(self.job);
+        }
+    }
+}
+
+impl Job {
+    /// An id that is unique among all jobs of the loop.
+    pub fn id(&self) -> u64 {
+        self.0.job.id
+    }
+
+    pub fn state(&self) -> LifecycleState {
+        self.with_state(JobState::lifecycle).unwrap_or(LifecycleState::Cancelled)
+    }
+
+    /// Returns whether the job has resolved or rejected.
+    pub fn is_settled(&self) -> bool {
+        matches!(self.state(), LifecycleState::Resolved | LifecycleState::Rejected)
+    }
+
+    /// The outcome of the job, if it has settled and the outcome has not been taken yet.
+    pub fn result(&self) -> Option<Result<Value, Value>> {
+        if let Some(core) = self.0.core.upgrade() {
+            core.handled(self.0.job);
+        }
+        self.peek()
+    }
+
+    // Like `result`, but does not count as observing a rejection.
+    fn peek(&self) -> Option<Result<Value, Value>> {
+        self.with_state(|state| match state {
+            JobState::Done(outcome) => Some(outcome.clone()),
+            _ => None,
+        }).flatten()
+    }
+
+    /// Take the outcome of the job, if it has settled and the outcome has not been taken yet.
+    /// Afterwards, the loop no longer keeps the outcome alive.
+    pub fn take_result(&self) -> Option<Result<Value, Value>> {
+        let core = self.0.core.upgrade()?;
+        core.handled(self.0.job);
+        core.take_outcome(self.0.job)
+    }
+
+    /// The outcome of the job if it is done: a cancelled job counts as rejected with an error of
+    /// kind `"cancelled"`, a job whose outcome has been taken as rejected with an error of kind
+    /// `"future"`. If the job is not done yet, the task of the context is woken once it is. Does
+    /// not take the outcome.
+    pub fn poll_outcome(&self, cx: &mut Context) -> Poll<Result<Value, Value>> {
+        let core = match self.0.core.upgrade() {
+            Some(core) => core,
+            None => return Poll::Ready(Err(cancelled())),
+        };
+        let mut slots = core.slots.borrow_mut();
+        match &mut slots[self.0.job.index] {
+            Slot::Occupied { id, state, wakers, .. } if *id == self.0.job.id => {
+                match state.outcome() {
+                    Some(outcome) => {
+                        core.handled(self.0.job);
+                        Poll::Ready(outcome)
+                    }
+                    None => {
+                        if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                            wakers.push(cx.waker().clone());
+                        }
+                        Poll::Pending
+                    }
+                }
+            }
+            _ => unreachable!("the slot of a job is not removed while it has handles"),
+        }
+    }
+
+    // Settle the job (and its pan future) if it is not done yet.
+    pub(crate) fn settle(&self, outcome: Result<Value, Value>) {
+        if let Some(core) = self.0.core.upgrade() {
+            core.settle(self.0.job, outcome);
+        }
+    }
+
+    /// Cancel the job (and its pan future) if it is not done yet. Returns whether it was not
+    /// done yet. Any rust future of the job is dropped, which is how it learns about the
+    /// cancellation.
+    pub fn cancel(&self) -> bool {
+        self.0.core.upgrade().is_some_and(|core| core.cancel(self.0.job))
+    }
+
+    // Cancel the job of the given future. Unlike `cancel`, this also works if the loop does not
+    // keep the future alive.
+    pub(super) fn cancel_future(&self, fut: &PanFuture) -> bool {
+        match self.0.core.upgrade() {
+            Some(core) => {
+                let cancelled = core.cancel(self.0.job);
+                if cancelled && fut.recorded_state() == LifecycleState::Running {
+                    core.transition(fut, LifecycleState::Cancelled)
+                        .expect("a running future can always be cancelled");
+                }
+                cancelled
+            }
+            None => false,
+        }
+    }
+
+    fn with_state<R>(&self, f: impl FnOnce(&JobState) -> R) -> Option<R> {
+        let core = self.0.core.upgrade()?;
+        let slots = core.slots.borrow();
+        match &slots[self.0.job.index] {
+            Slot::Occupied { id, state, .. } if *id == self.0.job.id => Some(f(state)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Debug for Job {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Job({})", self.id())
+    }
+}
+
+impl PartialEq for Job {
+    fn eq(&self, other: &Job) -> bool {
+        self.0.job == other.0.job && Weak::ptr_eq(&self.0.core, &other.0.core)
+    }
+}
+
+impl Eq for Job {}
+
+impl Finalize for Job {}
+unsafe impl Trace for Job {
+    unsafe_empty_trace!();
+}
+
+impl EventLoop {
+    /// An event loop whose timers follow the real passage of time.
+    pub fn new() -> EventLoop {
+        EventLoop::builder().build()
+    }
+
+    /// Configure an event loop before creating it.
+    pub fn builder() -> EventLoopBuilder {
+        EventLoopBuilder::default()
+    }
+
+    /// An event loop whose timers are driven by the given clock.
+    pub fn with_clock(clock: Rc<dyn Clock>) -> EventLoop {
+        EventLoop::builder().clock(clock).build()
+    }
+
+    /// A deterministic event loop: it runs on a virtual clock starting at zero, and polls woken
+    /// rust futures in a pseudo-random order determined by `seed`. Running the same program on
+    /// loops with the same seed results in the same trace (see `trace`) and the same outcomes.
+    ///
+    /// The virtual clock advances to the next deadline whenever only timers remain, so
+    /// `run_until_idle` also fires all timers, without actually waiting.
+    pub fn new_deterministic(seed: u64) -> EventLoop {
+        EventLoop::builder().deterministic(Some(seed)).build()
+    }
+
+    /// Like `new_deterministic`, but polls woken rust futures in the order in which they were
+    /// woken, just like a normal loop.
+    pub fn new_deterministic_fifo() -> EventLoop {
+        EventLoop::builder().deterministic(None).build()
+    }
+
+    fn from_builder(builder: EventLoopBuilder) -> EventLoop {
+        let (clock, policy, trace) = match builder.deterministic {
+            None => (builder.clock, Policy::Fifo, None),
+            Some(seed) => (
+                builder.clock.or_else(|| Some(Rc::new(ManualClock::new()))),
+                seed.map_or(Policy::Fifo, |seed| Policy::Seeded(SplitMix64::new(seed))),
+                Some(vec![]),
+            ),
+        };
+        let clock = clock.unwrap_or_else(|| Rc::new(SystemClock::new()));
+        let microtask_budget = builder.microtask_budget;
+        EventLoop {
+            core: Rc::new_cyclic(|this| Core {
+                this: this.clone(),
+                slots: RefCell::new(vec![]),
+                free: RefCell::new(vec![]),
+                ready: Arc::new(ReadyQueue::default()),
+                idle: RefCell::new(VecDeque::new()),
+                microtasks: RefCell::new(VecDeque::new()),
+                timers: RefCell::new(BinaryHeap::new()),
+                clock,
+                next_id: Cell::new(0),
+                on_transition: RefCell::new(None),
+                on_unhandled_rejection: RefCell::new(None),
+                unhandled: RefCell::new(vec![]),
+                on_idle: RefCell::new(None),
+                busy: Cell::new(false),
+                policy: RefCell::new(policy),
+                trace: trace.map(RefCell::new),
+                microtask_budget,
+                microtask_streak: Cell::new(0),
+                graveyard: RefCell::new(vec![]),
+            }),
+        }
+    }
+
+    /// Spawn a rust future onto the loop. It is first polled by the next call that runs the
+    /// loop.
+    pub fn spawn(&self, fut: LocalFutureObj<'static, Result<Value, Value>>) -> Job {
+        let job = self.core.insert(JobState::Task(Some(fut)), None);
+        self.core.ready.jobs.lock().unwrap().push_back(job);
+        self.handle(job)
+    }
+
+    /// Run an inert (or staged) pan future, returning the job that settles with its outcome.
+    /// Fails without any side effects if the future is in any other state.
+    pub fn run_future(&self, fut: &PanFuture) -> Result<Job, FutureError> {
+        self.core.transition(fut, LifecycleState::Running)?;
+        let handle = self.schedule(fut.take_run(), fut);
+        fut.attach(handle.clone());
+        if let Some(outcome) = handle.peek() {
+            self.core.settle_future(fut, &outcome);
+        }
+        Ok(handle)
+    }
+
+    // Act on a future that transitioned into the pending state. The immediate arms settle the
+    // job right away without going through any queue.
+    fn schedule(&self, run: Run, fut: &PanFuture) -> Job {
+        let job = match run {
+            Run::ResolveImmediately(v) => self.core.insert(JobState::Done(Ok(v)), None),
+            Run::RejectImmediately(v) => {
+                let job = self.core.insert(JobState::Done(Err(v.clone())), None);
+                self.core.unobserved(job, None, v, Some(fut.clone()));
+                job
+            }
+            // The job does not keep the future alive, so that dropping the future releases it.
+            Run::Never => self.core.insert(JobState::Never, None),
+            Run::OnIdle => {
+                let job = self.core.insert(JobState::Waiting, Some(fut.clone()));
+                self.core.idle.borrow_mut().push_back(job);
+                job
+            }
+            Run::SpawnOnEventLoop(task) => {
+                let job = self.core.insert(JobState::Task(Some(task)), Some(fut.clone()));
+                self.core.ready.jobs.lock().unwrap().push_back(job);
+                job
+            }
+            Run::Scope(body) => {
+                let job = self.core.insert(JobState::Scoping(Some(body)), Some(fut.clone()));
+                self.core.enqueue(job, 0, Ok(Value::Nil));
+                job
+            }
+            Run::Chain { source, callback, branch } => {
+                let job = self.core.insert(JobState::Chained(callback, branch), Some(fut.clone()));
+                self.observe(job, 0, &source);
+                job
+            }
+            Run::Sleep(millis) => {
+                let job = self.core.insert(JobState::Waiting, Some(fut.clone()));
+                self.core.add_timer(job, millis, TimerKind::Sleep);
+                job
+            }
+            Run::Timeout(source, millis) => {
+                // A source that is done already wins right away.
+                if let Some(outcome) = self.job_of(&source).result() {
+                    return self.handle(self.core.insert(JobState::Done(outcome), None));
+                }
+
+                let job = self.core.insert(JobState::TimingOut, Some(fut.clone()));
+                self.observe(job, 0, &source);
+                self.core.add_timer(job, millis, TimerKind::Timeout);
+                job
+            }
+            Run::Join(inputs, mode) => {
+                if inputs.is_empty() {
+                    let outcome = match mode {
+                        JoinMode::All => Ok(Value::array(vec![])),
+                        JoinMode::Race | JoinMode::Any => Err(Value::error(
+                            "empty",
+                            "can not wait for the first of zero futures",
+                        )),
+                    };
+                    return self.handle(self.core.insert(JobState::Done(outcome), None));
+                }
+
+                let join = Join { mode, values: vec![None; inputs.len()], remaining: inputs.len() };
+                let job = self.core.insert(JobState::Joining(join), Some(fut.clone()));
+                for (i, input) in inputs.iter().enumerate() {
+                    match input {
+                        Value::Future(source) => self.observe(job, i, source),
+                        v => self.core.enqueue(job, i, Ok(v.clone())),
+                    }
+                }
+                job
+            }
+            // The channel settles the job, possibly right away.
+            Run::Send(chan, v) => {
+                let job = self.handle(self.core.insert(JobState::Waiting, Some(fut.clone())));
+                chan.send(job.clone(), v);
+                return job;
+            }
+            Run::Recv(chan) => {
+                let job = self.handle(self.core.insert(JobState::Waiting, Some(fut.clone())));
+                chan.recv(job.clone());
+                return job;
+            }
+        };
+        self.handle(job)
+    }
+
+    // Arrange for a job to receive the outcome of one of its sources, running the source first
+    // if it has not been run yet. A source that is already done is passed on right away.
+    fn observe(&self, job: JobId, i: usize, source: &PanFuture) {
+        let source = self.job_of(source);
+
+        if !Weak::ptr_eq(&source.0.core, &Rc::downgrade(&self.core)) {
+            let err = Value::error("future", "the future runs on a different event loop");
+            return self.core.enqueue(job, i, Err(err));
+        }
+
+        let mut slots = self.core.slots.borrow_mut();
+        if let Slot::Occupied { sources, .. } = &mut slots[job.index] {
+            sources.push(source.0.job);
+        }
+        let outcome = match &mut slots[source.0.job.index] {
+            Slot::Occupied { state, observers, .. } => match state.outcome() {
+                Some(outcome) => outcome,
+                None => return observers.push((job, i)),
+            },
+            Slot::Vacant => unreachable!("the job of a future is not removed while it exists"),
+        };
+        self.core.handled(source.0.job);
+        self.core.enqueue(job, i, outcome);
+    }
+
+    /// The job of a pan future, running the future first if it has not been run yet.
+    pub fn job_of(&self, fut: &PanFuture) -> Job {
+        match fut.job() {
+            Some(job) => job,
+            None => self.run_future(fut).expect("a future without a job has not been run"),
+        }
+    }
+
+    /// Register a function to be called with the future, the old state and the new state
+    /// whenever the loop changes the lifecycle state of a pan future. Replaces any previously
+    /// registered function. Intended for debugging, the function must not register another one.
+    pub fn on_transition(
+        &self,
+        hook: impl Fn(&PanFuture, LifecycleState, LifecycleState) + 'static,
+    ) {
+        *self.core.on_transition.borrow_mut() = Some(Box::new(hook));
+    }
+
+    /// Register a function to be called with every rejection that nobody observed, together with
+    /// the rejected pan future (`None` for rust futures spawned directly onto the loop). A
+    /// rejection counts as observed if a combinator (e.g. `fut_then` or `fut_map_err`) or a rust
+    /// task waits for it, or if its outcome is looked at through a `Job`, even after the fact.
+    /// The function is called for all rejections that remain unobserved once the loop would
+    /// otherwise be idle, i.e. after all jobs waiting for idleness have settled, and at most once
+    /// per rejection. Unobserved rejections of the children of a scope reject the scope instead.
+    /// Replaces any previously registered function, the function must not register another one.
+    pub fn on_unhandled_rejection(&self, hook: impl Fn(Value, Option<PanFuture>) + 'static) {
+        *self.core.on_unhandled_rejection.borrow_mut() = Some(Box::new(hook));
+    }
+
+    /// Register a function to be called whenever the loop runs out of work after having done
+    /// some, i.e. when a turn finds nothing to do (timers in the future may remain), at most once
+    /// per stretch of work. Used by the runtime to collect garbage when nothing else is going on
+    /// (see `Runtime::gc_configure`). Replaces any previously registered function, the function
+    /// must not register another one.
+    pub fn on_idle(&self, hook: impl Fn() + 'static) {
+        *self.core.on_idle.borrow_mut() = Some(Box::new(hook));
+    }
+
+    /// Returns whether the loop has no runnable work: no rust future has been woken, no timer is
+    /// due, and no combinator has an outcome to process. Jobs waiting for the loop to become
+    /// idle settle only in this situation.
+    pub fn is_idle(&self) -> bool {
+        !self.core.has_runnable_work()
+    }
+
+    /// Wake the given waker whenever a rust future on the loop is woken, from any thread.
+    /// Replaces any previously registered waker. Together with `next_deadline`, this allows
+    /// another executor to drive the loop without busy-waiting: run it until idle, then wait
+    /// until either the waker fires or the next deadline has passed.
+    pub fn on_wake(&self, waker: Waker) {
+        *self.core.ready.waker.lock().unwrap() = Some(waker);
+    }
+
+    /// The earliest deadline (as measured by the clock of the loop) of a timer whose job is not
+    /// done yet, if any.
+    pub fn next_deadline(&self) -> Option<u64> {
+        self.core.next_deadline()
+    }
+
+    /// The current time of the clock of the loop, in milliseconds.
+    pub fn now(&self) -> u64 {
+        self.core.clock.now()
+    }
+
+    /// A reference to the loop that does not keep it alive, for things owned by the loop's
+    /// futures (such as native functions) that need to reach it.
+    pub fn downgrade(&self) -> WeakEventLoop {
+        WeakEventLoop(Rc::downgrade(&self.core))
+    }
+
+    /// The clock of the loop.
+    pub fn clock(&self) -> Rc<dyn Clock> {
+        self.core.clock.clone()
+    }
+
+    /// The scheduling decisions made so far by a deterministic loop, in order. Always empty for
+    /// other loops.
+    pub fn trace(&self) -> Vec<SchedEvent> {
+        self.core.trace.as_ref().map(|trace| trace.borrow().clone()).unwrap_or_default()
+    }
+
+    /// The ids of all jobs that are not done yet, in no particular order.
+    pub fn active_jobs(&self) -> Vec<u64> {
+        self.core.slots.borrow().iter().filter_map(|slot| match slot {
+            Slot::Occupied { id, state, .. } if !state.is_done() => Some(*id),
+            _ => None,
+        }).collect()
+    }
+
+    /// Run until there is no more work to do right now: all spawned futures are either done or
+    /// waiting to be woken, all jobs waiting for the loop to become idle have been settled, and
+    /// all remaining timers lie in the future.
+    pub fn run_until_idle(&self) {
+        while self.core.turn() {}
+    }
+
+    /// Like `run_until_idle`, but whenever only timers remain, sleep until the next one is due
+    /// (as determined by the clock of the loop). Returns once no timers remain either.
+    pub fn run_blocking(&self) {
+        loop {
+            self.run_until_idle();
+            match self.core.next_deadline() {
+                Some(deadline) => self.core.clock.sleep_until(deadline),
+                None => return,
+            }
+        }
+    }
+
+    /// Run until the given job is done, and take its outcome. Returns `None` if the loop runs
+    /// out of work before that, or if the outcome has already been taken.
+    pub fn run_until(&self, job: &Job) -> Option<Result<Value, Value>> {
+        loop {
+            if job.state() != LifecycleState::Running {
+                return job.take_result();
+            }
+            if !self.core.turn() {
+                return None;
+            }
+        }
+    }
+
+    /// Like `run_blocking`, but returns as soon as the given job is done.
+    pub fn run_blocking_until(&self, job: &Job) {
+        while job.state() == LifecycleState::Running {
+            if !self.core.turn() {
+                match self.core.next_deadline() {
+                    Some(deadline) => self.core.clock.sleep_until(deadline),
+                    None => return,
+                }
+            }
+        }
+    }
+
+    /// Like `run_blocking_until`, but also returns once the clock of the loop has reached
+    /// `deadline`, without sleeping past it.
+    pub fn run_blocking_until_time(&self, job: &Job, deadline: u64) {
+        while job.state() == LifecycleState::Running && self.core.clock.now() < deadline {
+            if !self.core.turn() {
+                match self.core.next_deadline() {
+                    Some(next) => self.core.clock.sleep_until(next.min(deadline)),
+                    None => return,
+                }
+            }
+        }
+    }
+
+    /// A rust future that runs the loop until the given job is done, and then settles with its
+    /// outcome (see `Job::poll_outcome`), so that another single-threaded executor (e.g. a tokio
+    /// `LocalSet`) can drive the loop. Whenever there is no work to do right now, the future
+    /// registers its waker with `on_wake` and, if a timer is pending, awaits the future that
+    /// `sleep` returns for the time until the next timer is due, so neither executor
+    /// busy-waits. Once that sleep is over, the clock of the loop is advanced to the deadline,
+    /// see `Clock::sleep_until`.
+    ///
+    /// Unlike `run_until`, this does not give up when the loop runs out of work, as the job may
+    /// wait for a rust future that only the other executor wakes.
+    pub fn drive<S, F>(&self, job: &Job, sleep: S) -> Drive<S, F>
+    where
+        S: FnMut(Duration) -> F + Unpin,
+        F: Future<Output = ()>,
+    {
+        Drive { core: self.core.clone(), job: job.clone(), sleep, sleeping: None }
+    }
+
+    fn handle(&self, job: JobId) -> Job {
+        Job(Rc::new(JobHandle { job, core: Rc::downgrade(&self.core) }))
+    }
+}
+
+/// Runs an event loop inside another executor, see `EventLoop::drive`.
+pub struct Drive<S, F> {
+    core: Rc<Core>,
+    job: Job,
+    sleep: S,
+    // The deadline of the timer being waited for, and the sleep until then.
+    sleeping: Option<(u64, Pin<Box<F>>)>,
+}
+
+impl<S, F> Future for Drive<S, F>
+where
+    S: FnMut(Duration) -> F + Unpin,
+    F: Future<Output = ()>,
+{
+    type Output = Result<Value, Value>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<Value, Value>> {
+        let this = self.get_mut();
+        let core = &this.core;
+        *core.ready.waker.lock().unwrap() = Some(cx.waker().clone());
+        loop {
+            if this.job.state() != LifecycleState::Running {
+                return this.job.poll_outcome(cx);
+            }
+            if core.turn() {
+                continue;
+            }
+            let deadline = match core.next_deadline() {
+                Some(deadline) => deadline,
+                None => {
+                    this.sleeping = None;
+                    return Poll::Pending;
+                }
+            };
+            if this.sleeping.as_ref().map(|(sleeping, _)| *sleeping) != Some(deadline) {
+                let wait = Duration::from_millis(deadline.saturating_sub(core.clock.now()));
+                this.sleeping = Some((deadline, Box::pin((this.sleep)(wait))));
+            }
+            let (_, sleep) = this.sleeping.as_mut().expect("a sleep has just been started");
+            match sleep.as_mut().poll(cx) {
+                Poll::Ready(()) => {
+                    this.sleeping = None;
+                    core.clock.sleep_until(deadline);
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// A reference to an event loop that does not keep it alive, see `EventLoop::downgrade`.
+#[derive(Clone)]
+pub struct WeakEventLoop(Weak<Core>);
+
+impl WeakEventLoop {
+    /// The loop, or `None` if it has been dropped.
+    pub fn upgrade(&self) -> Option<EventLoop> {
+        self.0.upgrade().map(|core| EventLoop { core })
+    }
+}
+
+// The value that the observers of a cancelled future reject with.
+fn cancelled() -> Value {
+    Value::error("cancelled", "the future has been cancelled")
+}
+
+/// Configures an event loop, see `EventLoop::builder`.
+#[derive(Default)]
+pub struct EventLoopBuilder {
+    clock: Option<Rc<dyn Clock>>,
+    // `Some` for deterministic loops, with the seed of the scheduling policy, if any.
+    deterministic: Option<Option<u64>>,
+    microtask_budget: Option<usize>,
+}
+
+impl EventLoopBuilder {
+    /// Drive the timers of the loop by the given clock. By default, normal loops follow the real
+    /// passage of time and deterministic loops use a virtual clock starting at zero.
+    pub fn clock(mut self, clock: Rc<dyn Clock>) -> EventLoopBuilder {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Make the loop deterministic, see `EventLoop::new_deterministic`. Without a seed, woken
+    /// rust futures are polled in the order in which they were woken.
+    pub fn deterministic(mut self, seed: Option<u64>) -> EventLoopBuilder {
+        self.deterministic = Some(seed);
+        self
+    }
+
+    /// Let at most `budget` microtasks (but at least one) run in a row while a timer is due or a
+    /// rust future has been woken. The remaining microtasks run after the timer has fired or the
+    /// future has been polled. By default, all microtasks run first.
+    pub fn microtask_budget(mut self, budget: usize) -> EventLoopBuilder {
+        self.microtask_budget = Some(budget.max(1));
+        self
+    }
+
+    pub fn build(self) -> EventLoop {
+        EventLoop::from_builder(self)
+    }
+}
+
+impl Default for EventLoop {
+    fn default() -> EventLoop {
+        EventLoop::new()
+    }
+}
+
+impl Core {
+    fn event_loop(&self) -> EventLoop {
+        EventLoop { core: self.this.upgrade().expect("the loop is alive while it runs") }
+    }
+
+    // Make a single step of progress. Returns `false` if there was nothing to do.
+    fn turn(&self) -> bool {
+        if self.step() {
+            self.busy.set(true);
+            return true;
+        }
+        if self.busy.replace(false) {
+            if let Some(hook) = &*self.on_idle.borrow() {
+                hook();
+            }
+        }
+        false
+    }
+
+    fn step(&self) -> bool {
+        self.clear_graveyard();
+
+        let within_budget = self
+            .microtask_budget
+            .is_none_or(|budget| self.microtask_streak.get() < budget);
+        if within_budget && self.run_microtask() {
+            return true;
+        }
+        if self.run_macrotask() {
+            self.microtask_streak.set(0);
+            return true;
+        }
+        // Microtasks over budget, but nothing else is waiting.
+        if self.run_microtask() {
+            return true;
+        }
+
+        debug_assert!(!self.has_runnable_work());
+        if let Some(job) = self.pop_idle() {
+            self.record(SchedEvent::Idle { job: job.id });
+            self.settle(job, Ok(Value::Nil));
+            return true;
+        }
+
+        let unhandled = std::mem::take(&mut *self.unhandled.borrow_mut());
+        if !unhandled.is_empty() {
+            self.report(unhandled);
+            return true;
+        }
+
+        // Only timers remain, a virtual clock can skip ahead to the next one.
+        if self.trace.is_some() {
+            if let Some(deadline) = self.next_deadline() {
+                self.record(SchedEvent::Advance { to: deadline });
+                self.clock.sleep_until(deadline);
+                return true;
+            }
+        }
+        false
+    }
+
+    fn run_microtask(&self) -> bool {
+        let microtask = self.microtasks.borrow_mut().pop_front();
+        match microtask {
+            Some(microtask) => {
+                self.record(SchedEvent::Microtask { job: microtask.job.id });
+                self.microtask_streak.set(self.microtask_streak.get() + 1);
+                self.react(microtask);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Fire a due timer, or else poll a woken rust future.
+    fn run_macrotask(&self) -> bool {
+        let due = self.pop_due_timer();
+        if let Some(job) = due {
+            self.record(SchedEvent::Timer { job: job.id, at: self.clock.now() });
+            self.fire(job);
+            return true;
+        }
+
+        let woken = self.pop_woken();
+        if let Some(job) = woken {
+            self.record(SchedEvent::Poll { job: job.id });
+            self.poll(job);
+            return true;
+        }
+        false
+    }
+
+    fn report(&self, unhandled: Vec<Rejection>) {
+        for Rejection { job, value, future, .. } in unhandled {
+            self.record(SchedEvent::Unhandled { job: job.id });
+            if let Some(hook) = &*self.on_unhandled_rejection.borrow() {
+                hook(value, future);
+            }
+        }
+    }
+
+    // Remember a rejection that nobody observed when the job settled, and have the scope of the
+    // job check on it.
+    fn unobserved(
+        &self,
+        job: JobId,
+        scope: Option<JobId>,
+        value: Value,
+        future: Option<PanFuture>,
+    ) {
+        self.unhandled.borrow_mut().push(Rejection { job, scope, value, future });
+        if let Some(scope) = scope {
+            self.enqueue(scope, CHILD_REJECTED, Ok(Value::Nil));
+        }
+    }
+
+    // Make a job a child of a scope, or cancel it if the scope is done already. A child that
+    // rejected unobserved before being adopted is treated as if it rejected right now.
+    fn adopt(&self, scope: JobId, child: JobId) {
+        let adopted = {
+            let mut slots = self.slots.borrow_mut();
+            let adopted = match &mut slots[scope.index] {
+                Slot::Occupied { id, state, sources, .. }
+                    if *id == scope.id && !state.is_done() => {
+                    sources.push(child);
+                    true
+                }
+                _ => false,
+            };
+            if let Slot::Occupied { id, scope: parent, .. } = &mut slots[child.index] {
+                if adopted && *id == child.id {
+                    *parent = Some(scope);
+                }
+            }
+            adopted
+        };
+        if !adopted {
+            self.cancel(child);
+            return;
+        }
+
+        let rejected = match self.unhandled.borrow_mut().iter_mut().find(|r| r.job == child) {
+            Some(rejection) => {
+                rejection.scope = Some(scope);
+                true
+            }
+            None => false,
+        };
+        if rejected {
+            self.enqueue(scope, CHILD_REJECTED, Ok(Value::Nil));
+        }
+    }
+
+    // The function that the body of a scope receives: it runs a future as a child of the scope
+    // and returns it.
+    fn spawner(&self, scope: JobId) -> Native {
+        let core = self.this.clone();
+        Native::new("spawn", move |args| {
+            let core = match core.upgrade() {
+                Some(core) => core,
+                None => return Err(cancelled()),
+            };
+            let child = match args.first() {
+                Some(Value::Future(child)) => child,
+                _ => return Err(Value::error("type", "spawn expects a future")),
+            };
+            let job = core.event_loop().job_of(child);
+            if !Weak::ptr_eq(&job.0.core, &core.this) {
+                return Err(Value::error("future", "the future runs on a different event loop"));
+            }
+            core.adopt(scope, job.0.job);
+            Ok(Value::Future(child.clone()))
+        })
+    }
+
+    // Note that the outcome of a job has been observed after it settled.
+    fn handled(&self, job: JobId) {
+        let mut unhandled = self.unhandled.borrow_mut();
+        if !unhandled.is_empty() {
+            unhandled.retain(|rejection| rejection.job != job);
+        }
+    }
+
+    fn record(&self, event: SchedEvent) {
+        if let Some(trace) = &self.trace {
+            trace.borrow_mut().push(event);
+        }
+    }
+
+    // The next woken rust future to poll, as chosen by the scheduling policy.
+    fn pop_woken(&self) -> Option<JobId> {
+        let mut ready = self.ready.jobs.lock().unwrap();
+        if ready.is_empty() {
+            return None;
+        }
+        let index = self.policy.borrow_mut().pick(ready.len());
+        ready.remove(index)
+    }
+
+    // Whether there is a microtask, a due timer or a woken rust future.
+    fn has_runnable_work(&self) -> bool {
+        !self.microtasks.borrow().is_empty()
+            || self.timers.borrow().peek().is_some_and(|Reverse(timer)| {
+                timer.deadline <= self.clock.now()
+            })
+            || !self.ready.jobs.lock().unwrap().is_empty()
+    }
+
+    // The oldest job waiting for idleness that has not been cancelled.
+    fn pop_idle(&self) -> Option<JobId> {
+        let mut idle = self.idle.borrow_mut();
+        while let Some(job) = idle.pop_front() {
+            if self.is_pending(job) {
+                return Some(job);
+            }
+        }
+        None
+    }
+
+    fn pop_due_timer(&self) -> Option<JobId> {
+        let mut timers = self.timers.borrow_mut();
+        match timers.peek() {
+            Some(Reverse(timer)) if timer.deadline <= self.clock.now() => {
+                timers.pop().map(|Reverse(timer)| timer.job)
+            }
+            _ => None,
+        }
+    }
+
+    // The earliest deadline of a timer whose job is not done yet. Discards the timers of jobs
+    // that are done.
+    fn next_deadline(&self) -> Option<u64> {
+        let mut timers = self.timers.borrow_mut();
+        while let Some(Reverse(timer)) = timers.peek() {
+            if self.is_pending(timer.job) {
+                return Some(timer.deadline);
+            }
+            timers.pop();
+        }
+        None
+    }
+
+    // Durations of zero or less are over on the next turn.
+    fn add_timer(&self, job: JobId, millis: i64, kind: TimerKind) {
+        let deadline = self.clock.now() + millis.max(0) as u64;
+        self.timers.borrow_mut().push(Reverse(Timer { deadline, kind, job }));
+    }
+
+    // Settle the job of a timer that is due.
+    fn fire(&self, job: JobId) {
+        match self.take_state(job) {
+            Some(JobState::Waiting) => self.settle(job, Ok(Value::Nil)),
+            Some(JobState::TimingOut) => {
+                self.settle(job, Err(Value::error("timeout", "the future did not settle in time")));
+            }
+            Some(state) => self.restore(job, state),
+            None => {}
+        }
+    }
+
+    fn is_pending(&self, job: JobId) -> bool {
+        match &self.slots.borrow()[job.index] {
+            Slot::Occupied { id, state, .. } => *id == job.id && !state.is_done(),
+            Slot::Vacant => false,
+        }
+    }
+
+    fn poll(&self, job: JobId) {
+        // Take the rust future out of its slot, so that polling it may access the loop.
+        let mut fut = match self.slots.borrow_mut().get_mut(job.index) {
+            Some(Slot::Occupied { id, state: JobState::Task(fut), .. }) if *id == job.id => {
+                match fut.take() {
+                    Some(fut) => fut,
+                    None => return,
+                }
+            }
+            // Spurious wakeup of a job that is done.
+            _ => return,
+        };
+
+        let waker = task::waker(Arc::new(JobWaker { job, ready: self.ready.clone() }));
+        let polled = panic::catch_unwind(AssertUnwindSafe(|| {
+            Pin::new(&mut fut).poll(&mut Context::from_waker(&waker))
+        }));
+        match polled {
+            Ok(Poll::Ready(outcome)) => self.settle(job, outcome),
+            // A rust future that panicked is never polled again.
+            Err(payload) => {
+                drop(fut);
+                self.settle(job, Err(native_panic::error(None, payload)));
+            }
+            // Unless the job has been cancelled while being polled, in which case the rust
+            // future is dropped here.
+            Ok(Poll::Pending) => self.restore(job, JobState::Task(Some(fut))),
+        }
+    }
+
+    // Pass the outcome of a source to the job observing it.
+    fn react(&self, Microtask { job, source, outcome }: Microtask) {
+        let state = match self.take_state(job) {
+            Some(state) => state,
+            None => return,
+        };
+
+        match state {
+            JobState::Scoping(body) if source == CHILD_REJECTED => {
+                self.restore(job, JobState::Scoping(body));
+                let rejected = {
+                    let mut unhandled = self.unhandled.borrow_mut();
+                    let position = unhandled.iter().position(|r| r.scope == Some(job));
+                    position.map(|i| unhandled.remove(i))
+                };
+                if let Some(rejection) = rejected {
+                    self.settle(job, Err(rejection.value));
+                }
+            }
+            JobState::Chained(callback, Branch::Finally) => match &callback.apply(&[]) {
+                Ok(Value::Future(cleanup)) => {
+                    self.restore(job, JobState::Finalizing(outcome));
+                    self.event_loop().observe(job, 0, cleanup);
+                }
+                Ok(_) => self.settle(job, outcome),
+                Err(err) => self.settle(job, Err(err.clone())),
+            },
+            JobState::Scoping(Some(body)) => {
+                let spawn = Value::Fun(Fun::Native(self.spawner(job)));
+                match &body.apply(&[spawn]) {
+                    Ok(Value::Future(result)) if self.is_pending(job) => {
+                        self.restore(job, JobState::Scoping(None));
+                        self.event_loop().observe(job, 0, result);
+                    }
+                    // A child has failed the scope while the body was running.
+                    Ok(Value::Future(result)) => {
+                        self.event_loop().job_of(result).cancel_future(result);
+                    }
+                    Ok(v) => self.settle(job, Ok(v.clone())),
+                    Err(err) => self.settle(job, Err(err.clone())),
+                }
+            }
+            JobState::Scoping(None) => self.settle(job, outcome),
+            JobState::Finalizing(outcome_of_source) => match outcome {
+                Ok(_) => self.settle(job, outcome_of_source),
+                Err(err) => self.settle(job, Err(err)),
+            },
+            JobState::Chained(callback, branch) => {
+                let outcome = match (outcome, branch) {
+                    (Err(v), Branch::Reject) if exit::unwinds(&v) => Err(v),
+                    (Ok(v), Branch::Resolve) | (Err(v), Branch::Reject) => callback.apply(&[v]),
+                    (outcome, _) => outcome,
+                };
+                self.settle(job, outcome);
+            }
+            JobState::Joining(mut join) => {
+                let outcome = match (join.mode, outcome) {
+                    (JoinMode::Race, outcome)
+                    | (JoinMode::All, outcome @ Err(_))
+                    | (JoinMode::Any, outcome @ Ok(_)) => outcome,
+                    (mode, Ok(v)) | (mode, Err(v)) => {
+                        join.values[source] = Some(v);
+                        join.remaining -= 1;
+                        if join.remaining > 0 {
+                            return self.restore(job, JobState::Joining(join));
+                        }
+                        let values = Value::array(join.values.into_iter().flatten().collect());
+                        if mode == JoinMode::All { Ok(values) } else { Err(values) }
+                    }
+                };
+                self.settle(job, outcome);
+            }
+            JobState::TimingOut => self.settle(job, outcome),
+            state => self.restore(job, state),
+        }
+    }
+
+    // Take the state of a job that is not done yet out of its slot (leaving it waiting), so that
+    // acting on it (e.g. calling a callback) may access the loop.
+    fn take_state(&self, job: JobId) -> Option<JobState> {
+        match self.slots.borrow_mut().get_mut(job.index) {
+            Some(Slot::Occupied { id, state, .. }) if *id == job.id && !state.is_done() => {
+                Some(std::mem::replace(state, JobState::Waiting))
+            }
+            _ => None,
+        }
+    }
+
+    // Put back a state taken out of a slot, unless the job has been done in the meantime.
+    fn restore(&self, job: JobId, state: JobState) {
+        let mut slots = self.slots.borrow_mut();
+        match &mut slots[job.index] {
+            Slot::Occupied { id, state: current, .. } if *id == job.id && !current.is_done() => {
+                *current = state;
+            }
+            // The state may hold job handles, so it is dropped only after releasing the slots.
+            _ => {
+                drop(slots);
+                drop(state);
+            }
+        }
+    }
+
+    // Settle a job that is not done yet, together with its pan future, and notify its
+    // observers.
+    fn settle(&self, job: JobId, outcome: Result<Value, Value>) {
+        let done = JobState::Done(outcome.clone());
+        if let Some(mut finished) = self.finish(job, done, &outcome) {
+            self.cancel_all(std::mem::take(&mut finished.sources));
+            if let Some(fut) = &finished.future {
+                self.settle_future(fut, &outcome);
+            }
+            if let Err(value) = outcome {
+                if !finished.observed {
+                    self.unobserved(job, finished.scope, value, finished.future.clone());
+                }
+            }
+            self.release(finished);
+        }
+    }
+
+    // Cancel a job that is not done yet, together with its pan future. Its observers receive a
+    // rejection. Returns whether the job was not done yet.
+    fn cancel(&self, job: JobId) -> bool {
+        let done = JobState::Taken(LifecycleState::Cancelled);
+        match self.finish(job, done, &Err(cancelled())) {
+            Some(mut finished) => {
+                self.cancel_all(std::mem::take(&mut finished.sources));
+                if let Some(fut) = &finished.future {
+                    if fut.recorded_state() == LifecycleState::Running {
+                        self.transition(fut, LifecycleState::Cancelled)
+                            .expect("a running future can always be cancelled");
+                    }
+                }
+                self.release(finished);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Move a job that is not done yet into the given final state and enqueue its observers. If
+    // nobody holds a handle to the job, it is removed. Returns `None` if the job was done
+    // already.
+    fn finish(
+        &self,
+        job: JobId,
+        done: JobState,
+        observed: &Result<Value, Value>,
+    ) -> Option<Finished> {
+        let mut slots = self.slots.borrow_mut();
+        match &mut slots[job.index] {
+            Slot::Occupied { id, state, future, observers, sources, wakers, detached, scope }
+                if *id == job.id && !state.is_done() => {
+                let has_observers = !observers.is_empty() || !wakers.is_empty();
+                for (observer, i) in observers.drain(..) {
+                    self.enqueue(observer, i, observed.clone());
+                }
+                let finished = Finished {
+                    future: future.take(),
+                    _state: std::mem::replace(state, done),
+                    sources: std::mem::take(sources),
+                    wakers: std::mem::take(wakers),
+                    observed: has_observers,
+                    scope: *scope,
+                };
+                if *detached {
+                    slots[job.index] = Slot::Vacant;
+                    self.free.borrow_mut().push(job.index);
+                }
+                Some(finished)
+            }
+            _ => None,
+        }
+    }
+
+    // Cancel the sources of a job that is done: nothing waits for their outcomes anymore.
+    // Sources that are done already are not affected.
+    fn cancel_all(&self, sources: Vec<JobId>) {
+        for source in sources {
+            self.cancel(source);
+        }
+    }
+
+    // Drop what remains of a job that is done, and wake the rust tasks waiting for it.
+    fn release(&self, finished: Finished) {
+        for waker in finished.wakers {
+            waker.wake();
+        }
+    }
+
+    fn enqueue(&self, job: JobId, source: usize, outcome: Result<Value, Value>) {
+        self.microtasks.borrow_mut().push_back(Microtask { job, source, outcome });
+    }
+
+    // Move a running pan future into the state corresponding to the outcome. Futures that are
+    // already done (e.g. because they have been cancelled) are left alone.
+    fn settle_future(&self, fut: &PanFuture, outcome: &Result<Value, Value>) {
+        if fut.recorded_state() == LifecycleState::Running {
+            let to = match outcome {
+                Ok(_) => LifecycleState::Resolved,
+                Err(_) => LifecycleState::Rejected,
+            };
+            self.transition(fut, to).expect("a running future can always settle");
+        }
+    }
+
+    // Change the lifecycle state of a pan future, notifying the transition hook.
+    fn transition(&self, fut: &PanFuture, to: LifecycleState) -> Result<(), FutureError> {
+        let from = fut.recorded_state();
+        fut.transition_to(to)?;
+        if let Some(hook) = &*self.on_transition.borrow() {
+            hook(fut, from, to);
+        }
+        Ok(())
+    }
+
+    fn take_outcome(&self, job: JobId) -> Option<Result<Value, Value>> {
+        let mut slots = self.slots.borrow_mut();
+        match &mut slots[job.index] {
+            Slot::Occupied { id, state, .. } if *id == job.id => {
+                let lifecycle = state.lifecycle();
+                match std::mem::replace(state, JobState::Taken(lifecycle)) {
+                    JobState::Done(outcome) => Some(outcome),
+                    other => {
+                        *state = other;
+                        None
+                    }
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // Called when the last handle to a job has been dropped. Done jobs and never jobs are removed
+    // right away, others once they are done.
+    fn detach(&self, job: JobId) {
+        let removed = {
+            let mut slots = self.slots.borrow_mut();
+            match &mut slots[job.index] {
+                // Nothing can happen to a never job once its handles are gone.
+                Slot::Occupied { id, state, .. }
+                    if *id == job.id && (state.is_done() || matches!(state, JobState::Never)) => {
+                    self.free.borrow_mut().push(job.index);
+                    std::mem::replace(&mut slots[job.index], Slot::Vacant)
+                }
+                Slot::Occupied { id, detached, .. } if *id == job.id => {
+                    *detached = true;
+                    Slot::Vacant
+                }
+                _ => Slot::Vacant,
+            }
+        };
+        // The outcome may hold further job handles, so it is dropped only after releasing the
+        // slots.
+        if gc::finalizer_safe() {
+            drop(removed);
+        } else {
+            // The last handle was dropped by the garbage collector, which may be sweeping values
+            // that the slot still refers to.
+            self.graveyard.borrow_mut().push(removed);
+        }
+    }
+
+    fn clear_graveyard(&self) {
+        let buried = std::mem::take(&mut *self.graveyard.borrow_mut());
+        drop(buried);
+        super::drop_deferred();
+    }
+
+    fn insert(&self, state: JobState, future: Option<PanFuture>) -> JobId {
+        self.clear_graveyard();
+
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+
+        let mut slots = self.slots.borrow_mut();
+        let index = match self.free.borrow_mut().pop() {
+            Some(index) => index,
+            None => {
+                slots.push(Slot::Vacant);
+                slots.len() - 1
+            }
+        };
+        slots[index] = Slot::Occupied {
+            id,
+            state,
+            future,
+            observers: vec![],
+            sources: vec![],
+            wakers: vec![],
+            detached: false,
+            scope: None,
+        };
+        JobId { id, index }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::{self, LocalFutureObj};
+
+    use super::*;
+
+    fn ready(v: Value) -> LocalFutureObj<'static, Result<Value, Value>> {
+        LocalFutureObj::new(Box::new(future::ready(Ok(v))))
+    }
+
+    #[test]
+    fn slots_are_reused_once_jobs_are_done_and_released() {
+        let event_loop = EventLoop::new();
+        for n in 0..100 {
+            let job = event_loop.spawn(ready(Value::Int(n)));
+            assert_eq!(event_loop.run_until(&job), Some(Ok(Value::Int(n))));
+        }
+        assert_eq!(event_loop.core.slots.borrow().len(), 1);
+
+        // A done job keeps its slot while there are handles to it.
+        let jobs: Vec<Job> = (0..10)
+            .map(|_| event_loop.spawn(ready(Value::Nil)))
+            .collect();
+        event_loop.run_until_idle();
+        assert_eq!(event_loop.core.slots.borrow().len(), 10);
+        drop(jobs);
+        assert_eq!(event_loop.core.free.borrow().len(), 10);
+
+        // A job that is not done keeps its slot after its handles are dropped, until it is done.
+        let (send, receive) = futures::channel::oneshot::channel::<()>();
+        let job = event_loop.spawn(LocalFutureObj::new(Box::new(async move {
+            receive.await.unwrap();
+            Ok(Value::Nil)
+        })));
+        let index = job.0.job.index;
+        drop(job);
+        event_loop.run_until_idle();
+        assert!(matches!(event_loop.core.slots.borrow()[index], Slot::Occupied { .. }));
+        send.send(()).unwrap();
+        event_loop.run_until_idle();
+        assert!(matches!(event_loop.core.slots.borrow()[index], Slot::Vacant));
+        assert_eq!(event_loop.core.free.borrow().len(), 10);
+    }
+}
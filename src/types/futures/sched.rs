@@ -0,0 +1,59 @@
+// Scheduling policies and traces, which make event loops deterministic.
+//
+// The only scheduling decision a loop is free to make is which woken rust future to poll next:
+// microtasks, timers and idle jobs are ordered by the guarantees documented on the event loop.
+// A normal loop polls woken futures in the order in which they were woken. A seeded loop picks
+// among them pseudo-randomly, so that programs relying on an incidental order can be shaken out,
+// while the same seed always reproduces the same run.
+
+/// A scheduling decision of a deterministic event loop, as returned by `EventLoop::trace`. Jobs
+/// are given by their ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SchedEvent {
+    /// Passed the outcome of a source to the job observing it.
+    Microtask { job: u64 },
+    /// Fired the timer of a job, at the given time.
+    Timer { job: u64, at: u64 },
+    /// Polled the rust future of a job.
+    Poll { job: u64 },
+    /// Settled a job that waited for the loop to become idle.
+    Idle { job: u64 },
+    /// Reported a rejection of a job that nobody observed.
+    Unhandled { job: u64 },
+    /// Advanced the virtual clock to the given time, because only timers remained.
+    Advance { to: u64 },
+}
+
+// How to choose the next woken rust future to poll.
+pub(super) enum Policy {
+    Fifo,
+    Seeded(SplitMix64),
+}
+
+impl Policy {
+    // The index of the next future to poll among `len > 0` woken ones.
+    pub(super) fn pick(&mut self, len: usize) -> usize {
+        match self {
+            Policy::Fifo => 0,
+            Policy::Seeded(rng) => (rng.next() % len as u64) as usize,
+        }
+    }
+}
+
+// A small, fast pseudo-random number generator (Steele, Lea and Flood's SplitMix64). Not suitable
+// for anything but scheduling decisions.
+pub(super) struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub(super) fn new(seed: u64) -> SplitMix64 {
+        SplitMix64(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
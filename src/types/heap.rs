@@ -0,0 +1,341 @@
+// Statistics about the garbage-collected heap.
+//
+// The `gc` crate can force a collection, but it does not report how many objects or bytes it
+// manages. So this crate counts the allocations it is interested in itself: the arrays, sets and
+// maps that are alive (a collection stops being alive once it has been collected or otherwise
+// dropped), the collections allocated so far, the environments created for calls of pan
+// functions, and the collections of garbage that it caused, together with the time they took.
+// The heap of the `gc` crate is per thread, and so are the counters.
+//
+// Besides the collections the `gc` crate triggers by itself, the embedder can make collections
+// happen at predictable points with a `GcConfig` (see `Runtime::gc_configure`): after a number of
+// counted allocations (collections and environments) made while the runtime runs code, and
+// whenever the event loop of the runtime runs out of work.
+//
+// Memory budgets limit how much memory the code run by a runtime may allocate (see
+// `Runtime::set_memory_limit`). While a runtime runs code, its budget is the current budget of the
+// thread, and collections and environments charge their estimated size to the current budget when
+// they are created or grow, and credit it back once they are dropped (values only become garbage
+// at collections, so the credit may come late). The estimate counts a fixed size per environment
+// binding, array element, set member and map entry, plus the payload of strings and bytes that
+// are appended to arrays. Payloads are never credited back before the array is dropped, and
+// payloads in sets and maps, or written over array elements, are not counted, so the estimate
+// over-approximates the arrays that grow and shrink and under-approximates the others.
+//
+// Once the usage exceeds the limit, every call of a function fails with an out-of-memory value
+// (see `out_of_memory`). Like exit values, it passes through the catch regions of pan code, so it
+// unwinds to the embedder, unless enough memory is released on the way.
+
+use std::cell::{Cell, RefCell};
+use std::mem;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::types::userdata::{PanUserdata, Userdata};
+use crate::value::Value;
+
+/// A snapshot of the counters of the current thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct GcStats {
+    /// The number of arrays, sets and maps that have not been dropped yet.
+    pub live_collections: u64,
+    /// The number of arrays, sets and maps that have been allocated.
+    pub collections_allocated: u64,
+    /// The number of environments that have been allocated for calls of pan functions.
+    pub environments_created: u64,
+    /// The number of collections that have been forced via `collect`.
+    pub collections_forced: u64,
+    /// The number of collections that a `GcConfig` has triggered.
+    pub collections_triggered: u64,
+    /// The time spent in forced and triggered collections.
+    pub collection_time: Duration,
+}
+
+/// When to collect garbage besides the collections that the `gc` crate triggers by itself, see
+/// `Runtime::gc_configure`. By default, never.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct GcConfig {
+    /// Collect once this many collections and environments have been allocated since the last
+    /// collection, while the runtime runs code.
+    pub allocation_threshold: Option<u64>,
+    /// Collect whenever the event loop of the runtime runs out of work after having done some.
+    pub collect_at_idle: bool,
+}
+
+thread_local! {
+    static STATS: Cell<GcStats> = Cell::new(GcStats::default());
+    // The counted allocations since the last collection.
+    static SINCE_COLLECTION: Cell<u64> = const { Cell::new(0) };
+    // The allocation threshold of the runtime that is currently running code.
+    static THRESHOLD: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// The current values of the counters.
+pub fn stats() -> GcStats {
+    STATS.with(Cell::get)
+}
+
+/// Force a garbage collection.
+pub fn collect() {
+    update(|stats| stats.collections_forced += 1);
+    timed_collect();
+}
+
+/// Collect garbage on behalf of a `GcConfig`.
+pub(crate) fn trigger() {
+    update(|stats| stats.collections_triggered += 1);
+    timed_collect();
+}
+
+fn timed_collect() {
+    let start = Instant::now();
+    gc::force_collect();
+    let elapsed = start.elapsed();
+    SINCE_COLLECTION.with(|since| since.set(0));
+    update(|stats| stats.collection_time += elapsed);
+}
+
+pub(crate) fn environment_created() {
+    update(|stats| stats.environments_created += 1);
+    allocated();
+}
+
+// Count an allocation towards the threshold, and collect once it is reached.
+fn allocated() {
+    let since = SINCE_COLLECTION.with(|since| {
+        since.set(since.get() + 1);
+        since.get()
+    });
+    if THRESHOLD.with(Cell::get).is_some_and(|threshold| since >= threshold) {
+        trigger();
+    }
+}
+
+/// Makes the allocation threshold of a runtime the current one of the thread until it is
+/// dropped, then restores the previous one.
+pub(crate) struct ThresholdScope(Option<u64>);
+
+impl ThresholdScope {
+    pub(crate) fn enter(threshold: Option<u64>) -> ThresholdScope {
+        ThresholdScope(THRESHOLD.with(|current| current.replace(threshold)))
+    }
+}
+
+impl Drop for ThresholdScope {
+    fn drop(&mut self) {
+        let previous = self.0;
+        let _ = THRESHOLD.try_with(|current| current.set(previous));
+    }
+}
+
+/// Counts a collection as alive for as long as it exists. Every collection owns one.
+#[derive(Debug)]
+pub(crate) struct LiveCollection(());
+
+impl LiveCollection {
+    pub(crate) fn new() -> LiveCollection {
+        update(|stats| {
+            stats.live_collections += 1;
+            stats.collections_allocated += 1;
+        });
+        allocated();
+        LiveCollection(())
+    }
+}
+
+impl Drop for LiveCollection {
+    fn drop(&mut self) {
+        // The counters may already be gone if the thread is exiting.
+        let _ = STATS.try_with(|stats| {
+            let mut s = stats.get();
+            s.live_collections -= 1;
+            stats.set(s);
+        });
+    }
+}
+
+fn update(f: impl FnOnce(&mut GcStats)) {
+    STATS.with(|stats| {
+        let mut s = stats.get();
+        f(&mut s);
+        stats.set(s);
+    });
+}
+
+/// A limit on the memory that the code run by a runtime may allocate, and the memory charged to
+/// it so far.
+#[derive(Debug, Default)]
+pub struct MemoryBudget {
+    limit: Cell<Option<u64>>,
+    used: Cell<u64>,
+}
+
+impl MemoryBudget {
+    /// A budget without a limit.
+    pub fn new() -> MemoryBudget {
+        MemoryBudget::default()
+    }
+
+    /// The limit in bytes, if any.
+    pub fn limit(&self) -> Option<u64> {
+        self.limit.get()
+    }
+
+    pub fn set_limit(&self, limit: Option<u64>) {
+        self.limit.set(limit);
+    }
+
+    /// The estimated number of bytes charged to the budget.
+    pub fn used(&self) -> u64 {
+        self.used.get()
+    }
+
+    /// Returns whether the usage exceeds the limit.
+    pub fn is_exceeded(&self) -> bool {
+        self.limit.get().is_some_and(|limit| self.used.get() > limit)
+    }
+}
+
+thread_local! {
+    static BUDGET: RefCell<Option<Rc<MemoryBudget>>> = const { RefCell::new(None) };
+}
+
+/// Makes a budget the current one of the thread until it is dropped, then restores the previous
+/// one.
+pub(crate) struct BudgetScope(Option<Rc<MemoryBudget>>);
+
+impl BudgetScope {
+    pub(crate) fn enter(budget: &Rc<MemoryBudget>) -> BudgetScope {
+        BudgetScope(BUDGET.with(|current| current.replace(Some(budget.clone()))))
+    }
+}
+
+impl Drop for BudgetScope {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        let _ = BUDGET.try_with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// An amount of memory charged to a budget: the one that was current when the charge was created
+/// or, for charges created outside of any budget, the one that is current when it first grows. It
+/// can be resized, and it is credited back when dropped.
+#[derive(Debug)]
+pub(crate) struct Charge {
+    budget: RefCell<Option<Rc<MemoryBudget>>>,
+    bytes: Cell<u64>,
+}
+
+impl Charge {
+    pub(crate) fn new(bytes: u64) -> Charge {
+        let charge = Charge { budget: RefCell::new(None), bytes: Cell::new(0) };
+        charge.resize(bytes);
+        charge
+    }
+
+    /// The number of bytes charged.
+    pub(crate) fn bytes(&self) -> u64 {
+        self.bytes.get()
+    }
+
+    /// Change the number of bytes charged.
+    pub(crate) fn resize(&self, bytes: u64) {
+        let mut budget = self.budget.borrow_mut();
+        if budget.is_none() && bytes > 0 {
+            *budget = BUDGET.try_with(|current| current.borrow().clone()).ok().flatten();
+        }
+        if let Some(budget) = budget.as_ref() {
+            budget.used.set(budget.used.get() - self.bytes.get() + bytes);
+            self.bytes.set(bytes);
+        }
+    }
+}
+
+impl Drop for Charge {
+    fn drop(&mut self) {
+        self.resize(0);
+    }
+}
+
+/// Charges the same amount again.
+impl Clone for Charge {
+    fn clone(&self) -> Charge {
+        Charge::new(self.bytes())
+    }
+}
+
+// Charges are not part of the values that own them.
+impl PartialEq for Charge {
+    fn eq(&self, _other: &Charge) -> bool {
+        true
+    }
+}
+
+impl Eq for Charge {}
+
+impl PartialOrd for Charge {
+    fn partial_cmp(&self, other: &Charge) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Charge {
+    fn cmp(&self, _other: &Charge) -> std::cmp::Ordering {
+        std::cmp::Ordering::Equal
+    }
+}
+
+/// The estimated size of a value that is stored in a collection or an environment, not counting
+/// the collections it refers to.
+pub(crate) fn slot_size() -> u64 {
+    mem::size_of::<Value>() as u64
+}
+
+/// The estimated size of a value appended to an array: a slot, and the payload of strings and
+/// bytes.
+pub(crate) fn element_size(v: &Value) -> u64 {
+    slot_size() + match v {
+        Value::String(s) => s.len_bytes() as u64,
+        Value::Bytes(b) => b.len() as u64,
+        _ => 0,
+    }
+}
+
+/// The host value of the value that is thrown once a memory budget is exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfMemory {
+    /// The estimated usage when the value was thrown.
+    pub used: u64,
+    /// The limit of the budget.
+    pub limit: u64,
+}
+
+impl PanUserdata for OutOfMemory {
+    fn type_name(&self) -> &'static str {
+        "out_of_memory"
+    }
+}
+
+/// The value to throw if the current budget of the thread is exceeded.
+pub(crate) fn out_of_memory() -> Option<Value> {
+    BUDGET.with(|current| match current.borrow().as_ref() {
+        Some(budget) if budget.is_exceeded() => Some(out_of_memory_value(OutOfMemory {
+            used: budget.used(),
+            limit: budget.limit().unwrap_or(0),
+        })),
+        _ => None,
+    })
+}
+
+/// A new out-of-memory value, to be thrown.
+pub fn out_of_memory_value(oom: OutOfMemory) -> Value {
+    Value::Userdata(Userdata::new(oom))
+}
+
+/// The usage and limit of an out-of-memory value, or `None` if the value is not one.
+pub fn out_of_memory_of(v: &Value) -> Option<OutOfMemory> {
+    match v {
+        Value::Userdata(u) => u.with(|oom: &OutOfMemory| *oom),
+        _ => None,
+    }
+}
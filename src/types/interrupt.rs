@@ -0,0 +1,214 @@
+// Stopping running pan code from another thread.
+//
+// Every runtime has an interrupt flag, and hands out `InterruptHandle`s to it that can be sent to
+// other threads. Setting the flag does not stop anything by itself: the runtime checks it when
+// it starts to run code for the embedder, the interpreter checks the flag of the runtime it runs
+// in every `CHECK_INTERVAL` instructions, and native functions can check it through their
+// `HostCtx`. Whoever notices the flag first clears it and throws an
+// interrupted value. Like exit values, interrupted values pass through all catch regions, so they
+// unwind to the embedder. The runtime can be used again afterwards.
+//
+// The flag of the runtime that is currently running code is kept in a thread-local, like the
+// memory budget (see `types::heap`).
+//
+// Deadlines stop running code once a point in time has passed (see `Runtime::call_with_deadline`
+// and `Runtime::block_on_with_deadline`). Time is measured by the clock of the event loop of the
+// runtime, so a `ManualClock` makes deadlines deterministic. The interpreter checks the deadline
+// whenever it checks the interrupt flag, natives can ask their `HostCtx` for the remaining time,
+// and `block_on` checks it between the turns of the event loop. Code that misses its deadline
+// throws a deadline-exceeded value, which unwinds to the embedder like an interrupted value.
+
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::types::futures::Clock;
+
+use crate::types::userdata::{PanUserdata, Userdata};
+use crate::value::Value;
+
+/// The number of instructions the interpreter executes between two checks of the interrupt flag.
+pub const CHECK_INTERVAL: usize = 1024;
+
+/// Interrupts the code that a runtime is running, from any thread, see `Runtime::interrupt_handle`.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    pub(crate) fn new() -> InterruptHandle {
+        InterruptHandle::default()
+    }
+
+    /// Ask the runtime to stop the code it is running. If it is not running anything, the next
+    /// code it runs is interrupted instead.
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns whether an interruption has been asked for and not yet noticed.
+    pub fn is_interrupted(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Clear the flag, and fail with an interrupted value if it was set.
+    pub(crate) fn check(&self) -> Result<(), Value> {
+        if self.0.swap(false, Ordering::SeqCst) {
+            Err(interrupted_value())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The host value of an interrupted value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interrupted;
+
+impl PanUserdata for Interrupted {
+    fn type_name(&self) -> &'static str {
+        "interrupted"
+    }
+}
+
+/// A new interrupted value, to be thrown.
+pub fn interrupted_value() -> Value {
+    Value::Userdata(Userdata::new(Interrupted))
+}
+
+/// Returns whether the value is an interrupted value.
+pub fn is_interrupted(v: &Value) -> bool {
+    match v {
+        Value::Userdata(u) => u.with(|_: &Interrupted| ()).is_some(),
+        _ => false,
+    }
+}
+
+/// The host value of a deadline-exceeded value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadlineExceeded {
+    /// How long the code ran until the deadline was noticed, in milliseconds.
+    pub elapsed_millis: u64,
+    /// How long the code was allowed to run, in milliseconds.
+    pub limit_millis: u64,
+}
+
+impl PanUserdata for DeadlineExceeded {
+    fn type_name(&self) -> &'static str {
+        "deadline_exceeded"
+    }
+}
+
+/// A new deadline-exceeded value, to be thrown.
+pub fn deadline_exceeded_value(exceeded: DeadlineExceeded) -> Value {
+    Value::Userdata(Userdata::new(exceeded))
+}
+
+/// The durations of a deadline-exceeded value, or `None` if the value is not one.
+pub fn deadline_exceeded_of(v: &Value) -> Option<DeadlineExceeded> {
+    match v {
+        Value::Userdata(u) => u.with(|exceeded: &DeadlineExceeded| *exceeded),
+        _ => None,
+    }
+}
+
+/// A point in time on a clock, by which code has to be done.
+#[derive(Clone)]
+pub(crate) struct Deadline {
+    clock: Rc<dyn Clock>,
+    start: u64,
+    end: u64,
+}
+
+impl Deadline {
+    /// The deadline that lies `limit` after the current time of the clock.
+    pub(crate) fn after(clock: Rc<dyn Clock>, limit: Duration) -> Deadline {
+        let start = clock.now();
+        let limit = u64::try_from(limit.as_millis()).unwrap_or(u64::MAX);
+        Deadline { clock, start, end: start.saturating_add(limit) }
+    }
+
+    /// The time of the clock at which the deadline passes.
+    pub(crate) fn end(&self) -> u64 {
+        self.end
+    }
+
+    /// The time left until the deadline passes, zero if it has passed.
+    pub(crate) fn remaining(&self) -> Duration {
+        Duration::from_millis(self.end.saturating_sub(self.clock.now()))
+    }
+
+    /// Fail with a deadline-exceeded value if the deadline has passed.
+    pub(crate) fn check(&self) -> Result<(), DeadlineExceeded> {
+        let now = self.clock.now();
+        if now >= self.end {
+            Err(DeadlineExceeded {
+                elapsed_millis: now - self.start,
+                limit_millis: self.end - self.start,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<InterruptHandle>> = const { RefCell::new(None) };
+    static DEADLINE: RefCell<Option<Deadline>> = const { RefCell::new(None) };
+}
+
+/// Makes the flag of a runtime the current one of the thread until it is dropped, then restores
+/// the previous one.
+pub(crate) struct InterruptScope(Option<InterruptHandle>);
+
+impl InterruptScope {
+    pub(crate) fn enter(handle: &InterruptHandle) -> InterruptScope {
+        InterruptScope(CURRENT.with(|current| current.replace(Some(handle.clone()))))
+    }
+}
+
+impl Drop for InterruptScope {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        let _ = CURRENT.try_with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Makes a deadline the current one of the thread until it is dropped, then restores the previous
+/// one.
+pub(crate) struct DeadlineScope(Option<Deadline>);
+
+impl DeadlineScope {
+    pub(crate) fn enter(deadline: Deadline) -> DeadlineScope {
+        DeadlineScope(DEADLINE.with(|current| current.replace(Some(deadline))))
+    }
+}
+
+impl Drop for DeadlineScope {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        let _ = DEADLINE.try_with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Clear the current flag of the thread, and fail with an interrupted value if it was set, or
+/// with a deadline-exceeded value if the current deadline has passed.
+pub(crate) fn check() -> Result<(), Value> {
+    CURRENT.with(|current| current.borrow().as_ref().map_or(Ok(()), InterruptHandle::check))?;
+    check_deadline()
+}
+
+/// Fail with a deadline-exceeded value if the current deadline of the thread has passed.
+pub(crate) fn check_deadline() -> Result<(), Value> {
+    DEADLINE.with(|current| match current.borrow().as_ref() {
+        Some(deadline) => deadline.check().map_err(deadline_exceeded_value),
+        None => Ok(()),
+    })
+}
+
+/// The time left until the current deadline of the thread passes, `None` if there is none.
+pub(crate) fn remaining_time() -> Option<Duration> {
+    DEADLINE.with(|current| current.borrow().as_ref().map(Deadline::remaining))
+}
@@ -0,0 +1,237 @@
+// Iterators produce the elements of a container one at a time, synchronously.
+//
+// Every call to `next` yields either an element or the end of the iteration. Once an iterator has
+// ended, it stays ended. The iterators over the builtin containers do not copy them, they keep a
+// position instead:
+//
+// - Arrays are iterated by index. Each step reads the element at the current index, so it sees
+//   changes to elements that have not been visited yet. The iteration ends at the length the array
+//   had when the iterator was created, or earlier if the array shrinks below the current index.
+// - Sets (and maps) are iterated in ascending order. Each step yields the least member (or entry,
+//   as a two-element array of the key and the value) that is greater than the previously yielded
+//   one, in the current state of the set. So members that are inserted during the iteration are
+//   visited if they are greater than the last visited one, and removed members that were not
+//   visited yet are skipped.
+// - Strings (by char) and bytes (by byte, as ints) can not be mutated, so they are simply
+//   traversed.
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Bound::Excluded;
+
+use gc::{Gc, GcCell, Trace};
+use gc_derive::{Trace, Finalize};
+
+use crate::types::{
+    bytes::Bytes,
+    collection::Collection,
+    persistent::{PersistentMap, PersistentSet},
+    rope::Rope,
+};
+use crate::value::Value;
+
+/// A source of elements for an `Iter`. Implement this to provide iterators from rust.
+pub trait PanIter: Trace {
+    /// The next element, or `None` if the iteration has ended. Must keep returning `None` after
+    /// the end. The iterator is borrowed mutably during the call, so if this calls back into pan
+    /// code that advances the same iterator, that throws an error of kind
+    /// `"concurrent-modification"`.
+    fn next(&mut self) -> Result<Option<Value>, Value>;
+}
+
+/// A pan iterator. Clones refer to the same iterator.
+#[derive(Clone, Trace, Finalize)]
+pub struct Iter(Gc<GcCell<Box<dyn PanIter>>>);
+
+impl Iter {
+    pub fn new(iter: impl PanIter + 'static) -> Iter {
+        Iter(Gc::new(GcCell::new(Box::new(iter))))
+    }
+
+    /// An iterator over a value, or `None` if the value can not be iterated. Iterating an
+    /// iterator yields the iterator itself.
+    pub fn of(v: &Value) -> Option<Iter> {
+        Some(match v {
+            Value::Array(arr) => {
+                let end = arr.borrow().len();
+                Iter::new(ArrayIter { arr: arr.clone(), index: 0, end })
+            }
+            Value::Set(set) => Iter::new(SetIter { set: set.clone(), last: None, done: false }),
+            Value::Map(map) => Iter::new(MapIter { map: map.clone(), last: None, done: false }),
+            Value::String(s) => Iter::new(StringIter { s: s.clone(), index: 0 }),
+            Value::Bytes(b) => Iter::new(BytesIter { b: b.clone(), index: 0 }),
+            Value::Iter(iter) => iter.clone(),
+            _ => return None,
+        })
+    }
+
+    /// The next element, see `PanIter::next`. Throws an error of kind `"concurrent-modification"`
+    /// if called while the iterator is already producing an element.
+    pub fn next(&self) -> Result<Option<Value>, Value> {
+        match self.0.try_borrow_mut() {
+            Ok(mut iter) => iter.next(),
+            Err(_) => Err(Value::error(
+                "concurrent-modification",
+                "cannot advance an iterator while it is producing an element",
+            )),
+        }
+    }
+
+    /// Returns whether both values refer to the same iterator.
+    pub fn ptr_eq(a: &Iter, b: &Iter) -> bool {
+        Gc::ptr_eq(&a.0, &b.0)
+    }
+
+    fn address(&self) -> usize {
+        &*self.0 as *const GcCell<Box<dyn PanIter>> as usize
+    }
+}
+
+#[derive(Trace, Finalize)]
+struct ArrayIter {
+    arr: Gc<Collection<Vec<Value>>>,
+    index: usize,
+    end: usize,
+}
+
+impl PanIter for ArrayIter {
+    fn next(&mut self) -> Result<Option<Value>, Value> {
+        if self.index >= self.end {
+            return Ok(None);
+        }
+        match self.arr.borrow().get(self.index) {
+            Some(element) => {
+                self.index += 1;
+                Ok(Some(element.clone()))
+            }
+            None => {
+                self.end = self.index;
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[derive(Trace, Finalize)]
+struct SetIter {
+    set: Gc<Collection<PersistentSet<Value>>>,
+    last: Option<Value>,
+    done: bool,
+}
+
+impl PanIter for SetIter {
+    fn next(&mut self) -> Result<Option<Value>, Value> {
+        if self.done {
+            return Ok(None);
+        }
+        let next = {
+            let set = self.set.borrow();
+            match &self.last {
+                None => set.iter().next().cloned(),
+                Some(last) => set.range_from(Excluded(last)).next().cloned(),
+            }
+        };
+        self.done = next.is_none();
+        self.last = next.clone();
+        Ok(next)
+    }
+}
+
+#[derive(Trace, Finalize)]
+struct MapIter {
+    map: Gc<Collection<PersistentMap<Value, Value>>>,
+    last: Option<Value>,
+    done: bool,
+}
+
+impl PanIter for MapIter {
+    fn next(&mut self) -> Result<Option<Value>, Value> {
+        if self.done {
+            return Ok(None);
+        }
+        let next = {
+            let map = self.map.borrow();
+            let mut entries = match &self.last {
+                None => map.iter(),
+                Some(last) => map.range_from(Excluded(last)),
+            };
+            entries.next().map(|(k, v)| (k.clone(), v.clone()))
+        };
+        match next {
+            Some((k, v)) => {
+                self.last = Some(k.clone());
+                Ok(Some(Value::array(vec![k, v])))
+            }
+            None => {
+                self.done = true;
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[derive(Trace, Finalize)]
+struct StringIter {
+    s: Rope,
+    index: usize,
+}
+
+impl PanIter for StringIter {
+    fn next(&mut self) -> Result<Option<Value>, Value> {
+        let c = self.s.get(self.index);
+        if c.is_some() {
+            self.index += 1;
+        }
+        Ok(c.map(Value::Char))
+    }
+}
+
+#[derive(Trace, Finalize)]
+struct BytesIter {
+    b: Bytes,
+    index: usize,
+}
+
+impl PanIter for BytesIter {
+    fn next(&mut self) -> Result<Option<Value>, Value> {
+        let byte = self.b.get(self.index);
+        if byte.is_some() {
+            self.index += 1;
+        }
+        Ok(byte.map(|byte| Value::Int(i64::from(byte))))
+    }
+}
+
+impl fmt::Debug for Iter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Iter")
+    }
+}
+
+// Iterators are compared by identity.
+impl PartialEq for Iter {
+    fn eq(&self, other: &Iter) -> bool {
+        Iter::ptr_eq(self, other)
+    }
+}
+
+impl Eq for Iter {}
+
+impl PartialOrd for Iter {
+    fn partial_cmp(&self, other: &Iter) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Iter {
+    fn cmp(&self, other: &Iter) -> std::cmp::Ordering {
+        self.address().cmp(&other.address())
+    }
+}
+
+// Consistent with the comparison by identity.
+impl Hash for Iter {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.address().hash(state);
+    }
+}
@@ -0,0 +1,78 @@
+// Panics in native functions.
+//
+// A rust function called by pan code that panics would otherwise unwind through the interpreter,
+// past every catch region of the pan code that called it, and out of the runtime entry point. So
+// calls of natives catch panics, and turn them into a thrown error of kind `"native-panic"` whose
+// `data` is a map with the `name` of the native and the panic `message` (`nil` unless the panic
+// payload is a string). Pan code can catch it like any other error, and the runtime stays usable.
+//
+// Natives are not required to be `UnwindSafe`: a native that panics halfway through mutating the
+// state it captured may leave that state inconsistent, which is the responsibility of the host.
+// The state of the interpreter and the event loop is only ever borrowed for the duration of
+// single operations, so unwinding through a native does not leave it in an inconsistent state.
+//
+// Hosts that prefer panics to propagate (e.g. to fail fast in tests) can enable strict mode for a
+// runtime (see `Runtime::set_strict_panics`), in which the panic unwinds to the embedder as is.
+// Whether the runtime that is currently running code is strict is kept in a thread-local, like
+// the memory budget (see `types::heap`).
+//
+// Panics while polling rust futures are handled by the event loop, they reject the future with the
+// same kind of error, whose `name` is `nil`.
+
+use std::any::Any;
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::value::Value;
+
+thread_local! {
+    static STRICT: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Makes the strictness of a runtime the current one of the thread until it is dropped, then
+/// restores the previous one.
+pub(crate) struct StrictScope(bool);
+
+impl StrictScope {
+    pub(crate) fn enter(strict: bool) -> StrictScope {
+        StrictScope(STRICT.with(|current| current.replace(strict)))
+    }
+}
+
+impl Drop for StrictScope {
+    fn drop(&mut self) {
+        let previous = self.0;
+        let _ = STRICT.try_with(|current| current.set(previous));
+    }
+}
+
+/// Call the native of the given name, turning a panic into a thrown error unless the current
+/// runtime is strict.
+pub(crate) fn call<T>(name: &str, f: impl FnOnce() -> Result<T, Value>) -> Result<T, Value> {
+    if STRICT.with(Cell::get) {
+        return f();
+    }
+    panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|payload| Err(error(Some(name), payload)))
+}
+
+/// The value thrown for a panic of the native of the given name, or, without a name, the value
+/// that a rust future rejects with if polling it panicked.
+pub(crate) fn error(name: Option<&str>, payload: Box<dyn Any + Send>) -> Value {
+    let message = match payload.downcast::<String>() {
+        Ok(message) => Some(*message),
+        Err(payload) => payload.downcast::<&'static str>().ok().map(|message| message.to_string()),
+    };
+    let what = match name {
+        Some(name) => format!("native function {}", name),
+        None => "a rust future".to_string(),
+    };
+    let description = match &message {
+        Some(message) => format!("{} panicked: {}", what, message),
+        None => format!("{} panicked", what),
+    };
+    Value::error_with_data("native-panic", &description, Value::record(vec![
+        ("name", name.map_or(Value::Nil, Value::from)),
+        ("message", message.as_deref().map_or(Value::Nil, Value::from)),
+    ]))
+}
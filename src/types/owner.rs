@@ -0,0 +1,137 @@
+// Which runtime a value belongs to.
+//
+// Values are built from `Rc` and `Gc` pointers, so they (like runtimes) can not leave the thread
+// that created them. A thread can run several runtimes though, and nothing in the types keeps a
+// value created by one runtime from being handed to another one, where it would give pan code
+// access to state of the first runtime (e.g. its io through a builtin, or its globals through a
+// closure). To keep runtimes isolated, every runtime has an id, and the values created while it
+// runs code or registers builtins are tagged with it: arrays, sets and maps, the environments of
+// closures, and rust functions. The entry points of a runtime reject values tagged with a different
+// id, as do the builtins that store values in collections.
+//
+// Values created outside of any runtime (e.g. by the embedder) are not tagged and can be used with
+// every runtime, as can strings, bytes, numbers and the other values without state. Futures,
+// channels, streams and userdata are not tagged.
+//
+// The id of the runtime that is currently running code is kept in a thread-local, like the memory
+// budget (see `types::heap`).
+//
+// Every runtime also stamps the functions it creates (pan closures and rust functions) with
+// numbers that count up from 1, see `Fun::id`. Functions are ordered by their stamps, so that sets
+// of functions and maps with function keys iterate in the order in which the functions were
+// created, independently of where they were allocated. Stamps are only unique within a runtime
+// (functions created outside of any runtime share a counter of their thread), and the same code
+// only gets the same stamps if it runs in the same order; they mean nothing across processes.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use failure_derive::Fail;
+
+use crate::value::Value;
+
+/// The id of a runtime, `NONE` for values that do not belong to any runtime.
+pub type RuntimeId = u32;
+
+/// The id of no runtime.
+pub const NONE: RuntimeId = 0;
+
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+/// A fresh id for a new runtime.
+pub(crate) fn next_id() -> RuntimeId {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The next stamp of a runtime.
+pub(crate) type Stamps = Rc<Cell<u64>>;
+
+thread_local! {
+    static CURRENT: Cell<RuntimeId> = const { Cell::new(NONE) };
+    static STAMPS: RefCell<Stamps> = RefCell::new(Rc::new(Cell::new(1)));
+}
+
+/// The id of the runtime that is currently running code on this thread, if any.
+pub fn current() -> RuntimeId {
+    CURRENT.with(Cell::get)
+}
+
+/// A fresh stamp of the runtime that is currently running code on this thread, for a new
+/// function.
+pub(crate) fn stamp() -> u64 {
+    STAMPS.with(|stamps| {
+        let stamps = stamps.borrow();
+        stamps.replace(stamps.get() + 1)
+    })
+}
+
+/// Makes a runtime the current one of the thread until it is dropped, then restores the previous
+/// one.
+pub(crate) struct OwnerScope(RuntimeId, Option<Stamps>);
+
+impl OwnerScope {
+    pub(crate) fn enter(id: RuntimeId, stamps: &Stamps) -> OwnerScope {
+        OwnerScope(
+            CURRENT.with(|current| current.replace(id)),
+            Some(STAMPS.with(|current| current.replace(stamps.clone()))),
+        )
+    }
+}
+
+impl Drop for OwnerScope {
+    fn drop(&mut self) {
+        let previous = self.0;
+        let _ = CURRENT.try_with(|current| current.set(previous));
+        if let Some(stamps) = self.1.take() {
+            let _ = STAMPS.try_with(|current| current.replace(stamps));
+        }
+    }
+}
+
+/// A value of one runtime was used with another one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Fail)]
+#[fail(display = "a value of runtime {} can not be used in runtime {}", found, expected)]
+pub struct CrossRuntimeError {
+    /// The runtime the value was used with.
+    pub expected: RuntimeId,
+    /// The runtime the value belongs to.
+    pub found: RuntimeId,
+}
+
+impl CrossRuntimeError {
+    /// The error for pan code: an error of kind `"runtime"`.
+    pub fn to_value(self) -> Value {
+        Value::error("runtime", &self.to_string())
+    }
+}
+
+/// Fail if the value belongs to a runtime other than `runtime`. Values that belong to no runtime
+/// can be used everywhere, and so can every value by a `runtime` of `NONE`.
+pub fn check(runtime: RuntimeId, v: &Value) -> Result<(), CrossRuntimeError> {
+    match v.owner() {
+        found if found == NONE || runtime == NONE || found == runtime => Ok(()),
+        found => Err(CrossRuntimeError { expected: runtime, found }),
+    }
+}
+
+/// Like `check`, but throws the error for pan code, for builtins that store `v` in `container`.
+pub fn check_stored(container: RuntimeId, v: &Value) -> Result<(), Value> {
+    check(container, v).map_err(CrossRuntimeError::to_value)
+}
+
+// Values and runtimes must stay on their thread. This fails to compile if one of the types
+// becomes `Send` or `Sync`, as the method call is ambiguous then.
+#[allow(dead_code)]
+fn assert_thread_confined() {
+    trait AmbiguousIfSendOrSync<A> {
+        fn some_item() {}
+    }
+    impl<T: ?Sized> AmbiguousIfSendOrSync<()> for T {}
+    impl<T: ?Sized + Send> AmbiguousIfSendOrSync<u8> for T {}
+    impl<T: ?Sized + Sync> AmbiguousIfSendOrSync<u16> for T {}
+
+    let _ = <crate::runtime::Runtime as AmbiguousIfSendOrSync<_>>::some_item;
+    let _ = <Value as AmbiguousIfSendOrSync<_>>::some_item;
+    let _ = <crate::types::futures::Job as AmbiguousIfSendOrSync<_>>::some_item;
+}
@@ -0,0 +1,168 @@
+// A compact representation of values, behind the experimental `nan-boxing` feature.
+//
+// A `PackedValue` is a single `u64`. Floats are stored as their bits. All other values are stored
+// in bit patterns of negative quiet NaNs: the top 13 bits are set, the next 3 bits are a tag, and
+// the low 48 bits are the payload. Nil, bools, chars and ints that fit into 48 bits are stored
+// inline. All other values are boxed, and the payload is the address of the box (shifted right by
+// 3, boxes of values are 8-byte aligned). This includes ints that do not fit into 48 bits and the
+// floats whose bits look like the tagged patterns, so converting between `Value` and
+// `PackedValue` is lossless. Boxes are reference counted, so cloning a packed value never
+// allocates, only packing a value that is not inline does.
+//
+// Boxed values keep their Gc handles, which stay rooted while the value is boxed. So packed values
+// must not be stored in the gc heap, where they would keep what they refer to alive forever. The
+// interpreter only uses them for the temporary storage of executions.
+//
+// So far this does not pay off: with `benches/interpreter.rs`, every workload is slower with the
+// feature than without, by about a third for the numeric loop. Values still cross into the
+// storage and out of it as `Value`s, for every call and every instruction, and packing and
+// unpacking costs more than the smaller slots save.
+
+use std::marker::PhantomData;
+use std::mem;
+use std::rc::Rc;
+
+use ordered_float::OrderedFloat;
+
+use crate::value::Value;
+
+// The bits that are set in all tagged patterns.
+const TAGGED: u64 = 0xFFF8_0000_0000_0000;
+const PAYLOAD: u64 = (1 << 48) - 1;
+const TAG_SHIFT: u32 = 48;
+
+const NIL: u64 = 1;
+const BOOL: u64 = 2;
+const CHAR: u64 = 3;
+const INT: u64 = 4;
+const BOXED: u64 = 5;
+
+// The address of a box is stored shifted by 3, which requires boxes to be 8-byte aligned.
+const _: () = assert!(mem::align_of::<Value>() >= 8);
+
+/// A value packed into 64 bits, see the module documentation.
+pub struct PackedValue {
+    bits: u64,
+    // Boxed values are neither `Send` nor `Sync`, so neither are packed values.
+    _value: PhantomData<Value>,
+}
+
+impl PackedValue {
+    /// Pack a value.
+    ///
+    /// Panics if a boxed value is allocated at an address above 2^51, which the address spaces of
+    /// current 64 bit platforms do not reach by default.
+    pub fn new(v: Value) -> PackedValue {
+        match v {
+            Value::Nil => PackedValue::tagged(NIL, 0),
+            Value::Bool(b) => PackedValue::tagged(BOOL, b as u64),
+            Value::Char(c) => PackedValue::tagged(CHAR, c as u64),
+            Value::Int(n) if n == (n << 16) >> 16 => PackedValue::tagged(INT, n as u64 & PAYLOAD),
+            Value::Float(f) if f.to_bits() & TAGGED != TAGGED => {
+                PackedValue::from_bits(f.to_bits())
+            }
+            other => {
+                let address = Rc::into_raw(Rc::new(other)) as u64;
+                assert!(
+                    address >> 3 <= PAYLOAD,
+                    "a boxed value was allocated at an address that does not fit a packed value",
+                );
+                PackedValue::tagged(BOXED, address >> 3)
+            }
+        }
+    }
+
+    /// A copy of the value.
+    pub fn to_value(&self) -> Value {
+        match self.tag() {
+            None => Value::Float(OrderedFloat(f64::from_bits(self.bits))),
+            Some(NIL) => Value::Nil,
+            Some(BOOL) => Value::Bool(self.payload() != 0),
+            Some(CHAR) => Value::Char(
+                std::char::from_u32(self.payload() as u32).expect("packed chars are valid"),
+            ),
+            Some(INT) => Value::Int(((self.payload() << 16) as i64) >> 16),
+            _ => self.boxed().clone(),
+        }
+    }
+
+    /// The value, without copying a boxed one.
+    pub fn into_value(self) -> Value {
+        if self.tag() != Some(BOXED) {
+            return self.to_value();
+        }
+        let boxed = unsafe { Rc::from_raw(self.address()) };
+        mem::forget(self);
+        Rc::try_unwrap(boxed).unwrap_or_else(|shared| (*shared).clone())
+    }
+
+    /// Whether the value is stored inline rather than boxed.
+    pub fn is_inline(&self) -> bool {
+        self.tag() != Some(BOXED)
+    }
+
+    fn tagged(tag: u64, payload: u64) -> PackedValue {
+        PackedValue::from_bits(TAGGED | (tag << TAG_SHIFT) | payload)
+    }
+
+    fn from_bits(bits: u64) -> PackedValue {
+        PackedValue { bits, _value: PhantomData }
+    }
+
+    // The tag, or `None` for floats.
+    fn tag(&self) -> Option<u64> {
+        if self.bits & TAGGED == TAGGED {
+            Some((self.bits >> TAG_SHIFT) & 0b111)
+        } else {
+            None
+        }
+    }
+
+    fn payload(&self) -> u64 {
+        self.bits & PAYLOAD
+    }
+
+    fn address(&self) -> *const Value {
+        (self.payload() << 3) as *const Value
+    }
+
+    fn boxed(&self) -> &Value {
+        // This holds a reference to the box, which lives at least as long as it.
+        unsafe { &*self.address() }
+    }
+}
+
+impl Clone for PackedValue {
+    fn clone(&self) -> PackedValue {
+        if !self.is_inline() {
+            unsafe { Rc::increment_strong_count(self.address()) };
+        }
+        PackedValue::from_bits(self.bits)
+    }
+}
+
+impl Drop for PackedValue {
+    fn drop(&mut self) {
+        if !self.is_inline() {
+            drop(unsafe { Rc::from_raw(self.address()) });
+        }
+    }
+}
+
+impl Default for PackedValue {
+    fn default() -> PackedValue {
+        PackedValue::tagged(NIL, 0)
+    }
+}
+
+impl From<Value> for PackedValue {
+    fn from(v: Value) -> PackedValue {
+        PackedValue::new(v)
+    }
+}
+
+impl std::fmt::Debug for PackedValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_tuple("PackedValue").field(&self.to_value()).finish()
+    }
+}
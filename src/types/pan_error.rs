@@ -0,0 +1,284 @@
+// Building and inspecting error values from rust.
+//
+// A `PanError` is the rust side of the errors that builtins throw (see `builtins::error`): a kind,
+// a message, optional data and an optional source, the error that caused it. `into_value` turns it
+// into the map that pan code sees, with the source nested as a map in the `source` field, and
+// `from_thrown` goes the other way.
+//
+// Natives that return `Result<Value, PanError>` can use `?` on io errors, utf-8 errors and the
+// error types of this crate, and on whatever a pan callback threw (`From<Value>`). `adapt` and
+// `adapt_host` turn such natives into functions that `Runtime::register` and
+// `Runtime::register_native` accept.
+//
+// A `PanError` made from a thrown value remembers that value, and `into_value` returns it
+// unchanged unless `data` or `source` are set afterwards. So errors of callbacks keep their
+// identity, and values that are not errors at all (exit, interrupted, out-of-memory and
+// deadline-exceeded values, or anything else pan code throws) are rethrown as they were. Such
+// values have the kind `"thrown"`, and their rendering as the message.
+
+use std::fmt;
+use std::io;
+use std::str::Utf8Error;
+use std::string::FromUtf8Error;
+
+use crate::runtime::{HostCtx, RunError};
+use crate::types::bytes::DecodeError;
+use crate::types::owner::CrossRuntimeError;
+use crate::types::rope::{ReadError, Rope, RopeError};
+use crate::value::Value;
+
+/// An error for pan code, see the module documentation.
+#[derive(Debug, Clone)]
+pub struct PanError(Box<Fields>);
+
+// The fields of a `PanError`, boxed so that results with a `PanError` stay small.
+#[derive(Debug, Clone)]
+struct Fields {
+    kind: String,
+    message: String,
+    data: Option<Value>,
+    source: Option<PanError>,
+    // The value this was made from, if it has not been modified since.
+    thrown: Option<Value>,
+}
+
+impl PanError {
+    /// A new error with the given kind and message.
+    pub fn new(kind: &str, message: impl Into<String>) -> PanError {
+        PanError(Box::new(Fields {
+            kind: kind.to_string(),
+            message: message.into(),
+            data: None,
+            source: None,
+            thrown: None,
+        }))
+    }
+
+    /// Set the `data` field of the error.
+    pub fn data(mut self, data: Value) -> PanError {
+        self.0.data = Some(data);
+        self.0.thrown = None;
+        self
+    }
+
+    /// Set the error that caused this one, which becomes the `source` field of the error.
+    pub fn source(mut self, source: PanError) -> PanError {
+        self.0.source = Some(source);
+        self.0.thrown = None;
+        self
+    }
+
+    /// The kind of the error.
+    pub fn kind(&self) -> &str {
+        &self.0.kind
+    }
+
+    /// The message of the error.
+    pub fn message(&self) -> &str {
+        &self.0.message
+    }
+
+    /// The `data` of the error, if any.
+    pub fn payload(&self) -> Option<&Value> {
+        self.0.data.as_ref()
+    }
+
+    /// The error that caused this one, if any.
+    pub fn cause(&self) -> Option<&PanError> {
+        self.0.source.as_ref()
+    }
+
+    /// The value to throw: a map with the fields `kind`, `message`, and `data` and `source` if
+    /// they are set, or the value this error was made from.
+    pub fn into_value(self) -> Value {
+        let fields = *self.0;
+        if let Some(thrown) = fields.thrown {
+            return thrown;
+        }
+        let mut record = vec![
+            ("kind", Value::String(Rope::from_str(&fields.kind))),
+            ("message", Value::String(Rope::from_str(&fields.message))),
+        ];
+        if let Some(data) = fields.data {
+            record.push(("data", data));
+        }
+        if let Some(source) = fields.source {
+            record.push(("source", source.into_value()));
+        }
+        Value::record(record)
+    }
+
+    /// The error that a thrown value represents, or `None` if it is not a map with a string
+    /// `kind` and a string `message`. A `source` that is not an error itself becomes an error of
+    /// kind `"thrown"`, and a chain of sources that loops back on itself ends where it does.
+    pub fn from_thrown(thrown: &Value) -> Option<PanError> {
+        let mut chain = vec![];
+        let mut visited = vec![];
+        let mut next = Some(thrown.clone());
+        while let Some(current) = next.take() {
+            match &current {
+                Value::Map(map) => {
+                    let address = &**map as *const _ as usize;
+                    if visited.contains(&address) {
+                        break;
+                    }
+                    visited.push(address);
+                }
+                _ => {
+                    if chain.is_empty() {
+                        return None;
+                    }
+                    chain.push(PanError::other(current));
+                    break;
+                }
+            }
+            let (kind, message) = match (current.error_kind(), current.error_message()) {
+                (Some(kind), Some(message)) => (kind.to_string(), message.to_string()),
+                _ if chain.is_empty() => return None,
+                _ => {
+                    chain.push(PanError::other(current));
+                    break;
+                }
+            };
+            next = field(&current, "source");
+            chain.push(PanError(Box::new(Fields {
+                kind,
+                message,
+                data: field(&current, "data"),
+                source: None,
+                thrown: Some(current),
+            })));
+        }
+        chain.into_iter().rev().fold(None, |source, mut error: PanError| {
+            error.0.source = source;
+            Some(error)
+        })
+    }
+
+    // An error for a thrown value that is not an error.
+    fn other(thrown: Value) -> PanError {
+        let message = RunError::from_thrown(thrown.clone()).to_string();
+        let mut error = PanError::new("thrown", message);
+        error.0.thrown = Some(thrown);
+        error
+    }
+}
+
+impl fmt::Display for PanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.0.kind, self.0.message)
+    }
+}
+
+impl std::error::Error for PanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0.source.as_ref().map(|source| source as &(dyn std::error::Error + 'static))
+    }
+}
+
+impl From<PanError> for Value {
+    fn from(err: PanError) -> Value {
+        err.into_value()
+    }
+}
+
+/// Whatever pan code threw, so that natives can use `?` on the results of `HostCtx::call`.
+impl From<Value> for PanError {
+    fn from(thrown: Value) -> PanError {
+        PanError::from_thrown(&thrown).unwrap_or_else(|| PanError::other(thrown))
+    }
+}
+
+/// An error of kind `"io"`, with the kind of the io error (like `"not_found"`) in the `io_kind`
+/// field of its data, like the errors of the file system builtins.
+impl From<io::Error> for PanError {
+    fn from(err: io::Error) -> PanError {
+        PanError::new("io", err.to_string()).data(Value::record(vec![("io_kind", io_kind(&err))]))
+    }
+}
+
+/// An error of kind `"decode"`.
+impl From<Utf8Error> for PanError {
+    fn from(err: Utf8Error) -> PanError {
+        PanError::new("decode", err.to_string())
+    }
+}
+
+/// An error of kind `"decode"`.
+impl From<FromUtf8Error> for PanError {
+    fn from(err: FromUtf8Error) -> PanError {
+        PanError::new("decode", err.to_string())
+    }
+}
+
+/// An error of kind `"decode"`.
+impl From<DecodeError> for PanError {
+    fn from(err: DecodeError) -> PanError {
+        PanError::new("decode", err.to_string())
+    }
+}
+
+/// An error of kind `"range"`.
+impl From<RopeError> for PanError {
+    fn from(err: RopeError) -> PanError {
+        PanError::new("range", err.to_string())
+    }
+}
+
+/// Like the io error or decoding error, or an error of kind `"range"` if the limit was exceeded.
+impl From<ReadError> for PanError {
+    fn from(err: ReadError) -> PanError {
+        match err {
+            ReadError::Io(err) => PanError::from(err),
+            ReadError::InvalidUtf8 { .. } => PanError::new("decode", err.to_string()),
+            ReadError::LimitExceeded { .. } => PanError::new("range", err.to_string()),
+        }
+    }
+}
+
+/// An error of kind `"runtime"`, like `CrossRuntimeError::to_value`.
+impl From<CrossRuntimeError> for PanError {
+    fn from(err: CrossRuntimeError) -> PanError {
+        PanError::new("runtime", err.to_string())
+    }
+}
+
+/// The value that was thrown, see `RunError::into_value`.
+impl From<RunError> for PanError {
+    fn from(err: RunError) -> PanError {
+        PanError::from(err.into_value())
+    }
+}
+
+/// A native for `Runtime::register` from one that fails with a `PanError`.
+pub fn adapt(
+    fun: impl Fn(&[Value]) -> Result<Value, PanError> + 'static,
+) -> impl Fn(&[Value]) -> Result<Value, Value> + 'static {
+    move |args| fun(args).map_err(PanError::into_value)
+}
+
+/// A native for `Runtime::register_native` from one that fails with a `PanError`.
+pub fn adapt_host(
+    fun: impl Fn(&mut HostCtx, &[Value]) -> Result<Value, PanError> + 'static,
+) -> impl Fn(&mut HostCtx, &[Value]) -> Result<Value, Value> + 'static {
+    move |ctx, args| fun(ctx, args).map_err(PanError::into_value)
+}
+
+/// The kind of an io error as a string in snake case, like `"not_found"`.
+pub(crate) fn io_kind(err: &io::Error) -> Value {
+    let mut snake = String::new();
+    for c in format!("{:?}", err.kind()).chars() {
+        if c.is_ascii_uppercase() && !snake.is_empty() {
+            snake.push('_');
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    Value::String(Rope::from_str(&snake))
+}
+
+fn field(error: &Value, name: &str) -> Option<Value> {
+    match error {
+        Value::Map(map) => map.borrow().get(&Value::String(Rope::from_str(name))).cloned(),
+        _ => None,
+    }
+}
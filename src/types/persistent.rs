@@ -0,0 +1,1118 @@
+// Persistent ordered maps and sets, the contents of pan maps and sets.
+//
+// A `PersistentMap` is a B-tree whose nodes are reference counted. Mutations copy the nodes on the
+// path to the changed entry if they are shared, and change them in place otherwise, so a map that
+// is not shared behaves like a `BTreeMap`. A `PersistentSet` is a map with `()` values.
+//
+// Only nodes that contain no gc handles are ever shared. The gc roots its handles one by one, and
+// a handle that two collections reached through a shared node would be rooted or unrooted for
+// both of them at once. So cloning a map shares the subtrees whose entries are all `Shareable`
+// (for values: nil, bools, numbers, chars, strings and bytes) and copies the nodes above them.
+// Copying a map of such values takes constant time, whatever its size, and so do the snapshots
+// and deep copies of it (see `Value::deep_copy`). Tracing skips the shared subtrees entirely.
+//
+// Sharing is invisible to pan code: every copy can be mutated independently of the others, and
+// copies compare and hash like the original. Whether a collection may be mutated is decided by
+// freezing alone: the builtins throw when asked to mutate a frozen map, however it was made, and
+// `thaw` (see `Value::thaw`) returns a mutable copy of it, which takes constant time for maps of
+// plain data.
+//
+// Sharing costs some speed: looking up 100k random keys through `map_get` takes about 1.2 times
+// as long as with a `BTreeMap` (`cargo bench --bench collections`), while a snapshot of a runtime
+// whose global is a 100k-entry map takes 0.01 ms instead of 16 ms.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::iter::FromIterator;
+use std::mem;
+use std::ops::Bound;
+use std::rc::Rc;
+
+use gc::{custom_trace, Finalize, Trace};
+
+use crate::value::Value;
+
+// The minimum degree of the tree: nodes other than the root have between `B - 1` and `2 * B - 1`
+// entries.
+const B: usize = 8;
+const MAX: usize = 2 * B - 1;
+
+/// Whether persistent collections may share a key or value between copies, see the module
+/// documentation.
+pub trait Shareable {
+    /// Whether this contains no gc handles.
+    fn shareable(&self) -> bool;
+}
+
+impl Shareable for () {
+    fn shareable(&self) -> bool {
+        true
+    }
+}
+
+impl Shareable for Value {
+    fn shareable(&self) -> bool {
+        matches!(
+            self,
+            Value::Nil
+                | Value::Bool(_)
+                | Value::Int(_)
+                | Value::Float(_)
+                | Value::Char(_)
+                | Value::String(_)
+                | Value::Bytes(_)
+        )
+    }
+}
+
+#[derive(Clone)]
+struct Node<K, V> {
+    // Sorted, with the values at the same indices. They are kept apart so that searching a node
+    // only reads keys.
+    keys: Vec<K>,
+    values: Vec<V>,
+    // Empty for leaves, one more than the keys otherwise.
+    children: Vec<Rc<Node<K, V>>>,
+    // Whether all entries of this node and its descendants are shareable. Nodes that are not are
+    // never shared.
+    shareable: bool,
+}
+
+// Split `n` into `parts` sizes that differ by at most one.
+fn spread(n: usize, parts: usize) -> impl Iterator<Item = usize> {
+    (0..parts).map(move |i| n / parts + if i < n % parts { 1 } else { 0 })
+}
+
+impl<K: Ord + Clone + Shareable, V: Clone + Shareable> Node<K, V> {
+    fn empty() -> Node<K, V> {
+        Node { keys: vec![], values: vec![], children: vec![], shareable: true }
+    }
+
+    fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    // Recompute whether the node is shareable, after its entries or children changed.
+    fn update(&mut self) {
+        self.shareable = self.keys.iter().all(Shareable::shareable)
+            && self.values.iter().all(Shareable::shareable)
+            && self.children.iter().all(|child| child.shareable);
+    }
+
+    // Nodes are small, and a linear search is faster than a binary one, like in `BTreeMap`.
+    fn search(&self, key: &K) -> Result<usize, usize> {
+        for (i, k) in self.keys.iter().enumerate() {
+            match key.cmp(k) {
+                Ordering::Greater => {}
+                Ordering::Equal => return Ok(i),
+                Ordering::Less => return Err(i),
+            }
+        }
+        Err(self.keys.len())
+    }
+
+    fn insert_entry(&mut self, i: usize, (key, value): (K, V)) {
+        self.keys.insert(i, key);
+        self.values.insert(i, value);
+    }
+
+    fn remove_entry(&mut self, i: usize) -> (K, V) {
+        (self.keys.remove(i), self.values.remove(i))
+    }
+
+    fn replace_entry(&mut self, i: usize, (key, value): (K, V)) -> (K, V) {
+        (mem::replace(&mut self.keys[i], key), mem::replace(&mut self.values[i], value))
+    }
+
+    fn pop_entry(&mut self) -> (K, V) {
+        let key = self.keys.pop().expect("nodes are not empty");
+        let value = self.values.pop().expect("nodes are not empty");
+        (key, value)
+    }
+
+    fn push_entry(&mut self, (key, value): (K, V)) {
+        self.keys.push(key);
+        self.values.push(value);
+    }
+
+    // A copy that shares what may be shared, see the module documentation.
+    fn copy(node: &Rc<Node<K, V>>) -> Rc<Node<K, V>> {
+        Node::copy_with(node, &mut |k, v| (k.clone(), v.clone()))
+    }
+
+    // A copy whose entries are the results of `f`, which must keep their order. Shareable
+    // subtrees are shared rather than copied.
+    fn copy_with(node: &Rc<Node<K, V>>, f: &mut impl FnMut(&K, &V) -> (K, V)) -> Rc<Node<K, V>> {
+        if node.shareable {
+            return node.clone();
+        }
+        let mut copy = Node::empty();
+        for (k, v) in node.keys.iter().zip(node.values.iter()) {
+            copy.push_entry(f(k, v));
+        }
+        copy.children = node.children.iter().map(|child| Node::copy_with(child, f)).collect();
+        copy.update();
+        Rc::new(copy)
+    }
+
+    // Insert into a node that is not full.
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let result = match self.search(&key) {
+            Ok(i) => Some(mem::replace(&mut self.values[i], value)),
+            Err(i) if self.is_leaf() => {
+                self.insert_entry(i, (key, value));
+                None
+            }
+            Err(mut i) => {
+                if self.children[i].len() == MAX {
+                    self.split_child(i);
+                    match key.cmp(&self.keys[i]) {
+                        Ordering::Less => {}
+                        Ordering::Equal => {
+                            let old = mem::replace(&mut self.values[i], value);
+                            self.update();
+                            return Some(old);
+                        }
+                        Ordering::Greater => i += 1,
+                    }
+                }
+                Rc::make_mut(&mut self.children[i]).insert(key, value)
+            }
+        };
+        self.update();
+        result
+    }
+
+    // Split the full child at `i` into two, moving its median entry into this node.
+    fn split_child(&mut self, i: usize) {
+        let child = Rc::make_mut(&mut self.children[i]);
+        let mut right = Node {
+            keys: child.keys.split_off(B),
+            values: child.values.split_off(B),
+            children: if child.is_leaf() { vec![] } else { child.children.split_off(B) },
+            shareable: true,
+        };
+        right.update();
+        let median = child.pop_entry();
+        child.update();
+        self.insert_entry(i, median);
+        self.children.insert(i + 1, Rc::new(right));
+    }
+
+    // Remove from a node that has at least `B` entries, or is the root.
+    fn remove(&mut self, key: &K) -> Option<(K, V)> {
+        let result = match self.search(key) {
+            Ok(i) if self.is_leaf() => Some(self.remove_entry(i)),
+            Err(_) if self.is_leaf() => None,
+            Ok(i) => {
+                if self.children[i].len() >= B {
+                    let predecessor = Rc::make_mut(&mut self.children[i]).remove_last();
+                    Some(self.replace_entry(i, predecessor))
+                } else if self.children[i + 1].len() >= B {
+                    let successor = Rc::make_mut(&mut self.children[i + 1]).remove_first();
+                    Some(self.replace_entry(i, successor))
+                } else {
+                    self.merge(i);
+                    Rc::make_mut(&mut self.children[i]).remove(key)
+                }
+            }
+            Err(i) => {
+                let i = self.ensure_fat(i);
+                Rc::make_mut(&mut self.children[i]).remove(key)
+            }
+        };
+        self.update();
+        result
+    }
+
+    fn remove_first(&mut self) -> (K, V) {
+        let first = if self.is_leaf() {
+            self.remove_entry(0)
+        } else {
+            let i = self.ensure_fat(0);
+            Rc::make_mut(&mut self.children[i]).remove_first()
+        };
+        self.update();
+        first
+    }
+
+    fn remove_last(&mut self) -> (K, V) {
+        let last = if self.is_leaf() {
+            self.pop_entry()
+        } else {
+            let i = self.ensure_fat(self.children.len() - 1);
+            Rc::make_mut(&mut self.children[i]).remove_last()
+        };
+        self.update();
+        last
+    }
+
+    // Make sure the child at `i` has at least `B` entries, by moving an entry over from a sibling
+    // or merging it with one. Returns the new index of the child.
+    fn ensure_fat(&mut self, i: usize) -> usize {
+        if self.children[i].len() >= B {
+            return i;
+        }
+        if i > 0 && self.children[i - 1].len() >= B {
+            let left = Rc::make_mut(&mut self.children[i - 1]);
+            let entry = left.pop_entry();
+            let grandchild = left.children.pop();
+            left.update();
+            let separator = self.replace_entry(i - 1, entry);
+            let child = Rc::make_mut(&mut self.children[i]);
+            child.insert_entry(0, separator);
+            if let Some(grandchild) = grandchild {
+                child.children.insert(0, grandchild);
+            }
+            child.update();
+            i
+        } else if i + 1 < self.children.len() && self.children[i + 1].len() >= B {
+            let right = Rc::make_mut(&mut self.children[i + 1]);
+            let entry = right.remove_entry(0);
+            let grandchild = if right.is_leaf() { None } else { Some(right.children.remove(0)) };
+            right.update();
+            let separator = self.replace_entry(i, entry);
+            let child = Rc::make_mut(&mut self.children[i]);
+            child.push_entry(separator);
+            if let Some(grandchild) = grandchild {
+                child.children.push(grandchild);
+            }
+            child.update();
+            i
+        } else if i + 1 < self.children.len() {
+            self.merge(i);
+            i
+        } else {
+            self.merge(i - 1);
+            i - 1
+        }
+    }
+
+    // Merge the children at `i` and `i + 1` and the entry between them into one child.
+    fn merge(&mut self, i: usize) {
+        let separator = self.remove_entry(i);
+        let right = self.children.remove(i + 1);
+        let right = Rc::try_unwrap(right).unwrap_or_else(|shared| (*shared).clone());
+        let left = Rc::make_mut(&mut self.children[i]);
+        left.push_entry(separator);
+        left.keys.extend(right.keys);
+        left.values.extend(right.values);
+        left.children.extend(right.children);
+        left.update();
+    }
+
+    // A tree of the entries, which must be sorted by key without duplicates. The nodes are
+    // filled as far as possible, so the tree is as shallow as it can be.
+    fn build(entries: Vec<(K, V)>) -> Option<Rc<Node<K, V>>> {
+        if entries.is_empty() {
+            return None;
+        }
+        // Distribute the entries over as few leaves as possible, keeping one entry between each
+        // two leaves as their separator.
+        let leaves = (entries.len() + 1 + MAX) / (MAX + 1);
+        let mut entries = entries.into_iter();
+        let mut level = vec![];
+        let mut separators = vec![];
+        for (i, len) in spread(entries.len() - (leaves - 1), leaves).enumerate() {
+            if i > 0 {
+                separators.push(entries.next().expect("there is a separator per leaf"));
+            }
+            let mut leaf = Node::empty();
+            for entry in entries.by_ref().take(len) {
+                leaf.push_entry(entry);
+            }
+            leaf.update();
+            level.push(Rc::new(leaf));
+        }
+        // Then distribute the nodes of each level over as few parents as possible.
+        while level.len() > 1 {
+            let parents = (level.len() + MAX) / (MAX + 1);
+            let mut children = level.into_iter();
+            let mut below = separators.into_iter();
+            level = vec![];
+            separators = vec![];
+            for (i, len) in spread(children.len(), parents).enumerate() {
+                if i > 0 {
+                    separators.push(below.next().expect("there is a separator per parent"));
+                }
+                let mut parent = Node::empty();
+                parent.children.extend(children.by_ref().take(len));
+                for _ in 1..len {
+                    parent.push_entry(below.next().expect("there is a separator per child"));
+                }
+                parent.update();
+                level.push(Rc::new(parent));
+            }
+        }
+        level.pop()
+    }
+
+    // The entries of the subtree, in order, without copying those of nodes that are not shared.
+    fn into_entries(node: Rc<Node<K, V>>, out: &mut Vec<(K, V)>) {
+        let node = Rc::try_unwrap(node).unwrap_or_else(|shared| (*shared).clone());
+        let entries = node.keys.into_iter().zip(node.values);
+        if node.children.is_empty() {
+            out.extend(entries);
+            return;
+        }
+        let mut children = node.children.into_iter();
+        for entry in entries {
+            let child = children.next().expect("internal nodes have a child per entry");
+            Node::into_entries(child, out);
+            out.push(entry);
+        }
+        if let Some(last) = children.next() {
+            Node::into_entries(last, out);
+        }
+    }
+}
+
+/// A persistent ordered map, see the module documentation.
+pub struct PersistentMap<K, V> {
+    root: Option<Rc<Node<K, V>>>,
+    len: usize,
+}
+
+impl<K: Ord + Clone + Shareable, V: Clone + Shareable> PersistentMap<K, V> {
+    /// An empty map.
+    pub fn new() -> PersistentMap<K, V> {
+        PersistentMap { root: None, len: 0 }
+    }
+
+    /// The number of entries.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the map has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether all entries are shared with the copies of the map, so that copying it takes
+    /// constant time.
+    pub fn is_shareable(&self) -> bool {
+        self.root.as_ref().is_none_or(|root| root.shareable)
+    }
+
+    /// The value of the key, if any.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let mut node = self.root.as_ref()?;
+        loop {
+            match node.search(key) {
+                Ok(i) => return Some(&node.values[i]),
+                Err(_) if node.is_leaf() => return None,
+                Err(i) => node = &node.children[i],
+            }
+        }
+    }
+
+    /// Whether the map has the key.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Set the value of the key, and return the previous one, if any.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let root = self.root.get_or_insert_with(|| Rc::new(Node::empty()));
+        if root.len() == MAX {
+            let mut new_root = Node::empty();
+            new_root.children.push(root.clone());
+            new_root.split_child(0);
+            *root = Rc::new(new_root);
+        }
+        let previous = Rc::make_mut(root).insert(key, value);
+        if previous.is_none() {
+            self.len += 1;
+        }
+        previous
+    }
+
+    /// Remove the key, and return its value, if any.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        // Check first, so that removing a missing key does not copy any shared nodes.
+        if !self.contains_key(key) {
+            return None;
+        }
+        let root = self.root.as_mut()?;
+        let removed = Rc::make_mut(root).remove(key);
+        if root.keys.is_empty() {
+            self.root = root.children.first().cloned();
+        }
+        self.len -= 1;
+        removed.map(|(_, value)| value)
+    }
+
+    /// Remove all entries.
+    pub fn clear(&mut self) {
+        *self = PersistentMap::new();
+    }
+
+    /// The entries in ascending order of their keys.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        let mut iter = Iter { stack: vec![], remaining: Some(self.len) };
+        if let Some(root) = &self.root {
+            iter.descend(root, |_| 0);
+        }
+        iter
+    }
+
+    /// The entries whose keys are within the lower bound, in ascending order of their keys.
+    pub fn range_from(&self, lower: Bound<&K>) -> Iter<'_, K, V> {
+        let mut iter = Iter { stack: vec![], remaining: None };
+        if let Some(root) = &self.root {
+            iter.descend(root, |node| {
+                node.keys.partition_point(|k| match lower {
+                    Bound::Included(lower) => k < lower,
+                    Bound::Excluded(lower) => k <= lower,
+                    Bound::Unbounded => false,
+                })
+            });
+        }
+        iter
+    }
+
+    /// The keys in ascending order.
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    /// The values in ascending order of their keys.
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, v)| v)
+    }
+
+    /// A copy of the map whose entries are the results of `f`, which must keep their order, e.g.
+    /// by returning keys equal to the given ones. Entries that are shared with the copies of the
+    /// map (see `is_shareable`) are not passed to `f`, but shared with the result as well.
+    pub fn copy_with(&self, mut f: impl FnMut(&K, &V) -> (K, V)) -> PersistentMap<K, V> {
+        PersistentMap {
+            root: self.root.as_ref().map(|root| Node::copy_with(root, &mut f)),
+            len: self.len,
+        }
+    }
+}
+
+impl<K: Ord + Clone + Shareable, V: Clone + Shareable> Clone for PersistentMap<K, V> {
+    fn clone(&self) -> PersistentMap<K, V> {
+        PersistentMap { root: self.root.as_ref().map(Node::copy), len: self.len }
+    }
+}
+
+impl<K: Ord + Clone + Shareable, V: Clone + Shareable> Default for PersistentMap<K, V> {
+    fn default() -> PersistentMap<K, V> {
+        PersistentMap::new()
+    }
+}
+
+impl<K: Ord + Clone + Shareable, V: Clone + Shareable> FromIterator<(K, V)>
+    for PersistentMap<K, V>
+{
+    /// Later entries replace earlier ones with equal keys, as with `insert`.
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(entries: I) -> PersistentMap<K, V> {
+        let mut entries: Vec<(K, V)> = entries.into_iter().collect();
+        // Stable, so the last of several entries with equal keys is the last one kept.
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut unique: Vec<(K, V)> = Vec::with_capacity(entries.len());
+        for entry in entries {
+            match unique.last_mut() {
+                Some(last) if last.0 == entry.0 => *last = entry,
+                _ => unique.push(entry),
+            }
+        }
+        PersistentMap { len: unique.len(), root: Node::build(unique) }
+    }
+}
+
+impl<K: Ord + Clone + Shareable, V: Clone + Shareable> Extend<(K, V)> for PersistentMap<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        for (key, value) in entries {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K: Ord + Clone + Shareable, V: Clone + Shareable> IntoIterator for PersistentMap<K, V> {
+    type Item = (K, V);
+    type IntoIter = std::vec::IntoIter<(K, V)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let mut entries = Vec::with_capacity(self.len);
+        if let Some(root) = self.root {
+            Node::into_entries(root, &mut entries);
+        }
+        entries.into_iter()
+    }
+}
+
+impl<'a, K: Ord + Clone + Shareable, V: Clone + Shareable> IntoIterator
+    for &'a PersistentMap<K, V>
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<K: Ord + Clone + Shareable, V: Ord + Clone + Shareable> PartialEq for PersistentMap<K, V> {
+    fn eq(&self, other: &PersistentMap<K, V>) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<K: Ord + Clone + Shareable, V: Ord + Clone + Shareable> Eq for PersistentMap<K, V> {}
+
+impl<K: Ord + Clone + Shareable, V: Ord + Clone + Shareable> PartialOrd for PersistentMap<K, V> {
+    fn partial_cmp(&self, other: &PersistentMap<K, V>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Lexicographically by entries, like `BTreeMap`.
+impl<K: Ord + Clone + Shareable, V: Ord + Clone + Shareable> Ord for PersistentMap<K, V> {
+    fn cmp(&self, other: &PersistentMap<K, V>) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl<K, V> fmt::Debug for PersistentMap<K, V>
+where
+    K: Ord + Clone + Shareable + fmt::Debug,
+    V: Clone + Shareable + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V> Finalize for PersistentMap<K, V> {}
+
+// Shareable subtrees contain no gc handles, so they are skipped.
+unsafe impl<K: Trace, V: Trace> Trace for PersistentMap<K, V> {
+    custom_trace!(this, {
+        let mut pending: Vec<&Node<K, V>> = this.root.iter().map(|root| &**root).collect();
+        while let Some(node) = pending.pop() {
+            if node.shareable {
+                continue;
+            }
+            for k in node.keys.iter() {
+                mark(k);
+            }
+            for v in node.values.iter() {
+                mark(v);
+            }
+            pending.extend(node.children.iter().map(|child| &**child));
+        }
+    });
+}
+
+/// An iterator over the entries of a `PersistentMap`.
+pub struct Iter<'a, K, V> {
+    // The nodes from the root down to the current one, with the index of the next entry of each.
+    stack: Vec<(&'a Node<K, V>, usize)>,
+    // The number of entries left, if known.
+    remaining: Option<usize>,
+}
+
+impl<'a, K, V> Iter<'a, K, V> {
+    // Push the path from `node` down to a leaf, going down before the entry that `start` picks
+    // in each node.
+    fn descend(&mut self, mut node: &'a Node<K, V>, start: impl Fn(&Node<K, V>) -> usize) {
+        loop {
+            let i = start(node);
+            self.stack.push((node, i));
+            match node.children.get(i) {
+                Some(child) => node = child,
+                None => return,
+            }
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            let (node, i) = self.stack.last_mut()?;
+            let node: &'a Node<K, V> = node;
+            if *i < node.keys.len() {
+                let (k, v) = (&node.keys[*i], &node.values[*i]);
+                *i += 1;
+                if let Some(child) = node.children.get(*i) {
+                    self.descend(child, |_| 0);
+                }
+                if let Some(remaining) = &mut self.remaining {
+                    *remaining -= 1;
+                }
+                return Some((k, v));
+            }
+            self.stack.pop();
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.remaining {
+            Some(remaining) => (remaining, Some(remaining)),
+            None => (0, None),
+        }
+    }
+}
+
+/// A persistent ordered set, see the module documentation.
+pub struct PersistentSet<T> {
+    map: PersistentMap<T, ()>,
+}
+
+impl<T: Ord + Clone + Shareable> PersistentSet<T> {
+    /// An empty set.
+    pub fn new() -> PersistentSet<T> {
+        PersistentSet { map: PersistentMap::new() }
+    }
+
+    /// The number of members.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Whether the set has no members.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Whether all members are shared with the copies of the set, see
+    /// `PersistentMap::is_shareable`.
+    pub fn is_shareable(&self) -> bool {
+        self.map.is_shareable()
+    }
+
+    /// Whether the value is a member.
+    pub fn contains(&self, value: &T) -> bool {
+        self.map.contains_key(value)
+    }
+
+    /// Add the value, and return whether it was not a member yet.
+    pub fn insert(&mut self, value: T) -> bool {
+        self.map.insert(value, ()).is_none()
+    }
+
+    /// Remove the value, and return whether it was a member.
+    pub fn remove(&mut self, value: &T) -> bool {
+        self.map.remove(value).is_some()
+    }
+
+    /// Remove all members.
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    /// The members in ascending order.
+    pub fn iter(&self) -> SetIter<'_, T> {
+        SetIter(self.map.iter())
+    }
+
+    /// The members within the lower bound, in ascending order.
+    pub fn range_from(&self, lower: Bound<&T>) -> SetIter<'_, T> {
+        SetIter(self.map.range_from(lower))
+    }
+
+    /// A copy of the set whose members are the results of `f`, see `PersistentMap::copy_with`.
+    pub fn copy_with(&self, mut f: impl FnMut(&T) -> T) -> PersistentSet<T> {
+        PersistentSet { map: self.map.copy_with(|k, _| (f(k), ())) }
+    }
+
+    /// The members of either set, in ascending order.
+    pub fn union<'a>(&'a self, other: &'a PersistentSet<T>) -> std::vec::IntoIter<&'a T> {
+        merge(self, other, |_, _| true)
+    }
+
+    /// The members of both sets, in ascending order.
+    pub fn intersection<'a>(&'a self, other: &'a PersistentSet<T>) -> std::vec::IntoIter<&'a T> {
+        merge(self, other, |in_self, in_other| in_self && in_other)
+    }
+
+    /// The members of this set that are not members of the other one, in ascending order.
+    pub fn difference<'a>(&'a self, other: &'a PersistentSet<T>) -> std::vec::IntoIter<&'a T> {
+        merge(self, other, |in_self, in_other| in_self && !in_other)
+    }
+
+    /// The members of exactly one of the sets, in ascending order.
+    pub fn symmetric_difference<'a>(
+        &'a self,
+        other: &'a PersistentSet<T>,
+    ) -> std::vec::IntoIter<&'a T> {
+        merge(self, other, |in_self, in_other| in_self != in_other)
+    }
+
+    /// Whether all members of this set are members of the other one.
+    pub fn is_subset(&self, other: &PersistentSet<T>) -> bool {
+        self.len() <= other.len() && self.iter().all(|value| other.contains(value))
+    }
+
+    /// Whether all members of the other set are members of this one.
+    pub fn is_superset(&self, other: &PersistentSet<T>) -> bool {
+        other.is_subset(self)
+    }
+
+    /// Whether the sets have no members in common.
+    pub fn is_disjoint(&self, other: &PersistentSet<T>) -> bool {
+        merge(self, other, |in_self, in_other| in_self && in_other).next().is_none()
+    }
+}
+
+// The members of either set for which `keep` returns true, given whether they are members of the
+// first and of the second set.
+fn merge<'a, T: Ord + Clone + Shareable>(
+    a: &'a PersistentSet<T>,
+    b: &'a PersistentSet<T>,
+    keep: impl Fn(bool, bool) -> bool,
+) -> std::vec::IntoIter<&'a T> {
+    let mut merged = vec![];
+    let mut a = a.iter().peekable();
+    let mut b = b.iter().peekable();
+    loop {
+        let (value, in_a, in_b) = match (a.peek(), b.peek()) {
+            (None, None) => break,
+            (Some(_), None) => (a.next(), true, false),
+            (None, Some(_)) => (b.next(), false, true),
+            (Some(x), Some(y)) => match x.cmp(y) {
+                Ordering::Less => (a.next(), true, false),
+                Ordering::Greater => (b.next(), false, true),
+                Ordering::Equal => {
+                    b.next();
+                    (a.next(), true, true)
+                }
+            },
+        };
+        if keep(in_a, in_b) {
+            merged.extend(value);
+        }
+    }
+    merged.into_iter()
+}
+
+impl<T: Ord + Clone + Shareable> Clone for PersistentSet<T> {
+    fn clone(&self) -> PersistentSet<T> {
+        PersistentSet { map: self.map.clone() }
+    }
+}
+
+impl<T: Ord + Clone + Shareable> Default for PersistentSet<T> {
+    fn default() -> PersistentSet<T> {
+        PersistentSet::new()
+    }
+}
+
+impl<T: Ord + Clone + Shareable> FromIterator<T> for PersistentSet<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(members: I) -> PersistentSet<T> {
+        PersistentSet { map: members.into_iter().map(|member| (member, ())).collect() }
+    }
+}
+
+impl<T: Ord + Clone + Shareable> Extend<T> for PersistentSet<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, members: I) {
+        self.map.extend(members.into_iter().map(|member| (member, ())));
+    }
+}
+
+impl<T: Ord + Clone + Shareable> IntoIterator for PersistentSet<T> {
+    type Item = T;
+    type IntoIter = std::iter::Map<std::vec::IntoIter<(T, ())>, fn((T, ())) -> T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let member: fn((T, ())) -> T = |(member, _)| member;
+        self.map.into_iter().map(member)
+    }
+}
+
+impl<'a, T: Ord + Clone + Shareable> IntoIterator for &'a PersistentSet<T> {
+    type Item = &'a T;
+    type IntoIter = SetIter<'a, T>;
+
+    fn into_iter(self) -> SetIter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T: Ord + Clone + Shareable> PartialEq for PersistentSet<T> {
+    fn eq(&self, other: &PersistentSet<T>) -> bool {
+        self.map == other.map
+    }
+}
+
+impl<T: Ord + Clone + Shareable> Eq for PersistentSet<T> {}
+
+impl<T: Ord + Clone + Shareable> PartialOrd for PersistentSet<T> {
+    fn partial_cmp(&self, other: &PersistentSet<T>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Lexicographically by members, like `BTreeSet`.
+impl<T: Ord + Clone + Shareable> Ord for PersistentSet<T> {
+    fn cmp(&self, other: &PersistentSet<T>) -> Ordering {
+        self.map.cmp(&other.map)
+    }
+}
+
+impl<T: Ord + Clone + Shareable + fmt::Debug> fmt::Debug for PersistentSet<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T> Finalize for PersistentSet<T> {}
+
+unsafe impl<T: Trace> Trace for PersistentSet<T> {
+    custom_trace!(this, {
+        mark(&this.map);
+    });
+}
+
+/// An iterator over the members of a `PersistentSet`.
+pub struct SetIter<'a, T>(Iter<'a, T, ()>);
+
+impl<'a, T> Iterator for SetIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.0.next().map(|(member, _)| member)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use gc::Gc;
+
+    use super::*;
+
+    // A xorshift generator, so that the random operations are the same on every run.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % n
+        }
+    }
+
+    // Check the invariants of the tree below `node`, and return its height and number of entries.
+    fn check<K, V>(node: &Rc<Node<K, V>>, is_root: bool) -> (usize, usize)
+    where
+        K: Ord + Clone + Shareable,
+        V: Clone + Shareable,
+    {
+        assert!(node.len() <= MAX);
+        assert!(is_root || node.len() >= B - 1);
+        assert_eq!(node.keys.len(), node.values.len());
+        assert!(node.keys.windows(2).all(|pair| pair[0] < pair[1]));
+        let shareable = node.keys.iter().all(|k| k.shareable())
+            && node.values.iter().all(|v| v.shareable())
+            && node.children.iter().all(|child| child.shareable);
+        assert_eq!(node.shareable, shareable);
+        assert!(shareable || Rc::strong_count(node) == 1, "a node with gc handles is shared");
+        if node.is_leaf() {
+            return (1, node.len());
+        }
+        assert_eq!(node.children.len(), node.len() + 1);
+        let mut height = None;
+        let mut len = node.len();
+        for child in node.children.iter() {
+            let (child_height, child_len) = check(child, false);
+            assert!(height.is_none_or(|height| height == child_height), "unbalanced");
+            height = Some(child_height);
+            len += child_len;
+        }
+        (height.expect("internal nodes have children") + 1, len)
+    }
+
+    fn check_map<K, V>(map: &PersistentMap<K, V>)
+    where
+        K: Ord + Clone + Shareable,
+        V: Clone + Shareable,
+    {
+        let len = map.root.as_ref().map_or(0, |root| check(root, true).1);
+        assert_eq!(map.len(), len);
+        assert!(map.root.as_ref().is_none_or(|root| !root.keys.is_empty()));
+    }
+
+    // Whether the maps share their root node.
+    fn shares_root<K, V>(a: &PersistentMap<K, V>, b: &PersistentMap<K, V>) -> bool {
+        match (&a.root, &b.root) {
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn behaves_like_a_btree_map() {
+        let mut rng = Rng(0x5eed);
+        let mut map = PersistentMap::new();
+        let mut model = BTreeMap::new();
+        for step in 0..20_000 {
+            let key = Value::Int(rng.below(500) as i64);
+            match rng.below(3) {
+                0 => assert_eq!(map.remove(&key), model.remove(&key)),
+                _ => {
+                    let value = Value::Int(step);
+                    assert_eq!(map.insert(key.clone(), value.clone()), model.insert(key, value));
+                }
+            }
+            if step % 500 == 0 {
+                check_map(&map);
+                assert!(map.iter().eq(model.iter()));
+            }
+        }
+        check_map(&map);
+        assert!(map.iter().eq(model.iter()));
+        assert!(map.keys().eq(model.keys()));
+        assert!(map.values().eq(model.values()));
+        assert!(map.clone().into_iter().eq(model.clone().into_iter()));
+        for k in -1..501 {
+            let key = Value::Int(k);
+            assert_eq!(map.get(&key), model.get(&key));
+            let lower = Bound::Excluded(&key);
+            assert!(map.range_from(lower).eq(model.range((lower, Bound::Unbounded))));
+        }
+        while let Some(key) = model.keys().next().cloned() {
+            assert_eq!(map.remove(&key), model.remove(&key));
+        }
+        check_map(&map);
+        assert!(map.is_empty());
+        assert!(map.root.is_none());
+    }
+
+    #[test]
+    fn collecting_builds_valid_trees() {
+        for n in (0..700).chain(vec![5_000, 20_000]) {
+            let map: PersistentMap<Value, Value> =
+                (0..n).rev().map(|k| (Value::Int(k), Value::Int(-k))).collect();
+            check_map(&map);
+            assert!(map.iter().map(|(k, _)| k.clone()).eq((0..n).map(Value::Int)));
+        }
+        let map: PersistentMap<Value, Value> =
+            vec![(1, 1), (0, 0), (1, 2), (0, 3), (1, 4)]
+                .into_iter()
+                .map(|(k, v)| (Value::Int(k), Value::Int(v)))
+                .collect();
+        check_map(&map);
+        assert_eq!(map.get(&Value::Int(0)), Some(&Value::Int(3)));
+        assert_eq!(map.get(&Value::Int(1)), Some(&Value::Int(4)));
+    }
+
+    #[test]
+    fn copies_share_their_nodes() {
+        let map: PersistentMap<Value, Value> =
+            (0..10_000).map(|n| (Value::Int(n), Value::Int(n))).collect();
+        assert!(map.is_shareable());
+        let copy = map.clone();
+        assert!(shares_root(&map, &copy));
+        let copied = map.copy_with(|_, _| panic!("shared entries are not copied"));
+        assert!(shares_root(&map, &copied));
+    }
+
+    #[test]
+    fn mutations_copy_only_their_path() {
+        let mut rng = Rng(7);
+        let original: PersistentMap<Value, Value> =
+            (0..10_000).map(|n| (Value::Int(n), Value::Int(n))).collect();
+        let mut copy = original.clone();
+        let mut model: BTreeMap<Value, Value> = original.clone().into_iter().collect();
+        for step in 0..5_000 {
+            let key = Value::Int(rng.below(12_000) as i64);
+            if rng.below(2) == 0 {
+                assert_eq!(copy.remove(&key), model.remove(&key));
+            } else {
+                let value = Value::Int(-step);
+                assert_eq!(copy.insert(key.clone(), value.clone()), model.insert(key, value));
+            }
+        }
+        check_map(&original);
+        check_map(&copy);
+        assert!(original.iter().map(|(k, v)| (k.clone(), v.clone())).eq(
+            (0..10_000).map(|n| (Value::Int(n), Value::Int(n))),
+        ));
+        assert!(copy.iter().eq(model.iter()));
+
+        // Changing one entry copies one node per level, and shares all others.
+        let mut changed = original.clone();
+        changed.insert(Value::Int(5_000), Value::Nil);
+        let root = changed.root.as_ref().expect("the map is not empty");
+        let shared = root
+            .children
+            .iter()
+            .zip(original.root.as_ref().expect("the map is not empty").children.iter())
+            .filter(|(a, b)| Rc::ptr_eq(a, b))
+            .count();
+        assert_eq!(shared, root.children.len() - 1);
+    }
+
+    #[test]
+    fn nodes_with_gc_handles_are_not_shared() {
+        let mut map: PersistentMap<Value, Value> =
+            (0..1_000).map(|n| (Value::Int(n), Value::Int(n))).collect();
+        map.insert(Value::Int(500), Value::array(vec![]));
+        assert!(!map.is_shareable());
+        check_map(&map);
+
+        let copy = map.clone();
+        check_map(&map);
+        check_map(&copy);
+        assert!(!shares_root(&map, &copy));
+        match (map.get(&Value::Int(500)), copy.get(&Value::Int(500))) {
+            (Some(Value::Array(a)), Some(Value::Array(b))) => assert!(Gc::ptr_eq(a, b)),
+            other => panic!("expected arrays, got {:?}", other),
+        }
+
+        // Only the copied nodes see the entries; the others are still shared.
+        let mut copied = 0;
+        let deep = map.copy_with(|k, v| {
+            copied += 1;
+            (k.clone(), v.deep_copy())
+        });
+        check_map(&deep);
+        assert!(copied < 100, "copied {} entries", copied);
+        match (map.get(&Value::Int(500)), deep.get(&Value::Int(500))) {
+            (Some(Value::Array(a)), Some(Value::Array(b))) => assert!(!Gc::ptr_eq(a, b)),
+            other => panic!("expected arrays, got {:?}", other),
+        }
+
+        // Removing the entry makes its nodes shareable again.
+        map.remove(&Value::Int(500));
+        check_map(&map);
+        assert!(map.is_shareable());
+    }
+
+    #[test]
+    fn sets_behave_like_btree_sets() {
+        let a: PersistentSet<Value> = (0..300).step_by(2).map(Value::Int).collect();
+        let b: PersistentSet<Value> = (0..300).step_by(3).map(Value::Int).collect();
+        let model_a: BTreeSet<Value> = a.iter().cloned().collect();
+        let model_b: BTreeSet<Value> = b.iter().cloned().collect();
+        assert!(a.union(&b).eq(model_a.union(&model_b)));
+        assert!(a.intersection(&b).eq(model_a.intersection(&model_b)));
+        assert!(a.difference(&b).eq(model_a.difference(&model_b)));
+        assert!(a.symmetric_difference(&b).eq(model_a.symmetric_difference(&model_b)));
+        assert!(!a.is_subset(&b) && !a.is_disjoint(&b));
+        let small: PersistentSet<Value> = (0..30).step_by(6).map(Value::Int).collect();
+        assert!(small.is_subset(&a) && small.is_subset(&b) && a.is_superset(&small));
+        let odd: PersistentSet<Value> = (1..300).step_by(2).map(Value::Int).collect();
+        assert!(odd.is_disjoint(&a));
+        assert_eq!(a.cmp(&b), model_a.cmp(&model_b));
+        assert_eq!(a.clone(), a);
+    }
+}
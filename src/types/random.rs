@@ -0,0 +1,113 @@
+// Pseudo-random numbers for pan programs.
+//
+// Each runtime owns a generator, so that independent runtimes don't influence each other's
+// sequences. The generator is xoshiro256** (Blackman and Vigna), whose state is derived from a
+// 64 bit seed with SplitMix64. It is fast and statistically sound, but not suitable for
+// cryptography.
+
+use std::cell::RefCell;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A pseudo-random number generator. Clones refer to the same generator.
+#[derive(Clone)]
+pub struct Random(Rc<RefCell<State>>);
+
+struct State {
+    seed: u64,
+    s: [u64; 4],
+}
+
+impl Random {
+    /// A generator seeded from the entropy of the operating system and the current time.
+    pub fn new() -> Random {
+        Random::with_seed(entropy_seed())
+    }
+
+    /// A generator with the given seed. Generators with the same seed produce the same numbers.
+    pub fn with_seed(seed: u64) -> Random {
+        Random(Rc::new(RefCell::new(State { seed, s: expand(seed) })))
+    }
+
+    /// Restart the generator with the given seed.
+    pub fn seed(&self, seed: u64) {
+        *self.0.borrow_mut() = State { seed, s: expand(seed) };
+    }
+
+    /// The seed the generator was last (re)started with.
+    pub fn get_seed(&self) -> u64 {
+        self.0.borrow().seed
+    }
+
+    /// The next 64 uniformly distributed bits.
+    pub fn next_u64(&self) -> u64 {
+        let s = &mut self.0.borrow_mut().s;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    /// A uniformly distributed number from `0` up to (excluding) `n`, which must not be zero.
+    ///
+    /// Uses Lemire's multiply-and-reject method, so there is no bias towards small numbers.
+    pub fn below(&self, n: u64) -> u64 {
+        assert!(n > 0, "Random::below requires a non-empty range");
+        let mut m = u128::from(self.next_u64()) * u128::from(n);
+        if (m as u64) < n {
+            let threshold = n.wrapping_neg() % n;
+            while (m as u64) < threshold {
+                m = u128::from(self.next_u64()) * u128::from(n);
+            }
+        }
+        (m >> 64) as u64
+    }
+
+    /// A uniformly distributed float in `[0, 1)`, with 53 random bits.
+    pub fn float(&self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+}
+
+impl Default for Random {
+    fn default() -> Random {
+        Random::new()
+    }
+}
+
+impl fmt::Debug for Random {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Random(seed: {})", self.get_seed())
+    }
+}
+
+// Derive a full generator state from a seed with SplitMix64, which never yields the all-zero
+// state that xoshiro can not leave.
+fn expand(seed: u64) -> [u64; 4] {
+    let mut x = seed;
+    let mut next = || {
+        x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = x;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    [next(), next(), next(), next()]
+}
+
+// The standard library seeds the keys of `RandomState` from the operating system, and makes
+// every instance differ. Mixing in the time guards against platforms where that is weak.
+fn entropy_seed() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+    hasher.write_u128(now);
+    hasher.finish()
+}
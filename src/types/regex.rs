@@ -0,0 +1,940 @@
+// Regular expressions, matched in time linear in the length of the input (times the size of the
+// pattern) by a Pike VM.
+//
+// The syntax is a subset of that of the regex crate:
+//
+// - literal chars, `.` (any char but `\n`), `^`, `$`, `\A`, `\z`, `\b` and `\B`
+// - escapes `\n`, `\r`, `\t`, `\f`, `\v`, `\0`, `\xHH`, `\x{H...}` and `\` before any ASCII
+//   punctuation char
+// - perl classes `\d`, `\w`, `\s` and their negations `\D`, `\W`, `\S`, which use the unicode
+//   definitions of `char::is_numeric`, `char::is_alphanumeric` (plus `_`) and
+//   `char::is_whitespace`
+// - bracketed classes like `[a-z_\d]` and `[^"]`, which may contain ASCII classes like
+//   `[:alpha:]`, but no nested classes or set operations
+// - groups `(...)`, `(?:...)`, `(?P<name>...)` and `(?<name>...)`
+// - alternation `|`, and the greedy repetitions `*`, `+`, `?`, `{n}`, `{n,}`, `{n,m}`, each of
+//   which can be made lazy with a trailing `?`
+// - the flags `i` (case-insensitive, by simple case mapping), `m` (`^` and `$` match at line
+//   breaks) and `s` (`.` matches `\n`), set with `(?flags)` for the rest of the enclosing group or
+//   with `(?flags:...)` for a group, and cleared with a `-`, as in `(?i-s)`
+//
+// Matches are leftmost-first, as in perl and the regex crate: of all matches starting at the
+// leftmost possible position, the one preferred by the order of the alternatives and the greed of
+// the repetitions wins.
+//
+// The input is a slice of chars, and all positions are char indices.
+
+use std::fmt;
+use std::ops::Range;
+
+use failure_derive::Fail;
+
+// The maximum number of instructions of a compiled pattern.
+const SIZE_LIMIT: usize = 1 << 16;
+
+// The maximum count of a counted repetition.
+const REPEAT_LIMIT: u32 = 1000;
+
+/// Everything that can go wrong when compiling a regular expression. Offsets are char indices
+/// into the pattern.
+#[derive(Debug, Clone, PartialEq, Eq, Fail)]
+pub enum RegexError {
+    #[fail(display = "unexpected end of pattern")]
+    UnexpectedEnd,
+    #[fail(display = "unexpected {:?} at offset {}", c, offset)]
+    Unexpected { c: char, offset: usize },
+    #[fail(display = "unclosed group opened at offset {}", offset)]
+    UnclosedGroup { offset: usize },
+    #[fail(display = "unclosed character class opened at offset {}", offset)]
+    UnclosedClass { offset: usize },
+    #[fail(display = "invalid class range at offset {}", offset)]
+    InvalidRange { offset: usize },
+    #[fail(display = "invalid escape at offset {}", offset)]
+    InvalidEscape { offset: usize },
+    #[fail(display = "invalid repetition at offset {}", offset)]
+    InvalidRepetition { offset: usize },
+    #[fail(display = "repetition without an operand at offset {}", offset)]
+    NothingToRepeat { offset: usize },
+    #[fail(display = "unknown flag {:?} at offset {}", flag, offset)]
+    UnknownFlag { flag: char, offset: usize },
+    #[fail(display = "invalid group name at offset {}", offset)]
+    InvalidGroupName { offset: usize },
+    #[fail(display = "duplicate group name {:?}", name)]
+    DuplicateGroupName { name: String },
+    #[fail(display = "the compiled pattern exceeds the limit of {} instructions", limit)]
+    TooBig { limit: usize },
+}
+
+/// A compiled regular expression.
+#[derive(Clone)]
+pub struct Regex {
+    pattern: String,
+    program: Vec<Inst>,
+    // The names of the capture groups, indexed by group (the whole match is group zero).
+    names: Vec<Option<String>>,
+}
+
+/// The ranges matched by the capture groups of a match, indexed by group (the whole match is
+/// group zero). Groups that did not participate in the match are `None`.
+pub type Captures = Vec<Option<Range<usize>>>;
+
+impl Regex {
+    /// Compile a pattern.
+    pub fn new(pattern: &str) -> Result<Regex, RegexError> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            position: 0,
+            names: vec![None],
+        };
+        let ast = parser.parse()?;
+
+        let mut compiler = Compiler { program: vec![] };
+        compiler.push(Inst::Save(0))?;
+        compiler.compile(&ast)?;
+        compiler.push(Inst::Save(1))?;
+        compiler.push(Inst::Match)?;
+
+        Ok(Regex {
+            pattern: pattern.to_string(),
+            program: compiler.program,
+            names: parser.names,
+        })
+    }
+
+    /// The pattern this regex was compiled from.
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// The number of capture groups, including group zero for the whole match.
+    pub fn captures_len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// The name of a capture group, if it has one.
+    pub fn group_name(&self, group: usize) -> Option<&str> {
+        self.names.get(group)?.as_deref()
+    }
+
+    /// Returns whether the regex matches anywhere in `text`.
+    pub fn is_match(&self, text: &[char]) -> bool {
+        self.captures_at(text, 0).is_some()
+    }
+
+    /// Find the first match that starts at or after position `start` and return the ranges of
+    /// its capture groups. Assertions like `\b` see the text before `start`.
+    pub fn captures_at(&self, text: &[char], start: usize) -> Option<Captures> {
+        let slots = 2 * self.names.len();
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        let mut matched = None;
+
+        for position in start..=text.len() {
+            if matched.is_none() {
+                // Starting a match here has a lower priority than continuing earlier attempts.
+                self.add_thread(&mut current, 0, position, text, vec![None; slots]);
+            } else if current.is_empty() {
+                break;
+            }
+
+            let c = text.get(position).copied();
+            for i in 0..current.pcs.len() {
+                let pc = current.pcs[i];
+                let advance = match (&self.program[pc], c) {
+                    (Inst::Match, _) => {
+                        matched = Some(current.captures[pc].clone());
+                        // All remaining threads have a lower priority.
+                        break;
+                    }
+                    (Inst::Char(expected), Some(c)) => *expected == c,
+                    (Inst::Class(class), Some(c)) => class.matches(c),
+                    _ => false,
+                };
+                if advance {
+                    let captures = current.captures[pc].clone();
+                    self.add_thread(&mut next, pc + 1, position + 1, text, captures);
+                }
+            }
+
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+
+        matched.map(|slots| {
+            slots
+                .chunks(2)
+                .map(|pair| match (pair[0], pair[1]) {
+                    (Some(start), Some(end)) => Some(start..end),
+                    _ => None,
+                })
+                .collect()
+        })
+    }
+
+    /// Iterate over the captures of all successive non-overlapping matches in `text`. After an
+    /// empty match, the search resumes one char further.
+    pub fn captures_iter<'r, 't>(&'r self, text: &'t [char]) -> CapturesIter<'r, 't> {
+        CapturesIter { regex: self, text, position: Some(0) }
+    }
+
+    // Add the thread at `pc` to `threads`, following jumps, splits, saves and assertions. Threads
+    // are added in order of priority.
+    fn add_thread(
+        &self,
+        threads: &mut Threads,
+        pc: usize,
+        position: usize,
+        text: &[char],
+        mut captures: Vec<Option<usize>>,
+    ) {
+        enum Step {
+            Visit(usize),
+            Restore(usize, Option<usize>),
+        }
+
+        let mut stack = vec![Step::Visit(pc)];
+        while let Some(step) = stack.pop() {
+            let pc = match step {
+                Step::Visit(pc) => pc,
+                Step::Restore(slot, old) => {
+                    captures[slot] = old;
+                    continue;
+                }
+            };
+            if !threads.insert(pc) {
+                // Coming back to a loop without having consumed anything means that the body of
+                // the loop matched the empty string. As in backtracking engines, this ends the
+                // loop instead of failing.
+                if let Inst::Loop { exit, .. } = self.program[pc] {
+                    stack.push(Step::Visit(exit));
+                }
+                continue;
+            }
+
+            match &self.program[pc] {
+                Inst::Jmp(target) => stack.push(Step::Visit(*target)),
+                Inst::Split(first, second) => {
+                    stack.push(Step::Visit(*second));
+                    stack.push(Step::Visit(*first));
+                }
+                Inst::Loop { exit, greedy: true } => {
+                    stack.push(Step::Visit(*exit));
+                    stack.push(Step::Visit(pc + 1));
+                }
+                Inst::Loop { exit, greedy: false } => {
+                    stack.push(Step::Visit(pc + 1));
+                    stack.push(Step::Visit(*exit));
+                }
+                Inst::Save(slot) => {
+                    stack.push(Step::Restore(*slot, captures[*slot]));
+                    stack.push(Step::Visit(pc + 1));
+                    captures[*slot] = Some(position);
+                }
+                Inst::Assert(assertion) => {
+                    if assertion.holds(text, position) {
+                        stack.push(Step::Visit(pc + 1));
+                    }
+                }
+                Inst::Char(_) | Inst::Class(_) | Inst::Match => {
+                    threads.pcs.push(pc);
+                    threads.captures[pc].clone_from(&captures);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Regex({:?})", self.pattern)
+    }
+}
+
+/// An iterator over the captures of successive matches, see `Regex::captures_iter`.
+pub struct CapturesIter<'r, 't> {
+    regex: &'r Regex,
+    text: &'t [char],
+    // Where to search for the next match, `None` once the search is over.
+    position: Option<usize>,
+}
+
+impl<'r, 't> Iterator for CapturesIter<'r, 't> {
+    type Item = Captures;
+
+    fn next(&mut self) -> Option<Captures> {
+        let captures = self.regex.captures_at(self.text, self.position?)?;
+        let matched = captures[0].clone().unwrap();
+        self.position = if !matched.is_empty() {
+            Some(matched.end)
+        } else if matched.end < self.text.len() {
+            Some(matched.end + 1)
+        } else {
+            None
+        };
+        Some(captures)
+    }
+}
+
+// The threads of the VM at a position, without duplicates, in order of priority.
+struct Threads {
+    pcs: Vec<usize>,
+    // Whether a pc has been visited while adding threads at this position.
+    visited: Vec<bool>,
+    visited_pcs: Vec<usize>,
+    // The capture slots of the thread at each pc.
+    captures: Vec<Vec<Option<usize>>>,
+}
+
+impl Threads {
+    fn new(len: usize) -> Threads {
+        Threads {
+            pcs: vec![],
+            visited: vec![false; len],
+            visited_pcs: vec![],
+            captures: vec![vec![]; len],
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pcs.is_empty()
+    }
+
+    // Mark `pc` as visited, returns false if it already was.
+    fn insert(&mut self, pc: usize) -> bool {
+        if self.visited[pc] {
+            false
+        } else {
+            self.visited[pc] = true;
+            self.visited_pcs.push(pc);
+            true
+        }
+    }
+
+    fn clear(&mut self) {
+        self.pcs.clear();
+        for pc in self.visited_pcs.drain(..) {
+            self.visited[pc] = false;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Class(Class),
+    // Continue at both targets, preferring the first one.
+    Split(usize, usize),
+    // The start of an unbounded loop whose body starts at the next instruction. A greedy loop
+    // prefers another iteration over continuing at `exit`.
+    Loop { exit: usize, greedy: bool },
+    Jmp(usize),
+    // Store the current position in a capture slot.
+    Save(usize),
+    Assert(Assertion),
+    Match,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Assertion {
+    StartText,
+    EndText,
+    StartLine,
+    EndLine,
+    WordBoundary,
+    NotWordBoundary,
+}
+
+impl Assertion {
+    fn holds(self, text: &[char], position: usize) -> bool {
+        let before = if position == 0 { None } else { text.get(position - 1).copied() };
+        let after = text.get(position).copied();
+        match self {
+            Assertion::StartText => before.is_none(),
+            Assertion::EndText => after.is_none(),
+            Assertion::StartLine => before.is_none_or(|c| c == '\n'),
+            Assertion::EndLine => after.is_none_or(|c| c == '\n'),
+            Assertion::WordBoundary => before.is_some_and(is_word) != after.is_some_and(is_word),
+            Assertion::NotWordBoundary => before.is_some_and(is_word) == after.is_some_and(is_word),
+        }
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+    case_insensitive: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClassItem {
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        let contains = |c: char| self.items.iter().any(|item| item.matches(c));
+        let found = contains(c)
+            || (self.case_insensitive
+                && (simple_case(c.to_lowercase()).is_some_and(contains)
+                    || simple_case(c.to_uppercase()).is_some_and(contains)));
+        found != self.negated
+    }
+}
+
+impl ClassItem {
+    fn matches(self, c: char) -> bool {
+        match self {
+            ClassItem::Range(low, high) => low <= c && c <= high,
+            ClassItem::Digit(positive) => c.is_numeric() == positive,
+            ClassItem::Word(positive) => is_word(c) == positive,
+            ClassItem::Space(positive) => c.is_whitespace() == positive,
+        }
+    }
+}
+
+// The result of a case mapping if it is a single char.
+fn simple_case(mut mapped: impl Iterator<Item = char>) -> Option<char> {
+    match (mapped.next(), mapped.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Flags {
+    case_insensitive: bool,
+    multi_line: bool,
+    dot_matches_new_line: bool,
+}
+
+#[derive(Debug)]
+enum Node {
+    Empty,
+    Char(char),
+    Class(Class),
+    Assert(Assertion),
+    Capture(Box<Node>, usize),
+    Concat(Vec<Node>),
+    Alternation(Vec<Node>),
+    Repeat { node: Box<Node>, min: u32, max: Option<u32>, greedy: bool },
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    names: Vec<Option<String>>,
+}
+
+impl Parser {
+    fn parse(&mut self) -> Result<Node, RegexError> {
+        let node = self.parse_alternation(&mut Flags::default())?;
+        match self.peek() {
+            None => Ok(node),
+            Some(c) => Err(RegexError::Unexpected { c, offset: self.position }),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Result<char, RegexError> {
+        let c = self.peek().ok_or(RegexError::UnexpectedEnd)?;
+        self.position += 1;
+        Ok(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Parse alternatives up to a `)` or the end of the pattern. Flags set by `(?flags)` stay in
+    // effect until the end of the enclosing group.
+    fn parse_alternation(&mut self, flags: &mut Flags) -> Result<Node, RegexError> {
+        let mut alternatives = vec![self.parse_concat(flags)?];
+        while self.eat('|') {
+            alternatives.push(self.parse_concat(flags)?);
+        }
+        Ok(if alternatives.len() == 1 {
+            alternatives.pop().unwrap()
+        } else {
+            Node::Alternation(alternatives)
+        })
+    }
+
+    fn parse_concat(&mut self, flags: &mut Flags) -> Result<Node, RegexError> {
+        let mut nodes = vec![];
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = match self.parse_atom(flags)? {
+                Some(atom) => atom,
+                None => continue,
+            };
+            nodes.push(self.parse_repetition(atom)?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    // Parse a single atom. Returns `None` for a group that only sets flags.
+    fn parse_atom(&mut self, flags: &mut Flags) -> Result<Option<Node>, RegexError> {
+        let offset = self.position;
+        let c = self.next()?;
+        Ok(Some(match c {
+            '(' => return self.parse_group(flags, offset),
+            '[' => Node::Class(self.parse_class(flags, offset)?),
+            '.' => Node::Class(Class {
+                items: if flags.dot_matches_new_line { vec![] } else { vec![newline()] },
+                negated: true,
+                case_insensitive: false,
+            }),
+            '^' => Node::Assert(if flags.multi_line {
+                Assertion::StartLine
+            } else {
+                Assertion::StartText
+            }),
+            '$' => Node::Assert(if flags.multi_line {
+                Assertion::EndLine
+            } else {
+                Assertion::EndText
+            }),
+            '*' | '+' | '?' | '{' => return Err(RegexError::NothingToRepeat { offset }),
+            '\\' => match self.parse_escape(offset)? {
+                Escape::Char(c) => literal(c, flags),
+                Escape::Item(item) => Node::Class(Class {
+                    items: vec![item],
+                    negated: false,
+                    case_insensitive: false,
+                }),
+                Escape::Assert(assertion) => Node::Assert(assertion),
+            },
+            c => literal(c, flags),
+        }))
+    }
+
+    // Parse a group after the `(`. Returns `None` for a group that only sets flags.
+    fn parse_group(
+        &mut self,
+        flags: &mut Flags,
+        offset: usize,
+    ) -> Result<Option<Node>, RegexError> {
+        let mut inner_flags = *flags;
+        let mut capture = None;
+
+        if self.eat('?') {
+            if self.eat('P') && self.peek() != Some('<') {
+                return Err(RegexError::InvalidGroupName { offset: self.position });
+            }
+            if self.eat('<') {
+                capture = Some(self.parse_group_name()?);
+            } else {
+                // Flags, followed by `)` to set them or `:` to start a group.
+                let mut negated = false;
+                loop {
+                    let flag_offset = self.position;
+                    let flag = match self.next()? {
+                        ')' => {
+                            *flags = inner_flags;
+                            return Ok(None);
+                        }
+                        ':' => break,
+                        '-' if !negated => {
+                            negated = true;
+                            continue;
+                        }
+                        flag => flag,
+                    };
+                    let value = !negated;
+                    match flag {
+                        'i' => inner_flags.case_insensitive = value,
+                        'm' => inner_flags.multi_line = value,
+                        's' => inner_flags.dot_matches_new_line = value,
+                        _ => return Err(RegexError::UnknownFlag { flag, offset: flag_offset }),
+                    }
+                }
+            }
+        } else {
+            self.names.push(None);
+            capture = Some(self.names.len() - 1);
+        }
+
+        let node = self.parse_alternation(&mut inner_flags)?;
+        if !self.eat(')') {
+            return Err(RegexError::UnclosedGroup { offset });
+        }
+        Ok(Some(match capture {
+            Some(index) => Node::Capture(Box::new(node), index),
+            None => node,
+        }))
+    }
+
+    // Parse the name of a group after the `<`, and allocate its index.
+    fn parse_group_name(&mut self) -> Result<usize, RegexError> {
+        let offset = self.position;
+        let mut name = String::new();
+        loop {
+            match self.next()? {
+                '>' => break,
+                c if c == '_' || c.is_ascii_alphanumeric() => name.push(c),
+                _ => return Err(RegexError::InvalidGroupName { offset }),
+            }
+        }
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(RegexError::InvalidGroupName { offset });
+        }
+        if self.names.iter().any(|other| other.as_deref() == Some(name.as_str())) {
+            return Err(RegexError::DuplicateGroupName { name });
+        }
+        self.names.push(Some(name));
+        Ok(self.names.len() - 1)
+    }
+
+    fn parse_repetition(&mut self, atom: Node) -> Result<Node, RegexError> {
+        let offset = self.position;
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                self.position += 1;
+                self.parse_counts(offset)?
+            }
+            _ => return Ok(atom),
+        };
+        if self.chars[offset] != '{' {
+            self.position += 1;
+        }
+
+        let greedy = !self.eat('?');
+        match self.peek() {
+            Some('*') | Some('+') | Some('?') | Some('{') => {
+                Err(RegexError::NothingToRepeat { offset: self.position })
+            }
+            _ => Ok(Node::Repeat { node: Box::new(atom), min, max, greedy }),
+        }
+    }
+
+    // Parse the counts of a counted repetition after the `{`, up to and including the `}`.
+    fn parse_counts(&mut self, offset: usize) -> Result<(u32, Option<u32>), RegexError> {
+        let invalid = RegexError::InvalidRepetition { offset };
+        let min = self.parse_count().ok_or_else(|| invalid.clone())?;
+        let max = if self.eat(',') {
+            if self.peek() == Some('}') {
+                None
+            } else {
+                Some(self.parse_count().ok_or_else(|| invalid.clone())?)
+            }
+        } else {
+            Some(min)
+        };
+        if !self.eat('}') || max.is_some_and(|max| max < min) {
+            return Err(invalid);
+        }
+        Ok((min, max))
+    }
+
+    fn parse_count(&mut self) -> Option<u32> {
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.position += 1;
+        }
+        let digits: String = self.chars[start..self.position].iter().collect();
+        digits.parse().ok().filter(|n| *n <= REPEAT_LIMIT)
+    }
+
+    // Parse an escape sequence after the `\`.
+    fn parse_escape(&mut self, offset: usize) -> Result<Escape, RegexError> {
+        Ok(match self.next()? {
+            'n' => Escape::Char('\n'),
+            'r' => Escape::Char('\r'),
+            't' => Escape::Char('\t'),
+            'f' => Escape::Char('\x0c'),
+            'v' => Escape::Char('\x0b'),
+            '0' => Escape::Char('\0'),
+            'x' => Escape::Char(self.parse_hex_escape(offset)?),
+            'd' => Escape::Item(ClassItem::Digit(true)),
+            'D' => Escape::Item(ClassItem::Digit(false)),
+            'w' => Escape::Item(ClassItem::Word(true)),
+            'W' => Escape::Item(ClassItem::Word(false)),
+            's' => Escape::Item(ClassItem::Space(true)),
+            'S' => Escape::Item(ClassItem::Space(false)),
+            'A' => Escape::Assert(Assertion::StartText),
+            'z' => Escape::Assert(Assertion::EndText),
+            'b' => Escape::Assert(Assertion::WordBoundary),
+            'B' => Escape::Assert(Assertion::NotWordBoundary),
+            c if c.is_ascii_punctuation() => Escape::Char(c),
+            _ => return Err(RegexError::InvalidEscape { offset }),
+        })
+    }
+
+    // Parse the code point of `\xHH` or `\x{H...}` after the `x`.
+    fn parse_hex_escape(&mut self, offset: usize) -> Result<char, RegexError> {
+        let digits: String = if self.eat('{') {
+            let start = self.position;
+            while self.peek().is_some_and(|c| c != '}') {
+                self.position += 1;
+            }
+            let digits = self.chars[start..self.position].iter().collect();
+            if !self.eat('}') {
+                return Err(RegexError::InvalidEscape { offset });
+            }
+            digits
+        } else {
+            let digits = self.chars.get(self.position..self.position + 2);
+            self.position += 2;
+            digits.ok_or(RegexError::InvalidEscape { offset })?.iter().collect()
+        };
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(RegexError::InvalidEscape { offset });
+        }
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(std::char::from_u32)
+            .ok_or(RegexError::InvalidEscape { offset })
+    }
+
+    // Parse a bracketed class after the `[`, up to and including the `]`.
+    fn parse_class(&mut self, flags: &Flags, offset: usize) -> Result<Class, RegexError> {
+        let negated = self.eat('^');
+        let mut items = vec![];
+        let mut first = true;
+
+        loop {
+            let item_offset = self.position;
+            let c = self.next().map_err(|_| RegexError::UnclosedClass { offset })?;
+            let low = match c {
+                ']' if !first => break,
+                '[' if self.peek() == Some(':') => {
+                    items.extend(self.parse_ascii_class(item_offset)?);
+                    first = false;
+                    continue;
+                }
+                '\\' => match self.parse_escape(item_offset)? {
+                    Escape::Char(c) => c,
+                    Escape::Item(item) => {
+                        items.push(item);
+                        first = false;
+                        continue;
+                    }
+                    Escape::Assert(_) => {
+                        return Err(RegexError::InvalidEscape { offset: item_offset });
+                    }
+                },
+                c => c,
+            };
+            first = false;
+
+            // A `-` followed by `]` is a literal.
+            if self.peek() == Some('-') && self.chars.get(self.position + 1) != Some(&']') {
+                self.position += 1;
+                let high_offset = self.position;
+                let high = match self.next().map_err(|_| RegexError::UnclosedClass { offset })? {
+                    '\\' => match self.parse_escape(high_offset)? {
+                        Escape::Char(c) => c,
+                        _ => return Err(RegexError::InvalidRange { offset: item_offset }),
+                    },
+                    '[' => return Err(RegexError::InvalidRange { offset: item_offset }),
+                    c => c,
+                };
+                if high < low {
+                    return Err(RegexError::InvalidRange { offset: item_offset });
+                }
+                items.push(ClassItem::Range(low, high));
+            } else {
+                items.push(ClassItem::Range(low, low));
+            }
+        }
+
+        Ok(Class { items, negated, case_insensitive: flags.case_insensitive })
+    }
+
+    // Parse an ASCII class like `[:alpha:]` after the `[`, up to and including the `]`.
+    fn parse_ascii_class(&mut self, offset: usize) -> Result<Vec<ClassItem>, RegexError> {
+        let rest: String = self.chars[self.position + 1..].iter().take(12).collect();
+        let end = rest.find(":]").ok_or(RegexError::InvalidEscape { offset })?;
+        let name = &rest[..end];
+        let (negated, name) = match name.strip_prefix('^') {
+            Some(name) => (true, name),
+            None => (false, name),
+        };
+        let ranges: &[(char, char)] = match name {
+            "alnum" => &[('0', '9'), ('A', 'Z'), ('a', 'z')],
+            "alpha" => &[('A', 'Z'), ('a', 'z')],
+            "ascii" => &[('\0', '\x7f')],
+            "blank" => &[('\t', '\t'), (' ', ' ')],
+            "cntrl" => &[('\0', '\x1f'), ('\x7f', '\x7f')],
+            "digit" => &[('0', '9')],
+            "graph" => &[('!', '~')],
+            "lower" => &[('a', 'z')],
+            "print" => &[(' ', '~')],
+            "punct" => &[('!', '/'), (':', '@'), ('[', '`'), ('{', '~')],
+            "space" => &[('\t', '\r'), (' ', ' ')],
+            "upper" => &[('A', 'Z')],
+            "word" => &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')],
+            "xdigit" => &[('0', '9'), ('A', 'F'), ('a', 'f')],
+            _ => return Err(RegexError::InvalidEscape { offset }),
+        };
+        self.position += end + 3;
+
+        let mut items: Vec<ClassItem> = vec![];
+        if negated {
+            // The complement of the sorted ranges.
+            let mut low = '\0';
+            for (start, end) in ranges {
+                if *start > low {
+                    items.push(ClassItem::Range(low, before(*start)));
+                }
+                low = after(*end);
+            }
+            items.push(ClassItem::Range(low, char::MAX));
+        } else {
+            items.extend(ranges.iter().map(|(low, high)| ClassItem::Range(*low, *high)));
+        }
+        Ok(items)
+    }
+}
+
+// The chars adjacent to an ASCII char.
+fn before(c: char) -> char {
+    (c as u8 - 1) as char
+}
+
+fn after(c: char) -> char {
+    (c as u8 + 1) as char
+}
+
+fn newline() -> ClassItem {
+    ClassItem::Range('\n', '\n')
+}
+
+enum Escape {
+    Char(char),
+    Item(ClassItem),
+    Assert(Assertion),
+}
+
+fn literal(c: char, flags: &Flags) -> Node {
+    if flags.case_insensitive && (c.is_lowercase() || c.is_uppercase()) {
+        Node::Class(Class {
+            items: vec![ClassItem::Range(c, c)],
+            negated: false,
+            case_insensitive: true,
+        })
+    } else {
+        Node::Char(c)
+    }
+}
+
+struct Compiler {
+    program: Vec<Inst>,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> Result<usize, RegexError> {
+        if self.program.len() >= SIZE_LIMIT {
+            return Err(RegexError::TooBig { limit: SIZE_LIMIT });
+        }
+        self.program.push(inst);
+        Ok(self.program.len() - 1)
+    }
+
+    fn next_pc(&self) -> usize {
+        self.program.len()
+    }
+
+    fn compile(&mut self, node: &Node) -> Result<(), RegexError> {
+        match node {
+            Node::Empty => {}
+            Node::Char(c) => {
+                self.push(Inst::Char(*c))?;
+            }
+            Node::Class(class) => {
+                self.push(Inst::Class(class.clone()))?;
+            }
+            Node::Assert(assertion) => {
+                self.push(Inst::Assert(*assertion))?;
+            }
+            Node::Capture(node, index) => {
+                self.push(Inst::Save(2 * index))?;
+                self.compile(node)?;
+                self.push(Inst::Save(2 * index + 1))?;
+            }
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile(node)?;
+                }
+            }
+            Node::Alternation(nodes) => {
+                let mut jumps = vec![];
+                for (i, node) in nodes.iter().enumerate() {
+                    if i + 1 < nodes.len() {
+                        let split = self.push(Inst::Split(0, 0))?;
+                        self.compile(node)?;
+                        jumps.push(self.push(Inst::Jmp(0))?);
+                        let next = self.next_pc();
+                        self.program[split] = Inst::Split(split + 1, next);
+                    } else {
+                        self.compile(node)?;
+                    }
+                }
+                let end = self.next_pc();
+                for jump in jumps {
+                    self.program[jump] = Inst::Jmp(end);
+                }
+            }
+            Node::Repeat { node, min, max, greedy } => {
+                for _ in 0..*min {
+                    self.compile(node)?;
+                }
+                match max {
+                    None => {
+                        let head = self.push(Inst::Loop { exit: 0, greedy: *greedy })?;
+                        self.compile(node)?;
+                        self.push(Inst::Jmp(head))?;
+                        let exit = self.next_pc();
+                        self.program[head] = Inst::Loop { exit, greedy: *greedy };
+                    }
+                    Some(max) => {
+                        let mut splits = vec![];
+                        for _ in *min..*max {
+                            splits.push(self.push(Inst::Split(0, 0))?);
+                            self.compile(node)?;
+                        }
+                        let end = self.next_pc();
+                        for split in splits {
+                            self.program[split] = self.split(split + 1, end, *greedy);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // A split that prefers `body` if greedy, and `skip` otherwise.
+    fn split(&self, body: usize, skip: usize, greedy: bool) -> Inst {
+        if greedy {
+            Inst::Split(body, skip)
+        } else {
+            Inst::Split(skip, body)
+        }
+    }
+}
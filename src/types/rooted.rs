@@ -0,0 +1,114 @@
+// Handles through which the host keeps values of a runtime alive.
+//
+// A value stored in a host structure is an ordinary rust value: nothing ties it to the runtime it
+// came from, and nothing tells the runtime that the host still needs it. A `Rooted` handle (see
+// `Runtime::root`) instead registers the value in the root table of the runtime, which keeps it
+// reachable for the garbage collector for as long as both the handle and the runtime exist.
+// Dropping the handle removes the value from the table. Dropping the runtime drops the table, and
+// with it the values of all handles that are still around; their `get` fails from then on rather
+// than handing out values of a runtime that is gone.
+//
+// `Rooted::get` also checks that it is asked through the runtime that created the handle, like
+// the entry points of a runtime check the values passed to them (see `types::owner`).
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::{Rc, Weak};
+
+use failure_derive::Fail;
+
+use crate::runtime::Runtime;
+use crate::types::owner::{CrossRuntimeError, RuntimeId};
+use crate::value::Value;
+
+/// The values rooted in a runtime, by the id of their handle.
+#[derive(Debug, Default)]
+pub(crate) struct RootTable {
+    values: BTreeMap<u64, Value>,
+    next_id: u64,
+}
+
+impl RootTable {
+    pub(crate) fn new() -> RootTable {
+        RootTable::default()
+    }
+
+    /// The number of rooted values.
+    pub(crate) fn len(&self) -> usize {
+        self.values.len()
+    }
+}
+
+/// A value kept alive by the root table of a runtime, see `Runtime::root`.
+pub struct Rooted {
+    table: Weak<RefCell<RootTable>>,
+    id: u64,
+    runtime: RuntimeId,
+}
+
+/// Why a rooted value can not be retrieved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Fail)]
+pub enum RootError {
+    /// The runtime that created the handle has been dropped, and the value with it.
+    #[fail(display = "the runtime that rooted the value has been dropped")]
+    Poisoned,
+    /// The handle was used with a runtime other than the one that created it.
+    #[fail(display = "{}", _0)]
+    CrossRuntime(CrossRuntimeError),
+}
+
+impl Rooted {
+    /// Add the value to the table of the runtime with the given id.
+    pub(crate) fn new(table: &Rc<RefCell<RootTable>>, runtime: RuntimeId, v: Value) -> Rooted {
+        let mut t = table.borrow_mut();
+        let id = t.next_id;
+        t.next_id += 1;
+        t.values.insert(id, v);
+        Rooted { table: Rc::downgrade(table), id, runtime }
+    }
+
+    /// The rooted value. Fails if the runtime that created the handle has been dropped, or if
+    /// `runtime` is a different one.
+    pub fn get(&self, runtime: &Runtime) -> Result<Value, RootError> {
+        let table = self.table.upgrade().ok_or(RootError::Poisoned)?;
+        if runtime.id() != self.runtime {
+            return Err(RootError::CrossRuntime(CrossRuntimeError {
+                expected: runtime.id(),
+                found: self.runtime,
+            }));
+        }
+        let v = table.borrow().values.get(&self.id).cloned();
+        v.ok_or(RootError::Poisoned)
+    }
+
+    /// Returns whether the runtime that created the handle is still alive.
+    pub fn is_alive(&self) -> bool {
+        self.table.strong_count() > 0
+    }
+
+    /// The id of the runtime that created the handle.
+    pub fn runtime(&self) -> RuntimeId {
+        self.runtime
+    }
+}
+
+impl Drop for Rooted {
+    fn drop(&mut self) {
+        if let Some(table) = self.table.upgrade() {
+            // The removed value is dropped once the table is no longer borrowed.
+            let removed = table.borrow_mut().values.remove(&self.id);
+            drop(removed);
+        }
+    }
+}
+
+impl fmt::Debug for Rooted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Rooted")
+            .field("runtime", &self.runtime)
+            .field("id", &self.id)
+            .field("alive", &self.is_alive())
+            .finish()
+    }
+}
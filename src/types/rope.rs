@@ -1,12 +1,1120 @@
 // The internal representation of pan strings. `O(log(n))` all the things!
+//
+// A rope is an immutable binary tree whose leaves are views into shared string buffers. Cloning
+// a rope only bumps a reference count, and slicing or concatenating ropes shares the untouched
+// parts of the inputs. Small strings are represented as a single leaf, so they don't pay for the
+// tree.
+//
+// All public indices are char indices (unicode scalar values), since that is what pan programs
+// observe. Methods working with byte offsets say so in their name.
 
+use std::cmp::{min, Ordering};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::ops::Range;
+use std::rc::Rc;
+
+use failure_derive::Fail;
 use gc_derive::{Trace, Finalize};
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Trace, Finalize)]
-pub struct Rope(String); // TODO use actual ropes (but keep String for small strings), make sure cloning is very cheap!
+mod case;
+mod lines;
+pub use self::lines::LineIndex;
+#[cfg(feature = "unicode-segmentation")]
+mod graphemes;
+#[cfg(feature = "unicode-segmentation")]
+pub use self::graphemes::Graphemes;
+#[cfg(feature = "unicode-normalization")]
+mod normalization;
+
+// Leaves created by splitting up or building strings hold at most this many bytes (unless a
+// single char is larger, which can not happen with the current value).
+const CHUNK_SIZE: usize = 1024;
+
+// Concatenations resulting in at most this many bytes are flattened into a single leaf.
+const FLATTEN_SIZE: usize = 256;
+
+// If a concatenation results in a tree deeper than this, the tree is rebalanced.
+const MAX_DEPTH: usize = 64;
+
+#[derive(Clone, Trace, Finalize)]
+pub struct Rope(#[unsafe_ignore_trace] Rc<Node>);
+
+enum Node {
+    // The string `text[start..end]`, which contains `chars` chars.
+    Leaf {
+        text: Rc<str>,
+        start: usize,
+        end: usize,
+        chars: usize,
+    },
+    // The concatenation of two ropes.
+    Concat {
+        left: Rope,
+        right: Rope,
+        bytes: usize,
+        chars: usize,
+        depth: usize,
+    },
+}
+
+/// Everything that can go wrong when working with ropes.
+#[derive(Debug, Clone, PartialEq, Eq, Fail)]
+pub enum RopeError {
+    #[fail(display = "index {} is out of bounds for a string of length {}", index, len)]
+    OutOfBounds { index: usize, len: usize },
+    #[fail(display = "range start {} is greater than range end {}", start, end)]
+    InvertedRange { start: usize, end: usize },
+    #[fail(display = "byte offset {} is not on a char boundary", offset)]
+    NotCharBoundary { offset: usize },
+    #[fail(display = "the pattern to replace must not be empty")]
+    EmptyPattern,
+}
+
+/// Everything that can go wrong when reading a rope from a byte source.
+#[derive(Debug, Fail)]
+pub enum ReadError {
+    #[fail(display = "{}", _0)]
+    Io(#[cause] io::Error),
+    #[fail(display = "invalid utf-8 at byte offset {}", offset)]
+    InvalidUtf8 { offset: usize },
+    #[fail(display = "input exceeds the limit of {} bytes", limit)]
+    LimitExceeded { limit: usize },
+}
 
 impl Rope {
+    /// Create an empty rope.
+    pub fn new() -> Rope {
+        Rope::leaf(Rc::from(""))
+    }
+
+    /// Create a rope holding a copy of the given string.
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Rope {
-        unimplemented!()
+        let mut b = RopeBuilder::new();
+        b.push_str(s);
+        b.finish()
+    }
+
+    // Create a rope consisting of a single leaf spanning the full text.
+    fn leaf(text: Rc<str>) -> Rope {
+        let end = text.len();
+        let chars = text.chars().count();
+        Rope(Rc::new(Node::Leaf { text, start: 0, end, chars }))
+    }
+
+    /// The length of the rope in chars.
+    pub fn len_chars(&self) -> usize {
+        match *self.0 {
+            Node::Leaf { chars, .. } | Node::Concat { chars, .. } => chars,
+        }
+    }
+
+    /// The length of the rope in bytes of its utf-8 encoding.
+    pub fn len_bytes(&self) -> usize {
+        match *self.0 {
+            Node::Leaf { start, end, .. } => end - start,
+            Node::Concat { bytes, .. } => bytes,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len_bytes() == 0
+    }
+
+    fn depth(&self) -> usize {
+        match *self.0 {
+            Node::Leaf { .. } => 0,
+            Node::Concat { depth, .. } => depth,
+        }
+    }
+
+    /// Returns whether both ropes share the same root, i.e. whether one is a cheap clone of
+    /// the other.
+    pub fn ptr_eq(a: &Rope, b: &Rope) -> bool {
+        Rc::ptr_eq(&a.0, &b.0)
+    }
+
+    /// Iterate over the chunks of contiguous text that make up this rope, from left to right.
+    /// Never yields empty chunks.
+    pub fn chunks(&self) -> Chunks<'_> {
+        Chunks {
+            next: Some(self),
+            stack: vec![],
+        }
+    }
+
+    /// Iterate over the chars of this rope, from left to right.
+    pub fn chars(&self) -> Chars<'_> {
+        Chars {
+            chunks: self.chunks(),
+            current: "".chars(),
+        }
+    }
+
+    /// Write the utf-8 encoding of the rope to `w`, chunk by chunk.
+    pub fn write_to(&self, w: &mut dyn io::Write) -> io::Result<()> {
+        for chunk in self.chunks() {
+            w.write_all(chunk.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Read utf-8 from `r` until the end of input, erroring if the input is longer than `limit`
+    /// bytes or is not valid utf-8. The input is validated as it is read, so no more than
+    /// `limit` bytes are ever buffered.
+    pub fn read_from(r: &mut dyn io::Read, limit: usize) -> Result<Rope, ReadError> {
+        let mut b = RopeBuilder::new();
+        let mut buf = vec![0; CHUNK_SIZE];
+        // The number of bytes at the start of `buf` that form an incomplete utf-8 sequence at the
+        // end of the previous read.
+        let mut carried = 0;
+        // The number of bytes that have been validated and added to the builder.
+        let mut total = 0;
+
+        loop {
+            let n = match r.read(&mut buf[carried..]) {
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(ReadError::Io(e)),
+            };
+
+            if n == 0 {
+                if carried > 0 {
+                    return Err(ReadError::InvalidUtf8 { offset: total });
+                }
+                return Ok(b.finish());
+            }
+
+            let filled = carried + n;
+            if total + filled > limit {
+                return Err(ReadError::LimitExceeded { limit });
+            }
+
+            let valid = match std::str::from_utf8(&buf[..filled]) {
+                Ok(_) => filled,
+                Err(e) => {
+                    if e.error_len().is_some() {
+                        return Err(ReadError::InvalidUtf8 { offset: total + e.valid_up_to() });
+                    }
+                    // The input ends with an incomplete sequence, which may be completed by the
+                    // next read.
+                    e.valid_up_to()
+                }
+            };
+
+            b.push_str(std::str::from_utf8(&buf[..valid]).unwrap());
+            total += valid;
+            buf.copy_within(valid..filled, 0);
+            carried = filled - valid;
+        }
+    }
+
+    /// Iterate over the chunks of contiguous text that make up this rope, from right to left.
+    /// Yields exactly the chunks of `Rope::chunks`, in reverse order.
+    pub fn chunks_rev(&self) -> ChunksRev<'_> {
+        ChunksRev {
+            next: Some(self),
+            stack: vec![],
+        }
+    }
+
+    /// Iterate over the chars of this rope, from right to left.
+    pub fn chars_rev(&self) -> CharsRev<'_> {
+        CharsRev {
+            chunks: self.chunks_rev(),
+            current: "".chars(),
+        }
+    }
+
+    /// The last char of this rope, if any.
+    pub fn last_char(&self) -> Option<char> {
+        self.chars_rev().next()
+    }
+
+    /// Return the sub-rope of the last `n` chars.
+    pub fn slice_from_end(&self, n: usize) -> Result<Rope, RopeError> {
+        if n > self.len_chars() {
+            return Err(RopeError::OutOfBounds { index: n, len: self.len_chars() });
+        }
+        let bytes: usize = self.chars_rev().take(n).map(char::len_utf8).sum();
+        Ok(self.byte_slice_unchecked(self.len_bytes() - bytes, self.len_bytes()))
+    }
+
+    /// Return a rope containing the text of `self` followed by the text of `other`.
+    pub fn concat(&self, other: &Rope) -> Rope {
+        if other.is_empty() {
+            return self.clone();
+        }
+        if self.is_empty() {
+            return other.clone();
+        }
+
+        if self.len_bytes() + other.len_bytes() <= FLATTEN_SIZE {
+            let mut s = String::with_capacity(self.len_bytes() + other.len_bytes());
+            s.extend(self.chunks());
+            s.extend(other.chunks());
+            return Rope::leaf(Rc::from(s));
+        }
+
+        let joined = if self.depth() > other.depth() + 1 {
+            Rope::join_right(self, other)
+        } else if other.depth() > self.depth() + 1 {
+            Rope::join_left(self, other)
+        } else {
+            Rope::join(self.clone(), other.clone())
+        };
+        // The joins keep trees of balanced inputs balanced, this only catches trees that were
+        // put together differently.
+        if joined.depth() > MAX_DEPTH {
+            joined.rebalance()
+        } else {
+            joined
+        }
+    }
+
+    // Join a tree with a shallower one by descending the right spine of the deeper tree and
+    // rotating on the way back up, as in the join of AVL trees. Takes time proportional to the
+    // difference in depth, and the result is at most one level deeper than `left`.
+    fn join_right(left: &Rope, right: &Rope) -> Rope {
+        let (l, c) = match left.children() {
+            Some(children) => children,
+            None => return Rope::join(left.clone(), right.clone()),
+        };
+        if c.depth() <= right.depth() + 1 {
+            let joined = Rope::join(c.clone(), right.clone());
+            if joined.depth() <= l.depth() + 1 {
+                Rope::join(l.clone(), joined)
+            } else {
+                Rope::join(l.clone(), joined.rotate_right()).rotate_left()
+            }
+        } else {
+            let joined = Rope::join_right(c, right);
+            let depth = joined.depth();
+            let node = Rope::join(l.clone(), joined);
+            if depth <= l.depth() + 1 {
+                node
+            } else {
+                node.rotate_left()
+            }
+        }
+    }
+
+    // The mirror image of `join_right`, for a `right` tree that is deeper than `left`.
+    fn join_left(left: &Rope, right: &Rope) -> Rope {
+        let (c, r) = match right.children() {
+            Some(children) => children,
+            None => return Rope::join(left.clone(), right.clone()),
+        };
+        if c.depth() <= left.depth() + 1 {
+            let joined = Rope::join(left.clone(), c.clone());
+            if joined.depth() <= r.depth() + 1 {
+                Rope::join(joined, r.clone())
+            } else {
+                Rope::join(joined.rotate_left(), r.clone()).rotate_right()
+            }
+        } else {
+            let joined = Rope::join_left(left, c);
+            let depth = joined.depth();
+            let node = Rope::join(joined, r.clone());
+            if depth <= r.depth() + 1 {
+                node
+            } else {
+                node.rotate_right()
+            }
+        }
+    }
+
+    fn children(&self) -> Option<(&Rope, &Rope)> {
+        match *self.0 {
+            Node::Leaf { .. } => None,
+            Node::Concat { ref left, ref right, .. } => Some((left, right)),
+        }
+    }
+
+    // Turn `a (b c)` into `(a b) c`, or return `self` if its right child is a leaf.
+    fn rotate_left(&self) -> Rope {
+        match self.children() {
+            Some((a, bc)) => match bc.children() {
+                Some((b, c)) => Rope::join(Rope::join(a.clone(), b.clone()), c.clone()),
+                None => self.clone(),
+            },
+            None => self.clone(),
+        }
+    }
+
+    // Turn `(a b) c` into `a (b c)`, or return `self` if its left child is a leaf.
+    fn rotate_right(&self) -> Rope {
+        match self.children() {
+            Some((ab, c)) => match ab.children() {
+                Some((a, b)) => Rope::join(a.clone(), Rope::join(b.clone(), c.clone())),
+                None => self.clone(),
+            },
+            None => self.clone(),
+        }
+    }
+
+    // Create a concatenation node, without flattening or rebalancing.
+    fn join(left: Rope, right: Rope) -> Rope {
+        let bytes = left.len_bytes() + right.len_bytes();
+        let chars = left.len_chars() + right.len_chars();
+        let depth = 1 + std::cmp::max(left.depth(), right.depth());
+        Rope(Rc::new(Node::Concat { left, right, bytes, chars, depth }))
+    }
+
+    // Build a balanced tree over the given pieces, in order.
+    fn join_balanced(pieces: &[Rope]) -> Rope {
+        match pieces.len() {
+            0 => Rope::new(),
+            1 => pieces[0].clone(),
+            n => {
+                let (left, right) = pieces.split_at(n / 2);
+                Rope::join(Rope::join_balanced(left), Rope::join_balanced(right))
+            }
+        }
+    }
+
+    // Rebuild the tree so that it is balanced, sharing all leaves.
+    fn rebalance(&self) -> Rope {
+        let mut leaves = vec![];
+        self.collect_leaves(&mut leaves);
+        Rope::join_balanced(&leaves)
+    }
+
+    fn collect_leaves(&self, leaves: &mut Vec<Rope>) {
+        match *self.0 {
+            Node::Leaf { .. } => {
+                if !self.is_empty() {
+                    leaves.push(self.clone());
+                }
+            }
+            Node::Concat { ref left, ref right, .. } => {
+                left.collect_leaves(leaves);
+                right.collect_leaves(leaves);
+            }
+        }
+    }
+
+    /// Convert a char index into the byte offset at which that char begins. An index equal to
+    /// the length of the rope is converted into the byte length of the rope.
+    pub fn char_to_byte(&self, char_index: usize) -> Result<usize, RopeError> {
+        if char_index > self.len_chars() {
+            return Err(RopeError::OutOfBounds { index: char_index, len: self.len_chars() });
+        }
+        Ok(self.char_to_byte_unchecked(char_index))
+    }
+
+    fn char_to_byte_unchecked(&self, char_index: usize) -> usize {
+        match *self.0 {
+            Node::Leaf { ref text, start, end, .. } => {
+                text[start..end]
+                    .char_indices()
+                    .nth(char_index)
+                    .map_or(end - start, |(offset, _)| offset)
+            }
+            Node::Concat { ref left, ref right, .. } => {
+                if char_index < left.len_chars() {
+                    left.char_to_byte_unchecked(char_index)
+                } else {
+                    left.len_bytes() + right.char_to_byte_unchecked(char_index - left.len_chars())
+                }
+            }
+        }
+    }
+
+    /// Convert a byte offset into the index of the char that begins at that offset. An offset
+    /// equal to the byte length of the rope is converted into the length of the rope.
+    pub fn byte_to_char(&self, byte_offset: usize) -> Result<usize, RopeError> {
+        if byte_offset > self.len_bytes() {
+            return Err(RopeError::OutOfBounds { index: byte_offset, len: self.len_bytes() });
+        }
+        if !self.is_char_boundary(byte_offset) {
+            return Err(RopeError::NotCharBoundary { offset: byte_offset });
+        }
+        Ok(self.byte_to_char_unchecked(byte_offset))
+    }
+
+    fn byte_to_char_unchecked(&self, byte_offset: usize) -> usize {
+        match *self.0 {
+            Node::Leaf { ref text, start, .. } => {
+                text[start..start + byte_offset].chars().count()
+            }
+            Node::Concat { ref left, ref right, .. } => {
+                if byte_offset < left.len_bytes() {
+                    left.byte_to_char_unchecked(byte_offset)
+                } else {
+                    left.len_chars() + right.byte_to_char_unchecked(byte_offset - left.len_bytes())
+                }
+            }
+        }
+    }
+
+    /// Returns whether the given byte offset lies on a char boundary. The start and the end of
+    /// the rope count as char boundaries.
+    pub fn is_char_boundary(&self, byte_offset: usize) -> bool {
+        match *self.0 {
+            Node::Leaf { ref text, start, end, .. } => {
+                byte_offset <= end - start && text.is_char_boundary(start + byte_offset)
+            }
+            Node::Concat { ref left, ref right, .. } => {
+                if byte_offset <= left.len_bytes() {
+                    left.is_char_boundary(byte_offset)
+                } else {
+                    right.is_char_boundary(byte_offset - left.len_bytes())
+                }
+            }
+        }
+    }
+
+    /// Return the sub-rope spanning the given range of chars. This shares all structure with
+    /// `self` and takes `O(log(n))` time.
+    pub fn slice(&self, range: Range<usize>) -> Result<Rope, RopeError> {
+        if range.start > range.end {
+            return Err(RopeError::InvertedRange { start: range.start, end: range.end });
+        }
+        let start = self.char_to_byte(range.start)?;
+        let end = self.char_to_byte(range.end)?;
+        Ok(self.byte_slice_unchecked(start, end))
+    }
+
+    /// Return the sub-rope spanning the given range of bytes, which must both lie on char
+    /// boundaries. This shares all structure with `self` and takes `O(log(n))` time.
+    pub fn byte_slice(&self, range: Range<usize>) -> Result<Rope, RopeError> {
+        if range.start > range.end {
+            return Err(RopeError::InvertedRange { start: range.start, end: range.end });
+        }
+        if range.end > self.len_bytes() {
+            return Err(RopeError::OutOfBounds { index: range.end, len: self.len_bytes() });
+        }
+        for &offset in &[range.start, range.end] {
+            if !self.is_char_boundary(offset) {
+                return Err(RopeError::NotCharBoundary { offset });
+            }
+        }
+        Ok(self.byte_slice_unchecked(range.start, range.end))
+    }
+
+    // Slice by byte offsets which are known to be in bounds and on char boundaries.
+    fn byte_slice_unchecked(&self, from: usize, to: usize) -> Rope {
+        if from == 0 && to == self.len_bytes() {
+            return self.clone();
+        }
+
+        match *self.0 {
+            Node::Leaf { ref text, start, .. } => {
+                let chars = text[start + from..start + to].chars().count();
+                Rope(Rc::new(Node::Leaf {
+                    text: text.clone(),
+                    start: start + from,
+                    end: start + to,
+                    chars,
+                }))
+            }
+            Node::Concat { ref left, ref right, .. } => {
+                let mid = left.len_bytes();
+                if to <= mid {
+                    left.byte_slice_unchecked(from, to)
+                } else if from >= mid {
+                    right.byte_slice_unchecked(from - mid, to - mid)
+                } else {
+                    left.byte_slice_unchecked(from, mid)
+                        .concat(&right.byte_slice_unchecked(0, to - mid))
+                }
+            }
+        }
+    }
+
+    /// Returns the char index of the first occurrence of `pat`, if any. An empty pattern is
+    /// found at index 0.
+    pub fn find(&self, pat: &str) -> Option<usize> {
+        self.find_bytes_from(pat.as_bytes(), 0)
+            .map(|offset| self.byte_to_char_unchecked(offset))
+    }
+
+    // Find the byte offset of the first occurrence of `needle` that starts at or after the byte
+    // offset `from` (which must lie on a char boundary). Occurrences may span any number of
+    // chunks. Since both the rope and the needle are valid utf-8, any match starts on a char
+    // boundary.
+    fn find_bytes_from(&self, needle: &[u8], from: usize) -> Option<usize> {
+        if needle.is_empty() {
+            return Some(from);
+        }
+
+        // The last `needle.len() - 1` bytes of the text searched so far, which may be the start
+        // of a match continuing in the next chunk.
+        let mut carry: Vec<u8> = Vec::with_capacity(needle.len());
+        // The offset of the first byte of `carry` (relative to `from`).
+        let mut carry_start = 0;
+        // The offset of the current chunk (relative to `from`).
+        let mut offset = 0;
+
+        for chunk in self.byte_slice_unchecked(from, self.len_bytes()).chunks() {
+            let chunk = chunk.as_bytes();
+
+            // Look for matches beginning in the carry.
+            if !carry.is_empty() {
+                let carried = carry.len();
+                carry.extend_from_slice(&chunk[..min(chunk.len(), needle.len() - 1)]);
+                if let Some(i) = find_bytes(&carry, needle) {
+                    if i < carried {
+                        return Some(from + carry_start + i);
+                    }
+                }
+                carry.truncate(carried);
+            }
+
+            // Look for matches beginning in the chunk.
+            if let Some(i) = find_bytes(chunk, needle) {
+                return Some(from + offset + i);
+            }
+
+            // Update the carry to the last `needle.len() - 1` bytes searched.
+            carry.extend_from_slice(chunk);
+            let excess = carry.len().saturating_sub(needle.len() - 1);
+            carry.drain(..excess);
+            carry_start = offset + chunk.len() - carry.len();
+            offset += chunk.len();
+        }
+
+        None
+    }
+
+    /// Returns the char index of the last occurrence of `pat`, if any. An empty pattern is found
+    /// at the end of the rope.
+    pub fn rfind(&self, pat: &str) -> Option<usize> {
+        self.rfind_bytes_before(pat.as_bytes(), self.len_bytes())
+            .map(|offset| self.byte_to_char_unchecked(offset))
+    }
+
+    // Find the byte offset of the last occurrence of `needle` that ends at or before the byte
+    // offset `to` (which must lie on a char boundary). This mirrors `find_bytes_from`, walking
+    // the chunks from right to left.
+    fn rfind_bytes_before(&self, needle: &[u8], to: usize) -> Option<usize> {
+        if needle.is_empty() {
+            return Some(to);
+        }
+
+        // The first `needle.len() - 1` bytes of the text searched so far, which may be the end of
+        // a match beginning in the next chunk.
+        let mut carry: Vec<u8> = Vec::with_capacity(needle.len());
+        // The offset one past the end of the current chunk.
+        let mut end = to;
+
+        for chunk in self.byte_slice_unchecked(0, to).chunks_rev() {
+            let chunk = chunk.as_bytes();
+            let start = end - chunk.len();
+
+            // Look for matches ending in the carry.
+            if !carry.is_empty() {
+                let tail = &chunk[chunk.len() - min(chunk.len(), needle.len() - 1)..];
+                let mut window = Vec::with_capacity(tail.len() + carry.len());
+                window.extend_from_slice(tail);
+                window.extend_from_slice(&carry);
+                if let Some(i) = rfind_bytes(&window, needle) {
+                    if i + needle.len() > tail.len() {
+                        return Some(end - tail.len() + i);
+                    }
+                }
+            }
+
+            // Look for matches ending in the chunk.
+            if let Some(i) = rfind_bytes(chunk, needle) {
+                return Some(start + i);
+            }
+
+            // Update the carry to the first `needle.len() - 1` bytes searched.
+            let mut new_carry = Vec::with_capacity(needle.len());
+            new_carry.extend_from_slice(chunk);
+            new_carry.extend_from_slice(&carry);
+            new_carry.truncate(needle.len() - 1);
+            carry = new_carry;
+            end = start;
+        }
+
+        None
+    }
+
+    /// Returns whether the rope begins with the given string.
+    pub fn starts_with(&self, pat: &str) -> bool {
+        let mut rest = pat.as_bytes();
+        for chunk in self.chunks() {
+            if rest.is_empty() {
+                break;
+            }
+            let chunk = chunk.as_bytes();
+            let n = min(chunk.len(), rest.len());
+            if chunk[..n] != rest[..n] {
+                return false;
+            }
+            rest = &rest[n..];
+        }
+        rest.is_empty()
+    }
+
+    /// Returns whether the rope ends with the given string.
+    pub fn ends_with(&self, pat: &str) -> bool {
+        if pat.len() > self.len_bytes() {
+            return false;
+        }
+        let start = self.len_bytes() - pat.len();
+        self.is_char_boundary(start)
+            && self.byte_slice_unchecked(start, self.len_bytes()).starts_with(pat)
+    }
+
+    /// Replace the first `max` (or all if `max` is `None`) non-overlapping occurrences of `from`
+    /// with `to`, scanning from left to right. The text between the replaced occurrences is
+    /// shared with `self` rather than copied, and if nothing is replaced, the result is a clone
+    /// of `self`.
+    ///
+    /// Errors if `from` is empty.
+    pub fn replace(&self, from: &str, to: &str, max: Option<usize>) -> Result<Rope, RopeError> {
+        if from.is_empty() {
+            return Err(RopeError::EmptyPattern);
+        }
+
+        let mut b = RopeBuilder::new();
+        let mut last = 0;
+        let mut count = 0;
+
+        while max != Some(count) {
+            match self.find_bytes_from(from.as_bytes(), last) {
+                Some(offset) => {
+                    b.push_rope(&self.byte_slice_unchecked(last, offset));
+                    b.push_str(to);
+                    last = offset + from.len();
+                    count += 1;
+                }
+                None => break,
+            }
+        }
+
+        if count == 0 {
+            return Ok(self.clone());
+        }
+
+        b.push_rope(&self.byte_slice_unchecked(last, self.len_bytes()));
+        Ok(b.finish())
+    }
+
+    /// Returns the char at the given char index, if any. Takes `O(log(n))` time.
+    pub fn get(&self, char_index: usize) -> Option<char> {
+        if char_index >= self.len_chars() {
+            return None;
+        }
+        let start = self.char_to_byte_unchecked(char_index);
+        self.byte_slice_unchecked(start, self.len_bytes()).chars().next()
+    }
+
+    /// Returns whether `pat` occurs in the rope.
+    pub fn contains(&self, pat: &str) -> bool {
+        self.find_bytes_from(pat.as_bytes(), 0).is_some()
+    }
+
+    /// Split the rope at all non-overlapping occurrences of `sep`, scanning from left to right.
+    /// The pieces share all structure with `self`. An empty separator splits the rope into its
+    /// chars (so an empty rope yields no pieces at all), otherwise there is always one piece
+    /// more than there are occurrences.
+    pub fn split(&self, sep: &str) -> Vec<Rope> {
+        if sep.is_empty() {
+            let mut pieces = Vec::with_capacity(self.len_chars());
+            let mut start = 0;
+            for c in self.chars() {
+                pieces.push(self.byte_slice_unchecked(start, start + c.len_utf8()));
+                start += c.len_utf8();
+            }
+            return pieces;
+        }
+
+        let mut pieces = vec![];
+        let mut last = 0;
+        while let Some(offset) = self.find_bytes_from(sep.as_bytes(), last) {
+            pieces.push(self.byte_slice_unchecked(last, offset));
+            last = offset + sep.len();
+        }
+        pieces.push(self.byte_slice_unchecked(last, self.len_bytes()));
+        pieces
+    }
+
+    /// Return the sub-rope without leading and trailing whitespace (as defined by
+    /// `char::is_whitespace`).
+    pub fn trim(&self) -> Rope {
+        self.trim_start().trim_end()
+    }
+
+    /// Return the sub-rope without leading whitespace.
+    pub fn trim_start(&self) -> Rope {
+        let start: usize = self
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .map(char::len_utf8)
+            .sum();
+        self.byte_slice_unchecked(start, self.len_bytes())
+    }
+
+    /// Return the sub-rope without trailing whitespace.
+    pub fn trim_end(&self) -> Rope {
+        let trailing: usize = self
+            .chars_rev()
+            .take_while(|c| c.is_whitespace())
+            .map(char::len_utf8)
+            .sum();
+        self.byte_slice_unchecked(0, self.len_bytes() - trailing)
+    }
+}
+
+// Return the index of the first occurrence of `needle` in `haystack`, if any.
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.len() > haystack.len() {
+        return None;
+    }
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+// Return the index of the last occurrence of `needle` in `haystack`, if any.
+fn rfind_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.len() > haystack.len() {
+        return None;
+    }
+    haystack.windows(needle.len()).rposition(|window| window == needle)
+}
+
+impl Default for Rope {
+    fn default() -> Rope {
+        Rope::new()
+    }
+}
+
+impl fmt::Debug for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use std::fmt::Write;
+
+        f.write_char('"')?;
+        for c in self.chars() {
+            for escaped in c.escape_debug() {
+                f.write_char(escaped)?;
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+impl fmt::Display for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for chunk in self.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+// Ropes compare by their content, which is independent of how the tree is shaped. The byte-wise
+// comparison of utf-8 coincides with the char-wise comparison.
+impl PartialEq for Rope {
+    fn eq(&self, other: &Rope) -> bool {
+        self.len_bytes() == other.len_bytes() && self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Rope {}
+
+impl PartialOrd for Rope {
+    fn partial_cmp(&self, other: &Rope) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Hashes only depend on the content of the rope, consistent with equality: the length, then the
+// bytes in blocks of a fixed size, so that also hashers that are not streaming (for which a single
+// `write` of two slices differs from two `write`s) hash equal ropes that are chunked differently
+// to the same value.
+impl Hash for Rope {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_blocks(self.len_bytes(), self.chunks().map(str::as_bytes), state);
+    }
+}
+
+const HASH_BLOCK: usize = 64;
+
+/// Feed `len` bytes, given in arbitrary chunks, to a hasher in a way that does not depend on the
+/// chunking. Shared with `Bytes`.
+pub(crate) fn hash_blocks<'a, H: Hasher>(
+    len: usize,
+    chunks: impl Iterator<Item = &'a [u8]>,
+    state: &mut H,
+) {
+    state.write_usize(len);
+    let mut block = [0u8; HASH_BLOCK];
+    let mut filled = 0;
+    for mut chunk in chunks {
+        if filled > 0 {
+            let n = chunk.len().min(HASH_BLOCK - filled);
+            block[filled..filled + n].copy_from_slice(&chunk[..n]);
+            filled += n;
+            chunk = &chunk[n..];
+            if filled < HASH_BLOCK {
+                continue;
+            }
+            state.write(&block);
+        }
+        let whole = chunk.len() - chunk.len() % HASH_BLOCK;
+        for full in chunk[..whole].chunks(HASH_BLOCK) {
+            state.write(full);
+        }
+        let rest = &chunk[whole..];
+        block[..rest.len()].copy_from_slice(rest);
+        filled = rest.len();
+    }
+    if filled > 0 {
+        state.write(&block[..filled]);
+    }
+}
+
+impl Ord for Rope {
+    fn cmp(&self, other: &Rope) -> Ordering {
+        if Rope::ptr_eq(self, other) {
+            return Ordering::Equal;
+        }
+
+        let mut chunks_a = self.chunks().map(str::as_bytes);
+        let mut chunks_b = other.chunks().map(str::as_bytes);
+        let mut a: &[u8] = &[];
+        let mut b: &[u8] = &[];
+
+        loop {
+            if a.is_empty() {
+                a = chunks_a.next().unwrap_or(&[]);
+            }
+            if b.is_empty() {
+                b = chunks_b.next().unwrap_or(&[]);
+            }
+
+            match (a.is_empty(), b.is_empty()) {
+                (true, true) => return Ordering::Equal,
+                (true, false) => return Ordering::Less,
+                (false, true) => return Ordering::Greater,
+                (false, false) => {}
+            }
+
+            let n = min(a.len(), b.len());
+            match a[..n].cmp(&b[..n]) {
+                Ordering::Equal => {
+                    a = &a[n..];
+                    b = &b[n..];
+                }
+                unequal => return unequal,
+            }
+        }
+    }
+}
+
+/// An iterator over the chunks of a rope, see `Rope::chunks`.
+pub struct Chunks<'a> {
+    // The next rope to descend into. Kept out of the stack so that iterating over a single leaf
+    // does not allocate.
+    next: Option<&'a Rope>,
+    // The ropes to the right of `next`, the rightmost one at the bottom.
+    stack: Vec<&'a Rope>,
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        loop {
+            let rope = match self.next.take() {
+                Some(rope) => rope,
+                None => self.stack.pop()?,
+            };
+
+            match *rope.0 {
+                Node::Leaf { ref text, start, end, .. } => {
+                    if start < end {
+                        return Some(&text[start..end]);
+                    }
+                }
+                Node::Concat { ref left, ref right, .. } => {
+                    self.stack.push(right);
+                    self.next = Some(left);
+                }
+            }
+        }
+    }
+}
+
+/// An iterator over the chars of a rope, see `Rope::chars`.
+pub struct Chars<'a> {
+    chunks: Chunks<'a>,
+    current: std::str::Chars<'a>,
+}
+
+impl<'a> Iterator for Chars<'a> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        loop {
+            if let Some(c) = self.current.next() {
+                return Some(c);
+            }
+            self.current = self.chunks.next()?.chars();
+        }
+    }
+}
+
+/// An iterator over the chunks of a rope in reverse order, see `Rope::chunks_rev`.
+pub struct ChunksRev<'a> {
+    next: Option<&'a Rope>,
+    // The ropes to the left of `next`, the leftmost one at the bottom.
+    stack: Vec<&'a Rope>,
+}
+
+impl<'a> Iterator for ChunksRev<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        loop {
+            let rope = match self.next.take() {
+                Some(rope) => rope,
+                None => self.stack.pop()?,
+            };
+
+            match *rope.0 {
+                Node::Leaf { ref text, start, end, .. } => {
+                    if start < end {
+                        return Some(&text[start..end]);
+                    }
+                }
+                Node::Concat { ref left, ref right, .. } => {
+                    self.stack.push(left);
+                    self.next = Some(right);
+                }
+            }
+        }
+    }
+}
+
+/// An iterator over the chars of a rope in reverse order, see `Rope::chars_rev`.
+pub struct CharsRev<'a> {
+    chunks: ChunksRev<'a>,
+    current: std::str::Chars<'a>,
+}
+
+impl<'a> Iterator for CharsRev<'a> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        loop {
+            if let Some(c) = self.current.next_back() {
+                return Some(c);
+            }
+            self.current = self.chunks.next()?.chars();
+        }
+    }
+}
+
+/// Incrementally builds a rope. Small pieces of text are collected into leaves of a reasonable
+/// size, large ropes are incorporated by reference rather than copied.
+pub struct RopeBuilder {
+    // Completed pieces of the rope, in order.
+    pieces: Vec<Rope>,
+    // Text that has not been turned into a piece yet.
+    buf: String,
+}
+
+impl RopeBuilder {
+    pub fn new() -> RopeBuilder {
+        RopeBuilder {
+            pieces: vec![],
+            buf: String::new(),
+        }
+    }
+
+    pub fn push_str(&mut self, mut s: &str) {
+        while !s.is_empty() {
+            let mut n = min(s.len(), CHUNK_SIZE - self.buf.len());
+            while !s.is_char_boundary(n) {
+                n -= 1;
+            }
+            self.buf.push_str(&s[..n]);
+            s = &s[n..];
+
+            if self.buf.len() + 4 > CHUNK_SIZE {
+                self.flush();
+            }
+        }
+    }
+
+    pub fn push_char(&mut self, c: char) {
+        self.push_str(c.encode_utf8(&mut [0; 4]));
+    }
+
+    /// Append the text of a rope. Ropes that are not tiny are shared rather than copied.
+    pub fn push_rope(&mut self, r: &Rope) {
+        if r.len_bytes() <= FLATTEN_SIZE {
+            for chunk in r.chunks() {
+                self.push_str(chunk);
+            }
+        } else {
+            self.flush();
+            self.pieces.push(r.clone());
+        }
+    }
+
+    // Turn the buffered text into a piece.
+    fn flush(&mut self) {
+        if !self.buf.is_empty() {
+            let text = std::mem::take(&mut self.buf);
+            self.pieces.push(Rope::leaf(Rc::from(text)));
+        }
+    }
+
+    pub fn finish(mut self) -> Rope {
+        self.flush();
+        let joined = Rope::join_balanced(&self.pieces);
+        if joined.depth() > MAX_DEPTH {
+            joined.rebalance()
+        } else {
+            joined
+        }
+    }
+}
+
+impl Default for RopeBuilder {
+    fn default() -> RopeBuilder {
+        RopeBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Checks the cached lengths and depths, returns the depth.
+    fn check(rope: &Rope) -> usize {
+        match rope.children() {
+            None => 0,
+            Some((left, right)) => {
+                let depth = 1 + std::cmp::max(check(left), check(right));
+                assert_eq!(rope.depth(), depth);
+                assert_eq!(rope.len_bytes(), left.len_bytes() + right.len_bytes());
+                assert_eq!(rope.len_chars(), left.len_chars() + right.len_chars());
+                depth
+            }
+        }
+    }
+
+    #[test]
+    fn concatenation_keeps_trees_shallow() {
+        let pieces: Vec<Rope> = (0..10_000)
+            .map(|i| Rope::from_str(&((b'a' + (i % 26) as u8) as char).to_string().repeat(300)))
+            .collect();
+        let expected: String = pieces.iter().map(Rope::to_string).collect();
+        // An AVL tree with 10_000 leaves is at most 19 levels deep.
+        let appended = pieces.iter().fold(Rope::new(), |acc, piece| acc.concat(piece));
+        assert!(check(&appended) <= 19, "depth {}", appended.depth());
+        assert_eq!(appended.to_string(), expected);
+        let prepended = pieces.iter().rev().fold(Rope::new(), |acc, piece| piece.concat(&acc));
+        assert!(check(&prepended) <= 19, "depth {}", prepended.depth());
+        assert_eq!(prepended.to_string(), expected);
     }
 }
@@ -0,0 +1,864 @@
+// Segmentation of ropes into extended grapheme clusters, following the rules of
+// [UAX #29](https://www.unicode.org/reports/tr29/#Grapheme_Cluster_Boundary_Rules) (without the
+// indic conjunct rule GB9c).
+
+use std::cmp::Ordering;
+use std::ops::Range;
+
+use super::{Chars, Rope, RopeError};
+
+impl Rope {
+    /// Iterate over the extended grapheme clusters of this rope, as sub-ropes sharing structure
+    /// with `self`.
+    pub fn graphemes(&self) -> Graphemes<'_> {
+        let mut boundaries = Boundaries::new(self);
+        let start = boundaries.next().unwrap_or(0);
+        Graphemes {
+            rope: self,
+            boundaries,
+            start,
+        }
+    }
+
+    /// The number of extended grapheme clusters in this rope. Takes `O(n)` time.
+    pub fn len_graphemes(&self) -> usize {
+        Boundaries::new(self).count() - 1
+    }
+
+    /// Return the sub-rope spanning the given range of grapheme clusters. Takes `O(n)` time.
+    pub fn slice_graphemes(&self, range: Range<usize>) -> Result<Rope, RopeError> {
+        if range.start > range.end {
+            return Err(RopeError::InvertedRange { start: range.start, end: range.end });
+        }
+
+        let mut start = None;
+        let mut end = None;
+        let mut count = 0;
+        for (i, offset) in Boundaries::new(self).enumerate() {
+            if i == range.start {
+                start = Some(offset);
+            }
+            if i == range.end {
+                end = Some(offset);
+            }
+            count = i;
+        }
+
+        match (start, end) {
+            (Some(start), Some(end)) => Ok(self.byte_slice_unchecked(start, end)),
+            _ => Err(RopeError::OutOfBounds { index: range.end, len: count }),
+        }
+    }
+}
+
+/// An iterator over the grapheme clusters of a rope, see `Rope::graphemes`.
+pub struct Graphemes<'a> {
+    rope: &'a Rope,
+    boundaries: Boundaries<'a>,
+    // The byte offset at which the next cluster starts.
+    start: usize,
+}
+
+impl<'a> Iterator for Graphemes<'a> {
+    type Item = Rope;
+
+    fn next(&mut self) -> Option<Rope> {
+        let end = self.boundaries.next()?;
+        let cluster = self.rope.byte_slice_unchecked(self.start, end);
+        self.start = end;
+        Some(cluster)
+    }
+}
+
+// Yields the byte offsets of all grapheme cluster boundaries of a rope, including the start and
+// the end of the rope (which coincide for the empty rope).
+struct Boundaries<'a> {
+    chars: Chars<'a>,
+    segmenter: Segmenter,
+    offset: usize,
+    done: bool,
+}
+
+impl<'a> Boundaries<'a> {
+    fn new(rope: &'a Rope) -> Boundaries<'a> {
+        Boundaries {
+            chars: rope.chars(),
+            segmenter: Segmenter::new(),
+            offset: 0,
+            done: false,
+        }
+    }
+}
+
+impl<'a> Iterator for Boundaries<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        loop {
+            match self.chars.next() {
+                Some(c) => {
+                    let offset = self.offset;
+                    self.offset += c.len_utf8();
+                    if self.segmenter.breaks_before(c) {
+                        return Some(offset);
+                    }
+                }
+                None => {
+                    if self.done {
+                        return None;
+                    }
+                    self.done = true;
+                    return Some(self.offset);
+                }
+            }
+        }
+    }
+}
+
+// The state needed to decide whether there is a cluster boundary before the next char.
+struct Segmenter {
+    // The break property of the previous char, `None` at the start of the text.
+    prev: Option<Gcb>,
+    // Progress through an emoji zwj sequence (GB11).
+    emoji: EmojiSequence,
+    // Whether the next char is preceded by an odd number of consecutive regional indicators
+    // (GB12/13).
+    odd_regional_indicators: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum EmojiSequence {
+    None,
+    // Seen an extended pictographic char followed by any number of extending chars.
+    Started,
+    // Seen the above followed by a zero-width joiner.
+    Joined,
+}
+
+impl Segmenter {
+    fn new() -> Segmenter {
+        Segmenter {
+            prev: None,
+            emoji: EmojiSequence::None,
+            odd_regional_indicators: false,
+        }
+    }
+
+    // Feed the next char into the segmenter, returning whether there is a boundary before it.
+    fn breaks_before(&mut self, c: char) -> bool {
+        use self::Gcb::*;
+
+        let next = gcb(c);
+        let boundary = match self.prev {
+            None => true, // GB1
+            Some(prev) => match (prev, next) {
+                (Cr, Lf) => false, // GB3
+                (Cr, _) | (Lf, _) | (Control, _) => true, // GB4
+                (_, Cr) | (_, Lf) | (_, Control) => true, // GB5
+                (L, L) | (L, V) | (L, Lv) | (L, Lvt) => false, // GB6
+                (Lv, V) | (Lv, T) | (V, V) | (V, T) => false, // GB7
+                (Lvt, T) | (T, T) => false, // GB8
+                (_, Extend) | (_, Zwj) => false, // GB9
+                (_, SpacingMark) => false, // GB9a
+                (Prepend, _) => false, // GB9b
+                (Zwj, ExtendedPictographic) if self.emoji == EmojiSequence::Joined => false, // GB11
+                (RegionalIndicator, RegionalIndicator) => !self.odd_regional_indicators, // GB12/13
+                _ => true, // GB999
+            },
+        };
+
+        self.emoji = match (next, self.emoji) {
+            (ExtendedPictographic, _) => EmojiSequence::Started,
+            (Extend, EmojiSequence::Started) => EmojiSequence::Started,
+            (Zwj, EmojiSequence::Started) => EmojiSequence::Joined,
+            _ => EmojiSequence::None,
+        };
+        self.odd_regional_indicators = next == RegionalIndicator && !self.odd_regional_indicators;
+        self.prev = Some(next);
+
+        boundary
+    }
+}
+
+// The values of the Grapheme_Cluster_Break property, plus Extended_Pictographic.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Gcb {
+    Other,
+    Cr,
+    Lf,
+    Control,
+    Extend,
+    Zwj,
+    RegionalIndicator,
+    Prepend,
+    SpacingMark,
+    L,
+    V,
+    T,
+    Lv,
+    Lvt,
+    ExtendedPictographic,
+}
+
+fn gcb(c: char) -> Gcb {
+    let c = c as u32;
+
+    // Hangul syllables alternate between LV and LVT, so they are computed rather than tabulated.
+    if (0xAC00..=0xD7A3).contains(&c) {
+        return if (c - 0xAC00).is_multiple_of(28) { Gcb::Lv } else { Gcb::Lvt };
+    }
+
+    let found = GCB_TABLE.binary_search_by(|&(lo, hi, _)| {
+        if hi < c {
+            Ordering::Less
+        } else if lo > c {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }
+    });
+    match found {
+        Ok(i) => GCB_TABLE[i].2,
+        Err(_) => Gcb::Other,
+    }
+}
+
+// Inclusive, sorted ranges of chars whose break property is not `Other`. Generated from the
+// Unicode Character Database (version 14.0).
+#[rustfmt::skip]
+static GCB_TABLE: &[(u32, u32, Gcb)] = &[
+    (0x0, 0x9, Gcb::Control),
+    (0xa, 0xa, Gcb::Lf),
+    (0xb, 0xc, Gcb::Control),
+    (0xd, 0xd, Gcb::Cr),
+    (0xe, 0x1f, Gcb::Control),
+    (0x7f, 0x9f, Gcb::Control),
+    (0xa9, 0xa9, Gcb::ExtendedPictographic),
+    (0xad, 0xad, Gcb::Control),
+    (0xae, 0xae, Gcb::ExtendedPictographic),
+    (0x300, 0x36f, Gcb::Extend),
+    (0x483, 0x489, Gcb::Extend),
+    (0x591, 0x5bd, Gcb::Extend),
+    (0x5bf, 0x5bf, Gcb::Extend),
+    (0x5c1, 0x5c2, Gcb::Extend),
+    (0x5c4, 0x5c5, Gcb::Extend),
+    (0x5c7, 0x5c7, Gcb::Extend),
+    (0x600, 0x605, Gcb::Prepend),
+    (0x610, 0x61a, Gcb::Extend),
+    (0x61c, 0x61c, Gcb::Control),
+    (0x64b, 0x65f, Gcb::Extend),
+    (0x670, 0x670, Gcb::Extend),
+    (0x6d6, 0x6dc, Gcb::Extend),
+    (0x6dd, 0x6dd, Gcb::Prepend),
+    (0x6df, 0x6e4, Gcb::Extend),
+    (0x6e7, 0x6e8, Gcb::Extend),
+    (0x6ea, 0x6ed, Gcb::Extend),
+    (0x70f, 0x70f, Gcb::Prepend),
+    (0x711, 0x711, Gcb::Extend),
+    (0x730, 0x74a, Gcb::Extend),
+    (0x7a6, 0x7b0, Gcb::Extend),
+    (0x7eb, 0x7f3, Gcb::Extend),
+    (0x7fd, 0x7fd, Gcb::Extend),
+    (0x816, 0x819, Gcb::Extend),
+    (0x81b, 0x823, Gcb::Extend),
+    (0x825, 0x827, Gcb::Extend),
+    (0x829, 0x82d, Gcb::Extend),
+    (0x859, 0x85b, Gcb::Extend),
+    (0x890, 0x891, Gcb::Prepend),
+    (0x898, 0x89f, Gcb::Extend),
+    (0x8ca, 0x8e1, Gcb::Extend),
+    (0x8e2, 0x8e2, Gcb::Prepend),
+    (0x8e3, 0x902, Gcb::Extend),
+    (0x903, 0x903, Gcb::SpacingMark),
+    (0x93a, 0x93a, Gcb::Extend),
+    (0x93b, 0x93b, Gcb::SpacingMark),
+    (0x93c, 0x93c, Gcb::Extend),
+    (0x93e, 0x940, Gcb::SpacingMark),
+    (0x941, 0x948, Gcb::Extend),
+    (0x949, 0x94c, Gcb::SpacingMark),
+    (0x94d, 0x94d, Gcb::Extend),
+    (0x94e, 0x94f, Gcb::SpacingMark),
+    (0x951, 0x957, Gcb::Extend),
+    (0x962, 0x963, Gcb::Extend),
+    (0x981, 0x981, Gcb::Extend),
+    (0x982, 0x983, Gcb::SpacingMark),
+    (0x9bc, 0x9bc, Gcb::Extend),
+    (0x9be, 0x9be, Gcb::Extend),
+    (0x9bf, 0x9c0, Gcb::SpacingMark),
+    (0x9c1, 0x9c4, Gcb::Extend),
+    (0x9c7, 0x9c8, Gcb::SpacingMark),
+    (0x9cb, 0x9cc, Gcb::SpacingMark),
+    (0x9cd, 0x9cd, Gcb::Extend),
+    (0x9d7, 0x9d7, Gcb::Extend),
+    (0x9e2, 0x9e3, Gcb::Extend),
+    (0x9fe, 0x9fe, Gcb::Extend),
+    (0xa01, 0xa02, Gcb::Extend),
+    (0xa03, 0xa03, Gcb::SpacingMark),
+    (0xa3c, 0xa3c, Gcb::Extend),
+    (0xa3e, 0xa40, Gcb::SpacingMark),
+    (0xa41, 0xa42, Gcb::Extend),
+    (0xa47, 0xa48, Gcb::Extend),
+    (0xa4b, 0xa4d, Gcb::Extend),
+    (0xa51, 0xa51, Gcb::Extend),
+    (0xa70, 0xa71, Gcb::Extend),
+    (0xa75, 0xa75, Gcb::Extend),
+    (0xa81, 0xa82, Gcb::Extend),
+    (0xa83, 0xa83, Gcb::SpacingMark),
+    (0xabc, 0xabc, Gcb::Extend),
+    (0xabe, 0xac0, Gcb::SpacingMark),
+    (0xac1, 0xac5, Gcb::Extend),
+    (0xac7, 0xac8, Gcb::Extend),
+    (0xac9, 0xac9, Gcb::SpacingMark),
+    (0xacb, 0xacc, Gcb::SpacingMark),
+    (0xacd, 0xacd, Gcb::Extend),
+    (0xae2, 0xae3, Gcb::Extend),
+    (0xafa, 0xaff, Gcb::Extend),
+    (0xb01, 0xb01, Gcb::Extend),
+    (0xb02, 0xb03, Gcb::SpacingMark),
+    (0xb3c, 0xb3c, Gcb::Extend),
+    (0xb3e, 0xb3f, Gcb::Extend),
+    (0xb40, 0xb40, Gcb::SpacingMark),
+    (0xb41, 0xb44, Gcb::Extend),
+    (0xb47, 0xb48, Gcb::SpacingMark),
+    (0xb4b, 0xb4c, Gcb::SpacingMark),
+    (0xb4d, 0xb4d, Gcb::Extend),
+    (0xb55, 0xb57, Gcb::Extend),
+    (0xb62, 0xb63, Gcb::Extend),
+    (0xb82, 0xb82, Gcb::Extend),
+    (0xbbe, 0xbbe, Gcb::Extend),
+    (0xbbf, 0xbbf, Gcb::SpacingMark),
+    (0xbc0, 0xbc0, Gcb::Extend),
+    (0xbc1, 0xbc2, Gcb::SpacingMark),
+    (0xbc6, 0xbc8, Gcb::SpacingMark),
+    (0xbca, 0xbcc, Gcb::SpacingMark),
+    (0xbcd, 0xbcd, Gcb::Extend),
+    (0xbd7, 0xbd7, Gcb::Extend),
+    (0xc00, 0xc00, Gcb::Extend),
+    (0xc01, 0xc03, Gcb::SpacingMark),
+    (0xc04, 0xc04, Gcb::Extend),
+    (0xc3c, 0xc3c, Gcb::Extend),
+    (0xc3e, 0xc40, Gcb::Extend),
+    (0xc41, 0xc44, Gcb::SpacingMark),
+    (0xc46, 0xc48, Gcb::Extend),
+    (0xc4a, 0xc4d, Gcb::Extend),
+    (0xc55, 0xc56, Gcb::Extend),
+    (0xc62, 0xc63, Gcb::Extend),
+    (0xc81, 0xc81, Gcb::Extend),
+    (0xc82, 0xc83, Gcb::SpacingMark),
+    (0xcbc, 0xcbc, Gcb::Extend),
+    (0xcbe, 0xcbe, Gcb::SpacingMark),
+    (0xcbf, 0xcbf, Gcb::Extend),
+    (0xcc0, 0xcc1, Gcb::SpacingMark),
+    (0xcc2, 0xcc2, Gcb::Extend),
+    (0xcc3, 0xcc4, Gcb::SpacingMark),
+    (0xcc6, 0xcc6, Gcb::Extend),
+    (0xcc7, 0xcc8, Gcb::SpacingMark),
+    (0xcca, 0xccb, Gcb::SpacingMark),
+    (0xccc, 0xccd, Gcb::Extend),
+    (0xcd5, 0xcd6, Gcb::Extend),
+    (0xce2, 0xce3, Gcb::Extend),
+    (0xd00, 0xd01, Gcb::Extend),
+    (0xd02, 0xd03, Gcb::SpacingMark),
+    (0xd3b, 0xd3c, Gcb::Extend),
+    (0xd3e, 0xd3e, Gcb::Extend),
+    (0xd3f, 0xd40, Gcb::SpacingMark),
+    (0xd41, 0xd44, Gcb::Extend),
+    (0xd46, 0xd48, Gcb::SpacingMark),
+    (0xd4a, 0xd4c, Gcb::SpacingMark),
+    (0xd4d, 0xd4d, Gcb::Extend),
+    (0xd4e, 0xd4e, Gcb::Prepend),
+    (0xd57, 0xd57, Gcb::Extend),
+    (0xd62, 0xd63, Gcb::Extend),
+    (0xd81, 0xd81, Gcb::Extend),
+    (0xd82, 0xd83, Gcb::SpacingMark),
+    (0xdca, 0xdca, Gcb::Extend),
+    (0xdcf, 0xdcf, Gcb::Extend),
+    (0xdd0, 0xdd1, Gcb::SpacingMark),
+    (0xdd2, 0xdd4, Gcb::Extend),
+    (0xdd6, 0xdd6, Gcb::Extend),
+    (0xdd8, 0xdde, Gcb::SpacingMark),
+    (0xddf, 0xddf, Gcb::Extend),
+    (0xdf2, 0xdf3, Gcb::SpacingMark),
+    (0xe31, 0xe31, Gcb::Extend),
+    (0xe33, 0xe33, Gcb::SpacingMark),
+    (0xe34, 0xe3a, Gcb::Extend),
+    (0xe47, 0xe4e, Gcb::Extend),
+    (0xeb1, 0xeb1, Gcb::Extend),
+    (0xeb3, 0xeb3, Gcb::SpacingMark),
+    (0xeb4, 0xebc, Gcb::Extend),
+    (0xec8, 0xecd, Gcb::Extend),
+    (0xf18, 0xf19, Gcb::Extend),
+    (0xf35, 0xf35, Gcb::Extend),
+    (0xf37, 0xf37, Gcb::Extend),
+    (0xf39, 0xf39, Gcb::Extend),
+    (0xf3e, 0xf3f, Gcb::SpacingMark),
+    (0xf71, 0xf7e, Gcb::Extend),
+    (0xf7f, 0xf7f, Gcb::SpacingMark),
+    (0xf80, 0xf84, Gcb::Extend),
+    (0xf86, 0xf87, Gcb::Extend),
+    (0xf8d, 0xf97, Gcb::Extend),
+    (0xf99, 0xfbc, Gcb::Extend),
+    (0xfc6, 0xfc6, Gcb::Extend),
+    (0x102d, 0x1030, Gcb::Extend),
+    (0x1031, 0x1031, Gcb::SpacingMark),
+    (0x1032, 0x1037, Gcb::Extend),
+    (0x1039, 0x103a, Gcb::Extend),
+    (0x103b, 0x103c, Gcb::SpacingMark),
+    (0x103d, 0x103e, Gcb::Extend),
+    (0x1056, 0x1057, Gcb::SpacingMark),
+    (0x1058, 0x1059, Gcb::Extend),
+    (0x105e, 0x1060, Gcb::Extend),
+    (0x1071, 0x1074, Gcb::Extend),
+    (0x1082, 0x1082, Gcb::Extend),
+    (0x1084, 0x1084, Gcb::SpacingMark),
+    (0x1085, 0x1086, Gcb::Extend),
+    (0x108d, 0x108d, Gcb::Extend),
+    (0x109d, 0x109d, Gcb::Extend),
+    (0x1100, 0x115f, Gcb::L),
+    (0x1160, 0x11a7, Gcb::V),
+    (0x11a8, 0x11ff, Gcb::T),
+    (0x135d, 0x135f, Gcb::Extend),
+    (0x1712, 0x1714, Gcb::Extend),
+    (0x1715, 0x1715, Gcb::SpacingMark),
+    (0x1732, 0x1733, Gcb::Extend),
+    (0x1734, 0x1734, Gcb::SpacingMark),
+    (0x1752, 0x1753, Gcb::Extend),
+    (0x1772, 0x1773, Gcb::Extend),
+    (0x17b4, 0x17b5, Gcb::Extend),
+    (0x17b6, 0x17b6, Gcb::SpacingMark),
+    (0x17b7, 0x17bd, Gcb::Extend),
+    (0x17be, 0x17c5, Gcb::SpacingMark),
+    (0x17c6, 0x17c6, Gcb::Extend),
+    (0x17c7, 0x17c8, Gcb::SpacingMark),
+    (0x17c9, 0x17d3, Gcb::Extend),
+    (0x17dd, 0x17dd, Gcb::Extend),
+    (0x180b, 0x180d, Gcb::Extend),
+    (0x180e, 0x180e, Gcb::Control),
+    (0x180f, 0x180f, Gcb::Extend),
+    (0x1885, 0x1886, Gcb::Extend),
+    (0x18a9, 0x18a9, Gcb::Extend),
+    (0x1920, 0x1922, Gcb::Extend),
+    (0x1923, 0x1926, Gcb::SpacingMark),
+    (0x1927, 0x1928, Gcb::Extend),
+    (0x1929, 0x192b, Gcb::SpacingMark),
+    (0x1930, 0x1931, Gcb::SpacingMark),
+    (0x1932, 0x1932, Gcb::Extend),
+    (0x1933, 0x1938, Gcb::SpacingMark),
+    (0x1939, 0x193b, Gcb::Extend),
+    (0x1a17, 0x1a18, Gcb::Extend),
+    (0x1a19, 0x1a1a, Gcb::SpacingMark),
+    (0x1a1b, 0x1a1b, Gcb::Extend),
+    (0x1a55, 0x1a55, Gcb::SpacingMark),
+    (0x1a56, 0x1a56, Gcb::Extend),
+    (0x1a57, 0x1a57, Gcb::SpacingMark),
+    (0x1a58, 0x1a5e, Gcb::Extend),
+    (0x1a60, 0x1a60, Gcb::Extend),
+    (0x1a62, 0x1a62, Gcb::Extend),
+    (0x1a65, 0x1a6c, Gcb::Extend),
+    (0x1a6d, 0x1a72, Gcb::SpacingMark),
+    (0x1a73, 0x1a7c, Gcb::Extend),
+    (0x1a7f, 0x1a7f, Gcb::Extend),
+    (0x1ab0, 0x1ace, Gcb::Extend),
+    (0x1b00, 0x1b03, Gcb::Extend),
+    (0x1b04, 0x1b04, Gcb::SpacingMark),
+    (0x1b34, 0x1b3a, Gcb::Extend),
+    (0x1b3b, 0x1b3b, Gcb::SpacingMark),
+    (0x1b3c, 0x1b3c, Gcb::Extend),
+    (0x1b3d, 0x1b41, Gcb::SpacingMark),
+    (0x1b42, 0x1b42, Gcb::Extend),
+    (0x1b43, 0x1b44, Gcb::SpacingMark),
+    (0x1b6b, 0x1b73, Gcb::Extend),
+    (0x1b80, 0x1b81, Gcb::Extend),
+    (0x1b82, 0x1b82, Gcb::SpacingMark),
+    (0x1ba1, 0x1ba1, Gcb::SpacingMark),
+    (0x1ba2, 0x1ba5, Gcb::Extend),
+    (0x1ba6, 0x1ba7, Gcb::SpacingMark),
+    (0x1ba8, 0x1ba9, Gcb::Extend),
+    (0x1baa, 0x1baa, Gcb::SpacingMark),
+    (0x1bab, 0x1bad, Gcb::Extend),
+    (0x1be6, 0x1be6, Gcb::Extend),
+    (0x1be7, 0x1be7, Gcb::SpacingMark),
+    (0x1be8, 0x1be9, Gcb::Extend),
+    (0x1bea, 0x1bec, Gcb::SpacingMark),
+    (0x1bed, 0x1bed, Gcb::Extend),
+    (0x1bee, 0x1bee, Gcb::SpacingMark),
+    (0x1bef, 0x1bf1, Gcb::Extend),
+    (0x1bf2, 0x1bf3, Gcb::SpacingMark),
+    (0x1c24, 0x1c2b, Gcb::SpacingMark),
+    (0x1c2c, 0x1c33, Gcb::Extend),
+    (0x1c34, 0x1c35, Gcb::SpacingMark),
+    (0x1c36, 0x1c37, Gcb::Extend),
+    (0x1cd0, 0x1cd2, Gcb::Extend),
+    (0x1cd4, 0x1ce0, Gcb::Extend),
+    (0x1ce1, 0x1ce1, Gcb::SpacingMark),
+    (0x1ce2, 0x1ce8, Gcb::Extend),
+    (0x1ced, 0x1ced, Gcb::Extend),
+    (0x1cf4, 0x1cf4, Gcb::Extend),
+    (0x1cf7, 0x1cf7, Gcb::SpacingMark),
+    (0x1cf8, 0x1cf9, Gcb::Extend),
+    (0x1dc0, 0x1dff, Gcb::Extend),
+    (0x200b, 0x200b, Gcb::Control),
+    (0x200c, 0x200c, Gcb::Extend),
+    (0x200d, 0x200d, Gcb::Zwj),
+    (0x200e, 0x200f, Gcb::Control),
+    (0x2028, 0x202e, Gcb::Control),
+    (0x203c, 0x203c, Gcb::ExtendedPictographic),
+    (0x2049, 0x2049, Gcb::ExtendedPictographic),
+    (0x2060, 0x2064, Gcb::Control),
+    (0x2066, 0x206f, Gcb::Control),
+    (0x20d0, 0x20f0, Gcb::Extend),
+    (0x2122, 0x2122, Gcb::ExtendedPictographic),
+    (0x2139, 0x2139, Gcb::ExtendedPictographic),
+    (0x2194, 0x2199, Gcb::ExtendedPictographic),
+    (0x21a9, 0x21aa, Gcb::ExtendedPictographic),
+    (0x231a, 0x231b, Gcb::ExtendedPictographic),
+    (0x2328, 0x2328, Gcb::ExtendedPictographic),
+    (0x2388, 0x2388, Gcb::ExtendedPictographic),
+    (0x23cf, 0x23cf, Gcb::ExtendedPictographic),
+    (0x23e9, 0x23f3, Gcb::ExtendedPictographic),
+    (0x23f8, 0x23fa, Gcb::ExtendedPictographic),
+    (0x24c2, 0x24c2, Gcb::ExtendedPictographic),
+    (0x25aa, 0x25ab, Gcb::ExtendedPictographic),
+    (0x25b6, 0x25b6, Gcb::ExtendedPictographic),
+    (0x25c0, 0x25c0, Gcb::ExtendedPictographic),
+    (0x25fb, 0x25fe, Gcb::ExtendedPictographic),
+    (0x2600, 0x2605, Gcb::ExtendedPictographic),
+    (0x2607, 0x2612, Gcb::ExtendedPictographic),
+    (0x2614, 0x2685, Gcb::ExtendedPictographic),
+    (0x2690, 0x2705, Gcb::ExtendedPictographic),
+    (0x2708, 0x2712, Gcb::ExtendedPictographic),
+    (0x2714, 0x2714, Gcb::ExtendedPictographic),
+    (0x2716, 0x2716, Gcb::ExtendedPictographic),
+    (0x271d, 0x271d, Gcb::ExtendedPictographic),
+    (0x2721, 0x2721, Gcb::ExtendedPictographic),
+    (0x2728, 0x2728, Gcb::ExtendedPictographic),
+    (0x2733, 0x2734, Gcb::ExtendedPictographic),
+    (0x2744, 0x2744, Gcb::ExtendedPictographic),
+    (0x2747, 0x2747, Gcb::ExtendedPictographic),
+    (0x274c, 0x274c, Gcb::ExtendedPictographic),
+    (0x274e, 0x274e, Gcb::ExtendedPictographic),
+    (0x2753, 0x2755, Gcb::ExtendedPictographic),
+    (0x2757, 0x2757, Gcb::ExtendedPictographic),
+    (0x2763, 0x2767, Gcb::ExtendedPictographic),
+    (0x2795, 0x2797, Gcb::ExtendedPictographic),
+    (0x27a1, 0x27a1, Gcb::ExtendedPictographic),
+    (0x27b0, 0x27b0, Gcb::ExtendedPictographic),
+    (0x27bf, 0x27bf, Gcb::ExtendedPictographic),
+    (0x2934, 0x2935, Gcb::ExtendedPictographic),
+    (0x2b05, 0x2b07, Gcb::ExtendedPictographic),
+    (0x2b1b, 0x2b1c, Gcb::ExtendedPictographic),
+    (0x2b50, 0x2b50, Gcb::ExtendedPictographic),
+    (0x2b55, 0x2b55, Gcb::ExtendedPictographic),
+    (0x2cef, 0x2cf1, Gcb::Extend),
+    (0x2d7f, 0x2d7f, Gcb::Extend),
+    (0x2de0, 0x2dff, Gcb::Extend),
+    (0x302a, 0x302f, Gcb::Extend),
+    (0x3030, 0x3030, Gcb::ExtendedPictographic),
+    (0x303d, 0x303d, Gcb::ExtendedPictographic),
+    (0x3099, 0x309a, Gcb::Extend),
+    (0x3297, 0x3297, Gcb::ExtendedPictographic),
+    (0x3299, 0x3299, Gcb::ExtendedPictographic),
+    (0xa66f, 0xa672, Gcb::Extend),
+    (0xa674, 0xa67d, Gcb::Extend),
+    (0xa69e, 0xa69f, Gcb::Extend),
+    (0xa6f0, 0xa6f1, Gcb::Extend),
+    (0xa802, 0xa802, Gcb::Extend),
+    (0xa806, 0xa806, Gcb::Extend),
+    (0xa80b, 0xa80b, Gcb::Extend),
+    (0xa823, 0xa824, Gcb::SpacingMark),
+    (0xa825, 0xa826, Gcb::Extend),
+    (0xa827, 0xa827, Gcb::SpacingMark),
+    (0xa82c, 0xa82c, Gcb::Extend),
+    (0xa880, 0xa881, Gcb::SpacingMark),
+    (0xa8b4, 0xa8c3, Gcb::SpacingMark),
+    (0xa8c4, 0xa8c5, Gcb::Extend),
+    (0xa8e0, 0xa8f1, Gcb::Extend),
+    (0xa8ff, 0xa8ff, Gcb::Extend),
+    (0xa926, 0xa92d, Gcb::Extend),
+    (0xa947, 0xa951, Gcb::Extend),
+    (0xa952, 0xa953, Gcb::SpacingMark),
+    (0xa960, 0xa97c, Gcb::L),
+    (0xa980, 0xa982, Gcb::Extend),
+    (0xa983, 0xa983, Gcb::SpacingMark),
+    (0xa9b3, 0xa9b3, Gcb::Extend),
+    (0xa9b4, 0xa9b5, Gcb::SpacingMark),
+    (0xa9b6, 0xa9b9, Gcb::Extend),
+    (0xa9ba, 0xa9bb, Gcb::SpacingMark),
+    (0xa9bc, 0xa9bd, Gcb::Extend),
+    (0xa9be, 0xa9c0, Gcb::SpacingMark),
+    (0xa9e5, 0xa9e5, Gcb::Extend),
+    (0xaa29, 0xaa2e, Gcb::Extend),
+    (0xaa2f, 0xaa30, Gcb::SpacingMark),
+    (0xaa31, 0xaa32, Gcb::Extend),
+    (0xaa33, 0xaa34, Gcb::SpacingMark),
+    (0xaa35, 0xaa36, Gcb::Extend),
+    (0xaa43, 0xaa43, Gcb::Extend),
+    (0xaa4c, 0xaa4c, Gcb::Extend),
+    (0xaa4d, 0xaa4d, Gcb::SpacingMark),
+    (0xaa7c, 0xaa7c, Gcb::Extend),
+    (0xaab0, 0xaab0, Gcb::Extend),
+    (0xaab2, 0xaab4, Gcb::Extend),
+    (0xaab7, 0xaab8, Gcb::Extend),
+    (0xaabe, 0xaabf, Gcb::Extend),
+    (0xaac1, 0xaac1, Gcb::Extend),
+    (0xaaeb, 0xaaeb, Gcb::SpacingMark),
+    (0xaaec, 0xaaed, Gcb::Extend),
+    (0xaaee, 0xaaef, Gcb::SpacingMark),
+    (0xaaf5, 0xaaf5, Gcb::SpacingMark),
+    (0xaaf6, 0xaaf6, Gcb::Extend),
+    (0xabe3, 0xabe4, Gcb::SpacingMark),
+    (0xabe5, 0xabe5, Gcb::Extend),
+    (0xabe6, 0xabe7, Gcb::SpacingMark),
+    (0xabe8, 0xabe8, Gcb::Extend),
+    (0xabe9, 0xabea, Gcb::SpacingMark),
+    (0xabec, 0xabec, Gcb::SpacingMark),
+    (0xabed, 0xabed, Gcb::Extend),
+    (0xd7b0, 0xd7c6, Gcb::V),
+    (0xd7cb, 0xd7fb, Gcb::T),
+    (0xfb1e, 0xfb1e, Gcb::Extend),
+    (0xfe00, 0xfe0f, Gcb::Extend),
+    (0xfe20, 0xfe2f, Gcb::Extend),
+    (0xfeff, 0xfeff, Gcb::Control),
+    (0xff9e, 0xff9f, Gcb::Extend),
+    (0xfff9, 0xfffb, Gcb::Control),
+    (0x101fd, 0x101fd, Gcb::Extend),
+    (0x102e0, 0x102e0, Gcb::Extend),
+    (0x10376, 0x1037a, Gcb::Extend),
+    (0x10a01, 0x10a03, Gcb::Extend),
+    (0x10a05, 0x10a06, Gcb::Extend),
+    (0x10a0c, 0x10a0f, Gcb::Extend),
+    (0x10a38, 0x10a3a, Gcb::Extend),
+    (0x10a3f, 0x10a3f, Gcb::Extend),
+    (0x10ae5, 0x10ae6, Gcb::Extend),
+    (0x10d24, 0x10d27, Gcb::Extend),
+    (0x10eab, 0x10eac, Gcb::Extend),
+    (0x10f46, 0x10f50, Gcb::Extend),
+    (0x10f82, 0x10f85, Gcb::Extend),
+    (0x11000, 0x11000, Gcb::SpacingMark),
+    (0x11001, 0x11001, Gcb::Extend),
+    (0x11002, 0x11002, Gcb::SpacingMark),
+    (0x11038, 0x11046, Gcb::Extend),
+    (0x11070, 0x11070, Gcb::Extend),
+    (0x11073, 0x11074, Gcb::Extend),
+    (0x1107f, 0x11081, Gcb::Extend),
+    (0x11082, 0x11082, Gcb::SpacingMark),
+    (0x110b0, 0x110b2, Gcb::SpacingMark),
+    (0x110b3, 0x110b6, Gcb::Extend),
+    (0x110b7, 0x110b8, Gcb::SpacingMark),
+    (0x110b9, 0x110ba, Gcb::Extend),
+    (0x110bd, 0x110bd, Gcb::Prepend),
+    (0x110c2, 0x110c2, Gcb::Extend),
+    (0x110cd, 0x110cd, Gcb::Prepend),
+    (0x11100, 0x11102, Gcb::Extend),
+    (0x11127, 0x1112b, Gcb::Extend),
+    (0x1112c, 0x1112c, Gcb::SpacingMark),
+    (0x1112d, 0x11134, Gcb::Extend),
+    (0x11145, 0x11146, Gcb::SpacingMark),
+    (0x11173, 0x11173, Gcb::Extend),
+    (0x11180, 0x11181, Gcb::Extend),
+    (0x11182, 0x11182, Gcb::SpacingMark),
+    (0x111b3, 0x111b5, Gcb::SpacingMark),
+    (0x111b6, 0x111be, Gcb::Extend),
+    (0x111bf, 0x111c0, Gcb::SpacingMark),
+    (0x111c2, 0x111c3, Gcb::Prepend),
+    (0x111c9, 0x111cc, Gcb::Extend),
+    (0x111ce, 0x111ce, Gcb::SpacingMark),
+    (0x111cf, 0x111cf, Gcb::Extend),
+    (0x1122c, 0x1122e, Gcb::SpacingMark),
+    (0x1122f, 0x11231, Gcb::Extend),
+    (0x11232, 0x11233, Gcb::SpacingMark),
+    (0x11234, 0x11234, Gcb::Extend),
+    (0x11235, 0x11235, Gcb::SpacingMark),
+    (0x11236, 0x11237, Gcb::Extend),
+    (0x1123e, 0x1123e, Gcb::Extend),
+    (0x112df, 0x112df, Gcb::Extend),
+    (0x112e0, 0x112e2, Gcb::SpacingMark),
+    (0x112e3, 0x112ea, Gcb::Extend),
+    (0x11300, 0x11301, Gcb::Extend),
+    (0x11302, 0x11303, Gcb::SpacingMark),
+    (0x1133b, 0x1133c, Gcb::Extend),
+    (0x1133e, 0x1133e, Gcb::Extend),
+    (0x1133f, 0x1133f, Gcb::SpacingMark),
+    (0x11340, 0x11340, Gcb::Extend),
+    (0x11341, 0x11344, Gcb::SpacingMark),
+    (0x11347, 0x11348, Gcb::SpacingMark),
+    (0x1134b, 0x1134d, Gcb::SpacingMark),
+    (0x11357, 0x11357, Gcb::Extend),
+    (0x11362, 0x11363, Gcb::SpacingMark),
+    (0x11366, 0x1136c, Gcb::Extend),
+    (0x11370, 0x11374, Gcb::Extend),
+    (0x11435, 0x11437, Gcb::SpacingMark),
+    (0x11438, 0x1143f, Gcb::Extend),
+    (0x11440, 0x11441, Gcb::SpacingMark),
+    (0x11442, 0x11444, Gcb::Extend),
+    (0x11445, 0x11445, Gcb::SpacingMark),
+    (0x11446, 0x11446, Gcb::Extend),
+    (0x1145e, 0x1145e, Gcb::Extend),
+    (0x114b0, 0x114b0, Gcb::Extend),
+    (0x114b1, 0x114b2, Gcb::SpacingMark),
+    (0x114b3, 0x114b8, Gcb::Extend),
+    (0x114b9, 0x114b9, Gcb::SpacingMark),
+    (0x114ba, 0x114ba, Gcb::Extend),
+    (0x114bb, 0x114bc, Gcb::SpacingMark),
+    (0x114bd, 0x114bd, Gcb::Extend),
+    (0x114be, 0x114be, Gcb::SpacingMark),
+    (0x114bf, 0x114c0, Gcb::Extend),
+    (0x114c1, 0x114c1, Gcb::SpacingMark),
+    (0x114c2, 0x114c3, Gcb::Extend),
+    (0x115af, 0x115af, Gcb::Extend),
+    (0x115b0, 0x115b1, Gcb::SpacingMark),
+    (0x115b2, 0x115b5, Gcb::Extend),
+    (0x115b8, 0x115bb, Gcb::SpacingMark),
+    (0x115bc, 0x115bd, Gcb::Extend),
+    (0x115be, 0x115be, Gcb::SpacingMark),
+    (0x115bf, 0x115c0, Gcb::Extend),
+    (0x115dc, 0x115dd, Gcb::Extend),
+    (0x11630, 0x11632, Gcb::SpacingMark),
+    (0x11633, 0x1163a, Gcb::Extend),
+    (0x1163b, 0x1163c, Gcb::SpacingMark),
+    (0x1163d, 0x1163d, Gcb::Extend),
+    (0x1163e, 0x1163e, Gcb::SpacingMark),
+    (0x1163f, 0x11640, Gcb::Extend),
+    (0x116ab, 0x116ab, Gcb::Extend),
+    (0x116ac, 0x116ac, Gcb::SpacingMark),
+    (0x116ad, 0x116ad, Gcb::Extend),
+    (0x116ae, 0x116af, Gcb::SpacingMark),
+    (0x116b0, 0x116b5, Gcb::Extend),
+    (0x116b6, 0x116b6, Gcb::SpacingMark),
+    (0x116b7, 0x116b7, Gcb::Extend),
+    (0x1171d, 0x1171f, Gcb::Extend),
+    (0x11722, 0x11725, Gcb::Extend),
+    (0x11726, 0x11726, Gcb::SpacingMark),
+    (0x11727, 0x1172b, Gcb::Extend),
+    (0x1182c, 0x1182e, Gcb::SpacingMark),
+    (0x1182f, 0x11837, Gcb::Extend),
+    (0x11838, 0x11838, Gcb::SpacingMark),
+    (0x11839, 0x1183a, Gcb::Extend),
+    (0x11930, 0x11930, Gcb::Extend),
+    (0x11931, 0x11935, Gcb::SpacingMark),
+    (0x11937, 0x11938, Gcb::SpacingMark),
+    (0x1193b, 0x1193c, Gcb::Extend),
+    (0x1193d, 0x1193d, Gcb::SpacingMark),
+    (0x1193e, 0x1193e, Gcb::Extend),
+    (0x1193f, 0x1193f, Gcb::Prepend),
+    (0x11940, 0x11940, Gcb::SpacingMark),
+    (0x11941, 0x11941, Gcb::Prepend),
+    (0x11942, 0x11942, Gcb::SpacingMark),
+    (0x11943, 0x11943, Gcb::Extend),
+    (0x119d1, 0x119d3, Gcb::SpacingMark),
+    (0x119d4, 0x119d7, Gcb::Extend),
+    (0x119da, 0x119db, Gcb::Extend),
+    (0x119dc, 0x119df, Gcb::SpacingMark),
+    (0x119e0, 0x119e0, Gcb::Extend),
+    (0x119e4, 0x119e4, Gcb::SpacingMark),
+    (0x11a01, 0x11a0a, Gcb::Extend),
+    (0x11a33, 0x11a38, Gcb::Extend),
+    (0x11a39, 0x11a39, Gcb::SpacingMark),
+    (0x11a3a, 0x11a3a, Gcb::Prepend),
+    (0x11a3b, 0x11a3e, Gcb::Extend),
+    (0x11a47, 0x11a47, Gcb::Extend),
+    (0x11a51, 0x11a56, Gcb::Extend),
+    (0x11a57, 0x11a58, Gcb::SpacingMark),
+    (0x11a59, 0x11a5b, Gcb::Extend),
+    (0x11a84, 0x11a89, Gcb::Prepend),
+    (0x11a8a, 0x11a96, Gcb::Extend),
+    (0x11a97, 0x11a97, Gcb::SpacingMark),
+    (0x11a98, 0x11a99, Gcb::Extend),
+    (0x11c2f, 0x11c2f, Gcb::SpacingMark),
+    (0x11c30, 0x11c36, Gcb::Extend),
+    (0x11c38, 0x11c3d, Gcb::Extend),
+    (0x11c3e, 0x11c3e, Gcb::SpacingMark),
+    (0x11c3f, 0x11c3f, Gcb::Extend),
+    (0x11c92, 0x11ca7, Gcb::Extend),
+    (0x11ca9, 0x11ca9, Gcb::SpacingMark),
+    (0x11caa, 0x11cb0, Gcb::Extend),
+    (0x11cb1, 0x11cb1, Gcb::SpacingMark),
+    (0x11cb2, 0x11cb3, Gcb::Extend),
+    (0x11cb4, 0x11cb4, Gcb::SpacingMark),
+    (0x11cb5, 0x11cb6, Gcb::Extend),
+    (0x11d31, 0x11d36, Gcb::Extend),
+    (0x11d3a, 0x11d3a, Gcb::Extend),
+    (0x11d3c, 0x11d3d, Gcb::Extend),
+    (0x11d3f, 0x11d45, Gcb::Extend),
+    (0x11d46, 0x11d46, Gcb::Prepend),
+    (0x11d47, 0x11d47, Gcb::Extend),
+    (0x11d8a, 0x11d8e, Gcb::SpacingMark),
+    (0x11d90, 0x11d91, Gcb::Extend),
+    (0x11d93, 0x11d94, Gcb::SpacingMark),
+    (0x11d95, 0x11d95, Gcb::Extend),
+    (0x11d96, 0x11d96, Gcb::SpacingMark),
+    (0x11d97, 0x11d97, Gcb::Extend),
+    (0x11ef3, 0x11ef4, Gcb::Extend),
+    (0x11ef5, 0x11ef6, Gcb::SpacingMark),
+    (0x13430, 0x13438, Gcb::Control),
+    (0x16af0, 0x16af4, Gcb::Extend),
+    (0x16b30, 0x16b36, Gcb::Extend),
+    (0x16f4f, 0x16f4f, Gcb::Extend),
+    (0x16f51, 0x16f87, Gcb::SpacingMark),
+    (0x16f8f, 0x16f92, Gcb::Extend),
+    (0x16fe4, 0x16fe4, Gcb::Extend),
+    (0x16ff0, 0x16ff1, Gcb::SpacingMark),
+    (0x1bc9d, 0x1bc9e, Gcb::Extend),
+    (0x1bca0, 0x1bca3, Gcb::Control),
+    (0x1cf00, 0x1cf2d, Gcb::Extend),
+    (0x1cf30, 0x1cf46, Gcb::Extend),
+    (0x1d165, 0x1d165, Gcb::Extend),
+    (0x1d166, 0x1d166, Gcb::SpacingMark),
+    (0x1d167, 0x1d169, Gcb::Extend),
+    (0x1d16d, 0x1d16d, Gcb::SpacingMark),
+    (0x1d16e, 0x1d172, Gcb::Extend),
+    (0x1d173, 0x1d17a, Gcb::Control),
+    (0x1d17b, 0x1d182, Gcb::Extend),
+    (0x1d185, 0x1d18b, Gcb::Extend),
+    (0x1d1aa, 0x1d1ad, Gcb::Extend),
+    (0x1d242, 0x1d244, Gcb::Extend),
+    (0x1da00, 0x1da36, Gcb::Extend),
+    (0x1da3b, 0x1da6c, Gcb::Extend),
+    (0x1da75, 0x1da75, Gcb::Extend),
+    (0x1da84, 0x1da84, Gcb::Extend),
+    (0x1da9b, 0x1da9f, Gcb::Extend),
+    (0x1daa1, 0x1daaf, Gcb::Extend),
+    (0x1e000, 0x1e006, Gcb::Extend),
+    (0x1e008, 0x1e018, Gcb::Extend),
+    (0x1e01b, 0x1e021, Gcb::Extend),
+    (0x1e023, 0x1e024, Gcb::Extend),
+    (0x1e026, 0x1e02a, Gcb::Extend),
+    (0x1e130, 0x1e136, Gcb::Extend),
+    (0x1e2ae, 0x1e2ae, Gcb::Extend),
+    (0x1e2ec, 0x1e2ef, Gcb::Extend),
+    (0x1e8d0, 0x1e8d6, Gcb::Extend),
+    (0x1e944, 0x1e94a, Gcb::Extend),
+    (0x1f000, 0x1f0ff, Gcb::ExtendedPictographic),
+    (0x1f10d, 0x1f10f, Gcb::ExtendedPictographic),
+    (0x1f12f, 0x1f12f, Gcb::ExtendedPictographic),
+    (0x1f16c, 0x1f171, Gcb::ExtendedPictographic),
+    (0x1f17e, 0x1f17f, Gcb::ExtendedPictographic),
+    (0x1f18e, 0x1f18e, Gcb::ExtendedPictographic),
+    (0x1f191, 0x1f19a, Gcb::ExtendedPictographic),
+    (0x1f1ad, 0x1f1e5, Gcb::ExtendedPictographic),
+    (0x1f1e6, 0x1f1ff, Gcb::RegionalIndicator),
+    (0x1f201, 0x1f20f, Gcb::ExtendedPictographic),
+    (0x1f21a, 0x1f21a, Gcb::ExtendedPictographic),
+    (0x1f22f, 0x1f22f, Gcb::ExtendedPictographic),
+    (0x1f232, 0x1f23a, Gcb::ExtendedPictographic),
+    (0x1f23c, 0x1f23f, Gcb::ExtendedPictographic),
+    (0x1f249, 0x1f3fa, Gcb::ExtendedPictographic),
+    (0x1f3fb, 0x1f3ff, Gcb::Extend),
+    (0x1f400, 0x1f53d, Gcb::ExtendedPictographic),
+    (0x1f546, 0x1f64f, Gcb::ExtendedPictographic),
+    (0x1f680, 0x1f6ff, Gcb::ExtendedPictographic),
+    (0x1f774, 0x1f77f, Gcb::ExtendedPictographic),
+    (0x1f7d5, 0x1f7ff, Gcb::ExtendedPictographic),
+    (0x1f80c, 0x1f80f, Gcb::ExtendedPictographic),
+    (0x1f848, 0x1f84f, Gcb::ExtendedPictographic),
+    (0x1f85a, 0x1f85f, Gcb::ExtendedPictographic),
+    (0x1f888, 0x1f88f, Gcb::ExtendedPictographic),
+    (0x1f8ae, 0x1f8ff, Gcb::ExtendedPictographic),
+    (0x1f90c, 0x1f93a, Gcb::ExtendedPictographic),
+    (0x1f93c, 0x1f945, Gcb::ExtendedPictographic),
+    (0x1f947, 0x1faff, Gcb::ExtendedPictographic),
+    (0x1fc00, 0x1fffd, Gcb::ExtendedPictographic),
+    (0xe0001, 0xe0001, Gcb::Control),
+    (0xe0020, 0xe007f, Gcb::Extend),
+    (0xe0100, 0xe01ef, Gcb::Extend),
+];
@@ -0,0 +1,82 @@
+// Conversion between byte offsets and line/column positions, for pointing at source code in
+// diagnostics.
+
+use std::ops::Range;
+
+use super::{Rope, RopeError};
+
+impl Rope {
+    /// Build an index of the lines of this rope in `O(n)` time. Lines are terminated by `\n` or
+    /// `\r\n`.
+    pub fn line_index(&self) -> LineIndex {
+        let mut starts = vec![0];
+        let mut ends = vec![];
+        let mut offset = 0;
+        let mut last = 0;
+
+        for chunk in self.chunks() {
+            for (i, &byte) in chunk.as_bytes().iter().enumerate() {
+                if byte == b'\n' {
+                    let end = offset + i;
+                    ends.push(if last == b'\r' { end - 1 } else { end });
+                    starts.push(end + 1);
+                }
+                last = byte;
+            }
+            offset += chunk.len();
+        }
+        ends.push(offset);
+
+        LineIndex {
+            rope: self.clone(),
+            starts,
+            ends,
+        }
+    }
+
+    /// Return the (zero-based) `n`-th line of this rope without its terminator, sharing structure
+    /// with `self`. Takes `O(n)` time, build a `LineIndex` for repeated lookups.
+    pub fn line(&self, n: usize) -> Option<Rope> {
+        self.line_index().line(n)
+    }
+}
+
+/// Maps byte offsets of a rope to (line, column) positions and back.
+///
+/// Lines and columns are zero-based, columns are counted in chars. A rope ending with a line
+/// terminator ends with an empty line.
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+    rope: Rope,
+    // The byte offset at which each line starts.
+    starts: Vec<usize>,
+    // The byte offset at which each line ends, excluding its terminator.
+    ends: Vec<usize>,
+}
+
+impl LineIndex {
+    /// The number of lines.
+    pub fn len_lines(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// The (line, column) position of the char beginning at the given byte offset. The
+    /// terminator of a line belongs to that line. Takes `O(log(n))` time.
+    pub fn position(&self, byte_offset: usize) -> Result<(usize, usize), RopeError> {
+        let char_index = self.rope.byte_to_char(byte_offset)?;
+        let line = self.starts.partition_point(|&start| start <= byte_offset) - 1;
+        let column = char_index - self.rope.byte_to_char_unchecked(self.starts[line]);
+        Ok((line, column))
+    }
+
+    /// The range of bytes of the given line, excluding its terminator.
+    pub fn line_range(&self, line: usize) -> Option<Range<usize>> {
+        Some(*self.starts.get(line)?..self.ends[line])
+    }
+
+    /// The given line without its terminator, sharing structure with the indexed rope.
+    pub fn line(&self, line: usize) -> Option<Rope> {
+        let range = self.line_range(line)?;
+        Some(self.rope.byte_slice_unchecked(range.start, range.end))
+    }
+}
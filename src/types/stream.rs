@@ -0,0 +1,319 @@
+// Streams produce a sequence of values over time, as pan futures for the individual items.
+//
+// Every call to `next` returns a new (lazy) future for the next item, which resolves either to a
+// map `{"done": false, "value": v}` or to `{"done": true}` once the stream is exhausted. An item is
+// only taken from its source when the future that receives it settles, so a future that is
+// cancelled while pending (or before it was ever run) does not lose a value: it stays queued for
+// the next future. Items go to the futures in the order in which they settle.
+
+use std::cell::Cell;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use futures::future::LocalFutureObj;
+use gc::{Finalize, Gc, GcCell, Trace, unsafe_empty_trace};
+use gc_derive::{Trace, Finalize};
+
+use crate::types::{
+    channel::Channel,
+    futures::Future,
+    rope::Rope,
+};
+use crate::value::{Fun, Native, Value};
+
+/// A source of items for a `Stream`. Implement this to provide streams from rust.
+pub trait PanStream: Trace {
+    /// A future for the next item, resolving to `item(v)` or to `done()`. It should only take
+    /// the item from the underlying source once it settles.
+    fn next(&mut self) -> Future;
+
+    /// Stop producing items, because nobody is interested in them anymore. Afterwards, `next`
+    /// should return futures that resolve to `done()`. Does nothing by default.
+    fn cancel(&mut self) {}
+}
+
+/// A pan stream. Clones refer to the same stream.
+#[derive(Clone, Trace, Finalize)]
+pub struct Stream(Gc<GcCell<Box<dyn PanStream>>>);
+
+impl Stream {
+    pub fn new(stream: impl PanStream + 'static) -> Stream {
+        Stream(Gc::new(GcCell::new(Box::new(stream))))
+    }
+
+    /// A stream of the given values, in order.
+    pub fn from_array(values: Vec<Value>) -> Stream {
+        Stream::new(ArrayStream(Gc::new(GcCell::new(values.into()))))
+    }
+
+    /// A stream of the values received from a channel. It is done once the channel has been
+    /// closed and drained.
+    pub fn from_channel(chan: Channel) -> Stream {
+        Stream::new(ChannelStream { chan, cancelled: false })
+    }
+
+    /// A stream of the numbers `0, 1, 2, ...`. The future for an item resolves `millis`
+    /// milliseconds (as measured by the clock of the event loop) after it is run, to the number
+    /// of items that have been produced before.
+    pub fn interval(millis: i64) -> Stream {
+        Stream::new(IntervalStream { millis, ticks: Rc::new(Cell::new(0)), cancelled: false })
+    }
+
+    /// A stream of the items of this stream, passed through `f`. A value thrown by `f` rejects
+    /// the future for the item. Cancelling the stream cancels this stream.
+    pub fn map(&self, f: Value) -> Stream {
+        Stream::new(MapStream { source: self.clone(), f })
+    }
+
+    /// A stream of the first `n` items of this stream, which is cancelled once the `n`-th item
+    /// has been produced.
+    pub fn take(&self, n: usize) -> Stream {
+        Stream::new(TakeStream { source: self.clone(), n, taken: Rc::new(Cell::new(0)) })
+    }
+
+    /// A future for the next item, see `PanStream::next`. If the stream is already busy creating
+    /// a future or being cancelled (because a `PanStream` called back into pan code that uses the
+    /// same stream), the future rejects with an error of kind `"concurrent-modification"`.
+    pub fn next(&self) -> Future {
+        match self.0.try_borrow_mut() {
+            Ok(mut stream) => stream.next(),
+            Err(_) => Future::reject(concurrent_modification("get the next item of")),
+        }
+    }
+
+    /// Stop producing items, see `PanStream::cancel`. Throws an error of kind
+    /// `"concurrent-modification"` if the stream is already busy, see `next`.
+    pub fn cancel(&self) -> Result<(), Value> {
+        match self.0.try_borrow_mut() {
+            Ok(mut stream) => {
+                stream.cancel();
+                Ok(())
+            }
+            Err(_) => Err(concurrent_modification("cancel")),
+        }
+    }
+
+    /// Returns whether both values refer to the same stream.
+    pub fn ptr_eq(a: &Stream, b: &Stream) -> bool {
+        Gc::ptr_eq(&a.0, &b.0)
+    }
+
+    fn address(&self) -> usize {
+        &*self.0 as *const GcCell<Box<dyn PanStream>> as usize
+    }
+}
+
+fn concurrent_modification(operation: &str) -> Value {
+    Value::error("concurrent-modification", &format!(
+        "cannot {} a stream that is busy creating a future or being cancelled", operation,
+    ))
+}
+
+/// An item of a stream: the map `{"done": false, "value": v}`.
+pub fn item(v: Value) -> Value {
+    let mut map = BTreeMap::new();
+    map.insert(key("done"), Value::Bool(false));
+    map.insert(key("value"), v);
+    Value::map(map)
+}
+
+/// The end of a stream: the map `{"done": true}`.
+pub fn done() -> Value {
+    let mut map = BTreeMap::new();
+    map.insert(key("done"), Value::Bool(true));
+    Value::map(map)
+}
+
+// If the given value is an item of a stream (rather than its end), its value.
+fn item_value(item: &Value) -> Option<Value> {
+    match item {
+        Value::Map(map) => {
+            let map = map.borrow();
+            match map.get(&key("done")) {
+                Some(Value::Bool(false)) => map.get(&key("value")).cloned(),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn key(name: &str) -> Value {
+    Value::String(Rope::from_str(name))
+}
+
+// A future that computes its outcome when it is first polled.
+fn lazily(f: impl FnOnce() -> Result<Value, Value> + 'static) -> Future {
+    Future::from_rust(LocalFutureObj::new(Box::new(async move { f() })))
+}
+
+fn native(fun: impl Fn(&[Value]) -> Result<Value, Value> + 'static) -> Value {
+    Value::Fun(Fun::Native(Native::new("stream", fun)))
+}
+
+#[derive(Trace, Finalize)]
+struct ArrayStream(Gc<GcCell<VecDeque<Value>>>);
+
+impl PanStream for ArrayStream {
+    fn next(&mut self) -> Future {
+        let values = self.0.clone();
+        lazily(move || Ok(values.borrow_mut().pop_front().map_or_else(done, item)))
+    }
+
+    fn cancel(&mut self) {
+        self.0.borrow_mut().clear();
+    }
+}
+
+#[derive(Trace, Finalize)]
+struct ChannelStream {
+    chan: Channel,
+    #[unsafe_ignore_trace]
+    cancelled: bool,
+}
+
+impl PanStream for ChannelStream {
+    // Receiving futures that are cancelled are skipped by the channel, so they take no value.
+    fn next(&mut self) -> Future {
+        if self.cancelled {
+            return Future::resolve(done());
+        }
+        let received = Future::then(Future::recv(self.chan.clone()), native(|args| {
+            Ok(item(args[0].clone()))
+        }));
+        Future::map_err(received, native(|args| match &args[0] {
+            Value::Map(err) if err.borrow().get(&key("kind")) == Some(&key("closed")) => {
+                Ok(done())
+            }
+            err => Err(err.clone()),
+        }))
+    }
+
+    fn cancel(&mut self) {
+        self.cancelled = true;
+    }
+}
+
+struct IntervalStream {
+    millis: i64,
+    // Shared with the callbacks of pending futures, which count the ticks as they happen.
+    ticks: Rc<Cell<i64>>,
+    cancelled: bool,
+}
+
+impl Finalize for IntervalStream {}
+unsafe impl Trace for IntervalStream {
+    unsafe_empty_trace!();
+}
+
+impl PanStream for IntervalStream {
+    fn next(&mut self) -> Future {
+        if self.cancelled {
+            return Future::resolve(done());
+        }
+        let ticks = self.ticks.clone();
+        Future::then(Future::sleep(self.millis), native(move |_| {
+            let tick = ticks.get();
+            ticks.set(tick + 1);
+            Ok(item(Value::Int(tick)))
+        }))
+    }
+
+    fn cancel(&mut self) {
+        self.cancelled = true;
+    }
+}
+
+#[derive(Trace, Finalize)]
+struct MapStream {
+    source: Stream,
+    f: Value,
+}
+
+impl PanStream for MapStream {
+    fn next(&mut self) -> Future {
+        let f = self.f.clone();
+        Future::then(self.source.next(), native(move |args| match item_value(&args[0]) {
+            Some(v) => Ok(item(f.apply(&[v])?)),
+            None => Ok(args[0].clone()),
+        }))
+    }
+
+    fn cancel(&mut self) {
+        let _ = self.source.cancel();
+    }
+}
+
+#[derive(Trace, Finalize)]
+struct TakeStream {
+    source: Stream,
+    #[unsafe_ignore_trace]
+    n: usize,
+    // Shared with the callbacks of pending futures, which count the items as they arrive.
+    #[unsafe_ignore_trace]
+    taken: Rc<Cell<usize>>,
+}
+
+impl PanStream for TakeStream {
+    fn next(&mut self) -> Future {
+        if self.taken.get() >= self.n {
+            return Future::resolve(done());
+        }
+        let (source, n, taken) = (self.source.clone(), self.n, self.taken.clone());
+        Future::then(self.source.next(), native(move |args| {
+            if item_value(&args[0]).is_none() {
+                return Ok(args[0].clone());
+            }
+            // Items beyond the first `n` arrive if more futures were pending than needed.
+            if taken.get() >= n {
+                return Ok(done());
+            }
+            taken.set(taken.get() + 1);
+            if taken.get() == n {
+                source.cancel()?;
+            }
+            Ok(args[0].clone())
+        }))
+    }
+
+    fn cancel(&mut self) {
+        self.taken.set(self.n);
+        let _ = self.source.cancel();
+    }
+}
+
+impl fmt::Debug for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Stream")
+    }
+}
+
+// Streams are compared by identity.
+impl PartialEq for Stream {
+    fn eq(&self, other: &Stream) -> bool {
+        Stream::ptr_eq(self, other)
+    }
+}
+
+impl Eq for Stream {}
+
+impl PartialOrd for Stream {
+    fn partial_cmp(&self, other: &Stream) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Stream {
+    fn cmp(&self, other: &Stream) -> std::cmp::Ordering {
+        self.address().cmp(&other.address())
+    }
+}
+
+// Consistent with the comparison by identity.
+impl Hash for Stream {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.address().hash(state);
+    }
+}
@@ -0,0 +1,152 @@
+// Observing throws, for logging and metrics.
+//
+// A runtime can have a throw observer (see `Runtime::set_throw_observer`), a function that is
+// told about every throw of the code the runtime runs, including the throws that pan code
+// catches. Every throw gets an id, and produces a sequence of events with that id: a `Thrown`
+// event where the value is thrown, then a `Caught` event if a catch region of pan code catches
+// it, or an `Escaped` event if it unwinds out of the runtime entry point (`Runtime::call` and
+// friends). A throw that is neither caught nor escapes was swallowed by a native function.
+//
+// Throws of pan code are reported where they happen, with the name of the throwing function.
+// Natives do not report their throws, so a value thrown by a native is reported once it arrives
+// at the pan code that called it (or at the entry point, or at a native that called it through
+// its `HostCtx`), with the name of the native. Exit, out-of-memory, interrupted and
+// deadline-exceeded values thrown by the runtime itself are not reported, unless they come out of
+// a native function.
+//
+// The ir does not record source positions, so events carry no span.
+//
+// The observer can not affect the code it observes: it only gets to see the thrown values, and a
+// panic of the observer is swallowed. An observer that throws while it is being called is not
+// told about that throw. Whether there is an observer at all is kept in a thread-local, like the
+// memory budget (see `types::heap`), so that code runs with a single check when there is none.
+
+use std::cell::{Cell, RefCell};
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+
+use crate::value::{Fun, Value};
+
+/// Something that happened to a thrown value, see `Runtime::set_throw_observer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ThrowEventKind {
+    /// The value was thrown.
+    Thrown,
+    /// A catch region of pan code caught the value.
+    Caught,
+    /// The value unwound out of the runtime entry point.
+    Escaped,
+}
+
+/// What a throw observer is told, see `Runtime::set_throw_observer`.
+#[derive(Debug, Clone, Copy)]
+pub struct ThrowEvent<'a> {
+    /// Identifies the throw: all events of a throw have the same id, and every throw of a
+    /// runtime a different one.
+    pub id: u64,
+    pub kind: ThrowEventKind,
+    /// The thrown value.
+    pub value: &'a Value,
+    /// The name of the function that threw or caught the value, `None` for anonymous functions
+    /// and for events of the entry point.
+    pub function: Option<&'a str>,
+}
+
+/// A throw observer of a runtime, see `Runtime::set_throw_observer`.
+pub(crate) type Observer = Rc<RefCell<dyn FnMut(&ThrowEvent)>>;
+
+thread_local! {
+    static OBSERVER: RefCell<Option<Observer>> = const { RefCell::new(None) };
+    static OBSERVING: Cell<bool> = const { Cell::new(false) };
+    // The id of the throw that is currently unwinding, if any.
+    static IN_FLIGHT: Cell<Option<u64>> = const { Cell::new(None) };
+    static NEXT_ID: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Makes the observer of a runtime the current one of the thread until it is dropped, then
+/// restores the previous one.
+pub(crate) struct ObserverScope {
+    observer: Option<Observer>,
+    in_flight: Option<u64>,
+}
+
+impl ObserverScope {
+    pub(crate) fn enter(observer: Option<Observer>) -> ObserverScope {
+        OBSERVING.with(|observing| observing.set(observer.is_some()));
+        ObserverScope {
+            observer: OBSERVER.with(|current| current.replace(observer)),
+            in_flight: IN_FLIGHT.with(|in_flight| in_flight.replace(None)),
+        }
+    }
+}
+
+impl Drop for ObserverScope {
+    fn drop(&mut self) {
+        let previous = self.observer.take();
+        let in_flight = self.in_flight;
+        let _ = OBSERVING.try_with(|observing| observing.set(previous.is_some()));
+        let _ = OBSERVER.try_with(|current| current.replace(previous));
+        let _ = IN_FLIGHT.try_with(|current| current.set(in_flight));
+    }
+}
+
+/// Whether the current runtime has an observer.
+#[inline]
+pub(crate) fn observing() -> bool {
+    OBSERVING.with(Cell::get)
+}
+
+/// Pan code of the function of the given name throws the value.
+pub(crate) fn thrown(value: &Value, function: Option<&str>) {
+    let id = NEXT_ID.with(|next| next.replace(next.get() + 1));
+    IN_FLIGHT.with(|in_flight| in_flight.set(Some(id)));
+    notify(ThrowEvent { id, kind: ThrowEventKind::Thrown, value, function });
+}
+
+/// The call of `fun` threw the value. If no throw is unwinding, the value comes from a native,
+/// and is reported as thrown by it.
+pub(crate) fn arrived(value: &Value, fun: &Value) {
+    if IN_FLIGHT.with(Cell::get).is_none() {
+        thrown(value, name(fun));
+    }
+}
+
+/// Pan code of the function of the given name catches the value.
+pub(crate) fn caught(value: &Value, function: Option<&str>) {
+    if let Some(id) = IN_FLIGHT.with(|in_flight| in_flight.replace(None)) {
+        notify(ThrowEvent { id, kind: ThrowEventKind::Caught, value, function });
+    }
+}
+
+/// The value thrown by calling `fun` unwinds out of the runtime entry point.
+pub(crate) fn escaped(value: &Value, fun: &Value) {
+    arrived(value, fun);
+    if let Some(id) = IN_FLIGHT.with(|in_flight| in_flight.replace(None)) {
+        notify(ThrowEvent { id, kind: ThrowEventKind::Escaped, value, function: None });
+    }
+}
+
+/// A native returned normally, so any throw that is unwinding was swallowed by it.
+pub(crate) fn returned() {
+    IN_FLIGHT.with(|in_flight| in_flight.set(None));
+}
+
+/// The name of a function, if it has one.
+fn name(fun: &Value) -> Option<&str> {
+    match fun {
+        Value::Fun(Fun::Pan(closure)) => closure.name(),
+        Value::Fun(Fun::Native(native)) => Some(native.name()),
+        Value::Fun(Fun::NativeAsync(native)) => Some(native.name()),
+        _ => None,
+    }
+}
+
+// Call the current observer, unless it is already running.
+fn notify(event: ThrowEvent) {
+    let observer = OBSERVER.with(|current| current.borrow().clone());
+    if let Some(observer) = observer {
+        if let Ok(mut observer) = observer.try_borrow_mut() {
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| (*observer)(&event)));
+        }
+    }
+}
@@ -0,0 +1,306 @@
+// Managed userdata: values of host types that pan code can pass around, but only inspect through
+// builtins provided by the host.
+//
+// A userdata is a garbage-collected, shared and mutable box around a value of a type implementing
+// `PanUserdata`. Clones refer to the same box, and userdata compare by identity. The boxed value
+// is opaque to the garbage collector, so it must not hold any pan values itself.
+//
+// Pan code can call the methods a type declares with the `ud_call` builtin (see
+// `builtins::userdata`). Types can implement `PanUserdata::call_method` by hand, or dispatch
+// through a `MethodTable`.
+//
+// Types that hold resources (files, sockets) can release them in `PanUserdata::finalize`. It runs
+// exactly once per userdata: either when pan code closes the userdata explicitly with `ud_close`,
+// or when the garbage collector frees it (which includes the end of the thread). A closed
+// userdata throws an error of kind `"closed"` for all method calls. Finalizers run while the
+// collector frees memory, so they only get a `FinalizeCtx`, which offers no way to reach pan
+// values or call back into pan code.
+
+use std::any::Any;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use gc::{Finalize, Gc, GcCell, Trace, unsafe_empty_trace};
+use gc_derive::{Trace, Finalize};
+
+use crate::runtime::HostCtx;
+use crate::value::Value;
+
+/// A host type that can be stored in a pan value.
+pub trait PanUserdata: Any + fmt::Debug {
+    /// The name of the type, for error messages and printing.
+    fn type_name(&self) -> &'static str;
+
+    /// Call the method of the given name. The default implementation has no methods, it throws
+    /// the error of `unknown_method` for every name.
+    fn call_method(
+        &mut self,
+        name: &str,
+        _args: &[Value],
+        _ctx: &mut HostCtx,
+    ) -> Result<Value, Value> {
+        Err(unknown_method(self.type_name(), name))
+    }
+
+    /// The names of the methods, for introspection.
+    fn methods(&self) -> &[&'static str] {
+        &[]
+    }
+
+    /// Release the resources of the value. Called exactly once, when the userdata is closed or
+    /// freed, whichever happens first. The default implementation does nothing.
+    fn finalize(&mut self, _ctx: &mut FinalizeCtx) {}
+}
+
+/// What a finalizer learns about why it runs, see `PanUserdata::finalize`.
+#[derive(Debug)]
+pub struct FinalizeCtx {
+    explicit: bool,
+}
+
+impl FinalizeCtx {
+    /// Returns whether the userdata was closed explicitly (with `ud_close` or `Userdata::close`),
+    /// rather than freed by the garbage collector.
+    pub fn is_explicit(&self) -> bool {
+        self.explicit
+    }
+}
+
+/// The error to throw when calling a method that a userdata does not have: an error of kind
+/// `"method"` whose data is a map with the `type` and the `method` name.
+pub fn unknown_method(type_name: &str, method: &str) -> Value {
+    Value::error_with_data(
+        "method",
+        &format!("{} has no method {}", type_name, method),
+        Value::record(vec![("type", Value::from(type_name)), ("method", Value::from(method))]),
+    )
+}
+
+/// A method of a userdata of type `T`, see `MethodTable`.
+pub type Method<T> = fn(&mut T, &[Value], &mut HostCtx) -> Result<Value, Value>;
+
+/// The methods of a userdata type by name, to implement `PanUserdata::call_method` and
+/// `PanUserdata::methods` without matching on names by hand: build the table once (it only holds
+/// function pointers, so it can live in a `lazy_static`), and forward both trait methods to
+/// `MethodTable::call` and `MethodTable::names`.
+pub struct MethodTable<T> {
+    names: Vec<&'static str>,
+    methods: Vec<Method<T>>,
+}
+
+impl<T: PanUserdata> MethodTable<T> {
+    pub fn new() -> MethodTable<T> {
+        MethodTable { names: vec![], methods: vec![] }
+    }
+
+    /// Add a method, replacing any method of the same name.
+    pub fn add(mut self, name: &'static str, method: Method<T>) -> MethodTable<T> {
+        match self.names.iter().position(|n| *n == name) {
+            Some(i) => self.methods[i] = method,
+            None => {
+                self.names.push(name);
+                self.methods.push(method);
+            }
+        }
+        self
+    }
+
+    /// Call the method of the given name, or throw the error of `unknown_method`.
+    pub fn call(
+        &self,
+        this: &mut T,
+        name: &str,
+        args: &[Value],
+        ctx: &mut HostCtx,
+    ) -> Result<Value, Value> {
+        match self.names.iter().position(|n| *n == name) {
+            Some(i) => (self.methods[i])(this, args, ctx),
+            None => Err(unknown_method(this.type_name(), name)),
+        }
+    }
+
+    /// The names of the methods, in the order they were added.
+    pub fn names(&self) -> &[&'static str] {
+        &self.names
+    }
+}
+
+impl<T: PanUserdata> Default for MethodTable<T> {
+    fn default() -> MethodTable<T> {
+        MethodTable::new()
+    }
+}
+
+/// A pan value of a host type. Clones refer to the same value.
+#[derive(Clone, Trace, Finalize)]
+pub struct Userdata {
+    #[unsafe_ignore_trace]
+    type_name: &'static str,
+    data: Gc<GcCell<Slot>>,
+}
+
+// Holds the host value, which does not contain any `Gc` pointers.
+struct Slot {
+    data: Box<dyn PanUserdata>,
+    // Whether the value has been finalized.
+    closed: bool,
+}
+
+impl Slot {
+    fn close(&mut self, explicit: bool) {
+        if !self.closed {
+            self.closed = true;
+            self.data.finalize(&mut FinalizeCtx { explicit });
+        }
+    }
+}
+
+// The collector drops the slot when it frees the userdata, nothing can reach it anymore by then.
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.close(false);
+    }
+}
+
+impl Finalize for Slot {}
+unsafe impl Trace for Slot {
+    unsafe_empty_trace!();
+}
+
+impl Userdata {
+    /// Move a host value into a new userdata.
+    pub fn new<T: PanUserdata>(data: T) -> Userdata {
+        Userdata {
+            type_name: data.type_name(),
+            data: Gc::new(GcCell::new(Slot { data: Box::new(data), closed: false })),
+        }
+    }
+
+    /// The name of the type of the host value.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// Returns whether the host value is of type `T`.
+    pub fn is<T: PanUserdata>(&self) -> bool {
+        let slot = self.data.borrow();
+        let data: &dyn Any = &*slot.data;
+        data.is::<T>()
+    }
+
+    /// Call `f` with a reference to the host value if it is of type `T`, and return its result.
+    ///
+    /// Panics if the value is currently borrowed mutably by `with_mut`.
+    pub fn with<T: PanUserdata, R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        let slot = self.data.borrow();
+        let data: &dyn Any = &*slot.data;
+        data.downcast_ref().map(f)
+    }
+
+    /// Call `f` with a mutable reference to the host value if it is of type `T`, and return its
+    /// result.
+    ///
+    /// Panics if the value is currently borrowed by `with` or `with_mut`.
+    pub fn with_mut<T: PanUserdata, R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        let mut slot = self.data.borrow_mut();
+        let data: &mut dyn Any = &mut *slot.data;
+        data.downcast_mut().map(f)
+    }
+
+    /// Call a method of the host value, see `PanUserdata::call_method`. The host value is
+    /// borrowed mutably for the duration of the call, so a method that (indirectly) calls a method
+    /// of the same userdata throws an error of kind `"concurrent-modification"` instead, like an
+    /// iterator that is advanced while producing an element. Throws an error of kind `"closed"` if
+    /// the userdata has been closed.
+    pub fn call_method(
+        &self,
+        name: &str,
+        args: &[Value],
+        ctx: &mut HostCtx,
+    ) -> Result<Value, Value> {
+        match self.data.try_borrow_mut() {
+            Ok(slot) if slot.closed => Err(Value::error("closed", &format!(
+                "cannot call {} on a {} that has been closed", name, self.type_name,
+            ))),
+            Ok(mut slot) => slot.data.call_method(name, args, ctx),
+            Err(_) => Err(Value::error("concurrent-modification", &format!(
+                "cannot call {} on a {} that is already in use", name, self.type_name,
+            ))),
+        }
+    }
+
+    /// The names of the methods of the host value, see `PanUserdata::methods`.
+    ///
+    /// Panics if the value is currently borrowed mutably, e.g. by `call_method`.
+    pub fn methods(&self) -> Vec<&'static str> {
+        self.data.borrow().data.methods().to_vec()
+    }
+
+    /// Finalize the host value now rather than when it is freed, see `PanUserdata::finalize`.
+    /// Closing a closed userdata does nothing. Throws an error of kind
+    /// `"concurrent-modification"` if a method of the userdata is running.
+    pub fn close(&self) -> Result<(), Value> {
+        match self.data.try_borrow_mut() {
+            Ok(mut slot) => {
+                slot.close(true);
+                Ok(())
+            }
+            Err(_) => Err(Value::error("concurrent-modification", &format!(
+                "cannot close a {} that is in use", self.type_name,
+            ))),
+        }
+    }
+
+    /// Returns whether the userdata has been closed.
+    ///
+    /// Panics if the value is currently borrowed mutably.
+    pub fn is_closed(&self) -> bool {
+        self.data.borrow().closed
+    }
+
+    /// Returns whether both values refer to the same userdata.
+    pub fn ptr_eq(a: &Userdata, b: &Userdata) -> bool {
+        Gc::ptr_eq(&a.data, &b.data)
+    }
+
+    fn address(&self) -> usize {
+        &*self.data as *const GcCell<Slot> as usize
+    }
+}
+
+impl fmt::Debug for Userdata {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.data.try_borrow() {
+            Ok(slot) => write!(f, "Userdata({:?})", slot.data),
+            Err(_) => write!(f, "Userdata({}, borrowed)", self.type_name),
+        }
+    }
+}
+
+// Userdata are compared by identity.
+impl PartialEq for Userdata {
+    fn eq(&self, other: &Userdata) -> bool {
+        Userdata::ptr_eq(self, other)
+    }
+}
+
+impl Eq for Userdata {}
+
+impl PartialOrd for Userdata {
+    fn partial_cmp(&self, other: &Userdata) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Userdata {
+    fn cmp(&self, other: &Userdata) -> std::cmp::Ordering {
+        self.address().cmp(&other.address())
+    }
+}
+
+// Consistent with the comparison by identity.
+impl Hash for Userdata {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.address().hash(state);
+    }
+}
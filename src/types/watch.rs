@@ -0,0 +1,251 @@
+// Watchpoints, for finding out which code writes a binding or a storage slot.
+//
+// A watchpoint names a location as seen from the code of a pan function, identified by its stamp
+// (see `Fun::id`): either a binding of its environment, addressed like by the ir, or a slot of the
+// temporary storage of its executions. Watchpoints are added with `Runtime::watch_env` and
+// `Runtime::watch_slot`, and the runtime calls its watch hook (see `Runtime::set_watch_hook`)
+// before every write to a watched location, with the old and the new value. The hook decides
+// whether the code continues, continues without the watchpoint, or stops, in which case the write
+// does not happen and the code throws an interrupted value, as if it was interrupted through an
+// `InterruptHandle`. A panic of the hook is ignored, and so are writes by the hook itself.
+//
+// Every execution of the function watches its own locations, so nested calls of the function
+// (e.g. through recursion) each report the writes to their own bindings and slots. A watched
+// binding is also reported when other code writes it while an execution of the function is
+// running, e.g. a closure that the function created and called. Writes by `Write`, `Literal`,
+// the results of `Apply` and the values caught by catch regions are reported, the arguments that
+// an execution starts with are not.
+//
+// Whether there are watchpoints at all is kept in a thread-local, like the throw observer (see
+// `types::throw_observer`), so that executions check a single flag when there are none.
+
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+
+use crate::ir::DeBruijnPair;
+use crate::types::interrupt;
+use crate::value::Value;
+
+/// Identifies a watchpoint of a runtime, see `Runtime::watch_env`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct WatchId(u64);
+
+/// A watched location, see the module documentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Watchpoint {
+    /// A binding of the environment, as addressed by the code of the function of stamp `fun`.
+    Env { fun: u64, pair: DeBruijnPair },
+    /// A slot of the temporary storage of the function of stamp `fun`.
+    Slot { fun: u64, index: usize },
+}
+
+impl Watchpoint {
+    fn fun(&self) -> u64 {
+        match self {
+            Watchpoint::Env { fun, .. } | Watchpoint::Slot { fun, .. } => *fun,
+        }
+    }
+}
+
+/// What a watch hook is told, see `Runtime::set_watch_hook`.
+#[derive(Debug, Clone, Copy)]
+pub struct WatchHit<'a> {
+    pub watch: WatchId,
+    pub point: Watchpoint,
+    /// The value at the location before the write.
+    pub old: &'a Value,
+    /// The value that is about to be written.
+    pub new: &'a Value,
+    /// The stamp of the function whose code writes, which is not the watched function if a
+    /// closure writes a binding it shares with it.
+    pub fun: u64,
+    /// The offset of the writing instruction in the ir code of that function.
+    pub pc: usize,
+}
+
+/// How the code continues after a watch hit, see `Runtime::set_watch_hook`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WatchAction {
+    /// Write the value and continue.
+    Continue,
+    /// Write the value, remove the watchpoint and continue.
+    Unwatch,
+    /// Do not write the value, throw an interrupted value instead.
+    Stop,
+}
+
+/// The hook of a runtime, see `Runtime::set_watch_hook`.
+pub(crate) type Hook = Box<dyn FnMut(&WatchHit) -> WatchAction>;
+
+/// The watchpoints and the watch hook of a runtime.
+#[derive(Default)]
+pub(crate) struct Watches {
+    hook: RefCell<Option<Hook>>,
+    points: RefCell<BTreeMap<WatchId, Watchpoint>>,
+    next_id: Cell<u64>,
+}
+
+impl Watches {
+    pub(crate) fn set_hook(&self, hook: Option<Hook>) {
+        *self.hook.borrow_mut() = hook;
+    }
+
+    pub(crate) fn add(&self, point: Watchpoint) -> WatchId {
+        let id = WatchId(self.next_id.replace(self.next_id.get() + 1));
+        self.points.borrow_mut().insert(id, point);
+        update();
+        id
+    }
+
+    pub(crate) fn remove(&self, id: WatchId) -> bool {
+        let removed = self.points.borrow_mut().remove(&id).is_some();
+        update();
+        removed
+    }
+
+    fn active(&self) -> bool {
+        self.hook.borrow().is_some() && !self.points.borrow().is_empty()
+    }
+}
+
+// An environment location that a running execution watches.
+struct Active {
+    env: usize,
+    index: usize,
+    watch: WatchId,
+    point: Watchpoint,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Rc<Watches>>> = const { RefCell::new(None) };
+    static WATCHING: Cell<bool> = const { Cell::new(false) };
+    static ACTIVE: RefCell<Vec<Active>> = const { RefCell::new(vec![]) };
+}
+
+/// Makes the watches of a runtime the current ones of the thread until it is dropped, then
+/// restores the previous ones.
+pub(crate) struct WatchScope(Option<Rc<Watches>>);
+
+impl WatchScope {
+    pub(crate) fn enter(watches: &Rc<Watches>) -> WatchScope {
+        let previous = CURRENT.with(|current| current.replace(Some(watches.clone())));
+        update();
+        WatchScope(previous)
+    }
+}
+
+impl Drop for WatchScope {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        let _ = CURRENT.try_with(|current| current.replace(previous));
+        let _ = WATCHING.try_with(|watching| watching.set(current_active()));
+    }
+}
+
+// Recompute whether the current runtime has watchpoints, after they changed.
+fn update() {
+    WATCHING.with(|watching| watching.set(current_active()));
+}
+
+fn current_active() -> bool {
+    let active = CURRENT.try_with(|current| current.borrow().as_ref().is_some_and(|w| w.active()));
+    active.unwrap_or(false)
+}
+
+/// Whether the current runtime has watchpoints and a hook.
+#[inline]
+pub(crate) fn watching() -> bool {
+    WATCHING.with(Cell::get)
+}
+
+/// The watchpoints of the function of the given stamp.
+pub(crate) fn points_of(fun: u64) -> Vec<(WatchId, Watchpoint)> {
+    CURRENT.with(|current| match &*current.borrow() {
+        Some(watches) => watches
+            .points
+            .borrow()
+            .iter()
+            .filter(|(_, point)| point.fun() == fun)
+            .map(|(id, point)| (*id, *point))
+            .collect(),
+        None => vec![],
+    })
+}
+
+/// The environment locations an execution watches while it runs. Dropping it stops watching
+/// them.
+pub(crate) struct ActiveScope(usize);
+
+impl ActiveScope {
+    /// Watch the given bindings, by the address of their environment and their index.
+    pub(crate) fn enter(locations: Vec<(usize, usize, WatchId, Watchpoint)>) -> ActiveScope {
+        ACTIVE.with(|active| {
+            let mut active = active.borrow_mut();
+            let previous = active.len();
+            active.extend(locations.into_iter().map(|(env, index, watch, point)| {
+                Active { env, index, watch, point }
+            }));
+            ActiveScope(previous)
+        })
+    }
+}
+
+impl Drop for ActiveScope {
+    fn drop(&mut self) {
+        let previous = self.0;
+        let _ = ACTIVE.try_with(|active| active.borrow_mut().truncate(previous));
+    }
+}
+
+/// The watchpoints of the binding at `index` of the environment at the address `env`.
+pub(crate) fn env_watches(env: usize, index: usize) -> Vec<(WatchId, Watchpoint)> {
+    ACTIVE.with(|active| {
+        active
+            .borrow()
+            .iter()
+            .filter(|location| location.env == env && location.index == index)
+            .map(|location| (location.watch, location.point))
+            .collect()
+    })
+}
+
+/// Report a write to a watched location. Fails with an interrupted value if the hook stops the
+/// code, so that the write does not happen.
+pub(crate) fn hit(
+    watch: WatchId,
+    point: Watchpoint,
+    old: &Value,
+    new: &Value,
+    fun: u64,
+    pc: usize,
+) -> Result<(), Value> {
+    let watches = match CURRENT.with(|current| current.borrow().clone()) {
+        Some(watches) => watches,
+        None => return Ok(()),
+    };
+    // The watchpoint may have been removed since the execution started.
+    if !watches.points.borrow().contains_key(&watch) {
+        return Ok(());
+    }
+    let action = match watches.hook.try_borrow_mut() {
+        Ok(mut hook) => match &mut *hook {
+            Some(hook) => {
+                let hit = WatchHit { watch, point, old, new, fun, pc };
+                panic::catch_unwind(AssertUnwindSafe(|| hook(&hit)))
+                    .unwrap_or(WatchAction::Continue)
+            }
+            None => WatchAction::Continue,
+        },
+        Err(_) => WatchAction::Continue,
+    };
+    match action {
+        WatchAction::Continue => Ok(()),
+        WatchAction::Unwatch => {
+            watches.remove(watch);
+            Ok(())
+        }
+        WatchAction::Stop => Err(interrupt::interrupted_value()),
+    }
+}
@@ -2,19 +2,43 @@ use std::collections::{
     BTreeSet,
     BTreeMap,
 };
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
 
-use gc::{Gc, GcCell};
+use futures::future::LocalFutureObj;
+
+use gc::{Gc, GcCell, Trace, unsafe_empty_trace};
 use gc_derive::{Trace, Finalize};
 use ordered_float::OrderedFloat;
 
 use crate::types::{
     rope::Rope,
     bytes::Bytes,
+    collection::Collection,
+    futures::Future,
+    heap,
+    native_panic,
+    owner::{self, RuntimeId},
+    throw_observer,
+    channel::Channel,
+    stream::Stream,
+    iter::Iter,
+    persistent::{PersistentMap, PersistentSet},
+    userdata::Userdata,
 };
-use crate::ir::IrClosure;
+use crate::ir::{Environment, IrClosure};
+
+pub mod convert;
+pub mod num;
+pub mod ops;
 
 /// Runtime representation of an arbitrary pan value.
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Trace, Finalize)]
+///
+/// Values are compared structurally, see the `Ord` impl.
+#[derive(Debug, Clone, Trace, Finalize)]
 pub enum Value {
     Nil,
     Bool(bool),
@@ -23,32 +47,996 @@ pub enum Value {
     Char(char),
     String(Rope),
     Bytes(Bytes),
-    Array(Gc<GcCell<Vec<Value>>>),
-    Set(Gc<GcCell<BTreeSet<Value>>>),
-    Map(Gc<GcCell<BTreeMap<Value, Value>>>),
+    Array(Gc<Collection<Vec<Value>>>),
+    Set(Gc<Collection<PersistentSet<Value>>>),
+    Map(Gc<Collection<PersistentMap<Value, Value>>>),
     Fun(Fun),
+    Future(Future),
+    Channel(Channel),
+    Stream(Stream),
+    Iter(Iter),
+    Userdata(Userdata),
 }
-// TODO functions, futures, userdata (light and/or managed?)
 
 impl Value {
     pub fn nil() -> Value {
         Value::Nil
     }
 
+    pub fn array(values: Vec<Value>) -> Value {
+        Value::Array(Gc::new(Collection::new(values)))
+    }
+
+    pub fn set(members: BTreeSet<Value>) -> Value {
+        Value::Set(Gc::new(Collection::new(members.into_iter().collect())))
+    }
+
+    pub fn map(entries: BTreeMap<Value, Value>) -> Value {
+        Value::Map(Gc::new(Collection::new(entries.into_iter().collect())))
+    }
+
+    /// A future that resolves to `v` when run.
+    pub fn future_resolve(v: Value) -> Value {
+        Value::Future(Future::resolve(v))
+    }
+
+    /// A future that rejects with `v` when run.
+    pub fn future_reject(v: Value) -> Value {
+        Value::Future(Future::reject(v))
+    }
+
+    /// A map from the given field names (as strings) to values.
+    pub fn record(fields: Vec<(&str, Value)>) -> Value {
+        let map = fields
+            .into_iter()
+            .map(|(name, v)| (Value::String(Rope::from_str(name)), v))
+            .collect();
+        Value::map(map)
+    }
+
+    /// The value that builtins and the interpreter throw to signal an error: a map with a `kind`
+    /// and a human-readable `message`, both strings. Errors that carry further information put it
+    /// into a `data` field instead, see `Value::error_with_data`.
+    pub fn error(kind: &str, message: &str) -> Value {
+        Value::record(vec![
+            ("kind", Value::String(Rope::from_str(kind))),
+            ("message", Value::String(Rope::from_str(message))),
+        ])
+    }
+
+    /// An error like `Value::error`, with an additional `data` field.
+    pub fn error_with_data(kind: &str, message: &str, data: Value) -> Value {
+        Value::record(vec![
+            ("kind", Value::String(Rope::from_str(kind))),
+            ("message", Value::String(Rope::from_str(message))),
+            ("data", data),
+        ])
+    }
+
+    /// The `kind` of an error, or `None` if this value is not a map with a string `kind`.
+    pub fn error_kind(&self) -> Option<Rope> {
+        self.error_string("kind")
+    }
+
+    /// The `message` of an error, or `None` if this value is not a map with a string `message`.
+    pub fn error_message(&self) -> Option<Rope> {
+        self.error_string("message")
+    }
+
+    fn error_string(&self, field: &str) -> Option<Rope> {
+        match self {
+            Value::Map(map) => match map.borrow().get(&Value::String(Rope::from_str(field))) {
+                Some(Value::String(s)) => Some(s.clone()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// A copy of this value that shares no arrays, sets or maps with it. A collection that occurs
+    /// several times (possibly inside itself) is copied once, so the copy has the same shape as
+    /// the original. None of the copied collections are frozen. Other values are shared, strings
+    /// and bytes because they are immutable, functions, futures, channels, streams and userdata
+    /// because they have an identity.
+    ///
+    /// The entries of sets and maps that contain no collections or other values with an identity
+    /// are shared with the copy (see `types::persistent`), so copying such a set or map takes
+    /// constant time.
+    pub fn deep_copy(&self) -> Value {
+        self.deep_copy_with(&mut Copies::default())
+    }
+
+    /// Like `deep_copy`, but frozen collections stay frozen, sealed collections are shared rather
+    /// than copied, and pan closures are copied together with their environments (see
+    /// `Runtime::snapshot`). Copying several values with
+    /// the same `copies` preserves what they share.
+    pub(crate) fn snapshot_copy(&self, copies: &mut Copies) -> Value {
+        copies.snapshot = true;
+        self.deep_copy_with(copies)
+    }
+
+    pub(crate) fn deep_copy_with(&self, copies: &mut Copies) -> Value {
+        let address = match self {
+            Value::Array(arr) => &**arr as *const _ as usize,
+            Value::Set(set) => &**set as *const _ as usize,
+            Value::Map(map) => &**map as *const _ as usize,
+            Value::Fun(Fun::Pan(closure)) if copies.snapshot => {
+                return Value::Fun(Fun::Pan(closure.copy_with(copies)));
+            }
+            _ => return self.clone(),
+        };
+        // Sealed collections can never change, so a snapshot can keep them. This covers the arrays
+        // and nested collections that `types::persistent` can not share.
+        if copies.snapshot && self.is_sealed() {
+            return self.clone();
+        }
+        if let Some(copy) = copies.collections.get(&address) {
+            return copy.clone();
+        }
+
+        // The copy is registered before its contents are copied, so that cycles end at it. Its
+        // contents are only assigned at the end, as copying them may compare against it.
+        let copy = match self {
+            Value::Array(arr) => {
+                let copy = Gc::new(Collection::new(vec![]));
+                copies.collections.insert(address, Value::Array(copy.clone()));
+                let elements = arr.borrow().iter().map(|v| v.deep_copy_with(copies)).collect();
+                *copy.borrow_mut() = elements;
+                Value::Array(copy)
+            }
+            Value::Set(set) => {
+                let copy = Gc::new(Collection::new(PersistentSet::new()));
+                copies.collections.insert(address, Value::Set(copy.clone()));
+                let members = set.borrow().copy_with(|v| v.deep_copy_with(copies));
+                *copy.borrow_mut() = members;
+                Value::Set(copy)
+            }
+            Value::Map(map) => {
+                let copy = Gc::new(Collection::new(PersistentMap::new()));
+                copies.collections.insert(address, Value::Map(copy.clone()));
+                let entries = map
+                    .borrow()
+                    .copy_with(|k, v| (k.deep_copy_with(copies), v.deep_copy_with(copies)));
+                *copy.borrow_mut() = entries;
+                Value::Map(copy)
+            }
+            _ => unreachable!("only collections are copied"),
+        };
+        if copies.snapshot && self.is_frozen() {
+            copy.freeze();
+        }
+        copy
+    }
+
+    /// An unfrozen copy of this value if it is an array, set or map, containing the same values.
+    /// Other values are returned as they are. This is how a frozen collection is thawed; its
+    /// contents stay frozen if they were. Copying a set or map whose entries are shared with
+    /// their copies (see `types::persistent`) takes constant time.
+    pub fn thaw(&self) -> Value {
+        match self {
+            Value::Array(arr) => Value::array(arr.borrow().clone()),
+            Value::Set(set) => Value::Set(Gc::new(Collection::new(set.borrow().clone()))),
+            Value::Map(map) => Value::Map(Gc::new(Collection::new(map.borrow().clone()))),
+            _ => self.clone(),
+        }
+    }
+
+    /// Freeze this value if it is an array, set or map, so that builtins refuse to mutate it. Its
+    /// contents are not frozen. Freezing a frozen collection or any other value does nothing.
+    pub fn freeze(&self) {
+        match self {
+            Value::Array(arr) => arr.freeze(),
+            Value::Set(set) => set.freeze(),
+            Value::Map(map) => map.freeze(),
+            _ => {}
+        }
+    }
+
+    /// Freeze this value and all arrays, sets and maps it (transitively) contains.
+    ///
+    /// Collections whose contents end up immutable (no functions, futures, channels, streams,
+    /// iterators or userdata, and no cycles) are also sealed, see `Collection::is_sealed`:
+    /// snapshots share sealed collections rather than copying them, and deep freezing them again
+    /// returns immediately.
+    pub fn deep_freeze(&self) {
+        self.deep_freeze_with(&mut BTreeSet::new());
+    }
+
+    // `visited` holds the addresses of the collections frozen so far. Returns whether the value
+    // is immutable afterwards: collections on a cycle are conservatively considered mutable, as
+    // the collection that closes the cycle has not been sealed yet.
+    fn deep_freeze_with(&self, visited: &mut BTreeSet<usize>) -> bool {
+        let (address, sealed) = match self {
+            Value::Array(arr) => (&**arr as *const _ as usize, arr.is_sealed()),
+            Value::Set(set) => (&**set as *const _ as usize, set.is_sealed()),
+            Value::Map(map) => (&**map as *const _ as usize, map.is_sealed()),
+            _ => return self.is_frozen(),
+        };
+        if sealed || !visited.insert(address) {
+            return sealed;
+        }
+
+        self.freeze();
+        // Every element is frozen, even after a mutable one has been found.
+        let mut immutable = true;
+        match self {
+            Value::Array(arr) => {
+                for v in arr.borrow().iter() {
+                    immutable &= v.deep_freeze_with(visited);
+                }
+            }
+            Value::Set(set) => {
+                for v in set.borrow().iter() {
+                    immutable &= v.deep_freeze_with(visited);
+                }
+            }
+            Value::Map(map) => {
+                for (k, v) in map.borrow().iter() {
+                    immutable &= k.deep_freeze_with(visited);
+                    immutable &= v.deep_freeze_with(visited);
+                }
+            }
+            _ => unreachable!("only collections are frozen"),
+        }
+        if immutable {
+            match self {
+                Value::Array(arr) => arr.seal(),
+                Value::Set(set) => set.seal(),
+                Value::Map(map) => map.seal(),
+                _ => {}
+            }
+        }
+        immutable
+    }
+
+    /// The runtime this value belongs to, `owner::NONE` if it does not belong to any, see
+    /// `types::owner`. Only the value itself is considered, not its contents.
+    pub fn owner(&self) -> RuntimeId {
+        match self {
+            Value::Array(arr) => arr.owner(),
+            Value::Set(set) => set.owner(),
+            Value::Map(map) => map.owner(),
+            Value::Fun(Fun::Pan(closure)) => closure.owner(),
+            Value::Fun(Fun::Native(native)) => native.owner,
+            Value::Fun(Fun::NativeAsync(native)) => native.owner,
+            _ => owner::NONE,
+        }
+    }
+
+    /// Whether this value is an array, set or map that has been sealed by `deep_freeze`, see
+    /// `Collection::is_sealed`.
+    pub fn is_sealed(&self) -> bool {
+        match self {
+            Value::Array(arr) => arr.is_sealed(),
+            Value::Set(set) => set.is_sealed(),
+            Value::Map(map) => map.is_sealed(),
+            _ => false,
+        }
+    }
+
+    /// Whether this value can not be mutated through builtins: true for frozen arrays, sets and
+    /// maps, and for the immutable types `nil`, `bool`, `int`, `float`, `char`, `string` and
+    /// `bytes`. False for everything else, including functions, whose environment can change.
+    pub fn is_frozen(&self) -> bool {
+        match self {
+            Value::Array(arr) => arr.is_frozen(),
+            Value::Set(set) => set.is_frozen(),
+            Value::Map(map) => map.is_frozen(),
+            Value::Nil
+            | Value::Bool(_)
+            | Value::Int(_)
+            | Value::Float(_)
+            | Value::Char(_)
+            | Value::String(_)
+            | Value::Bytes(_) => true,
+            Value::Fun(_)
+            | Value::Future(_)
+            | Value::Channel(_)
+            | Value::Stream(_)
+            | Value::Iter(_)
+            | Value::Userdata(_) => false,
+        }
+    }
+
+    /// The name of the type of this value, as used in the README and in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::Char(_) => "char",
+            Value::String(_) => "string",
+            Value::Bytes(_) => "bytes",
+            Value::Array(_) => "array",
+            Value::Set(_) => "set",
+            Value::Map(_) => "map",
+            Value::Fun(_) => "function",
+            Value::Future(_) => "future",
+            Value::Channel(_) => "channel",
+            Value::Stream(_) => "stream",
+            Value::Iter(_) => "iterator",
+            Value::Userdata(_) => "userdata",
+        }
+    }
+
     pub fn truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    // Apply this value to the given args. Applying anything but a function throws an error of
+    // kind `"type"`. A panic of a rust function throws an error of kind `"native-panic"`, see
+    // `types::native_panic`. If the current memory budget is exceeded afterwards, throws an
+    // out-of-memory value instead of the result, see `types::heap`. A native that returns
+    // normally swallowed any throw that was unwinding, see `types::throw_observer`.
+    pub fn apply(&self, args: &[Value]) -> Result<Value, Value> {
+        let result = match self {
+            Value::Fun(Fun::Pan(closure)) => closure.run(args),
+            Value::Fun(Fun::Native(native)) => {
+                let result = native_panic::call(native.name, || (native.fun.fun)(args));
+                if result.is_ok() && throw_observer::observing() {
+                    throw_observer::returned();
+                }
+                result
+            }
+            Value::Fun(Fun::NativeAsync(native)) => native_panic::call(native.name, || {
+                Ok(Value::Future(Future::from_rust((native.fun.fun)(args))))
+            }),
+            other => Err(Value::error("type", &format!(
+                "cannot call a value of type {}", other.type_name(),
+            ))),
+        };
+        match heap::out_of_memory() {
+            Some(oom) => Err(oom),
+            None => result,
+        }
+    }
+
+    /// Apply this function to the `positional` arguments followed by the `named` ones, a map from
+    /// parameter names (strings) to values. The named arguments are put into the positions of
+    /// their parameters, and parameters that get no argument are `nil`. Only functions that
+    /// declare the names of their parameters take named arguments (see `Native::with_params`
+    /// and `NativeAsync::with_params`), but any function can be called with an empty map.
+    ///
+    /// Throws an error of kind `"type"` if `named` is not a map of strings, and of kind
+    /// `"argument"` if the function does not declare parameter names, does not have a parameter
+    /// of a given name, or gets an argument for a parameter both positionally and by name.
+    pub fn apply_named(&self, positional: &[Value], named: &Value) -> Result<Value, Value> {
+        let named = match named {
+            Value::Map(map) => map.borrow(),
+            other => return Err(Value::error("type", &format!(
+                "named arguments must be a map, got {}", other.type_name(),
+            ))),
+        };
+        if named.is_empty() {
+            return self.apply(positional);
+        }
+
+        let function = match self {
+            Value::Fun(Fun::Pan(closure)) => closure.name().unwrap_or("the function").to_string(),
+            Value::Fun(Fun::Native(native)) => native.name().to_string(),
+            Value::Fun(Fun::NativeAsync(native)) => native.name().to_string(),
+            other => return Err(Value::error("type", &format!(
+                "cannot call a value of type {}", other.type_name(),
+            ))),
+        };
+        let position = |name: &str| match self {
+            Value::Fun(Fun::Pan(closure)) => closure.params().map(|params| {
+                params.iter().position(|param| &**param == name)
+            }),
+            Value::Fun(Fun::Native(native)) => native.params().map(|params| {
+                params.iter().position(|param| *param == name)
+            }),
+            Value::Fun(Fun::NativeAsync(native)) => native.params().map(|params| {
+                params.iter().position(|param| *param == name)
+            }),
+            _ => None,
+        };
+
+        let mut args = positional.to_vec();
+        for (name, v) in named.iter() {
+            let name = match name {
+                Value::String(name) => name.to_string(),
+                other => return Err(Value::error("type", &format!(
+                    "the names of named arguments must be strings, got {}", other.type_name(),
+                ))),
+            };
+            let index = match position(&name) {
+                None => return Err(Value::error("argument", &format!(
+                    "{} does not take named arguments", function,
+                ))),
+                Some(None) => return Err(Value::error("argument", &format!(
+                    "{} has no parameter named {}", function, name,
+                ))),
+                Some(Some(index)) if index < positional.len() => {
+                    return Err(Value::error("argument", &format!(
+                        "the argument {} of {} is given both positionally and by name",
+                        name, function,
+                    )));
+                }
+                Some(Some(index)) => index,
+            };
+            if args.len() <= index {
+                args.resize(index + 1, Value::Nil);
+            }
+            args[index] = v.clone();
+        }
+        drop(named);
+        self.apply(&args)
+    }
+}
+
+// Prints values in literal syntax. Strings and chars are escaped, long byte strings are
+// abbreviated (see the `Debug` impl of `Bytes`). A collection that contains itself is printed as
+// `...` where it recurs.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_literal(f, &mut vec![], usize::MAX)
+    }
+}
+
+/// The copies made so far by a deep copy, by the addresses of the originals.
+#[derive(Default)]
+pub(crate) struct Copies {
+    pub(crate) collections: BTreeMap<usize, Value>,
+    pub(crate) environments: BTreeMap<usize, Gc<GcCell<Environment>>>,
+    // Whether this is a `snapshot_copy`.
+    snapshot: bool,
+}
+
+/// Prints a value like its `Display` impl, except that non-empty collections nested deeper than
+/// a maximum depth are printed as `...`, see `Value::literal`.
+#[derive(Debug, Clone, Copy)]
+pub struct Literal<'a> {
+    value: &'a Value,
+    max_depth: usize,
+}
+
+impl fmt::Display for Literal<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.value.fmt_literal(f, &mut vec![], self.max_depth)
+    }
+}
+
+impl Value {
+    /// Print this value in literal syntax, but only the contents of the outermost `max_depth`
+    /// levels of collections. With a `max_depth` of zero, a non-empty array prints as `[...]`.
+    pub fn literal(&self, max_depth: usize) -> Literal<'_> {
+        Literal { value: self, max_depth }
+    }
+
+    // `enclosing` holds the addresses of the collections currently being printed, `depth` the
+    // number of collection levels whose contents may still be printed.
+    fn fmt_literal(
+        &self,
+        f: &mut fmt::Formatter,
+        enclosing: &mut Vec<usize>,
+        depth: usize,
+    ) -> fmt::Result {
+        match self {
+            Value::Nil => f.write_str("nil"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Float(x) => f.write_str(&num::format_float(x.into_inner())),
+            Value::Char(c) => write!(f, "'{}'", c.escape_debug()),
+            Value::String(s) => write!(f, "{:?}", s),
+            Value::Bytes(b) => write!(f, "{:?}", b),
+            Value::Array(arr) => {
+                let address = &**arr as *const _ as usize;
+                if enclosing.contains(&address) || (depth == 0 && !arr.borrow().is_empty()) {
+                    return f.write_str("[...]");
+                }
+                enclosing.push(address);
+                f.write_str("[")?;
+                for (i, v) in arr.borrow().iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    v.fmt_literal(f, enclosing, depth - 1)?;
+                }
+                enclosing.pop();
+                f.write_str("]")
+            }
+            Value::Set(set) => {
+                let address = &**set as *const _ as usize;
+                if enclosing.contains(&address) || (depth == 0 && !set.borrow().is_empty()) {
+                    return f.write_str("@{...}");
+                }
+                enclosing.push(address);
+                f.write_str("@{")?;
+                for (i, v) in set.borrow().iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    v.fmt_literal(f, enclosing, depth - 1)?;
+                }
+                enclosing.pop();
+                f.write_str("}")
+            }
+            Value::Map(map) => {
+                let address = &**map as *const _ as usize;
+                if enclosing.contains(&address) || (depth == 0 && !map.borrow().is_empty()) {
+                    return f.write_str("{...}");
+                }
+                enclosing.push(address);
+                f.write_str("{")?;
+                for (i, (k, v)) in map.borrow().iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    k.fmt_literal(f, enclosing, depth - 1)?;
+                    f.write_str(": ")?;
+                    v.fmt_literal(f, enclosing, depth - 1)?;
+                }
+                enclosing.pop();
+                f.write_str("}")
+            }
+            Value::Fun(fun) => match fun.name() {
+                Some(name) => write!(f, "<function #{} {}>", fun.id(), name),
+                None => write!(f, "<function #{}>", fun.id()),
+            },
+            Value::Future(_) => f.write_str("<future>"),
+            Value::Channel(_) => f.write_str("<channel>"),
+            Value::Stream(_) => f.write_str("<stream>"),
+            Value::Iter(_) => f.write_str("<iterator>"),
+            Value::Userdata(u) => write!(f, "<userdata {}>", u.type_name()),
+        }
+    }
+}
+
+thread_local! {
+    // The pairs of collections (by address) that are currently being compared.
+    static COMPARING: RefCell<Vec<(usize, usize)>> = const { RefCell::new(Vec::new()) };
+}
+
+// Removes the innermost pair from `COMPARING` when dropped, even if the comparison panics.
+struct Comparing;
+
+impl Drop for Comparing {
+    fn drop(&mut self) {
+        COMPARING.with(|comparing| comparing.borrow_mut().pop());
+    }
+}
+
+// Compare two collections with `cmp`, unless they are the same collection or already being
+// compared further up the stack. In both cases they are considered equal.
+fn cmp_collections<T: Trace>(
+    a: &Gc<Collection<T>>,
+    b: &Gc<Collection<T>>,
+    cmp: impl FnOnce(&T, &T) -> Ordering,
+) -> Ordering {
+    let pair = (&**a as *const Collection<T> as usize, &**b as *const Collection<T> as usize);
+    if pair.0 == pair.1 || COMPARING.with(|comparing| comparing.borrow().contains(&pair)) {
+        return Ordering::Equal;
+    }
+
+    COMPARING.with(|comparing| comparing.borrow_mut().push(pair));
+    let _comparing = Comparing;
+    cmp(&a.borrow(), &b.borrow())
+}
+
+impl Value {
+    // The position of the variant in the order of types.
+    fn type_rank(&self) -> u8 {
         match self {
-            Value::Nil | Value::Bool(false) => false,
-            _ => true,
+            Value::Nil => 0,
+            Value::Bool(_) => 1,
+            Value::Int(_) => 2,
+            Value::Float(_) => 3,
+            Value::Char(_) => 4,
+            Value::String(_) => 5,
+            Value::Bytes(_) => 6,
+            Value::Array(_) => 7,
+            Value::Set(_) => 8,
+            Value::Map(_) => 9,
+            Value::Fun(_) => 10,
+            Value::Future(_) => 11,
+            Value::Channel(_) => 12,
+            Value::Stream(_) => 13,
+            Value::Iter(_) => 14,
+            Value::Userdata(_) => 15,
         }
     }
+}
 
-    // Apply this value to the given args.
-    pub fn apply(&self, arg: &[Value]) -> Result<Value, Value> {
-        unimplemented!()
+/// Values of different types are ordered by type, in the order of the variants. Values of the
+/// same type are compared by content: numbers and chars by magnitude (NaN is greater than all
+/// other floats and equal to itself), strings, bytes and collections lexicographically. Functions,
+/// futures, channels, streams, iterators and userdata are compared by identity.
+///
+/// Collections can contain themselves. Comparing two collections that are already being compared
+/// further up the stack yields equality instead of recursing forever, so e.g. an array that
+/// contains itself is equal to an array that contains the array.
+impl Ord for Value {
+    fn cmp(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => Ordering::Equal,
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            (Value::Int(a), Value::Int(b)) => a.cmp(b),
+            (Value::Float(a), Value::Float(b)) => a.cmp(b),
+            (Value::Char(a), Value::Char(b)) => a.cmp(b),
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            (Value::Bytes(a), Value::Bytes(b)) => a.cmp(b),
+            (Value::Array(a), Value::Array(b)) => cmp_collections(a, b, |a, b| a.cmp(b)),
+            (Value::Set(a), Value::Set(b)) => cmp_collections(a, b, |a, b| a.cmp(b)),
+            (Value::Map(a), Value::Map(b)) => cmp_collections(a, b, |a, b| a.cmp(b)),
+            (Value::Fun(a), Value::Fun(b)) => a.cmp(b),
+            (Value::Future(a), Value::Future(b)) => a.cmp(b),
+            (Value::Channel(a), Value::Channel(b)) => a.cmp(b),
+            (Value::Stream(a), Value::Stream(b)) => a.cmp(b),
+            (Value::Iter(a), Value::Iter(b)) => a.cmp(b),
+            (Value::Userdata(a), Value::Userdata(b)) => a.cmp(b),
+            _ => self.type_rank().cmp(&other.type_rank()),
+        }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Trace, Finalize)]
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Value) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Value {}
+
+/// How many collections contribute their contents to the hash of a value, see `Hash for Value`.
+pub const HASH_BUDGET: usize = 256;
+
+/// Consistent with equality: equal values have equal hashes. Functions, futures, channels,
+/// streams and userdata are hashed by identity, all other values by content.
+///
+/// Only the contents of the first `HASH_BUDGET` collections encountered in a depth-first
+/// traversal are hashed, later collections only contribute their length. This bounds the work
+/// for deeply nested and cyclic values. Two equal values unfold into the same (possibly infinite)
+/// tree, so the traversal sees the same sequence of values for both.
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut budget = HASH_BUDGET;
+        self.hash_bounded(state, &mut budget);
+    }
+}
+
+impl Value {
+    // `budget` is the number of collections whose contents may still be hashed.
+    fn hash_bounded<H: Hasher>(&self, state: &mut H, budget: &mut usize) {
+        state.write_u8(self.type_rank());
+        match self {
+            Value::Nil => {}
+            Value::Bool(b) => b.hash(state),
+            Value::Int(n) => n.hash(state),
+            Value::Float(x) => x.hash(state),
+            Value::Char(c) => c.hash(state),
+            Value::String(s) => s.hash(state),
+            Value::Bytes(b) => b.hash(state),
+            Value::Array(arr) => {
+                let arr = arr.borrow();
+                state.write_usize(arr.len());
+                if take_budget(budget) {
+                    for v in arr.iter() {
+                        v.hash_bounded(state, budget);
+                    }
+                }
+            }
+            Value::Set(set) => {
+                let set = set.borrow();
+                state.write_usize(set.len());
+                if take_budget(budget) {
+                    for v in set.iter() {
+                        v.hash_bounded(state, budget);
+                    }
+                }
+            }
+            Value::Map(map) => {
+                let map = map.borrow();
+                state.write_usize(map.len());
+                if take_budget(budget) {
+                    for (k, v) in map.iter() {
+                        k.hash_bounded(state, budget);
+                        v.hash_bounded(state, budget);
+                    }
+                }
+            }
+            Value::Fun(fun) => fun.hash(state),
+            Value::Future(fut) => fut.hash(state),
+            Value::Channel(channel) => channel.hash(state),
+            Value::Stream(stream) => stream.hash(state),
+            Value::Iter(iter) => iter.hash(state),
+            Value::Userdata(userdata) => userdata.hash(state),
+        }
+    }
+}
+
+fn take_budget(budget: &mut usize) -> bool {
+    if *budget == 0 {
+        false
+    } else {
+        *budget -= 1;
+        true
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Trace, Finalize)]
 pub enum Fun {
     Pan(IrClosure),
+    Native(Native),
+    NativeAsync(NativeAsync),
+}
+
+impl Fun {
+    /// The stamp of the function: a number that the runtime which created the function assigned
+    /// to it, counting up from 1, see `types::owner`. Functions of a kind are ordered by their
+    /// stamps, so within a run the order follows the order of creation. Stamps are for debugging
+    /// and ordering only: they are not unique across runtimes, and the same function may get a
+    /// different stamp in another run.
+    pub fn id(&self) -> u64 {
+        match self {
+            Fun::Pan(closure) => closure.id(),
+            Fun::Native(native) => native.id(),
+            Fun::NativeAsync(native) => native.id(),
+        }
+    }
+
+    /// The name of the function, if it has one, see `fun_name`.
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            Fun::Pan(closure) => closure.name(),
+            Fun::Native(native) => Some(native.name()),
+            Fun::NativeAsync(native) => Some(native.name()),
+        }
+    }
+}
+
+/// A function implemented in rust. Natives are compared by identity.
+#[derive(Clone, Finalize)]
+pub struct Native {
+    name: &'static str,
+    // Stored as a `u32` so that the owner fits in without making values larger.
+    arity: Option<u32>,
+    fun: Rc<NativeFn>,
+    // The runtime that created the function, see `types::owner`.
+    owner: RuntimeId,
+}
+
+// The stamp (see `types::owner`) and the names of the parameters live behind the same pointer as
+// the function, so that they do not make values larger either.
+struct NativeBody<F: ?Sized> {
+    stamp: u64,
+    params: Option<&'static [&'static str]>,
+    fun: F,
+}
+
+type NativeFn = NativeBody<dyn Fn(&[Value]) -> Result<Value, Value>>;
+
+impl Native {
+    pub fn new(
+        name: &'static str,
+        fun: impl Fn(&[Value]) -> Result<Value, Value> + 'static,
+    ) -> Native {
+        Native {
+            name,
+            arity: None,
+            fun: Rc::new(NativeBody { stamp: owner::stamp(), params: None, fun }),
+            owner: owner::current(),
+        }
+    }
+
+    /// A native that declares the maximum number of arguments it uses, for reflection.
+    pub fn with_arity(
+        name: &'static str,
+        arity: usize,
+        fun: impl Fn(&[Value]) -> Result<Value, Value> + 'static,
+    ) -> Native {
+        Native {
+            name,
+            arity: Some(small_arity(arity)),
+            fun: Rc::new(NativeBody { stamp: owner::stamp(), params: None, fun }),
+            owner: owner::current(),
+        }
+    }
+
+    /// A native that declares the names of its parameters, so that it can be called with named
+    /// arguments (see `Value::apply_named`). Its arity is the number of parameters.
+    pub fn with_params(
+        name: &'static str,
+        params: &'static [&'static str],
+        fun: impl Fn(&[Value]) -> Result<Value, Value> + 'static,
+    ) -> Native {
+        Native {
+            name,
+            arity: Some(small_arity(params.len())),
+            fun: Rc::new(NativeBody { stamp: owner::stamp(), params: Some(params), fun }),
+            owner: owner::current(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The declared maximum number of arguments, if any.
+    pub fn arity(&self) -> Option<usize> {
+        self.arity.map(|arity| arity as usize)
+    }
+
+    /// The declared names of the parameters, if any.
+    pub fn params(&self) -> Option<&'static [&'static str]> {
+        self.fun.params
+    }
+
+    /// The stamp of the function, see `Fun::id`.
+    pub fn id(&self) -> u64 {
+        self.fun.stamp
+    }
+
+    fn address(&self) -> usize {
+        &*self.fun as *const NativeFn as *const () as usize
+    }
+}
+
+// Natives must not capture gc pointers, so there is nothing to trace.
+unsafe impl Trace for Native {
+    unsafe_empty_trace!();
+}
+
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Native({})", self.name)
+    }
+}
+
+impl PartialEq for Native {
+    fn eq(&self, other: &Native) -> bool {
+        self.address() == other.address()
+    }
+}
+
+impl Eq for Native {}
+
+impl PartialOrd for Native {
+    fn partial_cmp(&self, other: &Native) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Ordered by stamp, like closures (see `IrClosure`).
+impl Ord for Native {
+    fn cmp(&self, other: &Native) -> std::cmp::Ordering {
+        (self.id(), self.owner, self.address()).cmp(&(other.id(), other.owner, other.address()))
+    }
+}
+
+// Consistent with the comparison by identity.
+impl Hash for Native {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id().hash(state);
+    }
+}
+
+// Arities beyond `u32::MAX` can not be told apart from `u32::MAX` in practice.
+fn small_arity(arity: usize) -> u32 {
+    arity.min(u32::MAX as usize) as u32
+}
+
+/// A function implemented in rust that returns a rust future. Applying it returns a pan future
+/// that polls the rust future once it is run, so the interpreter never waits for it. Compared by
+/// identity.
+#[derive(Clone, Finalize)]
+pub struct NativeAsync {
+    name: &'static str,
+    // Stored as a `u32` so that the owner fits in without making values larger.
+    arity: Option<u32>,
+    fun: Rc<NativeAsyncFn>,
+    // The runtime that created the function, see `types::owner`.
+    owner: RuntimeId,
+}
+
+type NativeAsyncFn = NativeBody<dyn Fn(&[Value]) -> LocalFutureObj<'static, Result<Value, Value>>>;
+
+impl NativeAsync {
+    pub fn new(
+        name: &'static str,
+        fun: impl Fn(&[Value]) -> LocalFutureObj<'static, Result<Value, Value>> + 'static,
+    ) -> NativeAsync {
+        NativeAsync {
+            name,
+            arity: None,
+            fun: Rc::new(NativeBody { stamp: owner::stamp(), params: None, fun }),
+            owner: owner::current(),
+        }
+    }
+
+    /// An async native that declares the maximum number of arguments it uses, for reflection.
+    pub fn with_arity(
+        name: &'static str,
+        arity: usize,
+        fun: impl Fn(&[Value]) -> LocalFutureObj<'static, Result<Value, Value>> + 'static,
+    ) -> NativeAsync {
+        NativeAsync {
+            name,
+            arity: Some(small_arity(arity)),
+            fun: Rc::new(NativeBody { stamp: owner::stamp(), params: None, fun }),
+            owner: owner::current(),
+        }
+    }
+
+    /// An async native that declares the names of its parameters, like `Native::with_params`.
+    pub fn with_params(
+        name: &'static str,
+        params: &'static [&'static str],
+        fun: impl Fn(&[Value]) -> LocalFutureObj<'static, Result<Value, Value>> + 'static,
+    ) -> NativeAsync {
+        NativeAsync {
+            name,
+            arity: Some(small_arity(params.len())),
+            fun: Rc::new(NativeBody { stamp: owner::stamp(), params: Some(params), fun }),
+            owner: owner::current(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The declared maximum number of arguments, if any.
+    pub fn arity(&self) -> Option<usize> {
+        self.arity.map(|arity| arity as usize)
+    }
+
+    /// The declared names of the parameters, if any.
+    pub fn params(&self) -> Option<&'static [&'static str]> {
+        self.fun.params
+    }
+
+    /// The stamp of the function, see `Fun::id`.
+    pub fn id(&self) -> u64 {
+        self.fun.stamp
+    }
+
+    fn address(&self) -> usize {
+        &*self.fun as *const NativeAsyncFn as *const () as usize
+    }
+}
+
+unsafe impl Trace for NativeAsync {
+    unsafe_empty_trace!();
+}
+
+impl fmt::Debug for NativeAsync {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NativeAsync({})", self.name)
+    }
+}
+
+impl PartialEq for NativeAsync {
+    fn eq(&self, other: &NativeAsync) -> bool {
+        self.address() == other.address()
+    }
+}
+
+impl Eq for NativeAsync {}
+
+impl PartialOrd for NativeAsync {
+    fn partial_cmp(&self, other: &NativeAsync) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Ordered by stamp, like closures (see `IrClosure`).
+impl Ord for NativeAsync {
+    fn cmp(&self, other: &NativeAsync) -> std::cmp::Ordering {
+        (self.id(), self.owner, self.address()).cmp(&(other.id(), other.owner, other.address()))
+    }
+}
+
+// Consistent with the comparison by identity.
+impl Hash for NativeAsync {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id().hash(state);
+    }
 }
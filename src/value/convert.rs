@@ -0,0 +1,635 @@
+// Conversions between rust values and pan values, for embedders.
+//
+// Rust types that map onto a single kind of pan value convert into it with `From` (and `Into`).
+// The other direction can fail, so it uses `TryFrom` (and `TryInto`), with a `FromValueError`
+// that names the expected and the actual type. Ints convert only to ints and floats only to
+// floats, there is no implicit conversion between them.
+//
+// `IntoPanArgs` turns tuples of convertible values into argument lists, see
+// `Runtime::call_typed`.
+//
+// `IntoPan` and `FromPan` convert structured data: collections, and (with the `derive` feature)
+// structs and enums, which map onto pan maps. Errors of `FromPan` know the path to the part of the
+// value that could not be converted.
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::hash::BuildHasher;
+
+use failure_derive::Fail;
+use ordered_float::OrderedFloat;
+
+use crate::types::{bytes::Bytes, rope::Rope};
+use crate::value::{Fun, Value};
+
+#[cfg(feature = "derive")]
+pub use pan_derive::{FromPan, IntoPan};
+
+/// A pan value did not have the type that a conversion expected.
+#[derive(Debug, Clone, PartialEq, Eq, Fail)]
+#[fail(display = "expected {}, got {}", expected, got)]
+pub struct FromValueError {
+    /// The name of the expected type.
+    pub expected: &'static str,
+    /// The name of the type of the value, see `Value::type_name`.
+    pub got: &'static str,
+}
+
+impl From<()> for Value {
+    fn from(_: ()) -> Value {
+        Value::Nil
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Value {
+        Value::Bool(b)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Value {
+        Value::Int(n)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(n: i32) -> Value {
+        Value::Int(i64::from(n))
+    }
+}
+
+impl From<f64> for Value {
+    fn from(x: f64) -> Value {
+        Value::Float(OrderedFloat(x))
+    }
+}
+
+impl From<char> for Value {
+    fn from(c: char) -> Value {
+        Value::Char(c)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Value {
+        Value::String(Rope::from_str(s))
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Value {
+        Value::String(Rope::from_str(&s))
+    }
+}
+
+impl From<Rope> for Value {
+    fn from(s: Rope) -> Value {
+        Value::String(s)
+    }
+}
+
+impl From<Bytes> for Value {
+    fn from(b: Bytes) -> Value {
+        Value::Bytes(b)
+    }
+}
+
+impl From<Fun> for Value {
+    fn from(f: Fun) -> Value {
+        Value::Fun(f)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(values: Vec<Value>) -> Value {
+        Value::array(values)
+    }
+}
+
+/// `None` converts to `nil`.
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Value {
+        v.map_or(Value::Nil, Into::into)
+    }
+}
+
+fn mismatch(expected: &'static str, v: &Value) -> FromValueError {
+    FromValueError { expected, got: v.type_name() }
+}
+
+impl TryFrom<Value> for () {
+    type Error = FromValueError;
+
+    fn try_from(v: Value) -> Result<(), FromValueError> {
+        match v {
+            Value::Nil => Ok(()),
+            _ => Err(mismatch("nil", &v)),
+        }
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = FromValueError;
+
+    fn try_from(v: Value) -> Result<bool, FromValueError> {
+        match v {
+            Value::Bool(b) => Ok(b),
+            _ => Err(mismatch("bool", &v)),
+        }
+    }
+}
+
+impl TryFrom<Value> for i64 {
+    type Error = FromValueError;
+
+    fn try_from(v: Value) -> Result<i64, FromValueError> {
+        match v {
+            Value::Int(n) => Ok(n),
+            _ => Err(mismatch("int", &v)),
+        }
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = FromValueError;
+
+    fn try_from(v: Value) -> Result<f64, FromValueError> {
+        match v {
+            Value::Float(x) => Ok(x.into_inner()),
+            _ => Err(mismatch("float", &v)),
+        }
+    }
+}
+
+impl TryFrom<Value> for char {
+    type Error = FromValueError;
+
+    fn try_from(v: Value) -> Result<char, FromValueError> {
+        match v {
+            Value::Char(c) => Ok(c),
+            _ => Err(mismatch("char", &v)),
+        }
+    }
+}
+
+impl TryFrom<Value> for Rope {
+    type Error = FromValueError;
+
+    fn try_from(v: Value) -> Result<Rope, FromValueError> {
+        match &v {
+            Value::String(s) => Ok(s.clone()),
+            _ => Err(mismatch("string", &v)),
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = FromValueError;
+
+    fn try_from(v: Value) -> Result<String, FromValueError> {
+        Rope::try_from(v).map(|s| s.to_string())
+    }
+}
+
+impl TryFrom<Value> for Bytes {
+    type Error = FromValueError;
+
+    fn try_from(v: Value) -> Result<Bytes, FromValueError> {
+        match &v {
+            Value::Bytes(b) => Ok(b.clone()),
+            _ => Err(mismatch("bytes", &v)),
+        }
+    }
+}
+
+impl TryFrom<Value> for Fun {
+    type Error = FromValueError;
+
+    fn try_from(v: Value) -> Result<Fun, FromValueError> {
+        match &v {
+            Value::Fun(f) => Ok(f.clone()),
+            _ => Err(mismatch("function", &v)),
+        }
+    }
+}
+
+/// The elements of an array.
+impl TryFrom<Value> for Vec<Value> {
+    type Error = FromValueError;
+
+    fn try_from(v: Value) -> Result<Vec<Value>, FromValueError> {
+        match &v {
+            Value::Array(arr) => Ok(arr.borrow().clone()),
+            _ => Err(mismatch("array", &v)),
+        }
+    }
+}
+
+/// An argument could not be converted into a pan value, see `IntoPanArgs`.
+#[derive(Debug, Clone, PartialEq, Eq, Fail)]
+#[fail(display = "argument {} could not be converted: {}", position, message)]
+pub struct ArgumentError {
+    /// The position of the argument, starting at zero.
+    pub position: usize,
+    /// Why the conversion failed.
+    pub message: String,
+}
+
+/// Things that can be passed as the arguments of a call from rust, see `Runtime::call_typed`.
+/// Implemented for argument lists and for tuples of up to eight values that convert into pan
+/// values.
+pub trait IntoPanArgs {
+    fn into_pan_args(self) -> Result<Vec<Value>, ArgumentError>;
+}
+
+impl IntoPanArgs for Vec<Value> {
+    fn into_pan_args(self) -> Result<Vec<Value>, ArgumentError> {
+        Ok(self)
+    }
+}
+
+impl IntoPanArgs for &[Value] {
+    fn into_pan_args(self) -> Result<Vec<Value>, ArgumentError> {
+        Ok(self.to_vec())
+    }
+}
+
+impl IntoPanArgs for () {
+    fn into_pan_args(self) -> Result<Vec<Value>, ArgumentError> {
+        Ok(vec![])
+    }
+}
+
+fn argument<T>(position: usize, arg: T) -> Result<Value, ArgumentError>
+where
+    T: TryInto<Value>,
+    T::Error: fmt::Display,
+{
+    arg.try_into().map_err(|err| ArgumentError { position, message: err.to_string() })
+}
+
+macro_rules! tuple_args {
+    ($($arg:ident $position:tt),+) => {
+        impl<$($arg),+> IntoPanArgs for ($($arg,)+)
+        where
+            $($arg: TryInto<Value>, <$arg as TryInto<Value>>::Error: fmt::Display),+
+        {
+            fn into_pan_args(self) -> Result<Vec<Value>, ArgumentError> {
+                Ok(vec![$(argument($position, self.$position)?),+])
+            }
+        }
+    };
+}
+
+tuple_args!(A 0);
+tuple_args!(A 0, B 1);
+tuple_args!(A 0, B 1, C 2);
+tuple_args!(A 0, B 1, C 2, D 3);
+tuple_args!(A 0, B 1, C 2, D 3, E 4);
+tuple_args!(A 0, B 1, C 2, D 3, E 4, F 5);
+tuple_args!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+tuple_args!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+/// Conversion of rust values into pan values, for types that map onto pan values structurally.
+/// Unlike `From`, this is also implemented for collections of convertible values, and it can be
+/// derived for structs and enums (with the `derive` feature):
+///
+/// - A struct with named fields becomes a map from the field names (as strings) to the field
+///   values. `#[pan(rename = "name")]` on a field uses a different key.
+/// - A unit variant of an enum becomes its name as a string. Any other variant becomes a map with
+///   a single entry, from the name of the variant to its fields: the field itself for a single
+///   unnamed field, an array for several unnamed fields, or a map for named fields.
+///   `#[pan(rename = "name")]` on a variant uses a different name.
+pub trait IntoPan {
+    fn into_pan(self) -> Value;
+}
+
+/// Conversion of pan values into rust values, the inverse of `IntoPan`. Can be derived in the same
+/// way. A struct field that is missing from the map is an error, unless it is an `Option` (which
+/// becomes `None`) or marked with `#[pan(default)]` (which becomes `Default::default()`).
+pub trait FromPan: Sized {
+    fn from_pan(v: &Value) -> Result<Self, FromPanError>;
+
+    /// The value to use for a missing struct field, if there is one without `#[pan(default)]`.
+    fn from_missing() -> Option<Self> {
+        None
+    }
+}
+
+/// A pan value could not be converted by `FromPan`. Knows where in the value the problem is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FromPanError {
+    // Innermost first.
+    path: Vec<PathSegment>,
+    message: String,
+}
+
+/// A step into a pan value, see `FromPanError::path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    /// A struct field, map key or enum variant.
+    Field(String),
+    /// An array element.
+    Index(usize),
+}
+
+impl FromPanError {
+    /// An error at the root of the value.
+    pub fn new(message: &str) -> FromPanError {
+        FromPanError { path: vec![], message: message.to_string() }
+    }
+
+    /// The value at the root does not have the expected type.
+    pub fn mismatch(expected: &str, v: &Value) -> FromPanError {
+        FromPanError::new(&format!("expected {}, got {}", expected, v.type_name()))
+    }
+
+    /// Move the error into a field of an enclosing value.
+    pub fn at_field(mut self, name: &str) -> FromPanError {
+        self.path.push(PathSegment::Field(name.to_string()));
+        self
+    }
+
+    /// Move the error into an element of an enclosing array.
+    pub fn at_index(mut self, index: usize) -> FromPanError {
+        self.path.push(PathSegment::Index(index));
+        self
+    }
+
+    /// The steps from the root of the value to the offending part, outermost first.
+    pub fn path(&self) -> impl Iterator<Item = &PathSegment> {
+        self.path.iter().rev()
+    }
+
+    /// The path in the form `config.retries[2]`, empty for the root.
+    pub fn path_string(&self) -> String {
+        let mut path = String::new();
+        for segment in self.path() {
+            match segment {
+                PathSegment::Field(name) if path.is_empty() => path.push_str(name),
+                PathSegment::Field(name) => {
+                    path.push('.');
+                    path.push_str(name);
+                }
+                PathSegment::Index(index) => path.push_str(&format!("[{}]", index)),
+            }
+        }
+        path
+    }
+
+    /// What went wrong, without the path.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for FromPanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path_string(), self.message)
+        }
+    }
+}
+
+impl std::error::Error for FromPanError {}
+
+impl From<FromValueError> for FromPanError {
+    fn from(err: FromValueError) -> FromPanError {
+        FromPanError::new(&err.to_string())
+    }
+}
+
+impl IntoPan for Value {
+    fn into_pan(self) -> Value {
+        self
+    }
+}
+
+impl FromPan for Value {
+    fn from_pan(v: &Value) -> Result<Value, FromPanError> {
+        Ok(v.clone())
+    }
+}
+
+// The types that already convert with `From` and `TryFrom`.
+macro_rules! via_from {
+    ($($t:ty),*) => {$(
+        impl IntoPan for $t {
+            fn into_pan(self) -> Value {
+                Value::from(self)
+            }
+        }
+
+        impl FromPan for $t {
+            fn from_pan(v: &Value) -> Result<$t, FromPanError> {
+                Ok(<$t>::try_from(v.clone())?)
+            }
+        }
+    )*};
+}
+
+via_from!((), bool, i64, f64, char, String, Rope, Bytes, Fun);
+
+impl IntoPan for &str {
+    fn into_pan(self) -> Value {
+        Value::from(self)
+    }
+}
+
+// Smaller ints, which are range-checked when converting from pan.
+macro_rules! small_int {
+    ($($t:ty),*) => {$(
+        impl IntoPan for $t {
+            fn into_pan(self) -> Value {
+                Value::Int(i64::from(self))
+            }
+        }
+
+        impl FromPan for $t {
+            fn from_pan(v: &Value) -> Result<$t, FromPanError> {
+                let n = i64::from_pan(v)?;
+                <$t>::try_from(n).map_err(|_| FromPanError::new(&format!(
+                    "expected an int from {} to {}, got {}", <$t>::MIN, <$t>::MAX, n,
+                )))
+            }
+        }
+    )*};
+}
+
+small_int!(i8, i16, i32, u8, u16, u32);
+
+/// `None` converts to `nil`, and `nil` to `None`.
+impl<T: IntoPan> IntoPan for Option<T> {
+    fn into_pan(self) -> Value {
+        self.map_or(Value::Nil, IntoPan::into_pan)
+    }
+}
+
+impl<T: FromPan> FromPan for Option<T> {
+    fn from_pan(v: &Value) -> Result<Option<T>, FromPanError> {
+        match v {
+            Value::Nil => Ok(None),
+            _ => T::from_pan(v).map(Some),
+        }
+    }
+
+    fn from_missing() -> Option<Option<T>> {
+        Some(None)
+    }
+}
+
+/// Vectors convert to and from arrays.
+impl<T: IntoPan> IntoPan for Vec<T> {
+    fn into_pan(self) -> Value {
+        Value::array(self.into_iter().map(IntoPan::into_pan).collect())
+    }
+}
+
+impl<T: FromPan> FromPan for Vec<T> {
+    fn from_pan(v: &Value) -> Result<Vec<T>, FromPanError> {
+        match v {
+            Value::Array(arr) => arr.borrow().iter().enumerate().map(|(i, element)| {
+                T::from_pan(element).map_err(|err| err.at_index(i))
+            }).collect(),
+            _ => Err(FromPanError::mismatch("array", v)),
+        }
+    }
+}
+
+/// Maps with string keys convert to and from pan maps with string keys.
+impl<T: IntoPan> IntoPan for BTreeMap<String, T> {
+    fn into_pan(self) -> Value {
+        Value::map(self.into_iter().map(|(k, v)| (Value::from(k), v.into_pan())).collect())
+    }
+}
+
+impl<T: FromPan> FromPan for BTreeMap<String, T> {
+    fn from_pan(v: &Value) -> Result<BTreeMap<String, T>, FromPanError> {
+        string_entries(v)?.collect()
+    }
+}
+
+impl<T: IntoPan, S: BuildHasher> IntoPan for HashMap<String, T, S> {
+    fn into_pan(self) -> Value {
+        Value::map(self.into_iter().map(|(k, v)| (Value::from(k), v.into_pan())).collect())
+    }
+}
+
+impl<T: FromPan, S: BuildHasher + Default> FromPan for HashMap<String, T, S> {
+    fn from_pan(v: &Value) -> Result<HashMap<String, T, S>, FromPanError> {
+        string_entries(v)?.collect()
+    }
+}
+
+fn string_entries<T: FromPan>(
+    v: &Value,
+) -> Result<impl Iterator<Item = Result<(String, T), FromPanError>>, FromPanError> {
+    let entries: Vec<(Value, Value)> = match v {
+        Value::Map(map) => map.borrow().iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+        _ => return Err(FromPanError::mismatch("map", v)),
+    };
+    Ok(entries.into_iter().map(|(k, v)| {
+        let key = match &k {
+            Value::String(key) => key.to_string(),
+            _ => return Err(FromPanError::mismatch("a string key", &k)),
+        };
+        let value = T::from_pan(&v).map_err(|err| err.at_field(&key))?;
+        Ok((key, value))
+    }))
+}
+
+// Tuples convert to and from arrays of the same length.
+macro_rules! tuple_pan {
+    ($len:expr; $($t:ident $position:tt),+) => {
+        impl<$($t: IntoPan),+> IntoPan for ($($t,)+) {
+            fn into_pan(self) -> Value {
+                Value::array(vec![$(self.$position.into_pan()),+])
+            }
+        }
+
+        impl<$($t: FromPan),+> FromPan for ($($t,)+) {
+            fn from_pan(v: &Value) -> Result<($($t,)+), FromPanError> {
+                let elements = __elements(v, $len)?;
+                Ok(($(
+                    $t::from_pan(&elements[$position]).map_err(|err| err.at_index($position))?,
+                )+))
+            }
+        }
+    };
+}
+
+tuple_pan!(1; A 0);
+tuple_pan!(2; A 0, B 1);
+tuple_pan!(3; A 0, B 1, C 2);
+tuple_pan!(4; A 0, B 1, C 2, D 3);
+tuple_pan!(5; A 0, B 1, C 2, D 3, E 4);
+tuple_pan!(6; A 0, B 1, C 2, D 3, E 4, F 5);
+
+// Helpers for the code generated by the derive macros.
+
+#[doc(hidden)]
+pub fn __struct(entries: Vec<(&str, Value)>) -> Value {
+    Value::map(entries.into_iter().map(|(k, v)| (Value::from(k), v)).collect())
+}
+
+// The value of a field of a struct, or `None` if it is missing.
+#[doc(hidden)]
+pub fn __field(v: &Value, name: &str) -> Result<Option<Value>, FromPanError> {
+    match v {
+        Value::Map(map) => Ok(map.borrow().get(&Value::from(name)).cloned()),
+        _ => Err(FromPanError::mismatch("map", v)),
+    }
+}
+
+#[doc(hidden)]
+pub fn __missing<T: FromPan>(name: &str) -> Result<T, FromPanError> {
+    T::from_missing().ok_or_else(|| FromPanError::new("missing field").at_field(name))
+}
+
+#[doc(hidden)]
+pub fn __variant(name: &str, payload: Option<Value>) -> Value {
+    match payload {
+        None => Value::from(name),
+        Some(payload) => __struct(vec![(name, payload)]),
+    }
+}
+
+// The name of a variant and its payload, `None` for a unit variant.
+#[doc(hidden)]
+pub fn __variant_of(v: &Value) -> Result<(String, Option<Value>), FromPanError> {
+    match v {
+        Value::String(name) => Ok((name.to_string(), None)),
+        Value::Map(map) => {
+            let map = map.borrow();
+            let mut entries = map.iter();
+            match (entries.next(), entries.next()) {
+                (Some((Value::String(name), payload)), None) => {
+                    Ok((name.to_string(), Some(payload.clone())))
+                }
+                _ => Err(FromPanError::new("expected a map with a single string key")),
+            }
+        }
+        _ => Err(FromPanError::mismatch("string or map", v)),
+    }
+}
+
+#[doc(hidden)]
+pub fn __unknown_variant<T>(name: &str) -> Result<T, FromPanError> {
+    Err(FromPanError::new(&format!("unknown variant {}", name)))
+}
+
+// The elements of an array that must have the given length.
+#[doc(hidden)]
+pub fn __elements(v: &Value, len: usize) -> Result<Vec<Value>, FromPanError> {
+    match v {
+        Value::Array(arr) if arr.borrow().len() == len => Ok(arr.borrow().clone()),
+        Value::Array(arr) => Err(FromPanError::new(&format!(
+            "expected an array of length {}, got one of length {}", len, arr.borrow().len(),
+        ))),
+        _ => Err(FromPanError::mismatch("array", v)),
+    }
+}
@@ -0,0 +1,77 @@
+// The textual representation of numbers, shared by the printer and the builtins that convert
+// between numbers and strings.
+//
+// An int is written as an optional sign followed by one or more digits. In bases 2, 8 and 16 the
+// digits may be preceded by the prefix `0b`, `0o` or `0x` respectively. Digits above 9 are the
+// letters `a` to `z`, in either case. Underscores, whitespace and empty digit sequences are not
+// allowed.
+//
+// A float is written in the shortest form that parses back to the same float, e.g. `1.0`, `0.1`,
+// `1e300`, `inf`, `-inf` or `NaN`. Parsing accepts these forms and anything else Rust's float
+// parser accepts, e.g. `.5` or `1E3`.
+
+use std::num::IntErrorKind;
+
+use failure_derive::Fail;
+
+/// Everything that can go wrong when parsing a number.
+#[derive(Debug, Clone, PartialEq, Eq, Fail)]
+pub enum ParseNumberError {
+    #[fail(display = "the base {} is not between 2 and 36", base)]
+    InvalidBase { base: u32 },
+    #[fail(display = "not a number")]
+    Invalid,
+    #[fail(display = "the number does not fit into an int")]
+    OutOfRange,
+}
+
+/// Parse an int in the given base, see the module comment for the syntax.
+pub fn parse_int(s: &str, base: u32) -> Result<i64, ParseNumberError> {
+    if !(2..=36).contains(&base) {
+        return Err(ParseNumberError::InvalidBase { base });
+    }
+
+    let (sign, unsigned) = match s.as_bytes().first() {
+        Some(b'-') => ("-", &s[1..]),
+        Some(b'+') => ("", &s[1..]),
+        _ => ("", s),
+    };
+    let prefix = match base {
+        2 => Some("0b"),
+        8 => Some("0o"),
+        16 => Some("0x"),
+        _ => None,
+    };
+    let digits = prefix
+        .and_then(|prefix| strip_prefix_ignore_case(unsigned, prefix))
+        .unwrap_or(unsigned);
+
+    // `from_str_radix` would accept a second sign.
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(base)) {
+        return Err(ParseNumberError::Invalid);
+    }
+    i64::from_str_radix(&format!("{}{}", sign, digits), base).map_err(|err| match err.kind() {
+        IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => ParseNumberError::OutOfRange,
+        _ => ParseNumberError::Invalid,
+    })
+}
+
+fn strip_prefix_ignore_case<'s>(s: &'s str, prefix: &str) -> Option<&'s str> {
+    if s.len() >= prefix.len() && s.is_char_boundary(prefix.len()) {
+        let (start, rest) = s.split_at(prefix.len());
+        if start.eq_ignore_ascii_case(prefix) {
+            return Some(rest);
+        }
+    }
+    None
+}
+
+/// Parse a float, see the module comment for the syntax.
+pub fn parse_float(s: &str) -> Result<f64, ParseNumberError> {
+    s.parse().map_err(|_| ParseNumberError::Invalid)
+}
+
+/// The shortest representation of a float that `parse_float` turns back into the same float.
+pub fn format_float(x: f64) -> String {
+    format!("{:?}", x)
+}
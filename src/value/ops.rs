@@ -0,0 +1,182 @@
+// Arithmetic on pan numbers.
+//
+// Ints and floats can be mixed: if either operand is a float, the other one is converted to the
+// nearest float and the operation is performed on floats. Int arithmetic is checked: a result
+// that does not fit into an int throws an error of kind `"overflow"`, dividing by zero throws an
+// error of kind `"division_by_zero"`. Int division truncates towards zero, the remainder has the
+// sign of the dividend. Float arithmetic follows IEEE 754: it never throws, dividing by zero
+// yields an infinity or NaN, and NaN propagates.
+//
+// The explicit integer divisions only accept ints. The floor variants round the quotient towards
+// negative infinity, so the remainder has the sign of the divisor, the trunc variants round
+// towards zero like `div`, so the remainder has the sign of the dividend. All of them throw on
+// division by zero, and the quotient of the smallest int and `-1` overflows. The remainders never
+// overflow (the remainder of the smallest int and `-1` is zero), unlike the one of `rem`.
+//
+// `pow` of two ints is an int, negative exponents throw an error of kind `"range"`. If either
+// operand is a float, the result is a float.
+
+use std::convert::TryFrom;
+
+use ordered_float::OrderedFloat;
+
+use crate::value::Value;
+
+// The operands of a binary operation, after promotion.
+enum Operands {
+    Ints(i64, i64),
+    Floats(f64, f64),
+}
+
+fn operands(name: &str, a: &Value, b: &Value) -> Result<Operands, Value> {
+    match (a, b) {
+        (Value::Int(a), Value::Int(b)) => Ok(Operands::Ints(*a, *b)),
+        (Value::Int(a), Value::Float(b)) => Ok(Operands::Floats(*a as f64, b.into_inner())),
+        (Value::Float(a), Value::Int(b)) => Ok(Operands::Floats(a.into_inner(), *b as f64)),
+        (Value::Float(a), Value::Float(b)) => Ok(Operands::Floats(a.into_inner(), b.into_inner())),
+        _ => Err(Value::error("type", &format!(
+            "{} expects two numbers, got {} and {}", name, a.type_name(), b.type_name(),
+        ))),
+    }
+}
+
+fn ints(name: &str, a: &Value, b: &Value) -> Result<(i64, i64), Value> {
+    match (a, b) {
+        (Value::Int(a), Value::Int(b)) => Ok((*a, *b)),
+        _ => Err(Value::error("type", &format!(
+            "{} expects two ints, got {} and {}", name, a.type_name(), b.type_name(),
+        ))),
+    }
+}
+
+fn int(name: &str, n: Option<i64>) -> Result<Value, Value> {
+    n.map(Value::Int).ok_or_else(|| Value::error("overflow", &format!("{} overflowed", name)))
+}
+
+fn float(x: f64) -> Result<Value, Value> {
+    Ok(Value::Float(OrderedFloat(x)))
+}
+
+fn nonzero(name: &str, divisor: i64) -> Result<(), Value> {
+    if divisor == 0 {
+        Err(Value::error("division_by_zero", &format!("{} by zero", name)))
+    } else {
+        Ok(())
+    }
+}
+
+/// The int with the same value as an integral float, or `None` if the float is not in the range
+/// of ints. Fractional parts are truncated.
+pub fn float_to_int(x: f64) -> Option<i64> {
+    // The bounds are -2^63 and 2^63, both exactly representable as floats. NaN fails both.
+    if x >= i64::MIN as f64 && x < -(i64::MIN as f64) {
+        Some(x as i64)
+    } else {
+        None
+    }
+}
+
+pub fn add(a: &Value, b: &Value) -> Result<Value, Value> {
+    match operands("add", a, b)? {
+        Operands::Ints(a, b) => int("add", a.checked_add(b)),
+        Operands::Floats(a, b) => float(a + b),
+    }
+}
+
+pub fn sub(a: &Value, b: &Value) -> Result<Value, Value> {
+    match operands("sub", a, b)? {
+        Operands::Ints(a, b) => int("sub", a.checked_sub(b)),
+        Operands::Floats(a, b) => float(a - b),
+    }
+}
+
+pub fn mul(a: &Value, b: &Value) -> Result<Value, Value> {
+    match operands("mul", a, b)? {
+        Operands::Ints(a, b) => int("mul", a.checked_mul(b)),
+        Operands::Floats(a, b) => float(a * b),
+    }
+}
+
+pub fn div(a: &Value, b: &Value) -> Result<Value, Value> {
+    match operands("div", a, b)? {
+        Operands::Ints(a, b) => {
+            nonzero("div", b)?;
+            int("div", a.checked_div(b))
+        }
+        Operands::Floats(a, b) => float(a / b),
+    }
+}
+
+pub fn rem(a: &Value, b: &Value) -> Result<Value, Value> {
+    match operands("rem", a, b)? {
+        Operands::Ints(a, b) => {
+            nonzero("rem", b)?;
+            int("rem", a.checked_rem(b))
+        }
+        Operands::Floats(a, b) => float(a % b),
+    }
+}
+
+pub fn floor_div(a: &Value, b: &Value) -> Result<Value, Value> {
+    let (q, _) = floored("floor_div", a, b)?;
+    int("floor_div", q)
+}
+
+pub fn floor_mod(a: &Value, b: &Value) -> Result<Value, Value> {
+    let (_, r) = floored("floor_mod", a, b)?;
+    Ok(Value::Int(r))
+}
+
+pub fn divmod(a: &Value, b: &Value) -> Result<Value, Value> {
+    let (q, r) = floored("divmod", a, b)?;
+    Ok(Value::array(vec![int("divmod", q)?, Value::Int(r)]))
+}
+
+// The floored quotient (`None` if it overflows) and remainder of two ints.
+fn floored(name: &str, a: &Value, b: &Value) -> Result<(Option<i64>, i64), Value> {
+    let (a, b) = ints(name, a, b)?;
+    nonzero(name, b)?;
+    let r = a.wrapping_rem(b);
+    if r != 0 && (r < 0) != (b < 0) {
+        // The division is inexact, so `b` is neither `1` nor `-1` and the quotient can be
+        // decremented. Adding `b` to `r` can not overflow, as their signs differ.
+        Ok((Some(a / b - 1), r + b))
+    } else {
+        Ok((a.checked_div(b), r))
+    }
+}
+
+pub fn trunc_div(a: &Value, b: &Value) -> Result<Value, Value> {
+    let (a, b) = ints("trunc_div", a, b)?;
+    nonzero("trunc_div", b)?;
+    int("trunc_div", a.checked_div(b))
+}
+
+pub fn trunc_mod(a: &Value, b: &Value) -> Result<Value, Value> {
+    let (a, b) = ints("trunc_mod", a, b)?;
+    nonzero("trunc_mod", b)?;
+    Ok(Value::Int(a.wrapping_rem(b)))
+}
+
+pub fn pow(a: &Value, b: &Value) -> Result<Value, Value> {
+    match operands("pow", a, b)? {
+        Operands::Ints(_, exp) if exp < 0 => Err(Value::error("range", &format!(
+            "pow expects a non-negative int exponent, got {}", exp,
+        ))),
+        // Exponents beyond `u32::MAX` overflow unless the base is -1, 0 or 1, so replacing them
+        // with a large exponent of the same parity gives the same result.
+        Operands::Ints(base, exp) => {
+            let exp = u32::try_from(exp).unwrap_or(u32::MAX - 1 + (exp % 2) as u32);
+            int("pow", base.checked_pow(exp))
+        }
+        Operands::Floats(a, b) => float(a.powf(b)),
+    }
+}
+
+pub fn neg(a: &Value) -> Result<Value, Value> {
+    match a {
+        Value::Int(n) => int("neg", n.checked_neg()),
+        Value::Float(x) => float(-x.into_inner()),
+        _ => Err(Value::error("type", &format!("neg expects a number, got {}", a.type_name()))),
+    }
+}
@@ -0,0 +1,222 @@
+mod common;
+
+use std::convert::TryFrom;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::value::Value;
+
+use common::{call, float, int, kind, message, s};
+
+#[test]
+fn ints_stay_ints() {
+    let mut rt = Runtime::new();
+    let cases = [
+        ("add", 7, -3, 4),
+        ("sub", 7, -3, 10),
+        ("mul", 7, -3, -21),
+        ("div", 7, -3, -2),
+        ("div", -7, 3, -2),
+        ("rem", 7, -3, 1),
+        ("rem", -7, 3, -1),
+    ];
+    for &(name, a, b, expected) in &cases {
+        assert_eq!(call(&mut rt, name, &[int(a), int(b)]), Ok(int(expected)), "{}", name);
+    }
+    assert_eq!(call(&mut rt, "neg", &[int(5)]), Ok(int(-5)));
+    assert_eq!(call(&mut rt, "neg", &[float(0.5)]), Ok(float(-0.5)));
+}
+
+#[test]
+fn mixing_promotes_to_floats() {
+    let mut rt = Runtime::new();
+    let cases = [
+        ("add", 3.5),
+        ("sub", -0.5),
+        ("mul", 3.0),
+        ("div", 0.75),
+        ("rem", 1.5),
+    ];
+    for &(name, expected) in &cases {
+        let floats = call(&mut rt, name, &[float(1.5), float(2.0)]).unwrap();
+        let mixed = call(&mut rt, name, &[float(1.5), int(2)]).unwrap();
+        assert_eq!(mixed, float(expected), "{}", name);
+        assert_eq!(mixed, floats, "{}", name);
+    }
+    assert_eq!(call(&mut rt, "add", &[int(1), float(0.5)]), Ok(float(1.5)));
+}
+
+#[test]
+fn overflow_and_division_by_zero_throw() {
+    let mut rt = Runtime::new();
+    let overflowing = [
+        ("add", i64::MAX, 1),
+        ("sub", i64::MIN, 1),
+        ("mul", i64::MAX, 2),
+        ("div", i64::MIN, -1),
+        ("rem", i64::MIN, -1),
+    ];
+    for &(name, a, b) in &overflowing {
+        let err = call(&mut rt, name, &[int(a), int(b)]).unwrap_err();
+        assert_eq!(kind(&err), "overflow", "{}", name);
+    }
+    assert_eq!(kind(&call(&mut rt, "neg", &[int(i64::MIN)]).unwrap_err()), "overflow");
+    for name in &["div", "rem"] {
+        let err = call(&mut rt, name, &[int(1), int(0)]).unwrap_err();
+        assert_eq!(kind(&err), "division_by_zero", "{}", name);
+    }
+    // Floats follow IEEE 754 instead.
+    assert_eq!(call(&mut rt, "div", &[float(1.0), int(0)]), Ok(float(f64::INFINITY)));
+    assert_eq!(call(&mut rt, "div", &[int(-1), float(0.0)]), Ok(float(f64::NEG_INFINITY)));
+}
+
+#[test]
+fn nan_propagates() {
+    let mut rt = Runtime::new();
+    for name in &["add", "sub", "mul", "div", "rem"] {
+        let result = call(&mut rt, name, &[float(f64::NAN), int(1)]).unwrap();
+        match result {
+            Value::Float(x) => assert!(x.is_nan(), "{}", name),
+            other => panic!("not a float: {:?}", other),
+        }
+    }
+    match call(&mut rt, "div", &[float(0.0), float(0.0)]).unwrap() {
+        Value::Float(x) => assert!(x.is_nan()),
+        other => panic!("not a float: {:?}", other),
+    }
+}
+
+#[test]
+fn non_numbers_throw_type_errors() {
+    let mut rt = Runtime::new();
+    let err = call(&mut rt, "add", &[int(1), s("1")]).unwrap_err();
+    assert_eq!(kind(&err), "type");
+    assert_eq!(message(&err), "add expects two numbers, got int and string");
+    let err = call(&mut rt, "mul", &[int(1)]).unwrap_err();
+    assert_eq!(message(&err), "mul expects two numbers, got int and nil");
+    assert_eq!(kind(&call(&mut rt, "neg", &[s("1")]).unwrap_err()), "type");
+}
+
+const CORNERS: [i64; 8] = [
+    i64::MIN, i64::MIN + 1, -1_000_003, -2, 2, 999_983, i64::MAX - 1, i64::MAX,
+];
+
+// The operands to try: every small int, both signs, and the corners of the int range.
+fn operands() -> Vec<i64> {
+    (-7..=7).chain(CORNERS.iter().cloned()).collect()
+}
+
+// The floored quotient and remainder, computed without overflow.
+fn floored(a: i64, b: i64) -> (i128, i128) {
+    let (a, b) = (i128::from(a), i128::from(b));
+    let q = if b > 0 { a.div_euclid(b) } else { (-a).div_euclid(-b) };
+    (q, a - b * q)
+}
+
+fn expect_int(value: i128) -> Result<Value, String> {
+    i64::try_from(value).map(int).map_err(|_| "overflow".to_string())
+}
+
+fn outcome(result: Result<Value, Value>) -> Result<Value, String> {
+    result.map_err(|err| kind(&err))
+}
+
+#[test]
+fn integer_divisions_agree_with_an_oracle() {
+    let mut rt = Runtime::new();
+    for &a in &operands() {
+        for &b in &operands() {
+            let args = [int(a), int(b)];
+            if b == 0 {
+                for name in &["floor_div", "floor_mod", "trunc_div", "trunc_mod", "divmod"] {
+                    let err = call(&mut rt, name, &args).unwrap_err();
+                    assert_eq!(kind(&err), "division_by_zero", "{} {} {}", name, a, b);
+                }
+                continue;
+            }
+            let (q, r) = floored(a, b);
+            let floor_div = outcome(call(&mut rt, "floor_div", &args));
+            assert_eq!(floor_div, expect_int(q), "floor_div {} {}", a, b);
+            assert_eq!(call(&mut rt, "floor_mod", &args), Ok(int(r as i64)), "{} {}", a, b);
+            // The remainder has the sign of the divisor, and is the euclidean one for positive
+            // divisors.
+            assert!(r == 0 || (r < 0) == (b < 0), "{} {}", a, b);
+            if b > 0 {
+                assert_eq!(r as i64, a.rem_euclid(b), "{} {}", a, b);
+                assert_eq!(floor_div, Ok(int(a.div_euclid(b))), "{} {}", a, b);
+            }
+            let divmod = outcome(call(&mut rt, "divmod", &args));
+            assert_eq!(divmod, expect_int(q).map(|q| Value::array(vec![q, int(r as i64)])));
+
+            let trunc_div = outcome(call(&mut rt, "trunc_div", &args));
+            let expected = expect_int(i128::from(a) / i128::from(b));
+            assert_eq!(trunc_div, expected, "trunc_div {} {}", a, b);
+            assert_eq!(outcome(call(&mut rt, "div", &args)), expected, "div {} {}", a, b);
+            let trunc_mod = call(&mut rt, "trunc_mod", &args).unwrap();
+            assert_eq!(trunc_mod, int(a.wrapping_rem(b)), "trunc_mod {} {}", a, b);
+            assert_eq!(trunc_mod, int((i128::from(a) % i128::from(b)) as i64));
+        }
+    }
+}
+
+#[test]
+fn only_the_smallest_int_divided_by_minus_one_overflows() {
+    let mut rt = Runtime::new();
+    let args = [int(i64::MIN), int(-1)];
+    for name in &["floor_div", "trunc_div", "divmod", "div", "rem"] {
+        assert_eq!(kind(&call(&mut rt, name, &args).unwrap_err()), "overflow", "{}", name);
+    }
+    assert_eq!(call(&mut rt, "floor_mod", &args), Ok(int(0)));
+    assert_eq!(call(&mut rt, "trunc_mod", &args), Ok(int(0)));
+    let args = [int(i64::MIN), int(1)];
+    assert_eq!(call(&mut rt, "floor_div", &args), Ok(int(i64::MIN)));
+    assert_eq!(call(&mut rt, "divmod", &args), Ok(Value::array(vec![int(i64::MIN), int(0)])));
+}
+
+#[test]
+fn explicit_divisions_only_take_ints() {
+    let mut rt = Runtime::new();
+    for name in &["floor_div", "floor_mod", "trunc_div", "trunc_mod", "divmod"] {
+        for args in &[[float(4.0), int(2)], [int(4), float(2.0)], [int(4), s("2")]] {
+            assert_eq!(kind(&call(&mut rt, name, args).unwrap_err()), "type", "{}", name);
+        }
+        assert_eq!(kind(&call(&mut rt, name, &[int(4)]).unwrap_err()), "type", "{}", name);
+    }
+}
+
+#[test]
+fn int_powers_are_checked() {
+    let mut rt = Runtime::new();
+    let mut pow = |base: i64, exp: i64| outcome(call(&mut rt, "pow", &[int(base), int(exp)]));
+    // The overflow boundaries, against `checked_pow`.
+    for &(base, last) in &[(2, 62), (-2, 63), (3, 39), (-3, 39), (10, 18), (i64::MAX, 1)] {
+        for exp in 0..=last + 1 {
+            let expected = base.checked_pow(exp as u32).map(int).ok_or_else(|| "overflow".into());
+            assert_eq!(pow(base, exp), expected, "{} {}", base, exp);
+            assert_eq!(expected.is_ok(), exp <= last, "{} {}", base, exp);
+        }
+    }
+    assert_eq!(pow(-2, 63), Ok(int(i64::MIN)));
+    assert_eq!(pow(0, 0), Ok(int(1)));
+    // Huge exponents only fit for -1, 0 and 1.
+    let huge = 1 << 40;
+    assert_eq!(pow(1, huge), Ok(int(1)));
+    assert_eq!(pow(0, huge), Ok(int(0)));
+    assert_eq!(pow(-1, huge), Ok(int(1)));
+    assert_eq!(pow(-1, huge + 1), Ok(int(-1)));
+    assert_eq!(pow(-1, i64::MAX), Ok(int(-1)));
+    assert_eq!(pow(2, huge), Err("overflow".into()));
+    for &exp in &[-1, -2, i64::MIN] {
+        assert_eq!(pow(2, exp), Err("range".into()), "{}", exp);
+        assert_eq!(pow(1, exp), Err("range".into()), "{}", exp);
+    }
+}
+
+#[test]
+fn float_powers_are_used_for_any_float_operand() {
+    let mut rt = Runtime::new();
+    assert_eq!(call(&mut rt, "pow", &[int(2), float(-1.0)]), Ok(float(0.5)));
+    assert_eq!(call(&mut rt, "pow", &[float(2.0), int(-1)]), Ok(float(0.5)));
+    assert_eq!(call(&mut rt, "pow", &[float(2.0), int(64)]), Ok(float(2f64.powi(64))));
+    assert_eq!(call(&mut rt, "pow", &[int(10), float(400.0)]), Ok(float(f64::INFINITY)));
+    assert_eq!(kind(&call(&mut rt, "pow", &[s("2"), int(1)]).unwrap_err()), "type");
+}
@@ -0,0 +1,243 @@
+mod common;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, int, ints, kind, message, s};
+
+#[test]
+fn reading() {
+    let mut rt = Runtime::new();
+    let a = ints(&[1, 2, 3]);
+    assert_eq!(call(&mut rt, "len", std::slice::from_ref(&a)), Ok(int(3)));
+    assert_eq!(call(&mut rt, "len", &[ints(&[])]), Ok(int(0)));
+    assert_eq!(call(&mut rt, "get", &[a.clone(), int(2)]), Ok(int(3)));
+    assert_eq!(call(&mut rt, "index_of", &[a.clone(), int(2)]), Ok(int(1)));
+    assert_eq!(call(&mut rt, "index_of", &[a.clone(), int(4)]), Ok(Value::Nil));
+    assert_eq!(call(&mut rt, "index_of", &[ints(&[1, 1]), int(1)]), Ok(int(0)));
+    assert_eq!(call(&mut rt, "contains", &[a.clone(), int(3)]), Ok(Value::Bool(true)));
+    assert_eq!(call(&mut rt, "contains", &[a, s("3")]), Ok(Value::Bool(false)));
+    let nested = arr(vec![ints(&[1]), ints(&[2])]);
+    assert_eq!(call(&mut rt, "index_of", &[nested, ints(&[2])]), Ok(int(1)));
+}
+
+#[test]
+fn mutating() {
+    let mut rt = Runtime::new();
+    let a = ints(&[1, 2, 3]);
+    assert_eq!(call(&mut rt, "set", &[a.clone(), int(0), int(9)]), Ok(Value::Nil));
+    assert_eq!(call(&mut rt, "push", &[a.clone(), int(4)]), Ok(Value::Nil));
+    assert_eq!(a, ints(&[9, 2, 3, 4]));
+    assert_eq!(call(&mut rt, "pop", std::slice::from_ref(&a)), Ok(int(4)));
+    assert_eq!(call(&mut rt, "insert", &[a.clone(), int(3), int(5)]), Ok(Value::Nil));
+    assert_eq!(call(&mut rt, "insert", &[a.clone(), int(0), int(0)]), Ok(Value::Nil));
+    assert_eq!(a, ints(&[0, 9, 2, 3, 5]));
+    assert_eq!(call(&mut rt, "remove", &[a.clone(), int(1)]), Ok(int(9)));
+    assert_eq!(call(&mut rt, "reverse", std::slice::from_ref(&a)), Ok(Value::Nil));
+    assert_eq!(a, ints(&[5, 3, 2, 0]));
+    assert_eq!(call(&mut rt, "clear", std::slice::from_ref(&a)), Ok(Value::Nil));
+    assert_eq!(a, ints(&[]));
+}
+
+#[test]
+fn slices_and_concatenations_are_copies() {
+    let mut rt = Runtime::new();
+    let a = ints(&[1, 2, 3, 4]);
+    let b = ints(&[5]);
+    let sliced = call(&mut rt, "slice", &[a.clone(), int(1), int(3)]).unwrap();
+    assert_eq!(sliced, ints(&[2, 3]));
+    assert_eq!(call(&mut rt, "slice", &[a.clone(), int(2)]), Ok(ints(&[3, 4])));
+    assert_eq!(call(&mut rt, "slice", &[a.clone(), int(4)]), Ok(ints(&[])));
+    let joined = call(&mut rt, "concat", &[a.clone(), b.clone()]).unwrap();
+    assert_eq!(joined, ints(&[1, 2, 3, 4, 5]));
+
+    call(&mut rt, "set", &[sliced.clone(), int(0), int(0)]).unwrap();
+    call(&mut rt, "push", &[joined.clone(), int(6)]).unwrap();
+    call(&mut rt, "clear", std::slice::from_ref(&b)).unwrap();
+    assert_eq!(a, ints(&[1, 2, 3, 4]));
+    assert_eq!(sliced, ints(&[0, 3]));
+    assert_eq!(joined, ints(&[1, 2, 3, 4, 5, 6]));
+
+    let doubled = call(&mut rt, "concat", &[a.clone(), a.clone()]).unwrap();
+    assert_eq!(doubled, ints(&[1, 2, 3, 4, 1, 2, 3, 4]));
+}
+
+#[test]
+fn out_of_bounds() {
+    let mut rt = Runtime::new();
+    let a = ints(&[1, 2]);
+    let calls: [(&str, Vec<Value>); 8] = [
+        ("get", vec![a.clone(), int(2)]),
+        ("get", vec![a.clone(), int(-1)]),
+        ("set", vec![a.clone(), int(2), int(0)]),
+        ("insert", vec![a.clone(), int(3), int(0)]),
+        ("remove", vec![a.clone(), int(2)]),
+        ("slice", vec![a.clone(), int(3)]),
+        ("slice", vec![a.clone(), int(2), int(1)]),
+        ("pop", vec![ints(&[])]),
+    ];
+    for (name, args) in calls.iter() {
+        let err = call(&mut rt, name, args).unwrap_err();
+        assert_eq!(kind(&err), "range", "{}", name);
+    }
+    let err = call(&mut rt, "get", &[a.clone(), int(5)]).unwrap_err();
+    assert_eq!(message(&err), "get expects an index less than 2, got 5");
+    assert_eq!(a, ints(&[1, 2]));
+}
+
+#[test]
+fn wrong_types() {
+    let mut rt = Runtime::new();
+    let names = [
+        "len", "get", "set", "push", "pop", "insert", "remove", "slice", "concat", "index_of",
+        "contains", "reverse", "clear",
+    ];
+    for name in names.iter() {
+        let err = call(&mut rt, name, &[s("abc"), int(0), int(0)]).unwrap_err();
+        assert_eq!(kind(&err), "type", "{}", name);
+        assert_eq!(message(&err), format!("{} expects an array, got string", name));
+    }
+    let a = ints(&[1]);
+    assert_eq!(kind(&call(&mut rt, "get", &[a.clone(), s("0")]).unwrap_err()), "type");
+    assert_eq!(kind(&call(&mut rt, "concat", &[a.clone(), Value::Nil]).unwrap_err()), "type");
+    assert_eq!(kind(&call(&mut rt, "get", &[a]).unwrap_err()), "type");
+}
+
+#[test]
+fn self_referential_arrays() {
+    let mut rt = Runtime::new();
+    let a = ints(&[1]);
+    call(&mut rt, "push", &[a.clone(), a.clone()]).unwrap();
+    assert_eq!(call(&mut rt, "index_of", &[a.clone(), a.clone()]), Ok(int(1)));
+    assert_eq!(call(&mut rt, "contains", &[a.clone(), a.clone()]), Ok(Value::Bool(true)));
+    // An array that contains itself is equal to an array that contains the array.
+    let b = arr(vec![int(1), a.clone()]);
+    assert_eq!(call(&mut rt, "index_of", &[a.clone(), b.clone()]), Ok(int(1)));
+    assert_eq!(call(&mut rt, "index_of", &[b, a.clone()]), Ok(int(1)));
+    assert_eq!(call(&mut rt, "len", std::slice::from_ref(&a)), Ok(int(2)));
+    let copy = call(&mut rt, "concat", &[a.clone(), a.clone()]).unwrap();
+    assert_eq!(call(&mut rt, "index_of", &[copy, a.clone()]), Ok(int(1)));
+    call(&mut rt, "reverse", std::slice::from_ref(&a)).unwrap();
+    assert_eq!(call(&mut rt, "index_of", &[a.clone(), int(1)]), Ok(int(1)));
+    // Break the cycle so that the array can be collected.
+    call(&mut rt, "clear", &[a]).unwrap();
+}
+
+#[test]
+fn frozen_arrays() {
+    let mut rt = Runtime::new();
+    let a = ints(&[3, 1, 2]);
+    call(&mut rt, "freeze", std::slice::from_ref(&a)).unwrap();
+    let mutations: [(&str, Vec<Value>); 7] = [
+        ("set", vec![a.clone(), int(0), int(0)]),
+        ("push", vec![a.clone(), int(0)]),
+        ("pop", vec![a.clone()]),
+        ("insert", vec![a.clone(), int(0), int(0)]),
+        ("remove", vec![a.clone(), int(0)]),
+        ("reverse", vec![a.clone()]),
+        ("clear", vec![a.clone()]),
+    ];
+    for (name, args) in mutations.iter() {
+        let err = call(&mut rt, name, args).unwrap_err();
+        assert_eq!(kind(&err), "frozen", "{}", name);
+    }
+    assert_eq!(a, ints(&[3, 1, 2]));
+
+    // Reading works, and the copies are not frozen.
+    assert_eq!(call(&mut rt, "get", &[a.clone(), int(1)]), Ok(int(1)));
+    assert_eq!(call(&mut rt, "index_of", &[a.clone(), int(2)]), Ok(int(2)));
+    let sliced = call(&mut rt, "slice", &[a.clone(), int(0)]).unwrap();
+    let joined = call(&mut rt, "concat", &[a.clone(), a.clone()]).unwrap();
+    assert_eq!(call(&mut rt, "push", &[sliced.clone(), int(4)]), Ok(Value::Nil));
+    assert_eq!(call(&mut rt, "clear", &[joined]), Ok(Value::Nil));
+    assert_eq!(sliced, ints(&[3, 1, 2, 4]));
+}
+
+#[test]
+fn ranges() {
+    let mut rt = Runtime::new();
+    assert_eq!(call(&mut rt, "range", &[int(0), int(4)]), Ok(ints(&[0, 1, 2, 3])));
+    assert_eq!(call(&mut rt, "range", &[int(1), int(8), int(3)]), Ok(ints(&[1, 4, 7])));
+    assert_eq!(call(&mut rt, "range", &[int(1), int(7), int(3)]), Ok(ints(&[1, 4])));
+    assert_eq!(call(&mut rt, "range", &[int(3), int(-3), int(-2)]), Ok(ints(&[3, 1, -1])));
+    assert_eq!(call(&mut rt, "range", &[int(4), int(0)]), Ok(ints(&[])));
+    assert_eq!(call(&mut rt, "range", &[int(0), int(4), int(-1)]), Ok(ints(&[])));
+    assert_eq!(call(&mut rt, "range", &[int(2), int(2)]), Ok(ints(&[])));
+    let near_max = [int(i64::MAX - 2), int(i64::MAX)];
+    assert_eq!(call(&mut rt, "range", &near_max), Ok(ints(&[i64::MAX - 2, i64::MAX - 1])));
+    let across = [int(i64::MIN), int(i64::MAX), int(i64::MAX)];
+    assert_eq!(call(&mut rt, "range", &across), Ok(ints(&[i64::MIN, -1, i64::MAX - 1])));
+
+    let err = call(&mut rt, "range", &[int(0), int(4), int(0)]).unwrap_err();
+    assert_eq!(kind(&err), "range");
+    // Huge ranges throw instead of allocating.
+    for huge in [[int(0), int(i64::MAX)], [int(i64::MIN), int(i64::MAX)]].iter() {
+        assert_eq!(kind(&call(&mut rt, "range", huge).unwrap_err()), "range");
+    }
+    let max = pan_lang_rs::builtins::array::MAX_RANGE_LEN as i64;
+    assert_eq!(kind(&call(&mut rt, "range", &[int(0), int(max + 1)]).unwrap_err()), "range");
+    let stepped = [int(0), int(-2 * max - 1), int(-2)];
+    assert_eq!(kind(&call(&mut rt, "range", &stepped).unwrap_err()), "range");
+    assert_eq!(kind(&call(&mut rt, "range", &[int(0), s("4")]).unwrap_err()), "type");
+}
+
+#[test]
+fn zips_and_enumerations() {
+    let mut rt = Runtime::new();
+    let pairs = |ps: &[(i64, i64)]| arr(ps.iter().map(|(a, b)| ints(&[*a, *b])).collect());
+    let a = ints(&[1, 2, 3]);
+    let b = ints(&[4, 5]);
+    assert_eq!(call(&mut rt, "zip", &[a.clone(), b.clone()]), Ok(pairs(&[(1, 4), (2, 5)])));
+    assert_eq!(call(&mut rt, "zip", &[b.clone(), a.clone()]), Ok(pairs(&[(4, 1), (5, 2)])));
+    assert_eq!(call(&mut rt, "zip", &[a.clone(), ints(&[])]), Ok(ints(&[])));
+    assert_eq!(call(&mut rt, "enumerate", &[b]), Ok(pairs(&[(0, 4), (1, 5)])));
+    assert_eq!(call(&mut rt, "enumerate", &[ints(&[])]), Ok(ints(&[])));
+    assert_eq!(kind(&call(&mut rt, "zip", &[a]).unwrap_err()), "type");
+}
+
+#[test]
+fn flattening() {
+    let mut rt = Runtime::new();
+    let nested = arr(vec![int(1), arr(vec![int(2), arr(vec![int(3), ints(&[4])])]), ints(&[])]);
+    let once = arr(vec![int(1), int(2), arr(vec![int(3), ints(&[4])])]);
+    assert_eq!(call(&mut rt, "flatten", std::slice::from_ref(&nested)), Ok(once));
+    let twice = arr(vec![int(1), int(2), int(3), ints(&[4])]);
+    assert_eq!(call(&mut rt, "flatten", &[nested.clone(), int(2)]), Ok(twice));
+    let all = ints(&[1, 2, 3, 4]);
+    assert_eq!(call(&mut rt, "flatten", &[nested.clone(), int(10)]), Ok(all));
+    assert_eq!(call(&mut rt, "flatten", &[nested.clone(), int(0)]), Ok(nested.clone()));
+    assert_eq!(call(&mut rt, "flatten", &[ints(&[])]), Ok(ints(&[])));
+    assert_eq!(kind(&call(&mut rt, "flatten", &[nested, int(-1)]).unwrap_err()), "range");
+
+    // The same array twice is not a cycle.
+    let shared = ints(&[1]);
+    let diamond = arr(vec![shared.clone(), arr(vec![shared.clone()])]);
+    assert_eq!(call(&mut rt, "flatten", &[diamond, int(5)]), Ok(ints(&[1, 1])));
+
+    let cyclic = ints(&[1]);
+    let outer = arr(vec![cyclic.clone()]);
+    call(&mut rt, "push", &[cyclic.clone(), outer.clone()]).unwrap();
+    let err = call(&mut rt, "flatten", &[outer.clone(), int(100)]).unwrap_err();
+    assert_eq!(kind(&err), "cycle");
+    // Flattening stops before the cycle closes at a small enough depth.
+    assert_eq!(call(&mut rt, "flatten", &[outer.clone(), int(1)]), Ok(arr(vec![int(1), outer])));
+    call(&mut rt, "clear", &[cyclic]).unwrap();
+}
+
+#[test]
+fn chunking() {
+    let mut rt = Runtime::new();
+    let a = ints(&[1, 2, 3, 4, 5]);
+    let chunked = arr(vec![ints(&[1, 2]), ints(&[3, 4]), ints(&[5])]);
+    assert_eq!(call(&mut rt, "chunks", &[a.clone(), int(2)]), Ok(chunked));
+    assert_eq!(call(&mut rt, "chunks", &[a.clone(), int(9)]), Ok(arr(vec![a.clone()])));
+    assert_eq!(call(&mut rt, "chunks", &[ints(&[]), int(3)]), Ok(ints(&[])));
+    for &n in &[0, -1] {
+        assert_eq!(kind(&call(&mut rt, "chunks", &[a.clone(), int(n)]).unwrap_err()), "range");
+    }
+    // The chunks are copies.
+    let chunks = call(&mut rt, "chunks", &[a.clone(), int(5)]).unwrap();
+    let chunk = call(&mut rt, "get", &[chunks, int(0)]).unwrap();
+    call(&mut rt, "clear", &[chunk]).unwrap();
+    assert_eq!(a, ints(&[1, 2, 3, 4, 5]));
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use pan_lang_rs::builtins::assert::assertion_report;
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::types::exit;
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, field, int, kind, message, native, s};
+
+// An array nested `depth` levels deep around an int.
+fn nested(depth: usize) -> Value {
+    (0..depth).fold(int(1), |inner, _| arr(vec![inner]))
+}
+
+#[test]
+fn assert_checks_truthiness() {
+    let mut rt = Runtime::new();
+    for v in [Value::Bool(true), int(0), s(""), arr(vec![])].iter() {
+        assert_eq!(call(&mut rt, "assert", std::slice::from_ref(v)), Ok(Value::Nil), "{}", v);
+    }
+    for v in [Value::Bool(false), Value::Nil].iter() {
+        let err = call(&mut rt, "assert", std::slice::from_ref(v)).unwrap_err();
+        assert_eq!(kind(&err), "assertion");
+        assert_eq!(message(&err), "assertion failed");
+    }
+    let err = call(&mut rt, "assert", &[]).unwrap_err();
+    assert_eq!(kind(&err), "assertion");
+    let err = call(&mut rt, "assert", &[Value::Bool(false), s("the sky fell")]).unwrap_err();
+    assert_eq!(message(&err), "the sky fell");
+    let err = call(&mut rt, "assert", &[Value::Bool(true), int(1)]).unwrap_err();
+    assert_eq!(kind(&err), "type");
+}
+
+#[test]
+fn assert_eq_and_ne_render_both_operands() {
+    let mut rt = Runtime::new();
+    let a = arr(vec![int(1), s("two")]);
+    let b = arr(vec![int(1), s("two")]);
+    assert_eq!(call(&mut rt, "assert_eq", &[a.clone(), b.clone()]), Ok(Value::Nil));
+    assert_eq!(call(&mut rt, "assert_ne", &[a.clone(), int(1)]), Ok(Value::Nil));
+    assert_eq!(call(&mut rt, "assert_eq", &[]), Ok(Value::Nil));
+
+    let err = call(&mut rt, "assert_eq", &[a.clone(), s("x")]).unwrap_err();
+    assert_eq!(kind(&err), "assertion");
+    let expected = concat!(
+        "assert_eq failed, the values are not equal\n",
+        "  left: [1, \"two\"]\n",
+        " right: \"x\"",
+    );
+    assert_eq!(message(&err), expected);
+    let data = call(&mut rt, "error_data", &[err]).unwrap();
+    assert_eq!((field(&data, "left"), field(&data, "right")), (s("[1, \"two\"]"), s("\"x\"")));
+
+    let err = call(&mut rt, "assert_ne", &[a, b]).unwrap_err();
+    let expected = "assert_ne failed, the values are equal\n  left: [1, \"two\"]";
+    assert!(message(&err).starts_with(expected), "{}", message(&err));
+    assert!(message(&err).ends_with("\n right: [1, \"two\"]"), "{}", message(&err));
+}
+
+#[test]
+fn deep_operands_are_truncated() {
+    let mut rt = Runtime::new();
+    let err = call(&mut rt, "assert_eq", &[nested(6), nested(2)]).unwrap_err();
+    let data = call(&mut rt, "error_data", std::slice::from_ref(&err)).unwrap();
+    assert_eq!(field(&data, "left"), s("[[[[[...]]]]]"));
+    assert_eq!(field(&data, "right"), s("[[1]]"));
+    assert!(message(&err).ends_with("left: [[[[[...]]]]]\n right: [[1]]"), "{}", message(&err));
+    // Depth 4 is printed completely.
+    let err = call(&mut rt, "assert_eq", &[nested(4), int(0)]).unwrap_err();
+    let data = call(&mut rt, "error_data", &[err]).unwrap();
+    assert_eq!(field(&data, "left"), s("[[[[1]]]]"));
+
+    // Cyclic operands render finitely.
+    let cyclic = arr(vec![]);
+    call(&mut rt, "push", &[cyclic.clone(), cyclic.clone()]).unwrap();
+    let err = call(&mut rt, "assert_eq", &[cyclic.clone(), int(0)]).unwrap_err();
+    assert_eq!(kind(&err), "assertion");
+    call(&mut rt, "clear", &[cyclic]).unwrap();
+}
+
+#[test]
+fn assert_throws_returns_the_thrown_value() {
+    let mut rt = Runtime::new();
+    let thrower = native(|_| Err(s("thrown")));
+    assert_eq!(call(&mut rt, "assert_throws", &[thrower]), Ok(s("thrown")));
+    let dividing = native(|_| Err(Value::error("division_by_zero", "no")));
+    let thrown = call(&mut rt, "assert_throws", &[dividing]).unwrap();
+    assert_eq!(kind(&thrown), "division_by_zero");
+
+    let returning = native(|_| Ok(nested(6)));
+    let err = call(&mut rt, "assert_throws", &[returning]).unwrap_err();
+    assert_eq!(kind(&err), "assertion");
+    assert_eq!(message(&err), "assert_throws failed, the function returned [[[[[...]]]]]");
+
+    // Exits are not caught.
+    let exiting = native(|_| Err(exit::exit_value(3)));
+    let err = call(&mut rt, "assert_throws", &[exiting]).unwrap_err();
+    assert_eq!(exit::exit_code(&err), Some(3));
+
+    assert_eq!(kind(&call(&mut rt, "assert_throws", &[int(1)]).unwrap_err()), "type");
+}
+
+#[test]
+fn fail_always_throws() {
+    let mut rt = Runtime::new();
+    let err = call(&mut rt, "fail", &[s("not yet implemented")]).unwrap_err();
+    assert_eq!(kind(&err), "assertion");
+    assert_eq!(message(&err), "not yet implemented");
+    assert_eq!(kind(&call(&mut rt, "fail", &[]).unwrap_err()), "type");
+}
+
+#[test]
+fn assertion_errors_are_reported() {
+    let mut rt = Runtime::new();
+    let err = call(&mut rt, "assert_eq", &[int(1), int(2)]).unwrap_err();
+    let report = "assert_eq failed, the values are not equal\n  left: 1\n right: 2";
+    assert_eq!(assertion_report(&err).as_deref(), Some(report));
+    let err = call(&mut rt, "fail", &[s("reason")]).unwrap_err();
+    assert_eq!(assertion_report(&err).as_deref(), Some("reason"));
+    // Other throws are not assertion failures.
+    assert_eq!(assertion_report(&Value::error("type", "no")), None);
+    assert_eq!(assertion_report(&s("assertion")), None);
+    assert_eq!(assertion_report(&exit::exit_value(1)), None);
+}
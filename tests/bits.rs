@@ -0,0 +1,84 @@
+mod common;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::value::Value;
+
+use common::{call, int, kind, s};
+
+type BinaryOp = fn(i64, i64) -> i64;
+type ShiftOp = fn(i64, u32) -> i64;
+
+const EDGES: [i64; 8] = [i64::MIN, i64::MIN + 1, -2, -1, 0, 1, 0x5555, i64::MAX];
+
+#[test]
+fn binary_operations_match_i64() {
+    let mut rt = Runtime::new();
+    let ops: [(&str, BinaryOp); 3] =
+        [("bit_and", |a, b| a & b), ("bit_or", |a, b| a | b), ("bit_xor", |a, b| a ^ b)];
+    for &(name, op) in &ops {
+        for &a in &EDGES {
+            for &b in &EDGES {
+                assert_eq!(call(&mut rt, name, &[int(a), int(b)]), Ok(int(op(a, b))), "{}", name);
+            }
+        }
+    }
+}
+
+#[test]
+fn unary_operations_match_i64() {
+    let mut rt = Runtime::new();
+    for &a in &EDGES {
+        assert_eq!(call(&mut rt, "bit_not", &[int(a)]), Ok(int(!a)));
+        assert_eq!(call(&mut rt, "count_ones", &[int(a)]), Ok(int(a.count_ones().into())));
+        assert_eq!(call(&mut rt, "leading_zeros", &[int(a)]), Ok(int(a.leading_zeros().into())));
+    }
+    assert_eq!(call(&mut rt, "bit_not", &[int(0)]), Ok(int(-1)));
+    assert_eq!(call(&mut rt, "count_ones", &[int(-1)]), Ok(int(64)));
+    assert_eq!(call(&mut rt, "leading_zeros", &[int(0)]), Ok(int(64)));
+}
+
+#[test]
+fn shifts_and_rotations_match_i64() {
+    let mut rt = Runtime::new();
+    let ops: [(&str, ShiftOp); 5] = [
+        ("shl", |a, n| a << n),
+        ("shr", |a, n| a >> n),
+        ("shr_logical", |a, n| ((a as u64) >> n) as i64),
+        ("rotate_left", i64::rotate_left),
+        ("rotate_right", i64::rotate_right),
+    ];
+    for &(name, op) in &ops {
+        for &a in &EDGES {
+            for &n in &[0, 1, 7, 62, 63] {
+                let shifted = call(&mut rt, name, &[int(a), int(n.into())]);
+                assert_eq!(shifted, Ok(int(op(a, n))), "{}({}, {})", name, a, n);
+            }
+        }
+    }
+    assert_eq!(call(&mut rt, "shr", &[int(-8), int(1)]), Ok(int(-4)));
+    assert_eq!(call(&mut rt, "shr_logical", &[int(-1), int(63)]), Ok(int(1)));
+}
+
+#[test]
+fn out_of_range_amounts_throw() {
+    let mut rt = Runtime::new();
+    for name in &["shl", "shr", "shr_logical", "rotate_left", "rotate_right"] {
+        for &n in &[-1, 64, i64::MAX, i64::MIN] {
+            let err = call(&mut rt, name, &[int(1), int(n)]).unwrap_err();
+            assert_eq!(kind(&err), "range", "{}({})", name, n);
+        }
+    }
+}
+
+#[test]
+fn non_ints_throw() {
+    let mut rt = Runtime::new();
+    for name in &["bit_and", "bit_or", "bit_xor", "shl", "shr", "shr_logical", "rotate_left"] {
+        assert_eq!(kind(&call(&mut rt, name, &[int(1), s("1")]).unwrap_err()), "type");
+        let float = Value::Float(1.0.into());
+        assert_eq!(kind(&call(&mut rt, name, &[float, int(1)]).unwrap_err()), "type");
+    }
+    for name in &["bit_not", "count_ones", "leading_zeros"] {
+        assert_eq!(kind(&call(&mut rt, name, &[Value::Nil]).unwrap_err()), "type");
+    }
+}
@@ -0,0 +1,752 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use pan_lang_rs::types::bytes::{Bytes, BytesBuilder, BytesError, DecodeError, Utf8Mode};
+use pan_lang_rs::types::rope::Rope;
+
+// A hasher that is not streaming: it remembers where each `write` started.
+#[derive(Default)]
+struct Writes(Vec<Vec<u8>>);
+
+impl Hasher for Writes {
+    fn finish(&self) -> u64 {
+        let mut state = DefaultHasher::new();
+        self.0.hash(&mut state);
+        state.finish()
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.push(bytes.to_vec());
+    }
+}
+
+fn hash_of(bytes: &Bytes) -> u64 {
+    let mut state = Writes::default();
+    bytes.hash(&mut state);
+    state.finish()
+}
+
+fn data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 % 251) as u8).collect()
+}
+
+#[test]
+fn hash_does_not_depend_on_chunking() {
+    let v = data(10_000);
+    let whole = Bytes::from_slice(&v);
+    for &split in &[1, 63, 64, 65, 4097, 9_999] {
+        let parts = Bytes::from_slice(&v[..split]).concat(&Bytes::from_slice(&v[split..]));
+        assert_eq!(parts, whole);
+        assert_eq!(hash_of(&parts), hash_of(&whole), "split at {}", split);
+    }
+    let mut pieces = Bytes::from_slice(&[]);
+    for piece in v.chunks(777) {
+        pieces = pieces.concat(&Bytes::from_slice(piece));
+    }
+    assert!(pieces.chunks().count() > 1);
+    assert_eq!(hash_of(&pieces), hash_of(&whole));
+}
+
+#[test]
+fn hash_distinguishes_contents() {
+    assert_ne!(hash_of(&Bytes::from_slice(&[1, 2])), hash_of(&Bytes::from_slice(&[2, 1])));
+    assert_ne!(hash_of(&Bytes::from_slice(&[])), hash_of(&Bytes::from_slice(&[0])));
+    assert_ne!(hash_of(&Bytes::from_slice(&data(64))), hash_of(&Bytes::from_slice(&data(65))));
+}
+
+#[test]
+fn constructors_agree() {
+    let v = data(300);
+    let from_slice = Bytes::from_slice(&v);
+    let from_vec = Bytes::from_vec(v.clone());
+    assert_eq!(from_slice, from_vec);
+    assert_eq!(from_slice.len(), 300);
+    assert!(!from_slice.is_empty());
+    assert_eq!(from_slice.to_vec(), v);
+    from_vec.as_slice_with(|s| assert_eq!(s, &v[..]));
+
+    let empties = [
+        Bytes::empty(),
+        Bytes::from_slice(&[]),
+        Bytes::from_vec(vec![]),
+        Bytes::default(),
+    ];
+    for empty in &empties {
+        assert_eq!(empty.len(), 0);
+        assert!(empty.is_empty());
+        assert_eq!(empty.get(0), None);
+        assert_eq!(empty.to_vec(), Vec::<u8>::new());
+        empty.as_slice_with(|s| assert!(s.is_empty()));
+        assert_eq!(*empty, Bytes::empty());
+    }
+}
+
+#[test]
+fn get_is_bounds_checked() {
+    let bytes = Bytes::from_slice(&[1, 2, 3]);
+    assert_eq!(bytes.get(0), Some(1));
+    assert_eq!(bytes.get(2), Some(3));
+    assert_eq!(bytes.get(3), None);
+    assert_eq!(bytes.get(usize::MAX), None);
+    assert_eq!(bytes.get_checked(3), Err(BytesError::OutOfBounds { index: 3, len: 3 }));
+}
+
+#[test]
+fn slices_view_the_same_bytes() {
+    let v = data(100);
+    let bytes = Bytes::from_vec(v.clone());
+    assert_eq!(bytes.slice(10..20).unwrap().to_vec(), &v[10..20]);
+    assert_eq!(bytes.slice(0..100).unwrap(), bytes);
+    assert!(Bytes::ptr_eq(&bytes.slice(0..100).unwrap(), &bytes));
+    assert!(bytes.slice(50..50).unwrap().is_empty());
+    assert_eq!(bytes.slice_from(90).unwrap().to_vec(), &v[90..]);
+    assert_eq!(bytes.slice_to(5).unwrap().to_vec(), &v[..5]);
+    assert_eq!(bytes.slice(10..20).unwrap().slice(2..4).unwrap().to_vec(), &v[12..14]);
+    // Slices are views, so they are shared until written to.
+    assert!(bytes.slice(10..20).unwrap().is_shared());
+
+    let (start, end) = (20, 10);
+    assert_eq!(bytes.slice(start..end), Err(BytesError::InvertedRange { start, end }));
+    assert_eq!(bytes.slice(90..101), Err(BytesError::OutOfBounds { index: 101, len: 100 }));
+    assert_eq!(bytes.slice_from(101), Err(BytesError::OutOfBounds { index: 101, len: 100 }));
+    assert_eq!(bytes.slice_to(101), Err(BytesError::OutOfBounds { index: 101, len: 100 }));
+}
+
+#[test]
+fn writes_are_not_visible_through_overlapping_views() {
+    let original = Bytes::from_vec(data(100));
+    let mut a = original.slice(10..60).unwrap();
+    let b = original.slice(40..90).unwrap();
+    a.set(35, 0xff);
+    assert_eq!(a.get(35), Some(0xff));
+    assert_eq!(b.get(5), Some(data(100)[45]));
+    assert_eq!(original.to_vec(), data(100));
+
+    let mut whole = original.clone();
+    whole.set(0, 0xff);
+    assert_eq!(original.get(0), Some(0));
+    assert_eq!(whole.get(0), Some(0xff));
+}
+
+#[test]
+fn to_vec_copies() {
+    let bytes = Bytes::from_vec(data(10));
+    let mut v = bytes.to_vec();
+    v[0] = 0xff;
+    assert_eq!(bytes.get(0), Some(0));
+}
+
+// The given data as a concatenation of pieces of the given sizes (and a last piece holding the
+// rest).
+fn pieces(v: &[u8], sizes: &[usize]) -> Bytes {
+    let mut bytes = Bytes::empty();
+    let mut rest = v;
+    for &size in sizes {
+        let (piece, tail) = rest.split_at(size);
+        bytes = bytes.concat(&Bytes::from_slice(piece));
+        rest = tail;
+    }
+    bytes.concat(&Bytes::from_slice(rest))
+}
+
+#[test]
+fn chunked_and_flat_bytes_are_indistinguishable() {
+    let v = data(3_000);
+    let flat = Bytes::from_slice(&v);
+    let chunked = pieces(&v, &[300, 1, 700, 257, 999]);
+    assert!(chunked.chunks().count() > 1);
+    assert_eq!(chunked, flat);
+    assert_eq!(chunked.cmp(&flat), std::cmp::Ordering::Equal);
+    assert_eq!(hash_of(&chunked), hash_of(&flat));
+    assert_eq!(chunked.len(), flat.len());
+    assert_eq!(chunked.to_vec(), v);
+    assert!(chunked.iter().eq(v.iter().copied()));
+    for &i in &[0, 299, 300, 301, 1_000, 2_999] {
+        assert_eq!(chunked.get(i), Some(v[i]));
+    }
+    assert_eq!(chunked.get(3_000), None);
+    chunked.as_slice_with(|s| assert_eq!(s, &v[..]));
+    assert_eq!(chunked.slice(250..1_300).unwrap().to_vec(), &v[250..1_300]);
+    assert_eq!(format!("{:?}", chunked), format!("{:?}", flat));
+
+    let compact = chunked.compact();
+    assert_eq!(compact.chunks().count(), 1);
+    assert_eq!(compact, flat);
+    assert!(Bytes::ptr_eq(&compact.compact(), &compact));
+
+    let mut other = v.clone();
+    other[1_500] ^= 1;
+    let different = pieces(&other, &[1_000]);
+    assert_ne!(chunked, different);
+    assert_eq!(chunked.cmp(&different), v.cmp(&other));
+}
+
+#[test]
+fn small_concatenations_are_flattened() {
+    let bytes = Bytes::from_slice(b"abc").concat(&Bytes::from_slice(b"def"));
+    assert_eq!(bytes.chunks().count(), 1);
+    assert_eq!(bytes.to_vec(), b"abcdef");
+    let empty = Bytes::empty();
+    assert!(Bytes::ptr_eq(&bytes.concat(&empty), &bytes));
+    assert!(Bytes::ptr_eq(&empty.concat(&bytes), &bytes));
+}
+
+fn address(bytes: &Bytes) -> *const u8 {
+    bytes.as_slice_with(|s| s.as_ptr())
+}
+
+#[test]
+fn unique_bytes_are_written_in_place() {
+    let mut bytes = Bytes::from_vec(data(100));
+    assert!(!bytes.is_shared());
+    let before = address(&bytes);
+    bytes.set(0, 0xff);
+    bytes.fill(10..20, 1).unwrap();
+    bytes.copy_within(0..10, 50).unwrap();
+    bytes.write_u32_le(90, 7).unwrap();
+    assert_eq!(address(&bytes), before);
+    assert_eq!(bytes.get(0), Some(0xff));
+    assert_eq!(bytes.get(50), Some(0xff));
+}
+
+#[test]
+fn shared_bytes_are_copied_before_writing() {
+    let original = Bytes::from_vec(data(100));
+    let mut a = original.slice(0..50).unwrap();
+    let mut b = original.slice(0..50).unwrap();
+    assert!(a.is_shared());
+    a.set(0, 0xff);
+    b.set(0, 0xee);
+    assert_eq!(a.get(0), Some(0xff));
+    assert_eq!(b.get(0), Some(0xee));
+    assert_eq!(original.get(0), Some(0));
+    // The copies are unique, so they are written in place from now on.
+    assert!(!a.is_shared());
+    let before = address(&a);
+    a.set(1, 0xff);
+    assert_eq!(address(&a), before);
+
+    // A clone shares the buffer, so writing to either copies.
+    let mut c = a.clone();
+    assert!(a.is_shared());
+    c.set(1, 0);
+    assert_ne!(address(&c), before);
+    assert_eq!(a.get(1), Some(0xff));
+    // Once the clone is gone, the buffer is unique again.
+    drop(c);
+    assert!(!a.is_shared());
+
+    // Chunked bytes are compacted into a unique buffer on the first write.
+    let large = Bytes::from_vec(data(300));
+    let mut chunked = large.concat(&large);
+    assert!(chunked.is_shared());
+    chunked.set(350, 0xff);
+    assert_eq!(chunked.chunks().count(), 1);
+    assert_eq!(chunked.get(350), Some(0xff));
+    assert_eq!(large.get(50), Some(data(300)[50]));
+}
+
+#[test]
+fn checked_access_errors() {
+    let mut bytes = Bytes::from_vec(data(10));
+    fn oob<T>(index: usize) -> Result<T, BytesError> {
+        Err(BytesError::OutOfBounds { index, len: 10 })
+    }
+    assert_eq!(bytes.get_checked(10), oob(10));
+    assert_eq!(bytes.set_checked(10, 0), oob(10));
+    assert_eq!(bytes.fill(5..11, 0), oob(11));
+    let (start, end) = (6, 5);
+    assert_eq!(bytes.fill(start..end, 0), Err(BytesError::InvertedRange { start, end }));
+    assert_eq!(bytes.copy_within(5..11, 0), oob(11));
+    assert_eq!(bytes.copy_within(start..end, 0), Err(BytesError::InvertedRange { start, end }));
+    assert_eq!(bytes.copy_within(0..5, 6), oob(11));
+    assert_eq!(bytes.copy_within(0..5, usize::MAX), oob(usize::MAX));
+    // Failed operations leave the bytes unchanged.
+    assert_eq!(bytes.to_vec(), data(10));
+
+    assert_eq!(bytes.set_checked(9, 0xff), Ok(()));
+    assert_eq!(bytes.get_checked(9), Ok(0xff));
+    assert_eq!(bytes.fill(10..10, 0), Ok(()));
+    assert_eq!(bytes.copy_within(0..0, 10), Ok(()));
+    assert_eq!(bytes.copy_within(0..5, 5), Ok(()));
+}
+
+#[test]
+#[should_panic(expected = "index 3 is out of bounds for bytes of length 3")]
+fn set_panics_out_of_bounds() {
+    Bytes::from_slice(&[1, 2, 3]).set(3, 0);
+}
+
+#[test]
+fn copy_within_agrees_with_slices() {
+    let v = data(600);
+    let cases = [(0..300, 100), (100..400, 0), (0..300, 300), (50..60, 50), (0..600, 0)];
+    for (src, dest) in cases.iter().cloned() {
+        let mut expected = v.clone();
+        expected.copy_within(src.clone(), dest);
+        // Both contiguous and chunked bytes.
+        for mut bytes in [Bytes::from_slice(&v), pieces(&v, &[299])] {
+            bytes.copy_within(src.clone(), dest).unwrap();
+            assert_eq!(bytes.to_vec(), expected, "{:?} to {}", src, dest);
+        }
+    }
+}
+
+#[test]
+fn fill_across_chunks() {
+    let v = data(600);
+    let original = pieces(&v, &[300]);
+    assert_eq!(original.chunks().count(), 2);
+    let mut bytes = original.clone();
+    bytes.fill(290..310, 0xff).unwrap();
+    let mut expected = v.clone();
+    for b in &mut expected[290..310] {
+        *b = 0xff;
+    }
+    assert_eq!(bytes.to_vec(), expected);
+    assert_eq!(original.to_vec(), v);
+}
+
+// The test vectors of RFC 4648, section 10.
+const RFC_4648: &[(&str, &str, &str)] = &[
+    ("", "", ""),
+    ("f", "Zg==", "66"),
+    ("fo", "Zm8=", "666F"),
+    ("foo", "Zm9v", "666F6F"),
+    ("foob", "Zm9vYg==", "666F6F62"),
+    ("fooba", "Zm9vYmE=", "666F6F6261"),
+    ("foobar", "Zm9vYmFy", "666F6F626172"),
+];
+
+#[test]
+fn encodings_match_the_rfc_vectors() {
+    for &(input, base64, base16) in RFC_4648 {
+        let bytes = Bytes::from_slice(input.as_bytes());
+        assert_eq!(bytes.to_base64().to_string(), base64);
+        assert_eq!(Bytes::from_base64(base64).unwrap(), bytes);
+        assert_eq!(bytes.to_hex().to_string(), base16.to_lowercase());
+        assert_eq!(Bytes::from_hex(base16).unwrap(), bytes);
+        assert_eq!(Bytes::from_hex(&base16.to_lowercase()).unwrap(), bytes);
+    }
+}
+
+#[test]
+fn hex_decoding_errors() {
+    assert_eq!(Bytes::from_hex("abc"), Err(DecodeError::OddLength { len: 3 }));
+    assert_eq!(Bytes::from_hex("0g"), Err(DecodeError::InvalidChar { offset: 1 }));
+    assert_eq!(Bytes::from_hex("00 0"), Err(DecodeError::InvalidChar { offset: 2 }));
+    assert_eq!(Bytes::from_hex("ä"), Err(DecodeError::InvalidChar { offset: 0 }));
+}
+
+#[test]
+fn base64_decoding_is_strict() {
+    // Missing padding and whitespace.
+    assert_eq!(Bytes::from_base64("Zg"), Err(DecodeError::InvalidLength { len: 2 }));
+    assert_eq!(Bytes::from_base64("Zm9v\n"), Err(DecodeError::InvalidLength { len: 5 }));
+    assert_eq!(Bytes::from_base64("Zm 9"), Err(DecodeError::InvalidChar { offset: 2 }));
+    // The url-safe alphabet is not the standard one.
+    assert_eq!(Bytes::from_base64("-_==").unwrap_err(), DecodeError::InvalidChar { offset: 0 });
+    // Padding only at the very end, and at most two chars of it.
+    assert_eq!(Bytes::from_base64("Zg==Zm9v"), Err(DecodeError::InvalidPadding { offset: 2 }));
+    assert_eq!(Bytes::from_base64("Z==="), Err(DecodeError::InvalidPadding { offset: 1 }));
+    assert_eq!(Bytes::from_base64("Zg=v"), Err(DecodeError::InvalidPadding { offset: 2 }));
+    // Nonzero unused bits: "Zh==" would decode to "f" as well.
+    assert_eq!(Bytes::from_base64("Zh=="), Err(DecodeError::InvalidChar { offset: 1 }));
+    assert_eq!(Bytes::from_base64("Zm9="), Err(DecodeError::InvalidChar { offset: 2 }));
+}
+
+// A deterministic stream of pseudo-random numbers (a 64-bit linear congruential generator).
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        self.0 >> 33
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+}
+
+#[test]
+fn encodings_round_trip() {
+    let mut rng = Lcg(356);
+    for _ in 0..300 {
+        let len = (rng.next() % 1_000) as usize;
+        let v = rng.bytes(len);
+        let split = if len == 0 { 0 } else { (rng.next() as usize) % len };
+        for bytes in [Bytes::from_slice(&v), pieces(&v, &[split])] {
+            let hex = bytes.to_hex().to_string();
+            assert_eq!(hex.len(), 2 * len);
+            assert_eq!(Bytes::from_hex(&hex).unwrap().to_vec(), v);
+            let base64 = bytes.to_base64().to_string();
+            assert_eq!(base64.len(), len.div_ceil(3) * 4);
+            assert_eq!(Bytes::from_base64(&base64).unwrap().to_vec(), v);
+        }
+    }
+}
+
+// Checks reading and writing `$t` at every offset of contiguous and chunked bytes, against
+// `from_le_bytes` and friends on slices.
+macro_rules! check_endian {
+    ($t:ty, $read_le:ident, $read_be:ident, $write_le:ident, $write_be:ident) => {{
+        const N: usize = std::mem::size_of::<$t>();
+        let v = data(40);
+        // Chunk boundaries at 13 and 17, so that reads straddle them.
+        for bytes in [Bytes::from_slice(&v), pieces(&v, &[13, 4])] {
+            for offset in 0..=v.len() - N {
+                let mut array = [0; N];
+                array.copy_from_slice(&v[offset..offset + N]);
+                // Compared as bytes, so that floats are compared bit for bit.
+                let le = bytes.$read_le(offset).unwrap();
+                assert_eq!(le.to_le_bytes(), <$t>::from_le_bytes(array).to_le_bytes());
+                let be = bytes.$read_be(offset).unwrap();
+                assert_eq!(be.to_le_bytes(), <$t>::from_be_bytes(array).to_le_bytes());
+
+                let value = <$t>::from_le_bytes([0xa5; N]);
+                let mut written = bytes.clone();
+                written.$write_le(offset, value).unwrap();
+                let mut expected = v.clone();
+                expected[offset..offset + N].copy_from_slice(&value.to_le_bytes());
+                assert_eq!(written.to_vec(), expected);
+                written.$write_be(offset, value).unwrap();
+                expected[offset..offset + N].copy_from_slice(&value.to_be_bytes());
+                assert_eq!(written.to_vec(), expected);
+            }
+
+            // Exactly at the end, and one past it.
+            let end = v.len() - N;
+            assert!(bytes.$read_le(end).is_ok());
+            let err = BytesError::OutOfBounds { index: v.len() + 1, len: v.len() };
+            assert_eq!(bytes.$read_le(end + 1).unwrap_err(), err);
+            assert_eq!(bytes.$read_be(end + 1).unwrap_err(), err);
+            let mut written = bytes.clone();
+            let zero = <$t>::from_le_bytes([0; N]);
+            assert_eq!(written.$write_le(end + 1, zero), Err(err.clone()));
+            assert_eq!(written.$write_be(end + 1, zero), Err(err.clone()));
+            assert_eq!(written.to_vec(), v);
+        }
+    }};
+}
+
+#[test]
+fn endian_reads_and_writes() {
+    check_endian!(u16, read_u16_le, read_u16_be, write_u16_le, write_u16_be);
+    check_endian!(u32, read_u32_le, read_u32_be, write_u32_le, write_u32_be);
+    check_endian!(u64, read_u64_le, read_u64_be, write_u64_le, write_u64_be);
+    check_endian!(i64, read_i64_le, read_i64_be, write_i64_le, write_i64_be);
+    check_endian!(f64, read_f64_le, read_f64_be, write_f64_le, write_f64_be);
+
+    let mut bytes = Bytes::from_slice(&[1, 2]);
+    assert_eq!(bytes.read_u8(1), Ok(2));
+    assert_eq!(bytes.read_u8(2), Err(BytesError::OutOfBounds { index: 2, len: 2 }));
+    assert_eq!(bytes.write_u8(1, 7), Ok(()));
+    assert_eq!(bytes.to_vec(), [1, 7]);
+    assert_eq!(
+        bytes.read_u64_le(usize::MAX),
+        Err(BytesError::OutOfBounds { index: usize::MAX, len: 2 })
+    );
+}
+
+#[test]
+fn integers_encode_at_every_width() {
+    for width in 1..=8 {
+        let max = if width == 8 { i64::MAX } else { (1i64 << (8 * width)) - 1 };
+        let min = if width == 8 { i64::MIN } else { -(1i64 << (8 * width - 1)) };
+        for &value in &[0, 1, -1, max, min, max / 3] {
+            let le = Bytes::from_int_le(value, width).unwrap();
+            let be = Bytes::from_int_be(value, width).unwrap();
+            assert_eq!(le.to_vec(), &value.to_le_bytes()[..width]);
+            assert_eq!(be.to_vec(), &value.to_be_bytes()[8 - width..]);
+        }
+        if width < 8 {
+            for &value in &[max + 1, min - 1] {
+                let err = Err(BytesError::IntOutOfRange { value, width });
+                assert_eq!(Bytes::from_int_le(value, width), err);
+                assert_eq!(Bytes::from_int_be(value, width), err);
+            }
+        }
+    }
+    for &width in &[0, 9] {
+        assert_eq!(Bytes::from_int_le(0, width), Err(BytesError::InvalidWidth { width }));
+        assert_eq!(Bytes::from_int_be(0, width), Err(BytesError::InvalidWidth { width }));
+    }
+}
+
+fn find_oracle(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    (from..=haystack.len()).find(|&i| haystack[i..].starts_with(needle))
+}
+
+fn rfind_oracle(haystack: &[u8], needle: &[u8], to: usize) -> Option<usize> {
+    (0..=to).rev().find(|&i| haystack[i..to].starts_with(needle))
+}
+
+#[test]
+fn search_agrees_with_slices() {
+    let mut rng = Lcg(358);
+    for _ in 0..100 {
+        // Few distinct bytes, so that there are many partial matches.
+        let v: Vec<u8> = (0..600).map(|_| (rng.next() % 3) as u8).collect();
+        let mut sizes = vec![];
+        let mut total = 0;
+        while total < 450 {
+            let size = 1 + (rng.next() % 150) as usize;
+            sizes.push(size);
+            total += size;
+        }
+        let bytes = pieces(&v, &sizes);
+        assert!(bytes.chunks().count() > 1);
+        assert!(bytes.iter().eq(v.iter().copied()));
+
+        // Needles taken from the data, so that they straddle chunk boundaries.
+        let mut needles = vec![vec![], vec![3], vec![0, 1, 2, 0]];
+        for &len in &[1, 2, 5, 17, 300] {
+            let start = (rng.next() as usize) % (v.len() - len);
+            needles.push(v[start..start + len].to_vec());
+        }
+        for needle in &needles {
+            for &position in &[0, 1, 299, 300, 301, 599, 600] {
+                assert_eq!(bytes.find(needle, position), find_oracle(&v, needle, position));
+                assert_eq!(bytes.rfind(needle, position), rfind_oracle(&v, needle, position));
+            }
+            assert_eq!(bytes.starts_with(needle), v.starts_with(needle));
+            assert_eq!(bytes.ends_with(needle), v.ends_with(needle));
+            if !needle.is_empty() {
+                let split: Vec<_> = bytes.split(needle).unwrap().map(|b| b.to_vec()).collect();
+                let mut expected = vec![];
+                let mut start = 0;
+                while let Some(i) = find_oracle(&v, needle, start) {
+                    expected.push(v[start..i].to_vec());
+                    start = i + needle.len();
+                }
+                expected.push(v[start..].to_vec());
+                assert_eq!(split, expected);
+            }
+        }
+        assert!(bytes.starts_with(&v));
+        assert!(bytes.ends_with(&v));
+        let mut longer = v.clone();
+        longer.push(0);
+        assert!(!bytes.starts_with(&longer));
+        assert!(!bytes.ends_with(&longer));
+    }
+}
+
+#[test]
+fn splitting_on_an_empty_separator_errors() {
+    assert!(matches!(Bytes::from_slice(b"abc").split(b""), Err(BytesError::EmptyPattern)));
+    let parts: Vec<_> = Bytes::from_slice(b",a,,b,")
+        .split(b",")
+        .unwrap()
+        .map(|b| b.to_vec())
+        .collect();
+    assert_eq!(parts, [&b""[..], b"a", b"", b"b", b""]);
+}
+
+#[test]
+fn builders_agree_with_direct_construction() {
+    let mut rng = Lcg(359);
+    for _ in 0..200 {
+        let mut builder = if rng.next().is_multiple_of(2) {
+            BytesBuilder::new()
+        } else {
+            BytesBuilder::with_capacity((rng.next() % 100) as usize)
+        };
+        let mut expected = vec![];
+        for _ in 0..rng.next() % 20 {
+            match rng.next() % 6 {
+                0 => {
+                    let b = rng.next() as u8;
+                    builder.push(b);
+                    expected.push(b);
+                }
+                1 => {
+                    let len = (rng.next() % 50) as usize;
+                    let v = rng.bytes(len);
+                    builder.extend_from_slice(&v);
+                    expected.extend_from_slice(&v);
+                }
+                2 => {
+                    // Both small and large byte strings, contiguous or not.
+                    let len = (rng.next() % 1_000) as usize;
+                    let v = rng.bytes(len);
+                    builder.push_bytes(&pieces(&v, &[len / 2]));
+                    expected.extend_from_slice(&v);
+                }
+                3 => {
+                    let n = rng.next() as u32;
+                    builder.write_u32_le(n);
+                    expected.extend_from_slice(&n.to_le_bytes());
+                }
+                4 => {
+                    let n = rng.next() as u16;
+                    builder.write_u16_be(n);
+                    expected.extend_from_slice(&n.to_be_bytes());
+                }
+                _ => {
+                    let n = rng.next() as f64 / 3.0;
+                    builder.write_f64_be(n);
+                    expected.extend_from_slice(&n.to_be_bytes());
+                }
+            }
+            assert_eq!(builder.len(), expected.len());
+        }
+        assert_eq!(builder.is_empty(), expected.is_empty());
+        let bytes = builder.finish();
+        assert_eq!(bytes, Bytes::from_slice(&expected));
+        assert_eq!(bytes.to_vec(), expected);
+    }
+}
+
+#[test]
+fn builders_share_large_byte_strings() {
+    let large = Bytes::from_vec(data(1 << 20));
+    let mut builder = BytesBuilder::new();
+    builder.extend_from_slice(b"header");
+    builder.push_bytes(&large);
+    builder.extend_from_slice(b"trailer");
+    let bytes = builder.finish();
+    assert_eq!(bytes.len(), (1 << 20) + 13);
+    assert_eq!(bytes.chunks().count(), 3);
+    let shared = bytes.chunks().nth(1).unwrap();
+    assert_eq!(shared.len(), 1 << 20);
+    assert_eq!(shared.as_ptr(), address(&large));
+    assert!(large.is_shared());
+}
+
+// Invalid sequences: a lone continuation byte, an overlong encoding, a surrogate, a code point
+// beyond U+10FFFF and a truncated sequence followed by an ascii byte.
+const INVALID: &[&[u8]] = &[
+    b"a\x80b",
+    b"a\xc0\xafb",
+    b"a\xed\xa0\x80b",
+    b"a\xf4\x90\x80\x80b",
+    b"a\xe2\x82b",
+];
+
+#[test]
+fn strict_decoding() {
+    let bytes = Bytes::from_slice("aä€𝄞".as_bytes());
+    let (text, rest) = bytes.decode_utf8(Utf8Mode::Strict).unwrap();
+    assert_eq!(text.to_string(), "aä€𝄞");
+    assert!(rest.is_empty());
+    for &input in INVALID {
+        let result = Bytes::from_slice(input).decode_utf8(Utf8Mode::Strict);
+        assert_eq!(result.unwrap_err(), DecodeError::InvalidUtf8 { offset: 1 }, "{:?}", input);
+    }
+    // An incomplete sequence at the end is an error, too.
+    let result = Bytes::from_slice(b"ab\xf0\x9d").decode_utf8(Utf8Mode::Strict);
+    assert_eq!(result.unwrap_err(), DecodeError::InvalidUtf8 { offset: 2 });
+}
+
+#[test]
+fn replacing_decoding_agrees_with_from_utf8_lossy() {
+    let mut inputs: Vec<Vec<u8>> = INVALID.iter().map(|input| input.to_vec()).collect();
+    inputs.push(b"ab\xf0\x9d".to_vec());
+    inputs.push(b"\xff\xfe".to_vec());
+    let mut rng = Lcg(360);
+    for _ in 0..100 {
+        let len = (rng.next() % 600) as usize;
+        // Mostly valid text, with some random bytes in between.
+        let mut v = vec![];
+        while v.len() < len {
+            if rng.next().is_multiple_of(10) {
+                v.push(rng.next() as u8);
+            } else {
+                v.extend_from_slice("a€𝄞".as_bytes());
+            }
+        }
+        inputs.push(v);
+    }
+    for input in &inputs {
+        let expected = String::from_utf8_lossy(input);
+        let split = input.len() / 2;
+        for bytes in [Bytes::from_slice(input), pieces(input, &[split])] {
+            let (text, rest) = bytes.decode_utf8(Utf8Mode::Replace).unwrap();
+            assert_eq!(text.to_string(), expected);
+            assert!(rest.is_empty());
+        }
+    }
+}
+
+#[test]
+fn prefix_decoding_reassembles_split_chars() {
+    let input = "ab𝄞c€".as_bytes();
+    // Every split point, including ones inside the four-byte and the three-byte char.
+    for split in 0..=input.len() {
+        let first = Bytes::from_slice(&input[..split]);
+        let second = Bytes::from_slice(&input[split..]);
+        let (head, rest) = first.decode_utf8(Utf8Mode::Prefix).unwrap();
+        assert!(rest.len() < 4);
+        let (tail, rest) = rest.concat(&second).decode_utf8(Utf8Mode::Prefix).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(head.concat(&tail).to_string(), "ab𝄞c€");
+    }
+
+    let (text, rest) = Bytes::from_slice(b"ab\xf0\x9d").decode_utf8(Utf8Mode::Prefix).unwrap();
+    assert_eq!(text.to_string(), "ab");
+    assert_eq!(rest.to_vec(), b"\xf0\x9d");
+    // Invalid sequences are still errors.
+    for &input in INVALID {
+        let result = Bytes::from_slice(input).decode_utf8(Utf8Mode::Prefix);
+        assert_eq!(result.unwrap_err(), DecodeError::InvalidUtf8 { offset: 1 }, "{:?}", input);
+    }
+}
+
+#[test]
+fn encoding_text() {
+    let text = Rope::from_str(&"ä".repeat(200)).concat(&Rope::from_str(&"€".repeat(200)));
+    let bytes = Bytes::encode_utf8_of(&text);
+    assert_eq!(bytes.to_vec(), text.to_string().into_bytes());
+    let (decoded, _) = bytes.decode_utf8(Utf8Mode::Strict).unwrap();
+    assert_eq!(decoded, text);
+}
+
+fn dump_sample() -> Bytes {
+    let mut v = b"Hello, pan!".to_vec();
+    v.extend_from_slice(&[0x00, 0x01, 0x7f, 0x80, 0xff, b'\t', b'\n']);
+    v.extend_from_slice(b"~ 0123456789");
+    Bytes::from_vec(v)
+}
+
+#[test]
+fn hexdump_layout() {
+    let bytes = dump_sample();
+    assert_eq!(
+        bytes.hexdump(usize::MAX),
+        "00000000  48 65 6c 6c 6f 2c 20 70  61 6e 21 00 01 7f 80 ff  |Hello, pan!.....|\n\
+         00000010  09 0a 7e 20 30 31 32 33  34 35 36 37 38 39        |..~ 0123456789|\n\
+         0000001e\n"
+    );
+    assert_eq!(
+        bytes.hexdump(16),
+        "00000000  48 65 6c 6c 6f 2c 20 70  61 6e 21 00 01 7f 80 ff  |Hello, pan!.....|\n\
+         ... (14 more bytes)\n\
+         0000001e\n"
+    );
+    assert_eq!(bytes.hexdump(0), "... (30 more bytes)\n0000001e\n");
+    assert_eq!(Bytes::empty().hexdump(16), "00000000\n");
+    // Chunking does not show.
+    assert_eq!(pieces(&bytes.to_vec(), &[7]).hexdump(20), bytes.hexdump(20));
+}
+
+#[test]
+fn debug_formatting() {
+    let bytes = dump_sample();
+    assert_eq!(
+        format!("{:?}", bytes),
+        r#"b"Hello, pan!\x00\x01\x7f\x80\xff\t\n~ 0123456789""#
+    );
+    assert_eq!(format!("{:?}", Bytes::empty()), r#"b"""#);
+    assert_eq!(format!("{:#?}", bytes), bytes.hexdump(usize::MAX));
+
+    let long = bytes.concat(&bytes);
+    assert_eq!(
+        format!("{:?}", long),
+        "b\"48 65 6c 6c 6f 2c 20 70 61 6e 21 00 01 7f 80 ff…\" (60 bytes)"
+    );
+}
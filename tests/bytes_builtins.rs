@@ -0,0 +1,200 @@
+mod common;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::types::bytes::Bytes;
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, field, float, int, kind, message, s};
+
+fn b(bytes: &[u8]) -> Value {
+    Value::Bytes(Bytes::from_slice(bytes))
+}
+
+#[test]
+fn basics() {
+    let mut rt = Runtime::new();
+    let data = b(&[1, 2, 3, 2, 3]);
+    assert_eq!(call(&mut rt, "bytes_len", std::slice::from_ref(&data)), Ok(int(5)));
+    assert_eq!(call(&mut rt, "bytes_get", &[data.clone(), int(4)]), Ok(int(3)));
+    let set = call(&mut rt, "bytes_set", &[data.clone(), int(0), int(9)]);
+    assert_eq!(set, Ok(b(&[9, 2, 3, 2, 3])));
+    assert_eq!(call(&mut rt, "bytes_slice", &[data.clone(), int(1), int(3)]), Ok(b(&[2, 3])));
+    assert_eq!(call(&mut rt, "bytes_slice", &[data.clone(), int(3)]), Ok(b(&[2, 3])));
+    assert_eq!(call(&mut rt, "bytes_concat", &[b(&[1]), b(&[2])]), Ok(b(&[1, 2])));
+    assert_eq!(call(&mut rt, "bytes_find", &[data.clone(), b(&[2, 3])]), Ok(int(1)));
+    assert_eq!(call(&mut rt, "bytes_find", &[data.clone(), b(&[2, 3]), int(2)]), Ok(int(3)));
+    assert_eq!(call(&mut rt, "bytes_find", &[data.clone(), b(&[7])]), Ok(Value::Nil));
+    let parts = call(&mut rt, "bytes_split", &[data.clone(), b(&[2])]).unwrap();
+    assert_eq!(parts, arr(vec![b(&[1]), b(&[3]), b(&[3])]));
+    let filled = call(&mut rt, "bytes_fill", &[data.clone(), int(1), int(4), int(0)]);
+    assert_eq!(filled, Ok(b(&[1, 0, 0, 0, 3])));
+    let copied = call(&mut rt, "bytes_copy_within", &[data.clone(), int(0), int(3), int(2)]);
+    assert_eq!(copied, Ok(b(&[1, 2, 1, 2, 3])));
+    // The argument is left as it was.
+    assert_eq!(data, b(&[1, 2, 3, 2, 3]));
+}
+
+#[test]
+fn encodings() {
+    let mut rt = Runtime::new();
+    let data = b(&[0, 0xab, 0xff]);
+    assert_eq!(call(&mut rt, "bytes_to_hex", std::slice::from_ref(&data)), Ok(s("00abff")));
+    assert_eq!(call(&mut rt, "bytes_from_hex", &[s("00ABff")]), Ok(data.clone()));
+    assert_eq!(call(&mut rt, "bytes_to_base64", std::slice::from_ref(&data)), Ok(s("AKv/")));
+    assert_eq!(call(&mut rt, "bytes_from_base64", &[s("AKv/")]), Ok(data));
+    assert_eq!(call(&mut rt, "bytes_from_int_le", &[int(258), int(2)]), Ok(b(&[2, 1])));
+    assert_eq!(call(&mut rt, "bytes_from_int_be", &[int(-2), int(2)]), Ok(b(&[0xff, 0xfe])));
+    let encoded = call(&mut rt, "str_encode_utf8", &[s("añ")]).unwrap();
+    assert_eq!(encoded, b(&[b'a', 0xc3, 0xb1]));
+    assert_eq!(call(&mut rt, "bytes_decode_utf8", &[encoded]), Ok(s("añ")));
+    let broken = b(&[b'a', 0xff, b'b', 0xc3]);
+    let replaced = call(&mut rt, "bytes_decode_utf8", &[broken, s("replace")]);
+    assert_eq!(replaced, Ok(s("a\u{fffd}b\u{fffd}")));
+    let prefix = call(&mut rt, "bytes_decode_utf8", &[b(&[b'a', 0xc3]), s("prefix")]).unwrap();
+    assert_eq!(field(&prefix, "text"), s("a"));
+    assert_eq!(field(&prefix, "rest"), b(&[0xc3]));
+}
+
+#[test]
+fn endian_family() {
+    let mut rt = Runtime::new();
+    let zero = b(&[0; 8]);
+    let cases: &[(&str, &str, Value, &[u8])] = &[
+        ("bytes_write_u16_le", "bytes_read_u16_le", int(0x0102), &[2, 1, 0, 0, 0, 0, 0, 0]),
+        ("bytes_write_u16_be", "bytes_read_u16_be", int(0x0102), &[1, 2, 0, 0, 0, 0, 0, 0]),
+        ("bytes_write_u32_le", "bytes_read_u32_le", int(0x01020304), &[4, 3, 2, 1, 0, 0, 0, 0]),
+        ("bytes_write_u32_be", "bytes_read_u32_be", int(0x01020304), &[1, 2, 3, 4, 0, 0, 0, 0]),
+        ("bytes_write_u64_le", "bytes_read_u64_le", int(1), &[1, 0, 0, 0, 0, 0, 0, 0]),
+        ("bytes_write_u64_be", "bytes_read_u64_be", int(1), &[0, 0, 0, 0, 0, 0, 0, 1]),
+        ("bytes_write_i64_le", "bytes_read_i64_le", int(-1), &[0xff; 8]),
+        ("bytes_write_i64_be", "bytes_read_i64_be", int(-256),
+            &[255, 255, 255, 255, 255, 255, 255, 0]),
+        ("bytes_write_f64_le", "bytes_read_f64_le", float(1.0), &[0, 0, 0, 0, 0, 0, 0xf0, 0x3f]),
+        ("bytes_write_f64_be", "bytes_read_f64_be", float(1.0), &[0x3f, 0xf0, 0, 0, 0, 0, 0, 0]),
+        ("bytes_write_u8", "bytes_read_u8", int(7), &[7, 0, 0, 0, 0, 0, 0, 0]),
+    ];
+    for (write, read, n, expected) in cases {
+        let written = call(&mut rt, write, &[zero.clone(), int(0), n.clone()]).unwrap();
+        assert_eq!(written, b(expected), "{}", write);
+        assert_eq!(call(&mut rt, read, &[written, int(0)]).as_ref(), Ok(n), "{}", read);
+    }
+}
+
+// Every builtin with an argument of the wrong type, and with each way it can fail otherwise.
+#[test]
+fn throw_cases() {
+    let mut rt = Runtime::new();
+    let data = b(&[1, 2, 3]);
+    let cases: Vec<(&str, Vec<Value>, &str, &str)> = vec![
+        ("bytes_len", vec![s("x")], "type", "bytes_len expects bytes, got string"),
+        ("bytes_get", vec![data.clone(), int(3)], "range",
+            "bytes_get failed: index 3 is out of bounds for bytes of length 3"),
+        ("bytes_get", vec![data.clone(), int(-1)], "range",
+            "bytes_get expects a non-negative int, got -1"),
+        ("bytes_set", vec![data.clone(), int(0), int(256)], "range",
+            "bytes_set expects a byte (0 to 255), got 256"),
+        ("bytes_set", vec![data.clone(), int(3), int(0)], "range",
+            "bytes_set failed: index 3 is out of bounds for bytes of length 3"),
+        ("bytes_slice", vec![data.clone(), int(2), int(1)], "range",
+            "bytes_slice failed: range start 2 is greater than range end 1"),
+        ("bytes_slice", vec![data.clone(), int(4)], "range",
+            "bytes_slice failed: index 4 is out of bounds for bytes of length 3"),
+        ("bytes_concat", vec![data.clone(), int(1)], "type",
+            "bytes_concat expects bytes, got int"),
+        ("bytes_find", vec![data.clone(), s("x")], "type", "bytes_find expects bytes, got string"),
+        ("bytes_split", vec![data.clone(), b(&[])], "range",
+            "bytes_split failed: the separator must not be empty"),
+        ("bytes_fill", vec![data.clone(), int(0), int(4), int(0)], "range",
+            "bytes_fill failed: index 4 is out of bounds for bytes of length 3"),
+        ("bytes_copy_within", vec![data.clone(), int(0), int(2), int(2)], "range",
+            "bytes_copy_within failed: index 4 is out of bounds for bytes of length 3"),
+        ("bytes_to_hex", vec![int(1)], "type", "bytes_to_hex expects bytes, got int"),
+        ("bytes_from_hex", vec![s("abc")], "decode",
+            "bytes_from_hex failed: hex input of odd length 3"),
+        ("bytes_from_hex", vec![s("zz")], "decode",
+            "bytes_from_hex failed: invalid character at byte offset 0"),
+        ("bytes_to_base64", vec![s("x")], "type", "bytes_to_base64 expects bytes, got string"),
+        ("bytes_from_base64", vec![s("abc")], "decode",
+            "bytes_from_base64 failed: base64 input of length 3 is not a multiple of four"),
+        ("bytes_from_base64", vec![s("a=bc")], "decode",
+            "bytes_from_base64 failed: invalid padding at byte offset 1"),
+        ("bytes_from_int_le", vec![int(256), int(1)], "range",
+            "bytes_from_int_le failed: 256 does not fit into 1 bytes"),
+        ("bytes_from_int_be", vec![int(1), int(9)], "range",
+            "bytes_from_int_be failed: integer width must be between 1 and 8 bytes, got 9"),
+        ("bytes_decode_utf8", vec![b(&[b'a', 0xff])], "decode",
+            "bytes_decode_utf8 failed: invalid utf-8 at byte offset 1"),
+        ("bytes_decode_utf8", vec![data.clone(), s("lossy")], "range",
+            "bytes_decode_utf8 expects the mode \"strict\", \"replace\" or \"prefix\", \
+             got \"lossy\""),
+        ("bytes_decode_utf8", vec![data.clone(), int(1)], "type",
+            "bytes_decode_utf8 expects a string as the mode, got int"),
+        ("str_encode_utf8", vec![data.clone()], "type",
+            "str_encode_utf8 expects a string, got bytes"),
+        ("bytes_read_u8", vec![data.clone(), int(3)], "range",
+            "bytes_get failed: index 3 is out of bounds for bytes of length 3"),
+        ("bytes_write_u8", vec![data.clone(), int(0), int(-1)], "range",
+            "bytes_set expects a byte (0 to 255), got -1"),
+        ("bytes_read_u16_le", vec![data.clone(), int(2)], "range",
+            "bytes_read_u16_le failed: index 4 is out of bounds for bytes of length 3"),
+        ("bytes_write_u16_be", vec![data.clone(), int(0), int(65536)], "range",
+            "bytes_write_u16_be expects an int from 0 to 65535, got 65536"),
+        ("bytes_write_u32_le", vec![data.clone(), int(0), int(1)], "range",
+            "bytes_write_u32_le failed: index 4 is out of bounds for bytes of length 3"),
+        ("bytes_read_u64_be", vec![b(&[0xff; 8]), int(0)], "range",
+            "bytes_read_u64_be read 18446744073709551615, which does not fit into an int"),
+        ("bytes_write_u64_le", vec![b(&[0; 8]), int(0), int(-1)], "range",
+            "bytes_write_u64_le expects an int from 0 to 18446744073709551615, got -1"),
+        ("bytes_read_i64_le", vec![data.clone(), int(0)], "range",
+            "bytes_read_i64_le failed: index 8 is out of bounds for bytes of length 3"),
+        ("bytes_write_i64_be", vec![b(&[0; 8]), int(0), s("1")], "type",
+            "bytes_write_i64_be expects an int, got string"),
+        ("bytes_write_f64_le", vec![b(&[0; 8]), int(0), s("1")], "type",
+            "bytes_write_f64_le expects a number, got string"),
+        ("bytes_read_f64_be", vec![data, int(0)], "range",
+            "bytes_read_f64_be failed: index 8 is out of bounds for bytes of length 3"),
+    ];
+    for (name, args, expected_kind, expected_message) in cases {
+        let err = call(&mut rt, name, &args).unwrap_err();
+        assert_eq!(kind(&err), expected_kind, "{}", name);
+        assert_eq!(message(&err), expected_message);
+    }
+}
+
+// A tiny binary format: records of a u16 big-endian length followed by that many bytes of utf-8,
+// parsed with the builtins only.
+#[test]
+fn length_prefixed_records() {
+    let mut rt = Runtime::new();
+    let mut input = b(&[]);
+    for text in &["one", "", "drei 🌍"] {
+        let encoded = call(&mut rt, "str_encode_utf8", &[s(text)]).unwrap();
+        let len = call(&mut rt, "bytes_len", std::slice::from_ref(&encoded)).unwrap();
+        let header = call(&mut rt, "bytes_write_u16_be", &[b(&[0, 0]), int(0), len]).unwrap();
+        input = call(&mut rt, "bytes_concat", &[input, header]).unwrap();
+        input = call(&mut rt, "bytes_concat", &[input, encoded]).unwrap();
+    }
+
+    let mut records = vec![];
+    let mut offset = 0;
+    let total = match call(&mut rt, "bytes_len", std::slice::from_ref(&input)) {
+        Ok(Value::Int(n)) => n,
+        other => panic!("{:?}", other),
+    };
+    while offset < total {
+        let len = match call(&mut rt, "bytes_read_u16_be", &[input.clone(), int(offset)]) {
+            Ok(Value::Int(n)) => n,
+            other => panic!("{:?}", other),
+        };
+        let start = offset + 2;
+        let body = call(&mut rt, "bytes_slice", &[input.clone(), int(start), int(start + len)]);
+        records.push(call(&mut rt, "bytes_decode_utf8", &[body.unwrap()]).unwrap());
+        offset = start + len;
+    }
+    assert_eq!(records, vec![s("one"), s(""), s("drei 🌍")]);
+
+    // A truncated record throws.
+    let truncated = call(&mut rt, "bytes_slice", &[input, int(0), int(4)]).unwrap();
+    let err = call(&mut rt, "bytes_slice", &[truncated, int(2), int(5)]).unwrap_err();
+    assert_eq!(kind(&err), "range");
+}
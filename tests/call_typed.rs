@@ -0,0 +1,139 @@
+use std::convert::TryFrom;
+
+use pan_lang_rs::runtime::{CallError, RunError, Runtime};
+use pan_lang_rs::types::rope::Rope;
+use pan_lang_rs::value::Value;
+
+/// Converts into a pan value only if it is even.
+struct Even(i64);
+
+impl TryFrom<Even> for Value {
+    type Error = String;
+
+    fn try_from(even: Even) -> Result<Value, String> {
+        if even.0 % 2 == 0 {
+            Ok(Value::Int(even.0))
+        } else {
+            Err(format!("{} is odd", even.0))
+        }
+    }
+}
+
+#[test]
+fn arguments_and_results_are_converted() {
+    let mut rt = Runtime::new();
+    let add = rt.global("add").unwrap();
+    let sum: i64 = rt.call_typed(&add, (1i64, 2i64)).unwrap();
+    assert_eq!(sum, 3);
+    let sum: f64 = rt.call_typed(&add, (0.5, 0.25)).unwrap();
+    assert_eq!(sum, 0.75);
+
+    let concat = rt.global("concat").unwrap();
+    let joined: Vec<Value> =
+        rt.call_typed(&concat, (vec![Value::Int(1)], vec![Value::Int(2)])).unwrap();
+    assert_eq!(joined, vec![Value::Int(1), Value::Int(2)]);
+    let joined: Rope = rt.call_typed(&rt.global("str_concat").unwrap(), ("a", "b")).unwrap();
+    assert_eq!(joined.to_string(), "ab");
+
+    // Argument lists and the empty tuple are passed as they are.
+    let sum: i64 = rt.call_typed(&add, vec![Value::Int(3), Value::Int(4)]).unwrap();
+    assert_eq!(sum, 7);
+    rt.register("nothing", |args| Ok(Value::Int(args.len() as i64)));
+    let nothing = rt.global("nothing").unwrap();
+    let count: i64 = rt.call_typed(&nothing, ()).unwrap();
+    assert_eq!(count, 0);
+    let count: i64 = rt.call_typed(&nothing, (1, 2, 3, 4, 5, 6, 7, 8)).unwrap();
+    assert_eq!(count, 8);
+    let options: i64 = rt.call_typed(&nothing, (Some(1i64), None::<i64>)).unwrap();
+    assert_eq!(options, 2);
+}
+
+#[test]
+fn conversion_failures_report_their_position() {
+    let mut rt = Runtime::new();
+    let add = rt.global("add").unwrap();
+    let sum: i64 = rt.call_typed(&add, (Even(2), Even(4))).unwrap();
+    assert_eq!(sum, 6);
+
+    let err = rt.call_typed::<_, i64>(&add, (Even(2), Even(3))).unwrap_err();
+    match &err {
+        CallError::Argument { position, message } => {
+            assert_eq!(*position, 1);
+            assert_eq!(message, "3 is odd");
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+    assert_eq!(err.to_string(), "argument 1 could not be converted: 3 is odd");
+
+    // The first failing argument is reported, and the function is not called.
+    rt.register("count", |_| panic!("called despite a bad argument"));
+    let count = rt.global("count").unwrap();
+    let err = rt.call_typed::<_, ()>(&count, (Even(1), Even(3))).unwrap_err();
+    assert!(matches!(err, CallError::Argument { position: 0, .. }));
+}
+
+#[test]
+fn return_values_that_do_not_convert_are_kept() {
+    let mut rt = Runtime::new();
+    let add = rt.global("add").unwrap();
+    let err = rt.call_typed::<_, i64>(&add, (0.5, 0.5)).unwrap_err();
+    match &err {
+        CallError::Return { value, message } => {
+            assert_eq!(value, &Value::Float(1.0.into()));
+            assert_eq!(message, "expected int, got float");
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+    assert_eq!(
+        err.to_string(),
+        "the return value could not be converted: expected int, got float"
+    );
+    let err = rt.call_typed::<_, String>(&add, (1, 2)).unwrap_err();
+    assert!(matches!(err, CallError::Return { value: Value::Int(3), .. }));
+}
+
+#[test]
+fn only_functions_can_be_called() {
+    let mut rt = Runtime::new();
+    for (target, type_name) in [(Value::Int(1), "int"), (Value::Nil, "nil")].iter() {
+        let err = rt.call_typed::<_, ()>(target, (1i64,)).unwrap_err();
+        match &err {
+            CallError::NotCallable { type_name: got } => assert_eq!(got, type_name),
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert_eq!(err.to_string(), format!("cannot call a value of type {}", type_name));
+    }
+    assert!(rt.global("no_such_global").is_none());
+}
+
+#[test]
+fn throws_carry_the_value_and_its_rendering() {
+    let mut rt = Runtime::new();
+    rt.register("fail", |args| Err(Value::error("custom", &format!("got {}", args[0]))));
+    let fail = rt.global("fail").unwrap();
+    let err = rt.call_typed::<_, ()>(&fail, (7i64,)).unwrap_err();
+    match &err {
+        CallError::Run(RunError::Thrown { value, rendered }) => {
+            assert_eq!(value.error_kind().unwrap().to_string(), "custom");
+            assert_eq!(rendered, "custom: got 7");
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+    assert_eq!(err.to_string(), "custom: got 7");
+
+    // Values that are not errors render in literal syntax.
+    rt.register("throw_str", |_| Err(Value::from("plain")));
+    let throw_str = rt.global("throw_str").unwrap();
+    let err = rt.call_typed::<_, ()>(&throw_str, ()).unwrap_err();
+    assert_eq!(err.to_string(), "\"plain\"");
+
+    // Builtins throw through the same path.
+    let add = rt.global("add").unwrap();
+    let err = rt.call_typed::<_, i64>(&add, (i64::MAX, 1i64)).unwrap_err();
+    match err {
+        CallError::Run(RunError::Thrown { rendered, .. }) => {
+            assert!(rendered.starts_with("overflow: "), "{}", rendered)
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
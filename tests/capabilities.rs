@@ -0,0 +1,157 @@
+mod common;
+
+use std::fs;
+use std::process;
+
+use pan_lang_rs::capabilities::{Capabilities, Capability};
+use pan_lang_rs::runtime::{ResolveError, Runtime};
+use pan_lang_rs::types::futures::EventLoop;
+use pan_lang_rs::value::Value;
+
+use common::{call, int, kind, runtime, s};
+
+// Whether the builtin exists in this build at all.
+fn built(name: &str) -> bool {
+    cfg!(feature = "secure-random") || Capability::required_by(name) != Some(Capability::Entropy)
+}
+
+#[test]
+fn profiles_register_exactly_their_builtins() {
+    let profiles = [
+        (Capabilities::PURE, vec![]),
+        (Capabilities::SCRIPTING, Capability::ALL[1..].to_vec()),
+        (Capabilities::FULL, Capability::ALL.to_vec()),
+    ];
+    for (caps, granted) in profiles.iter() {
+        let rt = runtime(caps);
+        for capability in Capability::ALL.iter().cloned() {
+            assert_eq!(caps.allows(capability), granted.contains(&capability));
+            for name in capability.builtins().iter().filter(|name| built(name)) {
+                assert_eq!(rt.global(name).is_some(), caps.allows(capability), "{}", name);
+            }
+        }
+        // The builtins that only compute are always there.
+        for name in ["len", "add", "sort", "format_timestamp", "error"].iter() {
+            assert!(rt.global(name).is_some(), "{}", name);
+        }
+    }
+    assert_eq!(Capabilities::default(), Capabilities::SCRIPTING);
+    assert_eq!(Capabilities::FULL.fs_root(), None);
+}
+
+#[test]
+fn the_builder_grants_and_denies_single_capabilities() {
+    type Grant = fn(Capabilities) -> Capabilities;
+    let grants: [(Grant, Capability); 7] = [
+        (Capabilities::allow_env, Capability::Env),
+        (Capabilities::allow_entropy, Capability::Entropy),
+        (Capabilities::allow_clock, Capability::Clock),
+        (Capabilities::allow_stdout, Capability::Stdout),
+        (Capabilities::allow_stderr, Capability::Stderr),
+        (Capabilities::allow_stdin, Capability::Stdin),
+        (Capabilities::allow_gc, Capability::Gc),
+    ];
+    for (grant, capability) in grants.iter() {
+        let caps = grant(Capabilities::PURE);
+        for other in Capability::ALL.iter() {
+            assert_eq!(caps.allows(*other), other == capability);
+        }
+        assert_eq!(caps.deny(*capability), Capabilities::PURE);
+    }
+    let caps = Capabilities::PURE.allow_fs(None);
+    assert!(caps.allows(Capability::Fs) && caps.fs_root().is_none());
+    assert_eq!(Capabilities::FULL.deny(Capability::Fs), Capabilities::SCRIPTING);
+
+    // Denying a capability of a profile removes just its builtins.
+    for capability in Capability::ALL.iter().cloned() {
+        let rt = runtime(&Capabilities::FULL.deny(capability));
+        for other in Capability::ALL.iter().cloned() {
+            for name in other.builtins().iter().filter(|name| built(name)) {
+                assert_eq!(rt.global(name).is_some(), other != capability, "{}", name);
+            }
+        }
+    }
+}
+
+#[test]
+fn missing_capabilities_are_diagnosed() {
+    let rt = runtime(&Capabilities::PURE);
+    for capability in Capability::ALL.iter().cloned() {
+        for name in capability.builtins() {
+            let err = rt.resolve(name).unwrap_err();
+            assert_eq!(err, ResolveError::Denied { name: name.to_string(), capability });
+            assert_eq!(
+                err.to_string(),
+                format!("{} requires the {} capability", name, capability.name())
+            );
+        }
+    }
+    assert_eq!(
+        rt.resolve("read_file").unwrap_err().to_string(),
+        "read_file requires the fs capability"
+    );
+    let err = rt.resolve("no_such_builtin").unwrap_err();
+    assert_eq!(err, ResolveError::Unbound { name: "no_such_builtin".to_string() });
+    assert_eq!(err.to_string(), "no_such_builtin is not bound");
+    assert!(rt.resolve("len").is_ok());
+
+    // Under the full profile, the same names resolve.
+    let rt = runtime(&Capabilities::FULL);
+    for capability in Capability::ALL.iter().cloned() {
+        for name in capability.builtins().iter().filter(|name| built(name)) {
+            assert!(rt.resolve(name).is_ok(), "{}", name);
+        }
+    }
+}
+
+#[test]
+fn what_the_embedder_hands_in_bypasses_capabilities() {
+    let mut rt = runtime(&Capabilities::PURE);
+    // A native with the name of a guarded builtin is the embedder's business.
+    rt.register("println", |args| Ok(int(args.len() as i64)));
+    assert_eq!(call(&mut rt, "println", &[s("a"), s("b")]).unwrap(), int(2));
+    rt.define("env", Value::from("defined")).unwrap();
+    assert_eq!(rt.resolve("env").unwrap(), s("defined"));
+    assert!(rt.resolve("args").is_err());
+
+    // So does granting the file system later on.
+    assert!(rt.resolve("file_exists").is_err());
+    rt.allow_fs(None).unwrap();
+    let exists = call(&mut rt, "file_exists", &[s("/no/such/file/anywhere")]).unwrap();
+    assert_eq!(exists, Value::Bool(false));
+}
+
+#[test]
+fn the_fs_root_is_enforced_at_runtime() {
+    let base = std::env::temp_dir().join(format!("pan-caps-test-{}", process::id()));
+    let root = base.join("root");
+    fs::create_dir_all(&root).unwrap();
+    let root = fs::canonicalize(root).unwrap();
+    let outside = root.parent().unwrap().join("outside.txt");
+    fs::write(&outside, "secret").unwrap();
+    let inside = root.join("inside.txt");
+    fs::write(&inside, "public").unwrap();
+
+    let caps = Capabilities::FULL.allow_fs(Some(&root));
+    assert_eq!(caps.fs_root(), Some(root.as_path()));
+    let mut rt = runtime(&caps);
+    let path = |p: &std::path::Path| s(p.to_str().unwrap());
+    assert_eq!(call(&mut rt, "read_file_text", &[path(&inside)]).unwrap(), s("public"));
+    let escape = root.join("..").join("outside.txt");
+    for target in [path(&outside), path(&escape)].iter() {
+        let err = call(&mut rt, "read_file_text", std::slice::from_ref(target)).unwrap_err();
+        assert_eq!(kind(&err), "forbidden");
+        let err = call(&mut rt, "write_file", &[target.clone(), s("x")]).unwrap_err();
+        assert_eq!(kind(&err), "forbidden");
+    }
+    assert_eq!(fs::read_to_string(&outside).unwrap(), "secret");
+
+    // Without a root, the same file is readable.
+    let mut rt = runtime(&Capabilities::FULL);
+    assert_eq!(call(&mut rt, "read_file_text", &[path(&outside)]).unwrap(), s("secret"));
+
+    // A root that does not exist fails the construction of the runtime.
+    let caps = Capabilities::FULL.allow_fs(Some(&base.join("missing")));
+    assert!(Runtime::with_capabilities(EventLoop::new(), &caps).is_err());
+    fs::remove_dir_all(&base).unwrap();
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::types::futures::LifecycleState;
+use pan_lang_rs::value::Value;
+
+use common::{call, int, kind, s};
+
+fn state(fut: &Value) -> LifecycleState {
+    match fut {
+        Value::Future(fut) => fut.state(),
+        other => panic!("not a future: {:?}", other),
+    }
+}
+
+fn run(rt: &Runtime, fut: &Value) {
+    match fut {
+        Value::Future(fut) => drop(rt.event_loop().run_future(fut).unwrap()),
+        other => panic!("not a future: {:?}", other),
+    }
+}
+
+fn send(rt: &mut Runtime, ch: &Value, v: Value) -> Value {
+    let fut = call(rt, "chan_send", &[ch.clone(), v]).unwrap();
+    run(rt, &fut);
+    fut
+}
+
+fn recv(rt: &mut Runtime, ch: &Value) -> Value {
+    let fut = call(rt, "chan_recv", std::slice::from_ref(ch)).unwrap();
+    run(rt, &fut);
+    fut
+}
+
+fn close(rt: &mut Runtime, ch: &Value) -> Result<Value, Value> {
+    call(rt, "chan_close", std::slice::from_ref(ch))
+}
+
+#[test]
+fn producers_wait_for_consumers() {
+    use LifecycleState::{Resolved, Running};
+    let mut rt = Runtime::new_deterministic(377);
+    let ch = call(&mut rt, "chan_new", &[int(1)]).unwrap();
+    let sends: Vec<Value> = (1..=3).map(|i| send(&mut rt, &ch, int(i))).collect();
+    rt.event_loop().run_until_idle();
+    let states: Vec<_> = sends.iter().map(state).collect();
+    assert_eq!(states, [Resolved, Running, Running]);
+
+    for i in 1..=3 {
+        let received = recv(&mut rt, &ch);
+        assert_eq!(rt.block_on_raw(&received), Ok(int(i)));
+        rt.event_loop().run_until_idle();
+        // Taking a value from the buffer lets the next waiting sender in.
+        for (j, sent) in sends.iter().enumerate() {
+            let expected = if j <= i as usize { Resolved } else { Running };
+            assert_eq!(state(sent), expected);
+        }
+    }
+    assert_eq!(rt.block_on_raw(&sends[2]), Ok(Value::Nil));
+}
+
+#[test]
+fn consumers_are_served_in_order() {
+    let mut rt = Runtime::new_deterministic(377);
+    let ch = call(&mut rt, "chan_new", &[int(0)]).unwrap();
+    let first = recv(&mut rt, &ch);
+    let cancelled = recv(&mut rt, &ch);
+    let second = recv(&mut rt, &ch);
+    rt.event_loop().run_until_idle();
+    assert_eq!(state(&first), LifecycleState::Running);
+    assert_eq!(call(&mut rt, "fut_cancel", &[cancelled]), Ok(Value::Bool(true)));
+
+    let a = send(&mut rt, &ch, s("a"));
+    let b = send(&mut rt, &ch, s("b"));
+    assert_eq!(rt.block_on_raw(&second), Ok(s("b")));
+    assert_eq!(rt.block_on_raw(&first), Ok(s("a")));
+    assert_eq!(state(&a), LifecycleState::Resolved);
+    assert_eq!(state(&b), LifecycleState::Resolved);
+}
+
+#[test]
+fn closing_rejects_waiting_futures() {
+    let mut rt = Runtime::new_deterministic(377);
+    let ch = call(&mut rt, "chan_new", &[int(0)]).unwrap();
+    let waiting = recv(&mut rt, &ch);
+    rt.event_loop().run_until_idle();
+    assert_eq!(close(&mut rt, &ch), Ok(Value::Bool(true)));
+    assert_eq!(close(&mut rt, &ch), Ok(Value::Bool(false)));
+    assert_eq!(kind(&rt.block_on_raw(&waiting).unwrap_err()), "closed");
+
+    let ch = call(&mut rt, "chan_new", &[int(1)]).unwrap();
+    let buffered = send(&mut rt, &ch, int(1));
+    let waiting = send(&mut rt, &ch, int(2));
+    rt.event_loop().run_until_idle();
+    close(&mut rt, &ch).unwrap();
+    assert_eq!(rt.block_on_raw(&buffered), Ok(Value::Nil));
+    assert_eq!(kind(&rt.block_on_raw(&waiting).unwrap_err()), "closed");
+    // Buffered values can still be received, then receiving rejects.
+    let received = recv(&mut rt, &ch);
+    assert_eq!(rt.block_on_raw(&received), Ok(int(1)));
+    let received = recv(&mut rt, &ch);
+    assert_eq!(kind(&rt.block_on_raw(&received).unwrap_err()), "closed");
+}
+
+#[test]
+fn sending_after_closing_rejects() {
+    let mut rt = Runtime::new_deterministic(377);
+    let ch = call(&mut rt, "chan_new", &[int(4)]).unwrap();
+    close(&mut rt, &ch).unwrap();
+    let sent = send(&mut rt, &ch, int(1));
+    assert_eq!(kind(&rt.block_on_raw(&sent).unwrap_err()), "closed");
+
+    assert_eq!(kind(&call(&mut rt, "chan_new", &[int(-1)]).unwrap_err()), "type");
+    assert_eq!(kind(&call(&mut rt, "chan_send", &[int(1), int(1)]).unwrap_err()), "type");
+    assert_eq!(kind(&call(&mut rt, "chan_recv", &[]).unwrap_err()), "type");
+}
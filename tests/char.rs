@@ -0,0 +1,138 @@
+mod common;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::value::Value;
+
+use common::{call, int, kind, message, s};
+
+fn c(c: char) -> Value {
+    Value::Char(c)
+}
+
+fn yes(b: bool) -> Value {
+    Value::Bool(b)
+}
+
+#[test]
+fn code_points() {
+    let mut rt = Runtime::new();
+    for &ch in &['\0', 'a', 'é', '€', '😀', '\u{D7FF}', '\u{E000}', '\u{10FFFF}'] {
+        let n = call(&mut rt, "char_to_int", &[c(ch)]).unwrap();
+        assert_eq!(n, int(i64::from(u32::from(ch))));
+        assert_eq!(call(&mut rt, "int_to_char", &[n]), Ok(c(ch)));
+    }
+    for &n in &[-1, 0xD800, 0xDFFF, 0x110000, i64::MAX, i64::MIN, 1 << 32] {
+        let err = call(&mut rt, "int_to_char", &[int(n)]).unwrap_err();
+        assert_eq!(kind(&err), "range", "{}", n);
+    }
+    let err = call(&mut rt, "int_to_char", &[int(0xD800)]).unwrap_err();
+    assert_eq!(message(&err), "int_to_char cannot convert 55296 to a char, it is a surrogate");
+    let err = call(&mut rt, "int_to_char", &[int(-1)]).unwrap_err();
+    assert_eq!(message(&err), "int_to_char cannot convert -1 to a char, it is not a code point");
+    assert_eq!(kind(&call(&mut rt, "int_to_char", &[s("a")]).unwrap_err()), "type");
+}
+
+#[test]
+fn classification() {
+    let mut rt = Runtime::new();
+    // The char, and whether it is alpha, digit, alphanumeric, whitespace, upper and lower.
+    let cases = [
+        ('a', [true, false, true, false, false, true]),
+        ('Z', [true, false, true, false, true, false]),
+        ('7', [false, true, true, false, false, false]),
+        ('é', [true, false, true, false, false, true]),
+        ('Σ', [true, false, true, false, true, false]),
+        ('中', [true, false, true, false, false, false]),
+        ('٣', [false, true, true, false, false, false]),
+        ('½', [false, true, true, false, false, false]),
+        (' ', [false, false, false, true, false, false]),
+        ('\u{3000}', [false, false, false, true, false, false]),
+        ('\n', [false, false, false, true, false, false]),
+        ('_', [false, false, false, false, false, false]),
+        ('😀', [false, false, false, false, false, false]),
+    ];
+    let names =
+        ["is_alpha", "is_digit", "is_alphanumeric", "is_whitespace", "is_upper", "is_lower"];
+    for (ch, expected) in cases.iter() {
+        for (name, expected) in names.iter().zip(expected.iter()) {
+            assert_eq!(call(&mut rt, name, &[c(*ch)]), Ok(yes(*expected)), "{} {:?}", name, ch);
+        }
+    }
+    for name in names.iter() {
+        let err = call(&mut rt, name, &[s("a")]).unwrap_err();
+        assert_eq!(message(&err), format!("{} expects a char, got string", name));
+    }
+}
+
+#[test]
+fn case_mappings_are_single_chars() {
+    let mut rt = Runtime::new();
+    let upper = [('a', 'A'), ('é', 'É'), ('σ', 'Σ'), ('ς', 'Σ'), ('A', 'A'), ('1', '1')];
+    for &(from, to) in &upper {
+        assert_eq!(call(&mut rt, "char_to_upper", &[c(from)]), Ok(c(to)), "{:?}", from);
+    }
+    let lower = [('A', 'a'), ('É', 'é'), ('Σ', 'σ'), ('a', 'a')];
+    for &(from, to) in &lower {
+        assert_eq!(call(&mut rt, "char_to_lower", &[c(from)]), Ok(c(to)), "{:?}", from);
+    }
+    // Mappings to several chars leave the char unchanged, unlike the string functions.
+    assert_eq!(call(&mut rt, "char_to_upper", &[c('ß')]), Ok(c('ß')));
+    assert_eq!(call(&mut rt, "str_to_upper", &[s("ß")]), Ok(s("SS")));
+    assert_eq!(call(&mut rt, "char_to_upper", &[c('ﬁ')]), Ok(c('ﬁ')));
+    assert_eq!(call(&mut rt, "char_to_lower", &[c('İ')]), Ok(c('İ')));
+}
+
+#[test]
+fn digit_values() {
+    let mut rt = Runtime::new();
+    let cases = [
+        ('7', None, Some(7)),
+        ('a', None, None),
+        ('a', Some(16), Some(10)),
+        ('F', Some(16), Some(15)),
+        ('g', Some(16), None),
+        ('z', Some(36), Some(35)),
+        ('1', Some(2), Some(1)),
+        ('2', Some(2), None),
+        ('٣', None, None),
+        ('７', None, None),
+    ];
+    for &(ch, base, expected) in &cases {
+        let mut args = vec![c(ch)];
+        args.extend(base.map(int));
+        let expected = expected.map_or(Value::Nil, int);
+        assert_eq!(call(&mut rt, "digit_value", &args), Ok(expected), "{:?} {:?}", ch, base);
+    }
+    for &base in &[0, 1, 37] {
+        let err = call(&mut rt, "digit_value", &[c('1'), int(base)]).unwrap_err();
+        assert_eq!(kind(&err), "range");
+    }
+    assert_eq!(kind(&call(&mut rt, "digit_value", &[c('1'), s("10")]).unwrap_err()), "type");
+}
+
+#[test]
+fn classifying_the_chars_of_a_string() {
+    let mut rt = Runtime::new();
+    let text = s("Ünïcödé 42 ΣΑΣ\u{3000}😀");
+    let len = match call(&mut rt, "str_len", std::slice::from_ref(&text)).unwrap() {
+        Value::Int(n) => n,
+        other => panic!("not an int: {}", other),
+    };
+    let mut classes = String::new();
+    for i in 0..len {
+        let ch = call(&mut rt, "str_get", &[text.clone(), int(i)]).unwrap();
+        let class = if call(&mut rt, "is_upper", std::slice::from_ref(&ch)) == Ok(yes(true)) {
+            'U'
+        } else if call(&mut rt, "is_lower", std::slice::from_ref(&ch)) == Ok(yes(true)) {
+            'l'
+        } else if call(&mut rt, "is_digit", std::slice::from_ref(&ch)) == Ok(yes(true)) {
+            'd'
+        } else if call(&mut rt, "is_whitespace", std::slice::from_ref(&ch)) == Ok(yes(true)) {
+            ' '
+        } else {
+            '?'
+        };
+        classes.push(class);
+    }
+    assert_eq!(classes, "Ullllll dd UUU ?");
+}
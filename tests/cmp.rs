@@ -0,0 +1,185 @@
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::types::bytes::Bytes;
+use pan_lang_rs::types::channel::Channel;
+use pan_lang_rs::types::stream::Stream;
+use pan_lang_rs::types::userdata::{PanUserdata, Userdata};
+use pan_lang_rs::value::{Fun, Native, Value};
+
+fn call(runtime: &mut Runtime, name: &str, args: &[Value]) -> Result<Value, Value> {
+    let fun = runtime.global(name).unwrap();
+    runtime.call_raw(&fun, args)
+}
+
+fn message(err: Value) -> String {
+    err.error_message().unwrap().to_string()
+}
+
+#[test]
+fn min_and_max() {
+    let mut rt = Runtime::new();
+    let args = [Value::Int(3), Value::Int(-1), Value::Int(2)];
+    assert_eq!(call(&mut rt, "min", &args), Ok(Value::Int(-1)));
+    assert_eq!(call(&mut rt, "max", &args), Ok(Value::Int(3)));
+    assert!(call(&mut rt, "min", &[]).is_err());
+    let nan = Value::Float(f64::NAN.into());
+    let max = call(&mut rt, "max", &[Value::Float(1.0.into()), nan.clone()]).unwrap();
+    assert_eq!(max, nan);
+}
+
+#[test]
+fn min_and_max_return_the_first_of_equal_values() {
+    let mut rt = Runtime::new();
+    let (a, b) = (Value::array(vec![]), Value::array(vec![]));
+    for name in &["min", "max"] {
+        let first = call(&mut rt, name, &[a.clone(), b.clone()]).unwrap();
+        match (&first, &a) {
+            (Value::Array(first), Value::Array(a)) => assert!(gc::Gc::ptr_eq(first, a)),
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[test]
+fn type_errors_name_the_types_in_argument_order() {
+    let mut rt = Runtime::new();
+    let args = [Value::Int(1), Value::Float(0.5.into()), Value::from("a")];
+    for name in &["min", "max"] {
+        let err = call(&mut rt, name, &args).unwrap_err();
+        assert_eq!(err.error_kind().unwrap().to_string(), "type");
+        assert_eq!(
+            message(err),
+            format!("{} expects two values of the same type, got int and float", name),
+        );
+    }
+    let arr = Value::array(vec![Value::Int(1), Value::from("a")]);
+    let err = call(&mut rt, "arr_min", &[arr]).unwrap_err();
+    assert_eq!(message(err), "arr_min expects two values of the same type, got int and string");
+}
+
+#[test]
+fn clamp() {
+    let mut rt = Runtime::new();
+    let clamp = |rt: &mut Runtime, x, lo, hi| {
+        call(rt, "clamp", &[Value::Int(x), Value::Int(lo), Value::Int(hi)])
+    };
+    assert_eq!(clamp(&mut rt, 5, 0, 3), Ok(Value::Int(3)));
+    assert_eq!(clamp(&mut rt, -5, 0, 3), Ok(Value::Int(0)));
+    assert_eq!(clamp(&mut rt, 2, 0, 3), Ok(Value::Int(2)));
+    let err = clamp(&mut rt, 2, 3, 0).unwrap_err();
+    assert_eq!(err.error_kind().unwrap().to_string(), "range");
+}
+
+#[derive(Debug)]
+struct Data;
+
+impl PanUserdata for Data {
+    fn type_name(&self) -> &'static str {
+        "data"
+    }
+}
+
+// One value of every variant, created twice: collections and the like as equal copies, values
+// compared by identity as clones.
+fn samples(rt: &mut Runtime) -> Vec<(Value, Value)> {
+    let structural = |make: &dyn Fn() -> Value| (make(), make());
+    let by_identity = |v: Value| (v.clone(), v);
+    let iter = call(rt, "iter", &[Value::array(vec![])]).unwrap();
+    vec![
+        structural(&|| Value::Nil),
+        structural(&|| Value::Bool(true)),
+        structural(&|| Value::Int(1)),
+        structural(&|| Value::Float(1.0.into())),
+        structural(&|| Value::Char('1')),
+        structural(&|| Value::from("1")),
+        structural(&|| Value::Bytes(Bytes::from_slice(b"1"))),
+        structural(&|| Value::array(vec![Value::Int(1)])),
+        structural(&|| Value::set(vec![Value::Int(1)].into_iter().collect())),
+        structural(&|| Value::map(vec![(Value::Int(1), Value::Nil)].into_iter().collect())),
+        by_identity(Value::Fun(Fun::Native(Native::new("f", |_| Ok(Value::Nil))))),
+        by_identity(Value::future_resolve(Value::Int(1))),
+        by_identity(Value::Channel(Channel::new(1))),
+        by_identity(Value::Stream(Stream::from_array(vec![]))),
+        by_identity(iter),
+        by_identity(Value::Userdata(Userdata::new(Data))),
+    ]
+}
+
+#[test]
+fn eq_compares_every_pair_of_variants() {
+    let mut rt = Runtime::new();
+    let pairs = samples(&mut rt);
+    for (i, (a, _)) in pairs.iter().enumerate() {
+        for (j, (_, b)) in pairs.iter().enumerate() {
+            let args = [a.clone(), b.clone()];
+            assert_eq!(call(&mut rt, "eq", &args), Ok(Value::Bool(i == j)), "{} {}", a, b);
+            assert_eq!(call(&mut rt, "neq", &args), Ok(Value::Bool(i != j)), "{} {}", a, b);
+        }
+    }
+    // Values compared by identity are not equal to lookalikes.
+    let fresh = samples(&mut rt);
+    for ((a, _), (b, _)) in pairs.iter().zip(fresh.iter()).skip(10) {
+        assert_eq!(call(&mut rt, "eq", &[a.clone(), b.clone()]), Ok(Value::Bool(false)), "{}", a);
+    }
+}
+
+#[test]
+fn ordered_comparisons_throw_on_mixed_types() {
+    let mut rt = Runtime::new();
+    for name in &["lt", "lte", "gt", "gte", "compare"] {
+        let err = call(&mut rt, name, &[Value::Int(1), Value::from("a")]).unwrap_err();
+        assert_eq!(err.error_kind().unwrap().to_string(), "type");
+        assert_eq!(
+            message(err),
+            format!("{} expects two values of the same type, got int and string", name),
+        );
+    }
+    let err = call(&mut rt, "lt", &[Value::Int(1), Value::Float(1.5.into())]).unwrap_err();
+    assert_eq!(message(err), "lt expects two values of the same type, got int and float");
+    // Equality works across types.
+    let mixed = [Value::Int(1), Value::Float(1.0.into())];
+    assert_eq!(call(&mut rt, "eq", &mixed), Ok(Value::Bool(false)));
+}
+
+#[test]
+fn ordered_comparisons_within_a_type() {
+    let mut rt = Runtime::new();
+    let pairs = [
+        (Value::Int(-1), Value::Int(2)),
+        (Value::from("ab"), Value::from("b")),
+        (Value::array(vec![Value::Int(1)]), Value::array(vec![Value::Int(1), Value::Int(0)])),
+        (Value::Float(1.0.into()), Value::Float(f64::NAN.into())),
+    ];
+    for (less, greater) in &pairs {
+        let (a, b) = (less.clone(), greater.clone());
+        assert_eq!(call(&mut rt, "lt", &[a.clone(), b.clone()]), Ok(Value::Bool(true)));
+        assert_eq!(call(&mut rt, "lte", &[a.clone(), b.clone()]), Ok(Value::Bool(true)));
+        assert_eq!(call(&mut rt, "gt", &[a.clone(), b.clone()]), Ok(Value::Bool(false)));
+        assert_eq!(call(&mut rt, "gte", &[b.clone(), a.clone()]), Ok(Value::Bool(true)));
+        assert_eq!(call(&mut rt, "compare", &[a.clone(), b.clone()]), Ok(Value::Int(-1)));
+        assert_eq!(call(&mut rt, "compare", &[b.clone(), a.clone()]), Ok(Value::Int(1)));
+        assert_eq!(call(&mut rt, "compare", &[a.clone(), a]), Ok(Value::Int(0)));
+    }
+}
+
+#[test]
+fn nan_equals_itself() {
+    let mut rt = Runtime::new();
+    let nan = Value::Float(f64::NAN.into());
+    assert_eq!(call(&mut rt, "eq", &[nan.clone(), nan.clone()]), Ok(Value::Bool(true)));
+    assert_eq!(call(&mut rt, "lte", &[nan.clone(), nan.clone()]), Ok(Value::Bool(true)));
+    assert_eq!(call(&mut rt, "compare", &[nan.clone(), nan]), Ok(Value::Int(0)));
+}
+
+#[test]
+fn compare_sorts() {
+    let mut rt = Runtime::new();
+    let arr = Value::array(["pear", "apple", "fig"].iter().map(|&s| Value::from(s)).collect());
+    let compare = rt.global("compare").unwrap();
+    call(&mut rt, "sort_by", &[arr.clone(), compare.clone()]).unwrap();
+    let sorted = ["apple", "fig", "pear"].iter().map(|&s| Value::from(s)).collect();
+    assert_eq!(arr, Value::array(sorted));
+
+    let mixed = Value::array(vec![Value::Int(1), Value::from("a")]);
+    let err = call(&mut rt, "sort_by", &[mixed, compare]).unwrap_err();
+    assert_eq!(err.error_kind().unwrap().to_string(), "type");
+}
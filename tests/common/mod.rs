@@ -0,0 +1,73 @@
+// Helpers shared by the integration tests.
+#![allow(dead_code)]
+
+use pan_lang_rs::capabilities::Capabilities;
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::types::futures::EventLoop;
+use pan_lang_rs::types::rope::Rope;
+use pan_lang_rs::value::{Fun, Native, Value};
+
+/// Call the global of the given name.
+pub fn call(runtime: &mut Runtime, name: &str, args: &[Value]) -> Result<Value, Value> {
+    let fun = runtime.global(name).unwrap_or_else(|| panic!("no global named {}", name));
+    runtime.call_raw(&fun, args)
+}
+
+/// The kind of a thrown error.
+pub fn kind(err: &Value) -> String {
+    err.error_kind().unwrap_or_else(|| panic!("not an error: {}", err)).to_string()
+}
+
+/// The message of a thrown error.
+pub fn message(err: &Value) -> String {
+    err.error_message().unwrap_or_else(|| panic!("not an error: {}", err)).to_string()
+}
+
+/// The field of a map, or `nil`.
+pub fn field(map: &Value, name: &str) -> Value {
+    match map {
+        Value::Map(map) => map.borrow().get(&Value::from(name)).cloned().unwrap_or(Value::Nil),
+        other => panic!("not a map: {}", other),
+    }
+}
+
+pub fn s(s: &str) -> Value {
+    Value::String(Rope::from_str(s))
+}
+
+pub fn int(n: i64) -> Value {
+    Value::Int(n)
+}
+
+pub fn float(x: f64) -> Value {
+    Value::Float(x.into())
+}
+
+pub fn arr(elements: Vec<Value>) -> Value {
+    Value::array(elements)
+}
+
+/// An array of the given ints.
+pub fn ints(ns: &[i64]) -> Value {
+    arr(ns.iter().map(|n| int(*n)).collect())
+}
+
+/// A native function that runs `fun`.
+pub fn native(fun: impl Fn(&[Value]) -> Result<Value, Value> + 'static) -> Value {
+    Value::Fun(Fun::Native(Native::new("f", fun)))
+}
+
+/// Whether two arrays, sets or maps are the same collection.
+pub fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Array(a), Value::Array(b)) => std::ptr::eq(&**a, &**b),
+        (Value::Map(a), Value::Map(b)) => std::ptr::eq(&**a, &**b),
+        (Value::Set(a), Value::Set(b)) => std::ptr::eq(&**a, &**b),
+        _ => panic!("not collections of the same type: {} and {}", a, b),
+    }
+}
+
+/// A runtime on a new event loop with the given capabilities.
+pub fn runtime(capabilities: &Capabilities) -> Runtime {
+    Runtime::with_capabilities(EventLoop::new(), capabilities).unwrap()
+}
@@ -0,0 +1,134 @@
+use std::cell::RefCell;
+
+use gc::{unsafe_empty_trace, Finalize, Trace};
+
+use pan_lang_rs::runtime::{HostCtx, Runtime};
+use pan_lang_rs::types::futures::Future;
+use pan_lang_rs::types::iter::{Iter, PanIter};
+use pan_lang_rs::types::stream::{self, PanStream, Stream};
+use pan_lang_rs::types::userdata::{PanUserdata, Userdata};
+use pan_lang_rs::value::Value;
+
+thread_local! {
+    // The value that the host types below call back into, and what that call returned.
+    static TARGET: RefCell<Option<(Value, Value)>> = const { RefCell::new(None) };
+    static CAUGHT: RefCell<Option<Result<Value, Value>>> = const { RefCell::new(None) };
+}
+
+fn call_back() {
+    let (fun, target) = TARGET.with(|t| t.borrow().clone().unwrap());
+    let result = fun.apply(&[target]);
+    CAUGHT.with(|caught| *caught.borrow_mut() = Some(result));
+}
+
+fn caught() -> Result<Value, Value> {
+    CAUGHT.with(|caught| caught.borrow_mut().take().unwrap())
+}
+
+fn kind(err: &Value) -> String {
+    err.error_kind().unwrap().to_string()
+}
+
+struct Reentrant;
+
+impl Finalize for Reentrant {}
+unsafe impl Trace for Reentrant {
+    unsafe_empty_trace!();
+}
+
+impl PanIter for Reentrant {
+    fn next(&mut self) -> Result<Option<Value>, Value> {
+        call_back();
+        Ok(None)
+    }
+}
+
+impl PanStream for Reentrant {
+    fn next(&mut self) -> Future {
+        call_back();
+        Future::resolve(stream::done())
+    }
+}
+
+#[test]
+fn advancing_an_iterator_from_its_own_next() {
+    let mut runtime = Runtime::new();
+    let it = Value::Iter(Iter::new(Reentrant));
+    let iter_next = runtime.global("iter_next").unwrap();
+    TARGET.with(|t| *t.borrow_mut() = Some((iter_next.clone(), it.clone())));
+    runtime.call_raw(&iter_next, &[it]).unwrap();
+    assert_eq!(kind(&caught().unwrap_err()), "concurrent-modification");
+}
+
+#[test]
+fn advancing_a_stream_from_its_own_next() {
+    let mut runtime = Runtime::new();
+    let s = Value::Stream(Stream::new(Reentrant));
+    let stream_next = runtime.global("stream_next").unwrap();
+    TARGET.with(|t| *t.borrow_mut() = Some((stream_next.clone(), s.clone())));
+    let fut = runtime.call_raw(&stream_next, &[s]).unwrap();
+    assert_eq!(runtime.block_on_raw(&fut), Ok(stream::done()));
+    let inner = caught().unwrap();
+    let err = runtime.block_on_raw(&inner).unwrap_err();
+    assert_eq!(kind(&err), "concurrent-modification");
+}
+
+#[derive(Debug)]
+struct Counter;
+
+impl PanUserdata for Counter {
+    fn type_name(&self) -> &'static str {
+        "counter"
+    }
+
+    fn call_method(
+        &mut self,
+        name: &str,
+        args: &[Value],
+        ctx: &mut HostCtx,
+    ) -> Result<Value, Value> {
+        match name {
+            // Calls the function `args[0]` with the counter itself, `args[1]`.
+            "reenter" => ctx.call(&args[0], &args[1..]),
+            "get" => Ok(Value::Int(0)),
+            _ => Ok(Value::Nil),
+        }
+    }
+}
+
+#[test]
+fn calling_a_method_from_a_method() {
+    let mut runtime = Runtime::new();
+    let counter = Value::Userdata(Userdata::new(Counter));
+    let ud_call = runtime.global("ud_call").unwrap();
+    runtime.register("get_again", |args| {
+        let ud_call = args[1].clone();
+        ud_call.apply(&[args[0].clone(), Value::from("get")])
+    });
+    let get_again = runtime.global("get_again").unwrap();
+    // `get_again` is called from inside `reenter`, with the counter and `ud_call`.
+    let err = runtime
+        .call_raw(&ud_call, &[
+            counter.clone(),
+            Value::from("reenter"),
+            get_again,
+            counter.clone(),
+            ud_call.clone(),
+        ])
+        .unwrap_err();
+    assert_eq!(kind(&err), "concurrent-modification");
+    // Afterwards the counter can be used again.
+    assert_eq!(runtime.call_raw(&ud_call, &[counter, Value::from("get")]), Ok(Value::Int(0)));
+}
+
+#[test]
+fn closing_a_userdata_from_its_method() {
+    let mut runtime = Runtime::new();
+    let counter = Value::Userdata(Userdata::new(Counter));
+    let ud_call = runtime.global("ud_call").unwrap();
+    let ud_close = runtime.global("ud_close").unwrap();
+    let args = [counter.clone(), Value::from("reenter"), ud_close.clone(), counter.clone()];
+    let err = runtime.call_raw(&ud_call, &args).unwrap_err();
+    assert_eq!(kind(&err), "concurrent-modification");
+    assert_eq!(runtime.call_raw(&ud_close, &[counter]), Ok(Value::Nil));
+}
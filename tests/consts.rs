@@ -0,0 +1,89 @@
+mod common;
+
+use pan_lang_rs::runtime::{ConstError, Runtime};
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, int, s, same};
+
+fn frozen(rt: &mut Runtime, v: Value) -> Value {
+    call(rt, "freeze", std::slice::from_ref(&v)).unwrap();
+    v
+}
+
+#[test]
+fn constants_are_globals_that_can_not_change() {
+    let mut rt = Runtime::new();
+    rt.define_const("DEBUG", Value::Bool(false)).unwrap();
+    rt.define_const("KEY", s("name")).unwrap();
+    assert_eq!(rt.global("DEBUG"), Some(Value::Bool(false)));
+
+    // A string constant works as a map key.
+    let map = Value::map(Default::default());
+    let key = rt.global("KEY").unwrap();
+    call(&mut rt, "map_insert", &[map.clone(), key, int(1)]).unwrap();
+    assert_eq!(call(&mut rt, "map_get", &[map, s("name")]), Ok(int(1)));
+
+    // Frozen collections are copied, and the copy is frozen all the way down.
+    let inner = frozen(&mut rt, arr(vec![int(1)]));
+    let outer = frozen(&mut rt, arr(vec![inner.clone(), s("x")]));
+    rt.define_const("LIMITS", outer.clone()).unwrap();
+    let limits = rt.global("LIMITS").unwrap();
+    assert_eq!(limits, outer);
+    assert!(!same(&limits, &outer));
+    let copied = call(&mut rt, "get", &[limits, int(0)]).unwrap();
+    assert!(!same(&copied, &inner));
+    assert_eq!(call(&mut rt, "is_frozen", std::slice::from_ref(&copied)), Ok(Value::Bool(true)));
+    assert!(call(&mut rt, "push", &[copied, int(2)]).is_err());
+
+    let names: Vec<_> = rt.consts().map(|(name, _)| name).collect();
+    assert_eq!(names, ["DEBUG", "KEY", "LIMITS"]);
+}
+
+#[test]
+fn constants_shadow_and_replace() {
+    let mut rt = Runtime::new();
+    rt.define("level", int(1)).unwrap();
+    let snapshot = rt.snapshot();
+    rt.define_const("level", int(2)).unwrap();
+    rt.define_const("len", s("shadowed")).unwrap();
+    assert_eq!(rt.global("level"), Some(int(2)));
+    assert_eq!(rt.global("len"), Some(s("shadowed")));
+
+    // Redefining replaces the value, restoring leaves constants alone.
+    rt.define_const("level", int(3)).unwrap();
+    rt.restore(&snapshot).unwrap();
+    assert_eq!(rt.global("level"), Some(int(3)));
+    assert_eq!(rt.consts().count(), 2);
+}
+
+#[test]
+fn only_literal_values_can_be_constants() {
+    let mut rt = Runtime::new();
+    let err = rt.define_const("XS", arr(vec![int(1)])).unwrap_err();
+    assert_eq!(err, ConstError::Mutable { name: "XS", type_name: "array" });
+    assert_eq!(
+        err.to_string(),
+        "the constant XS contains an unfrozen array, but constants must be immutable"
+    );
+    let nested = frozen(&mut rt, arr(vec![Value::map(Default::default())]));
+    let err = rt.define_const("NESTED", nested).unwrap_err();
+    assert_eq!(err, ConstError::Mutable { name: "NESTED", type_name: "map" });
+
+    let len = rt.global("len").unwrap();
+    let err = rt.define_const("F", len.clone()).unwrap_err();
+    assert_eq!(err, ConstError::Unsupported { name: "F", type_name: "function" });
+    assert!(err.to_string().starts_with("the constant F contains a value of type function"));
+    let fs = frozen(&mut rt, arr(vec![len]));
+    assert_eq!(rt.define_const("FS", fs), Err(ConstError::Unsupported {
+        name: "FS",
+        type_name: "function",
+    }));
+
+    let cycle = arr(vec![]);
+    call(&mut rt, "push", &[cycle.clone(), cycle.clone()]).unwrap();
+    let cycle = frozen(&mut rt, cycle);
+    let err = rt.define_const("CYCLE", cycle).unwrap_err();
+    assert_eq!(err, ConstError::Cyclic { name: "CYCLE" });
+    assert_eq!(err.to_string(), "the constant CYCLE contains itself");
+    assert_eq!(rt.consts().count(), 0);
+}
@@ -0,0 +1,143 @@
+mod common;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::value::Value;
+
+use common::{call, float, int, kind, message, s};
+
+#[test]
+fn to_int_truncates_and_converts() {
+    let mut rt = Runtime::new();
+    let cases = [
+        (float(2.9), 2),
+        (float(-2.9), -2),
+        (float(-0.0), 0),
+        (float(-9.223372036854776e18), i64::MIN),
+        (Value::Char('a'), 97),
+        (Value::Char('\u{10FFFF}'), 0x10FFFF),
+        (s("-42"), -42),
+        (s("+7"), 7),
+        (s("9223372036854775807"), i64::MAX),
+        (s("-9223372036854775808"), i64::MIN),
+        (int(5), 5),
+    ];
+    for (v, expected) in cases.iter().cloned() {
+        assert_eq!(call(&mut rt, "to_int", std::slice::from_ref(&v)), Ok(int(expected)), "{}", v);
+    }
+}
+
+#[test]
+fn to_int_throws_outside_the_range_of_ints() {
+    let mut rt = Runtime::new();
+    for &x in &[f64::NAN, f64::INFINITY, 9.223372036854776e18, -1e19] {
+        let err = call(&mut rt, "to_int", &[float(x)]).unwrap_err();
+        assert_eq!(kind(&err), "range", "{}", x);
+    }
+    let err = call(&mut rt, "to_int", &[float(f64::NAN)]).unwrap_err();
+    assert_eq!(message(&err), "to_int cannot convert NaN to an int");
+    let err = call(&mut rt, "to_int", &[s("9223372036854775808")]).unwrap_err();
+    assert_eq!(kind(&err), "range");
+    assert!(message(&err).contains("\"9223372036854775808\""));
+    let err = call(&mut rt, "to_int", &[s("0x10")]).unwrap_err();
+    assert_eq!(message(&err), "to_int cannot parse \"0x10\" as an int in base 10");
+    assert_eq!(kind(&call(&mut rt, "to_int", &[Value::Nil]).unwrap_err()), "type");
+}
+
+#[test]
+fn parse_int_in_other_bases() {
+    let mut rt = Runtime::new();
+    let cases = [
+        ("ff", 16, 255),
+        ("0xff", 16, 255),
+        ("0XFF", 16, 255),
+        ("-0x8000000000000000", 16, i64::MIN),
+        ("0b101", 2, 5),
+        ("0o17", 8, 15),
+        ("zz", 36, 1295),
+        ("Zz", 36, 1295),
+        ("-10", 2, -2),
+    ];
+    for &(text, base, expected) in &cases {
+        let parsed = call(&mut rt, "parse_int", &[s(text), int(base)]);
+        assert_eq!(parsed, Ok(int(expected)), "{} in base {}", text, base);
+    }
+    assert_eq!(call(&mut rt, "parse_int", &[s("10")]), Ok(int(10)));
+}
+
+#[test]
+fn parse_int_rejects_what_the_literal_syntax_rejects() {
+    let mut rt = Runtime::new();
+    let invalid = [
+        ("1_000", 10),
+        ("", 10),
+        ("-", 10),
+        ("0x", 16),
+        ("+-1", 10),
+        (" 1", 10),
+        ("0x10", 10),
+        ("0b2", 2),
+        ("g", 16),
+    ];
+    for &(text, base) in &invalid {
+        let err = call(&mut rt, "parse_int", &[s(text), int(base)]).unwrap_err();
+        assert_eq!(kind(&err), "parse", "{:?} in base {}", text, base);
+        assert!(message(&err).contains(&format!("{:?}", text)));
+    }
+    let err = call(&mut rt, "parse_int", &[s("8000000000000000"), int(16)]).unwrap_err();
+    assert_eq!(kind(&err), "range");
+    for &base in &[0, 1, 37] {
+        let err = call(&mut rt, "parse_int", &[s("1"), int(base)]).unwrap_err();
+        assert_eq!(kind(&err), "range", "base {}", base);
+    }
+    assert_eq!(kind(&call(&mut rt, "parse_int", &[s("1"), s("10")]).unwrap_err()), "type");
+    assert_eq!(kind(&call(&mut rt, "parse_int", &[int(1)]).unwrap_err()), "type");
+}
+
+#[test]
+fn floats_round_trip_through_strings() {
+    let mut rt = Runtime::new();
+    let floats = [
+        0.1,
+        -0.0,
+        1.0,
+        1e300,
+        5e-324,
+        f64::MAX,
+        f64::MIN_POSITIVE,
+        std::f64::consts::PI,
+        1.0 / 3.0,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+    ];
+    for &x in &floats {
+        let text = call(&mut rt, "to_string", &[float(x)]).unwrap();
+        let parsed = call(&mut rt, "parse_float", std::slice::from_ref(&text)).unwrap();
+        match parsed {
+            Value::Float(y) => assert_eq!(y.to_bits(), x.to_bits(), "{}", text),
+            other => panic!("not a float: {:?}", other),
+        }
+    }
+    let nan = call(&mut rt, "to_string", &[float(f64::NAN)]).unwrap();
+    assert_eq!(nan, s("NaN"));
+    match call(&mut rt, "parse_float", &[nan]).unwrap() {
+        Value::Float(x) => assert!(x.is_nan()),
+        other => panic!("not a float: {:?}", other),
+    }
+    assert_eq!(call(&mut rt, "to_string", &[float(1.0)]), Ok(s("1.0")));
+    assert_eq!(call(&mut rt, "to_string", &[int(-3)]), Ok(s("-3")));
+}
+
+#[test]
+fn to_float_and_parse_float() {
+    let mut rt = Runtime::new();
+    assert_eq!(call(&mut rt, "to_float", &[int(3)]), Ok(float(3.0)));
+    assert_eq!(call(&mut rt, "to_float", &[int(i64::MAX)]), Ok(float(9.223372036854776e18)));
+    assert_eq!(call(&mut rt, "to_float", &[s(".5")]), Ok(float(0.5)));
+    assert_eq!(call(&mut rt, "parse_float", &[s("1E3")]), Ok(float(1000.0)));
+    let err = call(&mut rt, "parse_float", &[s("1.0x")]).unwrap_err();
+    assert_eq!(kind(&err), "parse");
+    assert_eq!(message(&err), "parse_float cannot parse \"1.0x\" as a float");
+    let err = call(&mut rt, "to_float", &[s("one")]).unwrap_err();
+    assert_eq!(message(&err), "to_float cannot parse \"one\" as a float");
+    assert_eq!(kind(&call(&mut rt, "to_float", &[Value::Char('1')]).unwrap_err()), "type");
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::types::bytes::Bytes;
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, field, kind, message, s};
+
+fn rows(rows: &[&[&str]]) -> Value {
+    arr(rows.iter().map(|row| arr(row.iter().map(|f| s(f)).collect())).collect())
+}
+
+fn parse(rt: &mut Runtime, input: &str) -> Result<Value, Value> {
+    call(rt, "csv_parse", &[s(input)])
+}
+
+fn parse_with(rt: &mut Runtime, input: &str, options: Vec<(&str, Value)>) -> Result<Value, Value> {
+    call(rt, "csv_parse", &[s(input), Value::record(options)])
+}
+
+#[test]
+fn line_breaks() {
+    let mut rt = Runtime::new();
+    let expected = rows(&[&["a", "b"], &["c", "d"]]);
+    assert_eq!(parse(&mut rt, "a,b\r\nc,d\r\n"), Ok(expected.clone()));
+    assert_eq!(parse(&mut rt, "a,b\nc,d"), Ok(expected.clone()));
+    assert_eq!(parse(&mut rt, "a,b\rc,d\r"), Ok(expected));
+    assert_eq!(parse(&mut rt, ""), Ok(rows(&[])));
+    assert_eq!(parse(&mut rt, "a\n\nb"), Ok(rows(&[&["a"], &[""], &["b"]])));
+}
+
+#[test]
+fn quoting() {
+    let mut rt = Runtime::new();
+    let parsed = parse(&mut rt, "\"a,b\",\"say \"\"hi\"\"\",\"\"\r\n\"x\r\ny\",z,\"\"\"\"\n");
+    assert_eq!(parsed, Ok(rows(&[&["a,b", "say \"hi\"", ""], &["x\r\ny", "z", "\""]])));
+    let err = parse(&mut rt, "ab,c\"d\n").unwrap_err();
+    assert_eq!(kind(&err), "parse");
+    assert_eq!(
+        message(&err),
+        "csv_parse failed: quote inside an unquoted field at row 1, column 2",
+    );
+    let err = parse(&mut rt, "a\n\"b\"c\n").unwrap_err();
+    assert_eq!(
+        message(&err),
+        "csv_parse failed: unexpected character after a closing quote at row 2, column 1",
+    );
+    let err = parse(&mut rt, "a,\"b\nc").unwrap_err();
+    assert_eq!(message(&err), "csv_parse failed: unclosed quoted field at row 1, column 2");
+}
+
+#[test]
+fn ragged_rows() {
+    let mut rt = Runtime::new();
+    let err = parse(&mut rt, "a,b,c\nd\n").unwrap_err();
+    assert_eq!(message(&err), "csv_parse failed: expected 3 fields, got 1 at row 2, column 2");
+    let lenient = parse_with(&mut rt, "a,b,c\nd\n", vec![("mode", s("lenient"))]).unwrap();
+    assert_eq!(lenient, arr(vec![
+        arr(vec![s("a"), s("b"), s("c")]),
+        arr(vec![s("d"), Value::Nil, Value::Nil]),
+    ]));
+    let err = parse_with(&mut rt, "a\nb,c\n", vec![("mode", s("lenient"))]).unwrap_err();
+    assert_eq!(kind(&err), "parse");
+}
+
+#[test]
+fn headers() {
+    let mut rt = Runtime::new();
+    let parsed = parse_with(&mut rt, "name,n\nx,1\ny,2\n", vec![("headers", Value::Bool(true))]);
+    let parsed = parsed.unwrap();
+    let first = match &parsed {
+        Value::Array(rows) => rows.borrow()[0].clone(),
+        other => panic!("{}", other),
+    };
+    assert_eq!(field(&first, "name"), s("x"));
+    assert_eq!(field(&first, "n"), s("1"));
+    let err = parse_with(&mut rt, "a,a\n", vec![("headers", Value::Bool(true))]).unwrap_err();
+    assert_eq!(message(&err), "csv_parse failed: duplicate column name \"a\" at row 1, column 2");
+}
+
+#[test]
+fn delimiters() {
+    let mut rt = Runtime::new();
+    let parsed = parse_with(&mut rt, "a;b\n", vec![("delimiter", s(";"))]);
+    assert_eq!(parsed, Ok(rows(&[&["a", "b"]])));
+    let parsed = parse_with(&mut rt, "a\tb\n", vec![("delimiter", Value::Char('\t'))]);
+    assert_eq!(parsed, Ok(rows(&[&["a", "b"]])));
+    for delimiter in &["§", "→", "\"", "\n", ";;"] {
+        let err = parse_with(&mut rt, "a", vec![("delimiter", s(delimiter))]).unwrap_err();
+        assert_eq!(kind(&err), "range", "{:?}", delimiter);
+    }
+    let err = call(&mut rt, "csv_emit", &[rows(&[]), Value::record(vec![("delimiter", s("ä"))])]);
+    assert_eq!(kind(&err.unwrap_err()), "range");
+}
+
+#[test]
+fn bytes_input() {
+    let mut rt = Runtime::new();
+    let input = Value::Bytes(Bytes::from_slice("ä,ö\n".as_bytes()));
+    assert_eq!(call(&mut rt, "csv_parse", &[input]), Ok(rows(&[&["ä", "ö"]])));
+    let broken = Value::Bytes(Bytes::from_slice(&[b'a', b',', 0xff, b'\n']));
+    let err = call(&mut rt, "csv_parse", &[broken]).unwrap_err();
+    assert_eq!(kind(&err), "decode");
+    assert_eq!(message(&err), "csv_parse failed: invalid utf-8 at row 1, column 2");
+}
+
+#[test]
+fn emit_quotes_only_when_necessary() {
+    let mut rt = Runtime::new();
+    let table = arr(vec![
+        arr(vec![s("plain"), s("a,b"), s("q\"q"), s("line\nbreak"), s(" space ")]),
+        arr(vec![Value::Int(1), Value::Nil, Value::Bool(true), s(""), s("x")]),
+    ]);
+    let emitted = call(&mut rt, "csv_emit", &[table]);
+    assert_eq!(emitted, Ok(s(
+        "plain,\"a,b\",\"q\"\"q\",\"line\nbreak\", space \r\n1,,true,,x\r\n",
+    )));
+}
+
+#[test]
+fn emit_maps_in_column_order() {
+    let mut rt = Runtime::new();
+    let table = arr(vec![
+        Value::record(vec![("b", s("1")), ("a", s("2"))]),
+        Value::record(vec![("a", s("3"))]),
+    ]);
+    let options = Value::record(vec![("columns", arr(vec![s("b"), s("a")]))]);
+    let emitted = call(&mut rt, "csv_emit", &[table.clone(), options]);
+    assert_eq!(emitted, Ok(s("b,a\r\n1,2\r\n,3\r\n")));
+    let options = Value::record(vec![("headers", Value::Bool(false))]);
+    assert_eq!(call(&mut rt, "csv_emit", &[table, options]), Ok(s("2,1\r\n3,\r\n")));
+    let mixed = arr(vec![Value::record(vec![]), arr(vec![])]);
+    let err = call(&mut rt, "csv_emit", &[mixed]).unwrap_err();
+    assert_eq!(kind(&err), "type");
+}
+
+#[test]
+fn round_trips_are_stable() {
+    let mut rt = Runtime::new();
+    let tricky = "\"a,b\",\"\"\"\",\"x\r\ny\",plain\r\n,\"\",\"🌍\",\" \"\r\n";
+    let parsed = parse(&mut rt, tricky).unwrap();
+    let emitted = call(&mut rt, "csv_emit", std::slice::from_ref(&parsed)).unwrap();
+    let reparsed = call(&mut rt, "csv_parse", std::slice::from_ref(&emitted)).unwrap();
+    assert_eq!(reparsed, parsed);
+    let emitted_again = call(&mut rt, "csv_emit", &[reparsed]).unwrap();
+    assert_eq!(emitted_again, emitted);
+}
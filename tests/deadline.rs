@@ -0,0 +1,116 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use pan_lang_rs::runtime::{Arity, RunError, Runtime};
+use pan_lang_rs::types::futures::{Clock, ManualClock};
+use pan_lang_rs::types::interrupt::{self, DeadlineExceeded};
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, int, s};
+
+fn manual() -> (Runtime, Rc<ManualClock>) {
+    let clock = Rc::new(ManualClock::new());
+    (Runtime::with_clock(clock.clone()), clock)
+}
+
+fn exceeded(elapsed_millis: u64, limit_millis: u64) -> DeadlineExceeded {
+    DeadlineExceeded { elapsed_millis, limit_millis }
+}
+
+fn millis(n: u64) -> Duration {
+    Duration::from_millis(n)
+}
+
+#[test]
+fn natives_give_up_at_the_deadline() {
+    let (mut rt, clock) = manual();
+    let remaining = Rc::new(RefCell::new(vec![]));
+    let (tick, seen) = (clock.clone(), remaining.clone());
+    // Works in steps of 30 ms while there is a deadline.
+    rt.register_native("work", Arity::Max(0), move |ctx, _| {
+        while let Some(left) = ctx.remaining_time() {
+            seen.borrow_mut().push(left);
+            ctx.check_interrupt()?;
+            tick.advance(30);
+        }
+        Ok(int(0))
+    })
+    .unwrap();
+    let work = rt.global("work").unwrap();
+
+    match rt.call_with_deadline(&work, &[], millis(100)) {
+        Err(RunError::DeadlineExceeded(e)) => assert_eq!(e, exceeded(120, 100)),
+        other => panic!("expected a missed deadline, got {:?}", other),
+    }
+    let expected: Vec<Duration> = [100, 70, 40, 10, 0].iter().map(|n| millis(*n)).collect();
+    assert_eq!(*remaining.borrow(), expected);
+    let err = RunError::DeadlineExceeded(exceeded(120, 100));
+    assert_eq!(err.to_string(), "deadline exceeded: ran for 120 ms with a limit of 100 ms");
+    assert!(interrupt::deadline_exceeded_of(&err.into_value()).is_some());
+
+    // The deadline only applies to its call.
+    assert_eq!(rt.call(&work, &[]).unwrap(), int(0));
+    let len = rt.global("len").unwrap();
+    assert_eq!(rt.call_with_deadline(&len, &[arr(vec![])], millis(1)).unwrap(), int(0));
+    assert_eq!(clock.now(), 120);
+}
+
+#[test]
+fn missed_deadlines_pass_through_assert_throws_and_callbacks() {
+    let (mut rt, clock) = manual();
+    let tick = clock.clone();
+    rt.register_native("tick", Arity::Max(0), move |_, _| {
+        tick.advance(10);
+        Ok(Value::Nil)
+    })
+    .unwrap();
+    let tick = rt.global("tick").unwrap();
+    // Calls back into `tick` until that fails.
+    rt.register_native("repeat", Arity::Max(0), move |ctx, _| loop {
+        ctx.call(&tick, &[])?;
+    })
+    .unwrap();
+    let repeat = rt.global("repeat").unwrap();
+    let assert_throws = rt.global("assert_throws").unwrap();
+    match rt.call_with_deadline(&assert_throws, &[repeat], millis(25)) {
+        Err(RunError::DeadlineExceeded(e)) => assert_eq!(e, exceeded(30, 25)),
+        other => panic!("expected a missed deadline, got {:?}", other),
+    }
+}
+
+#[test]
+fn block_on_cancels_futures_that_miss_the_deadline() {
+    let (mut rt, clock) = manual();
+    let slow = call(&mut rt, "fut_sleep", &[int(1000)]).unwrap();
+    let other = call(&mut rt, "fut_sleep", &[int(2000)]).unwrap();
+    let both = call(&mut rt, "fut_all", &[arr(vec![slow.clone(), other.clone()])]).unwrap();
+    match rt.block_on_with_deadline(&both, millis(100)) {
+        Err(RunError::DeadlineExceeded(e)) => assert_eq!(e, exceeded(100, 100)),
+        other => panic!("expected a missed deadline, got {:?}", other),
+    }
+    // The loop did not sleep past the deadline, and nothing is left running.
+    assert_eq!(clock.now(), 100);
+    for fut in [&both, &slow, &other].iter() {
+        let state = call(&mut rt, "fut_state", std::slice::from_ref(*fut)).unwrap();
+        assert_eq!(state, s("cancelled"));
+    }
+    assert!(rt.event_loop().active_jobs().is_empty());
+
+    // Futures that are fast enough are not affected.
+    let fast = call(&mut rt, "fut_sleep", &[int(10)]).unwrap();
+    assert_eq!(rt.block_on_with_deadline(&fast, millis(100)).unwrap(), Value::Nil);
+    assert_eq!(clock.now(), 110);
+
+    // Running out of work is still a deadlock rather than a missed deadline.
+    let never = call(&mut rt, "fut_never", &[]).unwrap();
+    match rt.block_on_with_deadline(&never, millis(100)) {
+        Err(RunError::Thrown { value, .. }) => {
+            assert_eq!(value.error_kind().unwrap().to_string(), "deadlock")
+        }
+        other => panic!("expected a deadlock, got {:?}", other),
+    }
+    assert_eq!(clock.now(), 110);
+}
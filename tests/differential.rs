@@ -0,0 +1,52 @@
+#![cfg(feature = "testing")]
+
+use pan_lang_rs::testing::ir_gen::{
+    differential, Execution, GenConfig, Outcome, Program, EXECUTIONS,
+};
+
+#[test]
+fn generated_programs_behave_the_same_in_all_executions() {
+    if let Err(mismatch) = differential(0x5eed, 3_000, &GenConfig::default()) {
+        panic!("{}", mismatch);
+    }
+}
+
+#[test]
+fn larger_programs_behave_the_same_in_all_executions() {
+    let config = GenConfig { max_len: 48, max_depth: 3, storage_size: 2, env_size: 3 };
+    if let Err(mismatch) = differential(1, 500, &config) {
+        panic!("{}", mismatch);
+    }
+}
+
+// Seeds of the default config whose programs catch values and write them to storage, with the
+// outcomes they had when they were pinned, so that a change of the semantics of the baseline
+// interpreter shows up too, not only differences between the executions.
+const PINNED: &[(u64, &str)] = &[
+    (16, "threw nil"),
+    (60, "returned []"),
+    (88, "returned 1.0"),
+    (91, "returned {\"kind\": \"type\", \"message\": \"cannot call a value of type nil\"}"),
+    (8, "threw {\"kind\": \"type\", \"message\": \"cannot call a value of type nil\"}"),
+    (17, "returned nil"),
+];
+
+fn describe(outcome: &Outcome) -> String {
+    match outcome {
+        Outcome::Returned(v) => format!("returned {}", v),
+        Outcome::Threw(v) => format!("threw {}", v),
+    }
+}
+
+#[test]
+fn pinned_seeds() {
+    for &(seed, expected) in PINNED {
+        let program = Program::generate(seed, &GenConfig::default());
+        assert!(program.disassemble().contains("Catch"), "seed {} no longer catches", seed);
+        for &execution in EXECUTIONS.iter() {
+            let found = describe(&program.run(execution));
+            assert_eq!(found, expected, "seed {} in {:?}:\n{:?}", seed, execution, program);
+        }
+        assert_eq!(describe(&program.run(Execution::Baseline)), expected);
+    }
+}
@@ -0,0 +1,191 @@
+mod common;
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures::channel::oneshot;
+use futures::executor::{block_on, LocalPool};
+use futures::future::{self, FutureExt};
+use futures::task::LocalSpawnExt;
+
+use pan_lang_rs::runtime::{RunError, Runtime};
+use pan_lang_rs::types::futures::ManualClock;
+use pan_lang_rs::value::{Fun, Native, Value};
+
+use common::{call, int, kind, s};
+
+// A sleep for executors without timers of their own: a thread wakes the task once it is over.
+fn thread_sleep(duration: Duration) -> impl Future<Output = ()> {
+    let (done, wait) = oneshot::channel();
+    thread::spawn(move || {
+        thread::sleep(duration);
+        let _ = done.send(());
+    });
+    wait.map(|_| ())
+}
+
+#[test]
+fn timers_sleep_in_the_other_executor() {
+    let mut rt = Runtime::new();
+    let fut = call(&mut rt, "fut_sleep", &[int(30)]).unwrap();
+    let sleeps = Rc::new(RefCell::new(vec![]));
+    let recorded = sleeps.clone();
+    let start = Instant::now();
+    let outcome = block_on(rt.drive(&fut, move |duration| {
+        recorded.borrow_mut().push(duration);
+        thread_sleep(duration)
+    }).unwrap());
+    assert_eq!(outcome, Ok(Value::Nil));
+    assert!(start.elapsed() >= Duration::from_millis(30));
+    // One sleep for the timer, and possibly a short one if the clock of the loop lags behind.
+    let sleeps = sleeps.borrow();
+    assert!(!sleeps.is_empty() && sleeps.len() <= 3, "slept {:?}", sleeps);
+    assert!(sleeps[0] > Duration::from_millis(20));
+}
+
+#[test]
+fn deterministic_loops_do_not_sleep() {
+    let mut rt = Runtime::new_deterministic(1);
+    let fut = call(&mut rt, "fut_sleep", &[int(60_000)]).unwrap();
+    let sleeps = Rc::new(RefCell::new(vec![]));
+    let recorded = sleeps.clone();
+    let outcome = block_on(rt.drive(&fut, move |duration| {
+        recorded.borrow_mut().push(duration);
+        future::ready(())
+    }).unwrap());
+    assert_eq!(outcome, Ok(Value::Nil));
+    // Their virtual clock jumps to the next timer by itself.
+    assert!(sleeps.borrow().is_empty());
+    assert!(rt.event_loop().now() >= 60_000);
+}
+
+#[test]
+fn manual_clocks_advance_to_the_deadline() {
+    let mut rt = Runtime::with_clock(Rc::new(ManualClock::new()));
+    let fut = call(&mut rt, "fut_sleep", &[int(60_000)]).unwrap();
+    let sleeps = Rc::new(RefCell::new(vec![]));
+    let recorded = sleeps.clone();
+    let outcome = block_on(rt.drive(&fut, move |duration| {
+        recorded.borrow_mut().push(duration);
+        future::ready(())
+    }).unwrap());
+    assert_eq!(outcome, Ok(Value::Nil));
+    assert_eq!(*sleeps.borrow(), vec![Duration::from_millis(60_000)]);
+    assert_eq!(rt.event_loop().now(), 60_000);
+}
+
+#[test]
+fn rust_futures_woken_by_other_threads_are_awaited() {
+    let wait = |rt: &Runtime| {
+        let (sender, receiver) = oneshot::channel();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            let _ = sender.send(7);
+        });
+        rt.spawn_rust(receiver.map(|n| Ok(int(n.expect("the sender sends")))))
+    };
+
+    // Blocking on the future gives up, as no other executor could wake it.
+    let mut rt = Runtime::new();
+    let fut = wait(&rt);
+    assert_eq!(kind(&rt.block_on_raw(&fut).unwrap_err()), "deadlock");
+
+    let rt = Runtime::new();
+    let fut = wait(&rt);
+    let outcome = block_on(rt.drive(&fut, |_| -> future::Ready<()> {
+        panic!("there are no timers")
+    }).unwrap());
+    assert_eq!(outcome, Ok(int(7)));
+}
+
+#[test]
+fn rejections_and_cancellations() {
+    let mut rt = Runtime::new();
+    let rejected = call(&mut rt, "fut_reject", &[s("no")]).unwrap();
+    let outcome = block_on(rt.drive(&rejected, |_| future::ready(())).unwrap());
+    assert_eq!(outcome, Err(s("no")));
+
+    let never = call(&mut rt, "fut_never", &[]).unwrap();
+    rt.event_loop().run_until_idle();
+    let timeout = call(&mut rt, "fut_sleep", &[int(10)]).unwrap();
+    let cancel = call(&mut rt, "fut_then", &[timeout, cancel_fun(&never)]).unwrap();
+    rt.handle(&cancel).unwrap();
+    let outcome = block_on(rt.drive(&never, thread_sleep).unwrap());
+    assert_eq!(kind(&outcome.unwrap_err()), "cancelled");
+
+    assert_eq!(kind(&rt.drive(&int(1), thread_sleep).err().unwrap()), "type");
+}
+
+// A function that cancels the future.
+fn cancel_fun(fut: &Value) -> Value {
+    let fut = fut.clone();
+    Value::Fun(Fun::Native(Native::new("cancel", move |_| {
+        match &fut {
+            Value::Future(fut) => Ok(Value::Bool(fut.cancel())),
+            other => panic!("not a future: {:?}", other),
+        }
+    })))
+}
+
+#[test]
+fn block_on_settles_chains_and_detects_deadlocks() {
+    let mut rt = Runtime::new();
+    let sleep = call(&mut rt, "fut_sleep", &[int(5)]).unwrap();
+    let add = Value::Fun(Fun::Native(Native::new("add", |_| Ok(int(2)))));
+    let chain = call(&mut rt, "fut_then", &[sleep, add]).unwrap();
+    assert_eq!(rt.block_on(&chain).unwrap(), int(2));
+
+    let rejected = call(&mut rt, "fut_reject", &[s("no")]).unwrap();
+    match rt.block_on(&rejected) {
+        Err(RunError::Thrown { value, rendered }) => {
+            assert_eq!(value, s("no"));
+            assert_eq!(rendered, "\"no\"");
+        }
+        other => panic!("unexpected outcome: {:?}", other),
+    }
+
+    let never = call(&mut rt, "fut_never", &[]).unwrap();
+    let start = Instant::now();
+    match rt.block_on(&never) {
+        Err(RunError::Thrown { value, .. }) => assert_eq!(kind(&value), "deadlock"),
+        other => panic!("unexpected outcome: {:?}", other),
+    }
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    match rt.block_on(&int(1)) {
+        Err(RunError::Thrown { value, .. }) => assert_eq!(kind(&value), "type"),
+        other => panic!("unexpected outcome: {:?}", other),
+    }
+    assert_eq!(kind(&rt.handle(&int(1)).err().unwrap()), "type");
+}
+
+#[test]
+fn handles_are_awaited_by_other_executors() {
+    let mut rt = Runtime::new_deterministic(375);
+    let sleep = call(&mut rt, "fut_sleep", &[int(20)]).unwrap();
+    let answer = Value::Fun(Fun::Native(Native::new("answer", |_| Ok(int(42)))));
+    let chain = call(&mut rt, "fut_then", &[sleep, answer]).unwrap();
+    let handle = rt.handle(&chain).unwrap();
+
+    let mut pool = LocalPool::new();
+    let outcome = Rc::new(RefCell::new(None));
+    let recorded = outcome.clone();
+    pool.spawner()
+        .spawn_local(async move {
+            let n = handle.await.unwrap();
+            *recorded.borrow_mut() = Some(n);
+        })
+        .unwrap();
+    // Both executors take turns until the rust task is done.
+    let mut turns = 0;
+    while outcome.borrow().is_none() {
+        pool.run_until_stalled();
+        rt.event_loop().run_until_idle();
+        turns += 1;
+        assert!(turns < 10, "the handle was not woken");
+    }
+    assert_eq!(*outcome.borrow(), Some(int(42)));
+}
@@ -0,0 +1,134 @@
+#![cfg(feature = "secure-random")]
+
+mod common;
+
+use std::collections::BTreeSet;
+
+use pan_lang_rs::builtins::entropy::MAX_RANDOM_BYTES;
+use pan_lang_rs::capabilities::{Capabilities, Capability};
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::types::futures::EventLoop;
+use pan_lang_rs::value::Value;
+
+use common::{call, int, kind, message};
+
+fn bytes(v: Value) -> Vec<u8> {
+    match &v {
+        Value::Bytes(b) => b.to_vec(),
+        other => panic!("not bytes: {}", other),
+    }
+}
+
+fn uuid(rt: &mut Runtime) -> String {
+    match &call(rt, "uuid_v4", &[]).unwrap() {
+        Value::String(s) => s.to_string(),
+        other => panic!("not a string: {}", other),
+    }
+}
+
+#[test]
+fn random_bytes_have_the_requested_length() {
+    let mut rt = Runtime::new();
+    for &n in &[0, 1, 15, 16, 1000, MAX_RANDOM_BYTES] {
+        let b = bytes(call(&mut rt, "random_bytes", &[int(n as i64)]).unwrap());
+        assert_eq!(b.len(), n);
+    }
+    // Long outputs are not constant, and consecutive ones differ.
+    let a = bytes(call(&mut rt, "random_bytes", &[int(64)]).unwrap());
+    let b = bytes(call(&mut rt, "random_bytes", &[int(64)]).unwrap());
+    assert_ne!(a, b);
+    assert!(a.iter().collect::<BTreeSet<_>>().len() > 16);
+}
+
+#[test]
+fn random_bytes_reject_bad_lengths() {
+    let mut rt = Runtime::new();
+    for &n in &[-1, MAX_RANDOM_BYTES as i64 + 1, i64::MIN, i64::MAX] {
+        let err = call(&mut rt, "random_bytes", &[int(n)]).unwrap_err();
+        assert_eq!(kind(&err), "range", "{}", n);
+    }
+    assert_eq!(kind(&call(&mut rt, "random_bytes", &[]).unwrap_err()), "type");
+    assert_eq!(kind(&call(&mut rt, "random_bytes", &[Value::Nil]).unwrap_err()), "type");
+}
+
+#[test]
+fn uuids_are_canonical_version_4() {
+    let mut rt = Runtime::new();
+    let mut seen = BTreeSet::new();
+    // Every bit that is not fixed by the version and variant is set in some sample.
+    let (mut ones, mut zeros) = ([0u8; 16], [0u8; 16]);
+    for _ in 0..1000 {
+        let uuid = uuid(&mut rt);
+        assert_eq!(uuid.len(), 36, "{}", uuid);
+        let groups: Vec<&str> = uuid.split('-').collect();
+        let lengths: Vec<usize> = groups.iter().map(|g| g.len()).collect();
+        assert_eq!(lengths, vec![8, 4, 4, 4, 12], "{}", uuid);
+        assert!(uuid.chars().all(|c| c == '-' || c.is_ascii_digit() || ('a'..='f').contains(&c)));
+
+        let hex: String = groups.concat();
+        let b: Vec<u8> = (0..16).map(|i| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap())
+            .collect();
+        assert_eq!(b[6] >> 4, 4, "{}", uuid);
+        assert_eq!(b[8] >> 6, 0b10, "{}", uuid);
+        assert!(uuid[14..15] == *"4" && "89ab".contains(&uuid[19..20]), "{}", uuid);
+        for i in 0..16 {
+            ones[i] |= b[i];
+            zeros[i] |= !b[i];
+        }
+        assert!(seen.insert(uuid));
+    }
+    for i in 0..16 {
+        let fixed = match i {
+            6 => 0xf0,
+            8 => 0xc0,
+            _ => 0,
+        };
+        assert_eq!(ones[i] | fixed, 0xff, "byte {}", i);
+        assert_eq!(zeros[i] | fixed, 0xff, "byte {}", i);
+    }
+}
+
+#[test]
+fn entropy_can_be_denied() {
+    let mut rt = Runtime::new();
+    rt.allow_entropy(false);
+    for (name, args) in [("random_bytes", vec![int(4)]), ("uuid_v4", vec![])].iter() {
+        let err = call(&mut rt, name, args).unwrap_err();
+        assert_eq!(kind(&err), "forbidden", "{}", name);
+        assert!(message(&err).starts_with(name), "{}", message(&err));
+    }
+    rt.allow_entropy(true);
+    assert!(call(&mut rt, "uuid_v4", &[]).is_ok());
+
+    // Deterministic runtimes deny it by default, and without the capability it does not exist.
+    let mut rt = Runtime::new_deterministic(1);
+    assert_eq!(kind(&call(&mut rt, "uuid_v4", &[]).unwrap_err()), "forbidden");
+    let caps = Capabilities::SCRIPTING.deny(Capability::Entropy);
+    let rt = Runtime::with_capabilities(EventLoop::new(), &caps).unwrap();
+    assert!(rt.global("random_bytes").is_none() && rt.global("uuid_v4").is_none());
+}
+
+#[test]
+fn entropy_and_the_seeded_generator_are_independent() {
+    let draws = |use_entropy: bool| {
+        let mut rt = Runtime::new_deterministic(418);
+        rt.allow_entropy(true);
+        let mut values = vec![];
+        for _ in 0..10 {
+            if use_entropy {
+                call(&mut rt, "random_bytes", &[int(32)]).unwrap();
+                call(&mut rt, "uuid_v4", &[]).unwrap();
+            }
+            values.push(call(&mut rt, "random_int", &[int(0), int(1 << 40)]).unwrap());
+        }
+        values
+    };
+    assert_eq!(draws(true), draws(false));
+
+    // Seeding the generator does not make the entropy builtins repeat.
+    let mut a = Runtime::new_deterministic(418);
+    let mut b = Runtime::new_deterministic(418);
+    a.allow_entropy(true);
+    b.allow_entropy(true);
+    assert_ne!(uuid(&mut a), uuid(&mut b));
+}
@@ -0,0 +1,179 @@
+#![allow(clippy::mutable_key_type)]
+
+mod common;
+
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+
+use pan_lang_rs::builtins::io::MAX_READ_LEN;
+use pan_lang_rs::capabilities::Capabilities;
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::types::futures::EventLoop;
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, kind, message, s};
+
+fn vars(pairs: &[(&str, &str)]) -> Option<BTreeMap<String, String>> {
+    Some(pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect())
+}
+
+fn with_stdin(input: &[u8]) -> Runtime {
+    let mut rt = Runtime::new();
+    rt.set_stdin(Box::new(io::Cursor::new(input.to_vec())));
+    rt
+}
+
+#[test]
+fn args_are_what_the_embedder_installed() {
+    let mut rt = Runtime::new();
+    assert_eq!(call(&mut rt, "args", &[]), Ok(arr(vec![])));
+    rt.set_args(vec!["--flag".into(), "".into(), "ünï côdé".into()]);
+    let args = call(&mut rt, "args", &[]).unwrap();
+    assert_eq!(args, arr(vec![s("--flag"), s(""), s("ünï côdé")]));
+
+    // Every call returns a new array.
+    call(&mut rt, "clear", std::slice::from_ref(&args)).unwrap();
+    let again = call(&mut rt, "args", &[]).unwrap();
+    assert_eq!(again, arr(vec![s("--flag"), s(""), s("ünï côdé")]));
+}
+
+#[test]
+fn injected_variables_replace_the_process_environment() {
+    let mut rt = Runtime::new();
+    rt.set_env(vars(&[("HOME", "/nowhere"), ("EMPTY", ""), ("ÜNI", "cödé")]));
+    assert_eq!(call(&mut rt, "env", &[s("HOME")]), Ok(s("/nowhere")));
+    assert_eq!(call(&mut rt, "env", &[s("EMPTY")]), Ok(s("")));
+    assert_eq!(call(&mut rt, "env", &[s("ÜNI")]), Ok(s("cödé")));
+    assert_eq!(call(&mut rt, "env", &[s("PATH")]), Ok(Value::Nil));
+    let all = call(&mut rt, "env_all", &[]).unwrap();
+    let expected = vec![(s("EMPTY"), s("")), (s("HOME"), s("/nowhere")), (s("ÜNI"), s("cödé"))];
+    assert_eq!(all, Value::map(expected.into_iter().collect()));
+
+    rt.set_env(vars(&[]));
+    assert_eq!(call(&mut rt, "env_all", &[]), Ok(Value::map(BTreeMap::new())));
+}
+
+#[test]
+fn the_process_environment_is_the_default() {
+    let mut rt = Runtime::new();
+    let expected: BTreeMap<Value, Value> = std::env::vars_os()
+        .filter_map(|(k, v)| Some((s(k.to_str()?), s(v.to_str()?))))
+        .collect();
+    assert_eq!(call(&mut rt, "env_all", &[]), Ok(Value::map(expected.clone())));
+    if let Some((name, value)) = expected.iter().next() {
+        assert_eq!(call(&mut rt, "env", std::slice::from_ref(name)).as_ref(), Ok(value));
+    }
+    // Names that can never be set are simply absent.
+    for name in ["", "A=B", "NUL\0", "PAN_LANG_RS_SURELY_UNSET"].iter() {
+        assert_eq!(call(&mut rt, "env", &[s(name)]), Ok(Value::Nil), "{:?}", name);
+    }
+    rt.set_env(vars(&[]));
+    rt.set_env(None);
+    assert_eq!(call(&mut rt, "env_all", &[]), Ok(Value::map(expected)));
+}
+
+#[test]
+fn env_expects_a_string() {
+    let mut rt = Runtime::new();
+    assert_eq!(kind(&call(&mut rt, "env", &[]).unwrap_err()), "type");
+    assert_eq!(kind(&call(&mut rt, "env", &[Value::Int(1)]).unwrap_err()), "type");
+}
+
+#[test]
+fn lines_are_read_without_their_line_breaks() {
+    let mut rt = with_stdin("first\r\nsecond\n\nlast ünïcode".as_bytes());
+    for line in ["first", "second", "", "last ünïcode"].iter() {
+        assert_eq!(call(&mut rt, "read_line", &[]), Ok(s(line)));
+    }
+    assert_eq!(call(&mut rt, "read_line", &[]), Ok(Value::Nil));
+    assert_eq!(call(&mut rt, "read_all_stdin", &[]), Ok(s("")));
+
+    let mut rt = with_stdin(b"a\nb\r\nc\n");
+    assert_eq!(call(&mut rt, "read_line", &[]), Ok(s("a")));
+    assert_eq!(call(&mut rt, "read_all_stdin", &[]), Ok(s("b\r\nc\n")));
+    assert_eq!(call(&mut rt, "read_line", &[]), Ok(Value::Nil));
+
+    // A lone `\r` is part of the line.
+    let mut rt = with_stdin(b"x\ry\n");
+    assert_eq!(call(&mut rt, "read_line", &[]), Ok(s("x\ry")));
+}
+
+#[test]
+fn invalid_utf8_is_thrown() {
+    let mut rt = with_stdin(b"ok\n\xff\xfe\nfine\n");
+    assert_eq!(call(&mut rt, "read_line", &[]), Ok(s("ok")));
+    let err = call(&mut rt, "read_line", &[]).unwrap_err();
+    assert_eq!(kind(&err), "decode");
+    assert!(message(&err).starts_with("read_line read invalid UTF-8"), "{}", message(&err));
+    // The offending line is consumed.
+    assert_eq!(call(&mut rt, "read_line", &[]), Ok(s("fine")));
+
+    // A code point split at the end of the input is invalid too.
+    let mut rt = with_stdin("é".as_bytes().split_at(1).0);
+    assert_eq!(kind(&call(&mut rt, "read_all_stdin", &[]).unwrap_err()), "decode");
+}
+
+#[test]
+fn reads_are_capped() {
+    let mut rt = Runtime::new();
+    rt.set_stdin(Box::new(io::repeat(b'a').take(MAX_READ_LEN as u64 * 2)));
+    let err = call(&mut rt, "read_line", &[]).unwrap_err();
+    assert_eq!(kind(&err), "range");
+    assert!(message(&err).starts_with("read_line can read at most"), "{}", message(&err));
+    // Only what exceeded the cap was consumed.
+    let rest = call(&mut rt, "read_all_stdin", &[]).unwrap();
+    let len = MAX_READ_LEN as i64 - 1;
+    assert_eq!(call(&mut rt, "str_byte_len", &[rest]), Ok(Value::Int(len)));
+
+    let mut rt = Runtime::new();
+    rt.set_stdin(Box::new(io::repeat(b'\n').take(MAX_READ_LEN as u64 + 1)));
+    let err = call(&mut rt, "read_all_stdin", &[]).unwrap_err();
+    assert_eq!(kind(&err), "range");
+    assert_eq!(call(&mut rt, "read_line", &[]), Ok(Value::Nil));
+
+    // Exactly the cap is fine, for a line with or without its line break.
+    let mut input = vec![b'a'; MAX_READ_LEN];
+    input.push(b'\n');
+    input.extend(vec![b'b'; MAX_READ_LEN]);
+    let mut rt = with_stdin(&input);
+    let line = call(&mut rt, "read_line", &[]).unwrap();
+    assert_eq!(call(&mut rt, "str_byte_len", &[line]), Ok(Value::Int(MAX_READ_LEN as i64)));
+    let rest = call(&mut rt, "read_all_stdin", &[]).unwrap();
+    assert_eq!(call(&mut rt, "str_byte_len", &[rest]), Ok(Value::Int(MAX_READ_LEN as i64)));
+}
+
+#[test]
+fn read_errors_are_thrown() {
+    struct Broken;
+
+    impl Read for Broken {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("unplugged"))
+        }
+    }
+
+    let mut rt = Runtime::new();
+    rt.set_stdin(Box::new(Broken));
+    for name in ["read_line", "read_all_stdin"].iter() {
+        let err = call(&mut rt, name, &[]).unwrap_err();
+        assert_eq!(kind(&err), "io");
+        assert!(message(&err).contains("unplugged"), "{}", message(&err));
+    }
+}
+
+#[test]
+fn inputs_require_their_capabilities() {
+    let names = ["args", "env", "env_all", "read_line", "read_all_stdin"];
+    let rt = Runtime::with_capabilities(EventLoop::new(), &Capabilities::PURE).unwrap();
+    for name in names.iter() {
+        assert!(rt.global(name).is_none(), "{}", name);
+    }
+    let caps = Capabilities::PURE.allow_env();
+    let rt = Runtime::with_capabilities(EventLoop::new(), &caps).unwrap();
+    assert!(names[..3].iter().all(|name| rt.global(name).is_some()));
+    assert!(names[3..].iter().all(|name| rt.global(name).is_none()));
+    let caps = Capabilities::PURE.allow_stdin();
+    let rt = Runtime::with_capabilities(EventLoop::new(), &caps).unwrap();
+    assert!(names[..3].iter().all(|name| rt.global(name).is_none()));
+    assert!(names[3..].iter().all(|name| rt.global(name).is_some()));
+}
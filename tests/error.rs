@@ -0,0 +1,136 @@
+mod common;
+
+use std::collections::BTreeMap;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, int, kind, s};
+
+// Assert that `err` has the standard shape: a string `kind` and `message`, and nothing but
+// `data` and `source` besides.
+fn assert_standard(err: &Value) {
+    let map = match err {
+        Value::Map(map) => map.borrow().clone(),
+        other => panic!("not a map: {}", other),
+    };
+    assert!(matches!(map.get(&s("kind")), Some(Value::String(_))), "{}", err);
+    assert!(matches!(map.get(&s("message")), Some(Value::String(_))), "{}", err);
+    for key in map.keys() {
+        assert!([s("kind"), s("message"), s("data"), s("source")].contains(key), "{}", err);
+    }
+}
+
+fn map(entries: Vec<(Value, Value)>) -> Value {
+    Value::map(entries.into_iter().collect::<BTreeMap<_, _>>())
+}
+
+#[test]
+fn builtins_and_calls_throw_standard_errors() {
+    let mut rt = Runtime::new();
+    let failures: Vec<(&str, Vec<Value>, &str)> = vec![
+        ("add", vec![int(i64::MAX), int(1)], "overflow"),
+        ("mul", vec![int(i64::MIN), int(-1)], "overflow"),
+        ("div", vec![int(1), int(0)], "division_by_zero"),
+        ("add", vec![int(1), s("1")], "type"),
+        ("get", vec![arr(vec![int(1)]), int(1)], "range"),
+        ("get", vec![arr(vec![]), int(-1)], "range"),
+        ("pop", vec![arr(vec![])], "range"),
+        ("str_get", vec![s("abc"), int(3)], "range"),
+        ("parse_int", vec![s("12x")], "parse"),
+        ("map_insert", vec![arr(vec![]), int(1), int(1)], "type"),
+    ];
+    for (name, args, expected) in failures.iter() {
+        let err = call(&mut rt, name, args).unwrap_err();
+        assert_standard(&err);
+        assert_eq!(&kind(&err), expected, "{}", name);
+        assert_eq!(call(&mut rt, "is_error", &[err]), Ok(Value::Bool(true)));
+    }
+
+    // Calling what is not a function, or with named arguments it does not take.
+    let err = int(3).apply(&[]).unwrap_err();
+    assert_standard(&err);
+    assert_eq!(kind(&err), "type");
+    let len = rt.global("len").unwrap();
+    let err = len.apply_named(&[], &map(vec![(s("nope"), int(1))])).unwrap_err();
+    assert_standard(&err);
+    assert_eq!(kind(&err), "argument");
+}
+
+#[test]
+fn error_constructs_the_standard_shape() {
+    let mut rt = Runtime::new();
+    let err = call(&mut rt, "error", &[s("mine"), s("it broke")]).unwrap();
+    assert_standard(&err);
+    assert_eq!(err, Value::error("mine", "it broke"));
+    assert_eq!(call(&mut rt, "error_kind", std::slice::from_ref(&err)), Ok(s("mine")));
+    assert_eq!(call(&mut rt, "error_message", std::slice::from_ref(&err)), Ok(s("it broke")));
+    assert_eq!(call(&mut rt, "error_data", std::slice::from_ref(&err)), Ok(Value::Nil));
+
+    let data = arr(vec![int(1), Value::Nil]);
+    let err = call(&mut rt, "error", &[s("mine"), s(""), data.clone()]).unwrap();
+    assert_standard(&err);
+    assert_eq!(call(&mut rt, "error_data", &[err]), Ok(data));
+    // Explicit nil data is kept as a field.
+    let err = call(&mut rt, "error", &[s("mine"), s(""), Value::Nil]).unwrap();
+    assert_eq!(err, Value::error_with_data("mine", "", Value::Nil));
+
+    for args in [vec![], vec![s("kind")], vec![int(1), s("m")], vec![s("k"), Value::Nil]].iter() {
+        let err = call(&mut rt, "error", args).unwrap_err();
+        assert_eq!(kind(&err), "type", "{:?}", args);
+    }
+}
+
+#[test]
+fn accessors_tolerate_anything() {
+    let mut rt = Runtime::new();
+    let odd = vec![
+        s("just a string"),
+        Value::Nil,
+        int(404),
+        arr(vec![s("kind"), s("message")]),
+        map(vec![]),
+        map(vec![(s("kind"), s("k"))]),
+        map(vec![(s("message"), s("m"))]),
+        map(vec![(s("kind"), int(1)), (s("message"), s("m"))]),
+        map(vec![(s("kind"), s("k")), (s("message"), Value::Char('m'))]),
+    ];
+    for v in odd.iter() {
+        assert_eq!(call(&mut rt, "is_error", std::slice::from_ref(v)), Ok(Value::Bool(false)));
+    }
+    // Each field is read on its own, whether or not the others are well-formed.
+    let expected = [
+        (Value::Nil, Value::Nil),
+        (Value::Nil, Value::Nil),
+        (Value::Nil, Value::Nil),
+        (Value::Nil, Value::Nil),
+        (Value::Nil, Value::Nil),
+        (s("k"), Value::Nil),
+        (Value::Nil, s("m")),
+        (Value::Nil, s("m")),
+        (s("k"), Value::Nil),
+    ];
+    for (v, (k, m)) in odd.iter().zip(expected.iter()) {
+        let v = std::slice::from_ref(v);
+        assert_eq!(call(&mut rt, "error_kind", v).as_ref(), Ok(k), "{}", v[0]);
+        assert_eq!(call(&mut rt, "error_message", v).as_ref(), Ok(m), "{}", v[0]);
+        assert_eq!(call(&mut rt, "error_data", v), Ok(Value::Nil), "{}", v[0]);
+    }
+    for name in ["is_error", "error_kind", "error_message", "error_data"].iter() {
+        assert!(call(&mut rt, name, &[]).is_ok(), "{}", name);
+    }
+    let data = map(vec![(s("data"), int(7))]);
+    assert_eq!(call(&mut rt, "error_data", &[data]), Ok(int(7)));
+}
+
+#[test]
+fn thrown_bare_values_reach_the_accessors_unchanged() {
+    let mut rt = Runtime::new();
+    let thrower = |args: &[Value]| Err(args[0].clone());
+    rt.register("throw_it", thrower);
+    let err = call(&mut rt, "throw_it", &[s("bare")]).unwrap_err();
+    assert_eq!(err, s("bare"));
+    assert_eq!(call(&mut rt, "is_error", std::slice::from_ref(&err)), Ok(Value::Bool(false)));
+    assert_eq!(call(&mut rt, "error_kind", std::slice::from_ref(&err)), Ok(Value::Nil));
+    assert_eq!(call(&mut rt, "error_message", &[err]), Ok(Value::Nil));
+}
@@ -0,0 +1,309 @@
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use futures::future::LocalFutureObj;
+
+use pan_lang_rs::types::futures::{EventLoop, Future as PanFuture, LifecycleState, SchedEvent};
+use pan_lang_rs::value::Value;
+
+// Pending once, after waking its own task.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+type Log = Rc<RefCell<Vec<String>>>;
+
+type Task = LocalFutureObj<'static, Result<Value, Value>>;
+
+// A task that logs each of its steps, yielding to the loop in between, and then settles with
+// the given outcome.
+fn task(log: &Log, name: &'static str, steps: usize, outcome: Result<Value, Value>) -> Task {
+    let log = log.clone();
+    LocalFutureObj::new(Box::new(async move {
+        for step in 0..steps {
+            log.borrow_mut().push(format!("{}{}", name, step));
+            YieldNow(false).await;
+        }
+        outcome
+    }))
+}
+
+#[test]
+fn spawned_tasks_interleave() {
+    let event_loop = EventLoop::new();
+    let log = Log::default();
+    let a = event_loop.spawn(task(&log, "a", 1, Ok(Value::Int(1))));
+    let b = event_loop.spawn(task(&log, "b", 3, Ok(Value::Int(3))));
+    let x = event_loop.spawn(task(&log, "x", 2, Err(Value::Int(2))));
+    // Nothing runs before the loop does.
+    assert!(log.borrow().is_empty());
+    assert_eq!(a.state(), LifecycleState::Running);
+
+    event_loop.run_until_idle();
+    assert_eq!(*log.borrow(), ["a0", "b0", "x0", "b1", "x1", "b2"]);
+    assert_eq!(a.result(), Some(Ok(Value::Int(1))));
+    assert_eq!(b.result(), Some(Ok(Value::Int(3))));
+    assert_eq!(x.result(), Some(Err(Value::Int(2))));
+    assert_eq!(b.state(), LifecycleState::Resolved);
+    assert_eq!(x.state(), LifecycleState::Rejected);
+    assert!(event_loop.is_idle());
+}
+
+#[test]
+fn run_until_stops_once_the_job_settles() {
+    let event_loop = EventLoop::new();
+    let log = Log::default();
+    let a = event_loop.spawn(task(&log, "a", 1, Ok(Value::Int(1))));
+    let b = event_loop.spawn(task(&log, "b", 3, Ok(Value::Int(3))));
+    assert_eq!(event_loop.run_until(&a), Some(Ok(Value::Int(1))));
+    assert_eq!(*log.borrow(), ["a0", "b0"]);
+    assert_eq!(b.state(), LifecycleState::Running);
+    assert_eq!(event_loop.run_until(&b), Some(Ok(Value::Int(3))));
+    // The outcome has been taken.
+    assert_eq!(event_loop.run_until(&b), None);
+}
+
+#[test]
+fn an_empty_loop_is_idle() {
+    let event_loop = EventLoop::new();
+    assert!(event_loop.is_idle());
+    event_loop.run_until_idle();
+    event_loop.run_blocking();
+    assert!(event_loop.is_idle());
+    assert!(event_loop.active_jobs().is_empty());
+}
+
+#[test]
+fn immediate_futures_bypass_the_queue() {
+    let event_loop = EventLoop::new();
+    for (fut, outcome) in [
+        (PanFuture::resolve(Value::Int(1)), Ok(Value::Int(1))),
+        (PanFuture::reject(Value::Int(2)), Err(Value::Int(2))),
+    ] {
+        let job = event_loop.run_future(&fut).unwrap();
+        // Settled without running the loop.
+        assert_eq!(job.result(), Some(outcome));
+        assert!(job.is_settled());
+        assert!(fut.state().is_done());
+    }
+}
+
+#[test]
+fn idle_futures_settle_once_nothing_else_is_left() {
+    let event_loop = EventLoop::new();
+    let log = Log::default();
+    let idle = PanFuture::on_idle();
+    let job = event_loop.run_future(&idle).unwrap();
+    let spawned = event_loop.spawn(task(&log, "a", 3, Ok(Value::Int(3))));
+    assert_eq!(event_loop.run_until(&job), Some(Ok(Value::Nil)));
+    assert_eq!(spawned.state(), LifecycleState::Resolved);
+    assert_eq!(*log.borrow(), ["a0", "a1", "a2"]);
+    assert_eq!(idle.state(), LifecycleState::Resolved);
+}
+
+#[test]
+fn the_idle_hook_runs_once_per_stretch_of_work() {
+    let event_loop = EventLoop::new();
+    let log = Log::default();
+    let hook_log = log.clone();
+    event_loop.on_idle(move || hook_log.borrow_mut().push("idle".to_string()));
+    // An empty loop has not done anything.
+    event_loop.run_until_idle();
+    assert!(log.borrow().is_empty());
+
+    event_loop.spawn(task(&log, "a", 2, Ok(Value::Nil)));
+    event_loop.run_until_idle();
+    event_loop.run_until_idle();
+    assert_eq!(*log.borrow(), ["a0", "a1", "idle"]);
+
+    // Registering another hook replaces the first one.
+    let count = Rc::new(Cell::new(0));
+    let hook_count = count.clone();
+    event_loop.on_idle(move || hook_count.set(hook_count.get() + 1));
+    event_loop.spawn(task(&log, "b", 1, Ok(Value::Nil)));
+    event_loop.run_until_idle();
+    assert_eq!(*log.borrow(), ["a0", "a1", "idle", "b0"]);
+    assert_eq!(count.get(), 1);
+}
+
+#[test]
+fn jobs_have_unique_ids_and_are_listed_while_active() {
+    let event_loop = EventLoop::new();
+    let log = Log::default();
+    let a = event_loop.spawn(task(&log, "a", 1, Ok(Value::Int(1))));
+    let b = event_loop.spawn(task(&log, "b", 3, Ok(Value::Int(3))));
+    assert_ne!(a.id(), b.id());
+    assert_eq!(a, a.clone());
+    assert_ne!(a, b);
+    let mut active = event_loop.active_jobs();
+    active.sort();
+    assert_eq!(active, [a.id(), b.id()]);
+
+    event_loop.run_until(&a);
+    assert_eq!(event_loop.active_jobs(), [b.id()]);
+    event_loop.run_until_idle();
+    assert!(event_loop.active_jobs().is_empty());
+
+    // Ids are not reused, even though slots are.
+    drop(a);
+    let c = event_loop.spawn(task(&log, "c", 0, Ok(Value::Nil)));
+    assert!(c.id() > b.id());
+}
+
+#[test]
+fn job_states_and_results() {
+    let event_loop = EventLoop::new();
+    let log = Log::default();
+    let job = event_loop.spawn(task(&log, "a", 2, Ok(Value::Int(2))));
+    assert_eq!(job.state(), LifecycleState::Running);
+    assert!(!job.is_settled());
+    assert_eq!(job.result(), None);
+    event_loop.run_until_idle();
+    assert_eq!(job.state(), LifecycleState::Resolved);
+    assert!(job.is_settled());
+    // The outcome is kept until it is taken.
+    assert_eq!(job.result(), Some(Ok(Value::Int(2))));
+    assert_eq!(job.result(), Some(Ok(Value::Int(2))));
+    assert_eq!(job.take_result(), Some(Ok(Value::Int(2))));
+    assert_eq!(job.result(), None);
+    assert_eq!(job.take_result(), None);
+    assert_eq!(job.state(), LifecycleState::Resolved);
+
+    let job = event_loop.spawn(task(&log, "x", 2, Err(Value::Int(2))));
+    event_loop.run_until_idle();
+    assert_eq!(job.state(), LifecycleState::Rejected);
+    assert!(job.is_settled());
+    assert_eq!(job.take_result(), Some(Err(Value::Int(2))));
+
+    let job = event_loop.spawn(task(&log, "c", 2, Ok(Value::Nil)));
+    assert!(job.cancel());
+    assert_eq!(job.state(), LifecycleState::Cancelled);
+    assert!(!job.is_settled());
+    assert!(!job.cancel());
+    assert_eq!(job.result(), None);
+    event_loop.run_until_idle();
+    // The cancelled task never ran.
+    assert!(!log.borrow().iter().any(|step| step.starts_with('c')));
+}
+
+#[test]
+fn never_and_idle_futures_are_registered_jobs() {
+    let event_loop = EventLoop::new();
+    let never = PanFuture::never();
+    let idle = PanFuture::on_idle();
+    let never_job = event_loop.run_future(&never).unwrap();
+    let idle_job = event_loop.run_future(&idle).unwrap();
+    assert_eq!(never.job(), Some(never_job.clone()));
+    assert_eq!(idle.job(), Some(idle_job.clone()));
+    let mut active = event_loop.active_jobs();
+    active.sort();
+    assert_eq!(active, [never_job.id(), idle_job.id()]);
+
+    event_loop.run_until_idle();
+    assert_eq!(idle_job.state(), LifecycleState::Resolved);
+    assert_eq!(event_loop.active_jobs(), [never_job.id()]);
+    assert!(never.cancel());
+    assert!(event_loop.active_jobs().is_empty());
+}
+
+// Sets the flag when dropped.
+struct DropFlag(Rc<Cell<bool>>);
+
+impl Drop for DropFlag {
+    fn drop(&mut self) {
+        self.0.set(true);
+    }
+}
+
+#[test]
+fn cancelling_a_task_drops_it() {
+    let event_loop = EventLoop::new();
+    let log = Log::default();
+    let dropped = Rc::new(Cell::new(false));
+    let flag = DropFlag(dropped.clone());
+    let inner = task(&log, "a", 1, Ok(Value::Nil));
+    let job = event_loop.spawn(LocalFutureObj::new(Box::new(async move {
+        let _flag = flag;
+        inner.await.unwrap();
+        futures::future::pending().await
+    })));
+    event_loop.run_until_idle();
+    assert!(!dropped.get());
+    assert_eq!(job.state(), LifecycleState::Running);
+
+    assert!(job.cancel());
+    assert!(dropped.get());
+    assert_eq!(job.state(), LifecycleState::Cancelled);
+    event_loop.run_until_idle();
+    assert_eq!(*log.borrow(), ["a0"]);
+}
+
+// Three tasks racing to log their steps.
+fn racy(event_loop: &EventLoop) -> Vec<String> {
+    let log = Log::default();
+    let jobs: Vec<_> = ["a", "b", "c"]
+        .iter()
+        .map(|name| event_loop.spawn(task(&log, name, 3, Ok(Value::Nil))))
+        .collect();
+    event_loop.run_until_idle();
+    assert!(jobs.iter().all(|job| job.state() == LifecycleState::Resolved));
+    log.take()
+}
+
+#[test]
+fn seeds_pick_interleavings_reproducibly() {
+    let mut interleavings = vec![];
+    for seed in 0..8 {
+        let first = EventLoop::new_deterministic(seed);
+        let second = EventLoop::new_deterministic(seed);
+        let log = racy(&first);
+        assert_eq!(log, racy(&second), "seed {}", seed);
+        assert_eq!(first.trace(), second.trace(), "seed {}", seed);
+        assert!(!first.trace().is_empty());
+        interleavings.push(log);
+    }
+    interleavings.sort();
+    interleavings.dedup();
+    assert!(interleavings.len() > 1, "all seeds picked {:?}", interleavings);
+
+    let fifo = EventLoop::new_deterministic_fifo();
+    assert_eq!(racy(&fifo), ["a0", "b0", "c0", "a1", "b1", "c1", "a2", "b2", "c2"]);
+}
+
+#[test]
+fn deterministic_loops_sleep_in_virtual_time() {
+    let event_loop = EventLoop::new_deterministic(378);
+    let long = PanFuture::sleep(60_000);
+    let short = PanFuture::sleep(10);
+    let long_job = event_loop.run_future(&long).unwrap();
+    let short_job = event_loop.run_future(&short).unwrap();
+    let start = std::time::Instant::now();
+    event_loop.run_until_idle();
+    assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    assert!(long_job.is_settled() && short_job.is_settled());
+    assert_eq!(event_loop.now(), 60_000);
+    let timers: Vec<_> = event_loop
+        .trace()
+        .into_iter()
+        .filter_map(|event| match event {
+            SchedEvent::Timer { job, at } => Some((job, at)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(timers, [(short_job.id(), 10), (long_job.id(), 60_000)]);
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use pan_lang_rs::runtime::{RunError, Runtime};
+use pan_lang_rs::types::exit::{exit_code, exit_value, is_exit, unwinds};
+use pan_lang_rs::value::{Fun, Native, Value};
+
+use common::{call, int, kind, s};
+
+#[test]
+fn exit_throws_an_exit_value() {
+    let mut rt = Runtime::new();
+    for &code in &[0, 1, 3, -1, i64::from(i32::MAX), i64::from(i32::MIN)] {
+        let thrown = call(&mut rt, "exit", &[int(code)]).unwrap_err();
+        assert_eq!(exit_code(&thrown), Some(code as i32));
+        assert!(is_exit(&thrown) && unwinds(&thrown));
+    }
+    assert_eq!(exit_code(&call(&mut rt, "exit", &[]).unwrap_err()), Some(0));
+    assert_eq!(exit_code(&call(&mut rt, "exit", &[Value::Nil]).unwrap_err()), Some(0));
+    // Exit values are not errors.
+    let thrown = exit_value(2);
+    assert_eq!(call(&mut rt, "is_error", &[thrown]), Ok(Value::Bool(false)));
+}
+
+#[test]
+fn bad_codes_throw_ordinary_errors() {
+    let mut rt = Runtime::new();
+    for &code in &[i64::from(i32::MAX) + 1, i64::from(i32::MIN) - 1, i64::MAX] {
+        let err = call(&mut rt, "exit", &[int(code)]).unwrap_err();
+        assert_eq!(kind(&err), "range", "{}", code);
+        assert!(!is_exit(&err));
+    }
+    assert_eq!(kind(&call(&mut rt, "exit", &[s("1")]).unwrap_err()), "type");
+    assert!(!is_exit(&s("exit")) && !unwinds(&int(0)));
+}
+
+#[test]
+fn embedders_receive_a_typed_exit_through_rust_frames() {
+    let mut rt = Runtime::new();
+    let exit = rt.global("exit").unwrap();
+    let mut f = Value::Fun(Fun::Native(Native::new("exits", move |_| exit.apply(&[int(42)]))));
+    // Rust functions that pass on what they catch let the exit unwind.
+    for _ in 0..10 {
+        let inner = f.clone();
+        f = Value::Fun(Fun::Native(Native::new("wraps", move |_| inner.apply(&[]))));
+    }
+    match rt.call(&f, &[]) {
+        Err(RunError::Exit(42)) => {}
+        other => panic!("expected an exit, got {:?}", other),
+    }
+    let err = rt.call(&f, &[]).unwrap_err();
+    assert_eq!(err.to_string(), "exited with code 42");
+    assert_eq!(exit_code(&err.into_value()), Some(42));
+    assert!(matches!(RunError::from_thrown(exit_value(-5)), RunError::Exit(-5)));
+}
@@ -0,0 +1,143 @@
+mod common;
+
+use pan_lang_rs::builtins::format::MAX_WIDTH;
+use pan_lang_rs::runtime::{RunError, Runtime};
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, float, int, kind, message, s};
+
+fn fmt(rt: &mut Runtime, template: &str, args: &[Value]) -> Result<Value, Value> {
+    let mut all = vec![s(template)];
+    all.extend_from_slice(args);
+    call(rt, "fmt", &all)
+}
+
+fn fmt_err(rt: &mut Runtime, template: &str, args: &[Value]) -> String {
+    let err = fmt(rt, template, args).unwrap_err();
+    assert_eq!(kind(&err), "format");
+    message(&err)
+}
+
+#[test]
+fn positional_and_indexed_placeholders() {
+    let mut rt = Runtime::new();
+    let args = [int(1), s("two"), float(3.5)];
+    assert_eq!(fmt(&mut rt, "{} {} {}", &args), Ok(s("1 two 3.5")));
+    assert_eq!(fmt(&mut rt, "{2} {0} {2}", &args), Ok(s("3.5 1 3.5")));
+    assert_eq!(fmt(&mut rt, "{1} {} {}", &args), Ok(s("two 1 two")));
+    assert_eq!(fmt(&mut rt, "{{{}}} }}{{", &args), Ok(s("{1} }{")));
+    assert_eq!(fmt(&mut rt, "no placeholders", &[]), Ok(s("no placeholders")));
+}
+
+#[test]
+fn named_placeholders() {
+    let mut rt = Runtime::new();
+    let map = Value::record(vec![("name", s("pan")), ("n", int(3))]);
+    let out = call(&mut rt, "fmt_map", &[s("{name} has {n:>3} chars"), map.clone()]);
+    assert_eq!(out, Ok(s("pan has   3 chars")));
+    let err = call(&mut rt, "fmt_map", &[s("{name} {missing}"), map]).unwrap_err();
+    assert_eq!(message(&err), "fmt_map failed: missing key `missing` at offset 7");
+}
+
+#[test]
+fn mixing_positional_and_named_placeholders_is_rejected() {
+    let mut rt = Runtime::new();
+    let msg = fmt_err(&mut rt, "{} {name}", &[int(1)]);
+    assert_eq!(msg, "fmt failed: unexpected named placeholder `{name}` at offset 3");
+    let map = Value::record(vec![("name", s("pan"))]);
+    let err = call(&mut rt, "fmt_map", &[s("{name} {}"), map]).unwrap_err();
+    assert_eq!(message(&err), "fmt_map failed: unexpected positional placeholder `{}` at offset 7");
+}
+
+#[test]
+fn width_fill_and_alignment() {
+    let mut rt = Runtime::new();
+    assert_eq!(fmt(&mut rt, "[{:5}]", &[s("ab")]), Ok(s("[ab   ]")));
+    assert_eq!(fmt(&mut rt, "[{:5}]", &[int(42)]), Ok(s("[   42]")));
+    assert_eq!(fmt(&mut rt, "[{:<5}]", &[int(42)]), Ok(s("[42   ]")));
+    assert_eq!(fmt(&mut rt, "[{:*^6}]", &[s("ñö")]), Ok(s("[**ñö**]")));
+    assert_eq!(fmt(&mut rt, "[{:->4}]", &[s("x")]), Ok(s("[---x]")));
+    assert_eq!(fmt(&mut rt, "[{:2}]", &[s("long")]), Ok(s("[long]")));
+}
+
+#[test]
+fn precision_on_floats() {
+    let mut rt = Runtime::new();
+    assert_eq!(fmt(&mut rt, "{:.2}", &[float(1.23456)]), Ok(s("1.23")));
+    assert_eq!(fmt(&mut rt, "{:.0}", &[float(2.5)]), Ok(s("2")));
+    assert_eq!(fmt(&mut rt, "{:8.3}", &[float(-1.0)]), Ok(s("  -1.000")));
+    assert_eq!(fmt(&mut rt, "{:.1}", &[int(7)]), Ok(s("7.0")));
+    assert_eq!(fmt(&mut rt, "{}", &[float(0.1 + 0.2)]), Ok(s("0.30000000000000004")));
+    let msg = fmt_err(&mut rt, "ab{:.2}", &[s("x")]);
+    assert!(msg.starts_with("fmt failed: ") && msg.ends_with(" at offset 2"), "{}", msg);
+}
+
+#[test]
+fn debug_spec_renders_literals() {
+    let mut rt = Runtime::new();
+    assert_eq!(fmt(&mut rt, "{} {:?}", &[s("a"), s("a")]), Ok(s("a \"a\"")));
+    let nested = Value::record(vec![("k", arr(vec![int(1), s("x"), Value::Char('c')]))]);
+    let out = fmt(&mut rt, "{:?}", std::slice::from_ref(&nested)).unwrap();
+    assert_eq!(out, s("{\"k\": [1, \"x\", 'c']}"));
+}
+
+#[test]
+fn malformed_templates_report_offsets() {
+    let mut rt = Runtime::new();
+    assert_eq!(fmt_err(&mut rt, "ab}", &[]), "fmt failed: unmatched `}` at offset 2");
+    assert_eq!(fmt_err(&mut rt, "a{", &[]), "fmt failed: unclosed placeholder at offset 1");
+    assert_eq!(fmt_err(&mut rt, "{} {}", &[int(1)]), "fmt failed: missing argument 1 at offset 3");
+    assert_eq!(
+        fmt_err(&mut rt, "x{:5q}", &[int(1)]),
+        "fmt failed: malformed format spec `5q` at offset 1",
+    );
+    let err = call(&mut rt, "fmt", &[int(1)]).unwrap_err();
+    assert_eq!(message(&err), "fmt expects a string as the template, got int");
+}
+
+#[test]
+fn widths_and_precisions_are_bounded() {
+    let mut rt = Runtime::new();
+    let max = format!("{{:{}}}", MAX_WIDTH);
+    let out = fmt(&mut rt, &max, &[int(1)]).unwrap();
+    assert_eq!(call(&mut rt, "str_len", &[out]), Ok(int(MAX_WIDTH as i64)));
+    assert_eq!(
+        fmt_err(&mut rt, "{:65536}", &[int(1)]),
+        "fmt failed: width 65536 is larger than 65535 at offset 0",
+    );
+    assert_eq!(
+        fmt_err(&mut rt, "ab{:99999999999}", &[int(1)]),
+        "fmt failed: width 99999999999 is larger than 65535 at offset 2",
+    );
+    assert_eq!(
+        fmt_err(&mut rt, "{:.70000}", &[float(1.0)]),
+        "fmt failed: precision 70000 is larger than 65535 at offset 0",
+    );
+    // Numbers that do not even fit a usize are malformed.
+    assert_eq!(
+        fmt_err(&mut rt, "{:99999999999999999999999}", &[int(1)]),
+        "fmt failed: malformed format spec `99999999999999999999999` at offset 0",
+    );
+    let max = format!("{{:.{}}}", MAX_WIDTH);
+    assert!(fmt(&mut rt, &max, &[float(0.5)]).is_ok());
+}
+
+#[test]
+fn padding_is_charged_to_the_memory_budget() {
+    let mut rt = Runtime::new();
+    let fmt_fun = rt.global("fmt").unwrap();
+    let baseline = rt.memory_used();
+    rt.set_memory_limit(Some(baseline + 1024));
+    match rt.call(&fmt_fun, &[s("{:2000}"), int(1)]) {
+        Err(RunError::OutOfMemory(oom)) => assert!(oom.used > oom.limit),
+        other => panic!("expected out of memory, got {:?}", other),
+    }
+    // Padding in separate placeholders adds up.
+    let template = s(&"{0:200}".repeat(10));
+    assert!(matches!(rt.call(&fmt_fun, &[template, int(1)]), Err(RunError::OutOfMemory(_))));
+
+    // The charge is credited back afterwards, and padding within the limit is fine.
+    assert_eq!(rt.memory_used(), baseline);
+    let out = rt.call(&fmt_fun, &[s("{:*^500}"), s("pan")]).unwrap();
+    assert_eq!(out, s(&format!("{}pan{}", "*".repeat(248), "*".repeat(249))));
+}
@@ -0,0 +1,157 @@
+mod common;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::value::{Fun, Native, Value};
+
+use common::{arr, call, int, kind, s, same};
+
+fn frozen(rt: &mut Runtime, v: &Value) -> bool {
+    call(rt, "is_frozen", std::slice::from_ref(v)) == Ok(Value::Bool(true))
+}
+
+fn first(v: &Value) -> Value {
+    match v {
+        Value::Array(a) => a.borrow()[0].clone(),
+        other => panic!("not an array: {}", other),
+    }
+}
+
+fn zero() -> Value {
+    Value::Fun(Fun::Native(Native::new("zero", |_| Ok(int(0)))))
+}
+
+// Every builtin that mutates a collection, with arguments that would mutate `v`.
+fn mutations(v: &Value) -> Vec<(&'static str, Vec<Value>)> {
+    match v {
+        Value::Array(_) => vec![
+            ("set", vec![v.clone(), int(0), int(0)]),
+            ("push", vec![v.clone(), int(0)]),
+            ("pop", vec![v.clone()]),
+            ("insert", vec![v.clone(), int(0), int(0)]),
+            ("remove", vec![v.clone(), int(0)]),
+            ("reverse", vec![v.clone()]),
+            ("clear", vec![v.clone()]),
+            ("sort", vec![v.clone()]),
+            ("sort_by", vec![v.clone(), zero()]),
+            ("sort_by_key", vec![v.clone(), zero()]),
+            ("shuffle", vec![v.clone()]),
+        ],
+        Value::Set(_) => vec![
+            ("set_add", vec![v.clone(), int(0)]),
+            ("set_remove", vec![v.clone(), int(1)]),
+        ],
+        Value::Map(_) => vec![
+            ("map_insert", vec![v.clone(), int(0), int(0)]),
+            ("map_remove", vec![v.clone(), int(1)]),
+            ("map_clear", vec![v.clone()]),
+        ],
+        other => panic!("not a collection: {}", other),
+    }
+}
+
+fn collections() -> Vec<Value> {
+    vec![
+        arr(vec![int(2), int(1)]),
+        Value::set(vec![int(1), int(2)].into_iter().collect()),
+        Value::map(vec![(int(1), int(1))].into_iter().collect()),
+    ]
+}
+
+#[test]
+fn every_mutating_builtin_respects_the_flag() {
+    let mut rt = Runtime::new();
+    for v in collections() {
+        let before = v.to_string();
+        assert!(!frozen(&mut rt, &v));
+        assert_eq!(call(&mut rt, "freeze", std::slice::from_ref(&v)), Ok(v.clone()));
+        assert!(frozen(&mut rt, &v));
+        for (name, args) in mutations(&v) {
+            let err = call(&mut rt, name, &args).unwrap_err();
+            assert_eq!(kind(&err), "frozen", "{}", name);
+            assert_eq!(v.to_string(), before, "{}", name);
+        }
+        // A thawed copy can be mutated again.
+        let thawed = call(&mut rt, "thaw", std::slice::from_ref(&v)).unwrap();
+        assert!(!frozen(&mut rt, &thawed));
+        for (name, args) in mutations(&thawed) {
+            assert!(call(&mut rt, name, &args).is_ok(), "{}", name);
+        }
+    }
+}
+
+#[test]
+fn freeze_is_shallow_and_deep_freeze_is_not() {
+    let mut rt = Runtime::new();
+    let inner = arr(vec![int(1)]);
+    let outer = arr(vec![inner.clone()]);
+    call(&mut rt, "freeze", std::slice::from_ref(&outer)).unwrap();
+    assert!(!frozen(&mut rt, &inner));
+    assert_eq!(call(&mut rt, "push", &[inner.clone(), int(2)]), Ok(Value::Nil));
+
+    let map = Value::map(vec![(arr(vec![]), Value::set(Default::default()))].into_iter().collect());
+    let outer = arr(vec![inner.clone(), map.clone()]);
+    call(&mut rt, "deep_freeze", std::slice::from_ref(&outer)).unwrap();
+    for v in [&outer, &inner, &map].iter() {
+        assert!(frozen(&mut rt, v), "{}", v);
+    }
+    let entries = call(&mut rt, "map_entries", std::slice::from_ref(&map)).unwrap();
+    let entry = first(&entries);
+    assert!(frozen(&mut rt, &first(&entry)));
+
+    // Freezing what is already frozen does nothing.
+    assert_eq!(call(&mut rt, "freeze", std::slice::from_ref(&inner)), Ok(inner.clone()));
+    assert_eq!(call(&mut rt, "deep_freeze", std::slice::from_ref(&outer)), Ok(outer.clone()));
+    assert_eq!(inner, arr(vec![int(1), int(2)]));
+    assert!(frozen(&mut rt, &inner));
+
+    // Immutable values count as frozen, functions do not.
+    for v in [Value::Nil, int(1), s("s"), Value::Char('c')].iter() {
+        assert!(frozen(&mut rt, v));
+        assert_eq!(call(&mut rt, "freeze", std::slice::from_ref(v)).as_ref(), Ok(v));
+    }
+    assert!(!frozen(&mut rt, &zero()));
+}
+
+#[test]
+fn deep_freeze_terminates_on_cycles() {
+    let mut rt = Runtime::new();
+    let a = arr(vec![int(1)]);
+    let b = arr(vec![a.clone()]);
+    call(&mut rt, "push", &[a.clone(), b.clone()]).unwrap();
+    call(&mut rt, "push", &[a.clone(), a.clone()]).unwrap();
+    call(&mut rt, "deep_freeze", std::slice::from_ref(&a)).unwrap();
+    assert!(frozen(&mut rt, &a) && frozen(&mut rt, &b));
+    assert_eq!(kind(&call(&mut rt, "push", &[b.clone(), int(0)]).unwrap_err()), "frozen");
+}
+
+#[test]
+fn deep_copies_are_unfrozen_and_keep_their_shape() {
+    let mut rt = Runtime::new();
+    let shared = arr(vec![int(1)]);
+    let original = arr(vec![shared.clone(), shared.clone()]);
+    call(&mut rt, "push", &[original.clone(), original.clone()]).unwrap();
+    call(&mut rt, "deep_freeze", std::slice::from_ref(&original)).unwrap();
+
+    let copy = call(&mut rt, "deep_copy", std::slice::from_ref(&original)).unwrap();
+    assert_eq!(copy, original);
+    assert!(!frozen(&mut rt, &copy));
+    let elements = match &copy {
+        Value::Array(a) => a.borrow().clone(),
+        other => panic!("not an array: {}", other),
+    };
+    // The shared array is copied once, and the cycle leads back to the copy.
+    assert!(same(&elements[0], &elements[1]));
+    assert!(!same(&elements[0], &shared));
+    assert!(same(&elements[2], &copy));
+    assert!(!frozen(&mut rt, &elements[0]));
+
+    call(&mut rt, "push", &[elements[0].clone(), int(2)]).unwrap();
+    assert_eq!(first(&copy), arr(vec![int(1), int(2)]));
+    assert_eq!(first(&original), arr(vec![int(1)]));
+
+    // Thawing only unfreezes the outermost collection.
+    let thawed = call(&mut rt, "thaw", std::slice::from_ref(&original)).unwrap();
+    assert!(!frozen(&mut rt, &thawed));
+    assert!(frozen(&mut rt, &first(&thawed)));
+    assert!(same(&first(&thawed), &shared));
+}
@@ -0,0 +1,227 @@
+use std::collections::{BTreeMap, HashMap};
+
+use pan_lang_rs::value::convert::{FromPan, FromPanError, IntoPan, PathSegment};
+use pan_lang_rs::value::Value;
+
+fn round_trip<T: IntoPan + FromPan + Clone + PartialEq + std::fmt::Debug>(x: T) {
+    let v = x.clone().into_pan();
+    assert_eq!(T::from_pan(&v).unwrap(), x, "{}", v);
+}
+
+fn err<T: FromPan + std::fmt::Debug>(v: &Value) -> FromPanError {
+    T::from_pan(v).unwrap_err()
+}
+
+#[test]
+fn primitives_and_collections_round_trip() {
+    round_trip(());
+    round_trip(true);
+    round_trip(-3i64);
+    round_trip(2.5f64);
+    round_trip('λ');
+    round_trip("text".to_string());
+    round_trip(200u8);
+    round_trip(-7i32);
+    round_trip(Some(1i64));
+    round_trip(None::<i64>);
+    round_trip(vec![vec![1i64], vec![], vec![2, 3]]);
+    round_trip((1i64, "two".to_string(), Some(false)));
+
+    let mut tree = BTreeMap::new();
+    tree.insert("a".to_string(), vec![1i64]);
+    tree.insert("b".to_string(), vec![]);
+    round_trip(tree);
+    let mut hash = HashMap::new();
+    hash.insert("x".to_string(), 1.5f64);
+    round_trip(hash);
+
+    assert_eq!(None::<i64>.into_pan(), Value::Nil);
+    assert_eq!("s".into_pan(), Value::from("s"));
+    assert_eq!((1i64,).into_pan(), Value::array(vec![Value::Int(1)]));
+}
+
+#[test]
+fn mismatches_know_their_path() {
+    let e = err::<i64>(&Value::from("x"));
+    assert_eq!(e.to_string(), "expected int, got string");
+    assert_eq!(e.path_string(), "");
+
+    let e = err::<u8>(&Value::Int(256));
+    assert_eq!(e.to_string(), "expected an int from 0 to 255, got 256");
+
+    let nested = Value::array(vec![
+        Value::array(vec![Value::Int(1)]),
+        Value::array(vec![Value::Int(2), Value::Nil]),
+    ]);
+    let e = err::<Vec<Vec<i64>>>(&nested);
+    assert_eq!(e.to_string(), "[1][1]: expected int, got nil");
+    let path: Vec<PathSegment> = e.path().cloned().collect();
+    assert_eq!(path, [PathSegment::Index(1), PathSegment::Index(1)]);
+    assert_eq!(e.message(), "expected int, got nil");
+
+    let map = Value::map(
+        vec![(Value::from("retries"), Value::array(vec![Value::Int(1), Value::from("x")]))]
+            .into_iter()
+            .collect(),
+    );
+    let e = err::<BTreeMap<String, Vec<i64>>>(&map);
+    assert_eq!(e.to_string(), "retries[1]: expected int, got string");
+
+    let int_keys = Value::map(vec![(Value::Int(1), Value::Int(1))].into_iter().collect());
+    assert_eq!(
+        err::<HashMap<String, i64>>(&int_keys).to_string(),
+        "expected a string key, got int"
+    );
+
+    let short = Value::array(vec![Value::Int(1)]);
+    assert_eq!(
+        err::<(i64, i64)>(&short).to_string(),
+        "expected an array of length 2, got one of length 1"
+    );
+    let wrong = Value::array(vec![Value::Int(1), Value::Bool(true)]);
+    assert_eq!(err::<(i64, i64)>(&wrong).to_string(), "[1]: expected int, got bool");
+}
+
+#[cfg(feature = "derive")]
+mod derived {
+    use pan_lang_rs::value::convert::{FromPan, IntoPan};
+    use pan_lang_rs::value::Value;
+
+    use super::round_trip;
+
+    #[derive(Debug, Clone, PartialEq, IntoPan, FromPan)]
+    struct Config {
+        name: String,
+        #[pan(rename = "max-retries")]
+        retries: Vec<Retry>,
+        #[pan(default)]
+        verbose: bool,
+        timeout: Option<i64>,
+        mode: Mode,
+    }
+
+    #[derive(Debug, Clone, PartialEq, IntoPan, FromPan)]
+    struct Retry {
+        delay: i64,
+    }
+
+    #[derive(Debug, Clone, PartialEq, IntoPan, FromPan)]
+    enum Mode {
+        Off,
+        #[pan(rename = "fast")]
+        Fast(i64),
+        Pair(i64, String),
+        Named { level: u8 },
+    }
+
+    fn config() -> Config {
+        Config {
+            name: "main".to_string(),
+            retries: vec![Retry { delay: 1 }, Retry { delay: 2 }],
+            verbose: true,
+            timeout: None,
+            mode: Mode::Pair(1, "x".to_string()),
+        }
+    }
+
+    fn get(src: &Value, path: &[&str]) -> Value {
+        let mut v = src.clone();
+        for key in path {
+            v = match &v {
+                Value::Map(map) => map.borrow().get(&Value::from(*key)).cloned().unwrap(),
+                _ => panic!("not a map: {}", v),
+            };
+        }
+        v
+    }
+
+    fn without(v: &Value, key: &str) -> Value {
+        let copy = v.deep_copy();
+        if let Value::Map(map) = &copy {
+            map.borrow_mut().remove(&Value::from(key));
+        }
+        copy
+    }
+
+    #[test]
+    fn nested_structs_round_trip() {
+        round_trip(config());
+        let modes = [
+            Mode::Off,
+            Mode::Fast(3),
+            Mode::Pair(-1, String::new()),
+            Mode::Named { level: 9 },
+        ];
+        for mode in modes.iter().cloned() {
+            round_trip(Config { mode, ..config() });
+        }
+        round_trip(Config { timeout: Some(5), retries: vec![], ..config() });
+    }
+
+    #[test]
+    fn fields_and_variants_use_their_pan_names() {
+        let v = config().into_pan();
+        assert_eq!(get(&v, &["name"]), Value::from("main"));
+        assert_eq!(get(&v, &["max-retries"]).to_string(), "[{\"delay\": 1}, {\"delay\": 2}]");
+        assert_eq!(get(&v, &["timeout"]), Value::Nil);
+        assert_eq!(get(&v, &["mode", "Pair"]).to_string(), "[1, \"x\"]");
+
+        assert_eq!(Mode::Off.into_pan(), Value::from("Off"));
+        assert_eq!(get(&Mode::Fast(2).into_pan(), &["fast"]), Value::Int(2));
+        assert_eq!(get(&Mode::Named { level: 4 }.into_pan(), &["Named", "level"]), Value::Int(4));
+        assert_eq!(
+            Mode::from_pan(&Value::from("Fast")).unwrap_err().to_string(),
+            "unknown variant Fast"
+        );
+    }
+
+    #[test]
+    fn missing_fields() {
+        let v = config().into_pan();
+        // Options and defaulted fields may be missing.
+        let parsed = Config::from_pan(&without(&without(&v, "verbose"), "timeout")).unwrap();
+        assert_eq!(parsed, Config { verbose: false, ..config() });
+
+        let e = Config::from_pan(&without(&v, "name")).unwrap_err();
+        assert_eq!(e.to_string(), "name: missing field");
+        let e = Config::from_pan(&without(&v, "max-retries")).unwrap_err();
+        assert_eq!(e.to_string(), "max-retries: missing field");
+
+        let retries = get(&v, &["max-retries"]);
+        if let Value::Array(arr) = &retries {
+            let third = without(&arr.borrow()[0], "delay");
+            arr.borrow_mut().push(third);
+        }
+        let e = Config::from_pan(&v).unwrap_err();
+        assert_eq!(e.to_string(), "max-retries[2].delay: missing field");
+    }
+
+    #[test]
+    fn bad_fields_and_variants() {
+        let v = config().into_pan();
+        if let Value::Map(map) = &get(&v, &["mode"]) {
+            map.borrow_mut().insert(Value::from("Pair"), Value::array(vec![Value::Int(1)]));
+        }
+        assert_eq!(
+            Config::from_pan(&v).unwrap_err().to_string(),
+            "mode.Pair: expected an array of length 2, got one of length 1"
+        );
+
+        let mode = |v: Value| Mode::from_pan(&v).unwrap_err().to_string();
+        let single = |k: &str, v: Value| {
+            Value::map(vec![(Value::from(k), v)].into_iter().collect())
+        };
+        assert_eq!(mode(single("fast", Value::Nil)), "fast: expected int, got nil");
+        assert_eq!(
+            mode(single("Pair", Value::array(vec![Value::Int(1), Value::Int(2)]))),
+            "Pair[1]: expected string, got int"
+        );
+        assert_eq!(
+            mode(single("Named", single("level", Value::Int(300)))),
+            "Named.level: expected an int from 0 to 255, got 300"
+        );
+        assert_eq!(mode(Value::Int(1)), "expected string or map, got int");
+        assert_eq!(mode(Value::map(Default::default())), "expected a map with a single string key");
+        assert_eq!(Config::from_pan(&Value::Nil).unwrap_err().to_string(), "expected map, got nil");
+    }
+}
@@ -0,0 +1,198 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use pan_lang_rs::capabilities::Capabilities;
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::types::bytes::Bytes;
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, field, kind, message, runtime, s};
+
+// A fresh directory below the temporary directory of the system, removed again on drop.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> TempDir {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let n = COUNT.fetch_add(1, Ordering::SeqCst);
+        let path = std::env::temp_dir().join(format!("pan-fs-test-{}-{}", process::id(), n));
+        fs::create_dir_all(&path).unwrap();
+        TempDir(fs::canonicalize(path).unwrap())
+    }
+
+    fn join(&self, path: &str) -> String {
+        self.0.join(path).to_str().unwrap().to_string()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn bytes(b: &[u8]) -> Value {
+    Value::Bytes(Bytes::from_vec(b.to_vec()))
+}
+
+fn error_data(rt: &mut Runtime, err: &Value) -> Value {
+    call(rt, "error_data", std::slice::from_ref(err)).unwrap()
+}
+
+#[test]
+fn the_builtins_require_the_capability() {
+    let rt = Runtime::new();
+    assert!(rt.global("read_file").is_none());
+    let dir = TempDir::new();
+    let rt = runtime(&Capabilities::FULL.allow_fs(Some(&dir.0)));
+    assert!(rt.global("read_file").is_some() && rt.global("mkdir_all").is_some());
+    // A root that does not exist is rejected upfront.
+    assert!(Runtime::new().allow_fs(Some(&dir.0.join("missing"))).is_err());
+}
+
+#[test]
+fn round_trips() {
+    let dir = TempDir::new();
+    let mut rt = runtime(&Capabilities::FULL);
+    let file = s(&dir.join("file.txt"));
+    assert_eq!(call(&mut rt, "file_exists", std::slice::from_ref(&file)), Ok(Value::Bool(false)));
+    call(&mut rt, "write_file", &[file.clone(), s("grüße\n")]).unwrap();
+    call(&mut rt, "append_file", &[file.clone(), bytes(b"\x00\xff")]).unwrap();
+    let read = call(&mut rt, "read_file", std::slice::from_ref(&file)).unwrap();
+    assert_eq!(read, bytes(b"gr\xc3\xbc\xc3\x9fe\n\x00\xff"));
+    assert_eq!(fs::read(dir.0.join("file.txt")).unwrap(), b"gr\xc3\xbc\xc3\x9fe\n\x00\xff");
+
+    // Writing truncates, appending creates missing files.
+    call(&mut rt, "write_file", &[file.clone(), s("short")]).unwrap();
+    assert_eq!(call(&mut rt, "read_file_text", std::slice::from_ref(&file)), Ok(s("short")));
+    let other = s(&dir.join("other"));
+    call(&mut rt, "append_file", &[other.clone(), s("a")]).unwrap();
+    call(&mut rt, "append_file", &[other.clone(), s("b")]).unwrap();
+    assert_eq!(call(&mut rt, "read_file_text", std::slice::from_ref(&other)), Ok(s("ab")));
+    call(&mut rt, "write_file", &[other.clone(), bytes(b"")]).unwrap();
+    assert_eq!(call(&mut rt, "read_file", std::slice::from_ref(&other)), Ok(bytes(b"")));
+
+    call(&mut rt, "mkdir_all", &[s(&dir.join("a/b/c"))]).unwrap();
+    call(&mut rt, "mkdir_all", &[s(&dir.join("a/b"))]).unwrap();
+    let listed = call(&mut rt, "list_dir", &[s(&dir.join(""))]).unwrap();
+    assert_eq!(listed, arr(vec![s("a"), s("file.txt"), s("other")]));
+    assert_eq!(call(&mut rt, "list_dir", &[s(&dir.join("a/b/c"))]), Ok(arr(vec![])));
+    assert_eq!(call(&mut rt, "file_exists", &[s(&dir.join("a/b"))]), Ok(Value::Bool(true)));
+
+    call(&mut rt, "remove_file", std::slice::from_ref(&file)).unwrap();
+    assert_eq!(call(&mut rt, "file_exists", std::slice::from_ref(&file)), Ok(Value::Bool(false)));
+    assert!(!dir.0.join("file.txt").exists());
+}
+
+#[test]
+fn failures_carry_the_os_error_kind_and_the_path() {
+    let dir = TempDir::new();
+    let mut rt = runtime(&Capabilities::FULL);
+    let missing = dir.join("missing");
+    for name in ["read_file", "read_file_text", "remove_file", "list_dir"].iter() {
+        let err = call(&mut rt, name, &[s(&missing)]).unwrap_err();
+        assert_eq!(kind(&err), "io", "{}", name);
+        assert!(message(&err).starts_with(&format!("{} failed on {}", name, missing)));
+        let data = error_data(&mut rt, &err);
+        assert_eq!(field(&data, "io_kind"), s("not_found"), "{}", name);
+        assert_eq!(field(&data, "path"), s(&missing), "{}", name);
+    }
+    let nested = dir.join("missing/file");
+    let err = call(&mut rt, "write_file", &[s(&nested), s("x")]).unwrap_err();
+    assert_eq!(field(&error_data(&mut rt, &err), "io_kind"), s("not_found"));
+
+    // Reading a directory or listing a file fails too.
+    assert_eq!(kind(&call(&mut rt, "read_file", &[s(&dir.join(""))]).unwrap_err()), "io");
+    call(&mut rt, "write_file", &[s(&dir.join("f")), s("")]).unwrap();
+    assert_eq!(kind(&call(&mut rt, "list_dir", &[s(&dir.join("f"))]).unwrap_err()), "io");
+    assert_eq!(kind(&call(&mut rt, "mkdir_all", &[s(&dir.join("f/g"))]).unwrap_err()), "io");
+}
+
+#[test]
+fn text_must_be_utf8() {
+    let dir = TempDir::new();
+    let mut rt = runtime(&Capabilities::FULL);
+    fs::write(dir.0.join("latin1"), b"caf\xe9").unwrap();
+    let err = call(&mut rt, "read_file_text", &[s(&dir.join("latin1"))]).unwrap_err();
+    assert_eq!(kind(&err), "decode");
+    assert!(message(&err).contains(&dir.join("latin1")), "{}", message(&err));
+    // The bytes themselves can still be read.
+    assert_eq!(call(&mut rt, "read_file", &[s(&dir.join("latin1"))]), Ok(bytes(b"caf\xe9")));
+}
+
+#[test]
+fn wrong_types_are_thrown() {
+    let mut rt = runtime(&Capabilities::FULL);
+    assert_eq!(kind(&call(&mut rt, "read_file", &[]).unwrap_err()), "type");
+    assert_eq!(kind(&call(&mut rt, "file_exists", &[bytes(b"f")]).unwrap_err()), "type");
+    let err = call(&mut rt, "write_file", &[s("never-written"), Value::Int(1)]).unwrap_err();
+    assert_eq!(kind(&err), "type");
+    assert!(!Path::new("never-written").exists());
+}
+
+#[test]
+fn a_root_confines_all_paths() {
+    let outer = TempDir::new();
+    fs::create_dir(outer.0.join("root")).unwrap();
+    fs::write(outer.0.join("secret"), b"secret").unwrap();
+    let root = outer.0.join("root");
+    let mut rt = runtime(&Capabilities::FULL.allow_fs(Some(&root)));
+
+    // Relative paths are resolved against the root.
+    call(&mut rt, "mkdir_all", &[s("sub/dir")]).unwrap();
+    call(&mut rt, "write_file", &[s("sub/dir/../f"), s("inside")]).unwrap();
+    assert_eq!(fs::read(root.join("sub/f")).unwrap(), b"inside");
+    assert_eq!(call(&mut rt, "read_file_text", &[s("./sub/f")]), Ok(s("inside")));
+    assert_eq!(call(&mut rt, "list_dir", &[s(".")]), Ok(arr(vec![s("sub")])));
+    let inside = root.join("sub/f").to_str().unwrap().to_string();
+    assert_eq!(call(&mut rt, "read_file_text", &[s(&inside)]), Ok(s("inside")));
+
+    let escapes = [
+        "../secret".to_string(),
+        "sub/../../secret".to_string(),
+        "sub/dir/../../../secret".to_string(),
+        "missing/../../secret".to_string(),
+        "..".to_string(),
+        outer.join("secret"),
+        "/".to_string(),
+    ];
+    for path in escapes.iter() {
+        for name in ["read_file", "file_exists", "list_dir", "remove_file"].iter() {
+            let err = call(&mut rt, name, &[s(path)]).unwrap_err();
+            assert_eq!(kind(&err), "forbidden", "{} {}", name, path);
+            assert_eq!(error_data(&mut rt, &err), s(path));
+        }
+        let err = call(&mut rt, "write_file", &[s(path), s("x")]).unwrap_err();
+        assert_eq!(kind(&err), "forbidden", "{}", path);
+    }
+    // Paths that do not exist yet can not escape either.
+    let err = call(&mut rt, "mkdir_all", &[s("new/../../escaped")]).unwrap_err();
+    assert_eq!(kind(&err), "forbidden");
+    assert!(!outer.0.join("escaped").exists());
+    assert_eq!(fs::read(outer.0.join("secret")).unwrap(), b"secret");
+}
+
+#[cfg(unix)]
+#[test]
+fn symbolic_links_can_not_leave_the_root() {
+    let outer = TempDir::new();
+    let root = outer.0.join("root");
+    fs::create_dir_all(root.join("inner")).unwrap();
+    fs::write(outer.0.join("secret"), b"secret").unwrap();
+    std::os::unix::fs::symlink(outer.0.join("secret"), root.join("link")).unwrap();
+    std::os::unix::fs::symlink(&outer.0, root.join("up")).unwrap();
+    std::os::unix::fs::symlink(root.join("inner"), root.join("alias")).unwrap();
+    let mut rt = runtime(&Capabilities::FULL.allow_fs(Some(&root)));
+
+    assert_eq!(kind(&call(&mut rt, "read_file", &[s("link")]).unwrap_err()), "forbidden");
+    let err = call(&mut rt, "write_file", &[s("up/new"), s("x")]).unwrap_err();
+    assert_eq!(kind(&err), "forbidden");
+    assert!(!outer.0.join("new").exists());
+    // Links that stay inside of the root are fine.
+    call(&mut rt, "write_file", &[s("alias/f"), s("x")]).unwrap();
+    assert_eq!(fs::read(root.join("inner/f")).unwrap(), b"x");
+}
@@ -0,0 +1,175 @@
+mod common;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use futures::future::LocalFutureObj;
+
+use pan_lang_rs::runtime::{Arity, Runtime};
+use pan_lang_rs::value::{Fun, Native, NativeAsync, Value};
+
+use common::{arr, call, int, kind, message, s};
+
+fn reflect(rt: &mut Runtime, name: &str, f: &Value) -> Value {
+    call(rt, name, std::slice::from_ref(f)).unwrap()
+}
+
+#[test]
+fn natives_report_their_registration() {
+    let mut rt = Runtime::new();
+    rt.register("plain", |_| Ok(Value::Nil));
+    rt.register_with_arity("pair", 2, |_| Ok(Value::Nil));
+    rt.register_native("named", Arity::Named(&["x", "y"]), |_, _| Ok(Value::Nil)).unwrap();
+    rt.register_native("variadic", Arity::Variadic, |_, _| Ok(Value::Nil)).unwrap();
+    rt.register_native("nullary", Arity::Max(0), |_, _| Ok(Value::Nil)).unwrap();
+    rt.register_async("later", |_| LocalFutureObj::new(Box::new(async { Ok(Value::Nil) })));
+
+    let expected = [
+        ("plain", Value::Nil, "native", Value::Nil),
+        ("pair", int(2), "native", Value::Nil),
+        ("named", int(2), "native", arr(vec![s("x"), s("y")])),
+        ("variadic", Value::Nil, "native", Value::Nil),
+        ("nullary", int(0), "native", Value::Nil),
+        ("later", Value::Nil, "native_async", Value::Nil),
+    ];
+    for (name, arity, fun_kind, params) in expected.iter() {
+        let f = rt.global(name).unwrap();
+        assert_eq!(&reflect(&mut rt, "fun_name", &f), &s(name));
+        assert_eq!(&reflect(&mut rt, "fun_arity", &f), arity, "{}", name);
+        assert_eq!(reflect(&mut rt, "fun_kind", &f), s(fun_kind), "{}", name);
+        assert_eq!(&reflect(&mut rt, "fun_params", &f), params, "{}", name);
+    }
+
+    // The builtins declare no arity unless registered with one.
+    let len = rt.global("len").unwrap();
+    assert_eq!(reflect(&mut rt, "fun_name", &len), s("len"));
+    assert_eq!(reflect(&mut rt, "fun_kind", &len), s("native"));
+}
+
+#[test]
+fn natives_made_from_rust_keep_their_names() {
+    let mut rt = Runtime::new();
+    let f = Value::Fun(Fun::Native(Native::with_arity("from_rust", 4, |_| Ok(Value::Nil))));
+    assert_eq!(reflect(&mut rt, "fun_name", &f), s("from_rust"));
+    assert_eq!(reflect(&mut rt, "fun_arity", &f), int(4));
+    // Registering a function under another name does not rename it.
+    rt.define("alias", f.clone()).unwrap();
+    let alias = rt.global("alias").unwrap();
+    assert_eq!(reflect(&mut rt, "fun_name", &alias), s("from_rust"));
+}
+
+#[test]
+fn named_arguments_go_into_the_positions_of_their_parameters() {
+    let mut rt = Runtime::new();
+    rt.register_native("span", Arity::Named(&["from", "to", "step"]), |_, args| {
+        Ok(arr(args.to_vec()))
+    })
+    .unwrap();
+    let span = rt.global("span").unwrap();
+    let named = |fields: Vec<(&str, Value)>| Value::record(fields);
+    let step = span.apply_named(&[int(1)], &named(vec![("step", int(2))]));
+    assert_eq!(step, Ok(arr(vec![int(1), Value::Nil, int(2)])));
+    let reordered = span.apply_named(&[], &named(vec![("to", int(5)), ("from", int(1))]));
+    assert_eq!(reordered, Ok(arr(vec![int(1), int(5)])));
+    let positional = span.apply_named(&[int(1), int(2)], &named(vec![]));
+    assert_eq!(positional, Ok(arr(vec![int(1), int(2)])));
+
+    // Natives made from rust can declare their parameters as well.
+    let f = Native::with_params("first", &["a", "b"], |args| Ok(args[0].clone()));
+    let f = Value::Fun(Fun::Native(f));
+    assert_eq!(f.apply_named(&[], &named(vec![("a", int(7))])), Ok(int(7)));
+    let len = rt.global("len").unwrap();
+    assert_eq!(len.apply_named(&[arr(vec![])], &named(vec![])), Ok(int(0)));
+
+    let failures = [
+        (&span, vec![("size", int(1))], "argument", "span has no parameter named size"),
+        (
+            &span,
+            vec![("from", int(1))],
+            "argument",
+            "the argument from of span is given both positionally and by name",
+        ),
+        (&len, vec![("xs", arr(vec![]))], "argument", "len does not take named arguments"),
+    ];
+    for (f, fields, expected_kind, expected_message) in failures.iter() {
+        let err = f.apply_named(&[int(0)], &named(fields.clone())).unwrap_err();
+        assert_eq!(&kind(&err), expected_kind);
+        assert_eq!(&message(&err), expected_message);
+    }
+    let err = span.apply_named(&[], &arr(vec![])).unwrap_err();
+    assert_eq!(message(&err), "named arguments must be a map, got array");
+    let numbered = Value::map(Default::default());
+    call(&mut rt, "map_insert", &[numbered.clone(), int(0), int(1)]).unwrap();
+    let err = span.apply_named(&[], &numbered).unwrap_err();
+    assert_eq!(message(&err), "the names of named arguments must be strings, got int");
+}
+
+#[test]
+fn async_natives_take_named_arguments() {
+    let mut rt = Runtime::new();
+    let pair = Value::Fun(Fun::NativeAsync(NativeAsync::with_params("pair", &["a", "b"], |args| {
+        let args = args.to_vec();
+        LocalFutureObj::new(Box::new(async move { Ok(Value::array(args)) }))
+    })));
+    assert_eq!(reflect(&mut rt, "fun_params", &pair), arr(vec![s("a"), s("b")]));
+    assert_eq!(reflect(&mut rt, "fun_arity", &pair), int(2));
+
+    let fut = pair.apply_named(&[], &Value::record(vec![("b", int(2))])).unwrap();
+    assert_eq!(rt.block_on_raw(&fut), Ok(arr(vec![Value::Nil, int(2)])));
+    let fut = pair.apply_named(&[int(1)], &Value::record(vec![("b", int(2))])).unwrap();
+    assert_eq!(rt.block_on_raw(&fut), Ok(arr(vec![int(1), int(2)])));
+
+    // Async natives without parameter names reject named arguments, before creating a future.
+    let later = Value::Fun(Fun::NativeAsync(NativeAsync::new("later", |_| {
+        LocalFutureObj::new(Box::new(async { Ok(Value::Nil) }))
+    })));
+    let err = later.apply_named(&[], &Value::record(vec![("a", int(1))])).unwrap_err();
+    assert_eq!(message(&err), "later does not take named arguments");
+    let err = pair.apply_named(&[], &Value::record(vec![("c", int(1))])).unwrap_err();
+    assert_eq!(message(&err), "pair has no parameter named c");
+}
+
+#[test]
+fn ids_order_functions_by_creation() {
+    let mut rt = Runtime::new();
+    let first = Value::Fun(Fun::Native(Native::new("first", |_| Ok(Value::Nil))));
+    let second = Value::Fun(Fun::Native(Native::new("second", |_| Ok(Value::Nil))));
+    let (a, b) = (reflect(&mut rt, "fun_id", &first), reflect(&mut rt, "fun_id", &second));
+    assert!(a < b, "{} {}", a, b);
+    assert_eq!(reflect(&mut rt, "fun_id", &first.clone()), a);
+}
+
+#[test]
+fn runtimes_stamp_their_functions_independently() {
+    let hash = |v: &Value| {
+        let mut hasher = DefaultHasher::new();
+        v.hash(&mut hasher);
+        hasher.finish()
+    };
+    let mut registered = vec![];
+    for _ in 0..2 {
+        let mut rt = Runtime::new();
+        rt.register_native("f", Arity::Max(0), |_, _| Ok(Value::Nil)).unwrap();
+        let f = rt.global("f").unwrap();
+        registered.push((reflect(&mut rt, "fun_id", &f), f));
+    }
+    let ((id_a, a), (id_b, b)) = (&registered[0], &registered[1]);
+    assert_eq!(id_a, id_b);
+    assert_eq!(a.to_string(), format!("<function #{} f>", id_a));
+    // Equality is identity, but the hash only depends on the stamp.
+    assert_ne!(a, b);
+    assert_eq!(hash(a), hash(b));
+    assert_eq!(hash(a), hash(&a.clone()));
+}
+
+#[test]
+fn non_functions_throw() {
+    let mut rt = Runtime::new();
+    for name in ["fun_arity", "fun_name", "fun_kind", "fun_params", "fun_id"].iter() {
+        for v in [Value::Nil, int(1), s("len"), arr(vec![])].iter() {
+            let err = call(&mut rt, name, std::slice::from_ref(v)).unwrap_err();
+            assert_eq!(kind(&err), "type", "{} {}", name, v);
+        }
+        assert_eq!(kind(&call(&mut rt, name, &[]).unwrap_err()), "type", "{}", name);
+    }
+}
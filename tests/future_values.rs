@@ -0,0 +1,68 @@
+use pan_lang_rs::builtins::futures::{fut_never, fut_reject, fut_resolve};
+use pan_lang_rs::types::futures::{EventLoop, Future, LifecycleState};
+use pan_lang_rs::value::Value;
+
+fn future(v: &Value) -> &Future {
+    match v {
+        Value::Future(fut) => fut,
+        other => panic!("not a future: {:?}", other),
+    }
+}
+
+#[test]
+fn resolve_and_reject_settle_as_soon_as_they_run() {
+    let event_loop = EventLoop::new();
+    let resolved = fut_resolve(&[Value::Int(1)]).unwrap();
+    let rejected = fut_reject(&[Value::Int(2)]).unwrap();
+    assert_eq!(future(&resolved).state(), LifecycleState::Inert);
+    assert_eq!(future(&rejected).state(), LifecycleState::Inert);
+
+    // Running settles them right away, the loop has nothing to do.
+    let resolving = event_loop.run_future(future(&resolved)).unwrap();
+    let rejecting = event_loop.run_future(future(&rejected)).unwrap();
+    assert_eq!(future(&resolved).state(), LifecycleState::Resolved);
+    assert_eq!(future(&rejected).state(), LifecycleState::Rejected);
+    assert_eq!(event_loop.run_until(&resolving), Some(Ok(Value::Int(1))));
+    assert_eq!(event_loop.run_until(&rejecting), Some(Err(Value::Int(2))));
+
+    // Running them again is an illegal transition, and the outcomes are only handed out once.
+    assert!(event_loop.run_future(future(&resolved)).is_err());
+    assert!(event_loop.run_future(future(&rejected)).is_err());
+    assert_eq!(future(&resolved).state(), LifecycleState::Resolved);
+    assert_eq!(event_loop.run_until(&resolving), None);
+}
+
+#[test]
+fn futures_are_compared_by_identity() {
+    let a = fut_resolve(&[]).unwrap();
+    let b = fut_resolve(&[]).unwrap();
+    assert_eq!(a, a.clone());
+    assert_ne!(a, b);
+    assert!(Future::ptr_eq(future(&a), future(&a.clone())));
+}
+
+#[test]
+fn never_futures_stay_pending_until_cancelled() {
+    let event_loop = EventLoop::new();
+    let never = fut_never(&[]).unwrap();
+    drop(event_loop.run_future(future(&never)).unwrap());
+
+    // The loop does not wait for it.
+    event_loop.run_until_idle();
+    assert_eq!(future(&never).state(), LifecycleState::Running);
+    assert!(future(&never).cancel());
+    assert_eq!(future(&never).state(), LifecycleState::Cancelled);
+    assert!(!future(&never).cancel());
+}
+
+#[test]
+fn dropping_a_never_future_releases_its_job() {
+    let event_loop = EventLoop::new();
+    let never = fut_never(&[]).unwrap();
+    drop(event_loop.run_future(future(&never)).unwrap());
+    let id = future(&never).job().unwrap().id();
+    assert!(event_loop.active_jobs().contains(&id));
+    drop(never);
+    gc::force_collect();
+    assert!(!event_loop.active_jobs().contains(&id));
+}
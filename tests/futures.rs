@@ -0,0 +1,1204 @@
+use std::cell::{Cell, RefCell};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+
+use futures::future::LocalFutureObj;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::types::futures::{
+    Clock, EventLoop, Future, FutureError, LifecycleState, ManualClock,
+};
+use pan_lang_rs::types::userdata::{FinalizeCtx, PanUserdata, Userdata};
+use pan_lang_rs::value::{Fun, Native, Value};
+
+fn call(runtime: &mut Runtime, name: &str, args: &[Value]) -> Result<Value, Value> {
+    let fun = runtime.global(name).unwrap();
+    runtime.call_raw(&fun, args)
+}
+
+fn state(fut: &Value) -> LifecycleState {
+    match fut {
+        Value::Future(fut) => fut.state(),
+        other => panic!("not a future: {:?}", other),
+    }
+}
+
+thread_local! {
+    static FINALIZED: Cell<usize> = const { Cell::new(0) };
+}
+
+// A payload that counts how often it was finalized.
+#[derive(Debug)]
+struct Payload;
+
+impl PanUserdata for Payload {
+    fn type_name(&self) -> &'static str {
+        "payload"
+    }
+
+    fn finalize(&mut self, _ctx: &mut FinalizeCtx) {
+        FINALIZED.with(|finalized| finalized.set(finalized.get() + 1));
+    }
+}
+
+fn finalized() -> usize {
+    FINALIZED.with(Cell::get)
+}
+
+#[test]
+fn fut_resolve_and_fut_reject() {
+    let mut rt = Runtime::new();
+    let resolved = call(&mut rt, "fut_resolve", &[Value::Int(1)]).unwrap();
+    let rejected = call(&mut rt, "fut_reject", &[Value::Int(2)]).unwrap();
+    assert_eq!(state(&resolved), LifecycleState::Inert);
+    assert_eq!(state(&rejected), LifecycleState::Inert);
+    assert_eq!(rt.block_on_raw(&resolved), Ok(Value::Int(1)));
+    assert_eq!(rt.block_on_raw(&rejected), Err(Value::Int(2)));
+    assert_eq!(state(&resolved), LifecycleState::Resolved);
+    assert_eq!(state(&rejected), LifecycleState::Rejected);
+    // Settling again changes nothing.
+    assert_eq!(rt.block_on_raw(&resolved), Ok(Value::Int(1)));
+    assert_eq!(rt.block_on_raw(&rejected), Err(Value::Int(2)));
+    assert_eq!(state(&resolved), LifecycleState::Resolved);
+    assert_eq!(state(&rejected), LifecycleState::Rejected);
+}
+
+fn job_result(fut: &Value) -> Option<Result<Value, Value>> {
+    match fut {
+        Value::Future(fut) => fut.job().unwrap().take_result(),
+        other => panic!("not a future: {:?}", other),
+    }
+}
+
+// The future hands its value to the event loop when it settles, so once the outcome has been
+// taken from the loop, nothing keeps the value alive even though the future is.
+#[test]
+fn settled_futures_release_their_value() {
+    let mut rt = Runtime::new();
+    let before = finalized();
+    let resolved = Value::future_resolve(Value::Userdata(Userdata::new(Payload)));
+    let rejected = Value::future_reject(Value::Userdata(Userdata::new(Payload)));
+    rt.gc_collect();
+    assert_eq!(finalized(), before);
+
+    assert!(rt.block_on_raw(&resolved).is_ok());
+    assert!(rt.block_on_raw(&rejected).is_err());
+    rt.gc_collect();
+    assert_eq!(finalized(), before, "the loop keeps the outcomes until they are taken");
+
+    assert!(job_result(&resolved).unwrap().is_ok());
+    assert!(job_result(&rejected).unwrap().is_err());
+    rt.gc_collect();
+    assert_eq!(finalized(), before + 2);
+    assert_eq!(state(&resolved), LifecycleState::Resolved);
+    assert_eq!(state(&rejected), LifecycleState::Rejected);
+}
+
+fn never(rt: &mut Runtime) -> Value {
+    call(rt, "fut_never", &[]).unwrap()
+}
+
+fn run(rt: &Runtime, fut: &Value) {
+    match fut {
+        Value::Future(fut) => drop(rt.event_loop().run_future(fut).unwrap()),
+        other => panic!("not a future: {:?}", other),
+    }
+}
+
+#[test]
+fn fut_never_is_pending_until_cancelled() {
+    let mut rt = Runtime::new();
+    let fut = never(&mut rt);
+    assert_eq!(state(&fut), LifecycleState::Inert);
+    run(&rt, &fut);
+    rt.event_loop().run_until_idle();
+    assert_eq!(state(&fut), LifecycleState::Running);
+    assert_eq!(call(&mut rt, "fut_cancel", std::slice::from_ref(&fut)), Ok(Value::Bool(true)));
+    assert_eq!(state(&fut), LifecycleState::Cancelled);
+}
+
+#[test]
+fn run_until_idle_returns_with_a_never_future_outstanding() {
+    let mut rt = Runtime::new();
+    let fut = never(&mut rt);
+    run(&rt, &fut);
+    let start = std::time::Instant::now();
+    rt.event_loop().run_until_idle();
+    assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    assert!(rt.event_loop().is_idle());
+    assert_eq!(state(&fut), LifecycleState::Running);
+}
+
+#[test]
+fn fut_race_with_never_settles_like_the_other_future() {
+    let mut rt = Runtime::new();
+    let fut = never(&mut rt);
+    let other = call(&mut rt, "fut_sleep", &[Value::Int(0)]).unwrap();
+    let race = call(&mut rt, "fut_race", &[Value::array(vec![fut.clone(), other])]).unwrap();
+    assert_eq!(rt.block_on_raw(&race), Ok(Value::Nil));
+    assert_eq!(state(&fut), LifecycleState::Cancelled);
+
+    let fut = never(&mut rt);
+    let rejected = call(&mut rt, "fut_reject", &[Value::Int(1)]).unwrap();
+    let race = call(&mut rt, "fut_race", &[Value::array(vec![fut, rejected])]).unwrap();
+    assert_eq!(rt.block_on_raw(&race), Err(Value::Int(1)));
+}
+
+#[test]
+fn fut_all_with_never_only_settles_on_rejection_or_cancellation() {
+    let mut rt = Runtime::new();
+    let fut = never(&mut rt);
+    let all = call(&mut rt, "fut_all", &[Value::array(vec![fut.clone(), Value::Int(1)])]).unwrap();
+    run(&rt, &all);
+    rt.event_loop().run_until_idle();
+    assert_eq!(state(&all), LifecycleState::Running);
+    call(&mut rt, "fut_cancel", &[fut]).unwrap();
+    let err = rt.block_on_raw(&all).unwrap_err();
+    assert_eq!(err.error_kind().unwrap().to_string(), "cancelled");
+
+    let fut = never(&mut rt);
+    let rejected = call(&mut rt, "fut_reject", &[Value::Int(2)]).unwrap();
+    let all = call(&mut rt, "fut_all", &[Value::array(vec![fut.clone(), rejected])]).unwrap();
+    assert_eq!(rt.block_on_raw(&all), Err(Value::Int(2)));
+    assert_eq!(state(&fut), LifecycleState::Cancelled);
+}
+
+#[test]
+fn dropping_a_never_future_releases_its_job() {
+    let mut rt = Runtime::new();
+    let fut = never(&mut rt);
+    run(&rt, &fut);
+    let id = match &fut {
+        Value::Future(fut) => fut.job().unwrap().id(),
+        _ => unreachable!(),
+    };
+    assert!(rt.event_loop().active_jobs().contains(&id));
+    drop(fut);
+    rt.gc_collect();
+    assert!(!rt.event_loop().active_jobs().contains(&id));
+}
+
+#[test]
+fn the_legal_transitions() {
+    use pan_lang_rs::types::futures::LifecycleState::*;
+
+    let states = [Inert, Staged, Running, Resolved, Rejected, Cancelled];
+    let legal = [
+        (Inert, Staged),
+        (Inert, Running),
+        (Staged, Running),
+        (Running, Resolved),
+        (Running, Rejected),
+        (Running, Cancelled),
+    ];
+    for &from in &states {
+        for &to in &states {
+            let expected = legal.contains(&(from, to));
+            assert_eq!(from.can_transition_to(to), expected, "{:?} to {:?}", from, to);
+        }
+        assert_eq!(from.is_done(), matches!(from, Resolved | Rejected | Cancelled));
+    }
+}
+
+#[test]
+fn illegal_transitions_are_rejected() {
+    let rt = Runtime::new();
+    let fut = Future::resolve(Value::Int(1));
+    rt.event_loop().run_future(&fut).unwrap();
+    assert_eq!(fut.state(), LifecycleState::Resolved);
+    // Running a settled future again.
+    assert_eq!(
+        rt.event_loop().run_future(&fut),
+        Err(FutureError::IllegalTransition {
+            from: LifecycleState::Resolved,
+            to: LifecycleState::Running,
+        })
+    );
+
+    let fut = Future::never();
+    rt.event_loop().run_future(&fut).unwrap();
+    assert_eq!(
+        rt.event_loop().run_future(&fut),
+        Err(FutureError::IllegalTransition {
+            from: LifecycleState::Running,
+            to: LifecycleState::Running,
+        })
+    );
+    assert!(fut.cancel());
+    assert!(!fut.cancel());
+    assert_eq!(fut.state(), LifecycleState::Cancelled);
+}
+
+fn state_name(rt: &mut Runtime, fut: &Value) -> Value {
+    call(rt, "fut_state", std::slice::from_ref(fut)).unwrap()
+}
+
+#[test]
+fn fut_state_names_every_stage() {
+    let mut rt = Runtime::new();
+    let name = |s: &str| Value::from(s);
+
+    let sleep = call(&mut rt, "fut_sleep", &[Value::Int(0)]).unwrap();
+    assert_eq!(state_name(&mut rt, &sleep), name("inert"));
+    run(&rt, &sleep);
+    assert_eq!(state_name(&mut rt, &sleep), name("pending"));
+    rt.block_on_raw(&sleep).unwrap();
+    assert_eq!(state_name(&mut rt, &sleep), name("resolved"));
+
+    let rejected = call(&mut rt, "fut_reject", &[Value::Int(1)]).unwrap();
+    assert!(rt.block_on_raw(&rejected).is_err());
+    assert_eq!(state_name(&mut rt, &rejected), name("rejected"));
+
+    let fut = never(&mut rt);
+    run(&rt, &fut);
+    call(&mut rt, "fut_cancel", std::slice::from_ref(&fut)).unwrap();
+    assert_eq!(state_name(&mut rt, &fut), name("cancelled"));
+
+    let err = call(&mut rt, "fut_state", &[Value::Int(1)]).unwrap_err();
+    assert_eq!(err.error_kind().unwrap().to_string(), "type");
+}
+
+#[test]
+fn on_transition_sees_every_change() {
+    let mut rt = Runtime::new();
+    let log = Rc::new(RefCell::new(vec![]));
+    let recorded = log.clone();
+    rt.event_loop().on_transition(move |_, from, to| recorded.borrow_mut().push((from, to)));
+
+    let sleep = call(&mut rt, "fut_sleep", &[Value::Int(0)]).unwrap();
+    rt.block_on_raw(&sleep).unwrap();
+    assert_eq!(
+        *log.borrow(),
+        [
+            (LifecycleState::Inert, LifecycleState::Running),
+            (LifecycleState::Running, LifecycleState::Resolved),
+        ]
+    );
+
+    log.borrow_mut().clear();
+    let fut = never(&mut rt);
+    run(&rt, &fut);
+    call(&mut rt, "fut_cancel", &[fut]).unwrap();
+    assert_eq!(
+        *log.borrow(),
+        [
+            (LifecycleState::Inert, LifecycleState::Running),
+            (LifecycleState::Running, LifecycleState::Cancelled),
+        ]
+    );
+}
+
+type Log = Rc<RefCell<Vec<String>>>;
+
+// A native function that logs its name and argument, and then returns `f` of the argument.
+fn logged(log: &Log, name: &'static str, f: fn(Value) -> Result<Value, Value>) -> Value {
+    let log = log.clone();
+    Value::Fun(Fun::Native(Native::new(name, move |args| {
+        log.borrow_mut().push(format!("{}({})", name, args[0]));
+        f(args[0].clone())
+    })))
+}
+
+fn inc(v: Value) -> Result<Value, Value> {
+    match v {
+        Value::Int(n) => Ok(Value::Int(n + 1)),
+        other => panic!("not an int: {:?}", other),
+    }
+}
+
+fn throw(v: Value) -> Result<Value, Value> {
+    Err(Value::array(vec![Value::from("thrown"), v]))
+}
+
+#[test]
+fn then_chains_stop_at_the_first_throw() {
+    let mut rt = Runtime::new();
+    let log = Log::default();
+    let start = call(&mut rt, "fut_resolve", &[Value::Int(1)]).unwrap();
+    let a = call(&mut rt, "fut_then", &[start, logged(&log, "a", inc)]).unwrap();
+    let b = call(&mut rt, "fut_then", &[a, logged(&log, "b", throw)]).unwrap();
+    let c = call(&mut rt, "fut_then", &[b, logged(&log, "c", inc)]).unwrap();
+    // The callbacks run on the loop, not inside `fut_then`.
+    assert!(log.borrow().is_empty());
+    let thrown = Value::array(vec![Value::from("thrown"), Value::Int(2)]);
+    assert_eq!(rt.block_on_raw(&c), Err(thrown));
+    assert_eq!(*log.borrow(), ["a(1)", "b(2)"]);
+    assert_eq!(state(&c), LifecycleState::Rejected);
+}
+
+#[test]
+fn rejections_pass_through_then_and_resolutions_through_map_err() {
+    let mut rt = Runtime::new();
+    let log = Log::default();
+    let rejected = call(&mut rt, "fut_reject", &[Value::Int(1)]).unwrap();
+    let then = call(&mut rt, "fut_then", &[rejected, logged(&log, "then", inc)]).unwrap();
+    let mapped = call(&mut rt, "fut_map_err", &[then, logged(&log, "map_err", inc)]).unwrap();
+    let again = call(&mut rt, "fut_map_err", &[mapped, logged(&log, "again", inc)]).unwrap();
+    let last = call(&mut rt, "fut_then", &[again, logged(&log, "last", inc)]).unwrap();
+    assert_eq!(rt.block_on_raw(&last), Ok(Value::Int(3)));
+    assert_eq!(*log.borrow(), ["map_err(1)", "last(2)"]);
+
+    let rejected = call(&mut rt, "fut_reject", &[Value::Int(1)]).unwrap();
+    let mapped = call(&mut rt, "fut_map_err", &[rejected, logged(&log, "throw", throw)]).unwrap();
+    assert_eq!(
+        rt.block_on_raw(&mapped),
+        Err(Value::array(vec![Value::from("thrown"), Value::Int(1)]))
+    );
+}
+
+#[test]
+fn callbacks_of_independent_chains_interleave() {
+    let mut rt = Runtime::new();
+    let log = Log::default();
+    let mut chains = vec![];
+    for &name in &["x", "y"] {
+        let start = call(&mut rt, "fut_resolve", &[Value::Int(0)]).unwrap();
+        let first = call(&mut rt, "fut_then", &[start, logged(&log, name, inc)]).unwrap();
+        chains.push(call(&mut rt, "fut_then", &[first, logged(&log, name, inc)]).unwrap());
+    }
+    let all = call(&mut rt, "fut_all", &[Value::array(chains)]).unwrap();
+    assert_eq!(rt.block_on_raw(&all), Ok(Value::array(vec![Value::Int(2), Value::Int(2)])));
+    // Callbacks are microtasks, which run in the order in which their sources settled.
+    assert_eq!(*log.borrow(), ["x(0)", "y(0)", "x(1)", "y(1)"]);
+
+    let err = call(&mut rt, "fut_then", &[Value::Int(1), Value::Nil]).unwrap_err();
+    assert_eq!(err.error_kind().unwrap().to_string(), "type");
+}
+
+// A future that resolves with `v` after the given time, logging `name` when it does.
+fn after(rt: &mut Runtime, log: &Log, millis: i64, name: &'static str, v: Value) -> Value {
+    let sleep = call(rt, "fut_sleep", &[Value::Int(millis)]).unwrap();
+    let log = log.clone();
+    let callback = Value::Fun(Fun::Native(Native::new(name, move |_| {
+        log.borrow_mut().push(name.to_string());
+        Ok(v.clone())
+    })));
+    call(rt, "fut_then", &[sleep, callback]).unwrap()
+}
+
+#[test]
+fn fut_all_keeps_the_input_order() {
+    let mut rt = Runtime::new_deterministic(367);
+    let log = Log::default();
+    let inputs = vec![
+        after(&mut rt, &log, 30, "a", Value::from("a")),
+        after(&mut rt, &log, 10, "b", Value::from("b")),
+        Value::from("plain"),
+        after(&mut rt, &log, 20, "c", Value::from("c")),
+        call(&mut rt, "fut_resolve", &[Value::from("d")]).unwrap(),
+    ];
+    let all = call(&mut rt, "fut_all", &[Value::array(inputs)]).unwrap();
+    let expected = ["a", "b", "plain", "c", "d"].iter().map(|&s| Value::from(s)).collect();
+    assert_eq!(rt.block_on_raw(&all), Ok(Value::array(expected)));
+    assert_eq!(*log.borrow(), ["b", "c", "a"]);
+}
+
+#[test]
+fn fut_all_rejects_early_and_cancels_the_rest() {
+    let mut rt = Runtime::new_deterministic(367);
+    let log = Log::default();
+    let slow = after(&mut rt, &log, 50, "slow", Value::Nil);
+    let never = never(&mut rt);
+    let sleep = call(&mut rt, "fut_sleep", &[Value::Int(10)]).unwrap();
+    let failing = call(&mut rt, "fut_then", &[sleep, logged(&log, "fail", throw)]).unwrap();
+    let inputs = Value::array(vec![slow.clone(), never.clone(), failing]);
+    let all = call(&mut rt, "fut_all", &[inputs]).unwrap();
+    assert_eq!(rt.block_on_raw(&all), Err(throw(Value::Nil).unwrap_err()));
+    assert_eq!(state_name(&mut rt, &slow), Value::from("cancelled"));
+    assert_eq!(state_name(&mut rt, &never), Value::from("cancelled"));
+    rt.event_loop().run_blocking();
+    assert_eq!(*log.borrow(), ["fail(nil)"]);
+}
+
+#[test]
+fn fut_all_of_nothing() {
+    let mut rt = Runtime::new();
+    let all = call(&mut rt, "fut_all", &[Value::array(vec![])]).unwrap();
+    assert_eq!(rt.block_on_raw(&all), Ok(Value::array(vec![])));
+    let err = call(&mut rt, "fut_all", &[Value::Int(1)]).unwrap_err();
+    assert_eq!(err.error_kind().unwrap().to_string(), "type");
+}
+
+#[test]
+fn fut_race_settles_with_the_first_and_cancels_the_rest() {
+    let mut rt = Runtime::new_deterministic(368);
+    let log = Log::default();
+    let slow = after(&mut rt, &log, 50, "slow", Value::from("slow"));
+    let fast = after(&mut rt, &log, 10, "fast", Value::from("fast"));
+    let race = call(&mut rt, "fut_race", &[Value::array(vec![slow.clone(), fast])]).unwrap();
+    assert_eq!(rt.block_on_raw(&race), Ok(Value::from("fast")));
+    assert_eq!(state(&slow), LifecycleState::Cancelled);
+    rt.event_loop().run_blocking();
+    assert_eq!(*log.borrow(), ["fast"]);
+
+    // A rejection wins just like a resolution.
+    let slow = after(&mut rt, &log, 50, "slow", Value::from("slow"));
+    let rejected = call(&mut rt, "fut_reject", &[Value::Int(1)]).unwrap();
+    let race = call(&mut rt, "fut_race", &[Value::array(vec![slow.clone(), rejected])]).unwrap();
+    assert_eq!(rt.block_on_raw(&race), Err(Value::Int(1)));
+    assert_eq!(state(&slow), LifecycleState::Cancelled);
+}
+
+#[test]
+fn simultaneous_settlements_settle_races_once() {
+    let mut rt = Runtime::new_deterministic(368);
+    let log = Log::default();
+    // Both timers fire in the same turn of the loop; the first one to fire wins.
+    let a = after(&mut rt, &log, 10, "a", Value::from("a"));
+    let b = after(&mut rt, &log, 10, "b", Value::from("b"));
+    let race = call(&mut rt, "fut_race", &[Value::array(vec![a, b.clone()])]).unwrap();
+    let race = call(&mut rt, "fut_then", &[race, logged(&log, "race", Ok)]).unwrap();
+    assert_eq!(rt.block_on_raw(&race), Ok(Value::from("a")));
+    rt.event_loop().run_blocking();
+    assert_eq!(*log.borrow(), ["a", r#"race("a")"#]);
+    assert_eq!(state(&b), LifecycleState::Cancelled);
+
+    // Inputs that are already settled: the first one in the array wins.
+    let x = call(&mut rt, "fut_resolve", &[Value::from("x")]).unwrap();
+    let y = call(&mut rt, "fut_reject", &[Value::from("y")]).unwrap();
+    let race = call(&mut rt, "fut_race", &[Value::array(vec![x, y])]).unwrap();
+    assert_eq!(rt.block_on_raw(&race), Ok(Value::from("x")));
+    let race = call(&mut rt, "fut_race", &[Value::array(vec![Value::Int(1), Value::Int(2)])]);
+    assert_eq!(rt.block_on_raw(&race.unwrap()), Ok(Value::Int(1)));
+}
+
+#[test]
+fn fut_any_waits_for_a_resolution() {
+    let mut rt = Runtime::new_deterministic(368);
+    let log = Log::default();
+    let rejected = call(&mut rt, "fut_reject", &[Value::Int(1)]).unwrap();
+    let slow = after(&mut rt, &log, 50, "slow", Value::from("slow"));
+    let fast = after(&mut rt, &log, 10, "fast", Value::from("fast"));
+    let inputs = Value::array(vec![rejected, slow.clone(), fast]);
+    let any = call(&mut rt, "fut_any", &[inputs]).unwrap();
+    assert_eq!(rt.block_on_raw(&any), Ok(Value::from("fast")));
+    assert_eq!(state(&slow), LifecycleState::Cancelled);
+
+    let a = call(&mut rt, "fut_reject", &[Value::Int(1)]).unwrap();
+    let b = call(&mut rt, "fut_sleep", &[Value::Int(10)]).unwrap();
+    let b = call(&mut rt, "fut_then", &[b, logged(&log, "b", throw)]).unwrap();
+    let any = call(&mut rt, "fut_any", &[Value::array(vec![b, a])]).unwrap();
+    let all_rejected = Value::array(vec![throw(Value::Nil).unwrap_err(), Value::Int(1)]);
+    assert_eq!(rt.block_on_raw(&any), Err(all_rejected));
+}
+
+#[test]
+fn racing_nothing_rejects() {
+    let mut rt = Runtime::new();
+    for name in &["fut_race", "fut_any"] {
+        let fut = call(&mut rt, name, &[Value::array(vec![])]).unwrap();
+        let err = rt.block_on_raw(&fut).unwrap_err();
+        assert_eq!(err.error_kind().unwrap().to_string(), "empty");
+    }
+}
+
+#[test]
+fn losers_release_their_values() {
+    let mut rt = Runtime::new();
+    let before = finalized();
+    let loser = call(&mut rt, "fut_never", &[]).unwrap();
+    let payload = Value::Userdata(Userdata::new(Payload));
+    let hold = Value::Fun(Fun::Native(Native::new("hold", move |_| Ok(payload.clone()))));
+    let holding = call(&mut rt, "fut_then", &[loser, hold]).unwrap();
+    let winner = call(&mut rt, "fut_resolve", &[Value::Int(1)]).unwrap();
+    let race = call(&mut rt, "fut_race", &[Value::array(vec![holding.clone(), winner])]).unwrap();
+    assert_eq!(rt.block_on_raw(&race), Ok(Value::Int(1)));
+    assert_eq!(state(&holding), LifecycleState::Cancelled);
+    drop(race);
+    rt.gc_collect();
+    assert_eq!(finalized(), before + 1);
+}
+
+// A runtime on a manual clock, together with the clock.
+fn manual() -> (Runtime, Rc<ManualClock>) {
+    let clock = Rc::new(ManualClock::new());
+    (Runtime::with_clock(clock.clone()), clock)
+}
+
+#[test]
+fn sleeps_fire_in_order_of_their_deadlines() {
+    let (mut rt, clock) = manual();
+    let log = Log::default();
+    let a = after(&mut rt, &log, 30, "a", Value::Nil);
+    let b = after(&mut rt, &log, 10, "b", Value::Nil);
+    run(&rt, &a);
+    run(&rt, &b);
+    rt.event_loop().run_until_idle();
+    assert!(log.borrow().is_empty());
+    assert_eq!(rt.event_loop().next_deadline(), Some(10));
+
+    clock.advance(9);
+    rt.event_loop().run_until_idle();
+    assert!(log.borrow().is_empty());
+    clock.advance(1);
+    rt.event_loop().run_until_idle();
+    assert_eq!(*log.borrow(), ["b"]);
+    assert_eq!(state(&a), LifecycleState::Running);
+    clock.advance(100);
+    rt.event_loop().run_until_idle();
+    assert_eq!(*log.borrow(), ["b", "a"]);
+    assert_eq!(rt.event_loop().next_deadline(), None);
+}
+
+#[test]
+fn cancelled_sleeps_do_not_fire() {
+    let (mut rt, clock) = manual();
+    let log = Log::default();
+    let sleep = after(&mut rt, &log, 10, "a", Value::Nil);
+    run(&rt, &sleep);
+    assert_eq!(call(&mut rt, "fut_cancel", std::slice::from_ref(&sleep)), Ok(Value::Bool(true)));
+    clock.advance(10);
+    rt.event_loop().run_until_idle();
+    assert!(log.borrow().is_empty());
+    assert_eq!(state(&sleep), LifecycleState::Cancelled);
+    // The dead timer does not keep the loop waiting.
+    rt.event_loop().run_blocking();
+    assert_eq!(rt.event_loop().now(), 10);
+}
+
+#[test]
+fn run_blocking_wakes_at_the_deadline() {
+    let (mut rt, clock) = manual();
+    let log = Log::default();
+    let a = after(&mut rt, &log, 50, "a", Value::Nil);
+    let b = after(&mut rt, &log, 20, "b", Value::Nil);
+    run(&rt, &a);
+    run(&rt, &b);
+    let times = Rc::new(RefCell::new(vec![]));
+    let recorded = times.clone();
+    rt.event_loop().on_transition(move |_, _, to| {
+        if to == LifecycleState::Resolved {
+            recorded.borrow_mut().push(clock.now());
+        }
+    });
+    rt.event_loop().run_blocking();
+    assert_eq!(*log.borrow(), ["b", "a"]);
+    assert_eq!(rt.event_loop().now(), 50);
+    // Each sleep and its `fut_then` resolve once the clock reaches the deadline.
+    assert_eq!(*times.borrow(), [20, 20, 50, 50]);
+}
+
+#[test]
+fn short_sleeps_resolve_on_the_next_turn() {
+    let (mut rt, _clock) = manual();
+    for &millis in &[0, -5] {
+        let sleep = call(&mut rt, "fut_sleep", &[Value::Int(millis)]).unwrap();
+        run(&rt, &sleep);
+        assert_eq!(state(&sleep), LifecycleState::Running);
+        rt.event_loop().run_until_idle();
+        assert_eq!(state(&sleep), LifecycleState::Resolved);
+        assert_eq!(rt.event_loop().now(), 0);
+    }
+}
+
+fn timeout(rt: &mut Runtime, fut: &Value, millis: i64) -> Value {
+    call(rt, "fut_timeout", &[fut.clone(), Value::Int(millis)]).unwrap()
+}
+
+#[test]
+fn timeouts_lose_to_faster_futures() {
+    let (mut rt, clock) = manual();
+    let log = Log::default();
+    let inner = after(&mut rt, &log, 10, "inner", Value::Int(1));
+    let limited = timeout(&mut rt, &inner, 50);
+    run(&rt, &limited);
+    clock.advance(10);
+    rt.event_loop().run_until_idle();
+    assert_eq!(state(&limited), LifecycleState::Resolved);
+    assert_eq!(rt.block_on_raw(&limited), Ok(Value::Int(1)));
+    // The timer of the timeout does not keep the loop waiting.
+    rt.event_loop().run_blocking();
+    assert_eq!(clock.now(), 10);
+}
+
+#[test]
+fn timeouts_cancel_slower_futures() {
+    let (mut rt, clock) = manual();
+    let log = Log::default();
+    let inner = after(&mut rt, &log, 100, "inner", Value::Int(1));
+    let limited = timeout(&mut rt, &inner, 50);
+    let err = rt.block_on_raw(&limited).unwrap_err();
+    assert_eq!(err.error_kind().unwrap().to_string(), "timeout");
+    assert_eq!(clock.now(), 50);
+    assert_eq!(state(&inner), LifecycleState::Cancelled);
+    rt.event_loop().run_blocking();
+    assert!(log.borrow().is_empty());
+}
+
+#[test]
+fn futures_settling_at_the_deadline_beat_their_timeout() {
+    let (mut rt, _clock) = manual();
+    let log = Log::default();
+    let inner = after(&mut rt, &log, 50, "inner", Value::Int(1));
+    let limited = timeout(&mut rt, &inner, 50);
+    assert_eq!(rt.block_on_raw(&limited), Ok(Value::Int(1)));
+
+    // Also when it rejects at the deadline.
+    let sleep = call(&mut rt, "fut_sleep", &[Value::Int(50)]).unwrap();
+    let failing = call(&mut rt, "fut_then", &[sleep, logged(&log, "fail", throw)]).unwrap();
+    let limited = timeout(&mut rt, &failing, 50);
+    assert_eq!(rt.block_on_raw(&limited), Err(throw(Value::Nil).unwrap_err()));
+}
+
+#[test]
+fn timeouts_of_settled_futures_settle_right_away() {
+    let (mut rt, clock) = manual();
+    let resolved = call(&mut rt, "fut_resolve", &[Value::Int(1)]).unwrap();
+    let limited = timeout(&mut rt, &resolved, 0);
+    assert_eq!(rt.block_on_raw(&limited), Ok(Value::Int(1)));
+    let rejected = call(&mut rt, "fut_reject", &[Value::Int(2)]).unwrap();
+    let limited = timeout(&mut rt, &rejected, 10);
+    assert_eq!(rt.block_on_raw(&limited), Err(Value::Int(2)));
+    assert_eq!(clock.now(), 0);
+
+    let err = call(&mut rt, "fut_timeout", &[resolved, Value::from("1")]).unwrap_err();
+    assert_eq!(err.error_kind().unwrap().to_string(), "type");
+}
+
+fn cancel(rt: &mut Runtime, fut: &Value) -> Value {
+    call(rt, "fut_cancel", std::slice::from_ref(fut)).unwrap()
+}
+
+#[test]
+fn cancelling_a_chain_cancels_it_down_to_the_source() {
+    let mut rt = Runtime::new_deterministic(371);
+    let log = Log::default();
+    let source = never(&mut rt);
+    let first = call(&mut rt, "fut_then", &[source.clone(), logged(&log, "first", inc)]).unwrap();
+    let second = call(&mut rt, "fut_then", &[first.clone(), logged(&log, "second", inc)]).unwrap();
+    let third = call(&mut rt, "fut_then", &[second.clone(), logged(&log, "third", inc)]).unwrap();
+    run(&rt, &third);
+    rt.event_loop().run_until_idle();
+    assert_eq!(state(&source), LifecycleState::Running);
+
+    assert_eq!(cancel(&mut rt, &third), Value::Bool(true));
+    for fut in &[&third, &second, &first, &source] {
+        assert_eq!(state(fut), LifecycleState::Cancelled);
+    }
+    rt.event_loop().run_until_idle();
+    assert!(log.borrow().is_empty());
+    let err = rt.block_on_raw(&third).unwrap_err();
+    assert_eq!(err.error_kind().unwrap().to_string(), "cancelled");
+}
+
+#[test]
+fn cancelling_a_source_rejects_its_chain() {
+    let mut rt = Runtime::new_deterministic(371);
+    let log = Log::default();
+    let source = never(&mut rt);
+    let first = call(&mut rt, "fut_then", &[source.clone(), logged(&log, "first", inc)]).unwrap();
+    let second = call(&mut rt, "fut_then", &[first.clone(), logged(&log, "second", inc)]).unwrap();
+    run(&rt, &second);
+    rt.event_loop().run_until_idle();
+
+    assert_eq!(cancel(&mut rt, &source), Value::Bool(true));
+    let err = rt.block_on_raw(&second).unwrap_err();
+    assert_eq!(err.error_kind().unwrap().to_string(), "cancelled");
+    assert_eq!(state(&first), LifecycleState::Rejected);
+    assert_eq!(state(&second), LifecycleState::Rejected);
+    assert!(log.borrow().is_empty());
+}
+
+#[test]
+fn cancelling_fut_all_mid_flight() {
+    let mut rt = Runtime::new_deterministic(371);
+    let log = Log::default();
+    let fast = after(&mut rt, &log, 10, "fast", Value::Int(1));
+    let slow = after(&mut rt, &log, 100, "slow", Value::Int(2));
+    let all = call(&mut rt, "fut_all", &[Value::array(vec![fast.clone(), slow.clone()])]).unwrap();
+    let job = match &all {
+        Value::Future(fut) => rt.event_loop().run_future(fut).unwrap(),
+        _ => unreachable!(),
+    };
+    let deadline = rt.event_loop().now() + 50;
+    rt.event_loop().run_blocking_until_time(&job, deadline);
+    assert_eq!(*log.borrow(), ["fast"]);
+    assert_eq!(state(&fast), LifecycleState::Resolved);
+    assert_eq!(state(&all), LifecycleState::Running);
+
+    assert_eq!(cancel(&mut rt, &all), Value::Bool(true));
+    assert_eq!(state(&all), LifecycleState::Cancelled);
+    assert_eq!(state(&fast), LifecycleState::Resolved);
+    assert_eq!(state(&slow), LifecycleState::Cancelled);
+    rt.event_loop().run_blocking();
+    assert_eq!(*log.borrow(), ["fast"]);
+}
+
+#[test]
+fn cancelling_twice_or_after_settling_is_a_no_op() {
+    let mut rt = Runtime::new();
+    let fut = never(&mut rt);
+    run(&rt, &fut);
+    assert_eq!(cancel(&mut rt, &fut), Value::Bool(true));
+    assert_eq!(cancel(&mut rt, &fut), Value::Bool(false));
+    assert_eq!(state(&fut), LifecycleState::Cancelled);
+
+    let resolved = call(&mut rt, "fut_resolve", &[Value::Int(1)]).unwrap();
+    assert_eq!(rt.block_on_raw(&resolved), Ok(Value::Int(1)));
+    assert_eq!(cancel(&mut rt, &resolved), Value::Bool(false));
+    assert_eq!(state(&resolved), LifecycleState::Resolved);
+    assert_eq!(rt.block_on_raw(&resolved), Ok(Value::Int(1)));
+}
+
+#[test]
+fn cancelling_releases_held_values() {
+    let mut rt = Runtime::new();
+    let before = finalized();
+    let payload = Value::Userdata(Userdata::new(Payload));
+    let sleep = call(&mut rt, "fut_sleep", &[Value::Int(1000)]).unwrap();
+    let callback = Value::Fun(Fun::Native(Native::new("payload", move |_| Ok(payload.clone()))));
+    let fut = call(&mut rt, "fut_then", &[sleep, callback]).unwrap();
+    run(&rt, &fut);
+    rt.gc_collect();
+    assert_eq!(finalized(), before);
+    assert_eq!(cancel(&mut rt, &fut), Value::Bool(true));
+    rt.gc_collect();
+    assert_eq!(finalized(), before + 1);
+}
+
+fn on_idle(rt: &mut Runtime, log: &Log, name: &'static str) -> Value {
+    let idle = call(rt, "fut_on_idle", &[]).unwrap();
+    call(rt, "fut_then", &[idle, logged(log, name, Ok)]).unwrap()
+}
+
+#[test]
+fn idle_futures_settle_in_order_after_all_other_work() {
+    let mut rt = Runtime::new_deterministic(373);
+    let log = Log::default();
+    let first = on_idle(&mut rt, &log, "idle1");
+    let second = on_idle(&mut rt, &log, "idle2");
+    let resolved = call(&mut rt, "fut_resolve", &[Value::Nil]).unwrap();
+    let work = call(&mut rt, "fut_then", &[resolved, logged(&log, "work", Ok)]).unwrap();
+    let all = call(&mut rt, "fut_all", &[Value::array(vec![first, second, work])]).unwrap();
+    assert!(rt.block_on_raw(&all).is_ok());
+    assert_eq!(*log.borrow(), ["work(nil)", "idle1(nil)", "idle2(nil)"]);
+}
+
+#[test]
+fn work_spawned_when_idle_runs_before_the_next_idle_future() {
+    let mut rt = Runtime::new_deterministic(373);
+    let log = Log::default();
+    let idle = call(&mut rt, "fut_on_idle", &[]).unwrap();
+    let resolved = call(&mut rt, "fut_resolve", &[Value::Nil]).unwrap();
+    let more = call(&mut rt, "fut_then", &[resolved, logged(&log, "more", Ok)]).unwrap();
+    let spawning = log.clone();
+    let callback = Value::Fun(Fun::Native(Native::new("idle1", move |_| {
+        spawning.borrow_mut().push("idle1".to_string());
+        Ok(more.clone())
+    })));
+    let first = call(&mut rt, "fut_finally", &[idle, callback]).unwrap();
+    let second = on_idle(&mut rt, &log, "idle2");
+    let all = call(&mut rt, "fut_all", &[Value::array(vec![first, second])]).unwrap();
+    assert!(rt.block_on_raw(&all).is_ok());
+    assert_eq!(*log.borrow(), ["idle1", "more(nil)", "idle2(nil)"]);
+}
+
+#[test]
+fn cancelled_idle_futures_never_resolve() {
+    let mut rt = Runtime::new_deterministic(373);
+    let log = Log::default();
+    let idle = call(&mut rt, "fut_on_idle", &[]).unwrap();
+    let cancelled = call(&mut rt, "fut_then", &[idle.clone(), logged(&log, "idle1", Ok)]).unwrap();
+    let other = on_idle(&mut rt, &log, "idle2");
+    run(&rt, &cancelled);
+    run(&rt, &other);
+    assert_eq!(cancel(&mut rt, &cancelled), Value::Bool(true));
+    rt.event_loop().run_until_idle();
+    assert_eq!(state(&idle), LifecycleState::Cancelled);
+    assert_eq!(state(&other), LifecycleState::Resolved);
+    assert_eq!(*log.borrow(), ["idle2(nil)"]);
+}
+
+// A rust future that is pending twice, waking its task each time, before resolving.
+struct PendingTwice {
+    polls: Rc<Cell<usize>>,
+    value: Value,
+}
+
+impl std::future::Future for PendingTwice {
+    type Output = Result<Value, Value>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<Value, Value>> {
+        self.polls.set(self.polls.get() + 1);
+        if self.polls.get() > 2 {
+            Poll::Ready(Ok(self.value.clone()))
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+#[test]
+fn rust_futures_are_rescheduled_when_woken() {
+    let mut rt = Runtime::new_deterministic(374);
+    let polls = Rc::new(Cell::new(0));
+    let fut = rt.spawn_rust(PendingTwice { polls: polls.clone(), value: Value::Int(1) });
+    assert_eq!(state(&fut), LifecycleState::Running);
+    let log = Log::default();
+    let chained = call(&mut rt, "fut_then", &[fut.clone(), logged(&log, "inc", inc)]).unwrap();
+    assert_eq!(rt.block_on_raw(&chained), Ok(Value::Int(2)));
+    assert_eq!(polls.get(), 3);
+    assert_eq!(state(&fut), LifecycleState::Resolved);
+}
+
+#[test]
+fn rust_futures_combine_with_pan_futures() {
+    let mut rt = Runtime::new_deterministic(374);
+    let polls = Rc::new(Cell::new(0));
+    let rust = rt.spawn_rust(PendingTwice { polls, value: Value::from("rust") });
+    let pan = call(&mut rt, "fut_resolve", &[Value::from("pan")]).unwrap();
+    let all = call(&mut rt, "fut_all", &[Value::array(vec![rust, pan])]).unwrap();
+    let expected = Value::array(vec![Value::from("rust"), Value::from("pan")]);
+    assert_eq!(rt.block_on_raw(&all), Ok(expected));
+
+    let panicking = rt.spawn_rust(async {
+        if true {
+            panic!("polled");
+        }
+        Ok(Value::Nil)
+    });
+    let log = Log::default();
+    let recover = logged(&log, "recover", Ok);
+    let recovered = call(&mut rt, "fut_map_err", &[panicking, recover]).unwrap();
+    let err = rt.block_on_raw(&recovered).unwrap();
+    assert_eq!(err.error_kind().unwrap().to_string(), "native-panic");
+    assert_eq!(log.borrow().len(), 1);
+}
+
+type Wakers = Rc<RefCell<Vec<Waker>>>;
+
+// A sleep on a manual clock, woken by whoever advances the clock.
+struct MockSleep {
+    clock: Rc<ManualClock>,
+    deadline: u64,
+    wakers: Wakers,
+}
+
+impl std::future::Future for MockSleep {
+    type Output = Result<Value, Value>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<Value, Value>> {
+        if self.clock.now() >= self.deadline {
+            Poll::Ready(Ok(Value::Int(self.deadline as i64)))
+        } else {
+            self.wakers.borrow_mut().push(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn register_async_sleep(rt: &mut Runtime, clock: &Rc<ManualClock>, wakers: &Wakers) {
+    let (clock, wakers) = (clock.clone(), wakers.clone());
+    rt.register_async("async_sleep", move |args| {
+        let millis = match args.first() {
+            Some(Value::Int(millis)) => *millis as u64,
+            _ => return LocalFutureObj::new(Box::new(async { Err(Value::from("no millis")) })),
+        };
+        let deadline = clock.now() + millis;
+        let sleep = MockSleep { clock: clock.clone(), deadline, wakers: wakers.clone() };
+        LocalFutureObj::new(Box::new(sleep))
+    });
+}
+
+#[test]
+fn async_natives_return_futures_right_away() {
+    let (mut rt, clock) = manual();
+    let wakers = Wakers::default();
+    register_async_sleep(&mut rt, &clock, &wakers);
+    let sleep = call(&mut rt, "async_sleep", &[Value::Int(10)]).unwrap();
+    assert!(matches!(sleep, Value::Future(_)));
+    assert!(wakers.borrow().is_empty(), "the rust future is only polled once run");
+
+    let log = Log::default();
+    let chained = call(&mut rt, "fut_then", &[sleep, logged(&log, "inc", inc)]).unwrap();
+    let err = rt.block_on_raw(&chained).unwrap_err();
+    assert_eq!(err.error_kind().unwrap().to_string(), "deadlock");
+    assert_eq!(wakers.borrow().len(), 1);
+
+    clock.advance(10);
+    wakers.borrow_mut().drain(..).for_each(Waker::wake);
+    assert_eq!(rt.block_on_raw(&chained), Ok(Value::Int(11)));
+    assert_eq!(*log.borrow(), ["inc(10)"]);
+}
+
+#[test]
+fn async_natives_can_reject() {
+    let (mut rt, clock) = manual();
+    let wakers = Wakers::default();
+    register_async_sleep(&mut rt, &clock, &wakers);
+    let sleep = call(&mut rt, "async_sleep", &[Value::Nil]).unwrap();
+    let log = Log::default();
+    let chained = call(&mut rt, "fut_then", &[sleep.clone(), logged(&log, "inc", inc)]).unwrap();
+    assert_eq!(rt.block_on_raw(&chained), Err(Value::from("no millis")));
+    assert_eq!(state(&sleep), LifecycleState::Rejected);
+    assert!(log.borrow().is_empty());
+}
+
+// A cleanup callback for `fut_finally` that logs its name, and then returns `f()`.
+fn cleanup(log: &Log, name: &'static str, f: fn() -> Result<Value, Value>) -> Value {
+    let log = log.clone();
+    Value::Fun(Fun::Native(Native::new(name, move |args| {
+        assert!(args.is_empty(), "cleanup callbacks take no arguments");
+        log.borrow_mut().push(name.to_string());
+        f()
+    })))
+}
+
+fn finally(rt: &mut Runtime, fut: Value, callback: Value) -> Value {
+    call(rt, "fut_finally", &[fut, callback]).unwrap()
+}
+
+#[test]
+fn finally_passes_outcomes_through() {
+    let mut rt = Runtime::new();
+    let log = Log::default();
+    let resolved = call(&mut rt, "fut_resolve", &[Value::Int(1)]).unwrap();
+    let fut = finally(&mut rt, resolved, cleanup(&log, "resolved", || Ok(Value::Int(9))));
+    assert_eq!(rt.block_on_raw(&fut), Ok(Value::Int(1)));
+    let rejected = call(&mut rt, "fut_reject", &[Value::Int(2)]).unwrap();
+    let fut = finally(&mut rt, rejected, cleanup(&log, "rejected", || Ok(Value::Int(9))));
+    assert_eq!(rt.block_on_raw(&fut), Err(Value::Int(2)));
+    assert_eq!(*log.borrow(), ["resolved", "rejected"]);
+}
+
+#[test]
+fn throwing_cleanups_replace_outcomes() {
+    let mut rt = Runtime::new();
+    let log = Log::default();
+    let resolved = call(&mut rt, "fut_resolve", &[Value::Int(1)]).unwrap();
+    let fut = finally(&mut rt, resolved, cleanup(&log, "a", || throw(Value::Int(3))));
+    assert_eq!(rt.block_on_raw(&fut), Err(throw(Value::Int(3)).unwrap_err()));
+    let rejected = call(&mut rt, "fut_reject", &[Value::Int(2)]).unwrap();
+    let fut = finally(&mut rt, rejected, cleanup(&log, "b", || throw(Value::Int(4))));
+    assert_eq!(rt.block_on_raw(&fut), Err(throw(Value::Int(4)).unwrap_err()));
+
+    // So do rejecting futures returned by cleanups.
+    let resolved = call(&mut rt, "fut_resolve", &[Value::Int(1)]).unwrap();
+    let callback = cleanup(&log, "c", || Ok(Value::future_reject(Value::Int(5))));
+    let fut = finally(&mut rt, resolved, callback);
+    assert_eq!(rt.block_on_raw(&fut), Err(Value::Int(5)));
+    assert_eq!(*log.borrow(), ["a", "b", "c"]);
+}
+
+#[test]
+fn slow_cleanups_delay_settling() {
+    let (mut rt, clock) = manual();
+    let log = Log::default();
+    let resolved = call(&mut rt, "fut_resolve", &[Value::Int(1)]).unwrap();
+    let fut = finally(&mut rt, resolved, cleanup(&log, "slow", || {
+        Ok(Value::Future(Future::sleep(50)))
+    }));
+    run(&rt, &fut);
+    rt.event_loop().run_until_idle();
+    assert_eq!(*log.borrow(), ["slow"]);
+    assert_eq!(state(&fut), LifecycleState::Running);
+    clock.advance(49);
+    rt.event_loop().run_until_idle();
+    assert_eq!(state(&fut), LifecycleState::Running);
+    clock.advance(1);
+    rt.event_loop().run_until_idle();
+    assert_eq!(rt.block_on_raw(&fut), Ok(Value::Int(1)));
+}
+
+#[test]
+fn cancelled_sources_still_run_cleanups() {
+    let mut rt = Runtime::new();
+    let log = Log::default();
+    let source = never(&mut rt);
+    let fut = finally(&mut rt, source.clone(), cleanup(&log, "cleanup", || Ok(Value::Nil)));
+    run(&rt, &fut);
+    rt.event_loop().run_until_idle();
+    assert!(log.borrow().is_empty());
+    assert_eq!(cancel(&mut rt, &source), Value::Bool(true));
+    let err = rt.block_on_raw(&fut).unwrap_err();
+    assert_eq!(err.error_kind().unwrap().to_string(), "cancelled");
+    assert_eq!(*log.borrow(), ["cleanup"]);
+}
+
+// A scope body that spawns the given futures and then returns `result`.
+fn body(children: Vec<Value>, result: Result<Value, Value>) -> Value {
+    Value::Fun(Fun::Native(Native::new("body", move |args| {
+        for child in &children {
+            args[0].apply(std::slice::from_ref(child))?;
+        }
+        result.clone()
+    })))
+}
+
+#[test]
+fn throwing_scopes_cancel_their_children() {
+    let mut rt = Runtime::new_deterministic(381);
+    let children = vec![never(&mut rt), Value::Future(Future::sleep(1000))];
+    let scope = call(&mut rt, "fut_scope", &[body(children.clone(), Err(Value::from("body")))]);
+    let scope = scope.unwrap();
+    assert_eq!(rt.block_on_raw(&scope), Err(Value::from("body")));
+    for child in &children {
+        assert_eq!(state_name(&mut rt, child), Value::from("cancelled"));
+    }
+
+    // Also on the rust side.
+    let spawned = Rc::new(RefCell::new(vec![]));
+    let recorded = spawned.clone();
+    let scope = rt.scope(move |s| {
+        recorded.borrow_mut().push(s.spawn(&Value::Future(Future::never()))?);
+        recorded.borrow_mut().push(s.spawn_rust(futures::future::pending()));
+        Err(Value::from("body"))
+    });
+    assert_eq!(rt.block_on_raw(&scope), Err(Value::from("body")));
+    assert_eq!(spawned.borrow().len(), 2);
+    for child in spawned.borrow().iter() {
+        assert_eq!(state_name(&mut rt, child), Value::from("cancelled"));
+    }
+}
+
+#[test]
+fn nested_scopes_are_torn_down_inside_out() {
+    let mut rt = Runtime::new_deterministic(381);
+    // Sleeps rather than never futures: the loop does not hold on to the latter, so the hook
+    // does not see them being cancelled.
+    let inner_child = Value::Future(Future::sleep(1000));
+    let inner_result = Value::Future(Future::sleep(1000));
+    let inner = Value::Future(Future::scope(body(vec![inner_child.clone()], Ok(inner_result))));
+    let outer_child = Value::Future(Future::sleep(1000));
+    let later = Value::Future(Future::sleep(10));
+    let outer_body = body(vec![outer_child.clone(), inner.clone()], Ok(later));
+    let outer = call(&mut rt, "fut_scope", &[outer_body]).unwrap();
+
+    let named = [
+        (inner_child, "inner child"),
+        (inner, "inner"),
+        (outer_child, "outer child"),
+        (outer.clone(), "outer"),
+    ];
+    let log = Log::default();
+    let recorded = log.clone();
+    rt.event_loop().on_transition(move |fut, _, to| {
+        if to.is_done() {
+            let name = named.iter().find(|(named, _)| match named {
+                Value::Future(named) => Future::ptr_eq(named, fut),
+                _ => false,
+            });
+            if let Some((_, name)) = name {
+                recorded.borrow_mut().push(format!("{} {}", name, to.name()));
+            }
+        }
+    });
+    assert_eq!(rt.block_on_raw(&outer), Ok(Value::Nil));
+    assert_eq!(
+        *log.borrow(),
+        ["outer child cancelled", "inner child cancelled", "inner cancelled", "outer resolved"]
+    );
+}
+
+#[test]
+fn clean_scopes_settle_like_their_body() {
+    let mut rt = Runtime::new_deterministic(381);
+    let sleep = Value::Future(Future::sleep(5));
+    let scope = call(&mut rt, "fut_scope", &[body(vec![sleep.clone()], Ok(Value::Int(1)))]);
+    assert_eq!(rt.block_on_raw(&scope.unwrap()), Ok(Value::Int(1)));
+
+    let result = Value::future_resolve(Value::Int(2));
+    let scope = call(&mut rt, "fut_scope", &[body(vec![], Ok(result))]).unwrap();
+    assert_eq!(rt.block_on_raw(&scope), Ok(Value::Int(2)));
+
+    // Children that finish before the body are left alone.
+    let child = call(&mut rt, "fut_resolve", &[Value::Int(3)]).unwrap();
+    let scope = rt.scope({
+        let child = child.clone();
+        move |s| {
+            s.spawn(&child)?;
+            Ok(Value::Future(Future::sleep(5)))
+        }
+    });
+    assert_eq!(rt.block_on_raw(&scope), Ok(Value::Nil));
+    assert_eq!(state(&child), LifecycleState::Resolved);
+
+    let err = call(&mut rt, "fut_scope", &[Value::Int(1)]).unwrap_err();
+    assert_eq!(err.error_kind().unwrap().to_string(), "type");
+}
+
+// A rust future that logs each poll, and resolves on the second one.
+fn logging_task(log: &Log) -> impl std::future::Future<Output = Result<Value, Value>> {
+    let log = log.clone();
+    let mut polled = false;
+    futures::future::poll_fn(move |cx| {
+        log.borrow_mut().push(format!("task polled {}", if polled { "again" } else { "first" }));
+        if polled {
+            Poll::Ready(Ok(Value::Nil))
+        } else {
+            polled = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    })
+}
+
+fn fifo(clock: &Rc<ManualClock>, budget: Option<usize>) -> Runtime {
+    let builder = EventLoop::builder().deterministic(None).clock(clock.clone());
+    let builder = match budget {
+        Some(budget) => builder.microtask_budget(budget),
+        None => builder,
+    };
+    Runtime::with_event_loop(builder.build())
+}
+
+#[test]
+fn microtasks_drain_before_timers_and_tasks() {
+    let clock = Rc::new(ManualClock::new());
+    let mut rt = fifo(&clock, None);
+    let log = Log::default();
+    let sleep = call(&mut rt, "fut_sleep", &[Value::Int(0)]).unwrap();
+    let timer = call(&mut rt, "fut_then", &[sleep, logged(&log, "timer", Ok)]).unwrap();
+    let task = rt.spawn_rust(logging_task(&log));
+    let mut chain = call(&mut rt, "fut_resolve", &[Value::Int(0)]).unwrap();
+    for name in &["then1", "then2", "then3"] {
+        chain = call(&mut rt, "fut_then", &[chain, logged(&log, name, inc)]).unwrap();
+    }
+    run(&rt, &timer);
+    run(&rt, &chain);
+    let all = call(&mut rt, "fut_all", &[Value::array(vec![timer, task, chain])]).unwrap();
+    assert!(rt.block_on_raw(&all).is_ok());
+    assert_eq!(
+        *log.borrow(),
+        // Due timers fire before woken rust futures are polled.
+        [
+            "then1(0)",
+            "then2(1)",
+            "then3(2)",
+            "timer(nil)",
+            "task polled first",
+            "task polled again",
+        ]
+    );
+}
+
+#[test]
+fn microtask_budgets_let_timers_in() {
+    let chain_length = 1000;
+    let timer_position = |budget| {
+        let clock = Rc::new(ManualClock::new());
+        let mut rt = fifo(&clock, budget);
+        let log = Log::default();
+        let mut chain = call(&mut rt, "fut_resolve", &[Value::Int(0)]).unwrap();
+        for _ in 0..chain_length {
+            chain = call(&mut rt, "fut_then", &[chain, logged(&log, "step", inc)]).unwrap();
+            run(&rt, &chain);
+        }
+        let sleep = call(&mut rt, "fut_sleep", &[Value::Int(0)]).unwrap();
+        let timer = call(&mut rt, "fut_then", &[sleep, logged(&log, "timer", Ok)]).unwrap();
+        run(&rt, &timer);
+        // The timer is due while the chain is still pending.
+        clock.advance(1);
+        let all = call(&mut rt, "fut_all", &[Value::array(vec![timer, chain])]).unwrap();
+        assert!(rt.block_on_raw(&all).is_ok());
+        let log = log.borrow();
+        assert_eq!(log.len(), chain_length + 1);
+        log.iter().position(|entry| entry == "timer(nil)").unwrap()
+    };
+    assert_eq!(timer_position(None), chain_length);
+    // The callback of the timer queues up behind the step that is already waiting.
+    assert_eq!(timer_position(Some(5)), 6);
+    assert_eq!(timer_position(Some(1)), 2);
+}
@@ -0,0 +1,170 @@
+mod common;
+
+use pan_lang_rs::capabilities::{Capabilities, Capability};
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::types::futures::EventLoop;
+use pan_lang_rs::types::heap::GcConfig;
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, field, int, kind};
+
+fn stat(rt: &mut Runtime, name: &str) -> i64 {
+    match field(&call(rt, "gc_stats", &[]).unwrap(), name) {
+        Value::Int(n) => n,
+        other => panic!("{} is not an int: {}", name, other),
+    }
+}
+
+// An array that contains itself, which only a collection can free.
+fn cycle() -> Value {
+    let a = arr(vec![]);
+    if let Value::Array(inner) = &a {
+        inner.borrow_mut().push(a.clone());
+    }
+    a
+}
+
+#[test]
+fn the_builtin_returns_the_documented_keys() {
+    let mut rt = Runtime::new();
+    let stats = call(&mut rt, "gc_stats", &[]).unwrap();
+    let keys = [
+        "live_collections",
+        "collections_allocated",
+        "environments_created",
+        "collections_forced",
+        "collections_triggered",
+        "collection_micros",
+    ];
+    let expected: Vec<(&str, Value)> = keys.iter().map(|k| (*k, field(&stats, k))).collect();
+    assert_eq!(stats, Value::record(expected));
+    for key in keys.iter() {
+        assert!(matches!(field(&stats, key), Value::Int(n) if n >= 0), "{}", key);
+    }
+
+    // The rust side reports the same numbers.
+    let rust = rt.gc_stats();
+    assert_eq!(stat(&mut rt, "collections_allocated"), rust.collections_allocated as i64);
+    assert_eq!(stat(&mut rt, "collections_forced"), rust.collections_forced as i64);
+    assert_eq!(stat(&mut rt, "live_collections"), rust.live_collections as i64);
+}
+
+#[test]
+fn allocations_are_counted_and_collections_free_them() {
+    // The rust side, as every call of `gc_stats` allocates a map itself.
+    let mut rt = Runtime::new();
+    let before = rt.gc_stats();
+    let cycles: Vec<Value> = (0..1000).map(|_| cycle()).collect();
+    let stats = rt.gc_stats();
+    assert_eq!(stats.collections_allocated, before.collections_allocated + 1000);
+    assert_eq!(stats.live_collections, before.live_collections + 1000);
+
+    // Without a collection, the cycles stay alive after the last reference is dropped.
+    drop(cycles);
+    assert_eq!(rt.gc_stats().live_collections, before.live_collections + 1000);
+    assert_eq!(call(&mut rt, "gc_collect", &[]), Ok(Value::Nil));
+    let stats = rt.gc_stats();
+    assert_eq!(stats.collections_forced, before.collections_forced + 1);
+    assert_eq!(stats.live_collections, before.live_collections);
+    // Collecting does not free what is still reachable.
+    let kept = cycle();
+    rt.gc_collect();
+    let stats = rt.gc_stats();
+    assert_eq!(stats.live_collections, before.live_collections + 1);
+    assert_eq!(stats.collections_forced, before.collections_forced + 2);
+    assert!(stats.collection_time >= before.collection_time);
+    assert_eq!(call(&mut rt, "len", &[kept]), Ok(int(1)));
+
+    // The builtin reports the same counters.
+    let forced = stat(&mut rt, "collections_forced");
+    assert_eq!(forced, stats.collections_forced as i64);
+    let allocated = stat(&mut rt, "collections_allocated");
+    assert_eq!(stat(&mut rt, "collections_allocated"), allocated + 1);
+}
+
+#[test]
+fn a_threshold_triggers_collections() {
+    let mut rt = Runtime::new();
+    rt.gc_configure(GcConfig { allocation_threshold: Some(100), collect_at_idle: false });
+    let triggered = rt.gc_stats().collections_triggered;
+    for _ in 0..1000 {
+        call(&mut rt, "range", &[int(0), int(1)]).unwrap();
+    }
+    let stats = rt.gc_stats();
+    assert!(stats.collections_triggered >= triggered + 9, "{:?}", stats);
+    assert_eq!(rt.gc_config().allocation_threshold, Some(100));
+
+    // Allocations outside of calls never trigger.
+    let triggered = rt.gc_stats().collections_triggered;
+    let arrays: Vec<Value> = (0..1000).map(|_| arr(vec![])).collect();
+    assert_eq!(rt.gc_stats().collections_triggered, triggered);
+    drop(arrays);
+}
+
+#[test]
+fn idle_collections_follow_busy_turns() {
+    let mut rt = Runtime::new();
+    let before = rt.gc_stats();
+    // Without the policy, running out of work collects nothing.
+    rt.spawn_rust(async { Ok(Value::Nil) });
+    rt.event_loop().run_until_idle();
+    assert_eq!(rt.gc_stats().collections_triggered, before.collections_triggered);
+
+    rt.gc_configure(GcConfig { allocation_threshold: None, collect_at_idle: true });
+    rt.spawn_rust(async { Ok(Value::Nil) });
+    rt.event_loop().run_until_idle();
+    let stats = rt.gc_stats();
+    assert_eq!(stats.collections_triggered, before.collections_triggered + 1);
+    // A loop that stays without work does not collect again.
+    rt.event_loop().run_until_idle();
+    assert_eq!(rt.gc_stats().collections_triggered, stats.collections_triggered);
+
+    // Turning the policy off again.
+    rt.gc_configure(GcConfig::default());
+    rt.spawn_rust(async { Ok(Value::Nil) });
+    rt.event_loop().run_until_idle();
+    assert_eq!(rt.gc_stats().collections_triggered, stats.collections_triggered);
+}
+
+#[test]
+fn stats_never_decrease() {
+    let mut rt = Runtime::new();
+    rt.gc_configure(GcConfig { allocation_threshold: Some(10), collect_at_idle: true });
+    let mut previous = rt.gc_stats();
+    for round in 0..20 {
+        let kept: Vec<Value> = (0..round).map(|_| cycle()).collect();
+        call(&mut rt, "range", &[int(0), int(round)]).unwrap();
+        rt.spawn_rust(async { Ok(Value::Nil) });
+        rt.event_loop().run_until_idle();
+        if round % 5 == 0 {
+            rt.gc_collect();
+        }
+        drop(kept);
+        let stats = rt.gc_stats();
+        assert!(stats.collections_allocated >= previous.collections_allocated + round as u64);
+        assert!(stats.environments_created >= previous.environments_created);
+        assert!(stats.collections_forced >= previous.collections_forced);
+        assert!(stats.collections_triggered > previous.collections_triggered);
+        assert!(stats.collection_time >= previous.collection_time);
+        previous = stats;
+    }
+}
+
+#[test]
+fn embedders_can_disable_the_builtins() {
+    let mut rt = Runtime::new();
+    rt.allow_gc_control(false);
+    for name in ["gc_collect", "gc_stats"].iter() {
+        assert_eq!(kind(&call(&mut rt, name, &[]).unwrap_err()), "forbidden", "{}", name);
+    }
+    // The rust side keeps working.
+    let forced = rt.gc_stats().collections_forced;
+    rt.gc_collect();
+    assert_eq!(rt.gc_stats().collections_forced, forced + 1);
+    rt.allow_gc_control(true);
+    assert!(call(&mut rt, "gc_stats", &[]).is_ok());
+
+    let caps = Capabilities::SCRIPTING.deny(Capability::Gc);
+    let rt = Runtime::with_capabilities(EventLoop::new(), &caps).unwrap();
+    assert!(rt.global("gc_collect").is_none() && rt.global("gc_stats").is_none());
+}
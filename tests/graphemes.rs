@@ -0,0 +1,66 @@
+#![cfg(feature = "unicode-segmentation")]
+
+use pan_lang_rs::types::rope::{Rope, RopeError};
+
+const FAMILY: &str = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+const FLAGS: &str = "\u{1F1E9}\u{1F1EA}\u{1F1EB}\u{1F1F7}";
+const ACCENT: &str = "e\u{301}";
+
+fn clusters(rope: &Rope) -> Vec<String> {
+    rope.graphemes().map(|g| g.to_string()).collect()
+}
+
+#[test]
+fn counts_extended_clusters() {
+    let cases: &[(&str, &[&str])] = &[
+        ("", &[]),
+        ("abc", &["a", "b", "c"]),
+        (FAMILY, &[FAMILY]),
+        (FLAGS, &["\u{1F1E9}\u{1F1EA}", "\u{1F1EB}\u{1F1F7}"]),
+        ("\u{1F1E9}\u{1F1EA}\u{1F1EB}", &["\u{1F1E9}\u{1F1EA}", "\u{1F1EB}"]),
+        (ACCENT, &[ACCENT]),
+        ("a\r\nb", &["a", "\r\n", "b"]),
+        ("\n\r", &["\n", "\r"]),
+        ("x\u{1F44B}\u{1F3FD}y", &["x", "\u{1F44B}\u{1F3FD}", "y"]),
+    ];
+    for &(input, expected) in cases {
+        let rope = Rope::from_str(input);
+        assert_eq!(clusters(&rope), expected, "{:?}", input);
+        assert_eq!(rope.len_graphemes(), expected.len(), "{:?}", input);
+    }
+}
+
+#[test]
+fn clusters_span_chunk_boundaries() {
+    let pad = "a".repeat(300);
+    // Each cluster is cut in two by the chunk boundary.
+    for &(left, right) in &[
+        ("e", "\u{301}"),
+        ("\r", "\n"),
+        ("\u{1F468}\u{200D}", "\u{1F469}\u{200D}\u{1F467}"),
+        ("\u{1F1E9}", "\u{1F1EA}"),
+    ] {
+        let rope = Rope::from_str(&format!("{}{}", pad, left))
+            .concat(&Rope::from_str(&format!("{}{}", right, pad)));
+        assert!(rope.chunks().count() > 1);
+        let found = clusters(&rope);
+        assert_eq!(found.len(), 601);
+        assert_eq!(found[300], format!("{}{}", left, right));
+        assert_eq!(rope.len_graphemes(), 601);
+    }
+}
+
+#[test]
+fn slices_by_cluster() {
+    let input = format!("a{}{}{}\r\nb", FAMILY, ACCENT, FLAGS);
+    let rope = Rope::from_str(&input);
+    assert_eq!(rope.len_graphemes(), 7);
+    assert_eq!(rope.slice_graphemes(1..2).unwrap().to_string(), FAMILY);
+    assert_eq!(rope.slice_graphemes(2..3).unwrap().to_string(), ACCENT);
+    assert_eq!(rope.slice_graphemes(3..5).unwrap().to_string(), FLAGS);
+    assert_eq!(rope.slice_graphemes(0..7).unwrap().to_string(), input);
+    assert_eq!(rope.slice_graphemes(7..7).unwrap().to_string(), "");
+    let (start, end) = (2, 1);
+    assert_eq!(rope.slice_graphemes(start..end), Err(RopeError::InvertedRange { start, end }));
+    assert!(rope.slice_graphemes(0..8).is_err());
+}
@@ -0,0 +1,215 @@
+mod common;
+
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, float, int, ints, kind, native, s};
+
+fn n(v: &Value) -> i64 {
+    match v {
+        Value::Int(n) => *n,
+        other => panic!("not an int: {}", other),
+    }
+}
+
+fn map(entries: Vec<(Value, Value)>) -> Value {
+    Value::map(entries.into_iter().collect())
+}
+
+fn modulo(m: i64) -> Value {
+    native(move |args| Ok(int(n(&args[0]).rem_euclid(m))))
+}
+
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        self.0 >> 33
+    }
+}
+
+#[test]
+fn grouping_keeps_the_order_of_iteration() {
+    let mut rt = Runtime::new();
+    let input = ints(&[5, 3, 8, 1, 6, 4]);
+    let groups = call(&mut rt, "group_by", &[input.clone(), modulo(3)]).unwrap();
+    let expected = vec![(int(0), ints(&[3, 6])), (int(1), ints(&[1, 4])), (int(2), ints(&[5, 8]))];
+    assert_eq!(groups, map(expected));
+
+    // Keys are compared structurally, arrays included.
+    let pairs = native(|args| Ok(ints(&[n(&args[0]) % 2, n(&args[0]) / 4])));
+    let groups = call(&mut rt, "group_by", &[input.clone(), pairs.clone()]).unwrap();
+    let expected = vec![
+        (ints(&[0, 1]), ints(&[6, 4])),
+        (ints(&[0, 2]), ints(&[8])),
+        (ints(&[1, 0]), ints(&[3, 1])),
+        (ints(&[1, 1]), ints(&[5])),
+    ];
+    assert_eq!(groups, map(expected));
+    let counts = call(&mut rt, "count_by", &[input.clone(), pairs.clone()]).unwrap();
+    let expected = vec![
+        (ints(&[0, 1]), int(2)),
+        (ints(&[0, 2]), int(1)),
+        (ints(&[1, 0]), int(2)),
+        (ints(&[1, 1]), int(1)),
+    ];
+    assert_eq!(counts, map(expected));
+    let firsts = call(&mut rt, "unique_by", &[input, pairs]).unwrap();
+    assert_eq!(firsts, ints(&[5, 3, 8, 6]));
+
+    // Maps are grouped by entry.
+    let m = map(vec![(s("a"), int(1)), (s("b"), int(2)), (s("c"), int(3))]);
+    let value_parity = native(|args| Ok(int(n(&args[1]) % 2)));
+    let groups = call(&mut rt, "group_by", &[m.clone(), value_parity]).unwrap();
+    let expected = vec![
+        (int(0), arr(vec![arr(vec![s("b"), int(2)])])),
+        (int(1), arr(vec![arr(vec![s("a"), int(1)]), arr(vec![s("c"), int(3)])])),
+    ];
+    assert_eq!(groups, map(expected));
+    let odd_value = native(|args| Ok(Value::Bool(n(&args[1]) % 2 == 1)));
+    let parts = call(&mut rt, "partition", &[m, odd_value]).unwrap();
+    let expected = arr(vec![
+        arr(vec![arr(vec![s("a"), int(1)]), arr(vec![s("c"), int(3)])]),
+        arr(vec![arr(vec![s("b"), int(2)])]),
+    ]);
+    assert_eq!(parts, expected);
+}
+
+#[test]
+fn unique_keeps_first_occurrences() {
+    let mut rt = Runtime::new();
+    let input = arr(vec![int(3), s("x"), int(1), int(3), ints(&[1]), s("x"), ints(&[1]), int(1)]);
+    let expected = arr(vec![int(3), s("x"), int(1), ints(&[1])]);
+    assert_eq!(call(&mut rt, "unique", &[input]), Ok(expected));
+    // Structurally equal values count as duplicates, even if they are distinct arrays.
+    let input = arr(vec![ints(&[1, 2]), ints(&[1, 2]), float(0.0), float(-0.0)]);
+    assert_eq!(call(&mut rt, "unique", &[input]), Ok(arr(vec![ints(&[1, 2]), float(0.0)])));
+    assert_eq!(call(&mut rt, "unique", &[s("abracadabra")]), Ok(arr(
+        "abrcd".chars().map(Value::Char).collect(),
+    )));
+}
+
+#[test]
+fn partitions_split_by_truthiness() {
+    let mut rt = Runtime::new();
+    let input = arr(vec![int(0), Value::Nil, Value::Bool(false), s(""), ints(&[]), int(1)]);
+    let identity = native(|args| Ok(args[0].clone()));
+    let parts = call(&mut rt, "partition", &[input, identity]).unwrap();
+    let expected = arr(vec![
+        arr(vec![int(0), s(""), ints(&[]), int(1)]),
+        arr(vec![Value::Nil, Value::Bool(false)]),
+    ]);
+    assert_eq!(parts, expected);
+}
+
+#[test]
+fn empty_inputs_yield_empty_results() {
+    let mut rt = Runtime::new();
+    let empty = ints(&[]);
+    let f = modulo(2);
+    assert_eq!(call(&mut rt, "group_by", &[empty.clone(), f.clone()]), Ok(map(vec![])));
+    assert_eq!(call(&mut rt, "count_by", &[empty.clone(), f.clone()]), Ok(map(vec![])));
+    assert_eq!(call(&mut rt, "unique", std::slice::from_ref(&empty)), Ok(ints(&[])));
+    assert_eq!(call(&mut rt, "unique_by", &[empty.clone(), f.clone()]), Ok(ints(&[])));
+    assert_eq!(call(&mut rt, "partition", &[empty, f]), Ok(arr(vec![ints(&[]), ints(&[])])));
+}
+
+#[test]
+fn throwing_callbacks_leave_no_partial_output() {
+    let mut rt = Runtime::new();
+    let calls = Rc::new(Cell::new(0));
+    let counted = calls.clone();
+    let throws_at_three = native(move |args| {
+        counted.set(counted.get() + 1);
+        if args[0] == int(3) { Err(s("three")) } else { Ok(int(0)) }
+    });
+    let input = ints(&[1, 2, 3, 4, 5]);
+    for name in ["group_by", "count_by", "unique_by", "partition"].iter() {
+        calls.set(0);
+        let result = call(&mut rt, name, &[input.clone(), throws_at_three.clone()]);
+        // The thrown value itself, not a partial result, and nothing after the throw is visited.
+        assert_eq!(result, Err(s("three")), "{}", name);
+        assert_eq!(calls.get(), 3, "{}", name);
+        assert_eq!(input, ints(&[1, 2, 3, 4, 5]), "{}", name);
+    }
+}
+
+#[test]
+fn callbacks_may_mutate_the_input() {
+    let mut rt = Runtime::new();
+    let input = ints(&[1, 2, 3, 4]);
+    let target = input.clone();
+    let rt_push = rt.global("push").unwrap();
+    let rt_set = rt.global("set").unwrap();
+    // Appends past the original length are not visited, overwritten elements are.
+    let mutate = native(move |args| {
+        rt_push.apply(&[target.clone(), int(100)])?;
+        if args[0] == int(1) {
+            rt_set.apply(&[target.clone(), int(2), int(30)])?;
+        }
+        Ok(int(n(&args[0]) % 2))
+    });
+    let groups = call(&mut rt, "group_by", &[input.clone(), mutate]).unwrap();
+    assert_eq!(groups, map(vec![(int(0), ints(&[2, 30, 4])), (int(1), ints(&[1]))]));
+    assert_eq!(call(&mut rt, "len", &[input]), Ok(int(8)));
+
+    // Shrinking the input ends the iteration early.
+    let input = ints(&[1, 2, 3, 4]);
+    let target = input.clone();
+    let rt_clear = rt.global("clear").unwrap();
+    let clearing = native(move |args| {
+        if args[0] == int(2) {
+            rt_clear.apply(std::slice::from_ref(&target))?;
+        }
+        Ok(int(0))
+    });
+    let counts = call(&mut rt, "count_by", &[input, clearing]).unwrap();
+    assert_eq!(counts, map(vec![(int(0), int(2))]));
+}
+
+#[test]
+fn counts_agree_with_an_oracle() {
+    let mut rt = Runtime::new();
+    let mut lcg = Lcg(416);
+    let numbers: Vec<i64> = (0..20_000).map(|_| (lcg.next() % 1000) as i64 - 500).collect();
+    let input = ints(&numbers);
+
+    let mut counts = BTreeMap::new();
+    let mut groups: BTreeMap<i64, Vec<i64>> = BTreeMap::new();
+    for x in numbers.iter() {
+        *counts.entry(x.rem_euclid(37)).or_insert(0) += 1;
+        groups.entry(x.rem_euclid(37)).or_default().push(*x);
+    }
+    let expected = map(counts.iter().map(|(k, c)| (int(*k), int(*c))).collect());
+    assert_eq!(call(&mut rt, "count_by", &[input.clone(), modulo(37)]), Ok(expected));
+    let expected = map(groups.iter().map(|(k, g)| (int(*k), ints(g))).collect());
+    assert_eq!(call(&mut rt, "group_by", &[input.clone(), modulo(37)]), Ok(expected));
+
+    let mut seen = std::collections::BTreeSet::new();
+    let firsts: Vec<i64> = numbers.iter().cloned().filter(|x| seen.insert(*x)).collect();
+    assert_eq!(call(&mut rt, "unique", std::slice::from_ref(&input)), Ok(ints(&firsts)));
+    let (yes, no): (Vec<i64>, Vec<i64>) = numbers.iter().partition(|x| *x % 3 == 0);
+    let divisible = native(|args| Ok(Value::Bool(n(&args[0]) % 3 == 0)));
+    let parts = call(&mut rt, "partition", &[input, divisible]).unwrap();
+    assert_eq!(parts, arr(vec![ints(&yes), ints(&no)]));
+}
+
+#[test]
+fn wrong_types() {
+    let mut rt = Runtime::new();
+    for name in ["group_by", "count_by", "unique_by", "partition"].iter() {
+        let err = call(&mut rt, name, &[ints(&[1]), int(1)]).unwrap_err();
+        assert_eq!(kind(&err), "type", "{}", name);
+        let err = call(&mut rt, name, &[int(1), modulo(2)]).unwrap_err();
+        assert_eq!(kind(&err), "type", "{}", name);
+    }
+    assert_eq!(kind(&call(&mut rt, "unique", &[Value::Nil]).unwrap_err()), "type");
+}
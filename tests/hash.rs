@@ -0,0 +1,135 @@
+mod common;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::types::bytes::Bytes;
+use pan_lang_rs::types::rope::Rope;
+use pan_lang_rs::value::{Fun, Native, Value, HASH_BUDGET};
+
+use common::{arr, call, int, kind, s};
+
+fn hash(rt: &mut Runtime, v: &Value) -> i64 {
+    match call(rt, "hash", std::slice::from_ref(v)).unwrap() {
+        Value::Int(h) => h,
+        other => panic!("not an int: {}", other),
+    }
+}
+
+fn seeded(rt: &mut Runtime, v: &Value, seed: i64) -> i64 {
+    match call(rt, "hash_seeded", &[v.clone(), int(seed)]).unwrap() {
+        Value::Int(h) => h,
+        other => panic!("not an int: {}", other),
+    }
+}
+
+// Pairs of equal values that were constructed differently.
+fn lookalikes(rt: &mut Runtime) -> Vec<(Value, Value)> {
+    let text = "ünïcödé text that is long enough to span several pieces of a rope ".repeat(20);
+    let pieces = text
+        .chars()
+        .collect::<Vec<_>>()
+        .chunks(7)
+        .fold(Rope::from_str(""), |rope, chunk| {
+            rope.concat(&Rope::from_str(&chunk.iter().collect::<String>()))
+        });
+    let data: Vec<u8> = (0..5000u32).map(|i| (i * 31 % 251) as u8).collect();
+    let chunked = data
+        .chunks(13)
+        .fold(Bytes::from_slice(b""), |bytes, chunk| bytes.concat(&Bytes::from_slice(chunk)));
+    let sliced = call(rt, "str_slice", &[s(&format!("xx{}", text)), int(2)]).unwrap();
+    vec![
+        (Value::String(Rope::from_str(&text)), Value::String(pieces)),
+        (Value::String(Rope::from_str(&text)), sliced),
+        (Value::Bytes(Bytes::from_slice(&data)), Value::Bytes(chunked)),
+        (Value::from(0.0), Value::from(-0.0)),
+        (Value::from(f64::NAN), Value::from(-f64::NAN)),
+        (arr(vec![int(1), s("a")]), arr(vec![int(1), s("a")])),
+        (
+            Value::set(vec![int(3), int(1), int(2)].into_iter().collect()),
+            Value::set(vec![int(2), int(3), int(1)].into_iter().collect()),
+        ),
+        (
+            Value::record(vec![("a", int(1)), ("b", arr(vec![]))]),
+            Value::record(vec![("b", arr(vec![])), ("a", int(1))]),
+        ),
+    ]
+}
+
+#[test]
+fn equal_values_hash_equal() {
+    let mut rt = Runtime::new();
+    for (a, b) in lookalikes(&mut rt) {
+        assert_eq!(a, b);
+        assert_eq!(hash(&mut rt, &a), hash(&mut rt, &b), "{}", a.literal(1));
+        assert_eq!(seeded(&mut rt, &a, 7), seeded(&mut rt, &b, 7), "{}", a.literal(1));
+    }
+    // Hashes are stable within a process, also across runtimes.
+    let v = arr(vec![s("stable"), int(1)]);
+    let mut other = Runtime::new();
+    assert_eq!(hash(&mut rt, &v), hash(&mut other, &v));
+}
+
+#[test]
+fn different_values_hash_differently() {
+    let mut rt = Runtime::new();
+    let values = [
+        Value::Nil,
+        Value::Bool(false),
+        int(0),
+        Value::from(0.0),
+        Value::Char('0'),
+        s("0"),
+        s(""),
+        Value::Bytes(Bytes::from_slice(b"0")),
+        arr(vec![]),
+        arr(vec![int(0)]),
+        arr(vec![arr(vec![])]),
+        Value::set(Default::default()),
+        Value::map(Default::default()),
+    ];
+    let mut hashes: Vec<i64> = values.iter().map(|v| hash(&mut rt, v)).collect();
+    hashes.sort();
+    hashes.dedup();
+    assert_eq!(hashes.len(), values.len());
+}
+
+#[test]
+fn functions_are_hashed_by_identity() {
+    let mut rt = Runtime::new();
+    let f = Value::Fun(Fun::Native(Native::new("f", |_| Ok(Value::Nil))));
+    let g = Value::Fun(Fun::Native(Native::new("f", |_| Ok(Value::Nil))));
+    assert_eq!(hash(&mut rt, &f), hash(&mut rt, &f.clone()));
+    assert_ne!(hash(&mut rt, &f), hash(&mut rt, &g));
+    let a = arr(vec![f.clone()]);
+    assert_eq!(hash(&mut rt, &a), hash(&mut rt, &arr(vec![f])));
+}
+
+#[test]
+fn cyclic_and_deep_values_terminate() {
+    let mut rt = Runtime::new();
+    let cyclic = arr(vec![int(1)]);
+    call(&mut rt, "push", &[cyclic.clone(), cyclic.clone()]).unwrap();
+    // An array that contains itself is equal to an array that contains the array.
+    let unrolled = arr(vec![int(1), cyclic.clone()]);
+    assert_eq!(cyclic, unrolled);
+    assert_eq!(hash(&mut rt, &cyclic), hash(&mut rt, &unrolled));
+
+    let deep = (0..10 * HASH_BUDGET).fold(int(0), |inner, _| arr(vec![inner]));
+    let other = (0..10 * HASH_BUDGET).fold(int(1), |inner, _| arr(vec![inner]));
+    // Both only differ beyond the hashed prefix, so they collide.
+    assert_eq!(hash(&mut rt, &deep), hash(&mut rt, &other));
+    call(&mut rt, "clear", &[cyclic]).unwrap();
+}
+
+#[test]
+fn seeds_give_unrelated_hashes() {
+    let mut rt = Runtime::new();
+    let v = s("key");
+    let mut hashes: Vec<i64> = (0..100).map(|seed| seeded(&mut rt, &v, seed)).collect();
+    hashes.push(hash(&mut rt, &v));
+    hashes.sort();
+    hashes.dedup();
+    assert_eq!(hashes.len(), 101);
+    assert_eq!(seeded(&mut rt, &v, 5), seeded(&mut rt, &v, 5));
+    let err = call(&mut rt, "hash_seeded", &[v, s("5")]).unwrap_err();
+    assert_eq!(kind(&err), "type");
+}
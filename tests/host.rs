@@ -0,0 +1,109 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use pan_lang_rs::runtime::{Arity, HostCtx, RegisterError, Runtime};
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, int, kind, s};
+
+struct Counter(i64);
+
+#[test]
+fn registered_natives_are_globals() {
+    let mut rt = Runtime::new();
+    rt.register_native("twice", Arity::Max(1), |_, args| match args.first() {
+        Some(Value::Int(n)) => Ok(int(2 * n)),
+        _ => Err(s("not an int")),
+    })
+    .unwrap();
+    assert_eq!(call(&mut rt, "twice", &[int(21)]), Ok(int(42)));
+    assert_eq!(call(&mut rt, "twice", &[]), Err(s("not an int")));
+    // Other natives reach it like any global.
+    let twice = rt.global("twice").unwrap();
+    let map = rt.global("arr_map").unwrap();
+    let doubled = rt.call_raw(&map, &[arr(vec![int(1), int(2)]), twice]);
+    assert_eq!(doubled, Ok(arr(vec![int(2), int(4)])));
+    assert!(rt.resolve("twice").is_ok());
+}
+
+#[test]
+fn duplicate_names_are_rejected() {
+    let mut rt = Runtime::new();
+    rt.register_native("once", Arity::Variadic, |_, _| Ok(int(1))).unwrap();
+    let duplicate = rt.register_native("once", Arity::Variadic, |_, _| Ok(int(2)));
+    assert!(matches!(duplicate, Err(RegisterError::Duplicate { name: "once" })));
+    assert_eq!(call(&mut rt, "once", &[]), Ok(int(1)));
+    // Builtins and defined values count, too.
+    let builtin = rt.register_native("len", Arity::Max(1), |_, _| Ok(Value::Nil));
+    assert!(matches!(builtin, Err(RegisterError::Duplicate { name: "len" })));
+    rt.define("defined", Value::Nil).unwrap();
+    assert!(rt.register_native("defined", Arity::Variadic, |_, _| Ok(Value::Nil)).is_err());
+    assert_eq!(
+        RegisterError::Duplicate { name: "len" }.to_string(),
+        "there already is a global named len",
+    );
+}
+
+#[test]
+fn natives_spawn_futures_through_their_context() {
+    let mut rt = Runtime::new();
+    rt.register_native("later", Arity::Max(1), |ctx, args| {
+        let v = args[0].clone();
+        ctx.spawn_rust(async move { Ok(v) })
+    })
+    .unwrap();
+    let fut = call(&mut rt, "later", &[s("done")]).unwrap();
+    assert!(matches!(fut, Value::Future(_)));
+    assert_eq!(rt.block_on_raw(&fut), Ok(s("done")));
+
+    // Pan futures handed to a native can be started by it.
+    rt.register_native("start", Arity::Max(1), |ctx, args| ctx.spawn(&args[0])).unwrap();
+    let pending = rt.spawn_rust(async { Ok(int(7)) });
+    assert_eq!(call(&mut rt, "start", std::slice::from_ref(&pending)), Ok(pending.clone()));
+    assert_eq!(rt.block_on_raw(&pending), Ok(int(7)));
+    assert_eq!(kind(&call(&mut rt, "start", &[int(1)]).unwrap_err()), "type");
+}
+
+#[test]
+fn natives_reach_the_state_of_the_embedder() {
+    let mut rt = Runtime::new();
+    rt.register_native("bump", Arity::Variadic, |ctx: &mut HostCtx, _| {
+        let n = ctx.with_state(|counter: &mut Counter| {
+            counter.0 += 1;
+            counter.0
+        });
+        Ok(n.map_or(Value::Nil, int))
+    })
+    .unwrap();
+    assert_eq!(call(&mut rt, "bump", &[]), Ok(Value::Nil));
+    rt.set_host_state(Counter(10));
+    assert_eq!(call(&mut rt, "bump", &[]), Ok(int(11)));
+    assert_eq!(call(&mut rt, "bump", &[]), Ok(int(12)));
+    assert_eq!(rt.with_host_state(|counter: &mut Counter| counter.0), Some(12));
+}
+
+#[test]
+fn natives_call_back_into_pan_values() {
+    let mut rt = Runtime::new();
+    let seen = Rc::new(RefCell::new(vec![]));
+    let record = seen.clone();
+    rt.register_native("apply_to_each", Arity::Max(2), move |ctx, args| {
+        if let Value::Array(elements) = &args[0] {
+            let elements = elements.borrow().clone();
+            for element in elements.iter() {
+                record.borrow_mut().push(ctx.call(&args[1], std::slice::from_ref(element))?);
+            }
+        }
+        Ok(Value::Nil)
+    })
+    .unwrap();
+    let neg = rt.global("neg").unwrap();
+    call(&mut rt, "apply_to_each", &[arr(vec![int(1), int(2)]), neg.clone()]).unwrap();
+    assert_eq!(*seen.borrow(), vec![int(-1), int(-2)]);
+    // Throws of the callback reach the native, which passes them on here.
+    let err = call(&mut rt, "apply_to_each", &[arr(vec![s("x")]), neg]).unwrap_err();
+    assert_eq!(kind(&err), "type");
+    seen.borrow_mut().clear();
+}
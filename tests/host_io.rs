@@ -0,0 +1,135 @@
+mod common;
+
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use pan_lang_rs::host_io::{FileSystem, InMemoryFs, InMemoryHostIo, Metadata};
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, field, int, kind, s};
+
+fn read(fs: &dyn FileSystem, path: &str) -> Vec<u8> {
+    let mut contents = vec![];
+    fs.open_read(Path::new(path)).unwrap().read_to_end(&mut contents).unwrap();
+    contents
+}
+
+fn write(fs: &dyn FileSystem, path: &str, append: bool, contents: &[u8]) {
+    fs.open_write(Path::new(path), append).unwrap().write_all(contents).unwrap();
+}
+
+#[test]
+fn programs_run_entirely_in_memory() {
+    let io = InMemoryHostIo::new("shout\nignored\n");
+    io.fs.insert_file("/data/in.txt", "one\ntwo\n");
+    let mut rt = Runtime::new();
+    rt.set_host_io(io.host_io());
+    rt.allow_fs(Some(Path::new("/data"))).unwrap();
+
+    // Read a file, transform it as stdin asks, write another file and report on it.
+    let mode = call(&mut rt, "read_line", &[]).unwrap();
+    assert_eq!(mode, s("shout"));
+    let text = call(&mut rt, "read_file_text", &[s("in.txt")]).unwrap();
+    let text = call(&mut rt, "str_to_upper", &[text]).unwrap();
+    call(&mut rt, "mkdir_all", &[s("out")]).unwrap();
+    call(&mut rt, "write_file", &[s("out/in.txt"), text.clone()]).unwrap();
+    call(&mut rt, "append_file", &[s("out/in.txt"), s("THREE\n")]).unwrap();
+    let listed = call(&mut rt, "list_dir", &[s("out")]).unwrap();
+    let size = call(&mut rt, "str_len", &[text]).unwrap();
+    call(&mut rt, "println", &[s("wrote"), size, s("characters to"), listed]).unwrap();
+    call(&mut rt, "eprintln", &[s("done")]).unwrap();
+
+    let files = vec![PathBuf::from("/data/in.txt"), PathBuf::from("/data/out/in.txt")];
+    assert_eq!(io.fs.files(), files);
+    assert_eq!(io.fs.file("/data/out/in.txt").unwrap(), b"ONE\nTWO\nTHREE\n");
+    assert_eq!(io.fs.file("/data/in.txt").unwrap(), b"one\ntwo\n");
+    assert_eq!(io.stdout.text(), "wrote 8 characters to [\"in.txt\"]\n");
+    assert_eq!(io.stderr.text(), "done\n");
+
+    // The root confines the builtins in the in-memory file system as well.
+    let err = call(&mut rt, "read_file", &[s("../elsewhere")]).unwrap_err();
+    assert_eq!(kind(&err), "forbidden");
+    let err = call(&mut rt, "read_file", &[s("missing")]).unwrap_err();
+    assert_eq!(kind(&err), "io");
+    let data = call(&mut rt, "error_data", &[err]).unwrap();
+    assert_eq!(field(&data, "io_kind"), s("not_found"));
+}
+
+#[test]
+fn the_file_system_is_looked_up_for_every_call() {
+    let mut rt = Runtime::new();
+    // A root must exist in the file system that is current when registering.
+    assert!(rt.allow_fs(Some(Path::new("/only/in/memory"))).is_err());
+    let fs = InMemoryFs::new();
+    fs.insert_file("/only/in/memory/x", "1");
+    rt.set_file_system(Rc::new(fs.clone()));
+    rt.allow_fs(Some(Path::new("/only/in/memory"))).unwrap();
+    assert_eq!(call(&mut rt, "file_exists", &[s("x")]).unwrap(), Value::Bool(true));
+
+    // Replacing the file system affects the builtins registered before.
+    let other = InMemoryFs::new();
+    other.insert_file("/only/in/memory/y", "2");
+    rt.set_file_system(Rc::new(other));
+    assert_eq!(call(&mut rt, "file_exists", &[s("x")]).unwrap(), Value::Bool(false));
+    assert_eq!(call(&mut rt, "read_file_text", &[s("y")]).unwrap(), s("2"));
+    call(&mut rt, "remove_file", &[s("y")]).unwrap();
+    assert_eq!(call(&mut rt, "list_dir", &[s(".")]).unwrap(), arr(vec![]));
+    assert_eq!(fs.files(), vec![PathBuf::from("/only/in/memory/x")]);
+}
+
+#[test]
+fn in_memory_host_io_reads_stdin_from_the_start() {
+    let io = InMemoryHostIo::new("a\nb");
+    for _ in 0..2 {
+        let mut rt = Runtime::new();
+        rt.set_host_io(io.host_io());
+        assert_eq!(call(&mut rt, "read_line", &[]).unwrap(), s("a"));
+        assert_eq!(call(&mut rt, "read_all_stdin", &[]).unwrap(), s("b"));
+        assert_eq!(call(&mut rt, "read_line", &[]).unwrap(), Value::Nil);
+        call(&mut rt, "print", &[int(1)]).unwrap();
+    }
+    // Both runtimes wrote to the same buffer.
+    assert_eq!(io.stdout.text(), "11");
+    assert!(InMemoryHostIo::default().stdout.contents().is_empty());
+}
+
+#[test]
+fn in_memory_files_and_directories() {
+    let fs = InMemoryFs::new();
+    fs.create_dir_all(Path::new("/a/b")).unwrap();
+    write(&fs, "/a/b/f", false, b"12");
+    write(&fs, "/a/b/f", true, b"3");
+    write(&fs, "a/./c/../b/g", false, b"");
+    assert_eq!(read(&fs, "/a/b/f"), b"123");
+    write(&fs, "/a/b/f", false, b"4");
+    assert_eq!(read(&fs, "/a/b/../b/f"), b"4");
+
+    let mut names = fs.list(Path::new("/a/b")).unwrap();
+    names.sort();
+    assert_eq!(names, ["f", "g"]);
+    assert_eq!(fs.list(Path::new("/")).unwrap(), ["a"]);
+    let dir = Metadata { is_dir: true, len: 0 };
+    assert_eq!(fs.metadata(Path::new("/a")).unwrap(), dir);
+    assert_eq!(fs.metadata(Path::new("/a/b/f")).unwrap(), Metadata { is_dir: false, len: 1 });
+    assert_eq!(fs.canonicalize(Path::new("a/b/../b")).unwrap(), Path::new("/a/b"));
+
+    // Clones share the files.
+    let clone = fs.clone();
+    clone.remove_file(Path::new("/a/b/g")).unwrap();
+    assert_eq!(fs.files(), vec![PathBuf::from("/a/b/f")]);
+    assert_eq!(fs.file("/a/b"), None);
+
+    let kind = |err: std::io::Error| err.kind();
+    assert_eq!(fs.open_read(Path::new("/missing")).err().map(kind), Some(ErrorKind::NotFound));
+    assert_eq!(fs.metadata(Path::new("/a/missing")).err().map(kind), Some(ErrorKind::NotFound));
+    assert!(fs.open_read(Path::new("/a")).is_err());
+    assert!(fs.open_write(Path::new("/a"), false).is_err());
+    assert!(fs.open_write(Path::new("/missing/f"), false).is_err());
+    assert!(fs.open_write(Path::new("/a/b/f/g"), false).is_err());
+    assert!(fs.create_dir_all(Path::new("/a/b/f/g")).is_err());
+    assert!(fs.list(Path::new("/a/b/f")).is_err());
+    assert!(fs.remove_file(Path::new("/a")).is_err());
+    assert!(fs.remove_file(Path::new("/a/b/g")).is_err());
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use pan_lang_rs::capabilities::Capabilities;
+use pan_lang_rs::runtime::{Runtime, SwapError};
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, int, runtime};
+
+// A runtime with two versions of a native, `answer` and `answer_v2`.
+fn answers() -> Runtime {
+    let mut rt = runtime(&Capabilities::SCRIPTING);
+    rt.register("answer", |_| Ok(int(1)));
+    rt.register("answer_v2", |args| Ok(int(2 + args.len() as i64)));
+    rt
+}
+
+#[test]
+fn lookups_by_name_see_the_new_version() {
+    let mut rt = answers();
+    let new = rt.global("answer_v2").unwrap();
+    // A caller that looks the function up by name for every call.
+    let by_name = |rt: &mut Runtime| call(rt, "answer", &[]);
+    assert_eq!(by_name(&mut rt), Ok(int(1)));
+    rt.hot_swap("answer", new).unwrap();
+    assert_eq!(by_name(&mut rt), Ok(int(2)));
+    // The arity may change.
+    assert_eq!(call(&mut rt, "answer", &[int(0)]), Ok(int(3)));
+    assert_eq!(rt.resolve("answer").unwrap(), rt.global("answer_v2").unwrap());
+}
+
+#[test]
+fn held_references_keep_the_old_version() {
+    let mut rt = answers();
+    let old = rt.global("answer").unwrap();
+    let table = arr(vec![old.clone()]);
+    let new = rt.global("answer_v2").unwrap();
+    rt.hot_swap("answer", new).unwrap();
+    assert_eq!(rt.call_raw(&old, &[]), Ok(int(1)));
+    let held = match &table {
+        Value::Array(table) => table.borrow()[0].clone(),
+        _ => unreachable!(),
+    };
+    assert_eq!(rt.call_raw(&held, &[]), Ok(int(1)));
+}
+
+#[test]
+fn failed_swaps_change_nothing() {
+    let mut rt = answers();
+    rt.define("number", int(7)).unwrap();
+    rt.define_const("CONSTANT", int(8)).unwrap();
+    let new = rt.global("answer_v2").unwrap();
+    let before = rt.top_level_bindings();
+
+    let undefined = rt.hot_swap("nonexistent", new.clone());
+    assert_eq!(undefined, Err(SwapError::Undefined { name: "nonexistent".to_string() }));
+    let constant = rt.hot_swap("CONSTANT", new.clone());
+    assert_eq!(constant, Err(SwapError::Constant { name: "CONSTANT".to_string() }));
+    assert!(matches!(rt.hot_swap("number", new), Err(SwapError::NotAFunction { .. })));
+    assert!(matches!(
+        rt.hot_swap("answer", int(3)),
+        Err(SwapError::NotAFunctionReplacement { .. }),
+    ));
+    let other = answers();
+    let foreign = other.global("answer_v2").unwrap();
+    assert!(matches!(rt.hot_swap("answer", foreign), Err(SwapError::CrossRuntime(_))));
+
+    assert_eq!(rt.top_level_bindings(), before);
+    assert_eq!(call(&mut rt, "answer", &[]), Ok(int(1)));
+}
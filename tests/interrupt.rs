@@ -0,0 +1,92 @@
+mod common;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use pan_lang_rs::runtime::{Arity, RunError, Runtime};
+use pan_lang_rs::types::interrupt::{self, InterruptHandle};
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, int};
+
+fn interrupt_later(handle: InterruptHandle) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        handle.interrupt();
+    })
+}
+
+#[test]
+fn natives_can_poll_for_interruptions() {
+    let mut rt = Runtime::new();
+    rt.register_native("spin", Arity::Max(0), |ctx, _| {
+        let mut rounds = 0;
+        while !ctx.is_interrupted() {
+            rounds += 1;
+            thread::yield_now();
+        }
+        ctx.check_interrupt()?;
+        Ok(int(rounds))
+    })
+    .unwrap();
+    let spin = rt.global("spin").unwrap();
+
+    let interrupter = interrupt_later(rt.interrupt_handle());
+    let start = Instant::now();
+    match rt.call(&spin, &[]) {
+        Err(RunError::Interrupted) => {}
+        other => panic!("expected an interruption, got {:?}", other),
+    }
+    assert!(start.elapsed() < Duration::from_secs(5));
+    interrupter.join().unwrap();
+
+    // Checking clears the flag, the runtime keeps working.
+    assert!(!rt.interrupt_handle().is_interrupted());
+    assert_eq!(call(&mut rt, "len", &[arr(vec![int(1)])]).unwrap(), int(1));
+}
+
+#[test]
+fn handles_share_one_flag() {
+    let mut rt = Runtime::new();
+    let first = rt.interrupt_handle();
+    let second = rt.interrupt_handle();
+    assert!(!first.is_interrupted());
+    second.interrupt();
+    second.interrupt();
+    assert!(first.is_interrupted());
+
+    // The next call is interrupted before doing anything, and only that one.
+    let len = rt.global("len").unwrap();
+    assert!(matches!(rt.call(&len, &[arr(vec![])]), Err(RunError::Interrupted)));
+    assert!(!first.is_interrupted());
+    assert_eq!(rt.call(&len, &[arr(vec![])]).unwrap(), int(0));
+
+    // Handles of other runtimes are independent.
+    let other = Runtime::new();
+    other.interrupt_handle().interrupt();
+    assert_eq!(rt.call(&len, &[arr(vec![])]).unwrap(), int(0));
+}
+
+#[test]
+fn interruptions_pass_through_assert_throws() {
+    let mut rt = Runtime::new();
+    rt.register_native("wait", Arity::Max(0), |ctx, _| loop {
+        ctx.check_interrupt()?;
+        thread::yield_now();
+    })
+    .unwrap();
+    let wait = rt.global("wait").unwrap();
+    let assert_throws = rt.global("assert_throws").unwrap();
+    let interrupter = interrupt_later(rt.interrupt_handle());
+    let thrown = rt.call_raw(&assert_throws, &[wait]).unwrap_err();
+    interrupter.join().unwrap();
+    assert!(interrupt::is_interrupted(&thrown));
+    assert!(!interrupt::is_interrupted(&Value::Nil));
+    assert_eq!(RunError::from_thrown(thrown).to_string(), "interrupted");
+}
+
+#[test]
+fn handles_are_send_and_sync() {
+    fn shareable<T: Send + Sync + 'static>(_: &T) {}
+    shareable(&Runtime::new().interrupt_handle());
+}
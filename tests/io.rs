@@ -0,0 +1,124 @@
+mod common;
+
+use std::io::{self, Write};
+
+use pan_lang_rs::host_io::SharedBuffer;
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, int, kind, message, s};
+
+fn captured() -> (Runtime, SharedBuffer, SharedBuffer) {
+    let mut rt = Runtime::new();
+    let (stdout, stderr) = (SharedBuffer::new(), SharedBuffer::new());
+    rt.set_stdout(Box::new(stdout.clone()));
+    rt.set_stderr(Box::new(stderr.clone()));
+    (rt, stdout, stderr)
+}
+
+// Accepts `budget` bytes, then fails every write.
+struct Failing {
+    written: SharedBuffer,
+    budget: usize,
+}
+
+impl Write for Failing {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.budget == 0 {
+            return Err(io::Error::other("disk full"));
+        }
+        let n = buf.len().min(self.budget);
+        self.budget -= n;
+        self.written.write(&buf[..n])
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn strings_are_written_verbatim() {
+    let (mut rt, stdout, stderr) = captured();
+    let pieces = call(&mut rt, "str_concat", &[s("grüße, "), s("🦀\n\"quoted\"")]).unwrap();
+    assert_eq!(call(&mut rt, "print", &[pieces]), Ok(Value::Nil));
+    assert_eq!(call(&mut rt, "print", &[s("")]), Ok(Value::Nil));
+    assert_eq!(call(&mut rt, "println", &[s("\u{0}tab\t")]), Ok(Value::Nil));
+    assert_eq!(stdout.contents(), "grüße, 🦀\n\"quoted\"\u{0}tab\t\n".as_bytes());
+    assert_eq!(stderr.contents(), b"");
+}
+
+#[test]
+fn other_values_are_written_as_literals() {
+    let (mut rt, stdout, _) = captured();
+    let nested = arr(vec![int(1), s("a\"b"), arr(vec![Value::Nil, Value::Bool(true)])]);
+    call(&mut rt, "println", &[nested.clone(), s("é"), int(-3), Value::Char('x')]).unwrap();
+    let expected = format!("{} é -3 {}\n", nested, Value::Char('x'));
+    assert_eq!(stdout.text(), expected);
+    assert!(stdout.text().starts_with("[1, \"a\\\"b\", [nil, true]] é -3 "), "{}", stdout.text());
+}
+
+#[test]
+fn arguments_are_separated_by_single_spaces() {
+    let (mut rt, stdout, _) = captured();
+    call(&mut rt, "print", &[]).unwrap();
+    call(&mut rt, "println", &[]).unwrap();
+    call(&mut rt, "print", &[s("a"), s(""), s("b")]).unwrap();
+    call(&mut rt, "println", &[int(1), int(2)]).unwrap();
+    assert_eq!(stdout.text(), "\na  b1 2\n");
+}
+
+#[test]
+fn stderr_is_a_separate_sink() {
+    let (mut rt, stdout, stderr) = captured();
+    call(&mut rt, "eprint", &[s("oh"), s("no")]).unwrap();
+    call(&mut rt, "eprintln", &[int(1)]).unwrap();
+    call(&mut rt, "println", &[s("fine")]).unwrap();
+    assert_eq!(stderr.text(), "oh no1\n");
+    assert_eq!(stdout.text(), "fine\n");
+
+    // Replacing a sink only affects later writes.
+    let later = SharedBuffer::new();
+    rt.set_stderr(Box::new(later.clone()));
+    call(&mut rt, "eprintln", &[s("later")]).unwrap();
+    assert_eq!(stderr.text(), "oh no1\n");
+    assert_eq!(later.text(), "later\n");
+}
+
+#[test]
+fn write_errors_are_thrown() {
+    for (name, budget) in [("print", 0), ("println", 5), ("eprint", 3), ("eprintln", 7)].iter() {
+        let mut rt = Runtime::new();
+        let written = SharedBuffer::new();
+        let sink = Box::new(Failing { written: written.clone(), budget: *budget });
+        if name.starts_with('e') {
+            rt.set_stderr(sink);
+        } else {
+            rt.set_stdout(sink);
+        }
+        let err = call(&mut rt, name, &[s("héllo"), s("world")]).unwrap_err();
+        assert_eq!(kind(&err), "io", "{}", name);
+        assert!(message(&err).starts_with(&format!("{} failed to write", name)), "{}", name);
+        assert!(message(&err).contains("disk full"), "{}", message(&err));
+        // Whatever fit is written, nothing more.
+        assert_eq!(written.contents(), &"héllo world".as_bytes()[..*budget], "{}", name);
+
+        // The runtime stays usable after a failed write.
+        rt.set_stdout(Box::new(SharedBuffer::new()));
+        rt.set_stderr(Box::new(SharedBuffer::new()));
+        assert_eq!(call(&mut rt, name, &[s("again")]), Ok(Value::Nil));
+    }
+}
+
+#[test]
+fn writing_a_huge_string_streams_it() {
+    let (mut rt, stdout, _) = captured();
+    let mut value = s("0123456789");
+    for _ in 0..16 {
+        value = call(&mut rt, "str_concat", &[value.clone(), value]).unwrap();
+    }
+    call(&mut rt, "print", &[value]).unwrap();
+    let text = stdout.text();
+    assert_eq!(text.len(), 10 << 16);
+    assert!(text.as_bytes().chunks(10).all(|chunk| chunk == b"0123456789"));
+}
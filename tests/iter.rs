@@ -0,0 +1,304 @@
+mod common;
+
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::types::bytes::Bytes;
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, field, int, ints, kind, native, s};
+
+fn n(v: &Value) -> i64 {
+    match v {
+        Value::Int(n) => *n,
+        other => panic!("not an int: {}", other),
+    }
+}
+
+fn double() -> Value {
+    native(|args| Ok(int(2 * n(&args[0]))))
+}
+
+fn even() -> Value {
+    native(|args| Ok(Value::Bool(n(&args[0]) % 2 == 0)))
+}
+
+fn sum() -> Value {
+    native(|args| Ok(int(args.iter().map(n).sum())))
+}
+
+// A map from the keys to their squares, built in descending order.
+fn squares(keys: &[i64]) -> Value {
+    Value::map(keys.iter().rev().map(|k| (int(*k), int(k * k))).collect())
+}
+
+#[test]
+fn over_arrays() {
+    let mut rt = Runtime::new();
+    let a = ints(&[1, 2, 3, 4]);
+    assert_eq!(call(&mut rt, "arr_map", &[a.clone(), double()]), Ok(ints(&[2, 4, 6, 8])));
+    assert_eq!(call(&mut rt, "arr_filter", &[a.clone(), even()]), Ok(ints(&[2, 4])));
+    assert_eq!(call(&mut rt, "fold", &[a.clone(), int(100), sum()]), Ok(int(110)));
+    assert_eq!(call(&mut rt, "find", &[a.clone(), even()]), Ok(int(2)));
+    assert_eq!(call(&mut rt, "any", &[a.clone(), even()]), Ok(Value::Bool(true)));
+    assert_eq!(call(&mut rt, "all", &[a.clone(), even()]), Ok(Value::Bool(false)));
+    assert_eq!(call(&mut rt, "find", &[ints(&[1, 3]), even()]), Ok(Value::Nil));
+
+    let seen = Rc::new(RefCell::new(vec![]));
+    let record = {
+        let seen = seen.clone();
+        native(move |args| {
+            seen.borrow_mut().push(args[0].clone());
+            Ok(s("ignored"))
+        })
+    };
+    assert_eq!(call(&mut rt, "each", &[a, record]), Ok(Value::Nil));
+    assert_eq!(*seen.borrow(), vec![int(1), int(2), int(3), int(4)]);
+}
+
+#[test]
+fn over_empty_collections() {
+    let mut rt = Runtime::new();
+    let empty = ints(&[]);
+    let unreachable = native(|_| panic!("called for an empty collection"));
+    let folded = call(&mut rt, "fold", &[empty.clone(), s("init"), unreachable.clone()]);
+    assert_eq!(folded, Ok(s("init")));
+    assert_eq!(call(&mut rt, "arr_map", &[empty.clone(), unreachable.clone()]), Ok(ints(&[])));
+    assert_eq!(call(&mut rt, "any", &[empty.clone(), unreachable.clone()]), Ok(Value::Bool(false)));
+    assert_eq!(call(&mut rt, "all", &[empty.clone(), unreachable.clone()]), Ok(Value::Bool(true)));
+    assert_eq!(call(&mut rt, "find", &[empty, unreachable.clone()]), Ok(Value::Nil));
+    let empty_map = squares(&[]);
+    assert_eq!(call(&mut rt, "fold", &[empty_map, int(0), unreachable]), Ok(int(0)));
+}
+
+#[test]
+fn over_maps_and_sets() {
+    let mut rt = Runtime::new();
+    let map = squares(&[3, 1, 2]);
+    let entry = native(|args| Ok(arr(args.to_vec())));
+    let entries = arr(vec![ints(&[1, 1]), ints(&[2, 4]), ints(&[3, 9])]);
+    assert_eq!(call(&mut rt, "arr_map", &[map.clone(), entry]), Ok(entries));
+    assert_eq!(call(&mut rt, "fold", &[map.clone(), int(0), sum()]), Ok(int(20)));
+    let even_key = native(|args| Ok(Value::Bool(n(&args[0]) % 2 == 0)));
+    assert_eq!(call(&mut rt, "find", &[map.clone(), even_key.clone()]), Ok(ints(&[2, 4])));
+    assert_eq!(call(&mut rt, "arr_filter", &[map, even_key]), Ok(squares(&[2])));
+
+    let set = Value::set([int(4), int(1), int(2)].iter().cloned().collect());
+    assert_eq!(call(&mut rt, "arr_map", &[set.clone(), double()]), Ok(ints(&[2, 4, 8])));
+    let evens = Value::set([int(2), int(4)].iter().cloned().collect());
+    assert_eq!(call(&mut rt, "arr_filter", &[set, even()]), Ok(evens));
+    let chars = call(&mut rt, "arr_map", &[s("ab"), native(|args| Ok(args[0].clone()))]);
+    assert_eq!(chars, Ok(arr(vec![Value::Char('a'), Value::Char('b')])));
+}
+
+#[test]
+fn callbacks_that_mutate_the_array() {
+    let mut rt = Runtime::new();
+    // Appended elements are not visited, but changes to later elements are.
+    let a = ints(&[1, 2, 3]);
+    let mutate = {
+        let a = a.clone();
+        native(move |args| {
+            if let Value::Array(arr) = &a {
+                let mut arr = arr.borrow_mut();
+                arr.push(int(0));
+                if args[0] == int(1) {
+                    arr[2] = int(30);
+                }
+            }
+            Ok(args[0].clone())
+        })
+    };
+    assert_eq!(call(&mut rt, "arr_map", &[a.clone(), mutate]), Ok(ints(&[1, 2, 30])));
+    assert_eq!(a, ints(&[1, 2, 30, 0, 0, 0]));
+
+    // The iteration ends early if the array shrinks.
+    let a = ints(&[1, 2, 3, 4]);
+    let calls = Rc::new(Cell::new(0));
+    let shrink = {
+        let (a, calls) = (a.clone(), calls.clone());
+        native(move |_| {
+            calls.set(calls.get() + 1);
+            if let Value::Array(arr) = &a {
+                arr.borrow_mut().pop();
+            }
+            Ok(Value::Nil)
+        })
+    };
+    assert_eq!(call(&mut rt, "each", &[a.clone(), shrink]), Ok(Value::Nil));
+    assert_eq!(calls.get(), 2);
+    assert_eq!(a, ints(&[1, 2]));
+}
+
+#[test]
+fn throws_stop_the_iteration() {
+    let mut rt = Runtime::new();
+    let a = ints(&[1, 2, 3, 4]);
+    let calls = Rc::new(Cell::new(0));
+    let throw_at_3 = {
+        let calls = calls.clone();
+        native(move |args| {
+            calls.set(calls.get() + 1);
+            if args[0] == int(3) { Err(s("three")) } else { Ok(Value::Bool(false)) }
+        })
+    };
+    for name in ["arr_map", "arr_filter", "each", "find", "any"].iter() {
+        calls.set(0);
+        let thrown = call(&mut rt, name, &[a.clone(), throw_at_3.clone()]);
+        assert_eq!(thrown, Err(s("three")), "{}", name);
+        assert_eq!(calls.get(), 3, "{}", name);
+        assert_eq!(a, ints(&[1, 2, 3, 4]), "{}", name);
+    }
+    let fold_thrower = native(|args| if args[1] == int(2) { Err(s("two")) } else { Ok(int(0)) });
+    assert_eq!(call(&mut rt, "fold", &[a, int(0), fold_thrower]), Err(s("two")));
+}
+
+#[test]
+fn wrong_types() {
+    let mut rt = Runtime::new();
+    let err = call(&mut rt, "arr_map", &[int(1), double()]).unwrap_err();
+    assert_eq!(kind(&err), "type");
+    let err = call(&mut rt, "arr_map", &[ints(&[1]), int(1)]).unwrap_err();
+    assert_eq!(kind(&err), "type");
+    let err = call(&mut rt, "fold", &[ints(&[1]), sum()]).unwrap_err();
+    assert_eq!(kind(&err), "type");
+}
+
+// Advance the iterator until it is done, collecting the values.
+fn drain(rt: &mut Runtime, it: &Value) -> Vec<Value> {
+    let mut values = vec![];
+    loop {
+        let step = call(rt, "iter_next", std::slice::from_ref(it)).unwrap();
+        if field(&step, "done") == Value::Bool(true) {
+            assert_eq!(step, Value::record(vec![("done", Value::Bool(true))]));
+            return values;
+        }
+        assert_eq!(field(&step, "done"), Value::Bool(false));
+        values.push(field(&step, "value"));
+    }
+}
+
+fn iter(rt: &mut Runtime, v: &Value) -> Value {
+    call(rt, "iter", std::slice::from_ref(v)).unwrap()
+}
+
+fn step(rt: &mut Runtime, it: &Value) -> Value {
+    field(&call(rt, "iter_next", std::slice::from_ref(it)).unwrap(), "value")
+}
+
+#[test]
+fn the_protocol_covers_every_container() {
+    let mut rt = Runtime::new();
+    let it = iter(&mut rt, &ints(&[3, 1, 2]));
+    assert_eq!(drain(&mut rt, &it), vec![int(3), int(1), int(2)]);
+    let it = iter(&mut rt, &squares(&[2, 1, 3]));
+    let entries = vec![ints(&[1, 1]), ints(&[2, 4]), ints(&[3, 9])];
+    assert_eq!(drain(&mut rt, &it), entries);
+    let it = iter(&mut rt, &Value::set(vec![int(2), s("a"), int(1)].into_iter().collect()));
+    assert_eq!(drain(&mut rt, &it), vec![int(1), int(2), s("a")]);
+    let it = iter(&mut rt, &s("añ🌍"));
+    assert_eq!(drain(&mut rt, &it), vec![Value::Char('a'), Value::Char('ñ'), Value::Char('🌍')]);
+    let it = iter(&mut rt, &Value::Bytes(Bytes::from_vec(vec![0, 7, 255])));
+    assert_eq!(drain(&mut rt, &it), vec![int(0), int(7), int(255)]);
+
+    let empties = [
+        ints(&[]),
+        Value::map(BTreeMap::new()),
+        Value::set(BTreeSet::new()),
+        s(""),
+        Value::Bytes(Bytes::from_vec(vec![])),
+    ];
+    for empty in empties.iter() {
+        let it = iter(&mut rt, empty);
+        assert_eq!(drain(&mut rt, &it), vec![], "{}", empty);
+        // An exhausted iterator stays exhausted.
+        assert_eq!(drain(&mut rt, &it), vec![], "{}", empty);
+    }
+}
+
+#[test]
+fn iterators_are_shared_and_iterable() {
+    let mut rt = Runtime::new();
+    let it = iter(&mut rt, &ints(&[1, 2, 3, 4]));
+    assert_eq!(step(&mut rt, &it), int(1));
+    // `iter` of an iterator is that iterator, and clones advance together.
+    let same = iter(&mut rt, &it);
+    assert_eq!(step(&mut rt, &same), int(2));
+    assert_eq!(step(&mut rt, &it.clone()), int(3));
+    // The higher-order builtins consume iterators like any other container.
+    assert_eq!(call(&mut rt, "arr_map", &[it.clone(), double()]), Ok(ints(&[8])));
+    assert_eq!(drain(&mut rt, &it), vec![]);
+
+    let it = iter(&mut rt, &s("abc"));
+    let chars = call(&mut rt, "arr_map", &[it, native(|args| Ok(args[0].clone()))]).unwrap();
+    assert_eq!(chars, arr(vec![Value::Char('a'), Value::Char('b'), Value::Char('c')]));
+    let bytes = Value::Bytes(Bytes::from_vec(vec![1, 2, 3, 4]));
+    assert_eq!(call(&mut rt, "arr_filter", &[bytes, even()]), Ok(ints(&[2, 4])));
+}
+
+#[test]
+fn arrays_are_iterated_by_index_up_to_their_original_length() {
+    let mut rt = Runtime::new();
+    let a = ints(&[1, 2, 3]);
+    let it = iter(&mut rt, &a);
+    assert_eq!(step(&mut rt, &it), int(1));
+    call(&mut rt, "set", &[a.clone(), int(1), int(20)]).unwrap();
+    call(&mut rt, "push", &[a.clone(), int(4)]).unwrap();
+    call(&mut rt, "insert", &[a.clone(), int(0), int(0)]).unwrap();
+    // Index 1 and 2 of the current array; the pushed element is past the original length.
+    assert_eq!(drain(&mut rt, &it), vec![int(1), int(20)]);
+
+    let a = ints(&[1, 2, 3, 4]);
+    let it = iter(&mut rt, &a);
+    assert_eq!(step(&mut rt, &it), int(1));
+    call(&mut rt, "clear", std::slice::from_ref(&a)).unwrap();
+    assert_eq!(drain(&mut rt, &it), vec![]);
+    // Growing the array again does not revive the iterator.
+    call(&mut rt, "push", &[a.clone(), int(5)]).unwrap();
+    call(&mut rt, "push", &[a, int(6)]).unwrap();
+    assert_eq!(drain(&mut rt, &it), vec![]);
+}
+
+#[test]
+fn sets_and_maps_continue_after_the_last_visited_element() {
+    let mut rt = Runtime::new();
+    let set = Value::set(vec![int(10), int(20), int(30)].into_iter().collect());
+    let it = iter(&mut rt, &set);
+    assert_eq!(step(&mut rt, &it), int(10));
+    for (name, n) in [("set_add", 5), ("set_add", 25), ("set_remove", 20), ("set_add", 40)].iter() {
+        call(&mut rt, name, &[set.clone(), int(*n)]).unwrap();
+    }
+    assert_eq!(drain(&mut rt, &it), vec![int(25), int(30), int(40)]);
+
+    let map = squares(&[1, 2, 3]);
+    let it = iter(&mut rt, &map);
+    assert_eq!(step(&mut rt, &it), ints(&[1, 1]));
+    call(&mut rt, "map_insert", &[map.clone(), int(0), int(0)]).unwrap();
+    call(&mut rt, "map_insert", &[map.clone(), int(2), int(-4)]).unwrap();
+    call(&mut rt, "map_remove", &[map.clone(), int(3)]).unwrap();
+    call(&mut rt, "map_insert", &[map.clone(), int(9), int(81)]).unwrap();
+    assert_eq!(drain(&mut rt, &it), vec![ints(&[2, -4]), ints(&[9, 81])]);
+
+    // Emptying the set ends the iteration, refilling it above the last member resumes it.
+    let set = Value::set(vec![int(1), int(2)].into_iter().collect());
+    let it = iter(&mut rt, &set);
+    assert_eq!(step(&mut rt, &it), int(1));
+    call(&mut rt, "set_remove", &[set.clone(), int(2)]).unwrap();
+    call(&mut rt, "set_remove", &[set.clone(), int(1)]).unwrap();
+    call(&mut rt, "set_add", &[set.clone(), int(0)]).unwrap();
+    assert_eq!(drain(&mut rt, &it), vec![]);
+}
+
+#[test]
+fn only_containers_can_be_iterated() {
+    let mut rt = Runtime::new();
+    for v in [Value::Nil, int(1), Value::Char('c'), double()].iter() {
+        let err = call(&mut rt, "iter", std::slice::from_ref(v)).unwrap_err();
+        assert_eq!(kind(&err), "type", "{}", v);
+    }
+    let err = call(&mut rt, "iter_next", &[ints(&[1])]).unwrap_err();
+    assert_eq!(kind(&err), "type");
+    assert_eq!(kind(&call(&mut rt, "iter_next", &[]).unwrap_err()), "type");
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, int, ints, kind, message, s};
+
+#[test]
+fn get_or_throw() {
+    let mut rt = Runtime::new();
+    let m = Value::record(vec![("a", int(1))]);
+    assert_eq!(call(&mut rt, "map_get_or_throw", &[m.clone(), s("a")]), Ok(int(1)));
+    let err = call(&mut rt, "map_get_or_throw", &[m, s("b")]).unwrap_err();
+    assert_eq!(kind(&err), "key");
+    assert_eq!(message(&err), "map_get_or_throw failed: the map has no key \"b\"");
+}
+
+fn pair(k: Value, v: Value) -> Value {
+    arr(vec![k, v])
+}
+
+#[test]
+fn get_insert_remove() {
+    let mut rt = Runtime::new();
+    let m = Value::record(vec![("a", int(1))]);
+    assert_eq!(call(&mut rt, "map_get", &[m.clone(), s("a")]), Ok(int(1)));
+    assert_eq!(call(&mut rt, "map_get", &[m.clone(), s("b")]), Ok(Value::Nil));
+    assert_eq!(call(&mut rt, "map_get", &[m.clone(), s("b"), int(0)]), Ok(int(0)));
+    assert_eq!(call(&mut rt, "map_get", &[m.clone(), s("a"), int(0)]), Ok(int(1)));
+
+    assert_eq!(call(&mut rt, "map_insert", &[m.clone(), s("b"), int(2)]), Ok(Value::Nil));
+    assert_eq!(call(&mut rt, "map_insert", &[m.clone(), s("a"), int(3)]), Ok(int(1)));
+    assert_eq!(call(&mut rt, "map_has", &[m.clone(), s("b")]), Ok(Value::Bool(true)));
+    assert_eq!(call(&mut rt, "map_len", std::slice::from_ref(&m)), Ok(int(2)));
+    assert_eq!(call(&mut rt, "map_remove", &[m.clone(), s("a")]), Ok(int(3)));
+    assert_eq!(call(&mut rt, "map_remove", &[m.clone(), s("a")]), Ok(Value::Nil));
+    assert_eq!(call(&mut rt, "map_has", &[m.clone(), s("a")]), Ok(Value::Bool(false)));
+    assert_eq!(m, Value::record(vec![("b", int(2))]));
+    assert_eq!(call(&mut rt, "map_clear", std::slice::from_ref(&m)), Ok(Value::Nil));
+    assert_eq!(call(&mut rt, "map_len", &[m]), Ok(int(0)));
+}
+
+#[test]
+fn keys_values_and_entries_are_in_key_order() {
+    let mut rt = Runtime::new();
+    let m = Value::record(vec![]);
+    // Inserted out of order, and with keys of several types.
+    let keys = [s("b"), int(2), ints(&[1, 2]), Value::Nil, s("a"), ints(&[1]), int(-5)];
+    for (i, k) in keys.iter().enumerate() {
+        call(&mut rt, "map_insert", &[m.clone(), k.clone(), int(i as i64)]).unwrap();
+    }
+    let sorted = arr(vec![Value::Nil, int(-5), int(2), s("a"), s("b"), ints(&[1]), ints(&[1, 2])]);
+    let values = ints(&[3, 6, 1, 4, 0, 5, 2]);
+    assert_eq!(call(&mut rt, "map_keys", std::slice::from_ref(&m)), Ok(sorted.clone()));
+    assert_eq!(call(&mut rt, "map_values", std::slice::from_ref(&m)), Ok(values.clone()));
+    let entries = call(&mut rt, "map_entries", std::slice::from_ref(&m)).unwrap();
+    let zipped = call(&mut rt, "zip", &[sorted, values]).unwrap();
+    assert_eq!(entries, zipped);
+
+    // The results are fresh arrays.
+    let keys = call(&mut rt, "map_keys", std::slice::from_ref(&m)).unwrap();
+    call(&mut rt, "clear", &[keys]).unwrap();
+    assert_eq!(call(&mut rt, "map_len", &[m]), Ok(int(7)));
+}
+
+#[test]
+fn keys_are_compared_structurally() {
+    let mut rt = Runtime::new();
+    let m = Value::record(vec![]);
+    let inner = Value::record(vec![("x", ints(&[1]))]);
+    call(&mut rt, "map_insert", &[m.clone(), ints(&[1, 2]), s("array")]).unwrap();
+    call(&mut rt, "map_insert", &[m.clone(), inner, s("map")]).unwrap();
+    call(&mut rt, "map_insert", &[m.clone(), Value::from(1.0), s("float")]).unwrap();
+
+    // Freshly built keys that are equal find the entries.
+    assert_eq!(call(&mut rt, "map_get", &[m.clone(), ints(&[1, 2])]), Ok(s("array")));
+    let rebuilt = Value::record(vec![("x", ints(&[1]))]);
+    assert_eq!(call(&mut rt, "map_get", &[m.clone(), rebuilt.clone()]), Ok(s("map")));
+    assert_eq!(call(&mut rt, "map_get", &[m.clone(), int(1)]), Ok(Value::Nil));
+    assert_eq!(call(&mut rt, "map_insert", &[m.clone(), rebuilt, s("again")]), Ok(s("map")));
+    assert_eq!(call(&mut rt, "map_len", std::slice::from_ref(&m)), Ok(int(3)));
+
+    // A map can be a key of itself.
+    call(&mut rt, "map_insert", &[m.clone(), m.clone(), s("itself")]).unwrap();
+    assert_eq!(call(&mut rt, "map_get", &[m.clone(), m.clone()]), Ok(s("itself")));
+    assert_eq!(call(&mut rt, "map_remove", &[m.clone(), m.clone()]), Ok(s("itself")));
+    assert_eq!(call(&mut rt, "map_len", &[m]), Ok(int(3)));
+}
+
+#[test]
+fn merges_prefer_the_right() {
+    let mut rt = Runtime::new();
+    let a = Value::record(vec![("x", int(1)), ("y", int(2))]);
+    let b = Value::record(vec![("y", int(3)), ("z", int(4))]);
+    let merged = call(&mut rt, "map_merge", &[a.clone(), b.clone()]).unwrap();
+    assert_eq!(merged, Value::record(vec![("x", int(1)), ("y", int(3)), ("z", int(4))]));
+    let merged = call(&mut rt, "map_merge", &[b.clone(), a.clone()]).unwrap();
+    assert_eq!(merged, Value::record(vec![("x", int(1)), ("y", int(2)), ("z", int(4))]));
+    assert_eq!(call(&mut rt, "map_merge", &[a.clone(), a.clone()]), Ok(a.clone()));
+
+    // The merged map is new, and not frozen even if its inputs are.
+    call(&mut rt, "freeze", std::slice::from_ref(&a)).unwrap();
+    let merged = call(&mut rt, "map_merge", &[a.clone(), b.clone()]).unwrap();
+    call(&mut rt, "map_clear", std::slice::from_ref(&merged)).unwrap();
+    assert_eq!(call(&mut rt, "map_len", &[a]), Ok(int(2)));
+    assert_eq!(call(&mut rt, "map_len", &[b]), Ok(int(2)));
+}
+
+#[test]
+fn frozen_maps() {
+    let mut rt = Runtime::new();
+    let m = Value::record(vec![("a", int(1))]);
+    call(&mut rt, "freeze", std::slice::from_ref(&m)).unwrap();
+    let mutations: [(&str, Vec<Value>); 3] = [
+        ("map_insert", vec![m.clone(), s("b"), int(2)]),
+        ("map_remove", vec![m.clone(), s("a")]),
+        ("map_clear", vec![m.clone()]),
+    ];
+    for (name, args) in mutations.iter() {
+        assert_eq!(kind(&call(&mut rt, name, args).unwrap_err()), "frozen", "{}", name);
+    }
+    assert_eq!(m, Value::record(vec![("a", int(1))]));
+    assert_eq!(call(&mut rt, "map_get", &[m.clone(), s("a")]), Ok(int(1)));
+    let entries = arr(vec![pair(s("a"), int(1))]);
+    assert_eq!(call(&mut rt, "map_entries", &[m]), Ok(entries));
+}
+
+#[test]
+fn wrong_types() {
+    let mut rt = Runtime::new();
+    let names = [
+        "map_get", "map_get_or_throw", "map_insert", "map_remove", "map_has", "map_len",
+        "map_keys", "map_values", "map_entries", "map_merge", "map_clear",
+    ];
+    for name in names.iter() {
+        let err = call(&mut rt, name, &[ints(&[1]), int(0), int(0)]).unwrap_err();
+        assert_eq!(kind(&err), "type", "{}", name);
+    }
+    let m = Value::record(vec![]);
+    assert_eq!(kind(&call(&mut rt, "map_merge", &[m, s("m")]).unwrap_err()), "type");
+}
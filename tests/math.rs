@@ -0,0 +1,131 @@
+mod common;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::value::Value;
+
+use common::{call, float, int, kind, s};
+
+// Whether a result is the float `expected`, where NaN matches NaN.
+fn is_float(v: &Value, expected: f64) -> bool {
+    match v {
+        Value::Float(x) => x.into_inner() == expected || (x.is_nan() && expected.is_nan()),
+        _ => false,
+    }
+}
+
+const GRID: [f64; 13] = [
+    f64::NEG_INFINITY, -1e300, -2.5, -1.0, -0.5, -0.0, 0.0, 0.5, 1.0, 2.5, 1e300, f64::INFINITY,
+    f64::NAN,
+];
+
+type UnaryFn = fn(f64) -> f64;
+
+#[test]
+fn unary_functions_match_f64() {
+    let mut rt = Runtime::new();
+    let functions: [(&str, UnaryFn); 13] = [
+        ("sqrt", f64::sqrt),
+        ("cbrt", f64::cbrt),
+        ("exp", f64::exp),
+        ("ln", f64::ln),
+        ("log2", f64::log2),
+        ("log10", f64::log10),
+        ("sin", f64::sin),
+        ("cos", f64::cos),
+        ("tan", f64::tan),
+        ("asin", f64::asin),
+        ("acos", f64::acos),
+        ("atan", f64::atan),
+        ("abs", f64::abs),
+    ];
+    for &(name, f) in &functions {
+        for &x in &GRID {
+            let result = call(&mut rt, name, &[float(x)]).unwrap();
+            assert!(is_float(&result, f(x)), "{}({}) = {}", name, x, result);
+        }
+        // Ints are promoted (except by `abs`, see below).
+        if name != "abs" {
+            let result = call(&mut rt, name, &[int(2)]).unwrap();
+            assert!(is_float(&result, f(2.0)), "{}(2) = {}", name, result);
+        }
+    }
+}
+
+#[test]
+fn binary_functions_match_f64() {
+    let mut rt = Runtime::new();
+    for &y in &GRID {
+        for &x in &GRID {
+            let result = call(&mut rt, "atan2", &[float(y), float(x)]).unwrap();
+            assert!(is_float(&result, y.atan2(x)), "atan2({}, {})", y, x);
+            let result = call(&mut rt, "pow", &[float(x), float(y)]).unwrap();
+            assert!(is_float(&result, x.powf(y)), "pow({}, {})", x, y);
+        }
+    }
+    // The power of two ints is an int.
+    assert_eq!(call(&mut rt, "pow", &[int(2), int(10)]), Ok(int(1024)));
+    let result = call(&mut rt, "pow", &[int(2), float(0.5)]).unwrap();
+    assert!(is_float(&result, 2f64.sqrt()));
+}
+
+#[test]
+fn domain_errors_follow_ieee() {
+    let mut rt = Runtime::new();
+    assert!(is_float(&call(&mut rt, "sqrt", &[int(-1)]).unwrap(), f64::NAN));
+    assert!(is_float(&call(&mut rt, "ln", &[int(0)]).unwrap(), f64::NEG_INFINITY));
+    assert!(is_float(&call(&mut rt, "asin", &[int(2)]).unwrap(), f64::NAN));
+}
+
+#[test]
+fn rounding_returns_ints_when_they_fit() {
+    let mut rt = Runtime::new();
+    let cases = [
+        ("floor", -2.5, -3),
+        ("ceil", -2.5, -2),
+        ("round", -2.5, -3),
+        ("round", 2.5, 3),
+        ("round", 2.4, 2),
+        ("trunc", -2.5, -2),
+        ("floor", -0.0, 0),
+    ];
+    for &(name, x, expected) in &cases {
+        assert_eq!(call(&mut rt, name, &[float(x)]), Ok(int(expected)), "{}({})", name, x);
+    }
+    for name in &["floor", "ceil", "round", "trunc"] {
+        assert_eq!(call(&mut rt, name, &[int(i64::MIN)]), Ok(int(i64::MIN)));
+        for &x in &[f64::NAN, f64::INFINITY, -1e300, 9.3e18] {
+            let result = call(&mut rt, name, &[float(x)]).unwrap();
+            assert!(matches!(result, Value::Float(_)), "{}({}) = {}", name, x, result);
+        }
+        let result = call(&mut rt, name, &[float(-9.2e18)]).unwrap();
+        assert!(matches!(result, Value::Int(_)), "{}(-9.2e18) = {}", name, result);
+    }
+}
+
+#[test]
+fn abs_and_sign() {
+    let mut rt = Runtime::new();
+    assert_eq!(call(&mut rt, "abs", &[int(-3)]), Ok(int(3)));
+    assert_eq!(kind(&call(&mut rt, "abs", &[int(i64::MIN)]).unwrap_err()), "overflow");
+    assert_eq!(call(&mut rt, "abs", &[float(-0.5)]), Ok(float(0.5)));
+    let signs = [(float(-0.5), -1), (float(-0.0), 0), (int(0), 0), (int(7), 1), (float(1e-300), 1)];
+    for (x, expected) in signs.iter().cloned() {
+        assert_eq!(call(&mut rt, "sign", &[x]), Ok(int(expected)));
+    }
+    assert_eq!(kind(&call(&mut rt, "sign", &[float(f64::NAN)]).unwrap_err()), "range");
+}
+
+#[test]
+fn constants_and_type_errors() {
+    let rt = Runtime::new();
+    assert_eq!(rt.global("pi"), Some(float(std::f64::consts::PI)));
+    assert_eq!(rt.global("e"), Some(float(std::f64::consts::E)));
+    assert_eq!(rt.global("inf"), Some(float(f64::INFINITY)));
+    assert!(is_float(&rt.global("nan").unwrap(), f64::NAN));
+
+    let mut rt = rt;
+    for name in &["sqrt", "floor", "abs", "sign", "atan2"] {
+        let err = call(&mut rt, name, &[s("1")]).unwrap_err();
+        assert_eq!(kind(&err), "type", "{}", name);
+    }
+}
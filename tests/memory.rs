@@ -0,0 +1,156 @@
+mod common;
+
+use std::mem;
+
+use pan_lang_rs::runtime::{Arity, RunError, Runtime};
+use pan_lang_rs::types::heap;
+use pan_lang_rs::value::Value;
+
+use common::{arr, int, s};
+
+const LIMIT: u64 = 64 * 1024;
+
+fn slot() -> u64 {
+    mem::size_of::<Value>() as u64
+}
+
+// Pushes strings of `payload` bytes onto `array` until a call fails, returns the error and the
+// number of successful pushes.
+fn fill(rt: &mut Runtime, array: &Value, payload: usize) -> (RunError, usize) {
+    let push = rt.global("push").unwrap();
+    let element = s(&"x".repeat(payload));
+    for pushed in 0.. {
+        if let Err(err) = rt.call(&push, &[array.clone(), element.clone()]) {
+            return (err, pushed);
+        }
+    }
+    unreachable!()
+}
+
+fn len(v: &Value) -> usize {
+    match v {
+        Value::Array(arr) => arr.borrow().len(),
+        other => panic!("not an array: {}", other),
+    }
+}
+
+#[test]
+fn appending_trips_the_limit_near_the_threshold() {
+    let mut rt = Runtime::new();
+    rt.set_memory_limit(Some(LIMIT));
+    let baseline = rt.memory_used();
+    let array = arr(vec![]);
+    let (err, pushed) = fill(&mut rt, &array, 100);
+    let oom = match err {
+        RunError::OutOfMemory(oom) => oom,
+        other => panic!("expected out of memory, got {:?}", other),
+    };
+    assert_eq!(oom.limit, LIMIT);
+    assert!(oom.used > LIMIT, "{:?}", oom);
+    // The call that crossed the limit did its work before failing, and nothing after it ran.
+    assert!(oom.used <= LIMIT + baseline + 100 + slot(), "{:?}", oom);
+    assert_eq!(len(&array), pushed + 1);
+    assert!(pushed as u64 >= (LIMIT - baseline) / (100 + slot()) - 1, "{}", pushed);
+    assert_eq!(
+        RunError::OutOfMemory(oom).to_string(),
+        format!("out of memory: used {} bytes with a limit of {} bytes", oom.used, oom.limit)
+    );
+
+    // Every further call fails until memory is released.
+    let len_fun = rt.global("len").unwrap();
+    assert!(matches!(rt.call(&len_fun, &[int(1)]), Err(RunError::OutOfMemory(_))));
+    let thrown = rt.call_raw(&len_fun, std::slice::from_ref(&array)).unwrap_err();
+    assert_eq!(heap::out_of_memory_of(&thrown).map(|oom| oom.limit), Some(LIMIT));
+    assert_eq!(heap::out_of_memory_of(&int(1)), None);
+}
+
+#[test]
+fn pan_code_can_not_catch_running_out_of_memory() {
+    let mut rt = Runtime::new();
+    rt.set_memory_limit(Some(LIMIT));
+    let push = rt.global("push").unwrap();
+    let array = arr(vec![]);
+    let target = array.clone();
+    rt.register_native("grow", Arity::Max(0), move |_, _| loop {
+        push.apply(&[target.clone(), s(&"y".repeat(64))])?;
+    })
+    .unwrap();
+    let grow = rt.global("grow").unwrap();
+
+    // `assert_throws` returns what its function throws, but not this.
+    let assert_throws = rt.global("assert_throws").unwrap();
+    match rt.call(&assert_throws, std::slice::from_ref(&grow)) {
+        Err(RunError::OutOfMemory(oom)) => assert!(oom.used > oom.limit),
+        other => panic!("expected out of memory, got {:?}", other),
+    }
+    assert!(len(&array) > 0);
+
+    // Once there is room again, other throws are caught as usual.
+    rt.set_memory_limit(Some(rt.memory_used() + LIMIT));
+    let fail = rt.global("fail").unwrap();
+    let caught = rt.call(&assert_throws, &[fail]).unwrap();
+    assert_eq!(common::kind(&caught), "type");
+}
+
+#[test]
+fn released_memory_is_credited_back() {
+    let mut rt = Runtime::new();
+    let baseline = rt.memory_used();
+    rt.set_memory_limit(Some(LIMIT));
+    let array = arr(vec![]);
+    let (err, _) = fill(&mut rt, &array, 1000);
+    assert!(matches!(err, RunError::OutOfMemory(_)));
+    assert!(rt.memory_used() > LIMIT);
+
+    // Raising the limit lets calls succeed again.
+    rt.set_memory_limit(Some(rt.memory_used() + 1024));
+    let len_fun = rt.global("len").unwrap();
+    let n = rt.call(&len_fun, std::slice::from_ref(&array)).unwrap();
+    assert_eq!(n, int(len(&array) as i64));
+
+    // Dropping the array gives its memory back.
+    rt.set_memory_limit(Some(LIMIT));
+    drop(array);
+    heap::collect();
+    assert!(rt.memory_used() <= baseline, "{} > {}", rt.memory_used(), baseline);
+    let array = arr(vec![]);
+    let (err, pushed) = fill(&mut rt, &array, 1000);
+    assert!(matches!(err, RunError::OutOfMemory(_)));
+    assert!(pushed > 0);
+}
+
+#[test]
+fn without_a_limit_nothing_fails() {
+    let mut rt = Runtime::new();
+    let baseline = rt.memory_used();
+    let push = rt.global("push").unwrap();
+    let array = arr(vec![]);
+    for i in 0..10_000 {
+        rt.call(&push, &[array.clone(), int(i)]).unwrap();
+    }
+    // The usage is still tracked.
+    assert!(rt.memory_used() >= baseline + 10_000 * slot());
+
+    // Removing a limit that was exceeded makes calls succeed again.
+    rt.set_memory_limit(Some(1));
+    assert!(matches!(rt.call(&push, &[array.clone(), int(0)]), Err(RunError::OutOfMemory(_))));
+    rt.set_memory_limit(None);
+    rt.call(&push, &[array.clone(), int(0)]).unwrap();
+    assert_eq!(len(&array), 10_002);
+}
+
+#[test]
+fn budgets_are_per_runtime() {
+    let mut small = Runtime::new();
+    small.set_memory_limit(Some(LIMIT));
+    let mut large = Runtime::new();
+    let array = arr(vec![]);
+    let push = large.global("push").unwrap();
+    for i in 0..(2 * LIMIT / slot()) as i64 {
+        large.call(&push, &[array.clone(), int(i)]).unwrap();
+    }
+    assert!(large.memory_used() > LIMIT);
+    let len_fun = small.global("len").unwrap();
+    assert_eq!(small.call(&len_fun, &[arr(vec![int(1)])]).unwrap(), int(1));
+    assert!(small.memory_used() < LIMIT);
+}
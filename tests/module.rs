@@ -0,0 +1,125 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use pan_lang_rs::module::{ModuleError, ModuleRequest, ResolvedModule};
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::value::{Fun, Native, Value};
+
+use common::{arr, call, field, int, kind, message, s, same};
+
+fn import(rt: &mut Runtime, specifier: &str, importer: Option<&str>) -> Result<Value, Value> {
+    call(rt, "import", &[s(specifier), importer.map_or(Value::Nil, s)])
+}
+
+// Serves `app`, which imports `left` and `right`, which both import `lib/shared`: `left` as
+// `./shared` and `right` as `../lib/shared`. Every resolution creates the module anew, and every
+// request is logged.
+fn diamond(rt: &mut Runtime) -> Rc<RefCell<Vec<ModuleRequest>>> {
+    let requests = Rc::new(RefCell::new(vec![]));
+    let log = requests.clone();
+    rt.set_module_resolver(move |request| {
+        log.borrow_mut().push(request.clone());
+        let key = match (request.importer.as_deref(), request.specifier.as_str()) {
+            (Some("left"), "./shared") | (Some("right"), "../lib/shared") => "lib/shared",
+            (_, specifier @ "app") | (Some("app"), specifier @ ("left" | "right")) => specifier,
+            (_, specifier) => return Err(ModuleError::new(format!("no module {}", specifier))),
+        };
+        let value = match key {
+            "lib/shared" => Value::record(vec![("state", arr(vec![]))]),
+            name => Value::record(vec![("name", s(name))]),
+        };
+        Ok(ResolvedModule::native(key, value))
+    });
+    requests
+}
+
+#[test]
+fn diamond_imports_share_one_module() {
+    let mut rt = Runtime::new();
+    let requests = diamond(&mut rt);
+    let app = rt.import("app").unwrap();
+    assert_eq!(field(&app, "name"), s("app"));
+    import(&mut rt, "left", Some("app")).unwrap();
+    import(&mut rt, "right", Some("app")).unwrap();
+    let from_left = import(&mut rt, "./shared", Some("left")).unwrap();
+    let from_right = import(&mut rt, "../lib/shared", Some("right")).unwrap();
+    assert!(same(&from_left, &from_right));
+
+    // State changed through one import is seen through the other.
+    call(&mut rt, "push", &[field(&from_left, "state"), int(1)]).unwrap();
+    assert_eq!(field(&from_right, "state"), arr(vec![int(1)]));
+    assert!(same(&rt.import("app").unwrap(), &app));
+
+    assert_eq!(rt.loaded_modules(), ["app", "left", "lib/shared", "right"]);
+    // The resolver is asked every time, with the importer of the request.
+    let importers: Vec<_> = requests.borrow().iter().map(|r| r.importer.clone()).collect();
+    let expected = [None, Some("app"), Some("app"), Some("left"), Some("right"), None];
+    assert_eq!(importers, expected.iter().map(|i| i.map(String::from)).collect::<Vec<_>>());
+    assert_eq!(requests.borrow()[3].specifier, "./shared");
+}
+
+#[test]
+fn native_modules_expose_their_functions() {
+    let mut rt = Runtime::new();
+    rt.set_module_resolver(|request| {
+        let double = Native::new("double", |args| match args.first() {
+            Some(Value::Int(n)) => Ok(int(n * 2)),
+            _ => Err(Value::error("type", "double expects an int")),
+        });
+        let value = Value::record(vec![("double", Value::Fun(Fun::Native(double)))]);
+        Ok(ResolvedModule::native(request.specifier.clone(), value))
+    });
+    let math = import(&mut rt, "math", None).unwrap();
+    let double = field(&math, "double");
+    assert_eq!(rt.call_raw(&double, &[int(21)]), Ok(int(42)));
+    let err = rt.call_raw(&double, &[s("x")]).unwrap_err();
+    assert_eq!(message(&err), "double expects an int");
+
+    // Replacing the resolver keeps the loaded modules.
+    rt.set_module_resolver(|_| Err(ModuleError::new("unavailable")));
+    assert_eq!(rt.loaded_modules(), ["math"]);
+    assert_eq!(rt.import("math").unwrap_err(), ModuleError::new("unavailable"));
+}
+
+#[test]
+fn failed_imports_throw() {
+    let mut rt = Runtime::new();
+    let err = rt.import("app").unwrap_err();
+    assert_eq!(err.to_string(), "cannot import app, there is no module resolver");
+    let err = import(&mut rt, "app", None).unwrap_err();
+    assert_eq!(kind(&err), "import");
+
+    diamond(&mut rt);
+    let err = import(&mut rt, "./shared", Some("app")).unwrap_err();
+    assert_eq!(kind(&err), "import");
+    assert_eq!(message(&err), "no module ./shared");
+    let data = call(&mut rt, "error_data", &[err]).unwrap();
+    assert_eq!(data, Value::record(vec![("specifier", s("./shared")), ("importer", s("app"))]));
+    assert!(rt.loaded_modules().is_empty());
+
+    let err = call(&mut rt, "import", &[int(1)]).unwrap_err();
+    assert_eq!(kind(&err), "type");
+    assert_eq!(message(&err), "import expects a string as the specifier, got int");
+    let err = call(&mut rt, "import", &[s("app"), int(1)]).unwrap_err();
+    assert_eq!(message(&err), "import expects a string or nil as the importer, got int");
+}
+
+#[test]
+fn modules_of_other_runtimes_are_rejected() {
+    let mut a = Runtime::new();
+    let mut b = Runtime::new();
+    let foreign = call(&mut a, "range", &[int(0), int(1)]).unwrap();
+    b.set_module_resolver(move |request| {
+        Ok(ResolvedModule::native(request.specifier.clone(), foreign.clone()))
+    });
+    let err = b.import("foreign").unwrap_err();
+    let expected = format!(
+        "cannot import foreign: a value of runtime {} can not be used in runtime {}",
+        a.id(),
+        b.id(),
+    );
+    assert_eq!(err.message, expected);
+    assert!(b.loaded_modules().is_empty());
+}
@@ -0,0 +1,55 @@
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::value::Value;
+
+fn field(error: &Value, name: &str) -> Value {
+    match error {
+        Value::Map(map) => map.borrow().get(&Value::from(name)).cloned().unwrap_or(Value::Nil),
+        _ => panic!("not a map: {:?}", error),
+    }
+}
+
+fn data_field(error: &Value, name: &str) -> Value {
+    field(&field(error, "data"), name)
+}
+
+#[test]
+fn panicking_native_throws_native_panic() {
+    let mut runtime = Runtime::new();
+    runtime.register("boom", |_| panic!("boom"));
+    let boom = runtime.global("boom").unwrap();
+    let err = runtime.call_raw(&boom, &[]).unwrap_err();
+    assert_eq!(err.error_kind().unwrap().to_string(), "native-panic");
+    assert_eq!(err.error_message().unwrap().to_string(), "native function boom panicked: boom");
+    assert_eq!(data_field(&err, "name"), Value::from("boom"));
+    assert_eq!(data_field(&err, "message"), Value::from("boom"));
+    // The runtime stays usable.
+    runtime.register("fine", |_| Ok(Value::Nil));
+    let fine = runtime.global("fine").unwrap();
+    assert_eq!(runtime.call_raw(&fine, &[]), Ok(Value::Nil));
+}
+
+#[test]
+fn panicking_rust_future_rejects_with_native_panic() {
+    let mut runtime = Runtime::new();
+    let fut = runtime.spawn_rust(async {
+        if true {
+            panic!("polled");
+        }
+        Ok(Value::Nil)
+    });
+    let err = runtime.block_on_raw(&fut).unwrap_err();
+    assert_eq!(err.error_kind().unwrap().to_string(), "native-panic");
+    assert_eq!(err.error_message().unwrap().to_string(), "a rust future panicked: polled");
+    assert_eq!(data_field(&err, "name"), Value::Nil);
+    assert_eq!(data_field(&err, "message"), Value::from("polled"));
+}
+
+#[test]
+fn non_string_payloads_have_no_message() {
+    let mut runtime = Runtime::new();
+    runtime.register("boom", |_| std::panic::panic_any(42));
+    let boom = runtime.global("boom").unwrap();
+    let err = runtime.call_raw(&boom, &[]).unwrap_err();
+    assert_eq!(err.error_message().unwrap().to_string(), "native function boom panicked");
+    assert_eq!(data_field(&err, "message"), Value::Nil);
+}
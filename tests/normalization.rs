@@ -0,0 +1,114 @@
+#![cfg(feature = "unicode-normalization")]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use pan_lang_rs::types::rope::Rope;
+
+// Counts the allocations of the current thread, so that tests running in parallel do not
+// interfere.
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.with(Cell::get);
+    let result = f();
+    (result, ALLOCATIONS.with(Cell::get) - before)
+}
+
+fn hash_of(rope: &Rope) -> u64 {
+    let mut state = DefaultHasher::new();
+    rope.hash(&mut state);
+    state.finish()
+}
+
+// Pairs of a composed string and its canonical decomposition.
+const PAIRS: &[(&str, &str)] = &[
+    ("\u{E9}", "e\u{301}"),
+    ("Caf\u{E9} cr\u{E8}me", "Cafe\u{301} cre\u{300}me"),
+    ("\u{1E69}", "s\u{323}\u{307}"),
+    ("\u{D55C}", "\u{1112}\u{1161}\u{11AB}"),
+    ("\u{212B}", "A\u{30A}"),
+];
+
+#[test]
+fn composed_and_decomposed_forms_agree() {
+    for &(composed, decomposed) in PAIRS {
+        let c = Rope::from_str(composed);
+        let d = Rope::from_str(decomposed);
+        assert_eq!(c.nfd().to_string(), decomposed, "{:?}", composed);
+        assert_eq!(d.nfd().to_string(), decomposed, "{:?}", composed);
+        assert_eq!(c.nfc(), d.nfc(), "{:?}", composed);
+        assert_eq!(hash_of(&c.nfc()), hash_of(&d.nfc()), "{:?}", composed);
+        assert!(d.nfd().is_nfd());
+        assert!(d.nfc().is_nfc());
+    }
+    // The angstrom sign is a singleton decomposition, so it is not in NFC.
+    assert_eq!(Rope::from_str("\u{212B}").nfc().to_string(), "\u{C5}");
+    assert!(!Rope::from_str("\u{212B}").is_nfc());
+}
+
+#[test]
+fn combining_marks_are_reordered() {
+    let a = Rope::from_str("s\u{307}\u{323}");
+    let b = Rope::from_str("s\u{323}\u{307}");
+    assert!(!a.is_nfd());
+    assert!(b.is_nfd());
+    assert_eq!(a.nfd(), b);
+    assert_eq!(a.nfc().to_string(), "\u{1E69}");
+}
+
+#[test]
+fn sequences_span_chunk_boundaries() {
+    let pad = "a".repeat(300);
+    let rope = Rope::from_str(&format!("{}e", pad))
+        .concat(&Rope::from_str(&format!("\u{301}\u{323}{}", pad)));
+    assert!(rope.chunks().count() > 1);
+    assert!(!rope.is_nfc());
+    assert_eq!(rope.nfc().to_string(), format!("{}\u{1EB9}\u{301}{}", pad, pad));
+    assert_eq!(rope.nfd().to_string(), format!("{}e\u{323}\u{301}{}", pad, pad));
+}
+
+#[test]
+fn normalized_ropes_are_returned_as_they_are() {
+    // A single chunk, so that traversing it does not allocate either.
+    let ascii = Rope::from_str(&"plain ascii text ".repeat(50));
+    assert_eq!(ascii.chunks().count(), 1);
+    let (is_nfc, n) = allocations(|| ascii.is_nfc());
+    assert!(is_nfc);
+    assert_eq!(n, 0);
+    let (is_nfd, n) = allocations(|| ascii.is_nfd());
+    assert!(is_nfd);
+    assert_eq!(n, 0);
+    assert!(Rope::ptr_eq(&ascii.nfc(), &ascii));
+    assert!(Rope::ptr_eq(&ascii.nfd(), &ascii));
+
+    let composed = Rope::from_str("Caf\u{E9}");
+    let (is_nfc, n) = allocations(|| composed.is_nfc());
+    assert!(is_nfc);
+    assert_eq!(n, 0);
+    assert!(Rope::ptr_eq(&composed.nfc(), &composed));
+
+    let long = Rope::from_str(&"plain ascii text ".repeat(1_000));
+    assert!(long.is_nfc());
+    assert!(Rope::ptr_eq(&long.nfc(), &long));
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use pan_lang_rs::builtins::{array, map, set};
+use pan_lang_rs::runtime::{RunError, Runtime};
+use pan_lang_rs::types::owner::{self, CrossRuntimeError};
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, int, kind, message, s};
+
+// An array created by the runtime.
+fn owned(rt: &mut Runtime) -> Value {
+    call(rt, "range", &[int(0), int(2)]).unwrap()
+}
+
+#[test]
+fn values_are_tagged_with_their_runtime() {
+    let mut a = Runtime::new();
+    let mut b = Runtime::new();
+    assert_ne!(a.id(), b.id());
+    assert_ne!(a.id(), owner::NONE);
+    assert_eq!(owned(&mut a).owner(), a.id());
+    assert_eq!(owned(&mut b).owner(), b.id());
+    assert_eq!(a.global("len").unwrap().owner(), a.id());
+
+    // Values the embedder creates, and values without state, belong to no runtime.
+    for v in [arr(vec![]), int(1), s("x"), Value::Nil].iter() {
+        assert_eq!(v.owner(), owner::NONE);
+        assert_eq!(owner::check(a.id(), v), Ok(()));
+    }
+    let err = owner::check(b.id(), &owned(&mut a)).unwrap_err();
+    assert_eq!(err, CrossRuntimeError { expected: b.id(), found: a.id() });
+    assert_eq!(
+        err.to_string(),
+        format!("a value of runtime {} can not be used in runtime {}", a.id(), b.id())
+    );
+}
+
+#[test]
+fn entry_points_reject_foreign_values() {
+    let mut a = Runtime::new();
+    let mut b = Runtime::new();
+    let foreign = owned(&mut a);
+    let foreign_fun = a.global("len").unwrap();
+    let len = b.global("len").unwrap();
+
+    let expected = CrossRuntimeError { expected: b.id(), found: a.id() };
+    for (fun, args) in [(&len, &foreign), (&foreign_fun, &arr(vec![]))].iter() {
+        match b.call(fun, std::slice::from_ref(*args)) {
+            Err(RunError::CrossRuntime(err)) => assert_eq!(err, expected),
+            other => panic!("expected a cross-runtime error, got {:?}", other),
+        }
+        let thrown = b.call_raw(fun, std::slice::from_ref(*args)).unwrap_err();
+        assert_eq!(kind(&thrown), "runtime");
+    }
+    assert_eq!(b.define("foreign", foreign.clone()), Err(expected));
+    assert!(b.global("foreign").is_none());
+    assert!(b.root(foreign.clone()).is_err());
+
+    // Untagged values work everywhere, and the foreign value still works at home.
+    assert_eq!(b.call(&len, &[arr(vec![int(1)])]).unwrap(), int(1));
+    assert_eq!(a.call(&foreign_fun, &[foreign]).unwrap(), int(2));
+}
+
+// The builtins are called directly: the entry points would already refuse the foreign value, but
+// pan code can reach one through a collection of the embedder.
+#[test]
+fn builtins_refuse_to_store_foreign_values() {
+    let mut a = Runtime::new();
+    let mut b = Runtime::new();
+    let foreign = owned(&mut a);
+    let empty_set = || Value::set(Default::default());
+    let empty_map = || Value::map(Default::default());
+    let array = owned(&mut b);
+    let set = call(&mut b, "union", &[empty_set(), empty_set()]).unwrap();
+    let map = call(&mut b, "map_merge", &[empty_map(), empty_map()]).unwrap();
+    assert_eq!((set.owner(), map.owner()), (b.id(), b.id()));
+
+    type Builtin = fn(&[Value]) -> Result<Value, Value>;
+    let cases: Vec<(Builtin, Vec<Value>)> = vec![
+        (array::push, vec![array.clone(), foreign.clone()]),
+        (array::set, vec![array.clone(), int(0), foreign.clone()]),
+        (array::insert, vec![array.clone(), int(0), foreign.clone()]),
+        (set::set_add, vec![set.clone(), foreign.clone()]),
+        (map::map_insert, vec![map.clone(), foreign.clone(), int(0)]),
+        (map::map_insert, vec![map.clone(), int(0), foreign.clone()]),
+    ];
+    for (builtin, args) in cases {
+        let thrown = builtin(&args).unwrap_err();
+        assert_eq!(kind(&thrown), "runtime");
+        let expected = CrossRuntimeError { expected: b.id(), found: a.id() };
+        assert_eq!(message(&thrown), expected.to_string());
+    }
+    assert_eq!(array, arr(vec![int(0), int(1)]));
+    assert_eq!((set, map), (empty_set(), empty_map()));
+
+    // Collections of the embedder take values of any runtime.
+    let untagged = arr(vec![]);
+    array::push(&[untagged.clone(), foreign.clone()]).unwrap();
+    assert_eq!(untagged, arr(vec![foreign]));
+}
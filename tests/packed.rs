@@ -0,0 +1,55 @@
+#![cfg(feature = "nan-boxing")]
+
+mod common;
+
+use pan_lang_rs::types::packed::PackedValue;
+use pan_lang_rs::value::Value;
+
+use common::{arr, int, s};
+
+fn round_trip(v: Value) {
+    let packed = PackedValue::new(v.clone());
+    assert_eq!(packed.to_value(), v);
+    assert_eq!(packed.clone().into_value(), v);
+    assert_eq!(packed.into_value(), v);
+}
+
+#[test]
+fn scalars_are_inline() {
+    let max = (1 << 47) - 1;
+    for v in [Value::Nil, Value::Bool(true), Value::Char('ß'), int(0), int(-1), int(max)] {
+        assert!(PackedValue::new(v.clone()).is_inline(), "{}", v);
+        round_trip(v);
+    }
+    assert!(PackedValue::new(Value::from(1.5)).is_inline());
+    round_trip(Value::from(1.5));
+}
+
+#[test]
+fn ints_beyond_48_bits_are_boxed() {
+    for n in [1 << 47, -(1 << 47) - 1, i64::MAX, i64::MIN] {
+        assert!(!PackedValue::new(int(n)).is_inline(), "{}", n);
+        round_trip(int(n));
+    }
+    assert!(PackedValue::new(int(-(1 << 47))).is_inline());
+}
+
+#[test]
+fn floats_that_look_tagged_are_boxed() {
+    let tagged = Value::from(f64::from_bits(0xFFFD_0000_0000_0007));
+    assert!(!PackedValue::new(tagged.clone()).is_inline());
+    round_trip(tagged);
+    round_trip(Value::from(f64::NEG_INFINITY));
+}
+
+#[test]
+fn clones_share_the_box() {
+    let array = arr(vec![s("a")]);
+    let packed = PackedValue::new(array.clone());
+    let copy = packed.clone();
+    drop(packed);
+    match (&copy.into_value(), &array) {
+        (Value::Array(a), Value::Array(b)) => assert!(gc::Gc::ptr_eq(a, b)),
+        other => panic!("not arrays: {:?}", other),
+    }
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use std::fs::File;
+use std::io::Read;
+
+use pan_lang_rs::runtime::{Arity, Runtime};
+use pan_lang_rs::types::pan_error::{self, PanError};
+use pan_lang_rs::value::Value;
+
+use common::{call, field, int, kind, message, s, same};
+
+#[test]
+fn natives_throw_io_errors_with_question_marks() {
+    let mut rt = Runtime::new();
+    rt.register("read_missing", pan_error::adapt(|_| {
+        let mut contents = String::new();
+        File::open("/this/path/does/not/exist")?.read_to_string(&mut contents)?;
+        Ok(s(&contents))
+    }));
+    let err = call(&mut rt, "read_missing", &[]).unwrap_err();
+    assert_eq!(call(&mut rt, "error_kind", std::slice::from_ref(&err)), Ok(s("io")));
+    let msg = call(&mut rt, "error_message", std::slice::from_ref(&err)).unwrap();
+    assert_eq!(msg, s(&message(&err)));
+    let data = call(&mut rt, "error_data", &[err]).unwrap();
+    assert_eq!(field(&data, "io_kind"), s("not_found"));
+
+    // Decoding errors work the same way.
+    rt.register("decode", pan_error::adapt(|_| Ok(s(&String::from_utf8(vec![0xff])?))));
+    let err = call(&mut rt, "decode", &[]).unwrap_err();
+    assert_eq!(kind(&err), "decode");
+}
+
+#[test]
+fn errors_round_trip_with_their_sources() {
+    let cause = PanError::new("io", "disk on fire").data(int(7));
+    let err = PanError::new("config", "cannot load the config").source(cause);
+    let thrown = err.into_value();
+    assert_eq!(kind(&thrown), "config");
+    assert_eq!(kind(&field(&thrown, "source")), "io");
+
+    let back = PanError::from_thrown(&thrown).unwrap();
+    assert_eq!((back.kind(), back.message()), ("config", "cannot load the config"));
+    assert_eq!(back.payload(), None);
+    let cause = back.cause().unwrap();
+    assert_eq!((cause.kind(), cause.message()), ("io", "disk on fire"));
+    assert_eq!(cause.payload(), Some(&int(7)));
+    assert!(cause.cause().is_none());
+    assert_eq!(back.to_string(), "config: cannot load the config");
+    let source = std::error::Error::source(&back).unwrap();
+    assert_eq!(source.to_string(), "io: disk on fire");
+
+    // Unmodified errors turn back into the value they came from.
+    assert!(same(&back.into_value(), &thrown));
+    assert!(PanError::from_thrown(&int(1)).is_none());
+    assert!(PanError::from_thrown(&s("oops")).is_none());
+}
+
+#[test]
+fn errors_of_callbacks_pass_through_unchanged() {
+    let mut rt = Runtime::new();
+    rt.register("fail", |_| Err(Value::error("parse", "bad input")));
+    rt.register("exit_now", |_| Err(int(3)));
+    rt.register_native("forward", Arity::Max(1), pan_error::adapt_host(|ctx, args| {
+        Ok(ctx.call(&args[0], &[])?)
+    }))
+    .unwrap();
+    let fail = rt.global("fail").unwrap();
+    let direct = rt.call_raw(&fail, &[]).unwrap_err();
+    let forwarded = call(&mut rt, "forward", &[fail]).unwrap_err();
+    assert_eq!(forwarded, direct);
+    assert_eq!(message(&forwarded), "bad input");
+
+    // Values that are not errors are rethrown as they were.
+    let exit_now = rt.global("exit_now").unwrap();
+    assert_eq!(call(&mut rt, "forward", &[exit_now]), Err(int(3)));
+    let other = PanError::from(int(3));
+    assert_eq!(other.kind(), "thrown");
+    assert_eq!(other.into_value(), int(3));
+
+    // Wrapping a callback error gives it a source.
+    let wrapped = PanError::from(direct).source(PanError::new("inner", "x")).into_value();
+    assert_eq!(kind(&wrapped), "parse");
+    assert_eq!(message(&field(&wrapped, "source")), "x");
+}
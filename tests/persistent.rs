@@ -0,0 +1,171 @@
+#![allow(clippy::mutable_key_type)]
+
+mod common;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, field, int, kind, s, same};
+
+fn plain_map(n: i64) -> Value {
+    Value::map((0..n).map(|k| (int(k), s(&k.to_string()))).collect())
+}
+
+fn plain_set(n: i64) -> Value {
+    Value::set((0..n).map(int).collect())
+}
+
+#[test]
+fn deep_copies_of_plain_maps_are_independent() {
+    let mut rt = Runtime::new();
+    let original = plain_map(1_000);
+    let copy = original.deep_copy();
+    assert_eq!(copy, original);
+    call(&mut rt, "map_insert", &[copy.clone(), int(5), s("changed")]).unwrap();
+    call(&mut rt, "map_remove", &[copy.clone(), int(6)]).unwrap();
+    call(&mut rt, "map_insert", &[copy.clone(), int(1_000), s("added")]).unwrap();
+    assert_eq!(original, plain_map(1_000));
+    assert_eq!(call(&mut rt, "map_get", &[copy.clone(), int(5)]), Ok(s("changed")));
+    assert_eq!(call(&mut rt, "map_has", &[copy.clone(), int(6)]), Ok(Value::Bool(false)));
+    assert_eq!(call(&mut rt, "map_len", &[copy]), Ok(int(1_000)));
+}
+
+#[test]
+fn deep_copies_still_copy_nested_collections() {
+    let mut rt = Runtime::new();
+    let inner = arr(vec![int(1)]);
+    let mut entries: BTreeMap<Value, Value> = (0..100).map(|k| (int(k), int(k))).collect();
+    entries.insert(int(50), inner.clone());
+    let original = Value::map(entries);
+    let copy = original.deep_copy();
+    let copied_inner = call(&mut rt, "map_get", &[copy, int(50)]).unwrap();
+    call(&mut rt, "push", &[copied_inner.clone(), int(2)]).unwrap();
+    assert_eq!(inner, arr(vec![int(1)]));
+    assert_eq!(copied_inner, arr(vec![int(1), int(2)]));
+}
+
+#[test]
+fn thawing_makes_a_mutable_copy() {
+    let mut rt = Runtime::new();
+    let frozen = plain_map(1_000);
+    frozen.deep_freeze();
+    let err = call(&mut rt, "map_insert", &[frozen.clone(), int(0), s("x")]).unwrap_err();
+    assert_eq!(kind(&err), "frozen");
+
+    let thawed = call(&mut rt, "thaw", std::slice::from_ref(&frozen)).unwrap();
+    assert_eq!(call(&mut rt, "is_frozen", std::slice::from_ref(&thawed)), Ok(Value::Bool(false)));
+    assert_eq!(thawed, frozen);
+    call(&mut rt, "map_insert", &[thawed.clone(), int(0), s("x")]).unwrap();
+    assert_eq!(call(&mut rt, "map_get", &[frozen.clone(), int(0)]), Ok(s("0")));
+    assert_eq!(call(&mut rt, "map_get", &[thawed, int(0)]), Ok(s("x")));
+
+    let set = plain_set(100);
+    set.freeze();
+    let thawed = call(&mut rt, "thaw", std::slice::from_ref(&set)).unwrap();
+    call(&mut rt, "set_remove", &[thawed.clone(), int(3)]).unwrap();
+    assert_eq!(call(&mut rt, "set_has", &[set, int(3)]), Ok(Value::Bool(true)));
+    assert_eq!(call(&mut rt, "set_has", &[thawed, int(3)]), Ok(Value::Bool(false)));
+
+    // Thawing is shallow, and leaves other values alone.
+    let nested = arr(vec![arr(vec![])]);
+    nested.deep_freeze();
+    let thawed = call(&mut rt, "thaw", &[nested]).unwrap();
+    let inner = call(&mut rt, "get", &[thawed, int(0)]).unwrap();
+    assert_eq!(call(&mut rt, "is_frozen", &[inner]), Ok(Value::Bool(true)));
+    assert_eq!(call(&mut rt, "thaw", &[int(1)]), Ok(int(1)));
+}
+
+#[test]
+fn snapshots_of_plain_maps_are_independent() {
+    let mut rt = Runtime::new();
+    let map = plain_map(10_000);
+    rt.define("m", map.clone()).unwrap();
+    let snapshot = rt.snapshot();
+    for k in 0..5_000 {
+        call(&mut rt, "map_remove", &[map.clone(), int(k)]).unwrap();
+    }
+    assert_eq!(call(&mut rt, "map_len", &[map]), Ok(int(5_000)));
+    rt.restore(&snapshot).unwrap();
+    assert_eq!(rt.global("m"), Some(plain_map(10_000)));
+    let restored = rt.global("m").unwrap();
+    call(&mut rt, "map_clear", &[restored]).unwrap();
+    rt.restore(&snapshot).unwrap();
+    assert_eq!(rt.global("m"), Some(plain_map(10_000)));
+}
+
+#[test]
+fn copies_agree_on_order_equality_and_hash() {
+    let mut rt = Runtime::new();
+    let built = plain_map(500);
+    // The same entries, after inserting and removing others, so the trees differ in shape.
+    let edited = plain_map(2_000).deep_copy();
+    for k in 500..2_000 {
+        call(&mut rt, "map_remove", &[edited.clone(), int(k)]).unwrap();
+    }
+    let copies = [built.deep_copy(), built.thaw(), edited.clone()];
+    for copy in copies.iter() {
+        assert_eq!(copy, &built);
+        assert_eq!(copy.cmp(&plain_map(501)), built.cmp(&plain_map(501)));
+        assert_eq!(
+            call(&mut rt, "hash", std::slice::from_ref(copy)),
+            call(&mut rt, "hash", std::slice::from_ref(&built)),
+        );
+        assert_eq!(
+            call(&mut rt, "map_keys", std::slice::from_ref(copy)),
+            Ok(arr((0..500).map(int).collect())),
+        );
+    }
+
+    let set = plain_set(300);
+    let model: BTreeSet<Value> = (0..300).map(int).collect();
+    assert_eq!(set.thaw(), Value::set(model));
+    assert_eq!(
+        call(&mut rt, "set_to_array", &[set.deep_copy()]),
+        Ok(arr((0..300).map(int).collect())),
+    );
+}
+
+#[test]
+fn iterating_sees_later_insertions() {
+    let mut rt = Runtime::new();
+    let map = plain_map(100);
+    let iter = call(&mut rt, "iter", std::slice::from_ref(&map)).unwrap();
+    let first = call(&mut rt, "iter_next", std::slice::from_ref(&iter)).unwrap();
+    assert_eq!(field(&first, "value"), arr(vec![int(0), s("0")]));
+    call(&mut rt, "map_remove", &[map.clone(), int(1)]).unwrap();
+    call(&mut rt, "map_insert", &[map, int(200), s("200")]).unwrap();
+    let mut keys = vec![];
+    loop {
+        let next = call(&mut rt, "iter_next", std::slice::from_ref(&iter)).unwrap();
+        if field(&next, "done") == Value::Bool(true) {
+            break;
+        }
+        keys.push(call(&mut rt, "get", &[field(&next, "value"), int(0)]).unwrap());
+    }
+    let expected: Vec<Value> = (2..100).chain(Some(200)).map(int).collect();
+    assert_eq!(keys, expected);
+}
+
+#[test]
+fn snapshots_keep_deep_frozen_collections() {
+    let mut rt = Runtime::new();
+    let nested = || arr(vec![arr(vec![int(1)]), Value::map(BTreeMap::new())]);
+    let sealed = nested();
+    sealed.deep_freeze();
+    let frozen = nested();
+    frozen.freeze();
+    rt.define("sealed", sealed.clone()).unwrap();
+    rt.define("frozen", frozen.clone()).unwrap();
+    let snapshot = rt.snapshot();
+    rt.restore(&snapshot).unwrap();
+
+    // Arrays and collections that contain collections are not shared by `types::persistent`, so
+    // only the deep frozen value is kept as it is, and the merely frozen one is copied.
+    assert!(same(&rt.global("sealed").unwrap(), &sealed));
+    let restored = rt.global("frozen").unwrap();
+    assert!(!same(&restored, &frozen));
+    assert_eq!(restored, frozen);
+    assert_eq!(call(&mut rt, "is_frozen", &[restored]), Ok(Value::Bool(true)));
+}
@@ -0,0 +1,172 @@
+mod common;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::types::random::Random;
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, int, ints, kind, message, s};
+
+fn n(v: Value) -> i64 {
+    match v {
+        Value::Int(n) => n,
+        other => panic!("not an int: {}", other),
+    }
+}
+
+// A call of every random builtin, in turn, with the results.
+fn everything(rt: &mut Runtime) -> Vec<Value> {
+    let shuffled = ints(&[1, 2, 3, 4, 5, 6, 7, 8]);
+    let mut results = vec![
+        call(rt, "random_int", &[int(-1000), int(1000)]).unwrap(),
+        call(rt, "random_int", &[int(i64::MIN), int(i64::MAX)]).unwrap(),
+        call(rt, "random_float", &[]).unwrap(),
+        call(rt, "random_bool", &[]).unwrap(),
+        call(rt, "random_bool", &[Value::from(0.3)]).unwrap(),
+        call(rt, "choice", &[ints(&[1, 2, 3, 4, 5])]).unwrap(),
+    ];
+    call(rt, "shuffle", std::slice::from_ref(&shuffled)).unwrap();
+    results.push(shuffled);
+    results
+}
+
+#[test]
+fn xoshiro_reference_values() {
+    // The first outputs of xoshiro256** seeded by SplitMix64 with the seed zero.
+    let random = Random::with_seed(0);
+    assert_eq!(random.next_u64(), 0x99ec_5f36_cb75_f2b4);
+    assert_eq!(random.next_u64(), 0xbf6e_1f78_4956_452a);
+    assert_eq!(random.next_u64(), 0x1a5f_849d_4933_e6e0);
+    random.seed(0);
+    assert_eq!(random.next_u64(), 0x99ec_5f36_cb75_f2b4);
+}
+
+#[test]
+fn seeds_determine_everything() {
+    let mut a = Runtime::new();
+    let mut b = Runtime::new();
+    for &seed in &[0, 1, -1, i64::MIN, 394] {
+        call(&mut a, "random_seed", &[int(seed)]).unwrap();
+        call(&mut b, "random_seed", &[int(seed)]).unwrap();
+        assert_eq!(call(&mut a, "random_get_seed", &[]), Ok(int(seed)));
+        let results = everything(&mut a);
+        assert_eq!(results, everything(&mut b), "{}", seed);
+        assert_ne!(results, everything(&mut a), "{}", seed);
+    }
+
+    // Deterministic runtimes are seeded with their seed.
+    let mut c = Runtime::new_deterministic(7);
+    assert_eq!(call(&mut c, "random_get_seed", &[]), Ok(int(7)));
+    call(&mut a, "random_seed", &[int(7)]).unwrap();
+    assert_eq!(everything(&mut c), everything(&mut a));
+}
+
+#[test]
+fn the_default_seed_reproduces_a_run() {
+    let mut a = Runtime::new();
+    let mut b = Runtime::new();
+    let seed = call(&mut a, "random_get_seed", &[]).unwrap();
+    let results = everything(&mut a);
+    call(&mut b, "random_seed", std::slice::from_ref(&seed)).unwrap();
+    assert_eq!(everything(&mut b), results);
+    // Runtimes have their own generators, seeded differently.
+    let mut c = Runtime::new();
+    assert_ne!(call(&mut c, "random_get_seed", &[]).unwrap(), seed);
+}
+
+#[test]
+fn ranges() {
+    let mut rt = Runtime::new_deterministic(1);
+    for _ in 0..1000 {
+        assert_eq!(call(&mut rt, "random_int", &[int(5), int(6)]), Ok(int(5)));
+        let x = n(call(&mut rt, "random_int", &[int(-2), int(2)]).unwrap());
+        assert!((-2..2).contains(&x));
+        let x = n(call(&mut rt, "random_int", &[int(i64::MAX - 1), int(i64::MAX)]).unwrap());
+        assert_eq!(x, i64::MAX - 1);
+        match call(&mut rt, "random_float", &[]).unwrap() {
+            Value::Float(x) => assert!((0.0..1.0).contains(&x.into_inner())),
+            other => panic!("not a float: {}", other),
+        }
+        assert_eq!(call(&mut rt, "random_bool", &[int(0)]), Ok(Value::Bool(false)));
+        assert_eq!(call(&mut rt, "random_bool", &[int(1)]), Ok(Value::Bool(true)));
+    }
+    // Both ends of a range of two are hit.
+    let mut seen = [false; 2];
+    for _ in 0..100 {
+        seen[n(call(&mut rt, "random_int", &[int(0), int(2)]).unwrap()) as usize] = true;
+    }
+    assert_eq!(seen, [true, true]);
+
+    for &(lo, hi) in &[(0, 0), (1, 0), (i64::MAX, i64::MIN)] {
+        let err = call(&mut rt, "random_int", &[int(lo), int(hi)]).unwrap_err();
+        assert_eq!(kind(&err), "range");
+    }
+    let err = call(&mut rt, "random_int", &[int(2), int(1)]).unwrap_err();
+    assert_eq!(message(&err), "random_int expects a non-empty range, got 2 to 1");
+    for p in [Value::from(-0.1), Value::from(1.5), Value::from(f64::NAN), int(2)].iter() {
+        let err = call(&mut rt, "random_bool", std::slice::from_ref(p)).unwrap_err();
+        assert_eq!(kind(&err), "range", "{}", p);
+    }
+    assert_eq!(kind(&call(&mut rt, "random_int", &[int(0), s("1")]).unwrap_err()), "type");
+    assert_eq!(kind(&call(&mut rt, "random_seed", &[s("1")]).unwrap_err()), "type");
+}
+
+#[test]
+fn shuffles_are_permutations() {
+    let mut rt = Runtime::new_deterministic(2);
+    let original: Vec<i64> = (0..50).collect();
+    let a = ints(&original);
+    let mut orders = std::collections::BTreeSet::new();
+    for _ in 0..20 {
+        call(&mut rt, "shuffle", std::slice::from_ref(&a)).unwrap();
+        orders.insert(a.to_string());
+        let sorted = call(&mut rt, "sort", std::slice::from_ref(&a));
+        assert_eq!((sorted, &a), (Ok(Value::Nil), &ints(&original)));
+    }
+    assert_eq!(orders.len(), 20);
+
+    let empty = ints(&[]);
+    assert_eq!(call(&mut rt, "shuffle", std::slice::from_ref(&empty)), Ok(Value::Nil));
+    let frozen = ints(&[1, 2]);
+    call(&mut rt, "freeze", std::slice::from_ref(&frozen)).unwrap();
+    let err = call(&mut rt, "shuffle", std::slice::from_ref(&frozen)).unwrap_err();
+    assert_eq!(kind(&err), "frozen");
+    assert_eq!(kind(&call(&mut rt, "shuffle", &[s("ab")]).unwrap_err()), "type");
+}
+
+#[test]
+fn choices() {
+    let mut rt = Runtime::new_deterministic(3);
+    let a = arr(vec![s("x"), s("y"), s("z")]);
+    let mut seen = std::collections::BTreeSet::new();
+    for _ in 0..100 {
+        seen.insert(call(&mut rt, "choice", std::slice::from_ref(&a)).unwrap().to_string());
+    }
+    assert_eq!(seen.len(), 3);
+    let err = call(&mut rt, "choice", &[ints(&[])]).unwrap_err();
+    assert_eq!(kind(&err), "range");
+}
+
+#[test]
+fn ints_are_uniform() {
+    let mut rt = Runtime::new_deterministic(4);
+    // A chi-square test with 9 degrees of freedom. The critical value for a false alarm rate of
+    // 0.1% is 27.9, and the seed is fixed, so this can not flake.
+    let buckets = 10;
+    let samples = 100_000;
+    let mut counts = vec![0u32; buckets];
+    for _ in 0..samples {
+        let x = n(call(&mut rt, "random_int", &[int(0), int(buckets as i64)]).unwrap());
+        counts[x as usize] += 1;
+    }
+    let expected = samples as f64 / buckets as f64;
+    let chi_square: f64 =
+        counts.iter().map(|&c| (f64::from(c) - expected).powi(2) / expected).sum();
+    assert!(chi_square < 27.9, "{} {:?}", chi_square, counts);
+
+    // A range that is not a power of two, where a biased reduction would favour the low values.
+    let span = (1i64 << 62) + (1i64 << 61);
+    let low = (0..10_000)
+        .filter(|_| n(call(&mut rt, "random_int", &[int(0), int(span)]).unwrap()) < span / 2)
+        .count();
+    assert!((4700..5300).contains(&low), "{}", low);
+}
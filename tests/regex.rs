@@ -0,0 +1,213 @@
+#![cfg(feature = "regex")]
+
+mod common;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::types::regex::{Regex, RegexError};
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, field, int, kind, message, native, s};
+
+fn compile(rt: &mut Runtime, pattern: &str) -> Value {
+    call(rt, "re_compile", &[s(pattern)]).unwrap()
+}
+
+fn found(start: i64, end: i64, text: &str) -> Value {
+    Value::record(vec![("start", int(start)), ("end", int(end)), ("text", s(text))])
+}
+
+// The text of the leftmost match of the pattern, matched directly against the engine.
+fn leftmost(pattern: &str, text: &str) -> Option<String> {
+    let regex = Regex::new(pattern).unwrap_or_else(|err| panic!("{}: {}", pattern, err));
+    let chars: Vec<char> = text.chars().collect();
+    let range = regex.captures_at(&chars, 0)?[0].clone().unwrap();
+    Some(chars[range].iter().collect())
+}
+
+#[test]
+fn invalid_patterns_throw_parse_errors() {
+    let mut rt = Runtime::new();
+    let err = call(&mut rt, "re_compile", &[s("a(b")]).unwrap_err();
+    assert_eq!(kind(&err), "parse");
+    assert!(message(&err).starts_with("re_compile failed: "), "{}", message(&err));
+}
+
+#[test]
+fn offsets_are_char_indices() {
+    let mut rt = Runtime::new();
+    let re = compile(&mut rt, "c[a-zé]+");
+    let text = s("😀 äöü café cœur cure");
+    assert_eq!(call(&mut rt, "re_find", &[re.clone(), text.clone()]), Ok(found(6, 10, "café")));
+    let all = arr(vec![found(6, 10, "café"), found(16, 20, "cure")]);
+    assert_eq!(call(&mut rt, "re_find_all", &[re.clone(), text.clone()]), Ok(all));
+    assert_eq!(call(&mut rt, "re_is_match", &[re.clone(), text]), Ok(Value::Bool(true)));
+    assert_eq!(call(&mut rt, "re_find", &[re.clone(), s("ça")]), Ok(Value::Nil));
+    assert_eq!(call(&mut rt, "re_find_all", &[re, s("")]), Ok(arr(vec![])));
+}
+
+#[test]
+fn named_and_numbered_captures() {
+    let mut rt = Runtime::new();
+    let re = compile(&mut rt, r"(?P<key>\w+)=(?<value>\d+)?(x)?");
+    let captures = call(&mut rt, "re_captures", &[re.clone(), s("-- größe= ü=1 --")]).unwrap();
+    assert_eq!(field(&captures, "key"), s("größe"));
+    assert_eq!(field(&captures, "value"), Value::Nil);
+    match &captures {
+        Value::Map(map) => {
+            let map = map.borrow();
+            assert_eq!(map.get(&int(0)), Some(&s("größe=")));
+            assert_eq!(map.get(&int(1)), Some(&s("größe")));
+            assert_eq!(map.get(&int(2)), Some(&Value::Nil));
+            assert_eq!(map.get(&int(3)), Some(&Value::Nil));
+            assert_eq!(map.len(), 6);
+        }
+        other => panic!("not a map: {}", other),
+    }
+    assert_eq!(call(&mut rt, "re_captures", &[re, s("nothing")]), Ok(Value::Nil));
+}
+
+#[test]
+fn template_replacements() {
+    let mut rt = Runtime::new();
+    let re = compile(&mut rt, r"(?P<first>\w+) (\w+)");
+    let replace = |rt: &mut Runtime, template: &str| {
+        call(rt, "re_replace", &[re.clone(), s("hällo wörld, ab cd"), s(template)]).unwrap()
+    };
+    assert_eq!(replace(&mut rt, "$2 $first"), s("wörld hällo, cd ab"));
+    assert_eq!(replace(&mut rt, "${2}_${1}"), s("wörld_hällo, cd_ab"));
+    assert_eq!(replace(&mut rt, "$$1"), s("$1, $1"));
+    assert_eq!(replace(&mut rt, "[$missing$9]"), s("[], []"));
+    // `$first_` is a reference to a group named `first_`, which does not exist.
+    assert_eq!(replace(&mut rt, "$first_"), s(", "));
+}
+
+#[test]
+fn function_replacements() {
+    let mut rt = Runtime::new();
+    let re = compile(&mut rt, r"(?<n>\d+)");
+    let double = native(|args| match &field(&args[0], "n") {
+        Value::String(n) => Ok(s(&(n.to_string().parse::<i64>().unwrap() * 2).to_string())),
+        other => panic!("not a string: {}", other),
+    });
+    let replaced = call(&mut rt, "re_replace", &[re.clone(), s("é1 ü20 3"), double]);
+    assert_eq!(replaced, Ok(s("é2 ü40 6")));
+
+    // A throw stops the replacement after the throwing call.
+    let calls = Rc::new(Cell::new(0));
+    let thrower = {
+        let calls = calls.clone();
+        native(move |args| {
+            calls.set(calls.get() + 1);
+            if calls.get() == 2 { Err(field(&args[0], "n")) } else { Ok(s("x")) }
+        })
+    };
+    let thrown = call(&mut rt, "re_replace", &[re.clone(), s("1 2 3"), thrower]);
+    assert_eq!(thrown, Err(s("2")));
+    assert_eq!(calls.get(), 2);
+
+    let err = call(&mut rt, "re_replace", &[re, s("1"), native(|_| Ok(int(1)))]).unwrap_err();
+    assert_eq!(kind(&err), "type");
+}
+
+#[test]
+fn splitting() {
+    let mut rt = Runtime::new();
+    let re = compile(&mut rt, r"\s*,\s*");
+    let parts = arr(vec![s("ä"), s(""), s("ö"), s("")]);
+    assert_eq!(call(&mut rt, "re_split", &[re.clone(), s("ä ,, ö ,")]), Ok(parts));
+    assert_eq!(call(&mut rt, "re_split", &[re, s("")]), Ok(arr(vec![s("")])));
+    // Empty matches split between all chars.
+    let empty = compile(&mut rt, "");
+    let parts = arr(vec![s(""), s("é"), s("ü"), s("")]);
+    assert_eq!(call(&mut rt, "re_split", &[empty, s("éü")]), Ok(parts));
+}
+
+#[test]
+fn wrong_types() {
+    let mut rt = Runtime::new();
+    let re = compile(&mut rt, "a");
+    assert_eq!(kind(&call(&mut rt, "re_compile", &[int(1)]).unwrap_err()), "type");
+    for name in ["re_is_match", "re_find", "re_find_all", "re_captures", "re_split"].iter() {
+        let err = call(&mut rt, name, &[s("a"), s("a")]).unwrap_err();
+        assert_eq!(message(&err), format!("{} expects a regex, got string", name));
+        let err = call(&mut rt, name, &[re.clone(), int(1)]).unwrap_err();
+        assert_eq!(message(&err), format!("{} expects a string, got int", name));
+    }
+    let err = call(&mut rt, "re_replace", &[re, s("a"), Value::Nil]).unwrap_err();
+    assert_eq!(kind(&err), "type");
+}
+
+#[test]
+fn syntax() {
+    let cases: &[(&str, &str, Option<&str>)] = &[
+        ("a.c", "abc", Some("abc")),
+        ("a.c", "a\nc", None),
+        ("(?s)a.c", "a\nc", Some("a\nc")),
+        ("^b", "ab", None),
+        ("(?m)^b$", "a\nb\nc", Some("b")),
+        (r"\Ab", "ab", None),
+        (r"a\z", "a\n", None),
+        (r"\bfoo\b", "foobar foo", Some("foo")),
+        (r"\Boo", "foo", Some("oo")),
+        (r"\x41\x{1F600}", "A😀", Some("A😀")),
+        (r"\d+", "x٣4y", Some("٣4")),
+        (r"\w+", "-ß_1-", Some("ß_1")),
+        (r"\S+", "  é ", Some("é")),
+        ("[^a-c]+", "abcdeab", Some("de")),
+        (r"[\d_]+", "a1_2b", Some("1_2")),
+        ("[[:alpha:]]+", "12ab3", Some("ab")),
+        ("[a-]+", "x-a-y", Some("-a-")),
+        ("(?i)straße", "STRAßE", Some("STRAßE")),
+        ("(?i)σ", "Σ", Some("Σ")),
+        ("(?i:a)b", "Ab AB", Some("Ab")),
+        ("(?i)a(?-i)b", "AB Ab", Some("Ab")),
+        ("a|ab", "ab", Some("a")),
+        ("ab|a", "ab", Some("ab")),
+        ("a{2}", "aaa", Some("aa")),
+        ("a{2,}", "aaaa", Some("aaaa")),
+        ("a{1,2}?", "aaa", Some("a")),
+        ("a+?b", "aaab", Some("aaab")),
+        ("<.*?>", "<a><b>", Some("<a>")),
+        ("<.*>", "<a><b>", Some("<a><b>")),
+        ("(a*)*b", "aab", Some("aab")),
+        ("(?:ab)+", "ababa", Some("abab")),
+        (r"\.\*\$", "a.*$", Some(".*$")),
+    ];
+    for (pattern, text, expected) in cases {
+        let expected = expected.map(str::to_string);
+        assert_eq!(leftmost(pattern, text), expected, "{:?} in {:?}", pattern, text);
+    }
+}
+
+#[test]
+fn syntax_errors() {
+    let cases = [
+        ("a(b", RegexError::UnclosedGroup { offset: 1 }),
+        ("[ab", RegexError::UnclosedClass { offset: 0 }),
+        ("[z-a]", RegexError::InvalidRange { offset: 1 }),
+        ("*", RegexError::NothingToRepeat { offset: 0 }),
+        ("(?x)", RegexError::UnknownFlag { flag: 'x', offset: 2 }),
+        ("(?P<a>x)(?P<a>y)", RegexError::DuplicateGroupName { name: "a".to_string() }),
+        ("a{1001}", RegexError::InvalidRepetition { offset: 1 }),
+        (r"\q", RegexError::InvalidEscape { offset: 0 }),
+        ("a\\", RegexError::UnexpectedEnd),
+    ];
+    for (pattern, expected) in cases.iter() {
+        assert_eq!(Regex::new(pattern).unwrap_err(), *expected, "{:?}", pattern);
+    }
+    match Regex::new("(a{1000}){1000}") {
+        Err(RegexError::TooBig { .. }) => {}
+        other => panic!("not too big: {:?}", other),
+    }
+}
+
+#[test]
+fn matching_takes_linear_time() {
+    // Pathological for backtracking engines.
+    let text = "a".repeat(5000);
+    assert_eq!(leftmost("(a*)*b", &text), None);
+    assert_eq!(leftmost("(a|a)*$", &text), Some(text.clone()));
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::types::heap;
+use pan_lang_rs::types::owner::CrossRuntimeError;
+use pan_lang_rs::types::rooted::RootError;
+use pan_lang_rs::types::userdata::{FinalizeCtx, PanUserdata, Userdata};
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, int};
+
+/// Counts how many of its kind have been finalized.
+#[derive(Debug)]
+struct Tracked(Rc<Cell<usize>>);
+
+impl PanUserdata for Tracked {
+    fn type_name(&self) -> &'static str {
+        "tracked"
+    }
+
+    fn finalize(&mut self, _: &mut FinalizeCtx) {
+        self.0.set(self.0.get() + 1);
+    }
+}
+
+fn tracked(finalized: &Rc<Cell<usize>>) -> Value {
+    arr(vec![Value::Userdata(Userdata::new(Tracked(finalized.clone())))])
+}
+
+#[test]
+fn rooted_values_survive_collections() {
+    let mut rt = Runtime::new();
+    let finalized = Rc::new(Cell::new(0));
+    let rooted = rt.root(tracked(&finalized)).unwrap();
+    drop(tracked(&finalized));
+    assert_eq!(rt.rooted_count(), 1);
+    for _ in 0..3 {
+        rt.gc_collect();
+    }
+    // Only the unrooted twin is gone.
+    assert_eq!(finalized.get(), 1);
+    let v = rooted.get(&rt).unwrap();
+    assert_eq!(call(&mut rt, "len", &[v]), Ok(int(1)));
+    assert!(rooted.is_alive());
+    assert_eq!(rooted.runtime(), rt.id());
+
+    // Dropping the handle unroots the value.
+    drop(rooted);
+    assert_eq!(rt.rooted_count(), 0);
+    rt.gc_collect();
+    assert_eq!(finalized.get(), 2);
+}
+
+#[test]
+fn handles_outliving_their_runtime_are_poisoned() {
+    let finalized = Rc::new(Cell::new(0));
+    let rt = Runtime::new();
+    let first = rt.root(tracked(&finalized)).unwrap();
+    let second = rt.root(arr(vec![])).unwrap();
+    assert_eq!(rt.rooted_count(), 2);
+    drop(rt);
+    heap::collect();
+    // The runtime took the rooted values with it.
+    assert_eq!(finalized.get(), 1);
+
+    let other = Runtime::new();
+    assert!(!first.is_alive());
+    assert_eq!(first.get(&other).unwrap_err(), RootError::Poisoned);
+    let err = second.get(&other).unwrap_err();
+    assert_eq!(err.to_string(), "the runtime that rooted the value has been dropped");
+    assert!(format!("{:?}", first).contains("alive: false"));
+    // Dropping them afterwards is fine.
+    drop(first);
+    drop(second);
+    assert_eq!(other.rooted_count(), 0);
+}
+
+#[test]
+fn handles_belong_to_their_runtime() {
+    let mut a = Runtime::new();
+    let b = Runtime::new();
+    let rooted = a.root(int(1)).unwrap();
+    let expected = CrossRuntimeError { expected: b.id(), found: a.id() };
+    assert_eq!(rooted.get(&b).unwrap_err(), RootError::CrossRuntime(expected));
+    assert_eq!(rooted.get(&a), Ok(int(1)));
+
+    // Values of other runtimes can not be rooted.
+    let foreign = call(&mut a, "range", &[int(0), int(1)]).unwrap();
+    let expected = CrossRuntimeError { expected: b.id(), found: a.id() };
+    assert_eq!(b.root(foreign).unwrap_err(), expected);
+    assert_eq!(b.rooted_count(), 0);
+}
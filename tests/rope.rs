@@ -0,0 +1,399 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read};
+
+use pan_lang_rs::types::rope::{ReadError, Rope, RopeError};
+
+// A hasher that is not streaming: it remembers where each `write` started.
+#[derive(Default)]
+struct Writes(Vec<Vec<u8>>);
+
+impl Hasher for Writes {
+    fn finish(&self) -> u64 {
+        let mut state = DefaultHasher::new();
+        self.0.hash(&mut state);
+        state.finish()
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.push(bytes.to_vec());
+    }
+}
+
+fn hash_of(rope: &Rope) -> u64 {
+    let mut state = Writes::default();
+    rope.hash(&mut state);
+    state.finish()
+}
+
+fn text(len: usize) -> String {
+    (0..len).map(|i| (b'a' + (i % 26) as u8) as char).collect()
+}
+
+#[test]
+fn hash_does_not_depend_on_chunking() {
+    let s = text(10_000);
+    let whole = Rope::from_str(&s);
+    for &split in &[1, 63, 64, 65, 4097, 9_999] {
+        let parts = Rope::from_str(&s[..split]).concat(&Rope::from_str(&s[split..]));
+        assert_eq!(parts, whole);
+        assert_eq!(hash_of(&parts), hash_of(&whole), "split at {}", split);
+    }
+    let mut pieces = Rope::new();
+    for piece in s.as_bytes().chunks(777) {
+        pieces = pieces.concat(&Rope::from_str(std::str::from_utf8(piece).unwrap()));
+    }
+    assert!(pieces.chunks().count() > 1);
+    assert_eq!(hash_of(&pieces), hash_of(&whole));
+}
+
+#[test]
+fn hash_distinguishes_contents() {
+    assert_ne!(hash_of(&Rope::from_str("ab")), hash_of(&Rope::from_str("ba")));
+    assert_ne!(hash_of(&Rope::from_str("")), hash_of(&Rope::from_str("a")));
+    assert_ne!(hash_of(&Rope::from_str(&text(64))), hash_of(&Rope::from_str(&text(65))));
+}
+
+// Concatenates the parts as separate leaves, so that patterns can span chunk boundaries.
+fn chunked(parts: &[&str]) -> Rope {
+    let rope = parts
+        .iter()
+        .fold(Rope::new(), |acc, part| acc.concat(&Rope::from_str(part)));
+    assert_eq!(rope.chunks().count(), parts.len());
+    rope
+}
+
+#[test]
+fn starts_and_ends_with_across_chunks() {
+    let left = format!("{}ab", text(300));
+    let right = format!("cd{}", text(300));
+    let rope = chunked(&[&left, &right]);
+    let whole = format!("{}{}", left, right);
+
+    assert!(rope.starts_with(""));
+    assert!(rope.starts_with(&whole));
+    assert!(rope.starts_with(&whole[..303]));
+    assert!(!rope.starts_with(&format!("{}x", &whole[..303])));
+    assert!(!rope.starts_with(&format!("{}x", whole)));
+
+    assert!(rope.ends_with(""));
+    assert!(rope.ends_with(&whole));
+    assert!(rope.ends_with(&whole[299..]));
+    assert!(!rope.ends_with(&format!("x{}", &whole[299..])));
+    assert!(!rope.ends_with(&format!("x{}", whole)));
+
+    let unicode = Rope::from_str("ä€𝄞");
+    assert!(unicode.starts_with("ä€"));
+    assert!(unicode.ends_with("𝄞"));
+    assert!(!unicode.ends_with("ä"));
+}
+
+#[test]
+fn replace_matches_str_replace() {
+    let left = format!("{}ab", text(300));
+    let right = format!("cd{}", text(300));
+    let rope = chunked(&[&left, &right]);
+    let whole = format!("{}{}", left, right);
+
+    for &(from, to) in &[("abcd", "<>"), ("bc", ""), ("a", "ä€"), ("z", "zz")] {
+        let replaced = rope.replace(from, to, None).unwrap();
+        assert_eq!(replaced.to_string(), whole.replace(from, to), "{:?} -> {:?}", from, to);
+    }
+
+    let unicode = Rope::from_str("ä€ä€ä");
+    assert_eq!(unicode.replace("ä", "a", None).unwrap().to_string(), "a€a€a");
+    assert_eq!(unicode.replace("€ä", "", None).unwrap().to_string(), "ä");
+}
+
+#[test]
+fn replace_is_leftmost_non_overlapping() {
+    let rope = Rope::from_str("aaaaa");
+    assert_eq!(rope.replace("aa", "b", None).unwrap().to_string(), "bba");
+    assert_eq!("aaaaa".replace("aa", "b"), "bba");
+}
+
+#[test]
+fn replace_respects_max() {
+    let rope = Rope::from_str("a-a-a-a");
+    for max in 0..6 {
+        let replaced = rope.replace("a", "b", Some(max)).unwrap();
+        assert_eq!(replaced.to_string(), "a-a-a-a".replacen("a", "b", max), "max {}", max);
+    }
+}
+
+#[test]
+fn replace_without_matches_shares_the_rope() {
+    let rope = chunked(&[&text(300), &text(300)]);
+    let replaced = rope.replace("zyx", "abc", None).unwrap();
+    assert!(Rope::ptr_eq(&rope, &replaced));
+    let replaced = rope.replace("a", "b", Some(0)).unwrap();
+    assert!(Rope::ptr_eq(&rope, &replaced));
+}
+
+#[test]
+fn replace_rejects_empty_patterns() {
+    assert_eq!(
+        Rope::from_str("abc").replace("", "x", None),
+        Err(RopeError::EmptyPattern)
+    );
+}
+
+// A reader that hands out its input in reads of the given sizes, then whatever remains.
+struct Trickle<'a> {
+    input: &'a [u8],
+    sizes: Vec<usize>,
+}
+
+impl<'a> Read for Trickle<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = if self.sizes.is_empty() { self.input.len() } else { self.sizes.remove(0) };
+        let n = size.min(buf.len()).min(self.input.len());
+        buf[..n].copy_from_slice(&self.input[..n]);
+        self.input = &self.input[n..];
+        Ok(n)
+    }
+}
+
+#[test]
+fn display_and_write_to_stream_the_chunks() {
+    let rope = chunked(&[&text(300), "ä€𝄞", &text(500)]);
+    let expected = format!("{}ä€𝄞{}", text(300), text(500));
+    assert_eq!(format!("{}", rope), expected);
+    assert_eq!(format!("{}", Rope::new()), "");
+
+    let mut out = Vec::new();
+    rope.write_to(&mut out).unwrap();
+    assert_eq!(out, expected.as_bytes());
+}
+
+#[test]
+fn read_from_carries_split_chars() {
+    let input = "a€b𝄞";
+    // Split inside the three-byte `€` and inside the four-byte `𝄞`.
+    for sizes in [vec![2, 1, 3, 2], vec![1; 9], vec![4, 1]] {
+        let mut r = Trickle { input: input.as_bytes(), sizes };
+        assert_eq!(Rope::read_from(&mut r, 100).unwrap().to_string(), input);
+    }
+
+    let long = text(5_000);
+    let mut r = Trickle { input: long.as_bytes(), sizes: vec![] };
+    assert_eq!(Rope::read_from(&mut r, 5_000).unwrap().to_string(), long);
+}
+
+#[test]
+fn read_from_rejects_invalid_input() {
+    let mut r = Trickle { input: b"ab\xffcd", sizes: vec![1, 1, 1] };
+    match Rope::read_from(&mut r, 100) {
+        Err(ReadError::InvalidUtf8 { offset }) => assert_eq!(offset, 2),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    // An incomplete sequence at the end of the input.
+    let mut r = Trickle { input: &"a€".as_bytes()[..3], sizes: vec![] };
+    match Rope::read_from(&mut r, 100) {
+        Err(ReadError::InvalidUtf8 { offset }) => assert_eq!(offset, 1),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let mut r = Trickle { input: b"abcdef", sizes: vec![2, 2] };
+    match Rope::read_from(&mut r, 5) {
+        Err(ReadError::LimitExceeded { limit }) => assert_eq!(limit, 5),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+// A deterministic stream of pseudo-random numbers (a 64-bit linear congruential generator).
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        self.0 >> 33
+    }
+
+    // A string mixing ascii, latin, greek, cyrillic and a few length-changing chars.
+    fn string(&mut self, len: usize) -> String {
+        const SPECIAL: &[char] = &['ß', 'İ', 'ı', 'Σ', 'ς', 'ﬁ', 'ŉ', 'ǰ', ' ', '.'];
+        (0..len)
+            .map(|_| match self.next() % 5 {
+                0 => SPECIAL[(self.next() % SPECIAL.len() as u64) as usize],
+                1 => std::char::from_u32(0xC0 + (self.next() % 0x180) as u32).unwrap(),
+                2 => std::char::from_u32(0x391 + (self.next() % 0x70) as u32).unwrap_or('x'),
+                3 => std::char::from_u32(0x410 + (self.next() % 0x40) as u32).unwrap(),
+                _ => (b'A' + (self.next() % 58) as u8) as char,
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn case_conversion_changes_lengths() {
+    let rope = Rope::from_str("Straße");
+    assert_eq!(rope.to_uppercase().to_string(), "STRASSE");
+    assert_eq!(rope.to_lowercase().to_string(), "straße");
+    assert_eq!(rope.casefold().to_string(), "strasse");
+    assert!(rope.eq_ignore_case(&Rope::from_str("STRASSE")));
+    assert!(rope.eq_ignore_case(&Rope::from_str("strasse")));
+    assert!(!rope.eq_ignore_case(&Rope::from_str("strase")));
+
+    let turkish = Rope::from_str("İstanbul");
+    assert_eq!(turkish.to_lowercase().to_string(), "i\u{307}stanbul");
+    assert_eq!(turkish.to_lowercase().len_chars(), 9);
+    assert_eq!(Rope::from_str("ı").to_uppercase().to_string(), "I");
+    // Full, non-turkic folding: the dotless i stays distinct from i.
+    assert!(!Rope::from_str("ı").eq_ignore_case(&Rope::from_str("i")));
+
+    let greek = Rope::from_str("ΟΔΟΣ ΟΔΟΣ.");
+    assert_eq!(greek.to_lowercase().to_string(), "οδος οδος.");
+}
+
+#[test]
+fn case_conversion_agrees_with_str() {
+    let mut rng = Lcg(42);
+    for _ in 0..200 {
+        let len = 1 + (rng.next() % 100) as usize;
+        let s = rng.string(len);
+        let rope = Rope::from_str(&s);
+        assert_eq!(rope.to_lowercase().to_string(), s.to_lowercase(), "{:?}", s);
+        assert_eq!(rope.to_uppercase().to_string(), s.to_uppercase(), "{:?}", s);
+    }
+
+    let s = rng.string(5_000);
+    let split = s.char_indices().nth(2_000).unwrap().0;
+    let rope = Rope::from_str(&s[..split]).concat(&Rope::from_str(&s[split..]));
+    assert_eq!(rope.to_lowercase().to_string(), s.to_lowercase());
+    assert_eq!(rope.to_uppercase().to_string(), s.to_uppercase());
+}
+
+#[test]
+fn casefold_is_idempotent() {
+    let mut rng = Lcg(7);
+    for _ in 0..200 {
+        // The dotless i uppercases to an `I`, which folds to a dotted `i`.
+        let rope = Rope::from_str(&rng.string(50).replace('ı', ""));
+        let folded = rope.casefold();
+        assert_eq!(folded.casefold(), folded);
+        assert!(rope.eq_ignore_case(&folded));
+        assert!(rope.eq_ignore_case(&rope.to_uppercase()));
+        assert!(rope.to_lowercase().eq_ignore_case(&rope.to_uppercase()));
+    }
+}
+
+// A rope of randomly sized pieces over a small alphabet, together with its contents.
+fn random_rope(rng: &mut Lcg) -> (Rope, String) {
+    const ALPHABET: &[char] = &['a', 'b', 'ä', '€', '𝄞'];
+    let mut rope = Rope::new();
+    let mut s = String::new();
+    for _ in 0..rng.next() % 6 {
+        let len = (rng.next() % 700) as usize;
+        let piece: String = (0..len)
+            .map(|_| ALPHABET[(rng.next() % ALPHABET.len() as u64) as usize])
+            .collect();
+        rope = rope.concat(&Rope::from_str(&piece));
+        s.push_str(&piece);
+    }
+    (rope, s)
+}
+
+#[test]
+fn reverse_iteration_mirrors_forward_iteration() {
+    let mut rng = Lcg(348);
+    for _ in 0..300 {
+        let (rope, s) = random_rope(&mut rng);
+        assert!(rope.chars_rev().eq(s.chars().rev()));
+        let mut chunks: Vec<_> = rope.chunks().collect();
+        chunks.reverse();
+        assert_eq!(rope.chunks_rev().collect::<Vec<_>>(), chunks);
+        assert_eq!(rope.last_char(), s.chars().last());
+
+        let len = s.chars().count();
+        for &n in &[0, len.min(1), len / 2, len] {
+            let expected: String = s.chars().skip(len - n).collect();
+            assert_eq!(rope.slice_from_end(n).unwrap().to_string(), expected);
+        }
+        assert_eq!(
+            rope.slice_from_end(len + 1),
+            Err(RopeError::OutOfBounds { index: len + 1, len })
+        );
+    }
+}
+
+#[test]
+fn rfind_agrees_with_str() {
+    let mut rng = Lcg(1_000);
+    for _ in 0..300 {
+        let (rope, s) = random_rope(&mut rng);
+        for pat in &["a", "ab", "€a𝄞", "𝄞𝄞", "bbbb", "äää€", ""] {
+            let expected = s.rfind(pat).map(|offset| s[..offset].chars().count());
+            assert_eq!(rope.rfind(pat), expected, "{:?} in {:?}", pat, s);
+            let expected = s.find(pat).map(|offset| s[..offset].chars().count());
+            assert_eq!(rope.find(pat), expected, "{:?} in {:?}", pat, s);
+        }
+    }
+}
+
+#[test]
+fn line_index_handles_mixed_endings() {
+    let rope = Rope::from_str("ab\r\ncd\nä€x\r\n\nlast");
+    let index = rope.line_index();
+    assert_eq!(index.len_lines(), 5);
+    let lines: Vec<_> = (0..5).map(|n| index.line(n).unwrap().to_string()).collect();
+    assert_eq!(lines, ["ab", "cd", "ä€x", "", "last"]);
+    assert_eq!(index.line_range(2), Some(7..13));
+    assert_eq!(index.line_range(3), Some(15..15));
+    assert_eq!(index.line_range(4), Some(16..20));
+    assert_eq!(index.line_range(5), None);
+    assert_eq!(index.line(5), None);
+
+    assert_eq!(index.position(0).unwrap(), (0, 0));
+    // Terminators belong to the line they end.
+    assert_eq!(index.position(2).unwrap(), (0, 2));
+    assert_eq!(index.position(3).unwrap(), (0, 3));
+    assert_eq!(index.position(4).unwrap(), (1, 0));
+    assert_eq!(index.position(6).unwrap(), (1, 2));
+    // Columns count chars, not bytes.
+    assert_eq!(index.position(12).unwrap(), (2, 2));
+    assert_eq!(index.position(13).unwrap(), (2, 3));
+    assert_eq!(index.position(15).unwrap(), (3, 0));
+    assert_eq!(index.position(19).unwrap(), (4, 3));
+    assert_eq!(index.position(20).unwrap(), (4, 4));
+    assert_eq!(index.position(8), Err(RopeError::NotCharBoundary { offset: 8 }));
+    assert!(index.position(21).is_err());
+
+    for n in 0..5 {
+        assert_eq!(rope.line(n), index.line(n));
+    }
+    assert_eq!(rope.line(5), None);
+}
+
+#[test]
+fn line_index_of_trailing_terminators() {
+    let index = Rope::from_str("a\n").line_index();
+    assert_eq!(index.len_lines(), 2);
+    assert_eq!(index.line(1).unwrap().to_string(), "");
+    assert_eq!(index.position(2).unwrap(), (1, 0));
+
+    let index = Rope::from_str("").line_index();
+    assert_eq!(index.len_lines(), 1);
+    assert_eq!(index.line_range(0), Some(0..0));
+
+    let index = Rope::from_str("\r\n\r\n").line_index();
+    assert_eq!(index.len_lines(), 3);
+    assert_eq!(index.line_range(1), Some(2..2));
+}
+
+#[test]
+fn line_index_across_chunks() {
+    let first = format!("{}\r", text(300));
+    let second = format!("\n€{}\nend", text(300));
+    let rope = chunked(&[&first, &second]);
+    let index = rope.line_index();
+    assert_eq!(index.len_lines(), 3);
+    assert_eq!(index.line(0).unwrap().to_string(), text(300));
+    assert_eq!(index.line(1).unwrap().to_string(), format!("€{}", text(300)));
+    assert_eq!(index.position(302 + 3 + 5).unwrap(), (1, 6));
+    assert_eq!(rope.line(2).unwrap().to_string(), "end");
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use std::error::Error;
+
+use pan_lang_rs::runtime::{RunError, Runtime};
+use pan_lang_rs::types::heap::{self, OutOfMemory};
+use pan_lang_rs::types::exit;
+use pan_lang_rs::types::interrupt::{self, DeadlineExceeded};
+use pan_lang_rs::value::{Fun, Native, Value};
+
+use common::{arr, call, int, kind, s};
+
+// Fails with whatever the native throws, through `?`.
+fn run(rt: &mut Runtime, thrown: Value) -> Result<Value, Box<dyn Error>> {
+    let throws = Value::Fun(Fun::Native(Native::new("throws", move |_| Err(thrown.clone()))));
+    Ok(rt.call(&throws, &[])?)
+}
+
+fn nested(depth: usize) -> Value {
+    (0..depth).fold(int(0), |inner, _| arr(vec![inner]))
+}
+
+#[test]
+fn thrown_values_are_classified() {
+    let oom = OutOfMemory { used: 10, limit: 5 };
+    let exceeded = DeadlineExceeded { elapsed_millis: 12, limit_millis: 10 };
+    let cases = vec![
+        (exit::exit_value(3), "exited with code 3"),
+        (heap::out_of_memory_value(oom), "out of memory: used 10 bytes with a limit of 5 bytes"),
+        (interrupt::interrupted_value(), "interrupted"),
+        (
+            interrupt::deadline_exceeded_value(exceeded),
+            "deadline exceeded: ran for 12 ms with a limit of 10 ms",
+        ),
+        (Value::error("custom", "went wrong"), "custom: went wrong"),
+        (s("text"), "\"text\""),
+        (arr(vec![int(1), Value::Nil]), "[1, nil]"),
+    ];
+    for (value, display) in cases {
+        let err = RunError::from_thrown(value.clone());
+        assert_eq!(err.to_string(), display);
+        match (&err, err.thrown()) {
+            (RunError::Thrown { value: thrown, rendered }, Some(same)) => {
+                assert_eq!(thrown, &value);
+                assert_eq!(same, &value);
+                assert_eq!(rendered, display);
+            }
+            (RunError::Thrown { .. }, None) => unreachable!(),
+            (_, thrown) => assert_eq!(thrown, None, "{}", display),
+        }
+        // Converting back gives an equivalent value.
+        assert_eq!(RunError::from_thrown(err.clone().into_value()).to_string(), display);
+        assert_eq!(RunError::from_thrown(Value::from(err)).to_string(), display);
+    }
+    assert!(matches!(RunError::from_thrown(exit::exit_value(0)), RunError::Exit(0)));
+    let err = RunError::from_thrown(heap::out_of_memory_value(oom));
+    assert!(matches!(err, RunError::OutOfMemory(o) if o == oom));
+    assert!(matches!(RunError::from_thrown(interrupt::interrupted_value()), RunError::Interrupted));
+}
+
+#[test]
+fn errors_without_a_message_render_as_literals() {
+    // Maps with only a kind are not errors in the standard shape.
+    let partial = Value::record(vec![("kind", s("custom"))]);
+    let err = RunError::from_thrown(partial);
+    assert_eq!(err.to_string(), "{\"kind\": \"custom\"}");
+
+    // Deeply nested values are abbreviated.
+    let rendered = RunError::from_thrown(nested(100)).to_string();
+    assert!(rendered.len() < 50, "{}", rendered);
+    assert!(rendered.starts_with("[[[["), "{}", rendered);
+    assert_eq!(RunError::from_thrown(nested(2)).to_string(), "[[0]]");
+}
+
+#[test]
+fn errors_convert_into_boxed_errors() {
+    let mut rt = Runtime::new();
+    let err = run(&mut rt, Value::error("custom", "went wrong")).unwrap_err();
+    assert_eq!(err.to_string(), "custom: went wrong");
+    let err = err.downcast::<RunError>().unwrap();
+    assert_eq!(kind(err.thrown().unwrap()), "custom");
+
+    let err = run(&mut rt, exit::exit_value(7)).unwrap_err();
+    assert_eq!(err.to_string(), "exited with code 7");
+    assert!(matches!(*err.downcast::<RunError>().unwrap(), RunError::Exit(7)));
+
+    // Builtins fail the same way.
+    let len = rt.global("len").unwrap();
+    let err: Box<dyn Error> = rt.call(&len, &[int(1)]).unwrap_err().into();
+    assert!(err.to_string().starts_with("type: "), "{}", err);
+    assert!(err.source().is_none());
+}
+
+#[test]
+fn values_of_other_runtimes_are_reported() {
+    let mut a = Runtime::new();
+    let mut b = Runtime::new();
+    let owned = call(&mut a, "range", &[int(0), int(3)]).unwrap();
+    let len = b.global("len").unwrap();
+    let err = b.call(&len, std::slice::from_ref(&owned)).unwrap_err();
+    let cross = match &err {
+        RunError::CrossRuntime(cross) => *cross,
+        other => panic!("expected a cross-runtime error, got {:?}", other),
+    };
+    assert_eq!(err.to_string(), cross.to_string());
+    assert!(err.thrown().is_none());
+    let value = err.into_value();
+    assert_eq!(kind(&value), "runtime");
+    assert_eq!(call(&mut a, "len", &[owned]).unwrap(), int(3));
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use pan_lang_rs::host_io::SharedBuffer;
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, int, s};
+
+// An array that contains itself, which only a collection can free.
+fn cycle() -> Value {
+    let a = arr(vec![]);
+    if let Value::Array(inner) = &a {
+        inner.borrow_mut().push(a.clone());
+    }
+    a
+}
+
+#[test]
+fn runtimes_have_separate_bindings() {
+    let mut a = Runtime::new();
+    let b = Runtime::new();
+    a.define("answer", int(42)).unwrap();
+    a.register("only_in_a", |_| Ok(Value::Nil));
+    assert_eq!(a.global("answer"), Some(int(42)));
+    assert_eq!(b.global("answer"), None);
+    assert!(b.global("only_in_a").is_none());
+    // Replacing a builtin in one runtime leaves the other alone.
+    a.register("len", |_| Ok(int(-1)));
+    let mut b = b;
+    assert_eq!(call(&mut a, "len", &[arr(vec![])]), Ok(int(-1)));
+    assert_eq!(call(&mut b, "len", &[arr(vec![])]), Ok(int(0)));
+}
+
+#[test]
+fn runtimes_have_separate_generators_and_io() {
+    let draws = |rt: &mut Runtime| -> Vec<Value> {
+        (0..20).map(|_| call(rt, "random_int", &[int(0), int(1 << 50)]).unwrap()).collect()
+    };
+    let mut a = Runtime::new_deterministic(7);
+    let mut b = Runtime::new_deterministic(7);
+    let first = draws(&mut a);
+    // Drawing from `a` did not advance `b`.
+    assert_eq!(draws(&mut b), first);
+    assert_ne!(draws(&mut a), first);
+
+    let (out_a, out_b) = (SharedBuffer::new(), SharedBuffer::new());
+    a.set_stdout(Box::new(out_a.clone()));
+    b.set_stdout(Box::new(out_b.clone()));
+    call(&mut a, "print", &[s("a")]).unwrap();
+    call(&mut b, "print", &[s("b")]).unwrap();
+    assert_eq!((out_a.text(), out_b.text()), ("a".to_string(), "b".to_string()));
+}
+
+#[test]
+fn bindings_only_grow_with_new_names() {
+    let mut rt = Runtime::new();
+    let initial = rt.top_level_bindings().len();
+    for i in 0..1000 {
+        rt.define("counter", int(i)).unwrap();
+        call(&mut rt, "len", &[arr(vec![int(i)])]).unwrap();
+    }
+    assert_eq!(rt.top_level_bindings().len(), initial + 1);
+    assert_eq!(rt.global("counter"), Some(int(999)));
+    for name in ["a", "b", "c"].iter() {
+        rt.define(name, Value::Nil).unwrap();
+    }
+    assert_eq!(rt.top_level_bindings().len(), initial + 4);
+    let names: Vec<String> = rt.top_level_bindings().into_iter().map(|(name, _)| name).collect();
+    let mut sorted = names.clone();
+    sorted.sort();
+    assert_eq!(names, sorted);
+}
+
+#[test]
+fn top_level_bindings_show_definitions_and_constants() {
+    let mut rt = Runtime::new();
+    let binding = |rt: &Runtime, name: &str| {
+        rt.top_level_bindings().into_iter().find(|(n, _)| n == name).map(|(_, v)| v)
+    };
+    assert_eq!(binding(&rt, "answer"), None);
+    assert!(binding(&rt, "len").is_some());
+    rt.define("answer", int(42)).unwrap();
+    assert_eq!(binding(&rt, "answer"), Some(int(42)));
+
+    // A constant hides the global of the same name, which is listed once.
+    let count = rt.top_level_bindings().len();
+    rt.define_const("answer", int(7)).unwrap();
+    rt.define_const("VERSION", s("1.0")).unwrap();
+    assert_eq!(rt.top_level_bindings().len(), count + 1);
+    assert_eq!(binding(&rt, "answer"), Some(int(7)));
+    assert_eq!(binding(&rt, "VERSION"), Some(s("1.0")));
+}
+
+#[test]
+fn dropping_a_runtime_releases_its_values() {
+    let baseline = Runtime::new().gc_stats().live_collections;
+    {
+        let mut rt = Runtime::new();
+        rt.define("cyclic", cycle()).unwrap();
+        let captured = cycle();
+        rt.register("holds", move |_| Ok(captured.clone()));
+        let rooted = rt.root(cycle()).unwrap();
+        assert_eq!(rt.rooted_count(), 1);
+        drop(rooted);
+        assert!(rt.gc_stats().live_collections >= baseline + 3);
+    }
+    let rt = Runtime::new();
+    rt.gc_collect();
+    assert_eq!(rt.gc_stats().live_collections, baseline);
+}
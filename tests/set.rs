@@ -0,0 +1,155 @@
+mod common;
+
+use std::collections::BTreeSet;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, int, ints, kind};
+
+fn set(members: &[Value]) -> Value {
+    Value::set(members.iter().cloned().collect())
+}
+
+fn int_set(ns: &BTreeSet<i64>) -> Value {
+    Value::set(ns.iter().map(|n| int(*n)).collect())
+}
+
+const ALGEBRA: [&str; 4] = ["union", "intersection", "difference", "symmetric_difference"];
+const PREDICATES: [&str; 3] = ["is_subset", "is_superset", "is_disjoint"];
+
+#[test]
+fn membership() {
+    let mut rt = Runtime::new();
+    let s = set(&[]);
+    assert_eq!(call(&mut rt, "set_add", &[s.clone(), int(2)]), Ok(Value::Bool(true)));
+    assert_eq!(call(&mut rt, "set_add", &[s.clone(), ints(&[1, 2])]), Ok(Value::Bool(true)));
+    assert_eq!(call(&mut rt, "set_add", &[s.clone(), int(2)]), Ok(Value::Bool(false)));
+    assert_eq!(call(&mut rt, "set_add", &[s.clone(), int(-1)]), Ok(Value::Bool(true)));
+    // Freshly built arrays are the same member.
+    assert_eq!(call(&mut rt, "set_has", &[s.clone(), ints(&[1, 2])]), Ok(Value::Bool(true)));
+    assert_eq!(call(&mut rt, "set_add", &[s.clone(), ints(&[1, 2])]), Ok(Value::Bool(false)));
+    assert_eq!(call(&mut rt, "set_len", std::slice::from_ref(&s)), Ok(int(3)));
+    let members = arr(vec![int(-1), int(2), ints(&[1, 2])]);
+    assert_eq!(call(&mut rt, "set_to_array", std::slice::from_ref(&s)), Ok(members));
+    assert_eq!(call(&mut rt, "set_remove", &[s.clone(), ints(&[1, 2])]), Ok(Value::Bool(true)));
+    assert_eq!(call(&mut rt, "set_remove", &[s.clone(), ints(&[1, 2])]), Ok(Value::Bool(false)));
+    assert_eq!(call(&mut rt, "set_has", &[s.clone(), ints(&[1, 2])]), Ok(Value::Bool(false)));
+    assert_eq!(s, set(&[int(-1), int(2)]));
+}
+
+#[test]
+fn algebra_with_structural_members() {
+    let mut rt = Runtime::new();
+    let a = set(&[ints(&[1, 2]), int(1)]);
+    let b = set(&[ints(&[1, 2]), ints(&[3])]);
+    let both = set(&[ints(&[1, 2])]);
+    assert_eq!(call(&mut rt, "intersection", &[a.clone(), b.clone()]), Ok(both));
+    let all = set(&[int(1), ints(&[1, 2]), ints(&[3])]);
+    assert_eq!(call(&mut rt, "union", &[a.clone(), b.clone()]), Ok(all));
+    assert_eq!(call(&mut rt, "difference", &[a.clone(), b.clone()]), Ok(set(&[int(1)])));
+    assert_eq!(call(&mut rt, "is_disjoint", &[a, b]), Ok(Value::Bool(false)));
+}
+
+#[test]
+fn the_same_set_twice() {
+    let mut rt = Runtime::new();
+    let a = set(&[int(1), int(2)]);
+    let empty = set(&[]);
+    let expected = [a.clone(), a.clone(), empty.clone(), empty];
+    for (name, expected) in ALGEBRA.iter().zip(expected.iter()) {
+        assert_eq!(call(&mut rt, name, &[a.clone(), a.clone()]).as_ref(), Ok(expected), "{}", name);
+    }
+    for (name, expected) in PREDICATES.iter().zip([true, true, false].iter()) {
+        let result = call(&mut rt, name, &[a.clone(), a.clone()]);
+        assert_eq!(result, Ok(Value::Bool(*expected)), "{}", name);
+    }
+    // A set that is a member of itself.
+    call(&mut rt, "set_add", &[a.clone(), a.clone()]).unwrap();
+    assert_eq!(call(&mut rt, "union", &[a.clone(), a.clone()]).map(|u| u == a), Ok(true));
+    assert_eq!(call(&mut rt, "is_subset", &[a.clone(), a.clone()]), Ok(Value::Bool(true)));
+}
+
+#[test]
+fn empty_sets() {
+    let mut rt = Runtime::new();
+    let a = set(&[int(1)]);
+    let empty = set(&[]);
+    assert_eq!(call(&mut rt, "union", &[empty.clone(), a.clone()]), Ok(a.clone()));
+    assert_eq!(call(&mut rt, "intersection", &[a.clone(), empty.clone()]), Ok(empty.clone()));
+    assert_eq!(call(&mut rt, "difference", &[empty.clone(), a.clone()]), Ok(empty.clone()));
+    assert_eq!(call(&mut rt, "is_subset", &[empty.clone(), a.clone()]), Ok(Value::Bool(true)));
+    assert_eq!(call(&mut rt, "is_superset", &[empty.clone(), a.clone()]), Ok(Value::Bool(false)));
+    assert_eq!(call(&mut rt, "is_disjoint", &[empty.clone(), empty]), Ok(Value::Bool(true)));
+}
+
+#[test]
+fn algebra_agrees_with_btree_sets() {
+    let mut rt = Runtime::new();
+    let mut rng = Lcg(396);
+    for _ in 0..300 {
+        let mut random = || -> BTreeSet<i64> {
+            let len = rng.next() % 8;
+            (0..len).map(|_| (rng.next() % 10) as i64).collect()
+        };
+        let (a, b) = (random(), random());
+        let (pa, pb) = (int_set(&a), int_set(&b));
+        let expected = [
+            a.union(&b).cloned().collect(),
+            a.intersection(&b).cloned().collect(),
+            a.difference(&b).cloned().collect(),
+            a.symmetric_difference(&b).cloned().collect::<BTreeSet<_>>(),
+        ];
+        for (name, expected) in ALGEBRA.iter().zip(expected.iter()) {
+            let result = call(&mut rt, name, &[pa.clone(), pb.clone()]).unwrap();
+            assert_eq!(result, int_set(expected), "{} {:?} {:?}", name, a, b);
+        }
+        let expected = [a.is_subset(&b), a.is_superset(&b), a.is_disjoint(&b)];
+        for (name, expected) in PREDICATES.iter().zip(expected.iter()) {
+            let result = call(&mut rt, name, &[pa.clone(), pb.clone()]);
+            assert_eq!(result, Ok(Value::Bool(*expected)), "{} {:?} {:?}", name, a, b);
+        }
+        // The inputs are never mutated.
+        assert_eq!((pa, pb), (int_set(&a), int_set(&b)));
+    }
+}
+
+// A deterministic stream of pseudo-random numbers (a 64-bit linear congruential generator).
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        self.0 >> 33
+    }
+}
+
+#[test]
+fn results_are_new_sets() {
+    let mut rt = Runtime::new();
+    let a = set(&[int(1)]);
+    call(&mut rt, "freeze", std::slice::from_ref(&a)).unwrap();
+    assert_eq!(kind(&call(&mut rt, "set_add", &[a.clone(), int(2)]).unwrap_err()), "frozen");
+    assert_eq!(kind(&call(&mut rt, "set_remove", &[a.clone(), int(1)]).unwrap_err()), "frozen");
+    let union = call(&mut rt, "union", &[a.clone(), a.clone()]).unwrap();
+    assert_eq!(call(&mut rt, "set_add", &[union.clone(), int(2)]), Ok(Value::Bool(true)));
+    assert_eq!((a, union), (set(&[int(1)]), set(&[int(1), int(2)])));
+}
+
+#[test]
+fn wrong_types() {
+    let mut rt = Runtime::new();
+    let a = set(&[int(1)]);
+    for name in ALGEBRA.iter().chain(PREDICATES.iter()) {
+        let err = call(&mut rt, name, &[a.clone(), ints(&[1])]).unwrap_err();
+        assert_eq!(kind(&err), "type", "{}", name);
+        let err = call(&mut rt, name, &[ints(&[1]), a.clone()]).unwrap_err();
+        assert_eq!(kind(&err), "type", "{}", name);
+    }
+    for name in ["set_add", "set_remove", "set_has", "set_len", "set_to_array"].iter() {
+        assert_eq!(kind(&call(&mut rt, name, &[ints(&[1])]).unwrap_err()), "type", "{}", name);
+    }
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::types::userdata::{PanUserdata, Userdata};
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, int, s, same};
+
+fn names(rt: &Runtime) -> Vec<String> {
+    rt.top_level_bindings().into_iter().map(|(name, _)| name).collect()
+}
+
+#[derive(Debug)]
+struct Handle;
+
+impl PanUserdata for Handle {
+    fn type_name(&self) -> &'static str {
+        "handle"
+    }
+}
+
+#[test]
+fn mutations_and_new_bindings_are_reverted() {
+    let mut rt = Runtime::new();
+    let array = arr(vec![int(1)]);
+    let map = Value::map(vec![(s("k"), int(1))].into_iter().collect());
+    let set = Value::set(vec![int(1)].into_iter().collect());
+    rt.define("a", array.clone()).unwrap();
+    rt.define("m", map.clone()).unwrap();
+    rt.define("st", set.clone()).unwrap();
+    let before = names(&rt);
+    let snapshot = rt.snapshot();
+    assert!(snapshot.names().any(|name| name == "a"));
+
+    call(&mut rt, "push", &[array.clone(), int(2)]).unwrap();
+    call(&mut rt, "map_insert", &[map.clone(), s("j"), int(2)]).unwrap();
+    call(&mut rt, "set_add", &[set.clone(), int(2)]).unwrap();
+    rt.define("added", int(0)).unwrap();
+    assert_ne!(names(&rt), before);
+
+    rt.restore(&snapshot).unwrap();
+    assert_eq!(names(&rt), before);
+    assert_eq!(rt.global("a").unwrap(), arr(vec![int(1)]));
+    let restored = rt.global("m").unwrap();
+    assert_eq!(restored, Value::map(vec![(s("k"), int(1))].into_iter().collect()));
+    assert_eq!(rt.global("st").unwrap(), Value::set(vec![int(1)].into_iter().collect()));
+    assert!(rt.global("added").is_none());
+    // The originals are not touched by restoring.
+    assert_eq!(array, arr(vec![int(1), int(2)]));
+
+    // Mutating what was restored does not reach into the snapshot.
+    let restored = rt.global("a").unwrap();
+    call(&mut rt, "push", &[restored, int(3)]).unwrap();
+    rt.restore(&snapshot).unwrap();
+    assert_eq!(rt.global("a").unwrap(), arr(vec![int(1)]));
+}
+
+#[test]
+fn sharing_cycles_and_freezing_are_preserved() {
+    let mut rt = Runtime::new();
+    let shared = arr(vec![int(1)]);
+    let cyclic = arr(vec![]);
+    call(&mut rt, "push", &[cyclic.clone(), cyclic.clone()]).unwrap();
+    let frozen = arr(vec![int(5)]);
+    call(&mut rt, "freeze", std::slice::from_ref(&frozen)).unwrap();
+    rt.define("x", arr(vec![shared.clone(), shared.clone()])).unwrap();
+    rt.define("y", shared).unwrap();
+    rt.define("cyclic", cyclic).unwrap();
+    rt.define("frozen", frozen).unwrap();
+    let snapshot = rt.snapshot();
+    rt.restore(&snapshot).unwrap();
+
+    let x = rt.global("x").unwrap();
+    let y = rt.global("y").unwrap();
+    let first = call(&mut rt, "get", &[x.clone(), int(0)]).unwrap();
+    let second = call(&mut rt, "get", &[x, int(1)]).unwrap();
+    assert!(same(&first, &second) && same(&first, &y));
+
+    let cyclic = rt.global("cyclic").unwrap();
+    let inner = call(&mut rt, "get", &[cyclic.clone(), int(0)]).unwrap();
+    assert!(same(&cyclic, &inner));
+
+    let frozen = rt.global("frozen").unwrap();
+    assert_eq!(call(&mut rt, "is_frozen", &[frozen]).unwrap(), Value::Bool(true));
+}
+
+#[test]
+fn natives_and_userdata_survive_restoring() {
+    let mut rt = Runtime::new();
+    let handle = Value::Userdata(Userdata::new(Handle));
+    rt.define("handle", handle.clone()).unwrap();
+    let snapshot = rt.snapshot();
+    rt.register("later", |_| Ok(int(7)));
+    rt.define("value_later", int(8)).unwrap();
+    rt.restore(&snapshot).unwrap();
+
+    // Natives registered after the snapshot are kept, other bindings are not.
+    assert_eq!(call(&mut rt, "later", &[]).unwrap(), int(7));
+    assert!(rt.global("value_later").is_none());
+    assert_eq!(call(&mut rt, "len", &[arr(vec![int(1)])]).unwrap(), int(1));
+    // Userdata is shared rather than copied.
+    match (&rt.global("handle").unwrap(), &handle) {
+        (Value::Userdata(a), Value::Userdata(b)) => assert!(Userdata::ptr_eq(a, b)),
+        other => panic!("not userdata: {:?}", other),
+    }
+}
+
+#[test]
+fn snapshots_belong_to_their_runtime() {
+    let a = Runtime::new();
+    let mut b = Runtime::new();
+    let snapshot = a.snapshot();
+    let before = names(&b);
+    let err = b.restore(&snapshot).unwrap_err();
+    assert!(err.to_string().contains("can not be used in runtime"), "{}", err);
+    assert_eq!(names(&b), before);
+}
@@ -0,0 +1,343 @@
+mod common;
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, field, int, ints, kind, native, s};
+
+fn pair(key: i64, tag: &str) -> Value {
+    arr(vec![int(key), s(tag)])
+}
+
+fn first(v: &Value) -> i64 {
+    match v {
+        Value::Array(a) => match a.borrow()[0] {
+            Value::Int(n) => n,
+            ref other => panic!("not an int: {}", other),
+        },
+        other => panic!("not an array: {}", other),
+    }
+}
+
+// Compares pairs by their first element only.
+fn by_key() -> Value {
+    native(|args| Ok(int((first(&args[0]) - first(&args[1])).signum())))
+}
+
+#[test]
+fn sorts_structurally() {
+    let mut rt = Runtime::new();
+    let a = arr(vec![s("b"), int(2), Value::Nil, s("a"), int(-1), Value::Bool(true)]);
+    assert_eq!(call(&mut rt, "sort", std::slice::from_ref(&a)), Ok(Value::Nil));
+    let sorted = arr(vec![Value::Nil, Value::Bool(true), int(-1), int(2), s("a"), s("b")]);
+    assert_eq!(a, sorted);
+    assert_eq!(call(&mut rt, "is_sorted", &[a]), Ok(Value::Bool(true)));
+}
+
+#[test]
+fn sorts_are_stable() {
+    let mut rt = Runtime::new();
+    let pairs = |tags: &str, keys: &[i64]| {
+        arr(keys.iter().zip(tags.split(' ')).map(|(key, tag)| pair(*key, tag)).collect())
+    };
+    let unsorted = || pairs("a b c d e f", &[2, 1, 2, 1, 0, 2]);
+    let expected = pairs("e b d a c f", &[0, 1, 1, 2, 2, 2]);
+
+    let a = unsorted();
+    call(&mut rt, "sort_by", &[a.clone(), by_key()]).unwrap();
+    assert_eq!(a, expected);
+
+    let a = unsorted();
+    let key = native(|args| Ok(int(first(&args[0]))));
+    call(&mut rt, "sort_by_key", &[a.clone(), key]).unwrap();
+    assert_eq!(a, expected);
+
+    // A comparator that considers everything equal keeps the order.
+    let a = unsorted();
+    call(&mut rt, "sort_by", &[a.clone(), native(|_| Ok(int(0)))]).unwrap();
+    assert_eq!(a, unsorted());
+}
+
+#[test]
+fn comparators_may_return_any_int() {
+    let mut rt = Runtime::new();
+    let descending = native(|args| match (&args[0], &args[1]) {
+        (Value::Int(a), Value::Int(b)) => Ok(int(if a > b { i64::MIN } else { i64::MAX })),
+        _ => unreachable!(),
+    });
+    let a = ints(&[3, 1, 4, 1, 5, 9, 2, 6]);
+    call(&mut rt, "sort_by", &[a.clone(), descending]).unwrap();
+    assert_eq!(a, ints(&[9, 6, 5, 4, 3, 2, 1, 1]));
+
+    let err = call(&mut rt, "sort_by", &[a, native(|_| Ok(Value::Bool(true)))]).unwrap_err();
+    assert_eq!(kind(&err), "type");
+}
+
+#[test]
+fn key_functions_are_called_once_per_element_in_order() {
+    let mut rt = Runtime::new();
+    let seen = Rc::new(RefCell::new(vec![]));
+    let key = {
+        let seen = seen.clone();
+        native(move |args| {
+            seen.borrow_mut().push(args[0].clone());
+            Ok(int(-first(&args[0])))
+        })
+    };
+    let a = arr(vec![pair(1, "a"), pair(3, "b"), pair(2, "c")]);
+    call(&mut rt, "sort_by_key", &[a.clone(), key]).unwrap();
+    assert_eq!(*seen.borrow(), vec![pair(1, "a"), pair(3, "b"), pair(2, "c")]);
+    assert_eq!(a, arr(vec![pair(3, "b"), pair(2, "c"), pair(1, "a")]));
+}
+
+#[test]
+fn throws_leave_the_array_unchanged() {
+    let mut rt = Runtime::new();
+    let calls = Rc::new(Cell::new(0));
+    let thrower = {
+        let calls = calls.clone();
+        native(move |args| {
+            calls.set(calls.get() + 1);
+            if calls.get() == 3 {
+                Err(arr(vec![s("thrown"), args[0].clone()]))
+            } else {
+                Ok(int(0))
+            }
+        })
+    };
+    let a = ints(&[5, 4, 3, 2, 1]);
+    let err = call(&mut rt, "sort_by", &[a.clone(), thrower.clone()]).unwrap_err();
+    match &err {
+        Value::Array(thrown) => assert_eq!(thrown.borrow()[0], s("thrown")),
+        other => panic!("not the thrown value: {}", other),
+    }
+    assert_eq!(calls.get(), 3);
+    assert_eq!(a, ints(&[5, 4, 3, 2, 1]));
+
+    calls.set(0);
+    let err = call(&mut rt, "sort_by_key", &[a.clone(), thrower]).unwrap_err();
+    assert!(matches!(err, Value::Array(_)));
+    assert_eq!(a, ints(&[5, 4, 3, 2, 1]));
+}
+
+#[test]
+fn comparators_see_the_original_and_their_changes_are_overwritten() {
+    let mut rt = Runtime::new();
+    let a = ints(&[3, 1, 2]);
+    let observed = Rc::new(RefCell::new(vec![]));
+    let mutating = {
+        let (a, observed) = (a.clone(), observed.clone());
+        native(move |args| {
+            if let Value::Array(arr) = &a {
+                observed.borrow_mut().push(Value::Array(arr.clone()).to_string());
+                arr.borrow_mut().push(int(100));
+                arr.borrow_mut().remove(0);
+            }
+            match (&args[0], &args[1]) {
+                (Value::Int(x), Value::Int(y)) => Ok(int(x - y)),
+                _ => unreachable!(),
+            }
+        })
+    };
+    call(&mut rt, "sort_by", &[a.clone(), mutating]).unwrap();
+    assert_eq!(a, ints(&[1, 2, 3]));
+    // The first call sees the array as it was passed in.
+    assert_eq!(observed.borrow()[0], "[3, 1, 2]");
+}
+
+#[test]
+fn inconsistent_comparators_terminate() {
+    let mut rt = Runtime::new();
+    let flip = Rc::new(Cell::new(false));
+    let inconsistent = native(move |_| {
+        flip.set(!flip.get());
+        Ok(int(if flip.get() { -1 } else { 1 }))
+    });
+    let elements: Vec<i64> = (0..1000).collect();
+    let a = ints(&elements);
+    call(&mut rt, "sort_by", &[a.clone(), inconsistent]).unwrap();
+    // The order is unspecified, but no element is lost or duplicated.
+    call(&mut rt, "sort", std::slice::from_ref(&a)).unwrap();
+    assert_eq!(a, ints(&elements));
+}
+
+#[test]
+fn frozen_arrays_are_not_sorted() {
+    let mut rt = Runtime::new();
+    let a = ints(&[2, 1]);
+    call(&mut rt, "freeze", std::slice::from_ref(&a)).unwrap();
+    assert_eq!(kind(&call(&mut rt, "sort", std::slice::from_ref(&a)).unwrap_err()), "frozen");
+
+    // An array frozen by its comparator stays as it was.
+    let b = ints(&[2, 1]);
+    let freezing = {
+        let b = b.clone();
+        let freeze = rt.global("freeze").unwrap();
+        native(move |_| {
+            freeze.apply(std::slice::from_ref(&b))?;
+            Ok(int(1))
+        })
+    };
+    let err = call(&mut rt, "sort_by", &[b.clone(), freezing]).unwrap_err();
+    assert_eq!(kind(&err), "frozen");
+    assert_eq!(b, ints(&[2, 1]));
+}
+
+#[test]
+fn large_arrays_sort_quickly() {
+    let mut rt = Runtime::new();
+    // A pseudo-random permutation with many equal elements.
+    let elements: Vec<i64> = (0..100_000i64).map(|i| (i * 7919) % 1009).collect();
+    let a = ints(&elements);
+    let b = ints(&elements);
+    let start = std::time::Instant::now();
+    call(&mut rt, "sort", std::slice::from_ref(&a)).unwrap();
+    let compare = rt.global("compare").unwrap();
+    call(&mut rt, "sort_by", &[b.clone(), compare]).unwrap();
+    assert!(start.elapsed() < std::time::Duration::from_secs(10));
+    let mut expected = elements;
+    expected.sort();
+    assert_eq!(a, ints(&expected));
+    assert_eq!(b, ints(&expected));
+}
+
+// Where a search found the needle: `Ok(index)` or `Err(insert_at)`.
+fn searched(result: Value) -> Result<i64, i64> {
+    let index = |name| match field(&result, name) {
+        Value::Int(i) => i,
+        other => panic!("not an index: {}", other),
+    };
+    match field(&result, "found") {
+        Value::Bool(true) => Ok(index("index")),
+        Value::Bool(false) => Err(index("insert_at")),
+        other => panic!("not a search result: {}", other),
+    }
+}
+
+// A probe for `binary_search_by` that looks for the int `needle`.
+fn probe(needle: i64) -> Value {
+    native(move |args| match args[0] {
+        Value::Int(n) => Ok(int((n - needle).signum())),
+        ref other => panic!("not an int: {}", other),
+    })
+}
+
+#[test]
+fn binary_searches_at_the_ends() {
+    let mut rt = Runtime::new();
+    let a = ints(&[10, 20, 30]);
+    let cases = [(10, Ok(0)), (30, Ok(2)), (5, Err(0)), (35, Err(3)), (25, Err(2))];
+    for &(needle, expected) in &cases {
+        let result = call(&mut rt, "binary_search", &[a.clone(), int(needle)]).unwrap();
+        assert_eq!(searched(result), expected, "{}", needle);
+        let result = call(&mut rt, "binary_search_by", &[a.clone(), probe(needle)]).unwrap();
+        assert_eq!(searched(result), expected, "{}", needle);
+    }
+    let small = [(ints(&[]), 1, Err(0)), (ints(&[1]), 1, Ok(0)), (ints(&[1]), 2, Err(1))];
+    for (a, needle, expected) in small {
+        let result = call(&mut rt, "binary_search", &[a.clone(), int(needle)]).unwrap();
+        assert_eq!(searched(result), expected);
+        let result = call(&mut rt, "binary_search_by", &[a, probe(needle)]).unwrap();
+        assert_eq!(searched(result), expected);
+    }
+    // Structural order across types: all ints are less than all strings.
+    let mixed = arr(vec![int(1), s("a")]);
+    let result = call(&mut rt, "binary_search", &[mixed, int(7)]).unwrap();
+    assert_eq!(searched(result), Err(1));
+}
+
+#[test]
+fn binary_searches_agree_with_a_linear_scan() {
+    let mut rt = Runtime::new();
+    let mut rng = Lcg(394);
+    for _ in 0..200 {
+        let len = (rng.next() % 20) as usize;
+        let mut elements: Vec<i64> = (0..len).map(|_| (rng.next() % 16) as i64).collect();
+        elements.sort();
+        let a = ints(&elements);
+        for needle in -1..17 {
+            let insert_at = elements.iter().take_while(|&&e| e < needle).count() as i64;
+            let matches: Vec<i64> = (0..len as i64)
+                .filter(|&i| elements[i as usize] == needle)
+                .collect();
+            let by_order = call(&mut rt, "binary_search", &[a.clone(), int(needle)]).unwrap();
+            let by_probe = call(&mut rt, "binary_search_by", &[a.clone(), probe(needle)]).unwrap();
+            for result in [by_order, by_probe] {
+                // Any of several equal elements may be found.
+                match searched(result) {
+                    Ok(i) => assert!(matches.contains(&i), "{:?} {}", elements, needle),
+                    Err(i) => assert_eq!((i, matches.len()), (insert_at, 0)),
+                }
+            }
+        }
+    }
+}
+
+// A deterministic stream of pseudo-random numbers (a 64-bit linear congruential generator).
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        self.0 >> 33
+    }
+}
+
+#[test]
+fn probes_may_throw_and_mutate() {
+    let mut rt = Runtime::new();
+    let a = ints(&[1, 2, 3, 4]);
+    let thrower = native(|args| Err(args[0].clone()));
+    assert_eq!(call(&mut rt, "binary_search_by", &[a.clone(), thrower.clone()]), Err(int(3)));
+    assert_eq!(call(&mut rt, "is_sorted_by", &[a.clone(), thrower]), Err(int(1)));
+
+    let shrinking = {
+        let a = a.clone();
+        native(move |_| {
+            if let Value::Array(arr) = &a {
+                arr.borrow_mut().clear();
+            }
+            Ok(int(-1))
+        })
+    };
+    let err = call(&mut rt, "binary_search_by", &[a, shrinking]).unwrap_err();
+    assert_eq!(kind(&err), "range");
+}
+
+#[test]
+fn sortedness() {
+    let mut rt = Runtime::new();
+    let sorted = |rt: &mut Runtime, a: &[i64]| call(rt, "is_sorted", &[ints(a)]);
+    assert_eq!(sorted(&mut rt, &[]), Ok(Value::Bool(true)));
+    assert_eq!(sorted(&mut rt, &[1]), Ok(Value::Bool(true)));
+    assert_eq!(sorted(&mut rt, &[1, 1, 2]), Ok(Value::Bool(true)));
+    assert_eq!(sorted(&mut rt, &[2, 1]), Ok(Value::Bool(false)));
+
+    let calls = Rc::new(Cell::new(0));
+    let descending = {
+        let calls = calls.clone();
+        native(move |args| {
+            calls.set(calls.get() + 1);
+            Ok(int((first_int(&args[1]) - first_int(&args[0])).signum()))
+        })
+    };
+    let a = ints(&[3, 2, 2, 5, 1]);
+    assert_eq!(call(&mut rt, "is_sorted_by", &[a, descending.clone()]), Ok(Value::Bool(false)));
+    // Stops at the first pair out of order.
+    assert_eq!(calls.get(), 3);
+    let a = ints(&[3, 2, 2]);
+    assert_eq!(call(&mut rt, "is_sorted_by", &[a, descending]), Ok(Value::Bool(true)));
+}
+
+fn first_int(v: &Value) -> i64 {
+    match v {
+        Value::Int(n) => *n,
+        other => panic!("not an int: {}", other),
+    }
+}
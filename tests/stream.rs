@@ -0,0 +1,157 @@
+mod common;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use gc::{Finalize, Trace, unsafe_empty_trace};
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::types::futures::{Clock, Future, ManualClock};
+use pan_lang_rs::types::stream::{self, PanStream, Stream};
+use pan_lang_rs::value::{Fun, Native, Value};
+
+use common::{arr, call, int, kind, s};
+
+fn next(rt: &mut Runtime, stream: &Value) -> Result<Value, Value> {
+    let fut = call(rt, "stream_next", std::slice::from_ref(stream)).unwrap();
+    rt.block_on_raw(&fut)
+}
+
+fn inc() -> Value {
+    Value::Fun(Fun::Native(Native::new("inc", |args| match &args[0] {
+        Value::Int(n) => Ok(int(n + 1)),
+        other => panic!("not an int: {:?}", other),
+    })))
+}
+
+fn run(rt: &Runtime, fut: &Value) {
+    match fut {
+        Value::Future(fut) => drop(rt.event_loop().run_future(fut).unwrap()),
+        other => panic!("not a future: {:?}", other),
+    }
+}
+
+#[test]
+fn array_streams_drain_in_order() {
+    let mut rt = Runtime::new();
+    let st = call(&mut rt, "stream_from_array", &[arr(vec![int(1), s("two"), int(3)])]).unwrap();
+    assert_eq!(next(&mut rt, &st), Ok(stream::item(int(1))));
+    assert_eq!(next(&mut rt, &st), Ok(stream::item(s("two"))));
+    assert_eq!(next(&mut rt, &st), Ok(stream::item(int(3))));
+    // Exhausted streams stay done.
+    assert_eq!(next(&mut rt, &st), Ok(stream::done()));
+    assert_eq!(next(&mut rt, &st), Ok(stream::done()));
+
+    assert_eq!(kind(&call(&mut rt, "stream_from_array", &[int(1)]).unwrap_err()), "type");
+    assert_eq!(kind(&call(&mut rt, "stream_next", &[int(1)]).unwrap_err()), "type");
+}
+
+#[test]
+fn mapping_an_interval_on_a_manual_clock() {
+    let clock = Rc::new(ManualClock::new());
+    let mut rt = Runtime::with_clock(clock.clone());
+    let ticks = call(&mut rt, "stream_interval", &[int(10)]).unwrap();
+    let mapped = call(&mut rt, "stream_map", &[ticks, inc()]).unwrap();
+    for i in 1..=3 {
+        assert_eq!(next(&mut rt, &mapped), Ok(stream::item(int(i))));
+        assert_eq!(clock.now(), 10 * i as u64);
+    }
+
+    // A throwing function rejects the future for the item.
+    let st = call(&mut rt, "stream_from_array", &[arr(vec![s("x")])]).unwrap();
+    let throwing = Value::Fun(Fun::Native(Native::new("throw", |args| Err(args[0].clone()))));
+    let mapped = call(&mut rt, "stream_map", &[st, throwing]).unwrap();
+    assert_eq!(next(&mut rt, &mapped), Err(s("x")));
+}
+
+#[test]
+fn take_cancels_its_source_afterwards() {
+    let mut rt = Runtime::new_deterministic(382);
+    let ticks = call(&mut rt, "stream_interval", &[int(10)]).unwrap();
+    let taken = call(&mut rt, "stream_take", &[ticks.clone(), int(3)]).unwrap();
+    for i in 0..3 {
+        assert_eq!(next(&mut rt, &taken), Ok(stream::item(int(i))));
+    }
+    assert_eq!(next(&mut rt, &taken), Ok(stream::done()));
+    assert_eq!(next(&mut rt, &ticks), Ok(stream::done()));
+    assert_eq!(rt.event_loop().now(), 30);
+
+    let st = call(&mut rt, "stream_from_array", &[arr(vec![int(1)])]).unwrap();
+    let nothing = call(&mut rt, "stream_take", &[st, int(0)]).unwrap();
+    assert_eq!(next(&mut rt, &nothing), Ok(stream::done()));
+}
+
+#[test]
+fn cancelled_next_futures_lose_no_items() {
+    let mut rt = Runtime::new_deterministic(382);
+    let st = call(&mut rt, "stream_from_array", &[arr(vec![int(1), int(2)])]).unwrap();
+    let never_run = call(&mut rt, "stream_next", std::slice::from_ref(&st)).unwrap();
+    drop(never_run);
+    let cancelled = call(&mut rt, "stream_next", std::slice::from_ref(&st)).unwrap();
+    run(&rt, &cancelled);
+    assert_eq!(call(&mut rt, "fut_cancel", &[cancelled]), Ok(Value::Bool(true)));
+    assert_eq!(next(&mut rt, &st), Ok(stream::item(int(1))));
+
+    let ch = call(&mut rt, "chan_new", &[int(0)]).unwrap();
+    let st = call(&mut rt, "stream_from_channel", std::slice::from_ref(&ch)).unwrap();
+    let pending = call(&mut rt, "stream_next", std::slice::from_ref(&st)).unwrap();
+    run(&rt, &pending);
+    rt.event_loop().run_until_idle();
+    assert_eq!(call(&mut rt, "fut_cancel", &[pending]), Ok(Value::Bool(true)));
+    let sent = call(&mut rt, "chan_send", &[ch.clone(), int(7)]).unwrap();
+    let received = call(&mut rt, "stream_next", std::slice::from_ref(&st)).unwrap();
+    let both = call(&mut rt, "fut_all", &[arr(vec![sent, received])]).unwrap();
+    assert_eq!(rt.block_on_raw(&both), Ok(arr(vec![Value::Nil, stream::item(int(7))])));
+    call(&mut rt, "chan_close", &[ch]).unwrap();
+    assert_eq!(next(&mut rt, &st), Ok(stream::done()));
+}
+
+// A stream of the numbers counting down from its start, which records being cancelled.
+struct Countdown {
+    left: i64,
+    cancelled: Rc<Cell<bool>>,
+}
+
+impl Finalize for Countdown {}
+unsafe impl Trace for Countdown {
+    unsafe_empty_trace!();
+}
+
+impl PanStream for Countdown {
+    fn next(&mut self) -> Future {
+        if self.left == 0 || self.cancelled.get() {
+            return Future::resolve(stream::done());
+        }
+        self.left -= 1;
+        Future::resolve(stream::item(int(self.left)))
+    }
+
+    fn cancel(&mut self) {
+        self.cancelled.set(true);
+    }
+}
+
+#[test]
+fn rust_streams_combine_with_pan_streams() {
+    let mut rt = Runtime::new();
+    let cancelled = Rc::new(Cell::new(false));
+    let countdown = Stream::new(Countdown { left: 5, cancelled: cancelled.clone() });
+    let mapped = call(&mut rt, "stream_map", &[Value::Stream(countdown), inc()]).unwrap();
+    let taken = call(&mut rt, "stream_take", &[mapped, int(2)]).unwrap();
+    assert_eq!(next(&mut rt, &taken), Ok(stream::item(int(5))));
+    assert!(!cancelled.get());
+    assert_eq!(next(&mut rt, &taken), Ok(stream::item(int(4))));
+    assert!(cancelled.get());
+    assert_eq!(next(&mut rt, &taken), Ok(stream::done()));
+}
+
+#[test]
+fn unrun_items_are_released_when_the_thread_exits() {
+    let thread = std::thread::spawn(|| {
+        let mut rt = Runtime::new();
+        let st = call(&mut rt, "stream_from_array", &[arr(vec![int(1)])]).unwrap();
+        let unrun = call(&mut rt, "stream_next", &[st]).unwrap();
+        Box::leak(Box::new(unrun));
+    });
+    assert!(thread.join().is_ok());
+}
@@ -0,0 +1,191 @@
+mod common;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, field, int, kind, message, s};
+
+#[test]
+fn lengths_count_chars_and_bytes() {
+    let mut rt = Runtime::new();
+    assert_eq!(call(&mut rt, "str_len", &[s("héllo 🌍")]), Ok(int(7)));
+    assert_eq!(call(&mut rt, "str_byte_len", &[s("héllo 🌍")]), Ok(int(11)));
+    assert_eq!(call(&mut rt, "str_len", &[s("")]), Ok(int(0)));
+}
+
+#[test]
+fn get_and_slice_use_char_indices() {
+    let mut rt = Runtime::new();
+    let text = s("añb🌍c");
+    assert_eq!(call(&mut rt, "str_get", &[text.clone(), int(1)]), Ok(Value::Char('ñ')));
+    assert_eq!(call(&mut rt, "str_get", &[text.clone(), int(3)]), Ok(Value::Char('🌍')));
+    assert_eq!(call(&mut rt, "str_slice", &[text.clone(), int(1), int(4)]), Ok(s("ñb🌍")));
+    assert_eq!(call(&mut rt, "str_slice", &[text.clone(), int(3)]), Ok(s("🌍c")));
+    assert_eq!(call(&mut rt, "str_slice", &[text.clone(), int(5), int(5)]), Ok(s("")));
+}
+
+#[test]
+fn out_of_range_indices_throw() {
+    let mut rt = Runtime::new();
+    let text = s("añb");
+    let err = call(&mut rt, "str_get", &[text.clone(), int(3)]).unwrap_err();
+    assert_eq!(kind(&err), "range");
+    assert_eq!(
+        message(&err),
+        "str_get failed: index 3 is out of bounds for a string of length 3",
+    );
+    let err = call(&mut rt, "str_slice", &[text.clone(), int(1), int(4)]).unwrap_err();
+    assert_eq!(kind(&err), "range");
+    let err = call(&mut rt, "str_slice", &[text.clone(), int(2), int(1)]).unwrap_err();
+    assert_eq!(message(&err), "str_slice failed: range start 2 is greater than range end 1");
+    let err = call(&mut rt, "str_get", &[text.clone(), int(-1)]).unwrap_err();
+    assert_eq!(message(&err), "str_get expects a non-negative index, got -1");
+    let err = call(&mut rt, "str_get", &[text, s("0")]).unwrap_err();
+    assert_eq!(kind(&err), "type");
+    assert_eq!(message(&err), "str_get expects an int, got string");
+}
+
+#[test]
+fn concat_contains_find() {
+    let mut rt = Runtime::new();
+    assert_eq!(call(&mut rt, "str_concat", &[s("ä"), s("ö")]), Ok(s("äö")));
+    assert_eq!(call(&mut rt, "str_contains", &[s("grüße"), s("üß")]), Ok(Value::Bool(true)));
+    assert_eq!(call(&mut rt, "str_contains", &[s("grüße"), s("ss")]), Ok(Value::Bool(false)));
+    assert_eq!(call(&mut rt, "str_find", &[s("🌍a🌍b"), s("b")]), Ok(int(3)));
+    assert_eq!(call(&mut rt, "str_find", &[s("abc"), s("x")]), Ok(Value::Nil));
+    assert_eq!(call(&mut rt, "str_starts_with", &[s("ñandú"), s("ña")]), Ok(Value::Bool(true)));
+    assert_eq!(call(&mut rt, "str_ends_with", &[s("ñandú"), s("dú")]), Ok(Value::Bool(true)));
+}
+
+#[test]
+fn split_and_empty_separators() {
+    let mut rt = Runtime::new();
+    let parts = call(&mut rt, "str_split", &[s("a→b→→c"), s("→")]).unwrap();
+    assert_eq!(parts, arr(vec![s("a"), s("b"), s(""), s("c")]));
+    let chars = call(&mut rt, "str_split", &[s("añ🌍"), s("")]).unwrap();
+    assert_eq!(chars, arr(vec![s("a"), s("ñ"), s("🌍")]));
+    assert_eq!(call(&mut rt, "str_split", &[s(""), s("")]), Ok(arr(vec![])));
+    assert_eq!(call(&mut rt, "str_split", &[s(""), s(",")]), Ok(arr(vec![s("")])));
+}
+
+#[test]
+fn join() {
+    let mut rt = Runtime::new();
+    let pieces = arr(vec![s("α"), s("β"), s("γ")]);
+    assert_eq!(call(&mut rt, "str_join", &[pieces, s("·")]), Ok(s("α·β·γ")));
+    assert_eq!(call(&mut rt, "str_join", &[arr(vec![]), s(",")]), Ok(s("")));
+    let many = arr((0..10_000).map(|_| s("ab")).collect());
+    let joined = call(&mut rt, "str_join", &[many, s("")]).unwrap();
+    assert_eq!(call(&mut rt, "str_len", &[joined]), Ok(int(20_000)));
+}
+
+#[test]
+fn join_names_the_first_non_string() {
+    let mut rt = Runtime::new();
+    let pieces = arr(vec![s("a"), s("b"), int(1), Value::Nil]);
+    let err = call(&mut rt, "str_join", &[pieces, s(",")]).unwrap_err();
+    assert_eq!(kind(&err), "type");
+    assert_eq!(message(&err), "str_join expects an array of strings, got int at index 2");
+    assert_eq!(field(&err, "data"), int(2));
+}
+
+#[test]
+fn trimming_and_case() {
+    let mut rt = Runtime::new();
+    let padded = s("\u{3000} ñ \t\n");
+    assert_eq!(call(&mut rt, "str_trim", std::slice::from_ref(&padded)), Ok(s("ñ")));
+    assert_eq!(call(&mut rt, "str_trim_start", std::slice::from_ref(&padded)), Ok(s("ñ \t\n")));
+    assert_eq!(call(&mut rt, "str_trim_end", &[padded]), Ok(s("\u{3000} ñ")));
+    assert_eq!(call(&mut rt, "str_to_upper", &[s("straße ñ")]), Ok(s("STRASSE Ñ")));
+    assert_eq!(call(&mut rt, "str_to_lower", &[s("ÀÉÎ")]), Ok(s("àéî")));
+}
+
+const MULTILINGUAL: &str = concat!(
+    "Grüße, 世界! Привет 👋🏽 ",
+    "مرحبا e\u{301} \u{0}\u{10FFFF}",
+);
+
+fn error_data(rt: &mut Runtime, err: &Value) -> Value {
+    call(rt, "error_data", std::slice::from_ref(err)).unwrap()
+}
+
+#[test]
+fn chars_and_codepoints_round_trip() {
+    let mut rt = Runtime::new();
+    for text in [MULTILINGUAL, "", "a"].iter() {
+        let chars = call(&mut rt, "str_chars", &[s(text)]).unwrap();
+        assert_eq!(chars, arr(text.chars().map(Value::Char).collect()));
+        assert_eq!(call(&mut rt, "chars_to_str", &[chars]), Ok(s(text)));
+
+        let codepoints = call(&mut rt, "str_codepoints", &[s(text)]).unwrap();
+        assert_eq!(codepoints, arr(text.chars().map(|c| int(c as i64)).collect()));
+        assert_eq!(call(&mut rt, "codepoints_to_str", &[codepoints]), Ok(s(text)));
+
+        let bytes = call(&mut rt, "str_bytes", &[s(text)]).unwrap();
+        assert_eq!(call(&mut rt, "str_encode_utf8", &[s(text)]).as_ref(), Ok(&bytes));
+        assert_eq!(call(&mut rt, "bytes_len", &[bytes]), Ok(int(text.len() as i64)));
+    }
+}
+
+#[test]
+fn codepoints_agree_with_char_to_int() {
+    let mut rt = Runtime::new();
+    // A string built from pieces, so that the chars span several leaves of the rope.
+    let mut text = s("");
+    for piece in MULTILINGUAL.split(' ') {
+        text = call(&mut rt, "str_concat", &[text, s(piece), s(" ")]).unwrap();
+    }
+    let chars = call(&mut rt, "str_chars", std::slice::from_ref(&text)).unwrap();
+    let codepoints = call(&mut rt, "str_codepoints", std::slice::from_ref(&text)).unwrap();
+    let chars = match &chars {
+        Value::Array(chars) => chars.borrow().clone(),
+        other => panic!("not an array: {}", other),
+    };
+    let mapped: Vec<Value> = chars
+        .iter()
+        .map(|c| call(&mut rt, "char_to_int", std::slice::from_ref(c)).unwrap())
+        .collect();
+    assert_eq!(codepoints, arr(mapped));
+}
+
+#[test]
+fn invalid_elements_throw_with_their_index() {
+    let mut rt = Runtime::new();
+    let invalid = [0xD800, 0xDFFF, 0x110000, -1, i64::MAX, i64::MIN];
+    for n in invalid.iter() {
+        let codepoints = arr(vec![int(97), int(98), int(*n)]);
+        let err = call(&mut rt, "codepoints_to_str", &[codepoints]).unwrap_err();
+        assert_eq!(kind(&err), "range", "{}", n);
+        assert!(message(&err).ends_with(&format!("got {} at index 2", n)), "{}", message(&err));
+        assert_eq!(error_data(&mut rt, &err), int(2));
+    }
+    let err = call(&mut rt, "codepoints_to_str", &[arr(vec![Value::Char('a')])]).unwrap_err();
+    assert_eq!(kind(&err), "type");
+    assert_eq!(error_data(&mut rt, &err), int(0));
+
+    let chars = arr(vec![Value::Char('a'), s("b"), Value::Char('c')]);
+    let err = call(&mut rt, "chars_to_str", &[chars]).unwrap_err();
+    assert_eq!(kind(&err), "type");
+    assert!(message(&err).ends_with("got string at index 1"), "{}", message(&err));
+    assert_eq!(error_data(&mut rt, &err), int(1));
+    let err = call(&mut rt, "chars_to_str", &[arr(vec![int(97)])]).unwrap_err();
+    assert_eq!(error_data(&mut rt, &err), int(0));
+
+    // The boundaries of the valid ranges are fine.
+    let edges = arr(vec![int(0), int(0xD7FF), int(0xE000), int(0x10FFFF)]);
+    let text = call(&mut rt, "codepoints_to_str", &[edges]).unwrap();
+    assert_eq!(text, s("\u{0}\u{D7FF}\u{E000}\u{10FFFF}"));
+}
+
+#[test]
+fn conversions_expect_strings_and_arrays() {
+    let mut rt = Runtime::new();
+    for name in ["str_chars", "str_codepoints", "str_bytes"].iter() {
+        assert_eq!(kind(&call(&mut rt, name, &[]).unwrap_err()), "type", "{}", name);
+        assert_eq!(kind(&call(&mut rt, name, &[arr(vec![])]).unwrap_err()), "type", "{}", name);
+    }
+    for name in ["chars_to_str", "codepoints_to_str"].iter() {
+        assert_eq!(kind(&call(&mut rt, name, &[s("abc")]).unwrap_err()), "type", "{}", name);
+        assert_eq!(call(&mut rt, name, &[arr(vec![])]), Ok(s("")), "{}", name);
+    }
+}
@@ -0,0 +1,205 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::types::futures::{Clock, ManualClock};
+use pan_lang_rs::value::{Fun, Native, Value};
+
+use common::{call, int, kind, message, s};
+
+const ISO: &str = "%Y-%m-%dT%H:%M:%S.%3f%z";
+const DAY: i64 = 24 * 60 * 60 * 1000;
+
+fn format(rt: &mut Runtime, millis: i64, format: &str) -> Result<Value, Value> {
+    call(rt, "format_timestamp", &[int(millis), s(format)])
+}
+
+fn parse(rt: &mut Runtime, text: &str, format: &str) -> Result<Value, Value> {
+    call(rt, "parse_timestamp", &[s(text), s(format)])
+}
+
+#[test]
+fn known_timestamps() {
+    let mut rt = Runtime::new();
+    let cases = [
+        (0, "1970-01-01T00:00:00.000+0000"),
+        (-1, "1969-12-31T23:59:59.999+0000"),
+        (951_868_799_999, "2000-02-29T23:59:59.999+0000"),
+        (-2_203_891_200_000, "1900-03-01T00:00:00.000+0000"),
+        (1_735_646_400_000, "2024-12-31T12:00:00.000+0000"),
+        (4_107_456_000_000, "2100-02-28T00:00:00.000+0000"),
+        (-62_167_219_200_000, "0000-01-01T00:00:00.000+0000"),
+        (253_402_300_799_999, "9999-12-31T23:59:59.999+0000"),
+    ];
+    for &(millis, text) in &cases {
+        assert_eq!(format(&mut rt, millis, ISO), Ok(s(text)), "{}", millis);
+        assert_eq!(parse(&mut rt, text, ISO), Ok(int(millis)), "{}", text);
+    }
+    let text = "2000-02-29T23:59:59.999Z";
+    assert_eq!(parse(&mut rt, text, ISO), Ok(int(951_868_799_999)));
+    assert_eq!(parse(&mut rt, "1970-01-01T00:00:00.000-0000", ISO), Ok(int(0)));
+}
+
+#[test]
+fn round_trips_across_month_ends_and_leap_years() {
+    let mut rt = Runtime::new();
+    let mut previous: Option<(i64, i64, i64)> = None;
+    // Every day from 1896 to 2104, which includes the non-leap years 1900 and 2100.
+    let start = match parse(&mut rt, "1896-01-01", "%Y-%m-%d").unwrap() {
+        Value::Int(n) => n,
+        other => panic!("not an int: {}", other),
+    };
+    for day in 0..(209 * 366) {
+        // A different time of day each day.
+        let millis = start + day * DAY + (day * 7_919_123) % DAY;
+        let text = match &format(&mut rt, millis, ISO).unwrap() {
+            Value::String(text) => text.to_string(),
+            other => panic!("not a string: {}", other),
+        };
+        assert_eq!(parse(&mut rt, &text, ISO), Ok(int(millis)), "{}", text);
+
+        let date: Vec<i64> = text[..10].split('-').map(|n| n.parse().unwrap()).collect();
+        let date = (date[0], date[1], date[2]);
+        if let Some((year, month, d)) = previous {
+            let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+            let february = if leap { 29 } else { 28 };
+            let month_len = [31, february, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+            let next = if d < month_len[month as usize - 1] {
+                (year, month, d + 1)
+            } else if month < 12 {
+                (year, month + 1, 1)
+            } else {
+                (year + 1, 1, 1)
+            };
+            assert_eq!(date, next, "{}", text);
+        }
+        previous = Some(date);
+    }
+}
+
+#[test]
+fn partial_formats() {
+    let mut rt = Runtime::new();
+    let millis = 951_868_799_999;
+    assert_eq!(format(&mut rt, millis, "%d.%m.%Y"), Ok(s("29.02.2000")));
+    assert_eq!(format(&mut rt, millis, "100%% at %H:%M"), Ok(s("100% at 23:59")));
+    assert_eq!(format(&mut rt, millis, "ünï %S"), Ok(s("ünï 59")));
+    assert_eq!(format(&mut rt, millis, ""), Ok(s("")));
+    // Missing components default to the epoch.
+    assert_eq!(parse(&mut rt, "2000", "%Y"), Ok(int(946_684_800_000)));
+    assert_eq!(parse(&mut rt, "01:02", "%H:%M"), Ok(int(3_720_000)));
+    assert_eq!(parse(&mut rt, "", ""), Ok(int(0)));
+    // Formats without a year work for all timestamps.
+    assert_eq!(format(&mut rt, i64::MAX, "%H:%M:%S.%3f"), Ok(s("07:12:55.807")));
+    assert_eq!(format(&mut rt, i64::MIN, "%H:%M:%S.%3f"), Ok(s("16:47:04.192")));
+}
+
+#[test]
+fn errors() {
+    let mut rt = Runtime::new();
+    let out_of_range = [
+        ("2001-02-29", "day"),
+        ("2100-02-29", "day"),
+        ("2000-04-31", "day"),
+        ("2000-00-01", "month"),
+        ("2000-13-01", "month"),
+        ("2000-01-00", "day"),
+    ];
+    for &(text, component) in &out_of_range {
+        let err = parse(&mut rt, text, "%Y-%m-%d").unwrap_err();
+        assert_eq!(kind(&err), "range", "{}", text);
+        assert!(message(&err).contains(component), "{}", message(&err));
+    }
+    for &text in &["24:00:00", "23:60:00", "23:59:60"] {
+        assert_eq!(kind(&parse(&mut rt, text, "%H:%M:%S").unwrap_err()), "range", "{}", text);
+    }
+    let err = parse(&mut rt, "00:00+0100", "%H:%M%z").unwrap_err();
+    assert_eq!(message(&err), "parse_timestamp only supports UTC, got the offset +0100");
+
+    let mismatched = [("2000-1-01", "%Y-%m-%d"), ("2000", "%Y-"), ("2000x", "%Y"), ("20", "%Y")];
+    for &(text, format) in &mismatched {
+        assert_eq!(kind(&parse(&mut rt, text, format).unwrap_err()), "parse", "{}", text);
+    }
+    let err = parse(&mut rt, "2000/01", "%Y-%m").unwrap_err();
+    assert_eq!(message(&err), "parse_timestamp expected '-' at char 4 of \"2000/01\"");
+    assert_eq!(kind(&parse(&mut rt, "+00", "%z").unwrap_err()), "parse");
+
+    for &bad in &["%", "%q", "%3", "%3g", "ü%"] {
+        let err = format(&mut rt, 0, bad).unwrap_err();
+        assert_eq!(kind(&err), "format", "{}", bad);
+        assert_eq!(kind(&parse(&mut rt, "", bad).unwrap_err()), "format", "{}", bad);
+    }
+    let err = format(&mut rt, 0, "ü%q").unwrap_err();
+    let expected = "format_timestamp does not support the directive at char 1 of \"ü%q\"";
+    assert_eq!(message(&err), expected);
+
+    let err = format(&mut rt, 253_402_300_800_000, "%Y").unwrap_err();
+    let expected = "format_timestamp can only format the years from 0 to 9999, got 10000";
+    assert_eq!(message(&err), expected);
+    assert_eq!(kind(&format(&mut rt, -62_167_219_200_001, "%Y").unwrap_err()), "range");
+    assert_eq!(kind(&format(&mut rt, i64::MIN, "%Y").unwrap_err()), "range");
+
+    assert_eq!(kind(&call(&mut rt, "format_timestamp", &[s("0"), s("%Y")]).unwrap_err()), "type");
+    assert_eq!(kind(&call(&mut rt, "parse_timestamp", &[int(0), s("%Y")]).unwrap_err()), "type");
+    assert_eq!(kind(&call(&mut rt, "format_timestamp", &[int(0)]).unwrap_err()), "type");
+}
+
+#[test]
+fn monotonic_time_never_decreases() {
+    let mut rt = Runtime::new();
+    let mut previous = 0;
+    for _ in 0..10_000 {
+        match call(&mut rt, "monotonic_millis", &[]).unwrap() {
+            Value::Int(now) => {
+                assert!(now >= previous);
+                previous = now;
+            }
+            other => panic!("not an int: {}", other),
+        }
+    }
+    match call(&mut rt, "wall_clock_millis", &[]).unwrap() {
+        // Some time after this test was written.
+        Value::Int(now) => assert!(now > 1_700_000_000_000),
+        other => panic!("not an int: {}", other),
+    }
+}
+
+#[test]
+fn the_clock_of_the_event_loop_drives_both_builtins_and_timers() {
+    let clock = Rc::new(ManualClock::starting_at(951_868_799_000));
+    let mut rt = Runtime::with_clock(clock.clone());
+    let read = |rt: &mut Runtime| {
+        let monotonic = call(rt, "monotonic_millis", &[]).unwrap();
+        let wall = call(rt, "wall_clock_millis", &[]).unwrap();
+        (monotonic, wall)
+    };
+    assert_eq!(read(&mut rt), (int(0), int(951_868_799_000)));
+    clock.advance(999);
+    assert_eq!(read(&mut rt), (int(999), int(951_868_799_999)));
+
+    // A timer callback sees the time at which it fired.
+    let seen = Rc::new(RefCell::new(vec![]));
+    let callback = {
+        let seen = seen.clone();
+        let monotonic = rt.global("monotonic_millis").unwrap();
+        let wall = rt.global("wall_clock_millis").unwrap();
+        let format = rt.global("format_timestamp").unwrap();
+        Value::Fun(Fun::Native(Native::new("fired", move |_| {
+            let now = wall.apply(&[])?;
+            seen.borrow_mut().push(monotonic.apply(&[])?);
+            seen.borrow_mut().push(format.apply(&[now, s(ISO)])?);
+            Ok(Value::Nil)
+        })))
+    };
+    let sleep = call(&mut rt, "fut_sleep", &[int(1)]).unwrap();
+    let fired = call(&mut rt, "fut_then", &[sleep, callback]).unwrap();
+    match &fired {
+        Value::Future(fut) => drop(rt.event_loop().run_future(fut).unwrap()),
+        other => panic!("not a future: {}", other),
+    }
+    rt.event_loop().run_blocking();
+    assert_eq!(clock.now(), 1000);
+    assert_eq!(*seen.borrow(), vec![int(1000), s("2000-03-01T00:00:00.000+0000")]);
+}
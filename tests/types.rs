@@ -0,0 +1,94 @@
+mod common;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::types::bytes::Bytes;
+use pan_lang_rs::types::channel::Channel;
+use pan_lang_rs::types::stream::Stream;
+use pan_lang_rs::types::userdata::{PanUserdata, Userdata};
+use pan_lang_rs::value::{Fun, Native, Value};
+
+use common::{call, s};
+
+#[derive(Debug)]
+struct Data;
+
+impl PanUserdata for Data {
+    fn type_name(&self) -> &'static str {
+        "data"
+    }
+}
+
+// One value of every variant, with the name `typeof` returns for it and its predicate.
+fn samples(rt: &mut Runtime) -> Vec<(Value, &'static str, &'static str)> {
+    let iter = call(rt, "iter", &[Value::array(vec![])]).unwrap();
+    let builtin = rt.global("typeof").unwrap();
+    vec![
+        (Value::Nil, "nil", "is_nil"),
+        (Value::Bool(false), "bool", "is_bool"),
+        (Value::Int(0), "int", "is_int"),
+        (Value::Float(f64::NAN.into()), "float", "is_float"),
+        (Value::Char('0'), "char", "is_char"),
+        (s(""), "string", "is_string"),
+        (Value::Bytes(Bytes::from_slice(b"")), "bytes", "is_bytes"),
+        (Value::array(vec![]), "array", "is_array"),
+        (Value::set(Default::default()), "set", "is_set"),
+        (Value::map(Default::default()), "map", "is_map"),
+        (Value::Fun(Fun::Native(Native::new("f", |_| Ok(Value::Nil)))), "function", "is_fun"),
+        (builtin, "function", "is_fun"),
+        (Value::future_resolve(Value::Nil), "future", "is_future"),
+        (Value::Channel(Channel::new(0)), "channel", "is_channel"),
+        (Value::Stream(Stream::from_array(vec![])), "stream", "is_stream"),
+        (iter, "iterator", "is_iterator"),
+        (Value::Userdata(Userdata::new(Data)), "userdata", "is_userdata"),
+    ]
+}
+
+const PREDICATES: [&str; 16] = [
+    "is_nil", "is_bool", "is_int", "is_float", "is_char", "is_string", "is_bytes", "is_array",
+    "is_set", "is_map", "is_fun", "is_future", "is_channel", "is_stream", "is_iterator",
+    "is_userdata",
+];
+
+#[test]
+fn every_predicate_against_every_variant() {
+    let mut rt = Runtime::new();
+    for (v, type_name, predicate) in samples(&mut rt) {
+        assert_eq!(call(&mut rt, "typeof", std::slice::from_ref(&v)), Ok(s(type_name)));
+        for name in PREDICATES.iter() {
+            let holds = call(&mut rt, name, std::slice::from_ref(&v));
+            assert_eq!(holds, Ok(Value::Bool(*name == predicate)), "{} of {}", name, type_name);
+        }
+        let number = type_name == "int" || type_name == "float";
+        let callable = type_name == "function";
+        let is_number = call(&mut rt, "is_number", std::slice::from_ref(&v));
+        assert_eq!(is_number, Ok(Value::Bool(number)), "{}", type_name);
+        let is_callable = call(&mut rt, "is_callable", std::slice::from_ref(&v));
+        assert_eq!(is_callable, Ok(Value::Bool(callable)), "{}", type_name);
+    }
+}
+
+#[test]
+fn the_type_names_are_stable() {
+    let mut rt = Runtime::new();
+    let names: Vec<&str> = samples(&mut rt).iter().map(|(_, name, _)| *name).collect();
+    let mut distinct = names.clone();
+    distinct.dedup();
+    let documented = [
+        "nil", "bool", "int", "float", "char", "string", "bytes", "array", "set", "map",
+        "function", "future", "channel", "stream", "iterator", "userdata",
+    ];
+    assert_eq!(distinct, documented);
+    // Userdata are of type `userdata`, whatever their own type name.
+    let data = Value::Userdata(Userdata::new(Data));
+    assert_eq!(call(&mut rt, "typeof", &[data]), Ok(s("userdata")));
+}
+
+#[test]
+fn missing_arguments_are_nil() {
+    let mut rt = Runtime::new();
+    assert_eq!(call(&mut rt, "typeof", &[]), Ok(s("nil")));
+    assert_eq!(call(&mut rt, "is_nil", &[]), Ok(Value::Bool(true)));
+    assert_eq!(call(&mut rt, "is_int", &[]), Ok(Value::Bool(false)));
+    // Additional arguments are ignored.
+    assert_eq!(call(&mut rt, "is_int", &[Value::Int(1), Value::Nil]), Ok(Value::Bool(true)));
+}
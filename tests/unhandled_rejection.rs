@@ -0,0 +1,61 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use pan_lang_rs::runtime::Runtime;
+use pan_lang_rs::value::Value;
+
+#[derive(Clone, Default)]
+struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+impl Buffer {
+    fn contents(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn default_hook_writes_to_the_runtime_stderr() {
+    let mut runtime = Runtime::new();
+    let stderr = Buffer::default();
+    runtime.set_stderr(Box::new(stderr.clone()));
+    runtime.spawn_rust(async { Err(Value::from("nobody cares")) });
+    runtime.event_loop().run_until_idle();
+    assert_eq!(stderr.contents(), "unhandled rejection: \"nobody cares\"\n");
+}
+
+#[test]
+fn custom_hook_replaces_the_default() {
+    let mut runtime = Runtime::new();
+    let stderr = Buffer::default();
+    runtime.set_stderr(Box::new(stderr.clone()));
+    let seen = Rc::new(RefCell::new(vec![]));
+    let record = seen.clone();
+    runtime.set_unhandled_rejection_hook(move |value, _| record.borrow_mut().push(value));
+    runtime.spawn_rust(async { Err(Value::Int(1)) });
+    runtime.event_loop().run_until_idle();
+    assert_eq!(*seen.borrow(), vec![Value::Int(1)]);
+    assert_eq!(stderr.contents(), "");
+}
+
+#[test]
+fn observed_rejections_are_not_reported() {
+    let mut runtime = Runtime::new();
+    let stderr = Buffer::default();
+    runtime.set_stderr(Box::new(stderr.clone()));
+    let fut = runtime.spawn_rust(async { Err(Value::Int(1)) });
+    assert_eq!(runtime.block_on_raw(&fut), Err(Value::Int(1)));
+    runtime.event_loop().run_until_idle();
+    assert_eq!(stderr.contents(), "");
+}
@@ -0,0 +1,260 @@
+mod common;
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use lazy_static::lazy_static;
+
+use pan_lang_rs::runtime::{HostCtx, Runtime};
+use pan_lang_rs::types::heap;
+use pan_lang_rs::types::userdata::{
+    unknown_method, FinalizeCtx, MethodTable, PanUserdata, Userdata,
+};
+use pan_lang_rs::value::Value;
+
+use common::{arr, call, field, int, kind, message, s};
+
+/// A ring buffer of ints that drops the oldest element once it is full.
+#[derive(Debug)]
+struct Ring {
+    capacity: usize,
+    elements: VecDeque<i64>,
+}
+
+fn ring(capacity: usize) -> Value {
+    Value::Userdata(Userdata::new(Ring { capacity, elements: VecDeque::new() }))
+}
+
+fn push(ring: &mut Ring, args: &[Value], _: &mut HostCtx) -> Result<Value, Value> {
+    let n = match args.first() {
+        Some(Value::Int(n)) => *n,
+        _ => return Err(Value::error("type", "push expects an int")),
+    };
+    if ring.elements.len() == ring.capacity {
+        ring.elements.pop_front();
+    }
+    ring.elements.push_back(n);
+    Ok(Value::Nil)
+}
+
+fn pop(ring: &mut Ring, _: &[Value], _: &mut HostCtx) -> Result<Value, Value> {
+    Ok(ring.elements.pop_front().map_or(Value::Nil, Value::Int))
+}
+
+fn len(ring: &mut Ring, _: &[Value], _: &mut HostCtx) -> Result<Value, Value> {
+    Ok(Value::Int(ring.elements.len() as i64))
+}
+
+lazy_static! {
+    static ref RING_METHODS: MethodTable<Ring> =
+        MethodTable::new().add("push", push).add("pop", pop).add("len", len);
+}
+
+impl PanUserdata for Ring {
+    fn type_name(&self) -> &'static str {
+        "ring"
+    }
+
+    fn call_method(
+        &mut self,
+        name: &str,
+        args: &[Value],
+        ctx: &mut HostCtx,
+    ) -> Result<Value, Value> {
+        RING_METHODS.call(self, name, args, ctx)
+    }
+
+    fn methods(&self) -> &[&'static str] {
+        RING_METHODS.names()
+    }
+}
+
+/// A type without methods.
+#[derive(Debug)]
+struct Opaque;
+
+impl PanUserdata for Opaque {
+    fn type_name(&self) -> &'static str {
+        "opaque"
+    }
+}
+
+fn ud_call(rt: &mut Runtime, obj: &Value, method: &str, args: &[Value]) -> Result<Value, Value> {
+    let mut all = vec![obj.clone(), s(method)];
+    all.extend_from_slice(args);
+    call(rt, "ud_call", &all)
+}
+
+#[test]
+fn methods_mutate_the_host_value() {
+    let mut rt = Runtime::new();
+    let ring = ring(3);
+    for n in 1..=4 {
+        assert_eq!(ud_call(&mut rt, &ring, "push", &[int(n)]), Ok(Value::Nil));
+    }
+    assert_eq!(ud_call(&mut rt, &ring, "len", &[]), Ok(int(3)));
+    assert_eq!(ud_call(&mut rt, &ring, "pop", &[]), Ok(int(2)));
+    // Clones refer to the same buffer.
+    let alias = ring.clone();
+    assert_eq!(ud_call(&mut rt, &alias, "pop", &[]), Ok(int(3)));
+    assert_eq!(ud_call(&mut rt, &ring, "len", &[]), Ok(int(1)));
+
+    // The host can reach the value as well.
+    match &ring {
+        Value::Userdata(ud) => {
+            assert!(ud.is::<Ring>() && !ud.is::<Opaque>());
+            assert_eq!(ud.type_name(), "ring");
+            assert_eq!(ud.with(|r: &Ring| r.elements.clone()), Some(vec![4].into()));
+            ud.with_mut(|r: &mut Ring| r.elements.push_back(9)).unwrap();
+            assert_eq!(ud.with(|_: &Opaque| ()), None);
+        }
+        other => panic!("not a userdata: {}", other),
+    }
+    assert_eq!(ud_call(&mut rt, &ring, "pop", &[]), Ok(int(4)));
+    assert_eq!(ud_call(&mut rt, &ring, "pop", &[]), Ok(int(9)));
+    assert_eq!(ud_call(&mut rt, &ring, "pop", &[]), Ok(Value::Nil));
+
+    // Errors of methods are thrown as they are.
+    let err = ud_call(&mut rt, &ring, "push", &[s("x")]).unwrap_err();
+    assert_eq!(message(&err), "push expects an int");
+}
+
+#[test]
+fn methods_can_be_listed() {
+    let mut rt = Runtime::new();
+    let names = arr(vec![s("push"), s("pop"), s("len")]);
+    assert_eq!(call(&mut rt, "ud_methods", &[ring(1)]), Ok(names));
+    let opaque = Value::Userdata(Userdata::new(Opaque));
+    assert_eq!(call(&mut rt, "ud_methods", &[opaque]), Ok(arr(vec![])));
+
+    // Adding a name again replaces the method, in its place.
+    let table: MethodTable<Ring> = MethodTable::new().add("a", pop).add("b", len).add("a", push);
+    assert_eq!(table.names(), ["a", "b"]);
+    assert!(MethodTable::<Ring>::default().names().is_empty());
+}
+
+#[test]
+fn unknown_methods_throw() {
+    let mut rt = Runtime::new();
+    let cases = vec![
+        (ring(1), "ring", "peek"),
+        (Value::Userdata(Userdata::new(Opaque)), "opaque", "len"),
+    ];
+    for (obj, type_name, method) in cases {
+        let err = ud_call(&mut rt, &obj, method, &[]).unwrap_err();
+        assert_eq!(kind(&err), "method");
+        assert_eq!(message(&err), format!("{} has no method {}", type_name, method));
+        let data = call(&mut rt, "error_data", &[err]).unwrap();
+        assert_eq!(field(&data, "type"), s(type_name));
+        assert_eq!(field(&data, "method"), s(method));
+    }
+}
+
+#[test]
+fn ud_call_checks_its_arguments() {
+    let mut rt = Runtime::new();
+    let err = call(&mut rt, "ud_call", &[int(1), s("len")]).unwrap_err();
+    assert_eq!(kind(&err), "type");
+    assert_eq!(message(&err), "ud_call expects a userdata, got int");
+    let err = call(&mut rt, "ud_call", &[ring(1), int(1)]).unwrap_err();
+    assert_eq!(message(&err), "ud_call expects a method name, got int");
+    let err = call(&mut rt, "ud_call", &[ring(1)]).unwrap_err();
+    assert_eq!(message(&err), "ud_call expects a method name, got nil");
+    let err = call(&mut rt, "ud_methods", &[arr(vec![])]).unwrap_err();
+    assert_eq!(message(&err), "ud_methods expects a userdata, got array");
+}
+
+/// A file-like resource that records how it was closed.
+#[derive(Debug)]
+struct File {
+    closes: Rc<RefCell<Vec<bool>>>,
+}
+
+fn file(closes: &Rc<RefCell<Vec<bool>>>) -> Value {
+    Value::Userdata(Userdata::new(File { closes: closes.clone() }))
+}
+
+impl PanUserdata for File {
+    fn type_name(&self) -> &'static str {
+        "file"
+    }
+
+    fn call_method(
+        &mut self,
+        name: &str,
+        _args: &[Value],
+        _ctx: &mut HostCtx,
+    ) -> Result<Value, Value> {
+        match name {
+            "read" => Ok(s("contents")),
+            _ => Err(unknown_method(self.type_name(), name)),
+        }
+    }
+
+    fn finalize(&mut self, ctx: &mut FinalizeCtx) {
+        self.closes.borrow_mut().push(ctx.is_explicit());
+    }
+}
+
+#[test]
+fn resources_are_closed_exactly_once() {
+    let mut rt = Runtime::new();
+    let closes = Rc::new(RefCell::new(vec![]));
+
+    // Closed explicitly, then dropped.
+    let f = file(&closes);
+    assert_eq!(ud_call(&mut rt, &f, "read", &[]), Ok(s("contents")));
+    assert_eq!(call(&mut rt, "ud_is_closed", std::slice::from_ref(&f)), Ok(Value::Bool(false)));
+    assert_eq!(call(&mut rt, "ud_close", std::slice::from_ref(&f)), Ok(Value::Nil));
+    assert_eq!(call(&mut rt, "ud_close", std::slice::from_ref(&f)), Ok(Value::Nil));
+    assert_eq!(call(&mut rt, "ud_is_closed", std::slice::from_ref(&f)), Ok(Value::Bool(true)));
+    drop(f);
+    heap::collect();
+    assert_eq!(*closes.borrow(), vec![true]);
+
+    // Only dropped.
+    closes.borrow_mut().clear();
+    let f = file(&closes);
+    let alias = f.clone();
+    drop(f);
+    heap::collect();
+    assert!(closes.borrow().is_empty());
+    drop(alias);
+    heap::collect();
+    assert_eq!(*closes.borrow(), vec![false]);
+
+    // Closed from rust, through a clone.
+    closes.borrow_mut().clear();
+    let f = file(&closes);
+    match &f {
+        Value::Userdata(ud) => {
+            ud.clone().close().unwrap();
+            assert!(ud.is_closed());
+        }
+        other => panic!("not a userdata: {}", other),
+    }
+    drop(f);
+    heap::collect();
+    assert_eq!(*closes.borrow(), vec![true]);
+}
+
+#[test]
+fn closed_userdata_throws() {
+    let mut rt = Runtime::new();
+    let closes = Rc::new(RefCell::new(vec![]));
+    let f = file(&closes);
+    call(&mut rt, "ud_close", std::slice::from_ref(&f)).unwrap();
+    for method in ["read", "missing"].iter() {
+        let err = ud_call(&mut rt, &f, method, &[]).unwrap_err();
+        assert_eq!(kind(&err), "closed");
+        assert_eq!(message(&err), format!("cannot call {} on a file that has been closed", method));
+    }
+    // Everything else still works on it.
+    assert_eq!(call(&mut rt, "ud_methods", std::slice::from_ref(&f)), Ok(arr(vec![])));
+    assert_eq!(call(&mut rt, "typeof", &[f]), Ok(s("userdata")));
+    assert_eq!(*closes.borrow(), vec![true]);
+
+    let err = call(&mut rt, "ud_close", &[int(1)]).unwrap_err();
+    assert_eq!(message(&err), "ud_close expects a userdata, got int");
+}
@@ -0,0 +1,52 @@
+#![cfg(feature = "testing")]
+
+mod common;
+
+use pan_lang_rs::testing::ir_gen::EXECUTIONS;
+use pan_lang_rs::testing::workloads::{self, Workload};
+use pan_lang_rs::value::Value;
+
+use common::{arr, int, s};
+
+// The results of the workload in all executions, which must be the same.
+fn results(workload: &Workload, n: i64) -> Value {
+    let results: Vec<Value> = EXECUTIONS
+        .iter()
+        .map(|&execution| {
+            let mut instance = workload.instance(execution, n);
+            let first = instance.run().unwrap();
+            assert_eq!(instance.run().unwrap(), first, "{} in {:?}", workload.name, execution);
+            first
+        })
+        .collect();
+    for (result, execution) in results.iter().zip(EXECUTIONS.iter()) {
+        assert_eq!(result, &results[0], "{} in {:?}", workload.name, execution);
+    }
+    results[0].clone()
+}
+
+#[test]
+fn numeric_loop() {
+    assert_eq!(results(&workloads::numeric_loop(), 100), int(4950));
+    assert_eq!(results(&workloads::numeric_loop(), 0), int(0));
+}
+
+#[test]
+fn call_heavy() {
+    assert_eq!(results(&workloads::call_heavy(), 100), int(100));
+    assert_eq!(results(&workloads::call_heavy(), -1), int(0));
+}
+
+#[test]
+fn array_building() {
+    assert_eq!(results(&workloads::array_building(), 100), arr(vec![int(99), int(99)]));
+    assert_eq!(results(&workloads::array_building(), 0), Value::Nil);
+}
+
+#[test]
+fn literal_heavy() {
+    let text = "a string literal that is long enough to not be trivial to copy";
+    let expected = arr(vec![s(text), int(1), int(2)]);
+    assert_eq!(results(&workloads::literal_heavy(), 100), expected);
+    assert_eq!(results(&workloads::literal_heavy(), 0), Value::Nil);
+}